@@ -581,6 +581,22 @@ fn create_vertex_buffer_descriptors(file_name: &str) -> Vec<VertexBufferDesc> {
                 rate: 1,
             }
         );
+    } else if file_name.starts_with("cs_path") {
+        descriptors.push(
+            VertexBufferDesc {
+                binding: 1,
+                stride: mem::size_of::<PathInstance>() as _,
+                rate: 1,
+            }
+        );
+    } else if file_name.starts_with("cs_svg_filter") {
+        descriptors.push(
+            VertexBufferDesc {
+                binding: 1,
+                stride: mem::size_of::<SvgFilterInstance>() as _,
+                rate: 1,
+            }
+        );
     } else if file_name.starts_with("cs_clip") {
         descriptors.push(
             VertexBufferDesc {
@@ -126,6 +126,8 @@ impl Example for App {
                                 value: self.opacity,
                             }
                         ],
+                        colors: vec![],
+                        scroll_offsets: vec![],
                     },
                 );
                 txn.generate_frame();
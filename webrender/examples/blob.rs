@@ -11,6 +11,7 @@ mod boilerplate;
 
 use boilerplate::{Example, HandyDandyRectBuilder};
 use rayon::{ThreadPool, ThreadPoolBuilder};
+use rayon::prelude::*;
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
 use std::sync::Arc;
@@ -95,11 +96,37 @@ fn render_blob(
     }
 
     Ok(api::RasterizedBlobImage {
+        tile,
         data: texels,
         size: descriptor.size,
     })
 }
 
+// Rasterizes blob images on the scene builder thread, before the scene that uses
+// them is swapped in. The requests carry the commands to render, so it only needs
+// the worker threads.
+struct AsyncCheckerboardRasterizer {
+    workers: Arc<ThreadPool>,
+}
+
+impl api::AsyncBlobImageRasterizer for AsyncCheckerboardRasterizer {
+    fn rasterize(
+        &mut self,
+        requests: &[api::BlobImageParams],
+    ) -> Vec<(api::BlobImageRequest, api::BlobImageResult)> {
+        // Render all of the requested tiles in parallel and wait for them.
+        self.workers.install(|| {
+            requests.par_iter().map(|params| {
+                let result = match deserialize_blob(&params.data[..]) {
+                    Ok(cmds) => render_blob(Arc::new(cmds), &params.descriptor, params.request.tile),
+                    Err(()) => Err(api::BlobImageError::InvalidData),
+                };
+                (params.request, result)
+            }).collect()
+        })
+    }
+}
+
 struct CheckerboardRenderer {
     // We are going to defer the rendering work to worker threads.
     // Using a pre-built Arc<ThreadPool> rather than creating our own threads
@@ -211,6 +238,13 @@ impl api::BlobImageRenderer for CheckerboardRenderer {
         // If we break out of the loop above it means the channel closed unexpectedly.
         Err(api::BlobImageError::Other("Channel closed".into()))
     }
+
+    fn create_async_rasterizer(&mut self) -> Option<Box<api::AsyncBlobImageRasterizer>> {
+        Some(Box::new(AsyncCheckerboardRasterizer {
+            workers: Arc::clone(&self.workers),
+        }))
+    }
+
     fn delete_font(&mut self, _font: api::FontKey) {}
     fn delete_font_instance(&mut self, _instance: api::FontInstanceKey) {}
     fn clear_namespace(&mut self, _namespace: api::IdNamespace) {}
@@ -146,7 +146,7 @@ pub fn main_wrapper<E: Example>(
     let adapter = adapters.remove(0);
     let mut surface = instance.create_surface(&window);
     let window_size = window.get_inner_size().unwrap();
-    let (mut renderer, sender) = webrender::Renderer::new(notifier, &adapter, &mut surface, window_size, opts).unwrap();
+    let (mut renderer, sender) = webrender::Renderer::new(notifier, &adapter, Some(&mut surface), window_size, opts).unwrap();
     let api = sender.create_api();
     let document_id = api.add_document(framebuffer_size, 0);
 
@@ -49,154 +49,51 @@ impl RenderNotifier for Notifier {
 }
 
 struct Window {
-    events_loop: winit::EventsLoop, //TODO: share events loop?
     window: winit::Window,
-    renderer: webrender::Renderer<back::Backend>,
+    surface: back::Surface,
     name: &'static str,
     pipeline_id: PipelineId,
     document_id: DocumentId,
     epoch: Epoch,
-    api: RenderApi,
+    background_color: ColorF,
     font_instance_key: FontInstanceKey,
 }
 
 impl Window {
-    fn new(name: &'static str, clear_color: ColorF) -> Self {
-        let events_loop = winit::EventsLoop::new();
-        /*let context_builder = glutin::ContextBuilder::new()
-            .with_gl(glutin::GlRequest::GlThenGles {
-                opengl_version: (3, 2),
-                opengles_version: (3, 0),
-            });
-        let window_builder = winit::WindowBuilder::new()
-            .with_title(name)
-            .with_multitouch()
-            .with_dimensions(800, 600);
-        let window = winit::GlWindow::new(window_builder, context_builder, &events_loop)
-            .unwrap();
-
-        unsafe {
-            window.make_current().ok();
-        }
-
-        let gl = match window.get_api() {
-            winit::Api::OpenGl => unsafe {
-                gl::GlFns::load_with(|symbol| window.get_proc_address(symbol) as *const _)
-            },
-            winit::Api::OpenGlEs => unsafe {
-                gl::GlesFns::load_with(|symbol| window.get_proc_address(symbol) as *const _)
-            },
-            winit::Api::WebGl => unimplemented!(),
-        };*/
-
-        let window = winit::WindowBuilder::new()
-            .with_title(name)
-            .with_multitouch()
-            .with_dimensions(800, 600)
-            .build(&events_loop)
-            .unwrap();
-
-        let device_pixel_ratio = window.hidpi_factor();
-
-        let opts = webrender::RendererOptions {
-            device_pixel_ratio,
-            clear_color: Some(clear_color),
-            ..webrender::RendererOptions::default()
-        };
-
+    fn new(
+        window: winit::Window,
+        surface: back::Surface,
+        name: &'static str,
+        background_color: ColorF,
+        pipeline_id: PipelineId,
+        api: &RenderApi,
+        font_instance_key: FontInstanceKey,
+    ) -> Self {
         let framebuffer_size = {
             let (width, height) = window.get_inner_size().unwrap();
             DeviceUintSize::new(width, height)
         };
-        let notifier = Box::new(Notifier::new(events_loop.create_proxy()));
-
-        #[cfg(any(feature = "dx12", feature = "vulkan"))]
-        let instance = back::Instance::create("gfx-rs instance", 1);
-        #[cfg(not(any(feature = "dx12", feature = "vulkan")))]
-        let instance = back::Instance;
-
-        let mut adapters = instance.enumerate_adapters();
-        let adapter = adapters.remove(0);
-
-        #[cfg(any(feature = "dx12", feature = "vulkan"))]
-        let mut surface = instance.create_surface(&window);
-        #[cfg(not(any(feature = "dx12", feature = "vulkan")))]
-        let mut surface = back::Surface;
-
-        let window_size = window.get_inner_size().unwrap();
-        let (renderer, sender) = webrender::Renderer::new(notifier, &adapter, &mut surface, window_size, opts).unwrap();
-        let api = sender.create_api();
         let document_id = api.add_document(framebuffer_size, 0);
 
-        let epoch = Epoch(0);
-        let pipeline_id = PipelineId(0, 0);
-        let mut txn = Transaction::new();
-
-        let font_key = api.generate_font_key();
-        let font_bytes = load_file("../wrench/reftests/text/FreeSans.ttf");
-        txn.add_raw_font(font_key, font_bytes, 0);
-
-        let font_instance_key = api.generate_font_instance_key();
-        txn.add_font_instance(font_instance_key, font_key, Au::from_px(32), None, None, Vec::new());
-
-        api.send_transaction(document_id, txn);
-
         Window {
-            events_loop,
             window,
-            renderer,
+            surface,
             name,
-            epoch,
             pipeline_id,
             document_id,
-            api,
+            epoch: Epoch(0),
+            background_color,
             font_instance_key,
         }
     }
 
-    fn tick(&mut self) -> bool {
-        /*unsafe {
-            self.window.make_current().ok();
-        }*/
-        let mut do_exit = false;
-        let my_name = &self.name;
-        let renderer = &mut self.renderer;
-
-        self.events_loop.poll_events(|global_event| match global_event {
-            winit::Event::WindowEvent { event, .. } => match event {
-                winit::WindowEvent::CloseRequested |
-                winit::WindowEvent::KeyboardInput {
-                    input: winit::KeyboardInput {
-                        virtual_keycode: Some(winit::VirtualKeyCode::Escape),
-                        ..
-                    },
-                    ..
-                } => {
-                    do_exit = true
-                }
-                winit::WindowEvent::KeyboardInput {
-                    input: winit::KeyboardInput {
-                        state: winit::ElementState::Pressed,
-                        virtual_keycode: Some(winit::VirtualKeyCode::P),
-                        ..
-                    },
-                    ..
-                } => {
-                    println!("toggle flags {}", my_name);
-                    renderer.toggle_debug_flags(webrender::DebugFlags::PROFILER_DBG);
-                }
-                _ => {}
-            }
-            _ => {}
-        });
-        if do_exit {
-            return true
-        }
+    fn framebuffer_size(&self) -> DeviceUintSize {
+        let (width, height) = self.window.get_inner_size().unwrap();
+        DeviceUintSize::new(width, height)
+    }
 
-        let framebuffer_size = {
-            let (width, height) = self.window.get_inner_size().unwrap();
-            DeviceUintSize::new(width, height)
-        };
+    fn send_frame(&mut self, api: &RenderApi) {
+        let framebuffer_size = self.framebuffer_size();
         let device_pixel_ratio = self.window.hidpi_factor();
         let layout_size = framebuffer_size.to_f32() / euclid::TypedScale::new(device_pixel_ratio);
         let mut txn = Transaction::new();
@@ -287,42 +184,146 @@ impl Window {
 
         txn.set_display_list(
             self.epoch,
-            None,
+            Some(self.background_color),
             layout_size,
             builder.finalize(),
             true,
         );
         txn.set_root_pipeline(self.pipeline_id);
         txn.generate_frame();
-        self.api.send_transaction(self.document_id, txn);
-
-        renderer.update();
-        renderer.render(framebuffer_size).unwrap();
-        //self.window.swap_buffers().ok();
-
-        false
+        api.send_transaction(self.document_id, txn);
     }
+}
 
-    fn deinit(self) {
-        self.renderer.deinit();
-    }
+fn build_window(events_loop: &winit::EventsLoop, name: &'static str) -> winit::Window {
+    winit::WindowBuilder::new()
+        .with_title(name)
+        .with_multitouch()
+        .with_dimensions(800, 600)
+        .build(events_loop)
+        .unwrap()
 }
 
 fn main() {
-    let mut win1 = Window::new("window1", ColorF::new(0.3, 0.0, 0.0, 1.0));
-    let mut win2 = Window::new("window2", ColorF::new(0.0, 0.3, 0.0, 1.0));
+    // Both windows share the events loop and a single renderer, so the
+    // texture cache and the glyph cache are shared as well.
+    let mut events_loop = winit::EventsLoop::new();
+    let window1 = build_window(&events_loop, "window1");
+    let window2 = build_window(&events_loop, "window2");
+
+    #[cfg(any(feature = "dx12", feature = "vulkan"))]
+    let instance = back::Instance::create("gfx-rs instance", 1);
+    #[cfg(not(any(feature = "dx12", feature = "vulkan")))]
+    let instance = back::Instance;
+
+    let mut adapters = instance.enumerate_adapters();
+    let adapter = adapters.remove(0);
+
+    #[cfg(any(feature = "dx12", feature = "vulkan"))]
+    let (mut surface1, surface2) = (instance.create_surface(&window1), instance.create_surface(&window2));
+    #[cfg(not(any(feature = "dx12", feature = "vulkan")))]
+    let (mut surface1, surface2) = (back::Surface, back::Surface);
+
+    let opts = webrender::RendererOptions {
+        device_pixel_ratio: window1.hidpi_factor(),
+        ..webrender::RendererOptions::default()
+    };
+    let notifier = Box::new(Notifier::new(events_loop.create_proxy()));
+    let window_size = window1.get_inner_size().unwrap();
+    let (mut renderer, sender) =
+        webrender::Renderer::new(notifier, &adapter, Some(&mut surface1), window_size, opts).unwrap();
+    let api = sender.create_api();
+
+    let font_key = api.generate_font_key();
+    let font_instance_key = api.generate_font_instance_key();
+
+    let mut win1 = Window::new(
+        window1,
+        surface1,
+        "window1",
+        ColorF::new(0.3, 0.0, 0.0, 1.0),
+        PipelineId(0, 0),
+        &api,
+        font_instance_key,
+    );
+    let mut win2 = Window::new(
+        window2,
+        surface2,
+        "window2",
+        ColorF::new(0.0, 0.3, 0.0, 1.0),
+        PipelineId(0, 1),
+        &api,
+        font_instance_key,
+    );
+
+    // The resources are shared by the documents of the api.
+    let mut txn = Transaction::new();
+    let font_bytes = load_file("../wrench/reftests/text/FreeSans.ttf");
+    txn.add_raw_font(font_key, font_bytes, 0);
+    txn.add_font_instance(font_instance_key, font_key, Au::from_px(32), None, None, Vec::new());
+    api.send_transaction(win1.document_id, txn);
+
+    // The first window is the one the renderer was created with, the
+    // document of the second one gets its own swapchain.
+    let window_size = win2.window.get_inner_size().unwrap();
+    renderer.add_document_surface(win2.document_id, &adapter, &mut win2.surface, window_size);
 
     loop {
-        if win1.tick() {
-            break;
+        let mut do_exit = false;
+        let mut win2_resized = false;
+        {
+            let win1 = &win1;
+            let win2 = &win2;
+            let renderer = &mut renderer;
+            events_loop.poll_events(|global_event| match global_event {
+                winit::Event::WindowEvent { event, window_id } => match event {
+                    winit::WindowEvent::CloseRequested |
+                    winit::WindowEvent::KeyboardInput {
+                        input: winit::KeyboardInput {
+                            virtual_keycode: Some(winit::VirtualKeyCode::Escape),
+                            ..
+                        },
+                        ..
+                    } => {
+                        do_exit = true
+                    }
+                    winit::WindowEvent::KeyboardInput {
+                        input: winit::KeyboardInput {
+                            state: winit::ElementState::Pressed,
+                            virtual_keycode: Some(winit::VirtualKeyCode::P),
+                            ..
+                        },
+                        ..
+                    } => {
+                        let name = if window_id == win1.window.id() { win1.name } else { win2.name };
+                        println!("toggle flags {}", name);
+                        renderer.toggle_debug_flags(webrender::DebugFlags::PROFILER_DBG);
+                    }
+                    winit::WindowEvent::Resized(..) if window_id == win2.window.id() => {
+                        win2_resized = true;
+                    }
+                    _ => {}
+                }
+                _ => {}
+            });
         }
-        if win2.tick() {
+        if do_exit {
             break;
         }
+        if win2_resized {
+            let window_size = win2.window.get_inner_size().unwrap();
+            renderer.resize_document_surface(win2.document_id, &adapter, &mut win2.surface, window_size);
+        }
+
+        win1.send_frame(&api);
+        win2.send_frame(&api);
+
+        // A single render presents both windows.
+        renderer.update();
+        renderer.render(win1.framebuffer_size()).unwrap();
     }
 
-    win1.deinit();
-    win2.deinit();
+    renderer.deinit();
 }
 
 fn load_file(name: &str) -> Vec<u8> {
@@ -0,0 +1,123 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#define VECS_PER_SPECIFIC_BRUSH 2
+
+#include shared,prim_shared,brush
+
+#define PI 3.141592653589793
+
+flat varying int vGradientAddress;
+flat varying float vGradientRepeat;
+
+flat varying vec2 vCenter;
+flat varying float vStartAngle;
+flat varying float vStartOffset;
+flat varying float vEndOffset;
+
+varying vec2 vPos;
+flat varying vec2 vRepeatedSize;
+
+#ifdef WR_FEATURE_ALPHA_PASS
+varying vec2 vLocalPos;
+flat varying vec2 vTileRepeat;
+#endif
+
+#ifdef WR_VERTEX_SHADER
+
+struct ConicGradient {
+    vec4 center_start_end_offset;
+    float angle;
+    int extend_mode;
+    vec2 stretch_size;
+};
+
+ConicGradient fetch_conic_gradient(int address) {
+    vec4 data[2] = fetch_from_resource_cache_2(address);
+    return ConicGradient(
+        data[0],
+        data[1].x,
+        int(data[1].y),
+        data[1].zw
+    );
+}
+
+void brush_vs(
+    VertexInfo vi,
+    int prim_address,
+    RectWithSize local_rect,
+    RectWithSize segment_rect,
+    ivec3 user_data,
+    mat4 transform,
+    PictureTask pic_task,
+    int brush_flags,
+    vec4 unused
+) {
+    ConicGradient gradient = fetch_conic_gradient(prim_address);
+
+    vPos = vi.local_pos - local_rect.p0;
+
+    vCenter = gradient.center_start_end_offset.xy;
+    vStartOffset = gradient.center_start_end_offset.z;
+    vEndOffset = gradient.center_start_end_offset.w;
+    vStartAngle = gradient.angle;
+
+    vec2 tile_repeat = local_rect.size / gradient.stretch_size;
+    vRepeatedSize = gradient.stretch_size;
+
+    vGradientAddress = user_data.x;
+
+    // Whether to repeat the gradient instead of clamping.
+    vGradientRepeat = float(gradient.extend_mode != EXTEND_MODE_CLAMP);
+
+#ifdef WR_FEATURE_ALPHA_PASS
+    vTileRepeat = tile_repeat.xy;
+    vLocalPos = vi.local_pos;
+#endif
+}
+#endif
+
+#ifdef WR_FRAGMENT_SHADER
+Fragment brush_fs() {
+
+#ifdef WR_FEATURE_ALPHA_PASS
+    // Handle top and left inflated edges (see brush_image).
+    vec2 local_pos = max(vPos, vec2(0.0));
+
+    // Apply potential horizontal and vertical repetitions.
+    vec2 pos = mod(local_pos, vRepeatedSize);
+
+    vec2 prim_size = vRepeatedSize * vTileRepeat;
+    // Handle bottom and right inflated edges (see brush_image).
+    if (local_pos.x >= prim_size.x) {
+        pos.x = vRepeatedSize.x;
+    }
+    if (local_pos.y >= prim_size.y) {
+        pos.y = vRepeatedSize.y;
+    }
+#else
+    // Apply potential horizontal and vertical repetitions.
+    vec2 pos = mod(vPos, vRepeatedSize);
+#endif
+
+    vec2 pd = pos - vCenter;
+
+    // The angle goes clockwise from the top, like in CSS. The y axis points
+    // down, so atan(x, -y) is zero at the top and grows clockwise.
+    float angle = atan(pd.x, -pd.y) - vStartAngle;
+    float turn = fract(angle / (2.0 * PI));
+
+    float offset = (turn - vStartOffset) / (vEndOffset - vStartOffset);
+
+    vec4 color = sample_gradient(vGradientAddress,
+                                 offset,
+                                 vGradientRepeat);
+
+#ifdef WR_FEATURE_ALPHA_PASS
+    color *= init_transform_fs(vLocalPos);
+#endif
+
+    return Fragment(color);
+}
+#endif
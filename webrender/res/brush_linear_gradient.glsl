@@ -49,11 +49,20 @@ void brush_vs(
     mat4 transform,
     PictureTask pic_task,
     int brush_flags,
-    vec4 unused
+    vec4 texel_rect
 ) {
     Gradient gradient = fetch_gradient(prim_address);
 
     vPos = vi.local_pos - local_rect.p0;
+    vec2 tile_repeat = local_rect.size / gradient.stretch_size;
+
+    // If this segment should interpolate relative to the segment,
+    // map it onto its rect of the (unrepeated) gradient pattern.
+    if ((brush_flags & BRUSH_FLAG_SEGMENT_RELATIVE) != 0) {
+        vec2 f = (vi.local_pos - segment_rect.p0) / segment_rect.size;
+        vPos = mix(texel_rect.xy, texel_rect.zw, f);
+        tile_repeat = vec2(1.0);
+    }
 
     vec2 start_point = gradient.start_end_point.xy;
     vec2 end_point = gradient.start_end_point.zw;
@@ -62,7 +71,6 @@ void brush_vs(
     vStartPoint = start_point;
     vScaledDir = dir / dot(dir, dir);
 
-    vec2 tile_repeat = local_rect.size / gradient.stretch_size;
     vRepeatedSize = gradient.stretch_size;
 
     vGradientAddress = user_data.x;
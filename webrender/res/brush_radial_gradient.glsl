@@ -49,11 +49,20 @@ void brush_vs(
     mat4 transform,
     PictureTask pic_task,
     int brush_flags,
-    vec4 unused
+    vec4 texel_rect
 ) {
     RadialGradient gradient = fetch_radial_gradient(prim_address);
 
     vPos = vi.local_pos - local_rect.p0;
+    vec2 tile_repeat = local_rect.size / gradient.stretch_size;
+
+    // If this segment should interpolate relative to the segment,
+    // map it onto its rect of the (unrepeated) gradient pattern.
+    if ((brush_flags & BRUSH_FLAG_SEGMENT_RELATIVE) != 0) {
+        vec2 f = (vi.local_pos - segment_rect.p0) / segment_rect.size;
+        vPos = mix(texel_rect.xy, texel_rect.zw, f);
+        tile_repeat = vec2(1.0);
+    }
 
     vCenter = gradient.center_start_end_radius.xy;
     vStartRadius = gradient.center_start_end_radius.z;
@@ -61,7 +70,6 @@ void brush_vs(
 
     // Transform all coordinates by the y scale so the
     // fragment shader can work with circles
-    vec2 tile_repeat = local_rect.size / gradient.stretch_size;
     vPos.y *= gradient.ratio_xy;
     vCenter.y *= gradient.ratio_xy;
     vRepeatedSize = gradient.stretch_size;
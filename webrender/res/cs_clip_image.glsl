@@ -4,22 +4,56 @@
 
 #include shared,clip_shared
 
+#define MASK_SOURCE_IMAGE           0
+#define MASK_SOURCE_GRADIENT        1
+
+#define MASK_CHANNEL_RED            0
+#define MASK_CHANNEL_ALPHA          1
+#define MASK_CHANNEL_LUMINANCE      2
+
 varying vec3 vPos;
-varying vec3 vClipMaskImageUv;
 
+flat varying vec4 vLocalRect;
+flat varying vec4 vTileSizeSpacing;
 flat varying vec4 vClipMaskUvRect;
 flat varying vec4 vClipMaskUvInnerRect;
 flat varying float vLayer;
 
+flat varying int vSourceKind;
+flat varying int vChannel;
+
+// The start and end points of the gradient line, and the
+// address, count and repeat mode of the gradient stops.
+flat varying vec4 vGradientLine;
+flat varying ivec2 vGradientStopsAddress;
+flat varying int vGradientStopCount;
+flat varying float vGradientRepeat;
+
 #ifdef WR_VERTEX_SHADER
 struct ImageMaskData {
     RectWithSize local_rect;
+    vec2 tile_size;
+    vec2 tile_spacing;
+    int source_kind;
+    int channel;
+    int stop_count;
+    float repeat;
+    vec4 gradient_line;
 };
 
 ImageMaskData fetch_mask_data(ivec2 address) {
-    vec4 data = fetch_from_resource_cache_1_direct(address);
-    RectWithSize local_rect = RectWithSize(data.xy, data.zw);
-    ImageMaskData mask_data = ImageMaskData(local_rect);
+    vec4 data[4] = fetch_from_resource_cache_4_direct(address);
+    RectWithSize local_rect = RectWithSize(data[0].xy, data[0].zw);
+    ImageMaskData mask_data = ImageMaskData(
+        local_rect,
+        data[1].xy,
+        data[1].zw,
+        int(data[2].x),
+        int(data[2].y),
+        int(data[2].z),
+        data[2].w,
+        data[3]
+    );
     return mask_data;
 }
 
@@ -37,8 +71,17 @@ void main(void) {
 
     vPos = vi.local_pos;
     vLayer = res.layer;
+    vLocalRect = vec4(local_rect.p0, local_rect.size);
+    vTileSizeSpacing = vec4(mask.tile_size, mask.tile_spacing);
+
+    vSourceKind = mask.source_kind;
+    vChannel = mask.channel;
+
+    vGradientLine = mask.gradient_line;
+    vGradientStopsAddress = cmi.clip_data_address + ivec2(4, 0);
+    vGradientStopCount = mask.stop_count;
+    vGradientRepeat = mask.repeat;
 
-    vClipMaskImageUv = vec3((vPos.xy / vPos.z - local_rect.p0) / local_rect.size, 0.0);
     vec2 texture_size = vec2(textureSize(sColor0, 0));
     vClipMaskUvRect = vec4(res.uv_rect.p0, res.uv_rect.p1 - res.uv_rect.p0) / texture_size.xyxy;
     // applying a half-texel offset to the UV boundaries to prevent linear samples from the outside
@@ -48,15 +91,65 @@ void main(void) {
 #endif
 
 #ifdef WR_FRAGMENT_SHADER
+// The mask value of a gradient, interpolated between the stops,
+// which are stored two per block as (offset, value) pairs.
+float sample_gradient_mask(float offset) {
+    float x = mix(offset, fract(offset), vGradientRepeat);
+
+    vec4 first = TEXEL_FETCH(sResourceCache, vGradientStopsAddress, 0, ivec2(0, 0));
+    float value = first.y;
+
+    for (int i = 1; i < vGradientStopCount; i++) {
+        vec4 block = TEXEL_FETCH(sResourceCache, vGradientStopsAddress + ivec2(i / 2, 0), 0, ivec2(0, 0));
+        vec2 stop = (i % 2 == 0) ? block.xy : block.zw;
+
+        vec4 prev_block = TEXEL_FETCH(sResourceCache, vGradientStopsAddress + ivec2((i - 1) / 2, 0), 0, ivec2(0, 0));
+        vec2 prev_stop = ((i - 1) % 2 == 0) ? prev_block.xy : prev_block.zw;
+
+        if (x >= stop.x) {
+            value = stop.y;
+        } else if (x > prev_stop.x) {
+            value = mix(prev_stop.y, stop.y, (x - prev_stop.x) / (stop.x - prev_stop.x));
+        }
+    }
+
+    return value;
+}
+
 void main(void) {
-    float alpha = init_transform_fs(vPos.xy / vPos.z);
-
-    bool repeat_mask = false; //TODO
-    vec2 clamped_mask_uv = repeat_mask ? fract(vClipMaskImageUv.xy) :
-        clamp(vClipMaskImageUv.xy, vec2(0.0, 0.0), vec2(1.0, 1.0));
-    vec2 source_uv = clamp(clamped_mask_uv * vClipMaskUvRect.zw + vClipMaskUvRect.xy,
-        vClipMaskUvInnerRect.xy, vClipMaskUvInnerRect.zw);
-    float clip_alpha = texture(sColor0, vec3(source_uv, vLayer)).r; //careful: texture has type A8
+    vec2 local_pos = vPos.xy / vPos.z;
+    float alpha = init_transform_fs(local_pos);
+
+    // Find the position within the tile, the mask is
+    // transparent in the spacing between tiles.
+    vec2 tile_size = vTileSizeSpacing.xy;
+    vec2 tile_stride = tile_size + vTileSizeSpacing.zw;
+    vec2 tile_pos = local_pos - vLocalRect.xy;
+    tile_pos -= floor(tile_pos / tile_stride) * tile_stride;
+    if (any(greaterThanEqual(tile_pos, tile_size))) {
+        alpha = 0.0;
+    }
+
+    float clip_alpha;
+    if (vSourceKind == MASK_SOURCE_GRADIENT) {
+        vec2 dir = vGradientLine.zw - vGradientLine.xy;
+        float offset = dot(tile_pos - vGradientLine.xy, dir) / max(dot(dir, dir), 1.0e-6);
+        clip_alpha = sample_gradient_mask(offset);
+    } else {
+        vec2 mask_uv = clamp(tile_pos / tile_size, vec2(0.0, 0.0), vec2(1.0, 1.0));
+        vec2 source_uv = clamp(mask_uv * vClipMaskUvRect.zw + vClipMaskUvRect.xy,
+            vClipMaskUvInnerRect.xy, vClipMaskUvInnerRect.zw);
+        vec4 texel = texture(sColor0, vec3(source_uv, vLayer));
+
+        if (vChannel == MASK_CHANNEL_ALPHA) {
+            clip_alpha = texel.a;
+        } else if (vChannel == MASK_CHANNEL_LUMINANCE) {
+            // The image is premultiplied, so this is the luminance times alpha.
+            clip_alpha = dot(texel.rgb, vec3(0.2125, 0.7154, 0.0721));
+        } else {
+            clip_alpha = texel.r; //careful: texture has type A8
+        }
+    }
 
     oFragColor = vec4(alpha * clip_alpha, 1.0, 1.0, 1.0);
 }
@@ -0,0 +1,94 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#include shared,clip_shared
+
+#define FILL_RULE_NONZERO       0
+#define FILL_RULE_EVEN_ODD      1
+
+varying vec3 vLocalPos;
+
+flat varying ivec2 vChunksAddress;
+flat varying int vSegmentCount;
+flat varying int vFillRule;
+
+#ifdef WR_VERTEX_SHADER
+
+struct PathClipData {
+    RectWithSize bounds;
+    int segment_count;
+    int fill_rule;
+};
+
+PathClipData fetch_path_clip(ivec2 address) {
+    vec4 data[2] = fetch_from_resource_cache_2_direct(address);
+    return PathClipData(
+        RectWithSize(data[0].xy, data[0].zw),
+        int(data[1].x),
+        int(data[1].y)
+    );
+}
+
+void main(void) {
+    ClipMaskInstance cmi = fetch_clip_item();
+    ClipArea area = fetch_clip_area(cmi.render_task_address);
+    ClipScrollNode scroll_node = fetch_clip_scroll_node(cmi.scroll_node_id);
+    PathClipData data = fetch_path_clip(cmi.clip_data_address);
+
+    ClipVertexInfo vi = write_clip_tile_vertex(data.bounds,
+                                               scroll_node,
+                                               area);
+
+    vLocalPos = vi.local_pos;
+
+    // The addresses of the segment chunks follow the two header blocks,
+    // two per block.
+    vChunksAddress = cmi.clip_data_address + ivec2(2, 0);
+    vSegmentCount = data.segment_count;
+    vFillRule = data.fill_rule;
+}
+#endif
+
+#ifdef WR_FRAGMENT_SHADER
+void main(void) {
+    vec2 local_pos = vLocalPos.xy / vLocalPos.z;
+    float alpha = init_transform_fs(local_pos);
+
+    float aa_range = compute_aa_range(local_pos);
+
+    // Find the winding number of the path around the position, counting the
+    // crossings of a ray towards +x, and the distance to the nearest segment.
+    int winding = 0;
+    float min_distance = 1.0e6;
+    ivec2 segments_address = ivec2(0);
+    for (int i = 0; i < vSegmentCount; i++) {
+        // The segments are split into chunks of one resource cache row.
+        int index = i % WR_MAX_VERTEX_TEXTURE_WIDTH;
+        if (index == 0) {
+            int chunk = i / WR_MAX_VERTEX_TEXTURE_WIDTH;
+            vec4 addresses = TEXEL_FETCH(sResourceCache, vChunksAddress + ivec2(chunk / 2, 0), 0, ivec2(0, 0));
+            segments_address = ivec2(chunk % 2 == 0 ? addresses.xy : addresses.zw);
+        }
+        vec4 segment = TEXEL_FETCH(sResourceCache, segments_address + ivec2(index, 0), 0, ivec2(0, 0));
+        vec2 from = segment.xy;
+        vec2 to = segment.zw;
+
+        if ((from.y <= local_pos.y) != (to.y <= local_pos.y)) {
+            float x = from.x + (local_pos.y - from.y) * (to.x - from.x) / (to.y - from.y);
+            if (x > local_pos.x) {
+                winding += to.y > from.y ? 1 : -1;
+            }
+        }
+
+        vec2 dir = to - from;
+        float t = clamp(dot(local_pos - from, dir) / max(dot(dir, dir), 1.0e-6), 0.0, 1.0);
+        min_distance = min(min_distance, length(local_pos - from - dir * t));
+    }
+
+    bool inside = vFillRule == FILL_RULE_EVEN_ODD ? (winding & 1) != 0 : winding != 0;
+    float path_alpha = distance_aa(aa_range, inside ? -min_distance : min_distance);
+
+    oFragColor = vec4(alpha * path_alpha);
+}
+#endif
@@ -0,0 +1,102 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#include shared,prim_shared
+
+#define FILL_RULE_NONZERO       0
+#define FILL_RULE_EVEN_ODD      1
+
+#define PATH_PAINT_COLOR        0
+#define PATH_PAINT_GRADIENT     1
+
+varying vec2 vLocalPos;
+
+flat varying ivec2 vChunksAddress;
+flat varying int vSegmentCount;
+flat varying int vFillRule;
+
+// The color for solid paints, or the start and end
+// points of the gradient line for gradient paints.
+flat varying vec4 vPaintData;
+flat varying int vPaintKind;
+flat varying int vGradientAddress;
+flat varying float vGradientRepeat;
+
+#ifdef WR_VERTEX_SHADER
+
+in vec2 aTaskOrigin;
+in vec4 aRect;
+in vec2 aLocalSize;
+in int aPathDataAddress;
+in int aPathStopsAddress;
+
+void main(void) {
+    vec4 header[2] = fetch_from_resource_cache_2(aPathDataAddress);
+
+    vSegmentCount = int(header[0].x);
+    vFillRule = int(header[0].y);
+    vPaintKind = int(header[0].z);
+    vGradientRepeat = header[0].w;
+    vPaintData = header[1];
+    vGradientAddress = aPathStopsAddress;
+
+    // The addresses of the segment chunks follow the two header blocks,
+    // two per block.
+    vChunksAddress = get_resource_cache_uv(aPathDataAddress + 2);
+
+    // The task covers the local bounds of the path.
+    vLocalPos = aPosition.xy * aLocalSize;
+
+    gl_Position = uTransform * vec4(aTaskOrigin + aRect.xy + aPosition.xy * aRect.zw, 0.0, 1.0);
+}
+#endif
+
+#ifdef WR_FRAGMENT_SHADER
+void main(void) {
+    float aa_range = compute_aa_range(vLocalPos);
+
+    // Find the winding number of the path around the position, counting the
+    // crossings of a ray towards +x, and the distance to the nearest segment.
+    int winding = 0;
+    float min_distance = 1.0e6;
+    ivec2 segments_address = ivec2(0);
+    for (int i = 0; i < vSegmentCount; i++) {
+        // The segments are split into chunks of one resource cache row.
+        int index = i % WR_MAX_VERTEX_TEXTURE_WIDTH;
+        if (index == 0) {
+            int chunk = i / WR_MAX_VERTEX_TEXTURE_WIDTH;
+            vec4 addresses = TEXEL_FETCH(sResourceCache, vChunksAddress + ivec2(chunk / 2, 0), 0, ivec2(0, 0));
+            segments_address = ivec2(chunk % 2 == 0 ? addresses.xy : addresses.zw);
+        }
+        vec4 segment = TEXEL_FETCH(sResourceCache, segments_address + ivec2(index, 0), 0, ivec2(0, 0));
+        vec2 from = segment.xy;
+        vec2 to = segment.zw;
+
+        if ((from.y <= vLocalPos.y) != (to.y <= vLocalPos.y)) {
+            float x = from.x + (vLocalPos.y - from.y) * (to.x - from.x) / (to.y - from.y);
+            if (x > vLocalPos.x) {
+                winding += to.y > from.y ? 1 : -1;
+            }
+        }
+
+        vec2 dir = to - from;
+        float t = clamp(dot(vLocalPos - from, dir) / max(dot(dir, dir), 1.0e-6), 0.0, 1.0);
+        min_distance = min(min_distance, length(vLocalPos - from - dir * t));
+    }
+
+    bool inside = vFillRule == FILL_RULE_EVEN_ODD ? (winding & 1) != 0 : winding != 0;
+    float alpha = distance_aa(aa_range, inside ? -min_distance : min_distance);
+
+    vec4 color;
+    if (vPaintKind == PATH_PAINT_GRADIENT) {
+        vec2 dir = vPaintData.zw - vPaintData.xy;
+        float offset = dot(vLocalPos - vPaintData.xy, dir) / max(dot(dir, dir), 1.0e-6);
+        color = sample_gradient(vGradientAddress, offset, vGradientRepeat);
+    } else {
+        color = vPaintData;
+    }
+
+    oFragColor = color * alpha;
+}
+#endif
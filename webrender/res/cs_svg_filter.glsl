@@ -0,0 +1,389 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#include shared,prim_shared,blend
+
+// Note: has to match `SvgFilterOp` in svg_filter.rs
+#define FILTER_IDENTITY             0
+#define FILTER_FLOOD                1
+#define FILTER_OFFSET               2
+#define FILTER_COLOR_MATRIX         3
+#define FILTER_COMPONENT_TRANSFER   4
+#define FILTER_COMPOSITE            5
+#define FILTER_BLEND                6
+#define FILTER_MORPHOLOGY           7
+#define FILTER_TURBULENCE           8
+#define FILTER_DISPLACEMENT_MAP     9
+
+// Note: has to match the `SVG_FILTER_*` flags in svg_filter.rs
+#define FLAG_LINEAR_RGB             1
+#define FLAG_CONVERT_INPUT_1        2
+#define FLAG_CONVERT_INPUT_2        4
+
+#define COMPOSITE_OVER              0
+#define COMPOSITE_IN                1
+#define COMPOSITE_OUT               2
+#define COMPOSITE_ATOP              3
+#define COMPOSITE_XOR               4
+#define COMPOSITE_ARITHMETIC        5
+
+#define MORPHOLOGY_ERODE            0
+#define MORPHOLOGY_VERTICAL         1
+
+#define TURBULENCE_FRACTAL_NOISE    0
+
+// Upper bound for the octave loop in the fragment shader.
+#define MAX_TURBULENCE_OCTAVES      8
+
+// Layout of the turbulence tables in the GPU cache, see svg_filter.rs
+#define TURBULENCE_LATTICE_OFFSET   1
+#define TURBULENCE_GRADIENT_OFFSET  129
+#define TURBULENCE_GRADIENT_BLOCKS  128
+
+varying vec2 vInput1Uv;
+varying vec2 vInput2Uv;
+varying vec2 vUserPos;
+flat varying vec4 vInput1UvBounds;
+flat varying vec4 vInput2UvBounds;
+flat varying vec2 vInput1UvScale;
+flat varying vec2 vInput2UvScale;
+flat varying vec2 vInputLayers;
+flat varying vec4 vFilterData0;
+flat varying mat4 vColorMat;
+flat varying vec4 vColorOffset;
+flat varying int vFilterKind;
+flat varying int vFilterGenericInt;
+flat varying int vFilterFlags;
+flat varying int vFilterDataAddress;
+
+#ifdef WR_VERTEX_SHADER
+
+in int aFilterRenderTaskAddress;
+in int aFilterInput1TaskAddress;
+in int aFilterInput2TaskAddress;
+in int aFilterKind;
+in int aFilterGenericInt;
+in int aFilterFlags;
+in int aFilterExtraDataAddress;
+
+struct SvgFilterTask {
+    RenderTaskCommonData common_data;
+    vec2 origin;
+    float device_pixel_scale;
+};
+
+SvgFilterTask fetch_svg_filter_task(int address) {
+    RenderTaskData task_data = fetch_render_task_data(address);
+
+    SvgFilterTask task = SvgFilterTask(
+        task_data.common_data,
+        task_data.data1.xy,
+        task_data.data1.z
+    );
+
+    return task;
+}
+
+void main(void) {
+    SvgFilterTask filter_task = fetch_svg_filter_task(aFilterRenderTaskAddress);
+    RectWithSize target_rect = filter_task.common_data.task_rect;
+    float scale = filter_task.device_pixel_scale;
+
+    // The inputs cover the same area as this task, but may have
+    // been drawn at a smaller size (e.g. by a downscaled blur).
+    RenderTaskCommonData input_1_task = fetch_render_task_common_data(aFilterInput1TaskAddress);
+    RectWithSize input_1_rect = input_1_task.task_rect;
+    vec2 texture_size_1 = vec2(textureSize(sColor0, 0).xy);
+    vInput1Uv = (input_1_rect.p0 + input_1_rect.size * aPosition.xy) / texture_size_1;
+    vInput1UvBounds = vec4(input_1_rect.p0, input_1_rect.p0 + input_1_rect.size) / texture_size_1.xyxy;
+    vInput1UvScale = input_1_rect.size / target_rect.size / texture_size_1;
+
+    RenderTaskCommonData input_2_task = fetch_render_task_common_data(aFilterInput2TaskAddress);
+    RectWithSize input_2_rect = input_2_task.task_rect;
+    vec2 texture_size_2 = vec2(textureSize(sColor1, 0).xy);
+    vInput2Uv = (input_2_rect.p0 + input_2_rect.size * aPosition.xy) / texture_size_2;
+    vInput2UvBounds = vec4(input_2_rect.p0, input_2_rect.p0 + input_2_rect.size) / texture_size_2.xyxy;
+    vInput2UvScale = input_2_rect.size / target_rect.size / texture_size_2;
+
+    vInputLayers = vec2(input_1_task.texture_layer_index, input_2_task.texture_layer_index);
+
+    // Position in the local space of the filtered picture.
+    vUserPos = (target_rect.size * aPosition.xy - filter_task.origin) / scale;
+
+    vFilterKind = aFilterKind;
+    vFilterGenericInt = aFilterGenericInt;
+    vFilterFlags = aFilterFlags;
+    vFilterDataAddress = aFilterExtraDataAddress;
+    vFilterData0 = vec4(0.0);
+    vColorMat = mat4(1.0);
+    vColorOffset = vec4(0.0);
+
+    // Distances in the extra data are in local units, so
+    // scale them to device pixels here.
+    switch (aFilterKind) {
+        case FILTER_FLOOD:
+        case FILTER_COMPOSITE:
+        case FILTER_TURBULENCE:
+            vFilterData0 = fetch_from_resource_cache_1(aFilterExtraDataAddress);
+            break;
+        case FILTER_OFFSET:
+        case FILTER_MORPHOLOGY:
+        case FILTER_DISPLACEMENT_MAP:
+            vFilterData0 = fetch_from_resource_cache_1(aFilterExtraDataAddress) * scale;
+            break;
+        case FILTER_COLOR_MATRIX: {
+            vec4 mat_data[4] = fetch_from_resource_cache_4(aFilterExtraDataAddress);
+            vColorMat = mat4(mat_data[0], mat_data[1], mat_data[2], mat_data[3]);
+            vColorOffset = fetch_from_resource_cache_1(aFilterExtraDataAddress + 4);
+            break;
+        }
+        default: break;
+    }
+
+    vec2 pos = target_rect.p0 + target_rect.size * aPosition.xy;
+    gl_Position = uTransform * vec4(pos, 0.0, 1.0);
+}
+#endif
+
+#ifdef WR_FRAGMENT_SHADER
+vec4 fetch_filter_data(int offset) {
+    ivec2 uv = get_resource_cache_uv(vFilterDataAddress + offset);
+    return TEXEL_FETCH(sResourceCache, uv, 0, ivec2(0, 0));
+}
+
+vec4 unpremultiply(vec4 color) {
+    return color.a > 0.0 ? vec4(color.rgb / color.a, color.a) : vec4(0.0);
+}
+
+vec3 srgb_to_linear(vec3 color) {
+    vec3 c = clamp(color, vec3(0.0), vec3(1.0));
+    vec3 lo = c / 12.92;
+    vec3 hi = pow((c + 0.055) / 1.055, vec3(2.4));
+    return mix(lo, hi, step(vec3(0.04045), c));
+}
+
+vec3 linear_to_srgb(vec3 color) {
+    vec3 c = clamp(color, vec3(0.0), vec3(1.0));
+    vec3 lo = c * 12.92;
+    vec3 hi = 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055;
+    return mix(lo, hi, step(vec3(0.0031308), c));
+}
+
+// Converts a premultiplied input color into the color space
+// of this primitive.
+vec4 convert_color_space(vec4 color, bool to_linear) {
+    if (color.a == 0.0) {
+        return color;
+    }
+    vec3 rgb = color.rgb / color.a;
+    rgb = to_linear ? srgb_to_linear(rgb) : linear_to_srgb(rgb);
+    return vec4(rgb * color.a, color.a);
+}
+
+// Inputs are transparent black outside of their task rect.
+vec4 sample_input_1(vec2 offset) {
+    vec2 uv = vInput1Uv + offset * vInput1UvScale;
+    if (any(lessThan(uv, vInput1UvBounds.xy)) || any(greaterThan(uv, vInput1UvBounds.zw))) {
+        return vec4(0.0);
+    }
+    vec4 color = textureLod(sColor0, vec3(uv, vInputLayers.x), 0.0);
+    if ((vFilterFlags & FLAG_CONVERT_INPUT_1) != 0) {
+        color = convert_color_space(color, (vFilterFlags & FLAG_LINEAR_RGB) != 0);
+    }
+    return color;
+}
+
+vec4 sample_input_2(vec2 offset) {
+    vec2 uv = vInput2Uv + offset * vInput2UvScale;
+    if (any(lessThan(uv, vInput2UvBounds.xy)) || any(greaterThan(uv, vInput2UvBounds.zw))) {
+        return vec4(0.0);
+    }
+    vec4 color = textureLod(sColor1, vec3(uv, vInputLayers.y), 0.0);
+    if ((vFilterFlags & FLAG_CONVERT_INPUT_2) != 0) {
+        color = convert_color_space(color, (vFilterFlags & FLAG_LINEAR_RGB) != 0);
+    }
+    return color;
+}
+
+vec4 composite(vec4 Cs, vec4 Cb, int op) {
+    switch (op) {
+        case COMPOSITE_OVER:
+            return Cs + Cb * (1.0 - Cs.a);
+        case COMPOSITE_IN:
+            return Cs * Cb.a;
+        case COMPOSITE_OUT:
+            return Cs * (1.0 - Cb.a);
+        case COMPOSITE_ATOP:
+            return Cs * Cb.a + Cb * (1.0 - Cs.a);
+        case COMPOSITE_XOR:
+            return Cs * (1.0 - Cb.a) + Cb * (1.0 - Cs.a);
+        case COMPOSITE_ARITHMETIC: {
+            vec4 k = vFilterData0;
+            vec4 result = k.x * Cs * Cb + k.y * Cs + k.z * Cb + k.w;
+            result = clamp(result, vec4(0.0), vec4(1.0));
+            return vec4(min(result.rgb, vec3(result.a)), result.a);
+        }
+        default:
+            return vec4(0.0);
+    }
+}
+
+vec4 blend(vec4 Cs, vec4 Cb, int op) {
+    vec4 s = unpremultiply(Cs);
+    vec4 b = unpremultiply(Cb);
+    vec3 mixed = blend_colors(op, b.rgb, s.rgb);
+    vec3 rgb = (1.0 - Cb.a) * Cs.rgb +
+               (1.0 - Cs.a) * Cb.rgb +
+               Cs.a * Cb.a * mixed;
+    return vec4(rgb, Cs.a + Cb.a * (1.0 - Cs.a));
+}
+
+// One pass of a morphology, along `direction`. The rectangle is
+// covered by a horizontal pass followed by a vertical one.
+vec4 morphology(float radius, vec2 direction, bool erode) {
+    int r = max(int(radius + 0.5), 0);
+    vec4 result = erode ? vec4(1.0) : vec4(0.0);
+    for (int i = -r; i <= r; i++) {
+        vec4 color = sample_input_1(direction * float(i));
+        result = erode ? min(result, color) : max(result, color);
+    }
+    return result;
+}
+
+int turbulence_lattice(int index) {
+    vec4 block = fetch_filter_data(TURBULENCE_LATTICE_OFFSET + index / 4);
+    return int(block[index % 4]);
+}
+
+vec2 turbulence_gradient(int channel, int index) {
+    vec4 block = fetch_filter_data(TURBULENCE_GRADIENT_OFFSET +
+                                   channel * TURBULENCE_GRADIENT_BLOCKS +
+                                   index / 2);
+    return (index % 2) == 0 ? block.xy : block.zw;
+}
+
+// The noise function of the reference implementation in the
+// SVG specification, for all four channels at once.
+vec4 noise2(vec2 p) {
+    vec2 t = p + 4096.0;
+    ivec2 b0 = ivec2(t) & 255;
+    ivec2 b1 = (b0 + 1) & 255;
+    vec2 r0 = fract(t);
+    vec2 r1 = r0 - 1.0;
+
+    int i = turbulence_lattice(b0.x);
+    int j = turbulence_lattice(b1.x);
+    int b00 = turbulence_lattice(i + b0.y);
+    int b10 = turbulence_lattice(j + b0.y);
+    int b01 = turbulence_lattice(i + b1.y);
+    int b11 = turbulence_lattice(j + b1.y);
+
+    vec2 s = r0 * r0 * (3.0 - 2.0 * r0);
+
+    vec4 result;
+    for (int channel = 0; channel < 4; channel++) {
+        float u = dot(r0, turbulence_gradient(channel, b00));
+        float v = dot(vec2(r1.x, r0.y), turbulence_gradient(channel, b10));
+        float a = mix(u, v, s.x);
+        u = dot(vec2(r0.x, r1.y), turbulence_gradient(channel, b01));
+        v = dot(r1, turbulence_gradient(channel, b11));
+        float b = mix(u, v, s.x);
+        result[channel] = mix(a, b, s.y);
+    }
+    return result;
+}
+
+vec4 turbulence(vec2 point, bool fractal_noise) {
+    vec2 frequency = vFilterData0.xy;
+    int num_octaves = int(vFilterData0.z);
+
+    vec2 p = point * frequency;
+    vec4 sum = vec4(0.0);
+    float ratio = 1.0;
+    for (int octave = 0; octave < MAX_TURBULENCE_OCTAVES; octave++) {
+        if (octave >= num_octaves) {
+            break;
+        }
+        vec4 n = noise2(p);
+        sum += (fractal_noise ? n : abs(n)) / ratio;
+        p *= 2.0;
+        ratio *= 2.0;
+    }
+
+    vec4 color = fractal_noise ? (sum + 1.0) * 0.5 : sum;
+    color = clamp(color, vec4(0.0), vec4(1.0));
+    return vec4(color.rgb * color.a, color.a);
+}
+
+vec4 displacement_map(float scale, int x_channel, int y_channel) {
+    vec4 map = unpremultiply(sample_input_2(vec2(0.0)));
+    vec2 offset = scale * (vec2(map[x_channel], map[y_channel]) - 0.5);
+    return sample_input_1(offset);
+}
+
+void main(void) {
+    // Return red if none of the branches match (shouldn't happen).
+    vec4 result = vec4(1.0, 0.0, 0.0, 1.0);
+
+    switch (vFilterKind) {
+        case FILTER_IDENTITY:
+            result = sample_input_1(vec2(0.0));
+            break;
+        case FILTER_FLOOD:
+            result = vFilterData0;
+            break;
+        case FILTER_OFFSET:
+            result = sample_input_1(-vFilterData0.xy);
+            break;
+        case FILTER_COLOR_MATRIX: {
+            vec4 color = unpremultiply(sample_input_1(vec2(0.0)));
+            result = clamp(vColorMat * color + vColorOffset, vec4(0.0), vec4(1.0));
+            result.rgb *= result.a;
+            break;
+        }
+        case FILTER_COMPONENT_TRANSFER: {
+            vec4 color = unpremultiply(sample_input_1(vec2(0.0)));
+            ivec4 k = ivec4(color * 255.0 + 0.5);
+            result = vec4(
+                fetch_filter_data(k.r).r,
+                fetch_filter_data(k.g).g,
+                fetch_filter_data(k.b).b,
+                fetch_filter_data(k.a).a
+            );
+            result.rgb *= result.a;
+            break;
+        }
+        case FILTER_COMPOSITE:
+            result = composite(sample_input_1(vec2(0.0)),
+                               sample_input_2(vec2(0.0)),
+                               vFilterGenericInt);
+            break;
+        case FILTER_BLEND:
+            result = blend(sample_input_1(vec2(0.0)),
+                           sample_input_2(vec2(0.0)),
+                           vFilterGenericInt);
+            break;
+        case FILTER_MORPHOLOGY: {
+            bool erode = (vFilterGenericInt & 0xff) == MORPHOLOGY_ERODE;
+            if ((vFilterGenericInt >> 8) == MORPHOLOGY_VERTICAL) {
+                result = morphology(vFilterData0.y, vec2(0.0, 1.0), erode);
+            } else {
+                result = morphology(vFilterData0.x, vec2(1.0, 0.0), erode);
+            }
+            break;
+        }
+        case FILTER_TURBULENCE:
+            result = turbulence(vUserPos, vFilterGenericInt == TURBULENCE_FRACTAL_NOISE);
+            break;
+        case FILTER_DISPLACEMENT_MAP:
+            result = displacement_map(vFilterData0.x,
+                                      vFilterGenericInt & 0xff,
+                                      vFilterGenericInt >> 8);
+            break;
+        default: break;
+    }
+
+    oFragColor = result;
+}
+#endif
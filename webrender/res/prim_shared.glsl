@@ -46,6 +46,7 @@ varying vec3 vClipMaskUv;
 #define COLOR_MODE_SUBPX_DUAL_SOURCE  6
 #define COLOR_MODE_BITMAP             7
 #define COLOR_MODE_COLOR_BITMAP       8
+#define COLOR_MODE_SDF                9
 
 uniform HIGHP_SAMPLER_FLOAT sampler2D sLocalClipRects;
 
@@ -220,9 +221,7 @@ VertexInfo write_vertex(RectWithSize instance_rect,
 
     // Apply offsets for the render task to get correct screen location.
     vec2 snapped_device_pos = device_pos + snap_offset;
-    vec2 final_pos = snapped_device_pos -
-                     task.content_origin +
-                     task.common_data.task_rect.p0;
+    vec2 final_pos = picture_task_position(task, snapped_device_pos);
 
     gl_Position = uTransform * vec4(final_pos, z, 1.0);
 
@@ -297,7 +296,7 @@ VertexInfo write_transform_vertex(RectWithSize local_segment_rect,
 
     // Convert the world positions to device pixel space.
     vec2 device_pos = world_pos.xy / world_pos.w * uDevicePixelRatio;
-    vec2 task_offset = task.common_data.task_rect.p0 - task.content_origin;
+    vec2 final_device_pos = picture_task_position(task, device_pos);
 
     // Force w = 1, if we don't want perspective interpolation (for
     // example, drawing a screen-space quad on an element with a
@@ -308,7 +307,7 @@ VertexInfo write_transform_vertex(RectWithSize local_segment_rect,
     // We also want that to apply to any interpolators. However, we
     // want a constant Z across the primitive, since we're using it
     // for draw ordering - so scale by the W coord to ensure this.
-    vec4 final_pos = vec4(device_pos + task_offset, z, 1.0) * world_pos.w;
+    vec4 final_pos = vec4(final_device_pos, z, 1.0) * world_pos.w;
     gl_Position = uTransform * final_pos;
 
     init_transform_vs(mix(
@@ -356,11 +355,12 @@ struct TextRun {
     vec4 color;
     vec4 bg_color;
     vec2 offset;
+    float sdf_scale;
 };
 
 TextRun fetch_text_run(int address) {
     vec4 data[3] = fetch_from_resource_cache_3(address);
-    return TextRun(data[0], data[1], data[2].xy);
+    return TextRun(data[0], data[1], data[2].xy, data[2].z);
 }
 
 struct Image {
@@ -40,18 +40,16 @@ void main(void) {
     PictureTask src_task = fetch_picture_task(ci.src_task_index);
     PictureTask dest_task = fetch_picture_task(ci.render_task_index);
 
-    vec2 dest_origin = dest_task.common_data.task_rect.p0 -
-                       dest_task.content_origin;
-
     vec3 world_pos = bilerp(geometry.points[0], geometry.points[1],
                             geometry.points[3], geometry.points[2],
                             aPosition.y, aPosition.x);
-    vec4 final_pos = vec4((world_pos.xy + dest_origin) * uDevicePixelRatio, ci.z, 1.0);
+    vec2 dest_pos = picture_task_position(dest_task, world_pos.xy);
+    vec4 final_pos = vec4(dest_pos * uDevicePixelRatio, ci.z, 1.0);
 
     gl_Position = uTransform * final_pos;
 
     vec2 uv_origin = src_task.common_data.task_rect.p0;
-    vec2 uv_pos = uv_origin + world_pos.xy - src_task.content_origin;
+    vec2 uv_pos = picture_task_position(src_task, world_pos.xy);
     vec2 texture_size = vec2(textureSize(sCacheRGBA8, 0));
     vUv = vec3(uv_pos / texture_size, src_task.common_data.texture_layer_index);
     vUvTaskBounds = vec4(uv_origin, uv_origin + src_task.common_data.task_rect.size) / texture_size.xyxy;
@@ -13,6 +13,10 @@ flat varying vec2 vMaskSwizzle;
 varying vec4 vUvClip;
 #endif
 
+#ifdef WR_FEATURE_GLYPH_SDF
+flat varying float vSdf;
+#endif
+
 #ifdef WR_VERTEX_SHADER
 
 VertexInfo write_text_vertex(vec2 clamped_local_pos,
@@ -22,7 +26,7 @@ VertexInfo write_text_vertex(vec2 clamped_local_pos,
                              PictureTask task,
                              RectWithSize snap_rect,
                              vec2 snap_bias) {
-#if defined(WR_FEATURE_GLYPH_TRANSFORM) || !defined(WR_FEATURE_TRANSFORM)
+#if defined(WR_FEATURE_GLYPH_TRANSFORM) || !(defined(WR_FEATURE_TRANSFORM) || defined(WR_FEATURE_GLYPH_SDF))
     // Ensure the transform does not contain a subpixel translation to ensure
     // that glyph snapping is stable for equivalent glyph subpixel positions.
     scroll_node.transform[3].xy = floor(scroll_node.transform[3].xy + 0.5);
@@ -33,18 +37,15 @@ VertexInfo write_text_vertex(vec2 clamped_local_pos,
 
     // Convert the world positions to device pixel space.
     vec2 device_pos = world_pos.xy / world_pos.w * uDevicePixelRatio;
-
-    // Apply offsets for the render task to get correct screen location.
-    vec2 final_pos = device_pos -
-                     task.content_origin +
-                     task.common_data.task_rect.p0;
+    vec2 snapped_device_pos = device_pos;
 
 #ifdef WR_FEATURE_GLYPH_TRANSFORM
     // For transformed subpixels, we just need to align the glyph origin to a device pixel.
-    final_pos += floor(snap_rect.p0 + snap_bias) - snap_rect.p0;
-#elif !defined(WR_FEATURE_TRANSFORM)
+    snapped_device_pos += floor(snap_rect.p0 + snap_bias) - snap_rect.p0;
+#elif !defined(WR_FEATURE_TRANSFORM) && !defined(WR_FEATURE_GLYPH_SDF)
     // Compute the snapping offset only if the scroll node transform is axis-aligned.
-    final_pos += compute_snap_offset(
+    // Distance field glyphs are never snapped, so that they scale smoothly.
+    snapped_device_pos += compute_snap_offset(
         clamped_local_pos,
         scroll_node.transform,
         snap_rect,
@@ -52,6 +53,9 @@ VertexInfo write_text_vertex(vec2 clamped_local_pos,
     );
 #endif
 
+    // Apply offsets for the render task to get correct screen location.
+    vec2 final_pos = picture_task_position(task, snapped_device_pos);
+
     gl_Position = uTransform * vec4(final_pos, z, 1.0);
 
     VertexInfo vi = VertexInfo(
@@ -101,9 +105,15 @@ void main(void) {
     local_pos = rect_inside_rect(local_rect, prim.local_clip_rect) ?
                     inv * (glyph_rect.p0 + glyph_rect.size * aPosition.xy) :
                     clamp_rect(local_pos, prim.local_clip_rect);
+#else
+#ifdef WR_FEATURE_GLYPH_SDF
+    // Distance field glyphs are rasterized at a fixed size, independently
+    // of the device pixel ratio, so scale them to the font size.
+    float scale = res.scale * text.sdf_scale;
 #else
     // Scale from glyph space to local space.
     float scale = res.scale / uDevicePixelRatio;
+#endif
 
     // Compute the glyph rect in local space.
     RectWithSize glyph_rect = RectWithSize(scale * res.offset + text.offset + glyph.offset,
@@ -158,9 +168,14 @@ void main(void) {
 
     write_clip(vi.screen_pos, prim.clip_area);
 
+#ifdef WR_FEATURE_GLYPH_SDF
+    vSdf = float(color_mode == COLOR_MODE_SDF);
+#endif
+
     switch (color_mode) {
         case COLOR_MODE_ALPHA:
         case COLOR_MODE_BITMAP:
+        case COLOR_MODE_SDF:
             vMaskSwizzle = vec2(0.0, 1.0);
             vColor = text.color;
             break;
@@ -197,6 +212,15 @@ void main(void) {
 void main(void) {
     vec3 tc = vec3(clamp(vUv.xy, vUvBorder.xy, vUvBorder.zw), vUv.z);
     vec4 mask = texture(sColor0, tc);
+
+#ifdef WR_FEATURE_GLYPH_SDF
+    // Reconstruct the coverage from the distance to the glyph's edge,
+    // which lies at 0.5, anti-aliasing over about a device pixel.
+    float aa_range = 0.7 * fwidth(mask.a);
+    float coverage = smoothstep(0.5 - aa_range, 0.5 + aa_range, mask.a);
+    mask = mix(mask, vec4(coverage), vSdf);
+#endif
+
     mask.rgb = mask.rgb * vMaskSwizzle.x + mask.aaa * vMaskSwizzle.y;
 
     float alpha = do_clip();
@@ -68,10 +68,13 @@ RenderTaskCommonData fetch_render_task_common_data(int index) {
  The dynamic picture that this brush exists on. Right now, it
  contains minimal information. In the future, it will describe
  the transform mode of primitives on this picture, among other things.
+ The content is drawn scaled by content_scale when the picture doesn't
+ fit in a render target.
  */
 struct PictureTask {
     RenderTaskCommonData common_data;
     vec2 content_origin;
+    float content_scale;
 };
 
 PictureTask fetch_picture_task(int address) {
@@ -79,12 +82,19 @@ PictureTask fetch_picture_task(int address) {
 
     PictureTask task = PictureTask(
         task_data.common_data,
-        task_data.data1.xy
+        task_data.data1.xy,
+        task_data.data1.z
     );
 
     return task;
 }
 
+// Maps a device space position of the picture content to the render target.
+vec2 picture_task_position(PictureTask task, vec2 device_pos) {
+    return (device_pos - task.content_origin) * task.content_scale +
+           task.common_data.task_rect.p0;
+}
+
 struct ClipArea {
     RenderTaskCommonData common_data;
     vec2 screen_origin;
@@ -4,7 +4,7 @@
 
 use api::{AlphaType, ClipMode, DeviceIntRect, DeviceIntSize};
 use api::{DeviceUintRect, DeviceUintPoint, ExternalImageType, FilterOp, ImageRendering, LayoutRect};
-use api::{DeviceIntPoint, YuvColorSpace, YuvFormat};
+use api::{DeviceIntPoint, FontRenderMode, MaskComposite, YuvColorSpace, YuvFormat};
 use api::{LayoutToWorldTransform, WorldPixel};
 use clip::{ClipSource, ClipStore, ClipWorkItem};
 use clip_scroll_tree::{CoordinateSystemId};
@@ -56,6 +56,7 @@ pub enum BrushBatchKind {
     },
     YuvImage(ImageBufferKind, YuvFormat, YuvColorSpace),
     RadialGradient,
+    ConicGradient,
     LinearGradient,
 }
 
@@ -633,6 +634,7 @@ impl AlphaBatchBuilder {
                     BrushKind::Image { ref visible_tiles, .. } => !visible_tiles.is_empty(),
                     BrushKind::LinearGradient { ref visible_tiles, .. } => !visible_tiles.is_empty(),
                     BrushKind::RadialGradient { ref visible_tiles, .. } => !visible_tiles.is_empty(),
+                    BrushKind::ConicGradient { ref visible_tiles, .. } => !visible_tiles.is_empty(),
                     _ => false,
                 }
             }
@@ -673,6 +675,14 @@ impl AlphaBatchBuilder {
 
                 match brush.kind {
                     BrushKind::Picture { pic_index, .. } => {
+                        // A masked stacking context is skipped as a whole
+                        // until all of its mask layers are available, rather
+                        // than drawing it with a partial mask.
+                        if !mask_layers_are_ready(prim_metadata, ctx) {
+                            warn!("Warnings: skip a masked picture");
+                            return;
+                        }
+
                         let picture =
                             &ctx.prim_store.pictures[pic_index.0];
 
@@ -947,7 +957,8 @@ impl AlphaBatchBuilder {
                                 batch.push(PrimitiveInstance::from(instance));
                                 false
                             }
-                            Some(PictureCompositeMode::Blit) => {
+                            Some(PictureCompositeMode::Blit) |
+                            Some(PictureCompositeMode::SvgFilter) => {
                                 let cache_task_id = picture
                                     .surface
                                     .as_ref()
@@ -956,10 +967,23 @@ impl AlphaBatchBuilder {
                                 let kind = BatchKind::Brush(
                                     BrushBatchKind::Image(ImageBufferKind::Texture2DArray)
                                 );
+                                // If backdrop filters sampled this picture, its
+                                // target was saved, and may be more than one
+                                // pass behind by now.
+                                let textures = match render_tasks[cache_task_id].saved_index {
+                                    Some(saved_index) => BatchTextures {
+                                        colors: [
+                                            SourceTexture::RenderTaskCache(saved_index),
+                                            SourceTexture::Invalid,
+                                            SourceTexture::Invalid,
+                                        ],
+                                    },
+                                    None => BatchTextures::render_target_cache(),
+                                };
                                 let key = BatchKey::new(
                                     kind,
                                     non_segmented_blend_mode,
-                                    BatchTextures::render_target_cache(),
+                                    textures,
                                 );
                                 let batch = self.batch_list.get_suitable_batch(
                                     key,
@@ -1067,6 +1091,68 @@ impl AlphaBatchBuilder {
                             &mut self.batch_list,
                         );
                     }
+                    BrushKind::ConicGradient { ref stops_handle, ref visible_tiles, .. } if !visible_tiles.is_empty() => {
+                        add_gradient_tiles(
+                            visible_tiles,
+                            stops_handle,
+                            BrushBatchKind::ConicGradient,
+                            specified_blend_mode,
+                            &task_relative_bounding_rect,
+                            clip_chain_rect_index,
+                            scroll_id,
+                            task_address,
+                            clip_task_address,
+                            z,
+                            gpu_cache,
+                            &mut self.batch_list,
+                        );
+                    }
+                    BrushKind::Backdrop { pic_index } => {
+                        let source = &ctx.prim_store.pictures[pic_index.0];
+
+                        // Draw the backdrop picture surface through the blend
+                        // shader, which samples it in screen space, with a
+                        // pass-through filter. The actual filters are applied
+                        // by the pictures this primitive is drawn into.
+                        if let Some(PictureSurface::RenderTask(source_task_id)) = source.surface {
+                            let saved_index = render_tasks[source_task_id]
+                                .saved_index
+                                .expect("bug: backdrop surface not saved");
+                            let textures = BatchTextures {
+                                colors: [
+                                    SourceTexture::RenderTaskCache(saved_index),
+                                    SourceTexture::Invalid,
+                                    SourceTexture::Invalid,
+                                ],
+                            };
+                            let key = BatchKey::new(
+                                BatchKind::Brush(BrushBatchKind::Blend),
+                                BlendMode::PremultipliedAlpha,
+                                textures,
+                            );
+                            let source_task_address = render_tasks.get_task_address(source_task_id);
+
+                            let instance = BrushInstance {
+                                picture_address: task_address,
+                                prim_address: prim_cache_address,
+                                clip_chain_rect_index,
+                                scroll_id,
+                                clip_task_address,
+                                z,
+                                segment_index: 0,
+                                edge_flags: EdgeAaSegmentMask::empty(),
+                                brush_flags: BrushFlags::empty(),
+                                user_data: [
+                                    source_task_address.0 as i32,
+                                    0,
+                                    0,
+                                ],
+                            };
+
+                            let batch = self.batch_list.get_suitable_batch(key, &task_relative_bounding_rect);
+                            batch.push(PrimitiveInstance::from(instance));
+                        }
+                    }
                     _ => {
                         if let Some((batch_kind, textures, user_data)) = brush.get_batch_params(
                                 ctx.resource_cache,
@@ -1131,9 +1217,18 @@ impl AlphaBatchBuilder {
                             ],
                         };
 
+                        // Distance field runs draw all of their glyphs with the
+                        // distance field shader, which also scales the bitmap
+                        // glyphs that can't be converted to distance fields.
+                        let batch_format = if text_cpu.font.render_mode == FontRenderMode::Sdf {
+                            GlyphFormat::Sdf
+                        } else {
+                            glyph_format
+                        };
+
                         let kind = BatchKind::Transformable(
                             transform_kind,
-                            TransformBatchKind::TextRun(glyph_format),
+                            TransformBatchKind::TextRun(batch_format),
                         );
 
                         let (blend_mode, color_mode) = match glyph_format {
@@ -1175,6 +1270,12 @@ impl AlphaBatchBuilder {
                                     ShaderColorMode::ColorBitmap,
                                 )
                             }
+                            GlyphFormat::Sdf => {
+                                (
+                                    BlendMode::PremultipliedAlpha,
+                                    ShaderColorMode::Sdf,
+                                )
+                            }
                         };
 
                         let key = BatchKey::new(kind, blend_mode, textures);
@@ -1507,10 +1608,38 @@ impl BrushPrimitive {
                     ))
                 }
             }
-            BrushKind::Picture { .. } => {
+            BrushKind::Path { ref handle, .. } => {
+                let rt_handle = handle
+                    .as_ref()
+                    .expect("bug: render task handle not allocated");
+                let rt_cache_entry = resource_cache
+                    .get_cached_render_task(rt_handle);
+                let cache_item = resource_cache
+                    .get_texture_cache_item(&rt_cache_entry.handle);
+
+                if cache_item.texture_id == SourceTexture::Invalid {
+                    None
+                } else {
+                    let textures = BatchTextures::color(cache_item.texture_id);
+
+                    Some((
+                        BrushBatchKind::Image(get_buffer_kind(cache_item.texture_id)),
+                        textures,
+                        [
+                            cache_item.uv_rect_handle.as_int(gpu_cache),
+                            (ShaderColorMode::ColorBitmap as i32) << 16|
+                             RasterizationSpace::Local as i32,
+                            0,
+                        ],
+                    ))
+                }
+            }
+            BrushKind::Picture { .. } |
+            BrushKind::Backdrop { .. } => {
                 panic!("bug: get_batch_key is handled at higher level for pictures");
             }
-            BrushKind::Solid { .. } => {
+            BrushKind::Solid { .. } |
+            BrushKind::TextDecoration { .. } => {
                 Some((
                     BrushBatchKind::Solid,
                     BatchTextures::no_texture(),
@@ -1535,6 +1664,17 @@ impl BrushPrimitive {
                     ],
                 ))
             }
+            BrushKind::ConicGradient { ref stops_handle, .. } => {
+                Some((
+                    BrushBatchKind::ConicGradient,
+                    BatchTextures::no_texture(),
+                    [
+                        stops_handle.as_int(gpu_cache),
+                        0,
+                        0,
+                    ],
+                ))
+            }
             BrushKind::LinearGradient { ref stops_handle, .. } => {
                 Some((
                     BrushBatchKind::LinearGradient,
@@ -1632,11 +1772,15 @@ impl AlphaBatchHelpers for PrimitiveStore {
                         }
                     }
                     BrushKind::Solid { .. } |
+                    BrushKind::TextDecoration { .. } |
                     BrushKind::YuvImage { .. } |
                     BrushKind::RadialGradient { .. } |
+                    BrushKind::ConicGradient { .. } |
                     BrushKind::LinearGradient { .. } |
                     BrushKind::Border { .. } |
-                    BrushKind::Picture { .. } => {
+                    BrushKind::Path { .. } |
+                    BrushKind::Picture { .. } |
+                    BrushKind::Backdrop { .. } => {
                         BlendMode::PremultipliedAlpha
                     }
                 }
@@ -1766,6 +1910,44 @@ fn make_polygon(
     Polygon::from_transformed_rect(rect.cast().unwrap(), mat, anchor)
 }
 
+/// Returns false if any of the image mask layers in the local clip of a
+/// primitive hasn't been rasterized.
+fn mask_layers_are_ready(
+    prim_metadata: &PrimitiveMetadata,
+    ctx: &RenderTargetContext,
+) -> bool {
+    let clip_sources = match prim_metadata.clip_sources {
+        Some(ref handle) => ctx.clip_store.get(handle),
+        None => return true,
+    };
+
+    clip_sources.clips().iter().all(|&(ref source, _)| {
+        let key = match *source {
+            ClipSource::MaskLayer(ref layer) => layer.image_key(),
+            _ => None,
+        };
+        key.map_or(true, |key| {
+            ctx.resource_cache.get_cached_image(
+                ImageRequest {
+                    key,
+                    rendering: ImageRendering::Auto,
+                    tile: None,
+                }
+            ).is_ok()
+        })
+    })
+}
+
+/// A run of mask layers drawn with the same texture and composite operator.
+#[derive(Debug)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct MaskLayerBatch {
+    pub texture: SourceTexture,
+    pub composite: MaskComposite,
+    pub instances: Vec<ClipMaskInstance>,
+}
+
 /// Batcher managing draw calls into the clip mask (in the RT cache).
 #[derive(Debug)]
 #[cfg_attr(feature = "capture", derive(Serialize))]
@@ -1777,6 +1959,11 @@ pub struct ClipBatcher {
     pub images: FastHashMap<SourceTexture, Vec<ClipMaskInstance>>,
     pub box_shadows: FastHashMap<SourceTexture, Vec<ClipMaskInstance>>,
     pub line_decorations: Vec<ClipMaskInstance>,
+    pub paths: Vec<ClipMaskInstance>,
+    /// Mask layers are composited with each other, so unlike the other
+    /// clips they are drawn in order, before the clips that multiply
+    /// with their result.
+    pub mask_layers: Vec<MaskLayerBatch>,
 }
 
 impl ClipBatcher {
@@ -1786,7 +1973,29 @@ impl ClipBatcher {
             images: FastHashMap::default(),
             box_shadows: FastHashMap::default(),
             line_decorations: Vec::new(),
+            paths: Vec::new(),
+            mask_layers: Vec::new(),
+        }
+    }
+
+    fn add_mask_layer(
+        &mut self,
+        texture: SourceTexture,
+        composite: MaskComposite,
+        instance: ClipMaskInstance,
+    ) {
+        if let Some(batch) = self.mask_layers.last_mut() {
+            if batch.texture == texture && batch.composite == composite {
+                batch.instances.push(instance);
+                return;
+            }
         }
+
+        self.mask_layers.push(MaskLayerBatch {
+            texture,
+            composite,
+            instances: vec![instance],
+        });
     }
 
     pub fn add_clip_region(
@@ -1853,12 +2062,59 @@ impl ClipBatcher {
                             continue;
                         }
                     }
+                    ClipSource::MaskLayer(ref layer) => {
+                        match layer.image_key() {
+                            Some(key) => {
+                                if let Ok(cache_item) = resource_cache.get_cached_image(
+                                    ImageRequest {
+                                        key,
+                                        rendering: ImageRendering::Auto,
+                                        tile: None,
+                                    }
+                                ) {
+                                    self.add_mask_layer(
+                                        cache_item.texture_id,
+                                        layer.composite(),
+                                        ClipMaskInstance {
+                                            clip_data_address: gpu_address,
+                                            resource_address: gpu_cache.get_address(&cache_item.uv_rect_handle),
+                                            ..instance
+                                        },
+                                    );
+                                } else {
+                                    // The masked picture isn't drawn either,
+                                    // see `mask_layers_are_ready`.
+                                    debug!("Skip a mask layer, Key:{:?}", key);
+                                    continue;
+                                }
+                            }
+                            None => {
+                                // Gradients don't sample a texture, but the
+                                // resource address still has to be valid.
+                                self.add_mask_layer(
+                                    SourceTexture::Invalid,
+                                    layer.composite(),
+                                    ClipMaskInstance {
+                                        clip_data_address: gpu_address,
+                                        resource_address: gpu_address,
+                                        ..instance
+                                    },
+                                );
+                            }
+                        }
+                    }
                     ClipSource::LineDecoration(..) => {
                         self.line_decorations.push(ClipMaskInstance {
                             clip_data_address: gpu_address,
                             ..instance
                         });
                     }
+                    ClipSource::Path(..) => {
+                        self.paths.push(ClipMaskInstance {
+                            clip_data_address: gpu_address,
+                            ..instance
+                        });
+                    }
                     ClipSource::BoxShadow(ref info) => {
                         let rt_handle = info
                             .cache_handle
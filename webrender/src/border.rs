@@ -5,7 +5,9 @@
 use api::{BorderRadius, BorderSide, BorderStyle, BorderWidths, ColorF};
 use api::{ColorU, DeviceRect, DeviceSize, LayoutSizeAu, LayoutPrimitiveInfo, LayoutToDeviceScale};
 use api::{DevicePixel, DeviceVector2D, DevicePoint, DeviceIntSize, LayoutRect, LayoutSize, NormalBorder};
+use api::{NinePatchBorder, RepeatMode, TexelRect};
 use app_units::Au;
+use euclid::SideOffsets2D;
 use ellipse::Ellipse;
 use display_list_flattener::DisplayListFlattener;
 use gpu_types::{BorderInstance, BorderSegment, BrushFlags};
@@ -1157,3 +1159,556 @@ fn add_edge_segment(
         brush_segments,
     );
 }
+
+/// The maximum number of segments of a primitive of a 9-part border. Each
+/// segment takes two blocks in the GPU cache entry of the primitive, which
+/// must fit in a single row of the GPU cache, so borders with more tiles are
+/// split into several primitives.
+const MAX_NINE_PATCH_SEGMENTS: usize = 256;
+
+/// The maximum number of tiles of a part of a 9-part border along one axis,
+/// and in total. Tiles that are tiny compared to their part are enlarged to
+/// stay within these.
+const MAX_NINE_PATCH_AXIS_TILES: f32 = 1024.0;
+const MAX_NINE_PATCH_PART_TILES: f32 = 16384.0;
+
+/// The source of a 9-part border: the size of the 9-part image, where it is
+/// sliced, and how its parts fill the border.
+#[derive(Debug, Copy, Clone)]
+pub struct NinePatchDescriptor {
+    pub width: f32,
+    pub height: f32,
+    pub slice: SideOffsets2D<f32>,
+    pub fill: bool,
+    pub repeat_horizontal: RepeatMode,
+    pub repeat_vertical: RepeatMode,
+}
+
+impl<'a> From<&'a NinePatchBorder> for NinePatchDescriptor {
+    fn from(border: &'a NinePatchBorder) -> Self {
+        NinePatchDescriptor {
+            width: border.width as f32,
+            height: border.height as f32,
+            slice: SideOffsets2D::new(
+                border.slice.top as f32,
+                border.slice.right as f32,
+                border.slice.bottom as f32,
+                border.slice.left as f32,
+            ),
+            fill: border.fill,
+            repeat_horizontal: border.repeat_horizontal,
+            repeat_vertical: border.repeat_vertical,
+        }
+    }
+}
+
+impl NinePatchDescriptor {
+    /// A 9-part image covering the whole border, with its parts stretched
+    /// over the border, as used by gradient borders.
+    pub fn stretched(size: LayoutSize, widths: &BorderWidths) -> Self {
+        NinePatchDescriptor {
+            width: size.width,
+            height: size.height,
+            slice: SideOffsets2D::new(widths.top, widths.right, widths.bottom, widths.left),
+            fill: false,
+            repeat_horizontal: RepeatMode::Stretch,
+            repeat_vertical: RepeatMode::Stretch,
+        }
+    }
+}
+
+/// A tile of a part of a 9-part border along one axis. It covers the range
+/// from start to end of the border, and shows the range from uv_start to
+/// uv_end (as fractions) of the part.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct NinePatchTile {
+    start: f32,
+    end: f32,
+    uv_start: f32,
+    uv_end: f32,
+}
+
+impl NinePatchTile {
+    fn whole(start: f32, end: f32) -> Self {
+        NinePatchTile {
+            start,
+            end,
+            uv_start: 0.0,
+            uv_end: 1.0,
+        }
+    }
+}
+
+/// The number of tiles of the given size along an axis of a part, before
+/// rounding, or None if the part isn't tiled along the axis.
+fn nine_patch_tile_count(size: f32, tile_size: f32, mode: RepeatMode) -> Option<f32> {
+    if mode == RepeatMode::Stretch || tile_size <= 0.0 || !tile_size.is_finite() {
+        None
+    } else {
+        Some(size / tile_size)
+    }
+}
+
+/// Enlarges the tiles of a part if there would be too many of them.
+fn bound_nine_patch_tile_size(
+    size: LayoutSize,
+    tile_size: LayoutSize,
+    repeat_horizontal: RepeatMode,
+    repeat_vertical: RepeatMode,
+) -> LayoutSize {
+    let columns = nine_patch_tile_count(size.width, tile_size.width, repeat_horizontal);
+    let rows = nine_patch_tile_count(size.height, tile_size.height, repeat_vertical);
+
+    let mut scale_x = columns.map_or(1.0, |columns| columns / MAX_NINE_PATCH_AXIS_TILES).max(1.0);
+    let mut scale_y = rows.map_or(1.0, |rows| rows / MAX_NINE_PATCH_AXIS_TILES).max(1.0);
+
+    // Parts tiled along both axes are also bounded in area. Both counts are
+    // larger than 16 if this applies, as each is within the axis limit.
+    if let (Some(columns), Some(rows)) = (columns, rows) {
+        let area_scale = (columns / scale_x * rows / scale_y / MAX_NINE_PATCH_PART_TILES).sqrt();
+        if area_scale > 1.0 {
+            scale_x *= area_scale;
+            scale_y *= area_scale;
+        }
+    }
+
+    LayoutSize::new(tile_size.width * scale_x, tile_size.height * scale_y)
+}
+
+/// Lays out the tiles of a part of a 9-part border along one axis of its area,
+/// following the border-image-repeat rules of CSS.
+fn layout_nine_patch_tiles(
+    start: f32,
+    size: f32,
+    tile_size: f32,
+    mode: RepeatMode,
+) -> Vec<NinePatchTile> {
+    if size <= 0.0 {
+        return Vec::new();
+    }
+
+    let end = start + size;
+    if nine_patch_tile_count(size, tile_size, mode).is_none() {
+        return vec![NinePatchTile::whole(start, end)];
+    }
+
+    match mode {
+        RepeatMode::Stretch => unreachable!(),
+        RepeatMode::Round => {
+            // Scale the tiles so that a whole number of them fills the area.
+            let count = (size / tile_size).round().max(1.0);
+            let tile_size = size / count;
+            (0 .. count as usize)
+                .map(|i| {
+                    let tile_start = start + i as f32 * tile_size;
+                    NinePatchTile::whole(tile_start, tile_start + tile_size)
+                })
+                .collect()
+        }
+        RepeatMode::Space => {
+            // Place as many whole tiles as fit, with equal gaps around them.
+            let count = (size / tile_size).floor();
+            let spacing = (size - count * tile_size) / (count + 1.0);
+            (0 .. count as usize)
+                .map(|i| {
+                    let tile_start = start + spacing + i as f32 * (tile_size + spacing);
+                    NinePatchTile::whole(tile_start, tile_start + tile_size)
+                })
+                .collect()
+        }
+        RepeatMode::Repeat => {
+            // One tile is centered in the area, and the others are laid out
+            // from it in both directions, clipped at the ends of the area.
+            let centered = start + (size - tile_size) * 0.5;
+            let mut tile_start = centered - ((centered - start) / tile_size).ceil() * tile_size;
+            let mut tiles = Vec::new();
+            while tile_start < end {
+                let visible_start = tile_start.max(start);
+                let visible_end = (tile_start + tile_size).min(end);
+                if visible_end > visible_start {
+                    tiles.push(NinePatchTile {
+                        start: visible_start,
+                        end: visible_end,
+                        uv_start: (visible_start - tile_start) / tile_size,
+                        uv_end: (visible_end - tile_start) / tile_size,
+                    });
+                }
+                tile_start += tile_size;
+            }
+            tiles
+        }
+    }
+}
+
+/// The scale from a slice of a 9-part image to the border width it fills,
+/// if it is neither zero nor infinite.
+fn nine_patch_scale(width: f32, slice: f32) -> Option<f32> {
+    let scale = width / slice;
+    if scale > 0.0 && scale.is_finite() {
+        Some(scale)
+    } else {
+        None
+    }
+}
+
+/// Builds the brush segments of a 9-part border covering `rect`, in groups
+/// that each fit in a primitive. The parts are sized and tiled as in CSS
+/// border-image, and the UV rects of the segments are in the width x height
+/// space of the 9-part image.
+pub fn create_nine_patch_segments(
+    rect: &LayoutRect,
+    widths: &BorderWidths,
+    nine_patch: &NinePatchDescriptor,
+) -> Vec<Vec<BrushSegment>> {
+    build_nine_patch_segments(rect, widths, nine_patch)
+        .chunks(MAX_NINE_PATCH_SEGMENTS)
+        .map(|segments| segments.to_vec())
+        .collect()
+}
+
+fn build_nine_patch_segments(
+    rect: &LayoutRect,
+    widths: &BorderWidths,
+    nine_patch: &NinePatchDescriptor,
+) -> Vec<BrushSegment> {
+    // If the widths of opposite sides don't fit in the area, they are all
+    // reduced proportionally.
+    let width_scale = (rect.size.width / (widths.left + widths.right))
+        .min(rect.size.height / (widths.top + widths.bottom))
+        .min(1.0);
+    let widths = BorderWidths {
+        left: widths.left * width_scale,
+        top: widths.top * width_scale,
+        right: widths.right * width_scale,
+        bottom: widths.bottom * width_scale,
+    };
+
+    // Calculate the local texel coords of the slices. As in CSS, each slice
+    // is clamped to the size of the image, but slices may still overlap: the
+    // corners then show the same parts of the image, and if the slices of
+    // opposite sides meet or overlap, the edges between them and the center
+    // are empty.
+    let slice = SideOffsets2D::new(
+        nine_patch.slice.top.max(0.0).min(nine_patch.height),
+        nine_patch.slice.right.max(0.0).min(nine_patch.width),
+        nine_patch.slice.bottom.max(0.0).min(nine_patch.height),
+        nine_patch.slice.left.max(0.0).min(nine_patch.width),
+    );
+
+    let px0 = 0.0;
+    let px1 = slice.left;
+    let px2 = nine_patch.width - slice.right;
+    let px3 = nine_patch.width;
+
+    let py0 = 0.0;
+    let py1 = slice.top;
+    let py2 = nine_patch.height - slice.bottom;
+    let py3 = nine_patch.height;
+
+    let x0 = rect.origin.x;
+    let x1 = x0 + widths.left;
+    let x3 = rect.origin.x + rect.size.width;
+    let x2 = x3 - widths.right;
+
+    let y0 = rect.origin.y;
+    let y1 = y0 + widths.top;
+    let y3 = rect.origin.y + rect.size.height;
+    let y2 = y3 - widths.bottom;
+
+    // The edge parts are scaled so that they are as thick as the border, and
+    // the center part by the same factors as the top and left edges.
+    let scale_top = nine_patch_scale(widths.top, py1 - py0);
+    let scale_bottom = nine_patch_scale(widths.bottom, py3 - py2);
+    let scale_left = nine_patch_scale(widths.left, px1 - px0);
+    let scale_right = nine_patch_scale(widths.right, px3 - px2);
+
+    let middle_width = px2 - px1;
+    let middle_height = py2 - py1;
+    let top_bottom_empty = middle_width <= 0.0;
+    let left_right_empty = middle_height <= 0.0;
+
+    let mut parts = vec![
+        // Top left
+        (
+            LayoutRect::from_floats(x0, y0, x1, y1),
+            TexelRect::new(px0, py0, px1, py1),
+            LayoutSize::zero(),
+            RepeatMode::Stretch,
+            RepeatMode::Stretch,
+        ),
+        // Top right
+        (
+            LayoutRect::from_floats(x2, y0, x3, y1),
+            TexelRect::new(px2, py0, px3, py1),
+            LayoutSize::zero(),
+            RepeatMode::Stretch,
+            RepeatMode::Stretch,
+        ),
+        // Bottom right
+        (
+            LayoutRect::from_floats(x2, y2, x3, y3),
+            TexelRect::new(px2, py2, px3, py3),
+            LayoutSize::zero(),
+            RepeatMode::Stretch,
+            RepeatMode::Stretch,
+        ),
+        // Bottom left
+        (
+            LayoutRect::from_floats(x0, y2, x1, y3),
+            TexelRect::new(px0, py2, px1, py3),
+            LayoutSize::zero(),
+            RepeatMode::Stretch,
+            RepeatMode::Stretch,
+        ),
+    ];
+
+    if !top_bottom_empty {
+        parts.push((
+            // Top
+            LayoutRect::from_floats(x1, y0, x2, y1),
+            TexelRect::new(px1, py0, px2, py1),
+            LayoutSize::new(middle_width * scale_top.unwrap_or(1.0), 0.0),
+            nine_patch.repeat_horizontal,
+            RepeatMode::Stretch,
+        ));
+        parts.push((
+            // Bottom
+            LayoutRect::from_floats(x1, y2, x2, y3),
+            TexelRect::new(px1, py2, px2, py3),
+            LayoutSize::new(middle_width * scale_bottom.unwrap_or(1.0), 0.0),
+            nine_patch.repeat_horizontal,
+            RepeatMode::Stretch,
+        ));
+    }
+
+    if !left_right_empty {
+        parts.push((
+            // Left
+            LayoutRect::from_floats(x0, y1, x1, y2),
+            TexelRect::new(px0, py1, px1, py2),
+            LayoutSize::new(0.0, middle_height * scale_left.unwrap_or(1.0)),
+            RepeatMode::Stretch,
+            nine_patch.repeat_vertical,
+        ));
+        parts.push((
+            // Right
+            LayoutRect::from_floats(x2, y1, x3, y2),
+            TexelRect::new(px2, py1, px3, py2),
+            LayoutSize::new(0.0, middle_height * scale_right.unwrap_or(1.0)),
+            RepeatMode::Stretch,
+            nine_patch.repeat_vertical,
+        ));
+    }
+
+    if nine_patch.fill && !top_bottom_empty && !left_right_empty {
+        parts.push((
+            LayoutRect::from_floats(x1, y1, x2, y2),
+            TexelRect::new(px1, py1, px2, py2),
+            LayoutSize::new(
+                middle_width * scale_top.or(scale_bottom).unwrap_or(1.0),
+                middle_height * scale_left.or(scale_right).unwrap_or(1.0),
+            ),
+            nine_patch.repeat_horizontal,
+            nine_patch.repeat_vertical,
+        ));
+    }
+
+    let mut segments = Vec::new();
+
+    for &(part_rect, uv_rect, tile_size, repeat_horizontal, repeat_vertical) in &parts {
+        if uv_rect.uv1.x <= uv_rect.uv0.x || uv_rect.uv1.y <= uv_rect.uv0.y {
+            continue;
+        }
+
+        let tile_size = bound_nine_patch_tile_size(
+            part_rect.size,
+            tile_size,
+            repeat_horizontal,
+            repeat_vertical,
+        );
+        let columns = layout_nine_patch_tiles(
+            part_rect.origin.x,
+            part_rect.size.width,
+            tile_size.width,
+            repeat_horizontal,
+        );
+        let rows = layout_nine_patch_tiles(
+            part_rect.origin.y,
+            part_rect.size.height,
+            tile_size.height,
+            repeat_vertical,
+        );
+
+        for row in &rows {
+            for column in &columns {
+                // Use segment relative interpolation for all
+                // instances in this primitive.
+                segments.push(BrushSegment::new(
+                    LayoutRect::from_floats(column.start, row.start, column.end, row.end),
+                    true,
+                    EdgeAaSegmentMask::empty(),
+                    [
+                        lerp(uv_rect.uv0.x, uv_rect.uv1.x, column.uv_start),
+                        lerp(uv_rect.uv0.y, uv_rect.uv1.y, row.uv_start),
+                        lerp(uv_rect.uv0.x, uv_rect.uv1.x, column.uv_end),
+                        lerp(uv_rect.uv0.y, uv_rect.uv1.y, row.uv_end),
+                    ],
+                    BrushFlags::SEGMENT_RELATIVE,
+                ));
+            }
+        }
+    }
+
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use api::{BorderWidths, LayoutPoint, LayoutRect, LayoutSize, RepeatMode};
+    use euclid::SideOffsets2D;
+    use super::{bound_nine_patch_tile_size, create_nine_patch_segments, layout_nine_patch_tiles};
+    use super::{MAX_NINE_PATCH_SEGMENTS, NinePatchDescriptor, NinePatchTile};
+
+    fn nine_patch(slice: f32, repeat: RepeatMode) -> NinePatchDescriptor {
+        NinePatchDescriptor {
+            width: 30.0,
+            height: 30.0,
+            slice: SideOffsets2D::new_all_same(slice),
+            fill: true,
+            repeat_horizontal: repeat,
+            repeat_vertical: repeat,
+        }
+    }
+
+    fn widths(width: f32) -> BorderWidths {
+        BorderWidths {
+            left: width,
+            top: width,
+            right: width,
+            bottom: width,
+        }
+    }
+
+    #[test]
+    fn nine_patch_tiles_round() {
+        // 100 / 30 rounds to three tiles.
+        let tiles = layout_nine_patch_tiles(10.0, 100.0, 30.0, RepeatMode::Round);
+        assert_eq!(tiles.len(), 3);
+        assert_eq!(tiles[0].start, 10.0);
+        assert!((tiles[2].end - 110.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn nine_patch_tiles_space() {
+        let tiles = layout_nine_patch_tiles(0.0, 100.0, 30.0, RepeatMode::Space);
+        assert_eq!(tiles, vec![
+            NinePatchTile::whole(2.5, 32.5),
+            NinePatchTile::whole(35.0, 65.0),
+            NinePatchTile::whole(67.5, 97.5),
+        ]);
+
+        // A tile larger than the area leaves it empty.
+        assert!(layout_nine_patch_tiles(0.0, 20.0, 30.0, RepeatMode::Space).is_empty());
+    }
+
+    #[test]
+    fn nine_patch_tiles_repeat() {
+        // The middle tile is centered, and the ones at the ends are clipped.
+        let tiles = layout_nine_patch_tiles(0.0, 100.0, 40.0, RepeatMode::Repeat);
+        assert_eq!(tiles, vec![
+            NinePatchTile { start: 0.0, end: 30.0, uv_start: 0.25, uv_end: 1.0 },
+            NinePatchTile::whole(30.0, 70.0),
+            NinePatchTile { start: 70.0, end: 100.0, uv_start: 0.0, uv_end: 0.75 },
+        ]);
+    }
+
+    #[test]
+    fn nine_patch_slices_clamped_to_image() {
+        let rect = LayoutRect::new(LayoutPoint::zero(), LayoutSize::new(100.0, 100.0));
+        let mut descriptor = nine_patch(10.0, RepeatMode::Stretch);
+        descriptor.slice = SideOffsets2D::new(10.0, 80.0, 45.0, 10.0);
+
+        let segments: Vec<_> = create_nine_patch_segments(&rect, &widths(20.0), &descriptor)
+            .into_iter()
+            .flat_map(|segments| segments)
+            .collect();
+        assert!(!segments.is_empty());
+        for segment in &segments {
+            let uv = segment.extra_data;
+            assert!(uv[0] >= 0.0 && uv[2] <= 30.0, "{:?}", uv);
+            assert!(uv[1] >= 0.0 && uv[3] <= 30.0, "{:?}", uv);
+        }
+    }
+
+    #[test]
+    fn nine_patch_tile_size_bounds() {
+        let size = LayoutSize::new(100000.0, 100000.0);
+
+        // Tiles along a single axis are only bounded by the axis limit.
+        let tile_size = bound_nine_patch_tile_size(
+            size,
+            LayoutSize::new(10.0, 0.0),
+            RepeatMode::Round,
+            RepeatMode::Stretch,
+        );
+        assert_eq!(tile_size.width, 100000.0 / 1024.0);
+
+        let tile_size = bound_nine_patch_tile_size(
+            size,
+            LayoutSize::new(10.0, 10.0),
+            RepeatMode::Space,
+            RepeatMode::Space,
+        );
+        assert!((size.width / tile_size.width * size.height / tile_size.height - 16384.0).abs() < 1.0);
+
+        let tile_size = LayoutSize::new(200.0, 300.0);
+        assert_eq!(
+            bound_nine_patch_tile_size(LayoutSize::new(1000.0, 1000.0), tile_size, RepeatMode::Repeat, RepeatMode::Repeat),
+            tile_size,
+        );
+    }
+
+    #[test]
+    fn nine_patch_many_tiles() {
+        // The top and bottom edges take 998 round tiles each, and the left and
+        // right edges 8, which are split over several primitives instead of
+        // being stretched.
+        let rect = LayoutRect::new(LayoutPoint::zero(), LayoutSize::new(10000.0, 100.0));
+        let mut nine_patch = nine_patch(10.0, RepeatMode::Round);
+        nine_patch.fill = false;
+        let primitives = create_nine_patch_segments(&rect, &widths(10.0), &nine_patch);
+
+        let segment_count: usize = primitives.iter().map(|segments| segments.len()).sum();
+        assert_eq!(segment_count, 4 + 2 * 998 + 2 * 8);
+        assert_eq!(primitives.len(), (segment_count + MAX_NINE_PATCH_SEGMENTS - 1) / MAX_NINE_PATCH_SEGMENTS);
+        for segments in &primitives {
+            assert!(segments.len() <= MAX_NINE_PATCH_SEGMENTS);
+            for segment in segments {
+                assert!(segment.local_rect.size.width < 10.001);
+            }
+        }
+    }
+
+    #[test]
+    fn nine_patch_overlapping_slices() {
+        // The left and right slices overlap, so the top and bottom edges and
+        // the center are empty, and the corners overlap in the image.
+        let rect = LayoutRect::new(LayoutPoint::zero(), LayoutSize::new(100.0, 100.0));
+        let mut nine_patch = nine_patch(10.0, RepeatMode::Stretch);
+        nine_patch.slice.left = 20.0;
+        nine_patch.slice.right = 20.0;
+        let primitives = create_nine_patch_segments(&rect, &widths(10.0), &nine_patch);
+        assert_eq!(primitives.len(), 1);
+
+        let segments = &primitives[0];
+        assert_eq!(segments.len(), 6);
+        assert_eq!(segments[0].extra_data, [0.0, 0.0, 20.0, 10.0]);
+        assert_eq!(segments[1].extra_data, [10.0, 0.0, 30.0, 10.0]);
+
+        // Slices beyond the image are used as given.
+        nine_patch.slice = SideOffsets2D::new_all_same(40.0);
+        let primitives = create_nine_patch_segments(&rect, &widths(10.0), &nine_patch);
+        assert_eq!(primitives[0].len(), 4);
+        assert_eq!(primitives[0][0].extra_data, [0.0, 0.0, 40.0, 40.0]);
+    }
+}
@@ -80,24 +80,38 @@ impl CaptureConfig {
         use png::{BitDepth, ColorType, Encoder, HasParameters};
         use std::io::BufWriter;
 
-        let color_type = match format {
-            ReadPixelsFormat::Rgba8 => ColorType::RGBA,
+        let (color_type, bit_depth) = match format {
+            ReadPixelsFormat::Rgba8 |
+            ReadPixelsFormat::Standard(ImageFormat::RGBA8) => (ColorType::RGBA, BitDepth::Eight),
             ReadPixelsFormat::Standard(ImageFormat::BGRA8) => {
                 warn!("Unable to swizzle PNG of BGRA8 type");
-                ColorType::RGBA
+                (ColorType::RGBA, BitDepth::Eight)
             },
-            ReadPixelsFormat::Standard(ImageFormat::R8) => ColorType::Grayscale,
-            ReadPixelsFormat::Standard(ImageFormat::RG8) => ColorType::GrayscaleAlpha,
+            ReadPixelsFormat::Standard(ImageFormat::R8) => (ColorType::Grayscale, BitDepth::Eight),
+            ReadPixelsFormat::Standard(ImageFormat::R16) => (ColorType::Grayscale, BitDepth::Sixteen),
+            ReadPixelsFormat::Standard(ImageFormat::RG8) => (ColorType::GrayscaleAlpha, BitDepth::Eight),
             ReadPixelsFormat::Standard(fm) => {
                 error!("Unable to save PNG of {:?}", fm);
                 return;
             }
         };
+        // PNG stores 16 bit samples in big endian order.
+        let mut be_data;
+        let data = match bit_depth {
+            BitDepth::Sixteen if cfg!(target_endian = "little") => {
+                be_data = data.to_vec();
+                for sample in be_data.chunks_mut(2) {
+                    sample.swap(0, 1);
+                }
+                &be_data[..]
+            }
+            _ => data,
+        };
         let w = BufWriter::new(File::create(path).unwrap());
         let mut enc = Encoder::new(w, size.0, size.1);
         enc
             .set(color_type)
-            .set(BitDepth::Eight);
+            .set(bit_depth);
         enc
             .write_header()
             .unwrap()
@@ -3,17 +3,21 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use api::{BorderRadius, ClipMode, ComplexClipRegion, DeviceIntRect, DevicePixelScale, ImageMask};
-use api::{ImageRendering, LayoutRect, LayoutSize, LayoutPoint, LayoutVector2D, LocalClip};
-use api::{BoxShadowClipMode, LayoutToWorldScale, LineOrientation, LineStyle};
+use api::{ExtendMode, GradientStop, ImageFormat, ImageKey, ImageRendering, LayoutRect, LayoutSize};
+use api::{LayoutPoint, LayoutVector2D, LocalClip, MaskComposite, MaskLayer, MaskMode, MaskSource};
+use api::{BoxShadowClipMode, FillRule, LayoutToWorldScale, LineOrientation, LineStyle, PathCommand};
 use border::{ensure_no_corner_overlap};
 use box_shadow::{BLUR_SAMPLE_SCALE, BoxShadowClipSource, BoxShadowCacheKey};
 use clip_scroll_tree::{ClipChainIndex, CoordinateSystemId};
 use ellipse::Ellipse;
 use freelist::{FreeList, FreeListHandle, WeakFreeListHandle};
-use gpu_cache::{GpuCache, GpuCacheHandle, ToGpuBlocks};
+use gpu_cache::{GpuCache, GpuCacheAddress, GpuCacheHandle, ToGpuBlocks};
 use gpu_types::{BoxShadowStretchMode, ClipScrollNodeIndex};
-use prim_store::{ClipData, ImageMaskData};
+use path::{LineSegment, MAX_PATH_SEGMENTS, PATH_CHUNK_SEGMENTS, flatten_path};
+use path::{segments_bounding_rect, write_path_chunk_addresses, write_path_segments};
+use prim_store::{ClipData, ImageMaskChannel, ImageMaskData};
 use render_task::to_cache_size;
+use renderer::MAX_VERTEX_TEXTURE_WIDTH;
 use resource_cache::{ImageRequest, ResourceCache};
 use util::{LayoutToWorldFastTransform, MaxRect, calculate_screen_bounding_rect};
 use util::{extract_inner_rect_safe, pack_as_float};
@@ -34,11 +38,171 @@ pub struct LineDecorationClipSource {
     wavy_line_thickness: f32,
 }
 
+#[derive(Clone, Debug)]
+pub struct PathClipSource {
+    segments: Vec<LineSegment>,
+    bounds: LayoutRect,
+    fill_rule: FillRule,
+    /// One handle per chunk of `PATH_CHUNK_SEGMENTS` segments.
+    segment_handles: Vec<GpuCacheHandle>,
+    chunk_addresses: Vec<GpuCacheAddress>,
+}
+
+impl PathClipSource {
+    pub fn new(
+        path: &[PathCommand],
+        fill_rule: FillRule,
+        offset: &LayoutVector2D,
+    ) -> PathClipSource {
+        let mut segments = flatten_path(path, offset);
+        if segments.len() > MAX_PATH_SEGMENTS {
+            warn!("Path clip has {} segments, only the first {} are used",
+                  segments.len(), MAX_PATH_SEGMENTS);
+            segments.truncate(MAX_PATH_SEGMENTS);
+        }
+        let bounds = segments_bounding_rect(&segments);
+        let chunk_count = (segments.len() + PATH_CHUNK_SEGMENTS - 1) / PATH_CHUNK_SEGMENTS;
+
+        PathClipSource {
+            segments,
+            bounds,
+            fill_rule,
+            segment_handles: (0 .. chunk_count).map(|_| GpuCacheHandle::new()).collect(),
+            chunk_addresses: Vec::new(),
+        }
+    }
+
+    // Writes the segment chunks to the GPU cache and returns whether any of
+    // them moved, in which case the clip data referring to them is stale.
+    fn update_segment_chunks(&mut self, gpu_cache: &mut GpuCache) -> bool {
+        let mut chunks_moved = false;
+        for (chunk_handle, chunk) in self.segment_handles
+            .iter_mut()
+            .zip(self.segments.chunks(PATH_CHUNK_SEGMENTS))
+        {
+            if let Some(mut request) = gpu_cache.request(chunk_handle) {
+                write_path_segments(&mut request, chunk);
+                chunks_moved = true;
+            }
+        }
+
+        self.chunk_addresses.clear();
+        for chunk_handle in &self.segment_handles {
+            self.chunk_addresses.push(gpu_cache.get_address(chunk_handle));
+        }
+        chunks_moved
+    }
+
+    pub fn segments(&self) -> &[LineSegment] {
+        &self.segments
+    }
+
+    pub fn fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+}
+
+// The GPU cache blocks of a mask layer before its gradient stops.
+const MASK_LAYER_HEADER_BLOCKS: usize = 4;
+
+// Gradient mask layers are written in a single GPU cache request, two stops per block.
+const MAX_MASK_GRADIENT_STOPS: usize = 2 * (MAX_VERTEX_TEXTURE_WIDTH - MASK_LAYER_HEADER_BLOCKS);
+
+// The kind of the source of a mask, as read by cs_clip_image.
+const MASK_SOURCE_GRADIENT: u32 = 1;
+
+#[derive(Debug)]
+pub enum MaskLayerSource {
+    Image(ImageKey),
+    Gradient {
+        start_point: LayoutPoint,
+        end_point: LayoutPoint,
+        extend_mode: ExtendMode,
+        /// The offset and mask value of each stop.
+        stops: Vec<(f32, f32)>,
+    },
+}
+
+/// A layer of the mask of a stacking context, composited with the
+/// layers drawn before it in the same clip mask.
+#[derive(Debug)]
+pub struct MaskLayerClipSource {
+    source: MaskLayerSource,
+    rect: LayoutRect,
+    tile_size: LayoutSize,
+    tile_spacing: LayoutSize,
+    mode: MaskMode,
+    composite: MaskComposite,
+}
+
+impl MaskLayerClipSource {
+    pub fn new(
+        layer: &MaskLayer,
+        gradient_stops: &[GradientStop],
+        composite: MaskComposite,
+        offset: &LayoutVector2D,
+    ) -> MaskLayerClipSource {
+        let source = match layer.source {
+            MaskSource::Image(key) => MaskLayerSource::Image(key),
+            MaskSource::Gradient { ref gradient, .. } => {
+                if gradient_stops.len() > MAX_MASK_GRADIENT_STOPS {
+                    warn!("Mask gradient has {} stops, only the first {} are used",
+                          gradient_stops.len(), MAX_MASK_GRADIENT_STOPS);
+                }
+                // Only the mask value of the stops matters, so compute it
+                // up front rather than in the shader.
+                let stops = gradient_stops
+                    .iter()
+                    .take(MAX_MASK_GRADIENT_STOPS)
+                    .map(|stop| {
+                        let value = match layer.mode {
+                            MaskMode::Alpha => stop.color.a,
+                            MaskMode::Luminance => {
+                                let color = stop.color;
+                                (0.2125 * color.r + 0.7154 * color.g + 0.0721 * color.b) * color.a
+                            }
+                        };
+                        (stop.offset, value)
+                    })
+                    .collect();
+
+                MaskLayerSource::Gradient {
+                    start_point: gradient.start_point,
+                    end_point: gradient.end_point,
+                    extend_mode: gradient.extend_mode,
+                    stops,
+                }
+            }
+        };
+
+        MaskLayerClipSource {
+            source,
+            rect: layer.rect.translate(offset),
+            tile_size: layer.tile_size,
+            tile_spacing: layer.tile_spacing,
+            mode: layer.mode,
+            composite,
+        }
+    }
+
+    pub fn image_key(&self) -> Option<ImageKey> {
+        match self.source {
+            MaskLayerSource::Image(key) => Some(key),
+            MaskLayerSource::Gradient { .. } => None,
+        }
+    }
+
+    pub fn composite(&self) -> MaskComposite {
+        self.composite
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ClipRegion {
     pub main: LayoutRect,
     pub image_mask: Option<ImageMask>,
     pub complex_clips: Vec<ComplexClipRegion>,
+    pub path: Option<PathClipSource>,
 }
 
 impl ClipRegion {
@@ -62,6 +226,21 @@ impl ClipRegion {
             main: rect,
             image_mask,
             complex_clips,
+            path: None,
+        }
+    }
+
+    pub fn create_for_clip_path(
+        rect: LayoutRect,
+        path: &[PathCommand],
+        fill_rule: FillRule,
+        reference_frame_relative_offset: &LayoutVector2D,
+    ) -> ClipRegion {
+        ClipRegion {
+            main: rect.translate(reference_frame_relative_offset),
+            image_mask: None,
+            complex_clips: Vec::new(),
+            path: Some(PathClipSource::new(path, fill_rule, reference_frame_relative_offset)),
         }
     }
 
@@ -89,6 +268,8 @@ pub enum ClipSource {
     Image(ImageMask),
     BoxShadow(BoxShadowClipSource),
     LineDecoration(LineDecorationClipSource),
+    Path(PathClipSource),
+    MaskLayer(MaskLayerClipSource),
 }
 
 impl From<ClipRegion> for ClipSources {
@@ -101,6 +282,10 @@ impl From<ClipRegion> for ClipSources {
 
         clips.push(ClipSource::Rectangle(region.main, ClipMode::Clip));
 
+        if let Some(path) = region.path {
+            clips.push(ClipSource::Path(path));
+        }
+
         for complex in region.complex_clips {
             clips.push(ClipSource::new_rounded_rect(
                 complex.rect,
@@ -299,6 +484,9 @@ impl ClipSources {
         let mut local_inner = local_outer;
         let mut can_calculate_inner_rect = true;
         let mut can_calculate_outer_rect = false;
+        // Mask layers may be added to each other, so they only
+        // bound the clip by the union of their rects.
+        let mut mask_layers_outer: Option<LayoutRect> = None;
         for source in clips {
             match *source {
                 ClipSource::Image(ref mask) => {
@@ -308,6 +496,18 @@ impl ClipSources {
                     }
                     local_inner = None;
                 }
+                ClipSource::MaskLayer(ref layer) => {
+                    mask_layers_outer = Some(match mask_layers_outer {
+                        Some(outer) => outer.union(&layer.rect),
+                        None => layer.rect,
+                    });
+                    local_inner = None;
+                }
+                ClipSource::Path(ref path) => {
+                    can_calculate_outer_rect = true;
+                    local_outer = local_outer.and_then(|r| r.intersection(&path.bounds));
+                    local_inner = None;
+                }
                 ClipSource::Rectangle(rect, mode) => {
                     // Once we encounter a clip-out, we just assume the worst
                     // case clip mask size, for now.
@@ -343,6 +543,11 @@ impl ClipSources {
             }
         }
 
+        if let Some(ref mask_layers_outer) = mask_layers_outer {
+            can_calculate_outer_rect = true;
+            local_outer = local_outer.and_then(|r| r.intersection(mask_layers_outer));
+        }
+
         let outer = if can_calculate_outer_rect {
             Some(local_outer.unwrap_or_else(LayoutRect::zero))
         } else {
@@ -365,12 +570,70 @@ impl ClipSources {
         device_pixel_scale: DevicePixelScale,
     ) {
         for &mut (ref mut source, ref mut handle) in &mut self.clips {
+            if let ClipSource::Path(ref mut path) = *source {
+                if path.update_segment_chunks(gpu_cache) {
+                    gpu_cache.invalidate(handle);
+                }
+            }
+
             if let Some(mut request) = gpu_cache.request(handle) {
                 match *source {
                     ClipSource::Image(ref mask) => {
-                        let data = ImageMaskData { local_rect: mask.rect };
+                        let data = ImageMaskData {
+                            local_rect: mask.rect,
+                            tile_size: mask.rect.size,
+                            tile_spacing: LayoutSize::zero(),
+                            channel: ImageMaskChannel::Red,
+                        };
                         data.write_gpu_blocks(request);
                     }
+                    ClipSource::MaskLayer(ref layer) => {
+                        match layer.source {
+                            MaskLayerSource::Image(key) => {
+                                // Alpha-only images are used as they are.
+                                let format = resource_cache
+                                    .get_image_properties(key)
+                                    .map(|properties| properties.descriptor.format);
+                                let channel = match (format, layer.mode) {
+                                    (Some(ImageFormat::R8), _) => ImageMaskChannel::Red,
+                                    (_, MaskMode::Alpha) => ImageMaskChannel::Alpha,
+                                    (_, MaskMode::Luminance) => ImageMaskChannel::Luminance,
+                                };
+                                let data = ImageMaskData {
+                                    local_rect: layer.rect,
+                                    tile_size: layer.tile_size,
+                                    tile_spacing: layer.tile_spacing,
+                                    channel,
+                                };
+                                data.write_gpu_blocks(request);
+                            }
+                            MaskLayerSource::Gradient { start_point, end_point, extend_mode, ref stops } => {
+                                request.push(layer.rect);
+                                request.push([
+                                    layer.tile_size.width,
+                                    layer.tile_size.height,
+                                    layer.tile_spacing.width,
+                                    layer.tile_spacing.height,
+                                ]);
+                                request.push([
+                                    pack_as_float(MASK_SOURCE_GRADIENT),
+                                    0.0,
+                                    stops.len() as f32,
+                                    if extend_mode == ExtendMode::Repeat { 1.0 } else { 0.0 },
+                                ]);
+                                request.push([
+                                    start_point.x,
+                                    start_point.y,
+                                    end_point.x,
+                                    end_point.y,
+                                ]);
+                                for pair in stops.chunks(2) {
+                                    let (offset1, value1) = pair.get(1).cloned().unwrap_or(pair[0]);
+                                    request.push([pair[0].0, pair[0].1, offset1, value1]);
+                                }
+                            }
+                        }
+                    }
                     ClipSource::BoxShadow(ref info) => {
                         request.push([
                             info.shadow_rect_alloc_size.width,
@@ -403,6 +666,16 @@ impl ClipSources {
                             0.0,
                         ]);
                     }
+                    ClipSource::Path(ref path) => {
+                        request.push(path.bounds);
+                        request.push([
+                            path.segments.len() as f32,
+                            pack_as_float(path.fill_rule as u32),
+                            0.0,
+                            0.0,
+                        ]);
+                        write_path_chunk_addresses(&mut request, &path.chunk_addresses);
+                    }
                 }
             }
 
@@ -417,6 +690,18 @@ impl ClipSources {
                         gpu_cache,
                     );
                 }
+                ClipSource::MaskLayer(ref layer) => {
+                    if let Some(key) = layer.image_key() {
+                        resource_cache.request_image(
+                            ImageRequest {
+                                key,
+                                rendering: ImageRendering::Auto,
+                                tile: None,
+                            },
+                            gpu_cache,
+                        );
+                    }
+                }
                 ClipSource::BoxShadow(ref mut info) => {
                     // Quote from https://drafts.csswg.org/css-backgrounds-3/#shadow-blur
                     // "the image that would be generated by applying to the shadow a
@@ -163,6 +163,7 @@ impl ClipScrollNode {
         frame_rect: &LayoutRect,
         content_size: &LayoutSize,
         scroll_sensitivity: ScrollSensitivity,
+        offset_binding: Option<PropertyBinding<LayoutVector2D>>,
     ) -> Self {
         let node_type = NodeType::ScrollFrame(ScrollFrameInfo::new(
             *frame_rect,
@@ -172,6 +173,7 @@ impl ClipScrollNode {
                 (content_size.height - frame_rect.size.height).max(0.0)
             ),
             external_id,
+            offset_binding,
         ));
 
         Self::new(pipeline_id, Some(parent_index), node_type)
@@ -413,6 +415,18 @@ impl ClipScrollNode {
             return;
         }
 
+        // Scroll frames with a bound scroll offset follow the current value of
+        // the property, resolved the same way as set_scroll_origin.
+        let bound_origin = match self.node_type {
+            NodeType::ScrollFrame(ref info) => info.offset_binding.map(|binding| {
+                scene_properties.resolve_layout_vector(&binding).to_point()
+            }),
+            _ => None,
+        };
+        if let Some(origin) = bound_origin {
+            self.set_scroll_origin(&origin, ScrollClamping::ToContentBounds);
+        }
+
         // We calculate this here to avoid a double-borrow later.
         let sticky_offset = self.calculate_sticky_offset(
             &state.nearest_scrolling_ancestor_offset,
@@ -737,6 +751,9 @@ pub struct ScrollFrameInfo {
     /// which may change between frames.
     pub external_id: Option<ExternalScrollId>,
 
+    /// A property binding that drives the scroll position of this frame,
+    /// overriding any offset set through the scrolling API.
+    pub offset_binding: Option<PropertyBinding<LayoutVector2D>>,
 }
 
 /// Manages scrolling offset.
@@ -746,6 +763,7 @@ impl ScrollFrameInfo {
         scroll_sensitivity: ScrollSensitivity,
         scrollable_size: LayoutSize,
         external_id: Option<ExternalScrollId>,
+        offset_binding: Option<PropertyBinding<LayoutVector2D>>,
     ) -> ScrollFrameInfo {
         ScrollFrameInfo {
             viewport_rect,
@@ -753,6 +771,7 @@ impl ScrollFrameInfo {
             scroll_sensitivity,
             scrollable_size,
             external_id,
+            offset_binding,
         }
     }
 
@@ -773,6 +792,7 @@ impl ScrollFrameInfo {
             scroll_sensitivity: self.scroll_sensitivity,
             scrollable_size: self.scrollable_size,
             external_id: self.external_id,
+            offset_binding: self.offset_binding,
         }
     }
 }
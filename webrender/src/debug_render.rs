@@ -112,7 +112,7 @@ fn create_debug_programs<B: hal::Backend>(device: &mut Device<B>)-> (Program, Pr
                 pipeline_requirement,
             "debug_font",
             &ShaderKind::DebugFont,
-        );
+        ).unwrap();
 
     let pipeline_requirement_color =
         pipeline_requirements
@@ -124,7 +124,7 @@ fn create_debug_programs<B: hal::Backend>(device: &mut Device<B>)-> (Program, Pr
             pipeline_requirement_color,
         "debug_color",
         &ShaderKind::DebugColor
-        );
+        ).unwrap();
     (font_program, color_program)
 }
 
@@ -9,7 +9,6 @@ use api::TextureTarget;
 use api::ImageDescriptor;
 use euclid::Transform3D;
 //use gleam::gl;
-use gpu_types;
 use internal_types::{FastHashMap, RenderTargetInfo};
 use rand::{self, Rng};
 use ron::de::from_reader;
@@ -19,39 +18,42 @@ use std::cmp;
 use std::collections::HashMap;
 use std::fs::File;
 use std::mem;
-use std::ops::Add;
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::slice;
-use std::sync::Arc;
-use std::thread;
 use vertex_types::*;
+use super::{DepthFunction, ExternalTexture, FBOId, FileWatcherHandler, FrameId, IBOId};
+use super::{PipelineRequirements, PrimitiveType, ProgramBinary, ProgramCache, ProgramId, ProgramSources};
+use super::shader_sources_digest;
+use super::{RBOId, ReadPixelsFormat, SurfaceId, MAIN_SURFACE};
+use super::{ShaderError, ShaderKind, Texel, Texture, TextureFilter, TextureId, TextureSlot, UploadMethod};
+use super::{VertexArrayKind, VertexDescriptor, VertexUsageHint, VAO, VBOId, PBO};
+use super::{DEFAULT_DRAW_FBO, DEFAULT_READ_FBO, INVALID_PROGRAM_ID, INVALID_TEXTURE_ID};
+use super::{MAX_FRAME_COUNT, MAX_INSTANCE_COUNT, decode_texel, encode_texel, texels_to_u8_slice};
+use super::{ALPHA, LESS_EQUAL_TEST, LESS_EQUAL_WRITE, LESS_WRITE, MAX, PREMULTIPLIED_DEST_OUT};
+use super::{MASK_ADD, MASK_EXCLUDE, MASK_SUBTRACT};
+use super::{SUBPIXEL_CONSTANT_TEXT_COLOR, SUBPIXEL_DUAL_SOURCE, SUBPIXEL_PASS0, SUBPIXEL_PASS1};
+use super::{SUBPIXEL_WITH_BG_COLOR_PASS0, SUBPIXEL_WITH_BG_COLOR_PASS1, SUBPIXEL_WITH_BG_COLOR_PASS2};
+#[cfg(feature = "debug_renderer")]
+use super::MIN;
+use super::Capabilities;
 
 use hal;
 
 // gfx-hal
-use hal::pso::{AttributeDesc, DescriptorRangeDesc, DescriptorSetLayoutBinding, VertexBufferDesc};
-use hal::pso::{BlendState, BlendOp, Comparison, DepthTest, Factor};
+use hal::pso::{DescriptorRangeDesc, DescriptorSetLayoutBinding};
+use hal::pso::{BlendState, Comparison, DepthTest};
 use hal::{Device as BackendDevice, PhysicalDevice, QueueFamily, Surface, Swapchain};
 use hal::{Backbuffer, DescriptorPool, FrameSync, Primitive, SwapchainConfig};
 use hal::pass::Subpass;
 use hal::pso::PipelineStage;
 use hal::queue::Submission;
 
-pub const MAX_INSTANCE_COUNT: usize = 1024;
 const MAX_DEBUG_COLOR_INDEX_COUNT: usize = 14544;
 const MAX_DEBUG_FONT_INDEX_COUNT: usize = 4296;
 const MAX_DEBUG_COLOR_VERTEX_COUNT: usize = 9696;
 const MAX_DEBUG_FONT_VERTEX_COUNT: usize = 2864;
 
-pub type TextureId = u32;
-
-pub const INVALID_TEXTURE_ID: TextureId = 0;
-pub const INVALID_PROGRAM_ID: ProgramId = ProgramId(0);
-pub const DEFAULT_READ_FBO: FBOId = FBOId(0);
-pub const DEFAULT_DRAW_FBO: FBOId = FBOId(1);
-
-pub const MAX_FRAME_COUNT: usize = 2;
 
 const COLOR_RANGE: hal::image::SubresourceRange = hal::image::SubresourceRange {
     aspects: hal::format::Aspects::COLOR,
@@ -74,16 +76,6 @@ pub struct Locals {
     uMode: i32,
 }
 
-#[derive(Clone, Deserialize)]
-pub struct PipelineRequirements {
-    pub attribute_descriptors: Vec<AttributeDesc>,
-    pub bindings_map: HashMap<String, u32>,
-    pub descriptor_range_descriptors: Vec<DescriptorRangeDesc>,
-    pub descriptor_set_layouts: Vec<DescriptorSetLayoutBinding>,
-    pub vertex_buffer_descriptors: Vec<VertexBufferDesc>,
-}
-
-
 const QUAD: [Vertex; 6] = [
     Vertex {
         aPosition: [0.0, 0.0, 0.0],
@@ -114,178 +106,15 @@ fn get_shader_source(filename: &str, extension: &str) -> Vec<u8> {
     shader
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Ord, Eq, PartialOrd)]
-#[cfg_attr(feature = "capture", derive(Serialize))]
-#[cfg_attr(feature = "replay", derive(Deserialize))]
-pub struct FrameId(usize);
-
-impl FrameId {
-    pub fn new(value: usize) -> Self {
-        FrameId(value)
-    }
-}
-
-impl Add<usize> for FrameId {
-    type Output = FrameId;
-
-    fn add(self, other: usize) -> FrameId {
-        FrameId(self.0 + other)
-    }
-}
-
-pub struct TextureSlot(pub usize);
-
-// In some places we need to temporarily bind a texture to any slot.
-//const DEFAULT_TEXTURE: TextureSlot = TextureSlot(0);
-
-#[repr(u32)]
-pub enum DepthFunction {
-    #[cfg(feature = "debug_renderer")]
-    Less,
-    LessEqual,
-}
-
-#[derive(Copy, Clone, Debug, PartialEq)]
-#[cfg_attr(feature = "capture", derive(Serialize))]
-#[cfg_attr(feature = "replay", derive(Deserialize))]
-pub enum TextureFilter {
-    Nearest,
-    Linear,
-    Trilinear,
-}
-
-#[derive(Debug)]
-pub enum VertexAttributeKind {
-    F32,
-    #[cfg(feature = "debug_renderer")]
-    U8Norm,
-    U16Norm,
-    I32,
-    U16,
-}
-
-#[derive(Debug)]
-pub struct VertexAttribute {
-    pub name: &'static str,
-    pub count: u32,
-    pub kind: VertexAttributeKind,
-}
-
-#[derive(Debug)]
-pub struct VertexDescriptor {
-    pub vertex_attributes: &'static [VertexAttribute],
-    pub instance_attributes: &'static [VertexAttribute],
-}
-
-pub trait PrimitiveType {
-    type Primitive: Clone + Copy;
-    fn to_primitive_type(&self) -> Self::Primitive;
-}
-
-
-impl PrimitiveType for gpu_types::BlurInstance {
-    type Primitive = BlurInstance;
-    fn to_primitive_type(&self) -> BlurInstance {
-        BlurInstance {
-            aData0: [0,0,0,0],
-            aData1: [0,0,0,0],
-            aBlurRenderTaskAddress: self.task_address.0 as i32,
-            aBlurSourceTaskAddress: self.src_task_address.0 as i32,
-            aBlurDirection: self.blur_direction as i32,
-        }
-    }
-}
-
-impl PrimitiveType for gpu_types::BorderInstance {
-    type Primitive = BorderInstance;
-    fn to_primitive_type(&self) -> BorderInstance {
-        BorderInstance {
-            aTaskOrigin: [self.task_origin.x, self.task_origin.y],
-            aRect: [self.local_rect.origin.x, self.local_rect.origin.y, self.local_rect.size.width, self.local_rect.size.height],
-            aColor0: self.color0.to_array(),
-            aColor1: self.color1.to_array(),
-            aFlags: self.flags,
-            aWidths: [self.widths.width, self.widths.height],
-            aRadii: [self.radius.width, self.radius.height],
-            aClipParams1: [
-                self.clip_params[0],
-                self.clip_params[1],
-                self.clip_params[2],
-                self.clip_params[3],
-            ],
-            aClipParams2: [
-                self.clip_params[4],
-                self.clip_params[5],
-                self.clip_params[6],
-                self.clip_params[7],
-            ],
-        }
-    }
-}
-
-impl PrimitiveType for gpu_types::ClipMaskInstance {
-    type Primitive = ClipMaskInstance;
-    fn to_primitive_type(&self) -> ClipMaskInstance {
-        ClipMaskInstance {
-            aClipRenderTaskAddress: self.render_task_address.0 as i32,
-            aScrollNodeId: self.scroll_node_data_index.0 as i32,
-            aClipSegment: self.segment,
-            aClipDataResourceAddress: [
-                self.clip_data_address.u as i32,
-                self.clip_data_address.v as i32,
-                self.resource_address.u as i32,
-                self.resource_address.v as i32,
-            ],
-        }
-    }
-}
-
-impl PrimitiveType for gpu_types::ClipMaskBorderCornerDotDash {
-    type Primitive = ClipMaskBorderCornerDotDash;
-    fn to_primitive_type(&self) -> ClipMaskBorderCornerDotDash {
-        ClipMaskBorderCornerDotDash {
-            aClipRenderTaskAddress: self.clip_mask_instance.render_task_address.0 as i32,
-            aScrollNodeId: self.clip_mask_instance.scroll_node_data_index.0 as i32,
-            aClipSegment: self.clip_mask_instance.segment,
-            aClipDataResourceAddress: [
-                self.clip_mask_instance.clip_data_address.u as i32,
-                self.clip_mask_instance.clip_data_address.v as i32,
-                self.clip_mask_instance.resource_address.u as i32,
-                self.clip_mask_instance.resource_address.v as i32,
-            ],
-            aDashOrDot0: [
-                self.dot_dash_data[0],
-                self.dot_dash_data[1],
-                self.dot_dash_data[2],
-                self.dot_dash_data[3],
-            ],
-            aDashOrDot1: [
-                self.dot_dash_data[4],
-                self.dot_dash_data[5],
-                self.dot_dash_data[6],
-                self.dot_dash_data[7],
-            ]
-        }
-    }
-}
-
-impl PrimitiveType for gpu_types::PrimitiveInstance {
-    type Primitive = PrimitiveInstance;
-    fn to_primitive_type(&self) -> PrimitiveInstance {
-        PrimitiveInstance {
-            aData0: [
-                self.data[0],
-                self.data[1],
-                self.data[2],
-                self.data[3],
-            ],
-            aData1: [
-                self.data[4],
-                self.data[5],
-                self.data[6],
-                self.data[7],
-            ],
-        }
+fn image_format_to_hal(format: ImageFormat) -> hal::format::Format {
+    match format {
+        ImageFormat::R8 => hal::format::Format::R8Unorm,
+        ImageFormat::R16 => hal::format::Format::R16Unorm,
+        ImageFormat::RG8 => hal::format::Format::Rg8Unorm,
+        ImageFormat::RGBA8 => hal::format::Format::Rgba8Unorm,
+        ImageFormat::BGRA8 => hal::format::Format::Bgra8Unorm,
+        ImageFormat::RGBAF32 => hal::format::Format::Rgba32Float,
+        ImageFormat::RGBAI32 => hal::format::Format::Rgba32Int,
     }
 }
 
@@ -294,341 +123,6 @@ enum FBOTarget {
     Draw,
 }
 
-/// Method of uploading texel data from CPU to GPU.
-#[derive(Debug, Clone)]
-pub enum UploadMethod {
-    /// Just call `glTexSubImage` directly with the CPU data pointer
-    Immediate,
-    /// Accumulate the changes in PBO first before transferring to a texture.
-    PixelBuffer,
-}
-
-/// Plain old data that can be used to initialize a texture.
-pub unsafe trait Texel: Copy {}
-unsafe impl Texel for u8 {}
-unsafe impl Texel for f32 {}
-
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum ReadPixelsFormat {
-    Standard(ImageFormat),
-    Rgba8,
-}
-
-pub trait FileWatcherHandler: Send {
-    fn file_changed(&self, path: PathBuf);
-}
-
-#[cfg_attr(feature = "replay", derive(Clone))]
-pub struct ExternalTexture {
-    id: u32,
-    _target: TextureTarget,
-}
-
-impl ExternalTexture {
-    pub fn new(id: u32, _target: TextureTarget) -> Self {
-        ExternalTexture {
-            id,
-            _target,
-        }
-    }
-
-    #[cfg(feature = "replay")]
-    pub fn internal_id(&self) -> u32 {
-        self.id
-    }
-}
-
-pub struct Texture {
-    id: TextureId,
-    _target: TextureTarget,
-    layer_count: i32,
-    format: ImageFormat,
-    width: u32,
-    height: u32,
-    filter: TextureFilter,
-    render_target: Option<RenderTargetInfo>,
-    fbo_ids: Vec<FBOId>,
-    depth_rb: Option<RBOId>,
-    last_frame_used: FrameId,
-    bound_in_frame: Cell<FrameId>,
-}
-
-impl Texture {
-    pub fn get_dimensions(&self) -> DeviceUintSize {
-        DeviceUintSize::new(self.width, self.height)
-    }
-
-    pub fn get_render_target_layer_count(&self) -> usize {
-        self.fbo_ids.len()
-    }
-
-    pub fn get_layer_count(&self) -> i32 {
-        self.layer_count
-    }
-
-    pub fn get_format(&self) -> ImageFormat {
-        self.format
-    }
-
-    #[cfg(any(feature = "debug_renderer", feature = "capture"))]
-    pub fn get_filter(&self) -> TextureFilter {
-        self.filter
-    }
-
-    #[cfg(any(feature = "debug_renderer", feature = "capture"))]
-    pub fn get_render_target(&self) -> Option<RenderTargetInfo> {
-        self.render_target.clone()
-    }
-
-    pub fn has_depth(&self) -> bool {
-        self.depth_rb.is_some()
-    }
-
-    pub fn get_rt_info(&self) -> Option<&RenderTargetInfo> {
-        self.render_target.as_ref()
-    }
-
-    pub fn used_in_frame(&self, frame_id: FrameId) -> bool {
-        self.last_frame_used == frame_id
-    }
-
-    fn still_in_flight(&self, frame_id: FrameId) -> bool {
-        for i in 0..MAX_FRAME_COUNT {
-            if self.bound_in_frame.get() == FrameId(frame_id.0 - i) {
-                return true
-            }
-        }
-        false
-    }
-
-    #[cfg(feature = "replay")]
-    pub fn into_external(mut self) -> ExternalTexture {
-        let ext = ExternalTexture {
-            id: self.id,
-            _target: self._target,
-        };
-        self.id = 0; // don't complain, moved out
-        ext
-    }
-}
-
-impl Drop for Texture {
-    fn drop(&mut self) {
-        debug_assert!(thread::panicking() || self.id == 0);
-    }
-}
-
-#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
-pub struct ProgramId(u32);
-
-pub struct PBO;
-pub struct VAO;
-
-#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
-pub struct FBOId(u32);
-
-#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
-pub struct RBOId(u32);
-
-#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
-pub struct VBOId(u32);
-
-#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
-struct IBOId(u32);
-
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
-#[cfg_attr(feature = "serialize_program", derive(Deserialize, Serialize))]
-pub struct ProgramSources;
-
-#[cfg_attr(feature = "serialize_program", derive(Deserialize, Serialize))]
-pub struct ProgramBinary;
-
-pub trait ProgramCacheObserver {
-    fn notify_binary_added(&self, program_binary: &Arc<ProgramBinary>);
-    fn notify_program_binary_failed(&self, program_binary: &Arc<ProgramBinary>);
-}
-
-pub struct ProgramCache;
-
-#[derive(Debug, Copy, Clone)]
-pub enum VertexUsageHint {
-    Static,
-    Dynamic,
-    Stream,
-}
-
-#[cfg(feature = "debug_renderer")]
-pub struct Capabilities {
-    pub supports_multisampling: bool,
-}
-
-#[derive(Clone, Debug)]
-pub enum ShaderError {
-    Compilation(String, String), // name, error message
-    Link(String, String),        // name, error message
-}
-
-#[derive(Debug, Copy, Clone)]
-pub enum VertexArrayKind {
-    Primitive,
-    Blur,
-    Clip,
-    VectorStencil,
-    VectorCover,
-    Border,
-}
-
-#[derive(Debug, Copy, Clone)]
-pub enum ShaderKind {
-    Primitive,
-    Cache(VertexArrayKind),
-    ClipCache,
-    Brush,
-    Text,
-    #[allow(dead_code)]
-    VectorStencil,
-    #[allow(dead_code)]
-    VectorCover,
-    DebugColor,
-    DebugFont,
-}
-
-impl ShaderKind {
-    fn is_debug(&self) -> bool {
-        match *self {
-            ShaderKind::DebugFont | ShaderKind::DebugColor => true,
-            _ => false,
-        }
-    }
-}
-
-const ALPHA: BlendState = BlendState::On {
-    color: BlendOp::Add {
-        src: Factor::SrcAlpha,
-        dst: Factor::OneMinusSrcAlpha,
-    },
-    alpha: BlendOp::Add {
-        src: Factor::One,
-        dst: Factor::One,
-    },
-};
-
-const PREMULTIPLIED_DEST_OUT: BlendState = BlendState::On {
-    color: BlendOp::Add {
-        src: Factor::Zero,
-        dst: Factor::OneMinusSrcAlpha,
-    },
-    alpha: BlendOp::Add {
-        src: Factor::Zero,
-        dst: Factor::OneMinusSrcAlpha,
-    },
-};
-
-const MAX: BlendState = BlendState::On {
-    color: BlendOp::Max,
-    alpha: BlendOp::Add {
-        src: Factor::One,
-        dst: Factor::One,
-    },
-};
-
-#[cfg(feature = "debug_renderer")]
-const MIN: BlendState = BlendState::On {
-    color: BlendOp::Min,
-    alpha: BlendOp::Add {
-        src: Factor::One,
-        dst: Factor::One,
-    },
-};
-
-const SUBPIXEL_PASS0: BlendState = BlendState::On {
-    color: BlendOp::Add {
-        src: Factor::Zero,
-        dst: Factor::OneMinusSrcColor,
-    },
-    alpha: BlendOp::Add {
-        src: Factor::Zero,
-        dst: Factor::OneMinusSrcAlpha,
-    },
-};
-
-const SUBPIXEL_PASS1: BlendState = BlendState::On {
-    color: BlendOp::Add {
-        src: Factor::One,
-        dst: Factor::One,
-    },
-    alpha: BlendOp::Add {
-        src: Factor::One,
-        dst: Factor::One,
-    },
-};
-
-const SUBPIXEL_WITH_BG_COLOR_PASS0: BlendState = BlendState::On {
-    color: BlendOp::Add {
-        src: Factor::Zero,
-        dst: Factor::OneMinusSrcColor,
-    },
-    alpha: BlendOp::Add {
-        src: Factor::Zero,
-        dst: Factor::One,
-    },
-};
-
-const SUBPIXEL_WITH_BG_COLOR_PASS1: BlendState = BlendState::On {
-    color: BlendOp::Add {
-        src: Factor::OneMinusDstAlpha,
-        dst: Factor::One,
-    },
-    alpha: BlendOp::Add {
-        src: Factor::Zero,
-        dst: Factor::One,
-    },
-};
-
-const SUBPIXEL_WITH_BG_COLOR_PASS2: BlendState = BlendState::On {
-    color: BlendOp::Add {
-        src: Factor::One,
-        dst: Factor::One,
-    },
-    alpha: BlendOp::Add {
-        src: Factor::One,
-        dst: Factor::OneMinusSrcAlpha,
-    },
-};
-
-// This requires blend color to be set
-const SUBPIXEL_CONSTANT_TEXT_COLOR: BlendState = BlendState::On {
-    color: BlendOp::Add {
-        src: Factor::ConstColor,
-        dst: Factor::OneMinusSrcColor,
-    },
-    alpha: BlendOp::Add {
-        src: Factor::ConstAlpha,
-        dst: Factor::OneMinusSrcAlpha,
-    },
-};
-
-const SUBPIXEL_DUAL_SOURCE: BlendState = BlendState::On {
-    color: BlendOp::Add {
-        src: Factor::One,
-        dst: Factor::OneMinusSrc1Color,
-    },
-    alpha: BlendOp::Add {
-        src: Factor::One,
-        dst: Factor::OneMinusSrc1Alpha,
-    },
-};
-
-const LESS_EQUAL_TEST: DepthTest = DepthTest::On {
-    fun: Comparison::LessEqual,
-    write: false,
-};
-
-const LESS_EQUAL_WRITE: DepthTest = DepthTest::On {
-    fun: Comparison::LessEqual,
-    write: true,
-};
-
 pub struct ImageBuffer<B: hal::Backend> {
     pub buffer: CopyBuffer<B>,
     pub offset: u64,
@@ -756,6 +250,8 @@ impl<B: hal::Backend> ImageCore<B> {
 
 pub struct Image<B: hal::Backend> {
     pub core: ImageCore<B>,
+    /// The multisampled image render passes draw into, resolved into `core`.
+    pub msaa: Option<ImageCore<B>>,
     pub upload_buffer: ImageBuffer<B>,
     pub kind: hal::image::Kind,
     pub format: ImageFormat,
@@ -771,14 +267,10 @@ impl<B: hal::Backend> Image<B> {
         image_depth: i32,
         view_kind: hal::image::ViewKind,
         mip_levels: hal::image::Level,
+        samples: hal::image::NumSamples,
         pitch_alignment: usize,
     ) -> Self {
-        let format = match image_format {
-            ImageFormat::R8 => hal::format::Format::R8Unorm,
-            ImageFormat::RG8 => hal::format::Format::Rg8Unorm,
-            ImageFormat::BGRA8 => hal::format::Format::Bgra8Unorm,
-            ImageFormat::RGBAF32 => hal::format::Format::Rgba32Float,
-        };
+        let format = image_format_to_hal(image_format);
         let upload_buffer = CopyBuffer::create(
             device,
             memory_types,
@@ -809,9 +301,33 @@ impl<B: hal::Backend> Image<B> {
                 layers: 0 .. image_depth as _,
             },
         );
+        let msaa = if samples > 1 {
+            Some(ImageCore::create(
+                device,
+                memory_types,
+                hal::image::Kind::D2(
+                    image_width as _,
+                    image_height as _,
+                    image_depth as _,
+                    samples,
+                ),
+                hal::image::ViewKind::D2Array,
+                1,
+                format,
+                hal::image::Usage::TRANSFER_DST | hal::image::Usage::COLOR_ATTACHMENT,
+                hal::image::SubresourceRange {
+                    aspects: hal::format::Aspects::COLOR,
+                    levels: 0 .. 1,
+                    layers: 0 .. image_depth as _,
+                },
+            ))
+        } else {
+            None
+        };
 
         Image {
             core,
+            msaa,
             upload_buffer: ImageBuffer::new(upload_buffer),
             kind,
             format: image_format,
@@ -829,9 +345,38 @@ impl<B: hal::Backend> Image<B> {
     ) -> hal::command::Submit<B, hal::Graphics, hal::command::MultiShot, hal::command::Primary>
     {
         //let (image_width, image_height, _, _) = self.kind.dimensions();
+        let offset = self.upload_buffer.update(device, image_data, offset_alignment);
+        let buffer_width = if self.kind.extent().width == rect.size.width {
+            self.upload_buffer.buffer.row_pitch() as u32 / self.format.bytes_per_pixel()
+        } else {
+            rect.size.width
+        };
+        let submit = self.copy_from_buffer(
+            cmd_pool,
+            &self.upload_buffer.buffer,
+            self.upload_buffer.offset,
+            buffer_width,
+            rect,
+            layer_index,
+        );
+        self.upload_buffer.offset += offset as u64;
+        submit
+    }
+
+    /// Records the copy of `rect` from a staging buffer, where each row of
+    /// the buffer is `buffer_width` texels long.
+    fn copy_from_buffer(
+        &self,
+        cmd_pool: &mut hal::CommandPool<B, hal::queue::Graphics>,
+        buffer: &CopyBuffer<B>,
+        buffer_offset: u64,
+        buffer_width: u32,
+        rect: DeviceUintRect,
+        layer_index: i32,
+    ) -> hal::command::Submit<B, hal::Graphics, hal::command::MultiShot, hal::command::Primary>
+    {
         let pos = rect.origin;
         let size = rect.size;
-        let offset = self.upload_buffer.update(device, image_data, offset_alignment);
         let mut cmd_buffer = cmd_pool.acquire_command_buffer(false);
 
         let range = hal::image::SubresourceRange {
@@ -851,18 +396,13 @@ impl<B: hal::Backend> Image<B> {
             );
         }
 
-        let buffer_width = if self.kind.extent().width == size.width {
-            self.upload_buffer.buffer.row_pitch() as u32 / self.format.bytes_per_pixel()
-        } else {
-            size.width
-        };
         cmd_buffer.copy_buffer_to_image(
-            &self.upload_buffer.buffer.buffer,
+            &buffer.buffer,
             &self.core.image,
             hal::image::Layout::TransferDstOptimal,
             &[
                 hal::command::BufferImageCopy {
-                    buffer_offset: self.upload_buffer.offset,
+                    buffer_offset,
                     buffer_width,
                     buffer_height: size.height,
                     image_layers: hal::image::SubresourceLayers {
@@ -896,12 +436,14 @@ impl<B: hal::Backend> Image<B> {
             );
         }
 
-        self.upload_buffer.offset += offset as u64;
         cmd_buffer.finish()
     }
 
     pub fn deinit(self, device: &B::Device) {
         self.core.deinit(device);
+        if let Some(msaa) = self.msaa {
+            msaa.deinit(device);
+        }
         self.upload_buffer.deinit(device);
     }
 }
@@ -1114,31 +656,175 @@ impl<B: hal::Backend> CopyBuffer<B> {
     }
 }
 
-pub struct InstanceBuffer<B: hal::Backend> {
-    pub buffer: Buffer<B>,
-    pub size: usize,
-    pub offset: usize,
+/// The initial size of the staging ring, in bytes.
+const STAGING_RING_SIZE: usize = 4 << 20;
+
+#[derive(Clone, Copy, Default)]
+struct StagingFrame {
+    /// The head of the ring after the last upload of the frame.
+    end: usize,
+    /// The number of bytes the frame used, including the padding.
+    used: usize,
 }
 
-impl<B: hal::Backend> InstanceBuffer<B> {
-    fn new(buffer: Buffer<B>) -> Self {
-        InstanceBuffer {
-            buffer,
-            size: 0,
-            offset: 0,
+/// A staging buffer shared by the immediate texture uploads, used as a ring.
+/// Each upload is written to the next free range of the buffer, and the
+/// ranges written during a frame are released once the fence of that frame
+/// is signaled.
+pub struct StagingRing<B: hal::Backend> {
+    buffer: CopyBuffer<B>,
+    capacity: usize,
+    /// The offset the next upload is written at.
+    head: usize,
+    /// The start of the oldest range the GPU may still read from.
+    tail: usize,
+    /// The number of bytes from the tail to the head, which tells a full
+    /// ring from an empty one.
+    used: usize,
+    /// The uploads of each frame in flight, indexed like the frame fences.
+    frames: SmallVec<[StagingFrame; 1]>,
+}
+
+impl<B: hal::Backend> StagingRing<B> {
+    fn new(
+        device: &B::Device,
+        memory_types: &[hal::MemoryType],
+        capacity: usize,
+    ) -> Self {
+        StagingRing {
+            buffer: CopyBuffer::create(
+                device,
+                memory_types,
+                hal::buffer::Usage::TRANSFER_SRC,
+                1,
+                capacity,
+                1,
+                0,
+            ),
+            capacity,
+            head: 0,
+            tail: 0,
+            used: 0,
+            frames: (0 .. MAX_FRAME_COUNT).map(|_| StagingFrame::default()).collect(),
         }
     }
 
-    fn update<T: Copy>(
-        &mut self,
-        device: &B::Device,
-        instances: &[T],
-    ) {
-        let data_stride = self.buffer.data_stride;
-        self.buffer.update(
-            device,
-            (self.offset * data_stride) as u64,
-            (instances.len() * data_stride) as u64,
+    /// Returns the offset of a free range of `size` bytes, aligned to
+    /// `alignment` (a power of two), or `None` if the ring is too full.
+    fn allocate(&mut self, size: usize, alignment: usize, frame_id: usize) -> Option<usize> {
+        if self.used == 0 {
+            self.head = 0;
+            self.tail = 0;
+        }
+        let start = (self.head + alignment - 1) & !(alignment - 1);
+        let offset = if self.used > 0 && self.head == self.tail {
+            return None;
+        } else if self.head >= self.tail {
+            // The free space is after the head, and before the tail once
+            // the ring wraps around.
+            if start + size <= self.capacity {
+                start
+            } else if size <= self.tail {
+                0
+            } else {
+                return None;
+            }
+        } else if start + size <= self.tail {
+            start
+        } else {
+            return None;
+        };
+
+        let end = offset + size;
+        let used = if offset >= self.head {
+            end - self.head
+        } else {
+            self.capacity - self.head + end
+        };
+        self.head = end;
+        self.used += used;
+        let frame = &mut self.frames[frame_id];
+        frame.end = end;
+        frame.used += used;
+        Some(offset)
+    }
+
+    /// Writes the rows of `data`, each `row_bytes` long, `pitch` bytes apart
+    /// from `offset` on.
+    fn write(
+        &mut self,
+        device: &B::Device,
+        offset: usize,
+        data: &[u8],
+        row_bytes: usize,
+        pitch: usize,
+    ) {
+        if data.is_empty() {
+            return;
+        }
+        let height = data.len() / row_bytes;
+        let mut mapping = device
+            .acquire_mapping_writer::<u8>(
+                &self.buffer.memory,
+                offset as u64 .. (offset + pitch * height) as u64,
+            )
+            .unwrap();
+        for (y, row) in data.chunks(row_bytes).enumerate() {
+            mapping[y * pitch .. y * pitch + row_bytes].copy_from_slice(row);
+        }
+        device.release_mapping_writer(mapping);
+    }
+
+    /// Releases the ranges written during the frame, once its fence is signaled.
+    /// Frames complete in order, so the tail moves to the end of the frame.
+    fn release_frame(&mut self, frame_id: usize) {
+        let frame = mem::replace(&mut self.frames[frame_id], StagingFrame::default());
+        if frame.used > 0 {
+            self.tail = frame.end;
+            self.used -= frame.used;
+        }
+    }
+
+    /// Releases every range, once all the frames are done.
+    fn release_all(&mut self) {
+        for frame in &mut self.frames {
+            *frame = StagingFrame::default();
+        }
+        self.head = 0;
+        self.tail = 0;
+        self.used = 0;
+    }
+
+    pub fn deinit(self, device: &B::Device) {
+        self.buffer.deinit(device);
+    }
+}
+
+pub struct InstanceBuffer<B: hal::Backend> {
+    pub buffer: Buffer<B>,
+    pub size: usize,
+    pub offset: usize,
+}
+
+impl<B: hal::Backend> InstanceBuffer<B> {
+    fn new(buffer: Buffer<B>) -> Self {
+        InstanceBuffer {
+            buffer,
+            size: 0,
+            offset: 0,
+        }
+    }
+
+    fn update<T: Copy>(
+        &mut self,
+        device: &B::Device,
+        instances: &[T],
+    ) {
+        let data_stride = self.buffer.data_stride;
+        self.buffer.update(
+            device,
+            (self.offset * data_stride) as u64,
+            (instances.len() * data_stride) as u64,
             &instances.to_owned(),
         );
 
@@ -1208,10 +894,19 @@ impl<B: hal::Backend> UniformBuffer<B> {
     }
 }
 
+// The blend and depth states of a pipeline, followed by the color format,
+// the depth attachment and the sample count of the render passes it is
+// compatible with.
+type PipelineKey = (BlendState, DepthTest, ImageFormat, bool, hal::image::NumSamples);
+
 pub(crate) struct Program<B: hal::Backend> {
     pub bindings_map: HashMap<String, u32>,
     pub pipeline_layout: B::PipelineLayout,
-    pub pipelines: HashMap<(BlendState, DepthTest), B::GraphicsPipeline>,
+    pub pipelines: HashMap<PipelineKey, B::GraphicsPipeline>,
+    vs_module: B::ShaderModule,
+    fs_module: B::ShaderModule,
+    vertex_buffer_descriptors: Vec<hal::pso::VertexBufferDesc>,
+    attribute_descriptors: Vec<hal::pso::AttributeDesc>,
     pub vertex_buffer: Buffer<B>,
     pub index_buffer: Option<Buffer<B>>,
     pub instance_buffer: SmallVec<[InstanceBuffer<B>; 1]>,
@@ -1226,130 +921,76 @@ impl<B: hal::Backend> Program<B> {
         device: &B::Device,
         pipeline_layout: B::PipelineLayout,
         memory_types: &[hal::MemoryType],
+        vs_source: &[u8],
+        fs_source: &[u8],
         shader_name: &str,
         shader_kind: ShaderKind,
         render_pass: &RenderPass<B>,
     ) -> Program<B> {
         let vs_module = device
-            .create_shader_module(get_shader_source(shader_name, ".vert.spv").as_slice())
+            .create_shader_module(vs_source)
             .unwrap();
         let fs_module = device
-            .create_shader_module(get_shader_source(shader_name, ".frag.spv").as_slice())
+            .create_shader_module(fs_source)
             .unwrap();
 
-        let pipelines = {
-            let (vs_entry, fs_entry) = (
-                hal::pso::EntryPoint::<B> {
-                    entry: ENTRY_NAME,
-                    module: &vs_module,
-                    specialization: &[],
-                },
-                hal::pso::EntryPoint::<B> {
-                    entry: ENTRY_NAME,
-                    module: &fs_module,
-                    specialization: &[],
-                },
-            );
-
-            let shader_entries = hal::pso::GraphicsShaderSet {
-                vertex: vs_entry,
-                hull: None,
-                domain: None,
-                geometry: None,
-                fragment: Some(fs_entry),
-            };
-
-            let pipeline_states = match shader_kind {
-                ShaderKind::Brush if shader_name.starts_with("brush_mask") => vec![(BlendState::Off, DepthTest::Off)],
-                ShaderKind::Cache(VertexArrayKind::Blur) => vec![(BlendState::Off, DepthTest::Off)],
-                ShaderKind::Cache(VertexArrayKind::Primitive) => vec![(BlendState::PREMULTIPLIED_ALPHA, DepthTest::Off)],
-                ShaderKind::Cache(VertexArrayKind::Border) => vec![(BlendState::PREMULTIPLIED_ALPHA, DepthTest::Off)],
-                ShaderKind::ClipCache => vec![(BlendState::MULTIPLY, DepthTest::Off)],
-                ShaderKind::Text => vec![
-                    (BlendState::PREMULTIPLIED_ALPHA, DepthTest::Off),
-                    (BlendState::PREMULTIPLIED_ALPHA, LESS_EQUAL_TEST),
-                    (SUBPIXEL_DUAL_SOURCE, DepthTest::Off),
-                    (SUBPIXEL_DUAL_SOURCE, LESS_EQUAL_TEST),
-                    (SUBPIXEL_CONSTANT_TEXT_COLOR, DepthTest::Off),
-                    (SUBPIXEL_CONSTANT_TEXT_COLOR, LESS_EQUAL_TEST),
-                    (SUBPIXEL_PASS0, DepthTest::Off),
-                    (SUBPIXEL_PASS0, LESS_EQUAL_TEST),
-                    (SUBPIXEL_PASS1, DepthTest::Off),
-                    (SUBPIXEL_PASS1, LESS_EQUAL_TEST),
-                    (SUBPIXEL_WITH_BG_COLOR_PASS0, DepthTest::Off),
-                    (SUBPIXEL_WITH_BG_COLOR_PASS0, LESS_EQUAL_TEST),
-                    (SUBPIXEL_WITH_BG_COLOR_PASS1, DepthTest::Off),
-                    (SUBPIXEL_WITH_BG_COLOR_PASS1, LESS_EQUAL_TEST),
-                    (SUBPIXEL_WITH_BG_COLOR_PASS2, DepthTest::Off),
-                    (SUBPIXEL_WITH_BG_COLOR_PASS2, LESS_EQUAL_TEST),
-                ],
-                ShaderKind::DebugColor | ShaderKind::DebugFont => vec![
-                    (BlendState::PREMULTIPLIED_ALPHA, DepthTest::Off),
-                ],
-                _ => vec![
-                    (BlendState::Off, DepthTest::Off),
-                    (BlendState::Off, LESS_EQUAL_TEST),
-                    (BlendState::Off, LESS_EQUAL_WRITE),
-                    (ALPHA, DepthTest::Off),
-                    (ALPHA, LESS_EQUAL_TEST),
-                    (ALPHA, LESS_EQUAL_WRITE),
-                    (BlendState::PREMULTIPLIED_ALPHA, DepthTest::Off),
-                    (BlendState::PREMULTIPLIED_ALPHA, LESS_EQUAL_TEST),
-                    (BlendState::PREMULTIPLIED_ALPHA, LESS_EQUAL_WRITE),
-                    (PREMULTIPLIED_DEST_OUT, DepthTest::Off),
-                    (PREMULTIPLIED_DEST_OUT, LESS_EQUAL_TEST),
-                    (PREMULTIPLIED_DEST_OUT, LESS_EQUAL_WRITE),
-                ],
-            };
-            let format = match shader_kind {
-                ShaderKind::ClipCache => ImageFormat::R8,
-                ShaderKind::Cache(VertexArrayKind::Blur) if shader_name.contains("_alpha_target") => ImageFormat::R8,
-                _ => ImageFormat::BGRA8,
-            };
-
-            let pipelines_descriptors = pipeline_states.iter().map(|&(blend_state, depth_test)| {
-                let subpass = Subpass {
-                    index: 0,
-                    main_pass: render_pass.get_render_pass(format, depth_test != DepthTest::Off),
-                };
-                let mut pipeline_descriptor = hal::pso::GraphicsPipelineDesc::new(
-                    shader_entries.clone(),
-                    Primitive::TriangleList,
-                    hal::pso::Rasterizer::FILL,
-                    &pipeline_layout,
-                    subpass,
-                );
-                pipeline_descriptor
-                    .blender
-                    .targets
-                    .push(hal::pso::ColorBlendDesc(
-                        hal::pso::ColorMask::ALL,
-                        blend_state,
-                    ));
-
-                pipeline_descriptor.depth_stencil = hal::pso::DepthStencilDesc {
-                    depth: depth_test,
-                    depth_bounds: false,
-                    stencil: hal::pso::StencilTest::Off,
-                };
-
-                pipeline_descriptor.vertex_buffers = pipeline_requirements.vertex_buffer_descriptors.clone();
-                pipeline_descriptor.attributes = pipeline_requirements.attribute_descriptors.clone();
-                pipeline_descriptor
-            }).collect::<Vec<_>>();
-
-            let pipelines = device
-                .create_graphics_pipelines(pipelines_descriptors.as_slice())
-                .into_iter();
-
-            pipeline_states.iter()
-                .cloned()
-                .zip(pipelines.map(|pipeline| pipeline.unwrap()))
-                .collect::<HashMap<(BlendState, DepthTest), B::GraphicsPipeline>>()
+        // Create the pipelines the program is usually drawn with up front,
+        // any other combination is created on first use.
+        let pipeline_states = match shader_kind {
+            ShaderKind::Brush if shader_name.starts_with("brush_mask") => vec![(BlendState::Off, DepthTest::Off)],
+            ShaderKind::Cache(VertexArrayKind::Blur) => vec![(BlendState::Off, DepthTest::Off)],
+            ShaderKind::Cache(VertexArrayKind::Primitive) => vec![(BlendState::PREMULTIPLIED_ALPHA, DepthTest::Off)],
+            ShaderKind::Cache(VertexArrayKind::Border) => vec![(BlendState::PREMULTIPLIED_ALPHA, DepthTest::Off)],
+            ShaderKind::Cache(VertexArrayKind::Path) => vec![(BlendState::PREMULTIPLIED_ALPHA, DepthTest::Off)],
+            ShaderKind::Cache(VertexArrayKind::SvgFilter) => vec![(BlendState::Off, DepthTest::Off)],
+            ShaderKind::ClipImage => vec![
+                (BlendState::MULTIPLY, DepthTest::Off),
+                (MASK_ADD, DepthTest::Off),
+                (MASK_SUBTRACT, DepthTest::Off),
+                (MASK_EXCLUDE, DepthTest::Off),
+            ],
+            ShaderKind::ClipCache => vec![(BlendState::MULTIPLY, DepthTest::Off)],
+            ShaderKind::Text => vec![
+                (BlendState::PREMULTIPLIED_ALPHA, DepthTest::Off),
+                (BlendState::PREMULTIPLIED_ALPHA, LESS_EQUAL_TEST),
+                (SUBPIXEL_DUAL_SOURCE, DepthTest::Off),
+                (SUBPIXEL_DUAL_SOURCE, LESS_EQUAL_TEST),
+                (SUBPIXEL_CONSTANT_TEXT_COLOR, DepthTest::Off),
+                (SUBPIXEL_CONSTANT_TEXT_COLOR, LESS_EQUAL_TEST),
+                (SUBPIXEL_PASS0, DepthTest::Off),
+                (SUBPIXEL_PASS0, LESS_EQUAL_TEST),
+                (SUBPIXEL_PASS1, DepthTest::Off),
+                (SUBPIXEL_PASS1, LESS_EQUAL_TEST),
+                (SUBPIXEL_WITH_BG_COLOR_PASS0, DepthTest::Off),
+                (SUBPIXEL_WITH_BG_COLOR_PASS0, LESS_EQUAL_TEST),
+                (SUBPIXEL_WITH_BG_COLOR_PASS1, DepthTest::Off),
+                (SUBPIXEL_WITH_BG_COLOR_PASS1, LESS_EQUAL_TEST),
+                (SUBPIXEL_WITH_BG_COLOR_PASS2, DepthTest::Off),
+                (SUBPIXEL_WITH_BG_COLOR_PASS2, LESS_EQUAL_TEST),
+            ],
+            ShaderKind::DebugColor | ShaderKind::DebugFont => vec![
+                (BlendState::PREMULTIPLIED_ALPHA, DepthTest::Off),
+            ],
+            _ => vec![
+                (BlendState::Off, DepthTest::Off),
+                (BlendState::Off, LESS_EQUAL_TEST),
+                (BlendState::Off, LESS_WRITE),
+                (ALPHA, DepthTest::Off),
+                (ALPHA, LESS_EQUAL_TEST),
+                (ALPHA, LESS_EQUAL_WRITE),
+                (BlendState::PREMULTIPLIED_ALPHA, DepthTest::Off),
+                (BlendState::PREMULTIPLIED_ALPHA, LESS_EQUAL_TEST),
+                (BlendState::PREMULTIPLIED_ALPHA, LESS_EQUAL_WRITE),
+                (PREMULTIPLIED_DEST_OUT, DepthTest::Off),
+                (PREMULTIPLIED_DEST_OUT, LESS_EQUAL_TEST),
+                (PREMULTIPLIED_DEST_OUT, LESS_EQUAL_WRITE),
+            ],
+        };
+        let format = match shader_kind {
+            ShaderKind::ClipCache | ShaderKind::ClipImage => ImageFormat::R8,
+            ShaderKind::Cache(VertexArrayKind::Blur) if shader_name.contains("_alpha_target") => ImageFormat::R8,
+            _ => ImageFormat::BGRA8,
         };
-
-        device.destroy_shader_module(vs_module);
-        device.destroy_shader_module(fs_module);
 
         let (vertex_buffer_stride, vertex_buffer_len) = match shader_kind {
             ShaderKind::DebugColor => {
@@ -1400,9 +1041,13 @@ impl<B: hal::Backend> Program<B> {
             ShaderKind::Brush |
             ShaderKind::Text |
             ShaderKind::Cache(VertexArrayKind::Primitive) => mem::size_of::<PrimitiveInstance>(),
-            ShaderKind::ClipCache | ShaderKind::Cache(VertexArrayKind::Clip) => mem::size_of::<ClipMaskInstance>(),
+            ShaderKind::ClipCache |
+            ShaderKind::ClipImage |
+            ShaderKind::Cache(VertexArrayKind::Clip) => mem::size_of::<ClipMaskInstance>(),
             ShaderKind::Cache(VertexArrayKind::Blur) => mem::size_of::<BlurInstance>(),
             ShaderKind::Cache(VertexArrayKind::Border) => mem::size_of::<BorderInstance>(),
+            ShaderKind::Cache(VertexArrayKind::Path) => mem::size_of::<PathInstance>(),
+            ShaderKind::Cache(VertexArrayKind::SvgFilter) => mem::size_of::<SvgFilterInstance>(),
             ShaderKind::DebugColor | ShaderKind::DebugFont => 1,
             _ => unreachable!()
         };
@@ -1433,19 +1078,113 @@ impl<B: hal::Backend> Program<B> {
             );
         }
 
-        let bindings_map = pipeline_requirements.bindings_map;
-
-        Program {
-            bindings_map,
+        let mut program = Program {
+            bindings_map: pipeline_requirements.bindings_map,
             pipeline_layout,
-            pipelines,
+            pipelines: HashMap::default(),
+            vs_module,
+            fs_module,
+            vertex_buffer_descriptors: pipeline_requirements.vertex_buffer_descriptors,
+            attribute_descriptors: pipeline_requirements.attribute_descriptors,
             vertex_buffer,
             index_buffer,
             instance_buffer,
             locals_buffer,
             shader_name: String::from(shader_name),
             shader_kind,
+        };
+
+        // Render targets always have a depth attachment, while the main
+        // framebuffer only has one bound when depth testing is enabled.
+        let draws_to_render_targets = !shader_kind.is_debug();
+        let draws_to_main_framebuffer = match shader_kind {
+            ShaderKind::Cache(..) | ShaderKind::ClipCache | ShaderKind::ClipImage => false,
+            ShaderKind::Brush => !shader_name.starts_with("brush_mask"),
+            _ => true,
+        };
+        let mut pipeline_keys = Vec::new();
+        for (blend_state, depth_test) in pipeline_states {
+            if draws_to_render_targets {
+                pipeline_keys.push((blend_state, depth_test, format, true, 1));
+            }
+            if draws_to_main_framebuffer && (depth_test == DepthTest::Off || !draws_to_render_targets) {
+                pipeline_keys.push((blend_state, depth_test, format, depth_test != DepthTest::Off, 1));
+            }
         }
+        program.create_pipelines(device, render_pass, &pipeline_keys);
+        program
+    }
+
+    fn create_pipelines(
+        &mut self,
+        device: &B::Device,
+        render_pass: &RenderPass<B>,
+        pipeline_keys: &[PipelineKey],
+    ) {
+        let shader_entries = hal::pso::GraphicsShaderSet {
+            vertex: hal::pso::EntryPoint::<B> {
+                entry: ENTRY_NAME,
+                module: &self.vs_module,
+                specialization: &[],
+            },
+            hull: None,
+            domain: None,
+            geometry: None,
+            fragment: Some(hal::pso::EntryPoint::<B> {
+                entry: ENTRY_NAME,
+                module: &self.fs_module,
+                specialization: &[],
+            }),
+        };
+
+        let pipelines_descriptors = pipeline_keys.iter().map(|&(blend_state, depth_test, format, has_depth, samples)| {
+            let subpass = Subpass {
+                index: 0,
+                main_pass: render_pass.get_render_pass(format, has_depth, samples),
+            };
+            let mut pipeline_descriptor = hal::pso::GraphicsPipelineDesc::new(
+                shader_entries.clone(),
+                Primitive::TriangleList,
+                hal::pso::Rasterizer::FILL,
+                &self.pipeline_layout,
+                subpass,
+            );
+            pipeline_descriptor
+                .blender
+                .targets
+                .push(hal::pso::ColorBlendDesc(
+                    hal::pso::ColorMask::ALL,
+                    blend_state,
+                ));
+
+            pipeline_descriptor.depth_stencil = hal::pso::DepthStencilDesc {
+                depth: depth_test,
+                depth_bounds: false,
+                stencil: hal::pso::StencilTest::Off,
+            };
+
+            if samples > 1 {
+                pipeline_descriptor.multisampling = Some(hal::pso::Multisampling {
+                    rasterization_samples: samples,
+                    sample_shading: None,
+                    sample_mask: !0,
+                    alpha_coverage: false,
+                    alpha_to_one: false,
+                });
+            }
+
+            pipeline_descriptor.vertex_buffers = self.vertex_buffer_descriptors.clone();
+            pipeline_descriptor.attributes = self.attribute_descriptors.clone();
+            pipeline_descriptor
+        }).collect::<Vec<_>>();
+
+        let pipelines = device
+            .create_graphics_pipelines(pipelines_descriptors.as_slice())
+            .into_iter()
+            .map(|pipeline| pipeline.unwrap())
+            .collect::<Vec<_>>();
+
+        self.pipelines.extend(pipeline_keys.iter().cloned().zip(pipelines));
     }
 
 
@@ -1520,9 +1259,13 @@ impl<B: hal::Backend> Program<B> {
 
     pub fn submit(
         &mut self,
+        device: &B::Device,
         cmd_pool: &mut hal::CommandPool<B, hal::queue::Graphics>,
         viewport: hal::pso::Viewport,
-        render_pass: &B::RenderPass,
+        render_pass: &RenderPass<B>,
+        format: ImageFormat,
+        has_depth: bool,
+        samples: hal::image::NumSamples,
         frame_buffer: &B::Framebuffer,
         desc_pools: &mut DescriptorPools<B>,
         clear_values: &[hal::command::ClearValue],
@@ -1532,6 +1275,12 @@ impl<B: hal::Backend> Program<B> {
         scissor_rect: Option<DeviceIntRect>,
         next_id: usize,
     ) -> hal::command::Submit<B, hal::Graphics, hal::command::MultiShot, hal::command::Primary> {
+        let pipeline_key = (blend_state, depth_test, format, has_depth, samples);
+        if !self.pipelines.contains_key(&pipeline_key) {
+            debug!("Creating pipeline {:?} for the {} program", pipeline_key, self.shader_name);
+            self.create_pipelines(device, render_pass, &[pipeline_key]);
+        }
+
         let mut cmd_buffer = cmd_pool.acquire_command_buffer(false);
 
         cmd_buffer.set_viewports(0, &[viewport.clone()]);
@@ -1542,8 +1291,7 @@ impl<B: hal::Backend> Program<B> {
             ),
             None => cmd_buffer.set_scissors(0, &[viewport.rect]),
         }
-        cmd_buffer.bind_graphics_pipeline(
-            &self.pipelines.get(&(blend_state, depth_test)).expect(&format!("The blend state {:?} with depth test {:?} not found for {} program!", blend_state, depth_test, self.shader_name)));
+        cmd_buffer.bind_graphics_pipeline(&self.pipelines[&pipeline_key]);
         cmd_buffer.bind_vertex_buffers(
             0,
             hal::pso::VertexBufferSet(vec![
@@ -1576,7 +1324,7 @@ impl<B: hal::Backend> Program<B> {
 
         {
             let mut encoder = cmd_buffer.begin_render_pass_inline(
-                render_pass,
+                render_pass.get_render_pass(format, has_depth, samples),
                 frame_buffer,
                 viewport.rect,
                 clear_values,
@@ -1614,6 +1362,8 @@ impl<B: hal::Backend> Program<B> {
         for pipeline in self.pipelines.drain() {
             device.destroy_graphics_pipeline(pipeline.1);
         }
+        device.destroy_shader_module(self.vs_module);
+        device.destroy_shader_module(self.fs_module);
     }
 }
 
@@ -1621,7 +1371,9 @@ pub struct Framebuffer<B: hal::Backend> {
     pub texture: TextureId,
     pub layer_index: u16,
     pub format: ImageFormat,
+    pub samples: hal::image::NumSamples,
     pub image_view: B::ImageView,
+    pub msaa_view: Option<B::ImageView>,
     pub fbo: B::Framebuffer,
     pub rbo: RBOId,
 }
@@ -1641,39 +1393,47 @@ impl<B: hal::Backend> Framebuffer<B> {
             height: texture.height as _,
             depth: 1,
         };
-        let format = match texture.format {
-            ImageFormat::R8 => hal::format::Format::R8Unorm,
-            ImageFormat::BGRA8 => hal::format::Format::Bgra8Unorm,
-            _ => unimplemented!("TODO image format missing"),
-        };
-        let image_view = device
-            .create_image_view(
-                &image.core.image,
-                hal::image::ViewKind::D2Array,
-                format,
-                hal::format::Swizzle::NO,
-                hal::image::SubresourceRange {
-                    aspects: hal::format::Aspects::COLOR,
-                    levels: 0 .. 1,
-                    layers: layer_index .. layer_index+1,
-                },
-            )
-            .unwrap();
-        let fbo = if rbo != RBOId(0) {
+        let format = image_format_to_hal(texture.format);
+        let create_layer_view = |core: &ImageCore<B>| {
             device
-                .create_framebuffer(render_pass.get_render_pass(texture.format, true), vec![&image_view, depth.unwrap()], extent)
-                .unwrap()
-        } else {
-            device
-                .create_framebuffer(render_pass.get_render_pass(texture.format, false), Some(&image_view), extent)
+                .create_image_view(
+                    &core.image,
+                    hal::image::ViewKind::D2Array,
+                    format,
+                    hal::format::Swizzle::NO,
+                    hal::image::SubresourceRange {
+                        aspects: hal::format::Aspects::COLOR,
+                        levels: 0 .. 1,
+                        layers: layer_index .. layer_index+1,
+                    },
+                )
                 .unwrap()
         };
+        let image_view = create_layer_view(&image.core);
+        let msaa_view = image.msaa.as_ref().map(create_layer_view);
+        let samples = texture.render_target.map_or(1, |rt| rt.samples);
+
+        // The attachments follow the render pass layout: the color target,
+        // which is the multisampled image if any, then the depth buffer.
+        let mut attachments = vec![msaa_view.as_ref().unwrap_or(&image_view)];
+        if rbo != RBOId(0) {
+            attachments.push(depth.unwrap());
+        }
+        let fbo = device
+            .create_framebuffer(
+                render_pass.get_render_pass(texture.format, rbo != RBOId(0), samples),
+                attachments,
+                extent,
+            )
+            .unwrap();
 
         Framebuffer {
             texture: texture.id,
             layer_index,
             format: texture.format,
+            samples,
             image_view,
+            msaa_view,
             fbo,
             rbo,
         }
@@ -1682,6 +1442,9 @@ impl<B: hal::Backend> Framebuffer<B> {
     pub fn deinit(self, device: &B::Device) {
         device.destroy_framebuffer(self.fbo);
         device.destroy_image_view(self.image_view);
+        if let Some(msaa_view) = self.msaa_view {
+            device.destroy_image_view(msaa_view);
+        }
     }
 }
 
@@ -1695,12 +1458,13 @@ impl<B: hal::Backend> DepthBuffer<B> {
         memory_types: &[hal::MemoryType],
         pixel_width: u32,
         pixel_height: u32,
+        samples: hal::image::NumSamples,
         depth_format: hal::format::Format
     ) -> Self {
         let core = ImageCore::create(
             device,
             memory_types,
-            hal::image::Kind::D2(pixel_width, pixel_height, 1, 1),
+            hal::image::Kind::D2(pixel_width, pixel_height, 1, samples),
             hal::image::ViewKind::D2,
             1,
             depth_format,
@@ -1717,29 +1481,122 @@ impl<B: hal::Backend> DepthBuffer<B> {
     }
 }
 
+// The color formats we can render to, each has a render pass
+// with and without a depth attachment. Integer formats can't be
+// blended, so RGBAI32 is only ever uploaded and fetched from.
+const RENDER_TARGET_FORMATS: [ImageFormat; 6] = [
+    ImageFormat::R8,
+    ImageFormat::R16,
+    ImageFormat::RG8,
+    ImageFormat::RGBA8,
+    ImageFormat::BGRA8,
+    ImageFormat::RGBAF32,
+];
+
 pub struct RenderPass<B: hal::Backend> {
-    pub r8: B::RenderPass,
-    pub r8_depth: B::RenderPass,
-    pub bgra8: B::RenderPass,
-    pub bgra8_depth: B::RenderPass,
+    passes: FastHashMap<(ImageFormat, bool, hal::image::NumSamples), B::RenderPass>,
 }
 
 impl<B: hal::Backend> RenderPass<B> {
-    pub fn get_render_pass(&self, format: ImageFormat, depth_enabled: bool) -> &B::RenderPass {
-        match format {
-            ImageFormat::R8 if depth_enabled => &self.r8_depth,
-            ImageFormat::R8 => &self.r8,
-            ImageFormat::BGRA8 if depth_enabled => &self.bgra8_depth,
-            ImageFormat::BGRA8 => &self.bgra8,
-            _ => unimplemented!(),
+    fn create(
+        device: &B::Device,
+        depth_format: hal::format::Format,
+        sample_counts: &[hal::image::NumSamples],
+    ) -> Self {
+        let dependency = hal::pass::SubpassDependency {
+            passes: hal::pass::SubpassRef::External .. hal::pass::SubpassRef::Pass(0),
+            stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT
+                .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+            accesses: hal::image::Access::empty()
+                .. (hal::image::Access::COLOR_ATTACHMENT_READ
+                | hal::image::Access::COLOR_ATTACHMENT_WRITE),
+        };
+
+        let dependency_depth = hal::pass::SubpassDependency {
+            passes: hal::pass::SubpassRef::External .. hal::pass::SubpassRef::Pass(0),
+            stages: (PipelineStage::COLOR_ATTACHMENT_OUTPUT | PipelineStage::EARLY_FRAGMENT_TESTS)
+                .. (PipelineStage::COLOR_ATTACHMENT_OUTPUT | PipelineStage::EARLY_FRAGMENT_TESTS),
+            accesses: hal::image::Access::empty()
+                .. (hal::image::Access::COLOR_ATTACHMENT_READ
+                | hal::image::Access::COLOR_ATTACHMENT_WRITE
+                | hal::image::Access::DEPTH_STENCIL_ATTACHMENT_READ
+                | hal::image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE),
+        };
+
+        let mut passes = FastHashMap::default();
+        for &samples in sample_counts {
+            // Every draw call begins a new render pass instance, so the
+            // attachments have to keep their contents between them.
+            let attachment_depth = hal::pass::Attachment {
+                format: Some(depth_format),
+                samples,
+                ops: hal::pass::AttachmentOps::new(
+                    hal::pass::AttachmentLoadOp::Load,
+                    hal::pass::AttachmentStoreOp::Store,
+                ),
+                stencil_ops: hal::pass::AttachmentOps::DONT_CARE,
+                layouts: hal::image::Layout::DepthStencilAttachmentOptimal .. hal::image::Layout::DepthStencilAttachmentOptimal,
+            };
+
+            for &format in RENDER_TARGET_FORMATS.iter() {
+                let attachment = hal::pass::Attachment {
+                    format: Some(image_format_to_hal(format)),
+                    samples,
+                    ops: hal::pass::AttachmentOps::new(
+                        hal::pass::AttachmentLoadOp::Load,
+                        hal::pass::AttachmentStoreOp::Store,
+                    ),
+                    stencil_ops: hal::pass::AttachmentOps::DONT_CARE,
+                    layouts: hal::image::Layout::ColorAttachmentOptimal .. hal::image::Layout::ColorAttachmentOptimal,
+                };
+                // Multisampled passes only draw into the multisampled image,
+                // which is resolved once the target is done with, see
+                // `Device::resolve_msaa`.
+                for &has_depth in &[false, true] {
+                    let mut attachments = vec![attachment.clone()];
+                    if has_depth {
+                        attachments.push(attachment_depth.clone());
+                    }
+                    let subpass = hal::pass::SubpassDesc {
+                        colors: &[(0, hal::image::Layout::ColorAttachmentOptimal)],
+                        depth_stencil: if has_depth {
+                            Some(&(1, hal::image::Layout::DepthStencilAttachmentOptimal))
+                        } else {
+                            None
+                        },
+                        inputs: &[],
+                        resolves: &[],
+                        preserves: &[],
+                    };
+                    let dependency = if has_depth {
+                        dependency_depth.clone()
+                    } else {
+                        dependency.clone()
+                    };
+                    passes.insert(
+                        (format, has_depth, samples),
+                        device.create_render_pass(&attachments, &[subpass], &[dependency]),
+                    );
+                }
+            }
         }
+
+        RenderPass { passes }
+    }
+
+    pub fn get_render_pass(
+        &self,
+        format: ImageFormat,
+        depth_enabled: bool,
+        samples: hal::image::NumSamples,
+    ) -> &B::RenderPass {
+        &self.passes[&(format, depth_enabled, samples)]
     }
 
     pub fn deinit(self, device: &B::Device) {
-        device.destroy_render_pass(self.r8);
-        device.destroy_render_pass(self.r8_depth);
-        device.destroy_render_pass(self.bgra8);
-        device.destroy_render_pass(self.bgra8_depth);
+        for (_, render_pass) in self.passes {
+            device.destroy_render_pass(render_pass);
+        }
     }
 }
 
@@ -1876,7 +1733,7 @@ impl<B: hal::Backend> DescriptorPools<B> {
     fn get_pool(&mut self, shader_kind: &ShaderKind) -> &mut DescPool<B> {
         match *shader_kind {
             ShaderKind::DebugColor | ShaderKind::DebugFont => &mut self.debug_pool,
-            ShaderKind::ClipCache => &mut self.cache_clip_pool,
+            ShaderKind::ClipCache | ShaderKind::ClipImage => &mut self.cache_clip_pool,
             _ => &mut self.default_pool,
         }
     }
@@ -1911,6 +1768,310 @@ struct Fence<B: hal::Backend> {
     is_submitted: bool,
 }
 
+/// Resources which may still be referenced by the command buffers of a frame.
+/// They are destroyed once the fence of that frame is signaled.
+struct RetiredResources<B: hal::Backend> {
+    images: Vec<Image<B>>,
+    framebuffers: Vec<Framebuffer<B>>,
+    depth_buffers: Vec<DepthBuffer<B>>,
+    staging_buffers: Vec<CopyBuffer<B>>,
+}
+
+impl<B: hal::Backend> RetiredResources<B> {
+    fn new() -> Self {
+        RetiredResources {
+            images: Vec::new(),
+            framebuffers: Vec::new(),
+            depth_buffers: Vec::new(),
+            staging_buffers: Vec::new(),
+        }
+    }
+
+    fn destroy(&mut self, device: &B::Device) {
+        for image in self.images.drain(..) {
+            image.deinit(device);
+        }
+        for framebuffer in self.framebuffers.drain(..) {
+            framebuffer.deinit(device);
+        }
+        for depth_buffer in self.depth_buffers.drain(..) {
+            depth_buffer.deinit(device);
+        }
+        for buffer in self.staging_buffers.drain(..) {
+            buffer.deinit(device);
+        }
+    }
+}
+
+/// A multisampled draw target, which has to be resolved into its texture
+/// or frame image before that is read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MsaaTarget {
+    Framebuffer(FBOId),
+    Surface(SurfaceId),
+}
+
+/// How many frames the storage of a freed render target is kept around for.
+const RENDER_TARGET_POOL_FRAMES: usize = 60;
+
+/// The layout a render target allocation has to match to be reused.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct RenderTargetKey {
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    layer_count: i32,
+    filter: TextureFilter,
+    render_target: RenderTargetInfo,
+}
+
+impl RenderTargetKey {
+    fn new(texture: &Texture) -> Option<Self> {
+        texture.render_target.map(|render_target| RenderTargetKey {
+            format: texture.format,
+            width: texture.width,
+            height: texture.height,
+            layer_count: texture.layer_count,
+            filter: texture.filter,
+            render_target,
+        })
+    }
+}
+
+/// The storage of a freed render target texture. Its framebuffers and depth
+/// buffer stay registered under their ids, so that a later render target with
+/// the same layout can take them over instead of allocating new memory.
+struct PooledRenderTarget<B: hal::Backend> {
+    key: RenderTargetKey,
+    image: Image<B>,
+    fbo_ids: Vec<FBOId>,
+    depth_rb: Option<RBOId>,
+    last_frame_used: FrameId,
+}
+
+/// The images a window surface presents, or the offscreen image drawn into
+/// when there is no window, with the framebuffers rendering to them.
+pub struct SurfaceTarget<B: hal::Backend> {
+    swap_chain: Option<B::Swapchain>,
+    format: hal::format::Format,
+    extent: hal::window::Extent2D,
+    frame_images: Vec<ImageCore<B>>,
+    framebuffers: Vec<B::Framebuffer>,
+    framebuffers_depth: Vec<B::Framebuffer>,
+    frame_depth: DepthBuffer<B>,
+    /// The multisampled image the framebuffers draw into, if MSAA is enabled.
+    frame_msaa: Option<ImageCore<B>>,
+    samples: hal::image::NumSamples,
+    current_frame_id: usize,
+    image_available_semaphore: B::Semaphore,
+    render_finished_semaphore: B::Semaphore,
+}
+
+impl<B: hal::Backend> SurfaceTarget<B> {
+    fn new(
+        device: &B::Device,
+        memory_types: &[hal::MemoryType],
+        surface: Option<(&B::PhysicalDevice, &mut B::Surface)>,
+        window_size: (u32, u32),
+        render_pass: &RenderPass<B>,
+        depth_format: hal::format::Format,
+        msaa_samples: hal::image::NumSamples,
+    ) -> Self {
+        let (surface_format, extent) = match surface {
+            Some((physical_device, ref surface)) => {
+                let (caps, formats, _present_modes) = surface.compatibility(physical_device);
+                let surface_format = formats
+                    .map_or(
+                        hal::format::Format::Bgra8Unorm,
+                        |formats| {
+                            formats
+                                .into_iter()
+                                .find(|format| {
+                                    format == &hal::format::Format::Bgra8Unorm
+                                })
+                                .unwrap()
+                        },
+                    );
+
+                let extent = caps.current_extent.unwrap_or(
+                    hal::window::Extent2D {
+                        width: window_size.0.max(caps.extents.start.width).min(caps.extents.end.width),
+                        height: window_size.1.max(caps.extents.start.height).min(caps.extents.end.height),
+                    }
+                );
+                (surface_format, extent)
+            }
+            // Without a surface we render into an offscreen image of the window size.
+            None => (
+                hal::format::Format::Bgra8Unorm,
+                hal::window::Extent2D {
+                    width: window_size.0,
+                    height: window_size.1,
+                },
+            ),
+        };
+
+        println!("{:?}", surface_format);
+        assert_eq!(surface_format, hal::format::Format::Bgra8Unorm);
+        let frame_usage =
+            hal::image::Usage::TRANSFER_SRC | hal::image::Usage::TRANSFER_DST | hal::image::Usage::COLOR_ATTACHMENT;
+        let (swap_chain, frame_images, backend_framebuffer) = match surface {
+            Some((_, surface)) => {
+                //let min_image_count = caps.image_count.start;
+                let swap_config =
+                    SwapchainConfig::new()
+                        .with_color(surface_format)
+                        //.with_image_count(min_image_count)
+                        .with_image_count(MAX_FRAME_COUNT as _)
+                        .with_image_usage(frame_usage);
+                let (swap_chain, backbuffer) = device.create_swapchain(surface, swap_config, None, &extent);
+                println!("backbuffer={:?}", backbuffer);
+                match backbuffer {
+                    Backbuffer::Images(images) => {
+                        let cores = images
+                            .into_iter()
+                            .map(|image| {
+                                ImageCore::from_image(device, image, hal::image::ViewKind::D2Array, surface_format, COLOR_RANGE.clone())
+                            })
+                            .collect();
+                        (Some(swap_chain), cores, None)
+                    }
+                    Backbuffer::Framebuffer(fbo) => (Some(swap_chain), Vec::new(), Some(fbo)),
+                }
+            }
+            None => {
+                let core = ImageCore::create(
+                    device,
+                    memory_types,
+                    hal::image::Kind::D2(extent.width, extent.height, 1, 1),
+                    hal::image::ViewKind::D2Array,
+                    1,
+                    surface_format,
+                    frame_usage,
+                    COLOR_RANGE.clone(),
+                );
+                (None, vec![core], None)
+            }
+        };
+        // The backend owned framebuffer can't be multisampled by us.
+        let frame_samples = match backend_framebuffer {
+            Some(_) => 1,
+            None => msaa_samples,
+        };
+        let frame_depth = DepthBuffer::new(device, memory_types, extent.width, extent.height, frame_samples, depth_format);
+        let frame_msaa = if frame_samples > 1 {
+            Some(ImageCore::create(
+                device,
+                memory_types,
+                hal::image::Kind::D2(extent.width, extent.height, 1, frame_samples),
+                hal::image::ViewKind::D2Array,
+                1,
+                surface_format,
+                hal::image::Usage::TRANSFER_DST | hal::image::Usage::COLOR_ATTACHMENT,
+                COLOR_RANGE.clone(),
+            ))
+        } else {
+            None
+        };
+
+        // Framebuffer and render target creation
+        let (framebuffers, framebuffers_depth) = match backend_framebuffer {
+            // TODO fix depth fbos
+            Some(fbo) => (vec![fbo], vec![]),
+            None => {
+                let extent = hal::image::Extent {
+                    width: extent.width as _,
+                    height: extent.height as _,
+                    depth: 1,
+                };
+                // With MSAA we draw into the multisampled image, which is
+                // resolved into the swapchain image at the end of the frame.
+                let fbos = frame_images
+                    .iter()
+                    .map(|core| {
+                        let attachments = match frame_msaa {
+                            Some(ref msaa) => vec![&msaa.view],
+                            None => vec![&core.view],
+                        };
+                        device
+                            .create_framebuffer(
+                                render_pass.get_render_pass(ImageFormat::BGRA8, false, frame_samples),
+                                attachments,
+                                extent,
+                            )
+                            .unwrap()
+                    })
+                    .collect();
+                let fbos_depth = frame_images
+                    .iter()
+                    .map(|core| {
+                        let attachments = match frame_msaa {
+                            Some(ref msaa) => vec![&msaa.view, &frame_depth.core.view],
+                            None => vec![&core.view, &frame_depth.core.view],
+                        };
+                        device
+                            .create_framebuffer(
+                                render_pass.get_render_pass(ImageFormat::BGRA8, true, frame_samples),
+                                attachments,
+                                extent,
+                            )
+                            .unwrap()
+                    })
+                    .collect();
+                (fbos, fbos_depth)
+            }
+        };
+
+        SurfaceTarget {
+            swap_chain,
+            format: surface_format,
+            extent,
+            frame_images,
+            framebuffers,
+            framebuffers_depth,
+            frame_depth,
+            frame_msaa,
+            samples: frame_samples,
+            current_frame_id: 0,
+            image_available_semaphore: device.create_semaphore(),
+            render_finished_semaphore: device.create_semaphore(),
+        }
+    }
+
+    fn frame_image(&self) -> &ImageCore<B> {
+        &self.frame_images[self.current_frame_id]
+    }
+
+    fn framebuffer(&self, has_depth: bool) -> &B::Framebuffer {
+        if has_depth {
+            &self.framebuffers_depth[self.current_frame_id]
+        } else {
+            &self.framebuffers[self.current_frame_id]
+        }
+    }
+
+    fn deinit(self, device: &B::Device) {
+        for image in self.frame_images {
+            image.deinit(device);
+        }
+        for framebuffer in self.framebuffers {
+            device.destroy_framebuffer(framebuffer);
+        }
+        for framebuffer_depth in self.framebuffers_depth {
+            device.destroy_framebuffer(framebuffer_depth);
+        }
+        self.frame_depth.deinit(device);
+        if let Some(frame_msaa) = self.frame_msaa {
+            frame_msaa.deinit(device);
+        }
+        device.destroy_semaphore(self.image_available_semaphore);
+        device.destroy_semaphore(self.render_finished_semaphore);
+        if let Some(swap_chain) = self.swap_chain {
+            device.destroy_swapchain(swap_chain);
+        }
+    }
+}
+
 pub struct Device<B: hal::Backend> {
     pub device: B::Device,
     pub memory_types: Vec<hal::MemoryType>,
@@ -1921,20 +2082,22 @@ pub struct Device<B: hal::Backend> {
     pub depth_format: hal::format::Format,
     pub queue_group: hal::QueueGroup<B, hal::Graphics>,
     pub command_pool: SmallVec<[hal::CommandPool<B, hal::Graphics>; 1]>,
-    pub swap_chain: B::Swapchain,
     pub render_pass: RenderPass<B>,
-    pub framebuffers: Vec<B::Framebuffer>,
-    pub framebuffers_depth: Vec<B::Framebuffer>,
-    pub frame_images: Vec<ImageCore<B>>,
-    pub frame_depth: DepthBuffer<B>,
+    surfaces: FastHashMap<SurfaceId, SurfaceTarget<B>>,
+    /// The surface the default framebuffer refers to.
+    bound_surface: SurfaceId,
+    next_surface_id: u32,
     pub viewport: hal::pso::Viewport,
     pub sampler_linear: B::Sampler,
     pub sampler_nearest: B::Sampler,
     pub upload_queue: Vec<hal::command::Submit<B, hal::Graphics, hal::command::MultiShot, hal::command::Primary>>,
-    pub current_frame_id: usize,
+    retired_resources: SmallVec<[RetiredResources<B>; 1]>,
+    render_target_pool: Vec<PooledRenderTarget<B>>,
+    staging_ring: StagingRing<B>,
     current_blend_state: BlendState,
     blend_color: ColorF,
     current_depth_test: DepthTest,
+    depth_function: Comparison,
     // device state
     programs: FastHashMap<ProgramId, Program<B>>,
     images: FastHashMap<TextureId, Image<B>>,
@@ -1947,6 +2110,8 @@ pub struct Device<B: hal::Backend> {
     bound_sampler: [TextureFilter; 16],
     bound_read_fbo: FBOId,
     bound_draw_fbo: FBOId,
+    // The multisampled target drawn to since its last resolve.
+    pending_msaa_resolve: Option<MsaaTarget>,
     program_mode_id: i32,
     scissor_rect: Option<DeviceIntRect>,
     //default_read_fbo: FBOId,
@@ -1956,7 +2121,6 @@ pub struct Device<B: hal::Backend> {
     upload_method: UploadMethod,
 
     // HW or API capabilities
-    #[cfg(feature = "debug_renderer")]
     capabilities: Capabilities,
 
     // debug
@@ -1966,7 +2130,11 @@ pub struct Device<B: hal::Backend> {
     _resource_override_path: Option<PathBuf>,
 
     max_texture_size: u32,
-    _renderer_name: String,
+    msaa_samples: hal::image::NumSamples,
+    renderer_name: String,
+    cached_programs: Option<Rc<ProgramCache>>,
+    // Digest of the shader sources, part of the program cache keys.
+    shaders_digest: u64,
 
     // Frame counter. This is used to map between CPU
     // frames and GPU frames.
@@ -1977,8 +2145,6 @@ pub struct Device<B: hal::Backend> {
 
     next_id: usize,
     frame_fence: SmallVec<[Fence<B>; 1]>,
-    image_available_semaphore: B::Semaphore,
-    render_finished_semaphore: B::Semaphore,
 }
 
 impl<B: hal::Backend> Device<B> {
@@ -1986,35 +2152,15 @@ impl<B: hal::Backend> Device<B> {
         resource_override_path: Option<PathBuf>,
         upload_method: UploadMethod,
         _file_changed_handler: Box<FileWatcherHandler>,
-        _cached_programs: Option<Rc<ProgramCache>>,
+        cached_programs: Option<Rc<ProgramCache>>,
         adapter: &hal::Adapter<B>,
-        surface: &mut <B as hal::Backend>::Surface,
+        surface: Option<&mut <B as hal::Backend>::Surface>,
         window_size: (u32, u32),
+        msaa_samples: u8,
     ) -> Self {
-        let renderer_name = "TODO renderer name".to_owned();
+        let renderer_name = adapter.info.name.clone();
         let features = adapter.physical_device.features();
 
-        let (caps, formats, _present_modes) = surface.compatibility(&adapter.physical_device);
-        let surface_format = formats
-            .map_or(
-                hal::format::Format::Bgra8Unorm,
-                |formats| {
-                    formats
-                        .into_iter()
-                        .find(|format| {
-                            format == &hal::format::Format::Bgra8Unorm
-                        })
-                        .unwrap()
-                },
-            );
-
-        let extent = caps.current_extent.unwrap_or(
-            hal::window::Extent2D {
-                width: window_size.0.max(caps.extents.start.width).min(caps.extents.end.width),
-                height: window_size.1.max(caps.extents.start.height).min(caps.extents.end.height),
-            }
-        );
-
         let memory_types = adapter
             .physical_device
             .memory_properties()
@@ -2022,7 +2168,57 @@ impl<B: hal::Backend> Device<B> {
         let limits = adapter
             .physical_device
             .limits();
-        let max_texture_size = 4096u32; // TODO use limits after it points to the correct texture size
+        // `Limits::max_texture_size` is the smallest of the image dimension limits,
+        // ask for the properties of the formats we render to instead.
+        let format_properties = [hal::format::Format::Bgra8Unorm, hal::format::Format::R8Unorm]
+            .iter()
+            .map(|&format| {
+                adapter.physical_device.image_format_properties(
+                    format,
+                    2,
+                    hal::image::Tiling::Optimal,
+                    hal::image::Usage::SAMPLED | hal::image::Usage::COLOR_ATTACHMENT,
+                    hal::image::StorageFlags::empty(),
+                )
+            })
+            .collect::<Option<Vec<_>>>();
+        let capabilities = match format_properties {
+            Some(properties) => Capabilities {
+                supports_multisampling: properties.iter().all(|p| p.sample_count_mask > 1),
+                supports_dual_source_blending: features.contains(hal::Features::DUAL_SRC_BLENDING),
+                max_texture_size: properties.iter()
+                    .map(|p| cmp::min(p.max_extent.width, p.max_extent.height))
+                    .min()
+                    .unwrap(),
+                max_texture_layers: properties.iter().map(|p| p.max_layers as u32).min().unwrap(),
+                max_samples: properties.iter()
+                    .map(|p| {
+                        // The mask has a bit set for every supported sample count.
+                        let mask = p.sample_count_mask & limits.framebuffer_color_samples_count;
+                        (1..8).rev().map(|bit| 1u8 << bit).find(|&count| mask & count != 0).unwrap_or(1)
+                    })
+                    .min()
+                    .unwrap(),
+            },
+            None => Capabilities {
+                supports_multisampling: false,
+                supports_dual_source_blending: features.contains(hal::Features::DUAL_SRC_BLENDING),
+                max_texture_size: limits.max_texture_size as u32,
+                // The minimum required by Vulkan.
+                max_texture_layers: 256,
+                max_samples: 1,
+            },
+        };
+        let max_texture_size = capabilities.max_texture_size;
+        // Sample counts are powers of two, round the requested one down.
+        let requested_samples = msaa_samples;
+        let msaa_samples = match requested_samples.min(capabilities.max_samples) {
+            0 => 1,
+            samples => 1 << (7 - samples.leading_zeros()),
+        };
+        if msaa_samples != requested_samples.max(1) {
+            warn!("Requested {} samples per pixel, using {}", requested_samples, msaa_samples);
+        }
 
         let upload_memory_type: hal::MemoryTypeId = memory_types
             .iter()
@@ -2044,7 +2240,10 @@ impl<B: hal::Backend> Device<B> {
 
         let queue_family = adapter.queue_families
             .iter()
-            .find(|family| surface.supports_queue_family(family))
+            .find(|family| match surface {
+                Some(ref surface) => surface.supports_queue_family(family),
+                None => family.supports_graphics(),
+            })
             .expect("No queue family is able to render to the surface!");
         let mut gpu = adapter.physical_device
             .open(&[(queue_family, &[1.0])])
@@ -2054,149 +2253,24 @@ impl<B: hal::Backend> Device<B> {
             .take(queue_family.id())
             .unwrap();
 
-        println!("{:?}", surface_format);
-        assert_eq!(surface_format, hal::format::Format::Bgra8Unorm);
-        //let min_image_count = caps.image_count.start;
-        let swap_config =
-            SwapchainConfig::new()
-                .with_color(surface_format)
-                //.with_image_count(min_image_count)
-                .with_image_count(MAX_FRAME_COUNT as _)
-                .with_image_usage(
-                    hal::image::Usage::TRANSFER_SRC | hal::image::Usage::TRANSFER_DST | hal::image::Usage::COLOR_ATTACHMENT
-                );
-        let (swap_chain, backbuffer) = device.create_swapchain(surface, swap_config, None, &extent);
-        println!("backbuffer={:?}", backbuffer);
         let depth_format = hal::format::Format::D32Float; //maybe d24s8?
 
-        let render_pass = {
-            let attachment_r8 = hal::pass::Attachment {
-                format: Some(hal::format::Format::R8Unorm),
-                samples: 1,
-                ops: hal::pass::AttachmentOps::new(
-                    hal::pass::AttachmentLoadOp::DontCare,
-                    hal::pass::AttachmentStoreOp::Store,
-                ),
-                stencil_ops: hal::pass::AttachmentOps::DONT_CARE,
-                layouts: hal::image::Layout::ColorAttachmentOptimal .. hal::image::Layout::ColorAttachmentOptimal,
-            };
-
-            let attachment_bgra8 = hal::pass::Attachment {
-                format: Some(hal::format::Format::Bgra8Unorm),
-                samples: 1,
-                ops: hal::pass::AttachmentOps::new(
-                    hal::pass::AttachmentLoadOp::DontCare,
-                    hal::pass::AttachmentStoreOp::Store,
-                ),
-                stencil_ops: hal::pass::AttachmentOps::DONT_CARE,
-                layouts: hal::image::Layout::ColorAttachmentOptimal .. hal::image::Layout::ColorAttachmentOptimal,
-            };
-
-            let attachment_depth = hal::pass::Attachment {
-                format: Some(depth_format),
-                samples: 1,
-                ops: hal::pass::AttachmentOps::new(
-                    hal::pass::AttachmentLoadOp::DontCare,
-                    hal::pass::AttachmentStoreOp::Store,
-                ),
-                stencil_ops: hal::pass::AttachmentOps::DONT_CARE,
-                layouts: hal::image::Layout::DepthStencilAttachmentOptimal .. hal::image::Layout::DepthStencilAttachmentOptimal,
-            };
-
-            let subpass_r8 = hal::pass::SubpassDesc {
-                colors: &[(0, hal::image::Layout::ColorAttachmentOptimal)],
-                depth_stencil: None,
-                inputs: &[],
-                resolves: &[],
-                preserves: &[],
-            };
-
-            let subpass_depth_r8 = hal::pass::SubpassDesc {
-                colors: &[(0, hal::image::Layout::ColorAttachmentOptimal)],
-                depth_stencil: Some(&(1, hal::image::Layout::DepthStencilAttachmentOptimal)),
-                inputs: &[],
-                resolves: &[],
-                preserves: &[],
-            };
-
-            let subpass_bgra8 = hal::pass::SubpassDesc {
-                colors: &[(0, hal::image::Layout::ColorAttachmentOptimal)],
-                depth_stencil: None,
-                inputs: &[],
-                resolves: &[],
-                preserves: &[],
-            };
-
-            let subpass_depth_bgra8 = hal::pass::SubpassDesc {
-                colors: &[(0, hal::image::Layout::ColorAttachmentOptimal)],
-                depth_stencil: Some(&(1, hal::image::Layout::DepthStencilAttachmentOptimal)),
-                inputs: &[],
-                resolves: &[],
-                preserves: &[],
-            };
-
-            let dependency = hal::pass::SubpassDependency {
-                passes: hal::pass::SubpassRef::External .. hal::pass::SubpassRef::Pass(0),
-                stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT
-                    .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
-                accesses: hal::image::Access::empty()
-                    .. (hal::image::Access::COLOR_ATTACHMENT_READ
-                    | hal::image::Access::COLOR_ATTACHMENT_WRITE),
-            };
+        let sample_counts = if msaa_samples > 1 { vec![1, msaa_samples] } else { vec![1] };
+        let render_pass = RenderPass::create(&device, depth_format, &sample_counts);
 
-            RenderPass {
-                r8: device.create_render_pass(&[attachment_r8.clone()], &[subpass_r8], &[dependency.clone()]),
-                r8_depth: device.create_render_pass(&[attachment_r8, attachment_depth.clone()], &[subpass_depth_r8], &[dependency.clone()]),
-                bgra8: device.create_render_pass(&[attachment_bgra8.clone()], &[subpass_bgra8], &[dependency.clone()]),
-                bgra8_depth: device.create_render_pass(&[attachment_bgra8, attachment_depth], &[subpass_depth_bgra8], &[dependency]),
-            }
-        };
-
-        let frame_depth = DepthBuffer::new(&device, &memory_types, extent.width, extent.height, depth_format);
-
-        // Framebuffer and render target creation
-        let (frame_images, framebuffers, framebuffers_depth) = match backbuffer {
-            Backbuffer::Images(images) => {
-                let extent = hal::image::Extent {
-                    width: extent.width as _,
-                    height: extent.height as _,
-                    depth: 1,
-                };
-                let cores = images
-                    .into_iter()
-                    .map(|image| {
-                        ImageCore::from_image(&device, image, hal::image::ViewKind::D2Array, surface_format, COLOR_RANGE.clone())
-                    })
-                    .collect::<Vec<_>>();
-                let fbos = cores
-                    .iter()
-                    .map(|core| {
-                        device
-                            .create_framebuffer(
-                                &render_pass.bgra8,
-                                Some(&core.view),
-                                extent,
-                            )
-                            .unwrap()
-                    })
-                    .collect();
-                let fbos_depth = cores
-                    .iter()
-                    .map(|core| {
-                        device
-                            .create_framebuffer(
-                                &render_pass.bgra8_depth,
-                                vec![&core.view, &frame_depth.core.view],
-                                extent,
-                            )
-                            .unwrap()
-                    })
-                    .collect();
-                (cores, fbos, fbos_depth)
-            }
-            // TODO fix depth fbos
-            Backbuffer::Framebuffer(fbo) => (Vec::new(), vec![fbo], vec![]),
-        };
+        let main_surface = SurfaceTarget::new(
+            &device,
+            &memory_types,
+            surface.map(|surface| (&adapter.physical_device, surface)),
+            window_size,
+            &render_pass,
+            depth_format,
+            msaa_samples,
+        );
+        let surface_format = main_surface.format;
+        let extent = main_surface.extent;
+        let mut surfaces = FastHashMap::default();
+        surfaces.insert(MAIN_SURFACE, main_surface);
 
         // Rendering setup
         let viewport = hal::pso::Viewport {
@@ -2229,7 +2303,9 @@ impl<B: hal::Backend> Device<B> {
         let mut descriptor_pools = SmallVec::new();
         let mut frame_fence = SmallVec::new();
         let mut command_pool = SmallVec::new();
+        let mut retired_resources = SmallVec::new();
         for _ in 0..MAX_FRAME_COUNT {
+            retired_resources.push(RetiredResources::new());
             descriptor_pools.push(
                 DescriptorPools::new(
                     &device,
@@ -2258,8 +2334,7 @@ impl<B: hal::Backend> Device<B> {
             );
         }
 
-        let image_available_semaphore = device.create_semaphore();
-        let render_finished_semaphore = device.create_semaphore();
+        let staging_ring = StagingRing::new(&device, &memory_types, STAGING_RING_SIZE);
 
         Device {
             device,
@@ -2271,19 +2346,20 @@ impl<B: hal::Backend> Device<B> {
             depth_format,
             queue_group,
             command_pool,
-            swap_chain,
             render_pass,
-            framebuffers,
-            framebuffers_depth,
-            frame_images,
-            frame_depth,
+            surfaces,
+            bound_surface: MAIN_SURFACE,
+            next_surface_id: MAIN_SURFACE.0 + 1,
             viewport,
             sampler_linear,
             sampler_nearest,
             upload_queue: Vec::new(),
-            current_frame_id: 0,
+            retired_resources,
+            render_target_pool: Vec::new(),
+            staging_ring,
             current_blend_state: BlendState::Off,
             current_depth_test: DepthTest::Off,
+            depth_function: Comparison::LessEqual,
             blend_color: ColorF::new(0.0, 0.0, 0.0, 0.0),
             _resource_override_path: resource_override_path,
             // This is initialized to 1 by default, but it is reset
@@ -2292,10 +2368,7 @@ impl<B: hal::Backend> Device<B> {
             upload_method,
             inside_frame: false,
 
-            #[cfg(feature = "debug_renderer")]
-            capabilities: Capabilities {
-                supports_multisampling: false, //TODO
-            },
+            capabilities,
 
             programs: FastHashMap::default(),
             images: FastHashMap::default(),
@@ -2307,39 +2380,152 @@ impl<B: hal::Backend> Device<B> {
             bound_sampler: [TextureFilter::Linear; 16],
             bound_read_fbo: DEFAULT_READ_FBO,
             bound_draw_fbo: DEFAULT_DRAW_FBO,
+            pending_msaa_resolve: None,
             program_mode_id: 0,
             scissor_rect: None,
 
             max_texture_size,
-            _renderer_name: renderer_name,
+            msaa_samples,
+            renderer_name,
+            cached_programs,
+            shaders_digest: shader_sources_digest(),
             frame_id: FrameId(0),
             features,
             next_id: 0,
             frame_fence,
-            image_available_semaphore,
-            render_finished_semaphore,
         }
     }
 
-    pub(crate) fn bound_program(&self) -> ProgramId { self.bound_program }
-
-    pub fn set_device_pixel_ratio(&mut self, ratio: f32) {
-        self.device_pixel_ratio = ratio;
+    pub(crate) fn bound_program(&self) -> ProgramId { self.bound_program }
+
+    pub fn set_device_pixel_ratio(&mut self, ratio: f32) {
+        self.device_pixel_ratio = ratio;
+    }
+
+    pub fn update_program_cache(&mut self, cached_programs: Rc<ProgramCache>) {
+        self.cached_programs = Some(cached_programs);
+    }
+
+    pub fn max_texture_size(&self) -> u32 {
+        self.max_texture_size
+    }
+
+    /// The number of samples per pixel of the main framebuffer and of the
+    /// render targets that ask for multisampling.
+    pub fn msaa_samples(&self) -> u8 {
+        self.msaa_samples
+    }
+
+    /// Creates a swapchain for another window, sharing the device resources
+    /// with the main surface.
+    pub fn add_surface(
+        &mut self,
+        adapter: &hal::Adapter<B>,
+        surface: &mut B::Surface,
+        window_size: (u32, u32),
+    ) -> SurfaceId {
+        let surface_target = SurfaceTarget::new(
+            &self.device,
+            &self.memory_types,
+            Some((&adapter.physical_device, surface)),
+            window_size,
+            &self.render_pass,
+            self.depth_format,
+            self.msaa_samples,
+        );
+        let id = SurfaceId(self.next_surface_id);
+        self.next_surface_id += 1;
+        self.surfaces.insert(id, surface_target);
+        id
+    }
+
+    pub fn remove_surface(&mut self, id: SurfaceId) {
+        assert_ne!(id, MAIN_SURFACE, "The main surface can't be removed");
+        debug_assert!(!self.inside_frame);
+        if let Some(surface) = self.surfaces.remove(&id) {
+            // The swapchain images may still be used by the frames in flight.
+            self.wait_for_resources();
+            surface.deinit(&self.device);
+        }
+        if self.bound_surface == id {
+            self.bound_surface = MAIN_SURFACE;
+        }
+    }
+
+    /// Recreates the swapchain of a surface added with `add_surface`, and its
+    /// framebuffers, for a new window size.
+    pub fn resize_surface(
+        &mut self,
+        id: SurfaceId,
+        adapter: &hal::Adapter<B>,
+        surface: &mut B::Surface,
+        window_size: (u32, u32),
+    ) {
+        assert_ne!(id, MAIN_SURFACE, "Only added surfaces can be resized");
+        debug_assert!(!self.inside_frame);
+        // The frames in flight may still render into the old swapchain images.
+        self.wait_for_resources();
+        let old_target = self.surfaces.remove(&id).expect("Unknown surface");
+        old_target.deinit(&self.device);
+        let target = SurfaceTarget::new(
+            &self.device,
+            &self.memory_types,
+            Some((&adapter.physical_device, surface)),
+            window_size,
+            &self.render_pass,
+            self.depth_format,
+            self.msaa_samples,
+        );
+        self.surfaces.insert(id, target);
+    }
+
+    /// Recreates the offscreen image the frames are rendered into, and its
+    /// framebuffers, for a new window size. The device must have been created
+    /// without a window surface.
+    pub fn resize_offscreen_surface(&mut self, window_size: (u32, u32)) {
+        debug_assert!(!self.inside_frame);
+        assert!(
+            self.surfaces[&MAIN_SURFACE].swap_chain.is_none(),
+            "Only a device without a window surface renders offscreen"
+        );
+        // The frames in flight may still render into the old image.
+        self.wait_for_resources();
+        let old_target = self.surfaces.remove(&MAIN_SURFACE).unwrap();
+        old_target.deinit(&self.device);
+        let target = SurfaceTarget::new(
+            &self.device,
+            &self.memory_types,
+            None,
+            window_size,
+            &self.render_pass,
+            self.depth_format,
+            self.msaa_samples,
+        );
+        self.surfaces.insert(MAIN_SURFACE, target);
     }
 
-    pub fn update_program_cache(&mut self, cached_programs: Rc<ProgramCache>) {
-        unimplemented!();
+    /// Makes the default framebuffer refer to the given surface.
+    pub fn bind_surface(&mut self, id: SurfaceId) {
+        debug_assert!(self.surfaces.contains_key(&id));
+        if self.bound_surface != id {
+            self.resolve_msaa();
+        }
+        self.bound_surface = id;
     }
 
-    pub fn max_texture_size(&self) -> u32 {
-        self.max_texture_size
+    pub fn surface_size(&self, id: SurfaceId) -> DeviceUintSize {
+        let extent = self.surfaces[&id].extent;
+        DeviceUintSize::new(extent.width, extent.height)
     }
 
-    #[cfg(feature = "debug_renderer")]
     pub fn get_capabilities(&self) -> &Capabilities {
         &self.capabilities
     }
 
+    pub fn renderer_name(&self) -> &str {
+        &self.renderer_name
+    }
+
     pub fn reset_state(&mut self) {
         self.bound_textures = [0; 16];
         self.bound_program = INVALID_PROGRAM_ID;
@@ -2371,20 +2557,53 @@ impl<B: hal::Backend> Device<B> {
         pipeline_requirements: PipelineRequirements,
         shader_name: &str,
         shader_kind: &ShaderKind,
-    ) -> ProgramId {
-        let program = Program::create(
-            pipeline_requirements,
-            &self.device,
-            self.device.create_pipeline_layout(Some(self.descriptor_pools[self.next_id].get_layout(shader_kind)), &[]),
-            &self.memory_types,
-            shader_name,
-            shader_kind.clone(),
-            &self.render_pass,
-        );
+    ) -> Result<ProgramId, ShaderError> {
+        let sources = ProgramSources::new(self.renderer_name.clone(), shader_name, self.shaders_digest);
+        let pipeline_layout =
+            self.device.create_pipeline_layout(Some(self.descriptor_pools[self.next_id].get_layout(shader_kind)), &[]);
+
+        let cached_binary = self.cached_programs
+            .as_ref()
+            .and_then(|cached_programs| cached_programs.get(&sources));
+
+        let program = match cached_binary {
+            Some(binary) => {
+                Program::create(
+                    binary.pipeline_requirements.clone(),
+                    &self.device,
+                    pipeline_layout,
+                    &self.memory_types,
+                    &binary.vs_module,
+                    &binary.fs_module,
+                    shader_name,
+                    shader_kind.clone(),
+                    &self.render_pass,
+                )
+            }
+            None => {
+                let vs_module = get_shader_source(shader_name, ".vert.spv");
+                let fs_module = get_shader_source(shader_name, ".frag.spv");
+                let program = Program::create(
+                    pipeline_requirements.clone(),
+                    &self.device,
+                    pipeline_layout,
+                    &self.memory_types,
+                    &vs_module,
+                    &fs_module,
+                    shader_name,
+                    shader_kind.clone(),
+                    &self.render_pass,
+                );
+                if let Some(ref cached_programs) = self.cached_programs {
+                    cached_programs.add(ProgramBinary::new(vs_module, fs_module, pipeline_requirements, &sources));
+                }
+                program
+            }
+        };
 
         let id = self.generate_program_id();
         self.programs.insert(id, program);
-        id
+        Ok(id)
     }
 
     pub fn bind_program(&mut self, program_id: &ProgramId) {
@@ -2415,11 +2634,15 @@ impl<B: hal::Backend> Device<B> {
         let desc_set = self.descriptor_pools[self.next_id].get(&program.shader_kind);
         for &(index, sampler_name) in SAMPLERS.iter() {
             if self.bound_textures[index] != 0 {
-                let sampler = match self.bound_sampler[index] {
-                    TextureFilter::Linear | TextureFilter::Trilinear => &self.sampler_linear,
-                    TextureFilter::Nearest => &self.sampler_nearest,
+                let image = &self.images[&self.bound_textures[index]];
+                // Integer textures don't support linear filtering.
+                let sampler = match (self.bound_sampler[index], image.format) {
+                    (_, ImageFormat::RGBAI32) |
+                    (TextureFilter::Nearest, _) => &self.sampler_nearest,
+                    (TextureFilter::Linear, _) |
+                    (TextureFilter::Trilinear, _) => &self.sampler_linear,
                 };
-                program.bind_texture(&self.device, desc_set, &self.images[&self.bound_textures[index]].core, &sampler, sampler_name);
+                program.bind_texture(&self.device, desc_set, &image.core, &sampler, sampler_name);
             }
         }
     }
@@ -2476,21 +2699,26 @@ impl<B: hal::Backend> Device<B> {
     fn draw(
         &mut self,
     ) {
+        self.mark_msaa_target_drawn();
         let submit = {
-            let (fb, format) = if self.bound_draw_fbo != DEFAULT_DRAW_FBO {
-                (&self.fbos[&self.bound_draw_fbo].fbo, self.fbos[&self.bound_draw_fbo].format)
+            // The render pass has to match the attachments of the framebuffer,
+            // even if depth testing is disabled for this draw.
+            let (fb, format, has_depth, samples) = if self.bound_draw_fbo != DEFAULT_DRAW_FBO {
+                let fbo = &self.fbos[&self.bound_draw_fbo];
+                (&fbo.fbo, fbo.format, fbo.rbo != RBOId(0), fbo.samples)
             } else {
-                if self.current_depth_test == DepthTest::Off {
-                    (&self.framebuffers[self.current_frame_id], ImageFormat::BGRA8)
-                } else {
-                    (&self.framebuffers_depth[self.current_frame_id], ImageFormat::BGRA8)
-                }
+                let surface = &self.surfaces[&self.bound_surface];
+                let has_depth = self.current_depth_test != DepthTest::Off;
+                (surface.framebuffer(has_depth), ImageFormat::BGRA8, has_depth, surface.samples)
             };
-            let rp = self.render_pass.get_render_pass(format, self.current_depth_test != DepthTest::Off);
             self.programs.get_mut(&self.bound_program).expect("Program not found").submit(
+                &self.device,
                 &mut self.command_pool[self.next_id],
                 self.viewport.clone(),
-                rp,
+                &self.render_pass,
+                format,
+                has_depth,
+                samples,
                 &fb,
                 &mut self.descriptor_pools[self.next_id],
                 &vec![],
@@ -2515,6 +2743,8 @@ impl<B: hal::Backend> Device<B> {
         self.bound_draw_fbo = DEFAULT_DRAW_FBO;
         self.program_mode_id = 0;
 
+        self.expire_pooled_render_targets();
+
         self.frame_id
     }
 
@@ -2562,10 +2792,142 @@ impl<B: hal::Backend> Device<B> {
         debug_assert!(self.inside_frame);
 
         if self.bound_draw_fbo != fbo_id {
+            self.resolve_msaa();
             self.bound_draw_fbo = fbo_id;
         }
     }
 
+    /// Records that the bound draw target was drawn to, if it is multisampled.
+    fn mark_msaa_target_drawn(&mut self) {
+        let target = if self.bound_draw_fbo != DEFAULT_DRAW_FBO {
+            if self.fbos[&self.bound_draw_fbo].samples <= 1 {
+                return;
+            }
+            MsaaTarget::Framebuffer(self.bound_draw_fbo)
+        } else {
+            if self.surfaces[&self.bound_surface].samples <= 1 {
+                return;
+            }
+            MsaaTarget::Surface(self.bound_surface)
+        };
+        if self.pending_msaa_resolve != Some(target) {
+            self.resolve_msaa();
+            self.pending_msaa_resolve = Some(target);
+        }
+    }
+
+    /// Resolves the multisampled image of the last multisampled target drawn
+    /// to into its texture layer or frame image. Draws accumulate in the
+    /// multisampled image, so this is only done once the target is switched
+    /// away from or read, rather than by every render pass.
+    fn resolve_msaa(&mut self) {
+        let target = match self.pending_msaa_resolve.take() {
+            Some(target) => target,
+            None => return,
+        };
+        // The target may have been freed since it was drawn to.
+        let (msaa, image, layer, width, height) = match target {
+            MsaaTarget::Framebuffer(fbo_id) => {
+                let fbo = match self.fbos.get(&fbo_id) {
+                    Some(fbo) => fbo,
+                    None => return,
+                };
+                let img = match self.images.get(&fbo.texture) {
+                    Some(img) => img,
+                    None => return,
+                };
+                let extent = img.kind.extent();
+                (img.msaa.as_ref(), &img.core, fbo.layer_index, extent.width, extent.height)
+            }
+            MsaaTarget::Surface(id) => {
+                let surface = match self.surfaces.get(&id) {
+                    Some(surface) => surface,
+                    None => return,
+                };
+                (surface.frame_msaa.as_ref(), surface.frame_image(), 0, surface.extent.width, surface.extent.height)
+            }
+        };
+        let msaa = match msaa {
+            Some(msaa) => msaa,
+            None => return,
+        };
+
+        let range = hal::image::SubresourceRange {
+            aspects: hal::format::Aspects::COLOR,
+            levels: 0 .. 1,
+            layers: layer .. layer + 1,
+        };
+        let layers = hal::image::SubresourceLayers {
+            aspects: hal::format::Aspects::COLOR,
+            level: 0,
+            layers: layer .. layer + 1,
+        };
+        let mut cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer(false);
+        {
+            let mut barriers = Vec::new();
+            barriers.extend(msaa.transit(
+                hal::image::Access::TRANSFER_READ,
+                hal::image::Layout::TransferSrcOptimal,
+                range.clone(),
+            ));
+            barriers.extend(image.transit(
+                hal::image::Access::TRANSFER_WRITE,
+                hal::image::Layout::TransferDstOptimal,
+                range.clone(),
+            ));
+            if !barriers.is_empty() {
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::COLOR_ATTACHMENT_OUTPUT .. PipelineStage::TRANSFER,
+                    hal::memory::Dependencies::empty(),
+                    &barriers,
+                );
+            }
+        }
+
+        cmd_buffer.resolve_image(
+            &msaa.image,
+            hal::image::Layout::TransferSrcOptimal,
+            &image.image,
+            hal::image::Layout::TransferDstOptimal,
+            &[
+                hal::command::ImageResolve {
+                    src_subresource: layers.clone(),
+                    src_offset: hal::image::Offset { x: 0, y: 0, z: 0 },
+                    dst_subresource: layers,
+                    dst_offset: hal::image::Offset { x: 0, y: 0, z: 0 },
+                    extent: hal::image::Extent {
+                        width,
+                        height,
+                        depth: 1,
+                    },
+                }
+            ],
+        );
+
+        {
+            let mut barriers = Vec::new();
+            barriers.extend(msaa.transit(
+                hal::image::Access::COLOR_ATTACHMENT_READ | hal::image::Access::COLOR_ATTACHMENT_WRITE,
+                hal::image::Layout::ColorAttachmentOptimal,
+                range.clone(),
+            ));
+            barriers.extend(image.transit(
+                hal::image::Access::COLOR_ATTACHMENT_READ | hal::image::Access::COLOR_ATTACHMENT_WRITE,
+                hal::image::Layout::ColorAttachmentOptimal,
+                range,
+            ));
+            if !barriers.is_empty() {
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                    hal::memory::Dependencies::empty(),
+                    &barriers,
+                );
+            }
+        }
+
+        self.upload_queue.push(cmd_buffer.finish());
+    }
+
     pub fn bind_draw_target(
         &mut self,
         texture_and_layer: Option<(&Texture, i32)>,
@@ -2574,6 +2936,9 @@ impl<B: hal::Backend> Device<B> {
         let fbo_id = texture_and_layer.map_or(DEFAULT_DRAW_FBO, |texture_and_layer| {
             texture_and_layer.0.fbo_ids[texture_and_layer.1 as usize]
         });
+        if fbo_id != self.bound_draw_fbo {
+            self.resolve_msaa();
+        }
 
         if let Some((texture, layer_index)) = texture_and_layer {
             let mut cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer(false);
@@ -2609,6 +2974,46 @@ impl<B: hal::Backend> Device<B> {
             self.upload_queue.push(cmd_buffer.finish())
         }
 
+        // The frame image may have been presented or read back since the last draw.
+        let frame_image = match texture_and_layer {
+            Some(_) => None,
+            None => {
+                let surface = &self.surfaces[&self.bound_surface];
+                surface.frame_images.get(surface.current_frame_id)
+            }
+        };
+        if let Some(barrier) = frame_image.and_then(|image| image.transit(
+            hal::image::Access::COLOR_ATTACHMENT_READ | hal::image::Access::COLOR_ATTACHMENT_WRITE,
+            hal::image::Layout::ColorAttachmentOptimal,
+            image.subresource_range.clone(),
+        )) {
+            let mut cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer(false);
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::COLOR_ATTACHMENT_OUTPUT .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                hal::memory::Dependencies::empty(),
+                &[barrier],
+            );
+            self.upload_queue.push(cmd_buffer.finish());
+        }
+
+        let msaa = match texture_and_layer {
+            Some((texture, _)) => self.images[&texture.id].msaa.as_ref(),
+            None => self.surfaces[&self.bound_surface].frame_msaa.as_ref(),
+        };
+        if let Some(barrier) = msaa.and_then(|msaa| msaa.transit(
+            hal::image::Access::COLOR_ATTACHMENT_READ | hal::image::Access::COLOR_ATTACHMENT_WRITE,
+            hal::image::Layout::ColorAttachmentOptimal,
+            msaa.subresource_range.clone(),
+        )) {
+            let mut cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer(false);
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::COLOR_ATTACHMENT_OUTPUT .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                hal::memory::Dependencies::empty(),
+                &[barrier],
+            );
+            self.upload_queue.push(cmd_buffer.finish());
+        }
+
         self.bind_draw_target_impl(fbo_id);
 
         if let Some(dimensions) = dimensions {
@@ -2703,10 +3108,44 @@ impl<B: hal::Backend> Device<B> {
     /// preserves the data by blitting the old texture contents over.
     pub fn resize_renderable_texture(
         &mut self,
-        _texture: &mut Texture,
-        _new_size: DeviceUintSize,
+        texture: &mut Texture,
+        new_size: DeviceUintSize,
     ) {
-        unimplemented!();
+        debug_assert!(self.inside_frame);
+
+        let old_size = texture.get_dimensions();
+        let old_fbos = mem::replace(&mut texture.fbo_ids, Vec::new());
+        let old_depth_rb = texture.depth_rb.take();
+        let old_texture_id = mem::replace(&mut texture.id, INVALID_TEXTURE_ID);
+
+        let rt_info = texture.render_target.unwrap_or(RenderTargetInfo { has_depth: false, samples: 1 });
+        let (filter, layer_count) = (texture.filter, texture.layer_count);
+        self.init_texture::<u8>(texture, new_size.width, new_size.height, filter, Some(rt_info), layer_count, None);
+
+        let rect = DeviceIntRect::new(
+            DeviceIntPoint::zero(),
+            DeviceIntSize::new(
+                old_size.width.min(new_size.width) as i32,
+                old_size.height.min(new_size.height) as i32,
+            ),
+        );
+        for (&read_fbo, &draw_fbo) in old_fbos.iter().zip(&texture.fbo_ids) {
+            self.bind_read_target_impl(read_fbo);
+            self.bind_draw_target_impl(draw_fbo);
+            self.blit_render_target(rect, rect);
+        }
+        self.bind_read_target(None);
+        self.bind_draw_target(None, None);
+
+        // The old storage is still referenced by the blits above.
+        let retired = &mut self.retired_resources[self.next_id];
+        for fbo_id in old_fbos {
+            retired.framebuffers.extend(self.fbos.remove(&fbo_id));
+        }
+        if let Some(depth_rb) = old_depth_rb {
+            retired.depth_buffers.extend(self.rbos.remove(&depth_rb));
+        }
+        retired.images.extend(self.images.remove(&old_texture_id));
     }
 
     pub fn init_texture<T: Texel>(
@@ -2727,8 +3166,27 @@ impl<B: hal::Backend> Device<B> {
             height = height.min(self.max_texture_size);
         }
 
+        // Render passes only exist for the sample count the device was
+        // created with, any multisampled target uses that. Every render
+        // target gets a depth attachment, so that its framebuffers match the
+        // render passes of the depth-tested pipelines.
+        let msaa_samples = self.msaa_samples;
+        let render_target = render_target.map(|rt| RenderTargetInfo {
+            has_depth: true,
+            samples: if rt.samples > 1 { msaa_samples } else { 1 },
+        });
+        let old_samples = texture.render_target.map_or(1, |rt| rt.samples);
         let is_resized = texture.width != width || texture.height != height;
 
+        // Keep the current allocation of a render target if it already has the
+        // requested layout, render targets are cleared before use anyway.
+        if render_target.is_some() && pixels.is_none() && !is_resized && texture.id != 0 &&
+            texture.layer_count == layer_count && texture.filter == filter &&
+            texture.render_target == render_target && self.images.contains_key(&texture.id) {
+            texture.last_frame_used = self.frame_id;
+            return;
+        }
+
         if texture.id == 0 {
             let id = self.generate_texture_id();
             texture.id = id;
@@ -2745,11 +3203,21 @@ impl<B: hal::Backend> Device<B> {
         texture.bound_in_frame.set(self.frame_id);
 
         assert_eq!(self.images.contains_key(&texture.id), false);
+        assert!(
+            render_target.is_none() || texture.format != ImageFormat::RGBAI32,
+            "RGBAI32 textures can't be used as render targets"
+        );
         let (view_kind, mip_levels) = match texture.filter {
             TextureFilter::Nearest => (hal::image::ViewKind::D2, 1),
             TextureFilter::Linear => (hal::image::ViewKind::D2Array, 1),
+            // Mipmaps are generated with linear blits, which integer formats don't support.
+            TextureFilter::Trilinear if texture.format == ImageFormat::RGBAI32 => (hal::image::ViewKind::D2Array, 1),
             TextureFilter::Trilinear => (hal::image::ViewKind::D2Array, (width as f32).max(height as f32).log2().floor() as u8 + 1),
         };
+        if pixels.is_none() && self.take_pooled_render_target(texture) {
+            return;
+        }
+        let samples = render_target.map_or(1, |rt| rt.samples);
         let img = Image::new(
             &self.device,
             &self.memory_types,
@@ -2759,6 +3227,7 @@ impl<B: hal::Backend> Device<B> {
             texture.layer_count,
             view_kind,
             mip_levels,
+            samples,
             (self.limits.min_buffer_copy_pitch_alignment - 1) as usize,
         );
 
@@ -2784,7 +3253,7 @@ impl<B: hal::Backend> Device<B> {
 
         if let Some(rt_info) = render_target {
             let (depth_rb, allocate_depth) = match texture.depth_rb {
-                Some(rbo) => (rbo, is_resized || !rt_info.has_depth),
+                Some(rbo) => (rbo, is_resized || !rt_info.has_depth || old_samples != rt_info.samples),
                 None if rt_info.has_depth => {
                     let depth_rb = self.generate_rbo_id();
                     texture.depth_rb = Some(depth_rb);
@@ -2804,6 +3273,7 @@ impl<B: hal::Backend> Device<B> {
                         &self.memory_types,
                         texture.width,
                         texture.height,
+                        rt_info.samples,
                         self.depth_format
                     );
                     self.rbos.insert(depth_rb, rbo);
@@ -2974,6 +3444,7 @@ impl<B: hal::Backend> Device<B> {
 
     pub fn blit_render_target(&mut self, src_rect: DeviceIntRect, dest_rect: DeviceIntRect) {
         debug_assert!(self.inside_frame);
+        self.resolve_msaa();
 
         let (src_img, src_layer) = if self.bound_read_fbo != DEFAULT_READ_FBO {
             let fbo = &self.fbos[&self.bound_read_fbo];
@@ -2981,7 +3452,7 @@ impl<B: hal::Backend> Device<B> {
             let layer = fbo.layer_index;
             (&img.core, layer)
         } else {
-            (&self.frame_images[self.current_frame_id], 0)
+            (self.surfaces[&self.bound_surface].frame_image(), 0)
         };
 
         let (dest_img, dest_layer) = if self.bound_draw_fbo != DEFAULT_DRAW_FBO {
@@ -2990,7 +3461,7 @@ impl<B: hal::Backend> Device<B> {
             let layer = fbo.layer_index;
             (&img.core, layer)
         } else {
-            (&self.frame_images[self.current_frame_id], 0)
+            (self.surfaces[&self.bound_surface].frame_image(), 0)
         };
 
         let mut cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer(false);
@@ -3151,7 +3622,66 @@ impl<B: hal::Backend> Device<B> {
         texture.id = 0;
     }
 
+    /// Moves the storage of a render target into the pool instead of
+    /// destroying it, which also avoids waiting on the frames still using it.
+    fn pool_render_target(&mut self, texture: &mut Texture) -> bool {
+        let key = match RenderTargetKey::new(texture) {
+            Some(key) if !texture.fbo_ids.is_empty() => key,
+            _ => return false,
+        };
+        let image = self.images.remove(&texture.id).expect("Texture not found.");
+        self.render_target_pool.push(PooledRenderTarget {
+            key,
+            image,
+            fbo_ids: mem::replace(&mut texture.fbo_ids, Vec::new()),
+            depth_rb: texture.depth_rb.take(),
+            last_frame_used: self.frame_id,
+        });
+        true
+    }
+
+    /// Gives the texture the storage of a pooled render target with the
+    /// same layout, if there is one.
+    fn take_pooled_render_target(&mut self, texture: &mut Texture) -> bool {
+        let key = RenderTargetKey::new(texture);
+        let index = match self.render_target_pool.iter().position(|rt| Some(rt.key) == key) {
+            Some(index) => index,
+            None => return false,
+        };
+        let pooled = self.render_target_pool.swap_remove(index);
+        for fbo_id in &pooled.fbo_ids {
+            self.fbos.get_mut(fbo_id).expect("Framebuffer not found.").texture = texture.id;
+        }
+        texture.fbo_ids = pooled.fbo_ids;
+        texture.depth_rb = pooled.depth_rb;
+        self.images.insert(texture.id, pooled.image);
+        true
+    }
+
+    /// Retires the pooled render targets that haven't been reused for a while.
+    fn expire_pooled_render_targets(&mut self) {
+        let frame_id = self.frame_id;
+        let (expired, pooled): (Vec<_>, Vec<_>) = self.render_target_pool
+            .drain(..)
+            .partition(|rt| rt.last_frame_used + RENDER_TARGET_POOL_FRAMES < frame_id);
+        self.render_target_pool = pooled;
+
+        let retired = &mut self.retired_resources[self.next_id];
+        for rt in expired {
+            for fbo_id in rt.fbo_ids {
+                retired.framebuffers.extend(self.fbos.remove(&fbo_id));
+            }
+            if let Some(depth_rb) = rt.depth_rb {
+                retired.depth_buffers.extend(self.rbos.remove(&depth_rb));
+            }
+            retired.images.push(rt.image);
+        }
+    }
+
     pub fn free_image(&mut self, texture: &mut Texture) {
+        if self.pool_render_target(texture) {
+            return;
+        }
         // Note: this is a very rare case, but if it becomes a problem
         // we need to handle this in renderer.rs
         if texture.still_in_flight(self.frame_id) {
@@ -3214,14 +3744,9 @@ impl<B: hal::Backend> Device<B> {
     ) -> TextureUploader<'a, B> {
         debug_assert!(self.inside_frame);
 
-        match self.upload_method {
-            UploadMethod::Immediate => unimplemented!(),
-            UploadMethod::PixelBuffer => {
-                TextureUploader {
-                        device: self,
-                        texture,
-                }
-            },
+        TextureUploader {
+            device: self,
+            texture,
         }
 
     }
@@ -3240,28 +3765,32 @@ impl<B: hal::Backend> Device<B> {
         format: ReadPixelsFormat,
         output: &mut [u8],
     ) {
+        self.resolve_msaa();
         self.wait_for_resources();
 
-        let bytes_per_pixel = match format {
-            ReadPixelsFormat::Standard(imf) => imf.bytes_per_pixel(),
-            ReadPixelsFormat::Rgba8 => 4,
+        let output_format = match format {
+            ReadPixelsFormat::Standard(imf) => imf,
+            ReadPixelsFormat::Rgba8 => ImageFormat::RGBA8,
         };
-        let size_in_bytes = (bytes_per_pixel * rect.size.width * rect.size.height) as usize;
+        let size_in_bytes = (output_format.bytes_per_pixel() * rect.size.width * rect.size.height) as usize;
         assert_eq!(output.len(), size_in_bytes);
-        let (image, layer) = if self.bound_read_fbo != DEFAULT_READ_FBO {
+        let (image, image_format, layer) = if self.bound_read_fbo != DEFAULT_READ_FBO {
             let fbo = &self.fbos[&self.bound_read_fbo];
             let img = &self.images[&fbo.texture];
             let layer = fbo.layer_index;
-            (&img.core, layer)
+            (&img.core, img.format, layer)
         } else {
-            (&self.frame_images[self.current_frame_id], 0)
+            (self.surfaces[&self.bound_surface].frame_image(), ImageFormat::BGRA8, 0)
         };
+        // The copy keeps the texels of the source, they are converted into the
+        // requested format afterwards if it differs.
+        let image_bytes_per_pixel = image_format.bytes_per_pixel();
         let download_buffer: CopyBuffer<B> = CopyBuffer::create(
             &self.device,
             &self.memory_types,
             hal::buffer::Usage::TRANSFER_DST,
             1,
-            (rect.size.width * bytes_per_pixel) as usize,
+            (rect.size.width * image_bytes_per_pixel) as usize,
             rect.size.height as usize,
             (self.limits.min_buffer_copy_pitch_alignment - 1) as usize,
         );
@@ -3343,26 +3872,21 @@ impl<B: hal::Backend> Device<B> {
         self.device.wait_for_fence(&copy_fence, !0);
         self.device.destroy_fence(copy_fence);
 
+        let download_size = (image_bytes_per_pixel * rect.size.width * rect.size.height) as usize;
         if let Ok(reader) = self.device
-            .acquire_mapping_reader::<[u8; 4]>(
+            .acquire_mapping_reader::<u8>(
                 &download_buffer.memory,
-                0 .. (rect.size.width * rect.size.height * bytes_per_pixel as u32) as u64,
+                0 .. download_size as u64,
             )
             {
-                assert_eq!(reader.len() * 4, output.len());
-                let mut offset = 0;
-                let (i0, i1, i2, i3) = match self.surface_format.base_format().0 {
-                    hal::format::SurfaceType::B8_G8_R8_A8 => (2, 1, 0, 3),
-                    //hal::format::SurfaceType::R8_G8_B8_A8 => (0, 1, 2, 3),
-                    _ => (0, 1, 2, 3)
-                };
-                for d in reader.iter() {
-                    let data = *d;
-                    output[offset + 0] = data[i0];
-                    output[offset + 1] = data[i1];
-                    output[offset + 2] = data[i2];
-                    output[offset + 3] = data[i3];
-                    offset += 4;
+                if output_format == image_format {
+                    output.copy_from_slice(&reader);
+                } else {
+                    let texels = reader.chunks(image_bytes_per_pixel as usize);
+                    let output_texels = output.chunks_mut(output_format.bytes_per_pixel() as usize);
+                    for (texel, output_texel) in texels.zip(output_texels) {
+                        encode_texel(output_format, decode_texel(image_format, texel), output_texel);
+                    }
                 }
                 self.device.release_mapping_reader(reader);
             } else {
@@ -3491,6 +4015,8 @@ impl<B: hal::Backend> Device<B> {
     }
 
     pub fn end_frame(&mut self) {
+        // The frame image has to be resolved before it is presented.
+        self.resolve_msaa();
         self.bind_draw_target(None, None);
         self.bind_read_target(None);
 
@@ -3549,18 +4075,18 @@ impl<B: hal::Backend> Device<B> {
             );
         }
 
+        self.mark_msaa_target_drawn();
         let submit = {
-            let (frame_buffer, format, has_depth) = if self.bound_draw_fbo != DEFAULT_DRAW_FBO {
-                (&self.fbos[&self.bound_draw_fbo].fbo, self.fbos[&self.bound_draw_fbo].format, self.fbos[&self.bound_draw_fbo].rbo != RBOId(0))
+            let (frame_buffer, format, has_depth, samples) = if self.bound_draw_fbo != DEFAULT_DRAW_FBO {
+                let fbo = &self.fbos[&self.bound_draw_fbo];
+                (&fbo.fbo, fbo.format, fbo.rbo != RBOId(0), fbo.samples)
             } else {
-                if self.current_depth_test == DepthTest::Off {
-                    (&self.framebuffers[self.current_frame_id], ImageFormat::BGRA8, false)
-                } else {
-                    (&self.framebuffers_depth[self.current_frame_id], ImageFormat::BGRA8, true)
-                }
+                let surface = &self.surfaces[&self.bound_surface];
+                let has_depth = self.current_depth_test != DepthTest::Off;
+                (surface.framebuffer(has_depth), ImageFormat::BGRA8, has_depth, surface.samples)
             };
 
-            let render_pass = self.render_pass.get_render_pass(format, has_depth);
+            let render_pass = self.render_pass.get_render_pass(format, has_depth, samples);
             let mut cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer(false);
             {
                 let mut encoder = cmd_buffer.begin_render_pass_inline(
@@ -3583,7 +4109,7 @@ impl<B: hal::Backend> Device<B> {
         color: Option<[f32; 4]>,
         depth: Option<f32>,
     ) {
-        let (img, layer, dimg) = if self.bound_draw_fbo != DEFAULT_DRAW_FBO {
+        let (img, msaa_img, layer, dimg) = if self.bound_draw_fbo != DEFAULT_DRAW_FBO {
             let fbo = &self.fbos[&self.bound_draw_fbo];
             let img = &self.images[&fbo.texture];
             let dimg = if depth.is_some() {
@@ -3591,9 +4117,10 @@ impl<B: hal::Backend> Device<B> {
             } else {
                 None
             };
-            (&img.core, fbo.layer_index, dimg)
+            (&img.core, img.msaa.as_ref(), fbo.layer_index, dimg)
         } else {
-            (&self.frame_images[self.current_frame_id], 0, Some(&self.frame_depth.core))
+            let surface = &self.surfaces[&self.bound_surface];
+            (surface.frame_image(), surface.frame_msaa.as_ref(), 0, Some(&surface.frame_depth.core))
         };
 
         let mut cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer(false);
@@ -3601,39 +4128,43 @@ impl<B: hal::Backend> Device<B> {
         // thus, we bring back the targets into renderable state
 
         if let Some(color) = color {
-            let color_range = hal::image::SubresourceRange {
-                aspects: hal::format::Aspects::COLOR,
-                levels: 0 .. 1,
-                layers: layer .. layer + 1,
-            };
-            if let Some(barrier) = img.transit(
-                hal::image::Access::COLOR_ATTACHMENT_READ | hal::image::Access::COLOR_ATTACHMENT_WRITE,
-                hal::image::Layout::TransferDstOptimal,
-                color_range.clone(),
-            ) {
-                cmd_buffer.pipeline_barrier(
-                    PipelineStage::COLOR_ATTACHMENT_OUTPUT .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
-                    hal::memory::Dependencies::empty(),
-                    &[barrier],
-                );
-            }
-            cmd_buffer.clear_image(
-                &img.image,
-                hal::image::Layout::TransferDstOptimal,
-                hal::command::ClearColor::Float([color[0], color[1], color[2], color[3]]),
-                hal::command::ClearDepthStencil(0.0, 0),
-                Some(color_range.clone()),
-            );
-            if let Some(barrier) = img.transit(
-                hal::image::Access::empty(),
-                hal::image::Layout::ColorAttachmentOptimal,
-                color_range.clone(),
-            ) {
-                cmd_buffer.pipeline_barrier(
-                    PipelineStage::COLOR_ATTACHMENT_OUTPUT .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
-                    hal::memory::Dependencies::empty(),
-                    &[barrier],
+            // The multisampled image is cleared too, as it is resolved into
+            // the texture at the end of the pass.
+            for img in Some(img).into_iter().chain(msaa_img) {
+                let color_range = hal::image::SubresourceRange {
+                    aspects: hal::format::Aspects::COLOR,
+                    levels: 0 .. 1,
+                    layers: layer .. layer + 1,
+                };
+                if let Some(barrier) = img.transit(
+                    hal::image::Access::COLOR_ATTACHMENT_READ | hal::image::Access::COLOR_ATTACHMENT_WRITE,
+                    hal::image::Layout::TransferDstOptimal,
+                    color_range.clone(),
+                ) {
+                    cmd_buffer.pipeline_barrier(
+                        PipelineStage::COLOR_ATTACHMENT_OUTPUT .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                        hal::memory::Dependencies::empty(),
+                        &[barrier],
+                    );
+                }
+                cmd_buffer.clear_image(
+                    &img.image,
+                    hal::image::Layout::TransferDstOptimal,
+                    hal::command::ClearColor::Float([color[0], color[1], color[2], color[3]]),
+                    hal::command::ClearDepthStencil(0.0, 0),
+                    Some(color_range.clone()),
                 );
+                if let Some(barrier) = img.transit(
+                    hal::image::Access::empty(),
+                    hal::image::Layout::ColorAttachmentOptimal,
+                    color_range.clone(),
+                ) {
+                    cmd_buffer.pipeline_barrier(
+                        PipelineStage::COLOR_ATTACHMENT_OUTPUT .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                        hal::memory::Dependencies::empty(),
+                        &[barrier],
+                    );
+                }
             }
         }
 
@@ -3696,25 +4227,33 @@ impl<B: hal::Backend> Device<B> {
     }
 
     pub fn enable_depth(&mut self) {
-        self.current_depth_test = LESS_EQUAL_TEST;
+        self.current_depth_test = DepthTest::On {
+            fun: self.depth_function,
+            write: false,
+        };
     }
 
     pub fn disable_depth(&mut self) {
         self.current_depth_test = DepthTest::Off;
     }
 
-    pub fn set_depth_func(&mut self, _depth_func: DepthFunction) {
-        // TODO add Less depth function
-        //self.current_depth_test = depth_func;
+    pub fn set_depth_func(&mut self, depth_func: DepthFunction) {
+        self.depth_function = depth_func.comparison();
+        if let DepthTest::On { ref mut fun, .. } = self.current_depth_test {
+            *fun = self.depth_function;
+        }
     }
 
     pub fn enable_depth_write(&mut self) {
-        self.current_depth_test = LESS_EQUAL_WRITE;
+        self.current_depth_test = DepthTest::On {
+            fun: self.depth_function,
+            write: true,
+        };
     }
 
     pub fn disable_depth_write(&mut self) {
-        if self.current_depth_test != DepthTest::Off {
-            self.current_depth_test = LESS_EQUAL_TEST;
+        if let DepthTest::On { ref mut write, .. } = self.current_depth_test {
+            *write = false;
         }
     }
 
@@ -3753,6 +4292,15 @@ impl<B: hal::Backend> Device<B> {
     pub fn set_blend_mode_multiply(&mut self) {
         self.current_blend_state = BlendState::MULTIPLY;
     }
+    pub fn set_blend_mode_mask_add(&mut self) {
+        self.current_blend_state = MASK_ADD;
+    }
+    pub fn set_blend_mode_mask_subtract(&mut self) {
+        self.current_blend_state = MASK_SUBTRACT;
+    }
+    pub fn set_blend_mode_mask_exclude(&mut self) {
+        self.current_blend_state = MASK_EXCLUDE;
+    }
     pub fn set_blend_mode_max(&mut self) {
         self.current_blend_state = MAX;
     }
@@ -3789,40 +4337,68 @@ impl<B: hal::Backend> Device<B> {
     }
 
     pub fn set_next_frame_id(&mut self) {
-        self.current_frame_id = self.swap_chain
-            .acquire_image(FrameSync::Semaphore(&mut self.image_available_semaphore)).unwrap() as _;
+        for surface in self.surfaces.values_mut() {
+            // Without a swapchain we always render into the same offscreen image.
+            if let Some(ref mut swap_chain) = surface.swap_chain {
+                surface.current_frame_id = swap_chain
+                    .acquire_image(FrameSync::Semaphore(&mut surface.image_available_semaphore)).unwrap() as _;
+            }
+        }
     }
 
     pub fn swap_buffers(&mut self) {
         {
-            let mut cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer(false);
-            let image = &self.frame_images[self.current_frame_id];
-            if let Some(barrier) = image.transit(
-                hal::image::Access::empty(),
-                hal::image::Layout::Present,
-                image.subresource_range.clone(),
-            ) {
+            let barriers = self.surfaces
+                .values()
+                .filter(|surface| surface.swap_chain.is_some())
+                .filter_map(|surface| {
+                    let image = surface.frame_image();
+                    image.transit(
+                        hal::image::Access::empty(),
+                        hal::image::Layout::Present,
+                        image.subresource_range.clone(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            if !barriers.is_empty() {
+                let mut cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer(false);
                 cmd_buffer.pipeline_barrier(
                     PipelineStage::COLOR_ATTACHMENT_OUTPUT .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
                     hal::memory::Dependencies::empty(),
-                    &[barrier],
+                    &barriers,
                 );
+                self.upload_queue.push(cmd_buffer.finish());
             }
-            self.upload_queue.push(cmd_buffer.finish());
         }
 
         {
+            // Every window is presented, the offscreen image stays in place
+            // to be read back.
+            let wait_semaphores = self.surfaces
+                .values()
+                .filter(|surface| surface.swap_chain.is_some())
+                .map(|surface| (&surface.image_available_semaphore, PipelineStage::BOTTOM_OF_PIPE))
+                .collect::<Vec<_>>();
+            let signal_semaphores = self.surfaces
+                .values()
+                .filter(|surface| surface.swap_chain.is_some())
+                .map(|surface| &surface.render_finished_semaphore)
+                .collect::<Vec<_>>();
             let submission = Submission::new()
-                .wait_on(&[(&self.image_available_semaphore, PipelineStage::BOTTOM_OF_PIPE)])
-                .signal(Some(&self.render_finished_semaphore))
+                .wait_on(&wait_semaphores)
+                .signal(signal_semaphores)
                 .submit(&self.upload_queue);
             self.queue_group.queues[0].submit(submission, Some(&mut self.frame_fence[self.next_id].inner));
-            self.frame_fence[self.next_id].is_submitted = true;
 
-            // present frame
-            self.swap_chain
-                .present(&mut self.queue_group.queues[0], self.current_frame_id as _, Some(&self.render_finished_semaphore)).ok();
+            // present frames
+            for surface in self.surfaces.values_mut() {
+                if let Some(ref mut swap_chain) = surface.swap_chain {
+                    swap_chain
+                        .present(&mut self.queue_group.queues[0], surface.current_frame_id as _, Some(&surface.render_finished_semaphore)).ok();
+                }
+            }
         }
+        self.frame_fence[self.next_id].is_submitted = true;
         self.upload_queue.clear();
         self.next_id = (self.next_id + 1) % MAX_FRAME_COUNT;
         self.reset_state();
@@ -3832,6 +4408,8 @@ impl<B: hal::Backend> Device<B> {
             self.device.reset_fence(&self.frame_fence[self.next_id].inner);
             self.frame_fence[self.next_id].is_submitted = false;
         }
+        self.staging_ring.release_frame(self.next_id);
+        self.retired_resources[self.next_id].destroy(&self.device);
         self.command_pool[self.next_id].reset();
         self.descriptor_pools[self.next_id].reset();
         self.reset_program_buffer_offsets();
@@ -3853,34 +4431,38 @@ impl<B: hal::Backend> Device<B> {
     }
 
     fn reset_command_pools(&mut self) {
+        self.staging_ring.release_all();
         for command_pool in &mut self.command_pool {
             command_pool.reset();
         }
+        for retired_resources in &mut self.retired_resources {
+            retired_resources.destroy(&self.device);
+        }
     }
 
     pub fn deinit(self) {
         for command_pool in self.command_pool {
             self.device.destroy_command_pool(command_pool.into_raw());
         }
-        for image in self.frame_images {
-            image.deinit(&self.device);
+        for mut retired_resources in self.retired_resources {
+            retired_resources.destroy(&self.device);
+        }
+        self.staging_ring.deinit(&self.device);
+        for (_, surface) in self.surfaces {
+            surface.deinit(&self.device);
         }
         for (_, image) in self.images {
             image.deinit(&self.device);
         }
+        for rt in self.render_target_pool {
+            rt.image.deinit(&self.device);
+        }
         for (_, rbo) in self.fbos {
             rbo.deinit(&self.device);
         }
         for (_, rbo) in self.rbos {
             rbo.deinit(&self.device);
         }
-        for framebuffer in self.framebuffers {
-            self.device.destroy_framebuffer(framebuffer);
-        }
-        for framebuffer_depth in self.framebuffers_depth {
-            self.device.destroy_framebuffer(framebuffer_depth);
-        }
-        self.frame_depth.deinit(&self.device);
         self.device.destroy_sampler(self.sampler_linear);
         self.device.destroy_sampler(self.sampler_nearest);
         for descriptor_pool in self.descriptor_pools {
@@ -3893,9 +4475,6 @@ impl<B: hal::Backend> Device<B> {
         for fence in self.frame_fence {
             self.device.destroy_fence(fence.inner);
         }
-        self.device.destroy_semaphore(self.image_available_semaphore);
-        self.device.destroy_semaphore(self.render_finished_semaphore);
-        self.device.destroy_swapchain(self.swap_chain);
     }
 }
 
@@ -3941,8 +4520,9 @@ impl<'a, B: hal::Backend> TextureUploader<'a, B> {
             data
         };
         assert_eq!(data.len(), width * height * data_stride);
-        self.device.upload_queue
-            .push(
+        let submit = match self.device.upload_method {
+            UploadMethod::Immediate => self.upload_immediate(rect, layer_index, data),
+            UploadMethod::PixelBuffer => {
                 self.device.images
                     .get_mut(&self.texture.id)
                     .expect("Texture not found.")
@@ -3954,16 +4534,66 @@ impl<'a, B: hal::Backend> TextureUploader<'a, B> {
                         data,
                         (self.device.limits.min_buffer_copy_offset_alignment - 1) as usize,
                     )
-            );
+            }
+        };
+        self.device.upload_queue.push(submit);
 
         if self.texture.filter == TextureFilter::Trilinear {
             self.device.generate_mipmaps(self.texture);
         }
     }
-}
 
-fn texels_to_u8_slice<T: Texel>(texels: &[T]) -> &[u8] {
-    unsafe {
-        slice::from_raw_parts(texels.as_ptr() as *const u8, texels.len() * mem::size_of::<T>())
+    /// Copies the data through the staging ring of the device, instead of the
+    /// upload buffer of the image.
+    fn upload_immediate(
+        &mut self,
+        rect: DeviceUintRect,
+        layer_index: i32,
+        data: &[u8],
+    ) -> hal::command::Submit<B, hal::Graphics, hal::command::MultiShot, hal::command::Primary> {
+        let device = &mut *self.device;
+        let bytes_per_pixel = self.texture.format.bytes_per_pixel();
+        let pitch_alignment = cmp::max(device.limits.min_buffer_copy_pitch_alignment as u32, 1);
+        let mut buffer_width = rect.size.width;
+        while (buffer_width * bytes_per_pixel) % pitch_alignment != 0 {
+            buffer_width += 1;
+        }
+        let row_bytes = (rect.size.width * bytes_per_pixel) as usize;
+        let pitch = (buffer_width * bytes_per_pixel) as usize;
+        let size = pitch * rect.size.height as usize;
+        // Copies from a buffer start at a multiple of the texel size.
+        let alignment = cmp::max(
+            device.limits.min_buffer_copy_offset_alignment as usize,
+            bytes_per_pixel as usize,
+        ).next_power_of_two();
+
+        let offset = match device.staging_ring.allocate(size, alignment, device.next_id) {
+            Some(offset) => offset,
+            None => {
+                // The uploads in flight don't leave enough room: replace the ring
+                // with a bigger one, and release the old buffer with this frame.
+                let capacity = cmp::max(device.staging_ring.capacity * 2, size.next_power_of_two());
+                let ring = StagingRing::new(&device.device, &device.memory_types, capacity);
+                let old_ring = mem::replace(&mut device.staging_ring, ring);
+                device.retired_resources[device.next_id].staging_buffers.push(old_ring.buffer);
+                device.staging_ring
+                    .allocate(size, alignment, device.next_id)
+                    .expect("bug: staging ring too small for the upload")
+            }
+        };
+        device.staging_ring.write(&device.device, offset, data, row_bytes, pitch);
+
+        device.images
+            .get(&self.texture.id)
+            .expect("Texture not found.")
+            .copy_from_buffer(
+                &mut device.command_pool[device.next_id],
+                &device.staging_ring.buffer,
+                offset as u64,
+                buffer_width,
+                rect,
+                layer_index,
+            )
     }
 }
+
@@ -0,0 +1,916 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use api::{ImageFormat, DeviceUintSize, TextureTarget};
+use byteorder::{ByteOrder, NativeEndian};
+#[cfg(feature = "serialize_program")]
+use bincode;
+#[cfg(not(feature = "software"))]
+use fxhash::FxHasher;
+use gpu_types;
+use hal::pso::{AttributeDesc, DescriptorRangeDesc, DescriptorSetLayoutBinding, VertexBufferDesc};
+use hal::pso::{BlendState, BlendOp, Comparison, DepthTest, Factor};
+use internal_types::{FastHashMap, RenderTargetInfo};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+#[cfg(feature = "serialize_program")]
+use std::fs::File;
+#[cfg(not(feature = "software"))]
+use shader_source;
+#[cfg(not(feature = "software"))]
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "serialize_program")]
+use std::io::{BufReader, BufWriter};
+use std::mem;
+use std::ops::Add;
+use std::path::PathBuf;
+#[cfg(feature = "serialize_program")]
+use std::path::Path;
+use std::rc::Rc;
+use std::slice;
+use std::sync::Arc;
+use std::thread;
+use vertex_types::*;
+
+cfg_if! {
+    if #[cfg(feature = "software")] {
+        mod software;
+        pub use self::software::*;
+    } else {
+        mod gfx;
+        pub use self::gfx::*;
+    }
+}
+
+pub const MAX_INSTANCE_COUNT: usize = 1024;
+
+pub type TextureId = u32;
+
+pub const INVALID_TEXTURE_ID: TextureId = 0;
+pub const INVALID_PROGRAM_ID: ProgramId = ProgramId(0);
+pub const DEFAULT_READ_FBO: FBOId = FBOId(0);
+pub const DEFAULT_DRAW_FBO: FBOId = FBOId(1);
+
+pub const MAX_FRAME_COUNT: usize = 2;
+
+/// The surface the device renders into when nothing else is bound, it is
+/// created together with the device.
+pub const MAIN_SURFACE: SurfaceId = SurfaceId(0);
+
+#[derive(Clone, Deserialize)]
+#[cfg_attr(feature = "serialize_program", derive(Serialize))]
+pub struct PipelineRequirements {
+    pub attribute_descriptors: Vec<AttributeDesc>,
+    pub bindings_map: HashMap<String, u32>,
+    pub descriptor_range_descriptors: Vec<DescriptorRangeDesc>,
+    pub descriptor_set_layouts: Vec<DescriptorSetLayoutBinding>,
+    pub vertex_buffer_descriptors: Vec<VertexBufferDesc>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Ord, Eq, PartialOrd)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct FrameId(usize);
+
+impl FrameId {
+    pub fn new(value: usize) -> Self {
+        FrameId(value)
+    }
+}
+
+impl Add<usize> for FrameId {
+    type Output = FrameId;
+
+    fn add(self, other: usize) -> FrameId {
+        FrameId(self.0 + other)
+    }
+}
+
+pub struct TextureSlot(pub usize);
+
+// In some places we need to temporarily bind a texture to any slot.
+//const DEFAULT_TEXTURE: TextureSlot = TextureSlot(0);
+
+#[repr(u32)]
+pub enum DepthFunction {
+    Less,
+    LessEqual,
+}
+
+impl DepthFunction {
+    fn comparison(&self) -> Comparison {
+        match *self {
+            DepthFunction::Less => Comparison::Less,
+            DepthFunction::LessEqual => Comparison::LessEqual,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+    Trilinear,
+}
+
+#[derive(Debug)]
+pub enum VertexAttributeKind {
+    F32,
+    #[cfg(feature = "debug_renderer")]
+    U8Norm,
+    U16Norm,
+    I32,
+    U16,
+}
+
+#[derive(Debug)]
+pub struct VertexAttribute {
+    pub name: &'static str,
+    pub count: u32,
+    pub kind: VertexAttributeKind,
+}
+
+#[derive(Debug)]
+pub struct VertexDescriptor {
+    pub vertex_attributes: &'static [VertexAttribute],
+    pub instance_attributes: &'static [VertexAttribute],
+}
+
+pub trait PrimitiveType {
+    type Primitive: Clone + Copy;
+    fn to_primitive_type(&self) -> Self::Primitive;
+}
+
+impl PrimitiveType for gpu_types::BlurInstance {
+    type Primitive = BlurInstance;
+    fn to_primitive_type(&self) -> BlurInstance {
+        BlurInstance {
+            aData0: [0,0,0,0],
+            aData1: [0,0,0,0],
+            aBlurRenderTaskAddress: self.task_address.0 as i32,
+            aBlurSourceTaskAddress: self.src_task_address.0 as i32,
+            aBlurDirection: self.blur_direction as i32,
+        }
+    }
+}
+
+impl PrimitiveType for gpu_types::SvgFilterInstance {
+    type Primitive = SvgFilterInstance;
+    fn to_primitive_type(&self) -> SvgFilterInstance {
+        SvgFilterInstance {
+            aData0: [0,0,0,0],
+            aData1: [0,0,0,0],
+            aFilterRenderTaskAddress: self.task_address.0 as i32,
+            aFilterInput1TaskAddress: self.input_1_task_address.0 as i32,
+            aFilterInput2TaskAddress: self.input_2_task_address.0 as i32,
+            aFilterKind: self.kind,
+            aFilterGenericInt: self.generic_int,
+            aFilterFlags: self.flags,
+            aFilterExtraDataAddress: self.extra_data_address.as_int(),
+        }
+    }
+}
+
+impl PrimitiveType for gpu_types::PathInstance {
+    type Primitive = PathInstance;
+    fn to_primitive_type(&self) -> PathInstance {
+        PathInstance {
+            aData0: [0,0,0,0],
+            aData1: [0,0,0,0],
+            aTaskOrigin: [self.task_origin.x, self.task_origin.y],
+            aRect: [self.local_rect.origin.x, self.local_rect.origin.y, self.local_rect.size.width, self.local_rect.size.height],
+            aLocalSize: [self.local_size.width, self.local_size.height],
+            aPathDataAddress: self.path_data_address.as_int(),
+            aPathStopsAddress: self.stops_address.as_int(),
+        }
+    }
+}
+
+impl PrimitiveType for gpu_types::BorderInstance {
+    type Primitive = BorderInstance;
+    fn to_primitive_type(&self) -> BorderInstance {
+        BorderInstance {
+            aTaskOrigin: [self.task_origin.x, self.task_origin.y],
+            aRect: [self.local_rect.origin.x, self.local_rect.origin.y, self.local_rect.size.width, self.local_rect.size.height],
+            aColor0: self.color0.to_array(),
+            aColor1: self.color1.to_array(),
+            aFlags: self.flags,
+            aWidths: [self.widths.width, self.widths.height],
+            aRadii: [self.radius.width, self.radius.height],
+            aClipParams1: [
+                self.clip_params[0],
+                self.clip_params[1],
+                self.clip_params[2],
+                self.clip_params[3],
+            ],
+            aClipParams2: [
+                self.clip_params[4],
+                self.clip_params[5],
+                self.clip_params[6],
+                self.clip_params[7],
+            ],
+        }
+    }
+}
+
+impl PrimitiveType for gpu_types::ClipMaskInstance {
+    type Primitive = ClipMaskInstance;
+    fn to_primitive_type(&self) -> ClipMaskInstance {
+        ClipMaskInstance {
+            aClipRenderTaskAddress: self.render_task_address.0 as i32,
+            aScrollNodeId: self.scroll_node_data_index.0 as i32,
+            aClipSegment: self.segment,
+            aClipDataResourceAddress: [
+                self.clip_data_address.u as i32,
+                self.clip_data_address.v as i32,
+                self.resource_address.u as i32,
+                self.resource_address.v as i32,
+            ],
+        }
+    }
+}
+
+impl PrimitiveType for gpu_types::ClipMaskBorderCornerDotDash {
+    type Primitive = ClipMaskBorderCornerDotDash;
+    fn to_primitive_type(&self) -> ClipMaskBorderCornerDotDash {
+        ClipMaskBorderCornerDotDash {
+            aClipRenderTaskAddress: self.clip_mask_instance.render_task_address.0 as i32,
+            aScrollNodeId: self.clip_mask_instance.scroll_node_data_index.0 as i32,
+            aClipSegment: self.clip_mask_instance.segment,
+            aClipDataResourceAddress: [
+                self.clip_mask_instance.clip_data_address.u as i32,
+                self.clip_mask_instance.clip_data_address.v as i32,
+                self.clip_mask_instance.resource_address.u as i32,
+                self.clip_mask_instance.resource_address.v as i32,
+            ],
+            aDashOrDot0: [
+                self.dot_dash_data[0],
+                self.dot_dash_data[1],
+                self.dot_dash_data[2],
+                self.dot_dash_data[3],
+            ],
+            aDashOrDot1: [
+                self.dot_dash_data[4],
+                self.dot_dash_data[5],
+                self.dot_dash_data[6],
+                self.dot_dash_data[7],
+            ]
+        }
+    }
+}
+
+impl PrimitiveType for gpu_types::PrimitiveInstance {
+    type Primitive = PrimitiveInstance;
+    fn to_primitive_type(&self) -> PrimitiveInstance {
+        PrimitiveInstance {
+            aData0: [
+                self.data[0],
+                self.data[1],
+                self.data[2],
+                self.data[3],
+            ],
+            aData1: [
+                self.data[4],
+                self.data[5],
+                self.data[6],
+                self.data[7],
+            ],
+        }
+    }
+}
+
+/// Method of uploading texel data from CPU to GPU.
+#[derive(Debug, Clone)]
+pub enum UploadMethod {
+    /// Just call `glTexSubImage` directly with the CPU data pointer
+    Immediate,
+    /// Accumulate the changes in PBO first before transferring to a texture.
+    PixelBuffer,
+}
+
+/// Plain old data that can be used to initialize a texture.
+pub unsafe trait Texel: Copy {}
+unsafe impl Texel for u8 {}
+unsafe impl Texel for f32 {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReadPixelsFormat {
+    Standard(ImageFormat),
+    Rgba8,
+}
+
+pub trait FileWatcherHandler: Send {
+    fn file_changed(&self, path: PathBuf);
+}
+
+#[cfg_attr(feature = "replay", derive(Clone))]
+pub struct ExternalTexture {
+    id: u32,
+    _target: TextureTarget,
+}
+
+impl ExternalTexture {
+    pub fn new(id: u32, _target: TextureTarget) -> Self {
+        ExternalTexture {
+            id,
+            _target,
+        }
+    }
+
+    #[cfg(feature = "replay")]
+    pub fn internal_id(&self) -> u32 {
+        self.id
+    }
+}
+
+pub struct Texture {
+    id: TextureId,
+    _target: TextureTarget,
+    layer_count: i32,
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    filter: TextureFilter,
+    render_target: Option<RenderTargetInfo>,
+    fbo_ids: Vec<FBOId>,
+    depth_rb: Option<RBOId>,
+    last_frame_used: FrameId,
+    bound_in_frame: Cell<FrameId>,
+}
+
+impl Texture {
+    pub fn get_dimensions(&self) -> DeviceUintSize {
+        DeviceUintSize::new(self.width, self.height)
+    }
+
+    pub fn get_render_target_layer_count(&self) -> usize {
+        self.fbo_ids.len()
+    }
+
+    pub fn get_layer_count(&self) -> i32 {
+        self.layer_count
+    }
+
+    pub fn get_format(&self) -> ImageFormat {
+        self.format
+    }
+
+    #[cfg(any(feature = "debug_renderer", feature = "capture"))]
+    pub fn get_filter(&self) -> TextureFilter {
+        self.filter
+    }
+
+    #[cfg(any(feature = "debug_renderer", feature = "capture"))]
+    pub fn get_render_target(&self) -> Option<RenderTargetInfo> {
+        self.render_target.clone()
+    }
+
+    pub fn has_depth(&self) -> bool {
+        self.depth_rb.is_some()
+    }
+
+    pub fn get_rt_info(&self) -> Option<&RenderTargetInfo> {
+        self.render_target.as_ref()
+    }
+
+    pub fn used_in_frame(&self, frame_id: FrameId) -> bool {
+        self.last_frame_used == frame_id
+    }
+
+    #[cfg_attr(feature = "software", allow(dead_code))]
+    fn still_in_flight(&self, frame_id: FrameId) -> bool {
+        for i in 0..MAX_FRAME_COUNT {
+            if self.bound_in_frame.get() == FrameId(frame_id.0 - i) {
+                return true
+            }
+        }
+        false
+    }
+
+    #[cfg(feature = "replay")]
+    pub fn into_external(mut self) -> ExternalTexture {
+        let ext = ExternalTexture {
+            id: self.id,
+            _target: self._target,
+        };
+        self.id = 0; // don't complain, moved out
+        ext
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        debug_assert!(thread::panicking() || self.id == 0);
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+pub struct ProgramId(u32);
+
+pub struct PBO;
+pub struct VAO;
+
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+pub struct FBOId(u32);
+
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+pub struct RBOId(u32);
+
+/// Identifies a window surface (or the offscreen image standing in for it)
+/// the device can present to.
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+pub struct SurfaceId(u32);
+
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+pub struct VBOId(u32);
+
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+struct IBOId(u32);
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serialize_program", derive(Deserialize, Serialize))]
+pub struct ProgramSources {
+    renderer_name: String,
+    shader_name: String,
+    digest: u64,
+}
+
+#[cfg(not(feature = "software"))]
+impl ProgramSources {
+    fn new(renderer_name: String, shader_name: &str, digest: u64) -> Self {
+        ProgramSources {
+            renderer_name,
+            shader_name: shader_name.to_owned(),
+            digest,
+        }
+    }
+}
+
+/// Returns a digest of the shader sources built into the crate, which
+/// identifies the SPIR-V modules build.rs generated from them without
+/// having to read the modules.
+#[cfg(not(feature = "software"))]
+fn shader_sources_digest() -> u64 {
+    let mut names = shader_source::_SHADERS.keys().collect::<Vec<_>>();
+    names.sort();
+    // FxHasher is deterministic, which keeps the digest stable across runs.
+    let mut hasher = FxHasher::default();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    for name in names {
+        name.hash(&mut hasher);
+        shader_source::_SHADERS[name].hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Everything needed to create the pipelines of a program without going
+/// through the shader files and `shader_bindings.ron` produced by build.rs.
+///
+/// The gfx-hal revision we depend on doesn't expose pipeline cache objects,
+/// so there is no driver specific blob stored here yet.
+#[cfg_attr(feature = "serialize_program", derive(Deserialize, Serialize))]
+pub struct ProgramBinary {
+    vs_module: Vec<u8>,
+    fs_module: Vec<u8>,
+    pipeline_requirements: PipelineRequirements,
+    sources: ProgramSources,
+}
+
+#[cfg(not(feature = "software"))]
+impl ProgramBinary {
+    fn new(
+        vs_module: Vec<u8>,
+        fs_module: Vec<u8>,
+        pipeline_requirements: PipelineRequirements,
+        sources: &ProgramSources,
+    ) -> Self {
+        ProgramBinary {
+            vs_module,
+            fs_module,
+            pipeline_requirements,
+            sources: sources.clone(),
+        }
+    }
+}
+
+/// The interfaces that an application can implement to handle ProgramCache update
+pub trait ProgramCacheObserver {
+    fn notify_binary_added(&self, program_binary: &Arc<ProgramBinary>);
+    fn notify_program_binary_failed(&self, program_binary: &Arc<ProgramBinary>);
+}
+
+/// Identifies a program cache file written by `save_to_disk`.
+#[cfg(feature = "serialize_program")]
+const PROGRAM_CACHE_MAGIC: u32 = 0x5752_5043;
+
+/// The version of the program cache file format. It has to be bumped
+/// whenever `ProgramBinary` or anything it contains changes, so that
+/// files written by older versions are rejected instead of misread.
+#[cfg(feature = "serialize_program")]
+const PROGRAM_CACHE_VERSION: u32 = 1;
+
+pub struct ProgramCache {
+    binaries: RefCell<FastHashMap<ProgramSources, Arc<ProgramBinary>>>,
+
+    /// Optional trait object that allows the client
+    /// application to handle ProgramCache updating
+    program_cache_handler: Option<Box<ProgramCacheObserver>>,
+}
+
+impl ProgramCache {
+    pub fn new(program_cache_observer: Option<Box<ProgramCacheObserver>>) -> Rc<Self> {
+        Rc::new(
+            ProgramCache {
+                binaries: RefCell::new(FastHashMap::default()),
+                program_cache_handler: program_cache_observer,
+            }
+        )
+    }
+
+    /// Load ProgramBinary to ProgramCache.
+    /// The function is typically used to load ProgramBinary from disk.
+    #[cfg(feature = "serialize_program")]
+    pub fn load_program_binary(&self, program_binary: Arc<ProgramBinary>) {
+        let sources = program_binary.sources.clone();
+        self.binaries.borrow_mut().insert(sources, program_binary);
+    }
+
+    /// Creates a cache from the binaries previously written by `save_to_disk`.
+    /// A missing or unreadable file, or one written with another version of
+    /// the file format, results in an empty cache.
+    #[cfg(feature = "serialize_program")]
+    pub fn load_from_disk(
+        path: &Path,
+        program_cache_observer: Option<Box<ProgramCacheObserver>>,
+    ) -> Rc<Self> {
+        let cache = Self::new(program_cache_observer);
+        let binaries = File::open(path)
+            .map_err(|e| e.to_string())
+            .and_then(|file| {
+                let mut reader = BufReader::new(file);
+                let (magic, version) = bincode::deserialize_from::<_, (u32, u32)>(&mut reader)
+                    .map_err(|e| e.to_string())?;
+                if magic != PROGRAM_CACHE_MAGIC {
+                    return Err("not a program cache".to_owned());
+                }
+                if version != PROGRAM_CACHE_VERSION {
+                    return Err(format!("unsupported version {}", version));
+                }
+                bincode::deserialize_from::<_, Vec<ProgramBinary>>(&mut reader)
+                    .map_err(|e| e.to_string())
+            });
+        match binaries {
+            Ok(binaries) => {
+                for binary in binaries {
+                    cache.load_program_binary(Arc::new(binary));
+                }
+            }
+            Err(e) => warn!("Unable to load the program cache from {:?}: {}", path, e),
+        }
+        cache
+    }
+
+    /// Writes every binary of the cache to `path`.
+    #[cfg(feature = "serialize_program")]
+    pub fn save_to_disk(&self, path: &Path) -> Result<(), String> {
+        let binaries = self.binaries.borrow();
+        let binaries = binaries.values().map(|binary| &**binary).collect::<Vec<_>>();
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        let mut writer = BufWriter::new(file);
+        bincode::serialize_into(&mut writer, &(PROGRAM_CACHE_MAGIC, PROGRAM_CACHE_VERSION))
+            .map_err(|e| e.to_string())?;
+        bincode::serialize_into(&mut writer, &binaries).map_err(|e| e.to_string())
+    }
+
+    #[cfg(not(feature = "software"))]
+    fn get(&self, sources: &ProgramSources) -> Option<Arc<ProgramBinary>> {
+        self.binaries.borrow().get(sources).cloned()
+    }
+
+    #[cfg(not(feature = "software"))]
+    fn add(&self, program_binary: ProgramBinary) {
+        let program_binary = Arc::new(program_binary);
+        if let Some(ref handler) = self.program_cache_handler {
+            handler.notify_binary_added(&program_binary);
+        }
+        self.binaries
+            .borrow_mut()
+            .insert(program_binary.sources.clone(), program_binary);
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum VertexUsageHint {
+    Static,
+    Dynamic,
+    Stream,
+}
+
+pub struct Capabilities {
+    pub supports_multisampling: bool,
+    pub supports_dual_source_blending: bool,
+    /// Maximum width and height of a 2D texture.
+    pub max_texture_size: u32,
+    /// Maximum number of layers of a texture array.
+    pub max_texture_layers: u32,
+    /// Highest sample count usable with the render target formats.
+    pub max_samples: u8,
+}
+
+#[derive(Clone, Debug)]
+pub enum ShaderError {
+    Compilation(String, String), // name, error message
+    Link(String, String),        // name, error message
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum VertexArrayKind {
+    Primitive,
+    Blur,
+    Clip,
+    VectorStencil,
+    VectorCover,
+    Border,
+    Path,
+    SvgFilter,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum ShaderKind {
+    Primitive,
+    Cache(VertexArrayKind),
+    ClipCache,
+    /// The image clip shader, which also composites mask layers and so
+    /// is drawn with the mask-mode blend states.
+    ClipImage,
+    Brush,
+    Text,
+    #[allow(dead_code)]
+    VectorStencil,
+    #[allow(dead_code)]
+    VectorCover,
+    DebugColor,
+    DebugFont,
+}
+
+impl ShaderKind {
+    #[cfg_attr(feature = "software", allow(dead_code))]
+    fn is_debug(&self) -> bool {
+        match *self {
+            ShaderKind::DebugFont | ShaderKind::DebugColor => true,
+            _ => false,
+        }
+    }
+}
+
+const ALPHA: BlendState = BlendState::On {
+    color: BlendOp::Add {
+        src: Factor::SrcAlpha,
+        dst: Factor::OneMinusSrcAlpha,
+    },
+    alpha: BlendOp::Add {
+        src: Factor::One,
+        dst: Factor::One,
+    },
+};
+
+const PREMULTIPLIED_DEST_OUT: BlendState = BlendState::On {
+    color: BlendOp::Add {
+        src: Factor::Zero,
+        dst: Factor::OneMinusSrcAlpha,
+    },
+    alpha: BlendOp::Add {
+        src: Factor::Zero,
+        dst: Factor::OneMinusSrcAlpha,
+    },
+};
+
+const MAX: BlendState = BlendState::On {
+    color: BlendOp::Max,
+    alpha: BlendOp::Add {
+        src: Factor::One,
+        dst: Factor::One,
+    },
+};
+
+// Mask layers are composited in the red channel of the clip mask, with
+// the coverage of the layer as the source. Intersection is a multiply.
+const MASK_ADD: BlendState = BlendState::On {
+    color: BlendOp::Add {
+        src: Factor::One,
+        dst: Factor::OneMinusSrcColor,
+    },
+    alpha: BlendOp::Add {
+        src: Factor::One,
+        dst: Factor::OneMinusSrcAlpha,
+    },
+};
+
+const MASK_SUBTRACT: BlendState = BlendState::On {
+    color: BlendOp::Add {
+        src: Factor::OneMinusDstColor,
+        dst: Factor::Zero,
+    },
+    alpha: BlendOp::Add {
+        src: Factor::OneMinusDstAlpha,
+        dst: Factor::Zero,
+    },
+};
+
+const MASK_EXCLUDE: BlendState = BlendState::On {
+    color: BlendOp::Add {
+        src: Factor::OneMinusDstColor,
+        dst: Factor::OneMinusSrcColor,
+    },
+    alpha: BlendOp::Add {
+        src: Factor::OneMinusDstAlpha,
+        dst: Factor::OneMinusSrcAlpha,
+    },
+};
+
+#[cfg(feature = "debug_renderer")]
+const MIN: BlendState = BlendState::On {
+    color: BlendOp::Min,
+    alpha: BlendOp::Add {
+        src: Factor::One,
+        dst: Factor::One,
+    },
+};
+
+const SUBPIXEL_PASS0: BlendState = BlendState::On {
+    color: BlendOp::Add {
+        src: Factor::Zero,
+        dst: Factor::OneMinusSrcColor,
+    },
+    alpha: BlendOp::Add {
+        src: Factor::Zero,
+        dst: Factor::OneMinusSrcAlpha,
+    },
+};
+
+const SUBPIXEL_PASS1: BlendState = BlendState::On {
+    color: BlendOp::Add {
+        src: Factor::One,
+        dst: Factor::One,
+    },
+    alpha: BlendOp::Add {
+        src: Factor::One,
+        dst: Factor::One,
+    },
+};
+
+const SUBPIXEL_WITH_BG_COLOR_PASS0: BlendState = BlendState::On {
+    color: BlendOp::Add {
+        src: Factor::Zero,
+        dst: Factor::OneMinusSrcColor,
+    },
+    alpha: BlendOp::Add {
+        src: Factor::Zero,
+        dst: Factor::One,
+    },
+};
+
+const SUBPIXEL_WITH_BG_COLOR_PASS1: BlendState = BlendState::On {
+    color: BlendOp::Add {
+        src: Factor::OneMinusDstAlpha,
+        dst: Factor::One,
+    },
+    alpha: BlendOp::Add {
+        src: Factor::Zero,
+        dst: Factor::One,
+    },
+};
+
+const SUBPIXEL_WITH_BG_COLOR_PASS2: BlendState = BlendState::On {
+    color: BlendOp::Add {
+        src: Factor::One,
+        dst: Factor::One,
+    },
+    alpha: BlendOp::Add {
+        src: Factor::One,
+        dst: Factor::OneMinusSrcAlpha,
+    },
+};
+
+// This requires blend color to be set
+const SUBPIXEL_CONSTANT_TEXT_COLOR: BlendState = BlendState::On {
+    color: BlendOp::Add {
+        src: Factor::ConstColor,
+        dst: Factor::OneMinusSrcColor,
+    },
+    alpha: BlendOp::Add {
+        src: Factor::ConstAlpha,
+        dst: Factor::OneMinusSrcAlpha,
+    },
+};
+
+const SUBPIXEL_DUAL_SOURCE: BlendState = BlendState::On {
+    color: BlendOp::Add {
+        src: Factor::One,
+        dst: Factor::OneMinusSrc1Color,
+    },
+    alpha: BlendOp::Add {
+        src: Factor::One,
+        dst: Factor::OneMinusSrc1Alpha,
+    },
+};
+
+#[cfg_attr(feature = "software", allow(dead_code))]
+const LESS_EQUAL_TEST: DepthTest = DepthTest::On {
+    fun: Comparison::LessEqual,
+    write: false,
+};
+
+#[cfg_attr(feature = "software", allow(dead_code))]
+const LESS_EQUAL_WRITE: DepthTest = DepthTest::On {
+    fun: Comparison::LessEqual,
+    write: true,
+};
+
+#[cfg_attr(feature = "software", allow(dead_code))]
+const LESS_WRITE: DepthTest = DepthTest::On {
+    fun: Comparison::Less,
+    write: true,
+};
+
+fn unorm8(v: u8) -> f32 {
+    v as f32 / 255.0
+}
+
+fn to_unorm8(v: f32) -> u8 {
+    (v.max(0.0).min(1.0) * 255.0 + 0.5) as u8
+}
+
+fn unorm16(v: u16) -> f32 {
+    v as f32 / 65535.0
+}
+
+fn to_unorm16(v: f32) -> u16 {
+    (v.max(0.0).min(1.0) * 65535.0 + 0.5) as u16
+}
+
+/// Reads a texel of the given format as unpremultiplied RGBA.
+fn decode_texel(format: ImageFormat, data: &[u8]) -> [f32; 4] {
+    match format {
+        ImageFormat::R8 => [unorm8(data[0]), 0.0, 0.0, 1.0],
+        ImageFormat::R16 => [unorm16(NativeEndian::read_u16(data)), 0.0, 0.0, 1.0],
+        ImageFormat::RG8 => [unorm8(data[0]), unorm8(data[1]), 0.0, 1.0],
+        ImageFormat::RGBA8 => [unorm8(data[0]), unorm8(data[1]), unorm8(data[2]), unorm8(data[3])],
+        ImageFormat::BGRA8 => [unorm8(data[2]), unorm8(data[1]), unorm8(data[0]), unorm8(data[3])],
+        ImageFormat::RGBAF32 => [
+            NativeEndian::read_f32(&data[0 ..]),
+            NativeEndian::read_f32(&data[4 ..]),
+            NativeEndian::read_f32(&data[8 ..]),
+            NativeEndian::read_f32(&data[12 ..]),
+        ],
+        ImageFormat::RGBAI32 => [
+            NativeEndian::read_i32(&data[0 ..]) as f32,
+            NativeEndian::read_i32(&data[4 ..]) as f32,
+            NativeEndian::read_i32(&data[8 ..]) as f32,
+            NativeEndian::read_i32(&data[12 ..]) as f32,
+        ],
+    }
+}
+
+/// Writes an RGBA texel in the given format, clamping the values it can't
+/// represent.
+fn encode_texel(format: ImageFormat, color: [f32; 4], data: &mut [u8]) {
+    match format {
+        ImageFormat::R8 => data[0] = to_unorm8(color[0]),
+        ImageFormat::R16 => NativeEndian::write_u16(data, to_unorm16(color[0])),
+        ImageFormat::RG8 => {
+            data[0] = to_unorm8(color[0]);
+            data[1] = to_unorm8(color[1]);
+        }
+        ImageFormat::RGBA8 => {
+            for i in 0 .. 4 {
+                data[i] = to_unorm8(color[i]);
+            }
+        }
+        ImageFormat::BGRA8 => {
+            data[0] = to_unorm8(color[2]);
+            data[1] = to_unorm8(color[1]);
+            data[2] = to_unorm8(color[0]);
+            data[3] = to_unorm8(color[3]);
+        }
+        ImageFormat::RGBAF32 => {
+            for i in 0 .. 4 {
+                NativeEndian::write_f32(&mut data[i * 4 ..], color[i]);
+            }
+        }
+        ImageFormat::RGBAI32 => {
+            for i in 0 .. 4 {
+                NativeEndian::write_i32(&mut data[i * 4 ..], color[i].round() as i32);
+            }
+        }
+    }
+}
+
+fn texels_to_u8_slice<T: Texel>(texels: &[T]) -> &[u8] {
+    unsafe {
+        slice::from_raw_parts(texels.as_ptr() as *const u8, texels.len() * mem::size_of::<T>())
+    }
+}
@@ -0,0 +1,4648 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A software implementation of the device.
+//!
+//! Textures and render targets live in CPU memory, and the batches the
+//! renderer submits are rasterized by emulating the corresponding shaders.
+//! This makes it possible to run the renderer (and wrench reftests) on
+//! machines without a GPU or a working Vulkan/DX12 driver.
+//!
+//! The brush shaders, `ps_text_run`, `ps_split_composite`, the cache
+//! shaders (`cs_blur`, `cs_border_segment`, `cs_path`, `cs_svg_filter`)
+//! and the clip shaders are emulated. Creating a program for any other
+//! shader fails, so that the renderer reports it instead of silently
+//! dropping the batches. The debug overlay isn't drawn.
+
+use api::{ColorF, ImageFormat, YuvColorSpace, YuvFormat};
+use api::{DeviceIntPoint, DeviceIntRect, DeviceIntSize, DeviceUintRect, DeviceUintSize};
+use api::TextureTarget;
+#[cfg(any(feature = "debug_renderer", feature="capture"))]
+use api::{DeviceUintPoint, ImageDescriptor};
+use euclid::Transform3D;
+use internal_types::{FastHashMap, RenderTargetInfo};
+use renderer::MAX_VERTEX_TEXTURE_WIDTH;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::f32;
+use std::marker::PhantomData;
+use std::mem;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::slice;
+use vertex_types::*;
+use super::{DepthFunction, ExternalTexture, FBOId, FileWatcherHandler, FrameId};
+use super::{PipelineRequirements, PrimitiveType, ProgramCache, ProgramId, RBOId, ReadPixelsFormat};
+use super::{ShaderError, SurfaceId, MAIN_SURFACE};
+use super::{ShaderKind, Texel, Texture, TextureFilter, TextureId, TextureSlot, UploadMethod};
+use super::{VertexArrayKind, VertexDescriptor, VertexUsageHint, VAO, PBO};
+use super::{DEFAULT_DRAW_FBO, DEFAULT_READ_FBO, INVALID_PROGRAM_ID, INVALID_TEXTURE_ID};
+use super::{ALPHA, MASK_ADD, MASK_EXCLUDE, MASK_SUBTRACT, MAX, PREMULTIPLIED_DEST_OUT};
+use super::{SUBPIXEL_CONSTANT_TEXT_COLOR, SUBPIXEL_DUAL_SOURCE, SUBPIXEL_PASS0, SUBPIXEL_PASS1};
+use super::{SUBPIXEL_WITH_BG_COLOR_PASS0, SUBPIXEL_WITH_BG_COLOR_PASS1, SUBPIXEL_WITH_BG_COLOR_PASS2};
+use super::{decode_texel, encode_texel, texels_to_u8_slice, to_unorm8};
+use super::Capabilities;
+#[cfg(feature = "debug_renderer")]
+use super::MIN;
+
+use hal;
+use hal::pso::{BlendOp, BlendState, Comparison, DepthTest, Factor};
+
+const MAX_TEXTURE_SIZE: u32 = 8192;
+const MAX_TEXTURE_LAYERS: u32 = 2048;
+
+// Texture slots, these have to match `TextureSampler` in renderer.rs.
+const SLOT_COLOR0: usize = 0;
+const SLOT_COLOR1: usize = 1;
+const SLOT_COLOR2: usize = 2;
+const SLOT_CACHE_A8: usize = 3;
+const SLOT_CACHE_RGBA8: usize = 4;
+const SLOT_RESOURCE_CACHE: usize = 5;
+const SLOT_CLIP_SCROLL_NODES: usize = 6;
+const SLOT_RENDER_TASKS: usize = 7;
+const SLOT_LOCAL_CLIP_RECTS: usize = 10;
+
+// These have to match the defines in the shaders.
+const VECS_PER_RENDER_TASK: usize = 2;
+const VECS_PER_CLIP_SCROLL_NODE: usize = 9;
+const VECS_PER_LOCAL_CLIP_RECT: usize = 1;
+const VECS_PER_BRUSH_PRIM: i32 = 2;
+const VECS_PER_SEGMENT: i32 = 2;
+const CLIP_CHAIN_RECT_BITS: i32 = 22;
+const CLIP_SCROLL_INDEX_BITS: i32 = 31 - 4 - 4;
+const INVALID_CLIP_TASK: i32 = 0x7FFF;
+
+const BRUSH_FLAG_SEGMENT_RELATIVE: i32 = 2;
+const BRUSH_FLAG_SEGMENT_REPEAT_X: i32 = 4;
+const BRUSH_FLAG_SEGMENT_REPEAT_Y: i32 = 8;
+
+const COLOR_MODE_FROM_PASS: i32 = 0;
+const COLOR_MODE_ALPHA: i32 = 1;
+const COLOR_MODE_SUBPX_CONST_COLOR: i32 = 2;
+const COLOR_MODE_SUBPX_BG_PASS0: i32 = 3;
+const COLOR_MODE_SUBPX_BG_PASS1: i32 = 4;
+const COLOR_MODE_SUBPX_BG_PASS2: i32 = 5;
+const COLOR_MODE_SUBPX_DUAL_SOURCE: i32 = 6;
+const COLOR_MODE_BITMAP: i32 = 7;
+const COLOR_MODE_COLOR_BITMAP: i32 = 8;
+const COLOR_MODE_SDF: i32 = 9;
+
+const VECS_PER_PRIM_HEADER: i32 = 2;
+const VECS_PER_TEXT_RUN: i32 = 3;
+
+const SUBPX_DIR_HORIZONTAL: i32 = 1;
+const SUBPX_DIR_VERTICAL: i32 = 2;
+const SUBPX_DIR_MIXED: i32 = 3;
+
+const EXTEND_MODE_CLAMP: i32 = 0;
+const GRADIENT_ENTRIES: i32 = 128;
+
+const MIX_BLEND_MULTIPLY: i32 = 1;
+const MIX_BLEND_SCREEN: i32 = 2;
+const MIX_BLEND_OVERLAY: i32 = 3;
+const MIX_BLEND_DARKEN: i32 = 4;
+const MIX_BLEND_LIGHTEN: i32 = 5;
+const MIX_BLEND_COLOR_DODGE: i32 = 6;
+const MIX_BLEND_COLOR_BURN: i32 = 7;
+const MIX_BLEND_HARD_LIGHT: i32 = 8;
+const MIX_BLEND_SOFT_LIGHT: i32 = 9;
+const MIX_BLEND_DIFFERENCE: i32 = 10;
+const MIX_BLEND_EXCLUSION: i32 = 11;
+const MIX_BLEND_HUE: i32 = 12;
+const MIX_BLEND_SATURATION: i32 = 13;
+const MIX_BLEND_COLOR: i32 = 14;
+const MIX_BLEND_LUMINOSITY: i32 = 15;
+
+const BORDER_SEGMENT_TOP_LEFT: i32 = 0;
+const BORDER_SEGMENT_TOP_RIGHT: i32 = 1;
+const BORDER_SEGMENT_BOTTOM_RIGHT: i32 = 2;
+const BORDER_SEGMENT_BOTTOM_LEFT: i32 = 3;
+const BORDER_SEGMENT_TOP: i32 = 5;
+const BORDER_SEGMENT_BOTTOM: i32 = 7;
+
+const BORDER_STYLE_DOUBLE: i32 = 2;
+const BORDER_STYLE_GROOVE: i32 = 6;
+const BORDER_STYLE_RIDGE: i32 = 7;
+
+const BORDER_CLIP_DASH: i32 = 1;
+const BORDER_CLIP_DOT: i32 = 2;
+
+const BOX_SHADOW_MODE_STRETCH: i32 = 0;
+
+const LINE_STYLE_DOTTED: i32 = 1;
+const LINE_STYLE_DASHED: i32 = 2;
+const LINE_STYLE_WAVY: i32 = 3;
+const LINE_ORIENTATION_VERTICAL: i32 = 0;
+const LINE_ORIENTATION_HORIZONTAL: i32 = 1;
+
+const MASK_SOURCE_GRADIENT: i32 = 1;
+const MASK_CHANNEL_ALPHA: i32 = 1;
+const MASK_CHANNEL_LUMINANCE: i32 = 2;
+
+/// See `EPSILON` in shared.glsl.
+const EPSILON: f32 = 0.0001;
+
+const DIR_HORIZONTAL: i32 = 0;
+const DIR_VERTICAL: i32 = 1;
+
+const FILL_RULE_EVEN_ODD: i32 = 1;
+const PATH_PAINT_GRADIENT: i32 = 1;
+
+const FILTER_IDENTITY: i32 = 0;
+const FILTER_FLOOD: i32 = 1;
+const FILTER_OFFSET: i32 = 2;
+const FILTER_COLOR_MATRIX: i32 = 3;
+const FILTER_COMPONENT_TRANSFER: i32 = 4;
+const FILTER_COMPOSITE: i32 = 5;
+const FILTER_BLEND: i32 = 6;
+const FILTER_MORPHOLOGY: i32 = 7;
+const FILTER_TURBULENCE: i32 = 8;
+const FILTER_DISPLACEMENT_MAP: i32 = 9;
+
+const FILTER_FLAG_LINEAR_RGB: i32 = 1;
+const FILTER_FLAG_CONVERT_INPUT_1: i32 = 2;
+const FILTER_FLAG_CONVERT_INPUT_2: i32 = 4;
+
+const COMPOSITE_OVER: i32 = 0;
+const COMPOSITE_IN: i32 = 1;
+const COMPOSITE_OUT: i32 = 2;
+const COMPOSITE_ATOP: i32 = 3;
+const COMPOSITE_XOR: i32 = 4;
+const COMPOSITE_ARITHMETIC: i32 = 5;
+
+const MORPHOLOGY_ERODE: i32 = 0;
+const MORPHOLOGY_VERTICAL: i32 = 1;
+const TURBULENCE_FRACTAL_NOISE: i32 = 0;
+const MAX_TURBULENCE_OCTAVES: i32 = 8;
+const TURBULENCE_LATTICE_OFFSET: i32 = 1;
+const TURBULENCE_GRADIENT_OFFSET: i32 = 129;
+const TURBULENCE_GRADIENT_BLOCKS: i32 = 128;
+
+type Vec4 = [f32; 4];
+
+/// A 4x4 matrix stored as four columns, matching how the shaders
+/// interpret the matrices fetched from the data textures and uniforms.
+#[derive(Clone, Copy, Debug)]
+struct Mat4([Vec4; 4]);
+
+impl Mat4 {
+    fn identity() -> Self {
+        Mat4([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    fn mul(&self, v: Vec4) -> Vec4 {
+        let c = &self.0;
+        let mut r = [0.0; 4];
+        for i in 0 .. 4 {
+            r[i] = c[0][i] * v[0] + c[1][i] * v[1] + c[2][i] * v[2] + c[3][i] * v[3];
+        }
+        r
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Rect {
+    p0: (f32, f32),
+    size: (f32, f32),
+}
+
+impl Rect {
+    fn from_vec4(v: Vec4) -> Self {
+        Rect { p0: (v[0], v[1]), size: (v[2], v[3]) }
+    }
+
+    fn p1(&self) -> (f32, f32) {
+        (self.p0.0 + self.size.0, self.p0.1 + self.size.1)
+    }
+
+    fn intersect(&self, other: &Rect) -> Rect {
+        let p1 = self.p1();
+        let q1 = other.p1();
+        let x0 = self.p0.0.max(other.p0.0);
+        let y0 = self.p0.1.max(other.p0.1);
+        let x1 = p1.0.min(q1.0);
+        let y1 = p1.1.min(q1.1);
+        Rect { p0: (x0, y0), size: ((x1 - x0).max(0.0), (y1 - y0).max(0.0)) }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.size.0 <= 0.0 || self.size.1 <= 0.0
+    }
+
+    /// Includes the top and left edges only, like `point_inside_rect`.
+    fn contains(&self, p: (f32, f32)) -> bool {
+        let p1 = self.p1();
+        p.0 >= self.p0.0 && p.1 >= self.p0.1 && p.0 < p1.0 && p.1 < p1.1
+    }
+}
+
+struct RenderTaskData {
+    task_rect: Rect,
+    layer: usize,
+    data1: [f32; 3],
+}
+
+/// Places the device space content of a picture in its render task, see
+/// `picture_task_position` in render_task.glsl.
+#[derive(Clone, Copy)]
+struct PictureTransform {
+    content_origin: (f32, f32),
+    target_origin: (f32, f32),
+    scale: f32,
+}
+
+impl PictureTransform {
+    fn new(task: &RenderTaskData) -> Self {
+        PictureTransform {
+            content_origin: (task.data1[0], task.data1[1]),
+            target_origin: task.task_rect.p0,
+            scale: task.data1[2],
+        }
+    }
+
+    /// Shifts the content by a device space offset.
+    fn offset(&self, offset: (f32, f32)) -> Self {
+        PictureTransform {
+            content_origin: (self.content_origin.0 - offset.0, self.content_origin.1 - offset.1),
+            ..*self
+        }
+    }
+
+    fn apply(&self, device_pos: (f32, f32)) -> (f32, f32) {
+        (
+            (device_pos.0 - self.content_origin.0) * self.scale + self.target_origin.0,
+            (device_pos.1 - self.content_origin.1) * self.scale + self.target_origin.1,
+        )
+    }
+}
+
+struct ClipArea {
+    task_rect: Rect,
+    layer: usize,
+    screen_origin: (f32, f32),
+    local_space: bool,
+}
+
+struct ClipScrollNode {
+    transform: Mat4,
+    inv_transform: Mat4,
+}
+
+/// CPU storage of a texture, one tightly packed buffer per layer in the
+/// native layout of the texture format.
+struct SoftwareImage {
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    layers: Vec<Vec<u8>>,
+}
+
+impl SoftwareImage {
+    fn new(format: ImageFormat, width: u32, height: u32, layer_count: i32) -> Self {
+        let layer_size = (width * height * format.bytes_per_pixel()) as usize;
+        SoftwareImage {
+            format,
+            width,
+            height,
+            layers: (0 .. layer_count.max(1)).map(|_| vec![0; layer_size]).collect(),
+        }
+    }
+
+    fn stride(&self) -> usize {
+        (self.width * self.format.bytes_per_pixel()) as usize
+    }
+
+    fn contains(&self, layer: usize, x: i32, y: i32) -> bool {
+        layer < self.layers.len() && x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+    }
+
+    fn offset(&self, x: i32, y: i32) -> usize {
+        y as usize * self.stride() + x as usize * self.format.bytes_per_pixel() as usize
+    }
+
+    fn fetch(&self, layer: usize, x: i32, y: i32) -> Vec4 {
+        if !self.contains(layer, x, y) {
+            return [0.0; 4];
+        }
+        let offset = self.offset(x, y);
+        decode_texel(self.format, &self.layers[layer][offset ..])
+    }
+
+    fn store(&mut self, layer: usize, x: i32, y: i32, color: Vec4) {
+        if !self.contains(layer, x, y) {
+            return;
+        }
+        let offset = self.offset(x, y);
+        let format = self.format;
+        encode_texel(format, color, &mut self.layers[layer][offset ..]);
+    }
+
+    /// Samples the texture at a position given in texels.
+    /// Integer textures are never filtered.
+    fn sample(&self, layer: usize, x: f32, y: f32, filter: TextureFilter) -> Vec4 {
+        match (filter, self.format) {
+            (_, ImageFormat::RGBAI32) |
+            (TextureFilter::Nearest, _) => self.fetch(layer, x.floor() as i32, y.floor() as i32),
+            (TextureFilter::Linear, _) | (TextureFilter::Trilinear, _) => {
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let clamp_x = |v: i32| v.max(0).min(self.width as i32 - 1);
+                let clamp_y = |v: i32| v.max(0).min(self.height as i32 - 1);
+                let (ix0, iy0) = (clamp_x(x0 as i32), clamp_y(y0 as i32));
+                let (ix1, iy1) = (clamp_x(x0 as i32 + 1), clamp_y(y0 as i32 + 1));
+                let top = mix(self.fetch(layer, ix0, iy0), self.fetch(layer, ix1, iy0), fx);
+                let bottom = mix(self.fetch(layer, ix0, iy1), self.fetch(layer, ix1, iy1), fx);
+                mix(top, bottom, fy)
+            }
+        }
+    }
+
+    fn fill(&mut self, layer: usize, rect: DeviceIntRect, color: Vec4) {
+        let bpp = self.format.bytes_per_pixel() as usize;
+        let mut texel = [0u8; 16];
+        encode_texel(self.format, color, &mut texel);
+        let x0 = rect.origin.x.max(0);
+        let y0 = rect.origin.y.max(0);
+        let x1 = rect.max_x().min(self.width as i32);
+        let y1 = rect.max_y().min(self.height as i32);
+        if layer >= self.layers.len() || x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        for y in y0 .. y1 {
+            let start = self.offset(x0, y);
+            let row = &mut self.layers[layer][start .. start + (x1 - x0) as usize * bpp];
+            for chunk in row.chunks_mut(bpp) {
+                chunk.copy_from_slice(&texel[.. bpp]);
+            }
+        }
+    }
+
+    fn rect(&self) -> DeviceIntRect {
+        DeviceIntRect::new(
+            DeviceIntPoint::zero(),
+            DeviceIntSize::new(self.width as i32, self.height as i32),
+        )
+    }
+}
+
+/// A depth buffer attached to a render target.
+struct DepthBuffer {
+    width: u32,
+    data: Vec<f32>,
+}
+
+impl DepthBuffer {
+    fn new(width: u32, height: u32) -> Self {
+        DepthBuffer {
+            width,
+            data: vec![1.0; (width * height) as usize],
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+struct Framebuffer {
+    texture: TextureId,
+    layer_index: usize,
+    rbo: Option<RBOId>,
+}
+
+struct Program {
+    shader_name: String,
+    kind: ProgramKind,
+    transform: Mat4,
+    // Instance data is kept as 32 bit words, every instance type
+    // in `vertex_types` is made of 32 bit fields.
+    instances: Vec<u32>,
+}
+
+impl Program {
+    fn instances<T: Copy>(&self) -> &[T] {
+        let size = mem::size_of::<T>();
+        debug_assert_eq!(size % mem::size_of::<u32>(), 0);
+        let count = self.instances.len() * mem::size_of::<u32>() / size;
+        unsafe { slice::from_raw_parts(self.instances.as_ptr() as *const T, count) }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ProgramKind {
+    BrushSolid,
+    BrushImage,
+    BrushBlend,
+    BrushMixBlend,
+    BrushLinearGradient,
+    BrushRadialGradient,
+    BrushConicGradient,
+    BrushYuvImage(YuvFormat, YuvColorSpace),
+    TextRun(TextRunKind),
+    SplitComposite,
+    Blur,
+    Border,
+    Path,
+    SvgFilter,
+    ClipRectangle,
+    ClipBoxShadow,
+    ClipLine,
+    ClipImage,
+    ClipPath,
+    /// The debug overlay, its vertices are not kept so it isn't drawn.
+    Debug,
+}
+
+impl ProgramKind {
+    /// Returns `None` for the shaders that are not emulated.
+    fn new(shader_name: &str, shader_kind: &ShaderKind) -> Option<Self> {
+        let kind = match *shader_kind {
+            ShaderKind::Brush if shader_name.starts_with("brush_solid") => ProgramKind::BrushSolid,
+            ShaderKind::Brush if shader_name.starts_with("brush_image") => ProgramKind::BrushImage,
+            ShaderKind::Brush if shader_name.starts_with("brush_blend") => ProgramKind::BrushBlend,
+            ShaderKind::Brush if shader_name.starts_with("brush_mix_blend") => ProgramKind::BrushMixBlend,
+            ShaderKind::Brush if shader_name.starts_with("brush_linear_gradient") => ProgramKind::BrushLinearGradient,
+            ShaderKind::Brush if shader_name.starts_with("brush_radial_gradient") => ProgramKind::BrushRadialGradient,
+            ShaderKind::Brush if shader_name.starts_with("brush_conic_gradient") => ProgramKind::BrushConicGradient,
+            ShaderKind::Brush if shader_name.starts_with("brush_yuv_image") => {
+                let format = if shader_name.contains("_yuv_planar") {
+                    YuvFormat::PlanarYCbCr
+                } else if shader_name.contains("_yuv_interleaved") {
+                    YuvFormat::InterleavedYCbCr
+                } else {
+                    YuvFormat::NV12
+                };
+                let color_space = if shader_name.contains("_yuv_rec709") {
+                    YuvColorSpace::Rec709
+                } else {
+                    YuvColorSpace::Rec601
+                };
+                ProgramKind::BrushYuvImage(format, color_space)
+            }
+            ShaderKind::Text if shader_name.starts_with("ps_text_run") => {
+                ProgramKind::TextRun(if shader_name.ends_with("_glyph_transform") {
+                    TextRunKind::GlyphTransform
+                } else if shader_name.ends_with("_glyph_sdf") {
+                    TextRunKind::Sdf
+                } else if shader_name.ends_with("_transform") {
+                    TextRunKind::Transform
+                } else {
+                    TextRunKind::Snapped
+                })
+            }
+            ShaderKind::Primitive if shader_name.starts_with("ps_split_composite") => ProgramKind::SplitComposite,
+            ShaderKind::Cache(VertexArrayKind::Blur) => ProgramKind::Blur,
+            ShaderKind::Cache(VertexArrayKind::Border) => ProgramKind::Border,
+            ShaderKind::Cache(VertexArrayKind::Path) => ProgramKind::Path,
+            ShaderKind::Cache(VertexArrayKind::SvgFilter) => ProgramKind::SvgFilter,
+            ShaderKind::ClipCache if shader_name.starts_with("cs_clip_rectangle") => ProgramKind::ClipRectangle,
+            ShaderKind::ClipCache if shader_name.starts_with("cs_clip_box_shadow") => ProgramKind::ClipBoxShadow,
+            ShaderKind::ClipCache if shader_name.starts_with("cs_clip_line") => ProgramKind::ClipLine,
+            ShaderKind::ClipCache if shader_name.starts_with("cs_clip_path") => ProgramKind::ClipPath,
+            ShaderKind::ClipImage => ProgramKind::ClipImage,
+            ShaderKind::DebugColor | ShaderKind::DebugFont => ProgramKind::Debug,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    /// The number of resource cache blocks between the brush
+    /// primitive header and the segments, see `VECS_PER_SPECIFIC_BRUSH`.
+    fn vecs_per_specific_brush(&self) -> i32 {
+        match *self {
+            ProgramKind::BrushImage | ProgramKind::BrushBlend | ProgramKind::BrushMixBlend => 3,
+            ProgramKind::BrushLinearGradient |
+            ProgramKind::BrushRadialGradient |
+            ProgramKind::BrushConicGradient => 2,
+            ProgramKind::BrushYuvImage(..) => 0,
+            _ => 1,
+        }
+    }
+}
+
+/// The variants of ps_text_run.glsl.
+#[derive(Clone, Copy, PartialEq)]
+enum TextRunKind {
+    /// Glyphs snapped to device pixels in an axis aligned space.
+    Snapped,
+    /// Glyphs in a transformed space, `WR_FEATURE_TRANSFORM`.
+    Transform,
+    /// Glyphs rasterized with the transform, `WR_FEATURE_GLYPH_TRANSFORM`.
+    GlyphTransform,
+    /// Distance field glyphs, `WR_FEATURE_GLYPH_SDF`.
+    Sdf,
+}
+
+/// The per-draw state of the rasterizer.
+struct DrawState {
+    transform: Mat4,
+    viewport: DeviceIntRect,
+    scissor: DeviceIntRect,
+    blend: BlendState,
+    blend_color: Vec4,
+    depth: DepthTest,
+    device_pixel_ratio: f32,
+    mode: i32,
+    alpha_pass: bool,
+}
+
+impl DrawState {
+    /// Maps a point in the render target space (what the vertex
+    /// shaders feed into `uTransform`) to pixel coordinates.
+    fn to_pixel(&self, x: f32, y: f32, z: f32) -> (f32, f32, f32) {
+        let ndc = self.transform.mul([x, y, z, 1.0]);
+        let w = if ndc[3] != 0.0 { ndc[3] } else { 1.0 };
+        let (nx, ny, nz) = (ndc[0] / w, ndc[1] / w, ndc[2] / w);
+        (
+            self.viewport.origin.x as f32 + (nx * 0.5 + 0.5) * self.viewport.size.width as f32,
+            self.viewport.origin.y as f32 + (ny * 0.5 + 0.5) * self.viewport.size.height as f32,
+            nz,
+        )
+    }
+
+    /// Returns the pixel bounds of an axis aligned rectangle given in render target space.
+    fn pixel_rect(&self, rect: &Rect) -> DeviceIntRect {
+        let p1 = rect.p1();
+        let (ax, ay, _) = self.to_pixel(rect.p0.0, rect.p0.1, 0.0);
+        let (bx, by, _) = self.to_pixel(p1.0, p1.1, 0.0);
+        let x0 = ax.min(bx).round() as i32;
+        let y0 = ay.min(by).round() as i32;
+        let x1 = ax.max(bx).round() as i32;
+        let y1 = ay.max(by).round() as i32;
+        DeviceIntRect::new(DeviceIntPoint::new(x0, y0), DeviceIntSize::new(x1 - x0, y1 - y0))
+            .intersection(&self.scissor)
+            .unwrap_or(DeviceIntRect::zero())
+    }
+}
+
+/// The color target of a draw call along with its optional depth buffer.
+struct Target<'a> {
+    image: &'a mut SoftwareImage,
+    layer: usize,
+    depth: Option<&'a mut DepthBuffer>,
+}
+
+impl<'a> Target<'a> {
+    fn write(&mut self, state: &DrawState, x: i32, y: i32, z: f32, color: Vec4, color1: Vec4) {
+        if !self.image.contains(self.layer, x, y) {
+            return;
+        }
+        if let DepthTest::On { fun, write } = state.depth {
+            if let Some(ref mut depth) = self.depth {
+                let index = depth.index(x, y);
+                if !compare(fun, z, depth.data[index]) {
+                    return;
+                }
+                if write {
+                    depth.data[index] = z;
+                }
+            }
+        }
+        let dst = self.image.fetch(self.layer, x, y);
+        let result = blend(state.blend, color, color1, dst, state.blend_color);
+        self.image.store(self.layer, x, y, result);
+    }
+}
+
+/// Read-only views of the data textures the shaders fetch from.
+struct ShaderData<'a> {
+    resource_cache: Option<&'a SoftwareImage>,
+    render_tasks: Option<&'a SoftwareImage>,
+    clip_scroll_nodes: Option<&'a SoftwareImage>,
+    local_clip_rects: Option<&'a SoftwareImage>,
+}
+
+impl<'a> ShaderData<'a> {
+    fn fetch_vertex_data(image: Option<&SoftwareImage>, index: usize, vecs_per_item: usize, offset: usize) -> Vec4 {
+        let items_per_row = MAX_VERTEX_TEXTURE_WIDTH / vecs_per_item;
+        let x = vecs_per_item * (index % items_per_row) + offset;
+        let y = index / items_per_row;
+        image.map_or([0.0; 4], |image| image.fetch(0, x as i32, y as i32))
+    }
+
+    fn resource_cache(&self, address: i32) -> Vec4 {
+        let address = address.max(0) as usize;
+        self.resource_cache_direct(
+            (address % MAX_VERTEX_TEXTURE_WIDTH) as i32,
+            (address / MAX_VERTEX_TEXTURE_WIDTH) as i32,
+        )
+    }
+
+    fn resource_cache_direct(&self, u: i32, v: i32) -> Vec4 {
+        self.resource_cache.map_or([0.0; 4], |image| image.fetch(0, u, v))
+    }
+
+    fn render_task(&self, index: i32) -> RenderTaskData {
+        let texel0 = Self::fetch_vertex_data(self.render_tasks, index as usize, VECS_PER_RENDER_TASK, 0);
+        let texel1 = Self::fetch_vertex_data(self.render_tasks, index as usize, VECS_PER_RENDER_TASK, 1);
+        RenderTaskData {
+            task_rect: Rect::from_vec4(texel0),
+            layer: texel1[0] as usize,
+            data1: [texel1[1], texel1[2], texel1[3]],
+        }
+    }
+
+    fn clip_area(&self, index: i32) -> ClipArea {
+        if index == INVALID_CLIP_TASK {
+            return ClipArea {
+                task_rect: Rect { p0: (0.0, 0.0), size: (0.0, 0.0) },
+                layer: 0,
+                screen_origin: (0.0, 0.0),
+                local_space: false,
+            };
+        }
+        let task = self.render_task(index);
+        ClipArea {
+            task_rect: task.task_rect,
+            layer: task.layer,
+            screen_origin: (task.data1[0], task.data1[1]),
+            local_space: task.data1[2] == 0.0,
+        }
+    }
+
+    fn clip_scroll_node(&self, index: i32) -> ClipScrollNode {
+        let fetch = |offset| {
+            Self::fetch_vertex_data(self.clip_scroll_nodes, index as usize, VECS_PER_CLIP_SCROLL_NODE, offset)
+        };
+        ClipScrollNode {
+            transform: Mat4([fetch(0), fetch(1), fetch(2), fetch(3)]),
+            inv_transform: Mat4([fetch(4), fetch(5), fetch(6), fetch(7)]),
+        }
+    }
+
+    fn clip_chain_rect(&self, index: i32) -> Rect {
+        Rect::from_vec4(
+            Self::fetch_vertex_data(self.local_clip_rects, index as usize, VECS_PER_LOCAL_CLIP_RECT, 0)
+        )
+    }
+}
+
+/// The sampled textures a draw call can read from.
+struct ShaderTextures<'a> {
+    color0: Option<(&'a SoftwareImage, TextureFilter)>,
+    color1: Option<(&'a SoftwareImage, TextureFilter)>,
+    color2: Option<(&'a SoftwareImage, TextureFilter)>,
+    cache_a8: Option<&'a SoftwareImage>,
+    cache_rgba8: Option<&'a SoftwareImage>,
+}
+
+/// Maps a point of a local space rectangle to the render target space
+/// and back, assuming the transformation is affine.
+struct QuadMapping {
+    local: Rect,
+    // Device space (before the picture task transform) corners.
+    device: [(f32, f32); 3],
+    // Pixel space corners, plus depth.
+    pixel: [(f32, f32); 3],
+    z: f32,
+    inv: [f32; 4],
+}
+
+impl QuadMapping {
+    fn new(
+        local: Rect,
+        node: &ClipScrollNode,
+        device_pixel_ratio: f32,
+        task: PictureTransform,
+        z: f32,
+        state: &DrawState,
+    ) -> Option<Self> {
+        let p1 = local.p1();
+        let corners = [(local.p0.0, local.p0.1), (p1.0, local.p0.1), (local.p0.0, p1.1)];
+        let mut device = [(0.0, 0.0); 3];
+        for (i, &(x, y)) in corners.iter().enumerate() {
+            let world = node.transform.mul([x, y, 0.0, 1.0]);
+            if world[3] <= 0.0 {
+                return None;
+            }
+            device[i] = (world[0] / world[3] * device_pixel_ratio, world[1] / world[3] * device_pixel_ratio);
+        }
+        QuadMapping::from_device_corners(local, device, task, z, state)
+    }
+
+    /// Creates a mapping from the device space positions of the top left,
+    /// top right and bottom left corners of the local rectangle.
+    fn from_device_corners(
+        local: Rect,
+        device: [(f32, f32); 3],
+        task: PictureTransform,
+        z: f32,
+        state: &DrawState,
+    ) -> Option<Self> {
+        let mut pixel = [(0.0, 0.0); 3];
+        let mut depth = 0.0;
+        for (i, &device_pos) in device.iter().enumerate() {
+            let (tx, ty) = task.apply(device_pos);
+            let (px, py, pz) = state.to_pixel(tx, ty, z);
+            pixel[i] = (px, py);
+            depth = pz;
+        }
+        let ax = (pixel[1].0 - pixel[0].0, pixel[1].1 - pixel[0].1);
+        let ay = (pixel[2].0 - pixel[0].0, pixel[2].1 - pixel[0].1);
+        let det = ax.0 * ay.1 - ay.0 * ax.1;
+        if det.abs() < 1e-6 {
+            return None;
+        }
+        Some(QuadMapping {
+            local,
+            device,
+            pixel,
+            z: depth,
+            inv: [ay.1 / det, -ay.0 / det, -ax.1 / det, ax.0 / det],
+        })
+    }
+
+    fn pixel_bounds(&self, state: &DrawState) -> DeviceIntRect {
+        let p3 = (
+            self.pixel[1].0 + self.pixel[2].0 - self.pixel[0].0,
+            self.pixel[1].1 + self.pixel[2].1 - self.pixel[0].1,
+        );
+        let xs = [self.pixel[0].0, self.pixel[1].0, self.pixel[2].0, p3.0];
+        let ys = [self.pixel[0].1, self.pixel[1].1, self.pixel[2].1, p3.1];
+        let x0 = xs.iter().cloned().fold(f32::MAX, |a, b| a.min(b)).floor() as i32;
+        let y0 = ys.iter().cloned().fold(f32::MAX, |a, b| a.min(b)).floor() as i32;
+        let x1 = xs.iter().cloned().fold(f32::MIN, |a, b| a.max(b)).ceil() as i32;
+        let y1 = ys.iter().cloned().fold(f32::MIN, |a, b| a.max(b)).ceil() as i32;
+        DeviceIntRect::new(DeviceIntPoint::new(x0, y0), DeviceIntSize::new(x1 - x0, y1 - y0))
+            .intersection(&state.scissor)
+            .unwrap_or(DeviceIntRect::zero())
+    }
+
+    /// Returns the interpolation factors of the pixel center within the
+    /// local rectangle, if the center is covered by the quad.
+    fn factors(&self, x: i32, y: i32) -> Option<(f32, f32)> {
+        let (fx, fy) = self.unclipped_factors(x, y);
+        if fx < 0.0 || fy < 0.0 || fx >= 1.0 || fy >= 1.0 {
+            return None;
+        }
+        Some((fx, fy))
+    }
+
+    /// Returns the interpolation factors of any pixel center, these are
+    /// used to compute derivatives across neighbouring pixels.
+    fn unclipped_factors(&self, x: i32, y: i32) -> (f32, f32) {
+        let dx = x as f32 + 0.5 - self.pixel[0].0;
+        let dy = y as f32 + 0.5 - self.pixel[0].1;
+        (self.inv[0] * dx + self.inv[2] * dy, self.inv[1] * dx + self.inv[3] * dy)
+    }
+
+    fn local_pos(&self, f: (f32, f32)) -> (f32, f32) {
+        (self.local.p0.0 + self.local.size.0 * f.0, self.local.p0.1 + self.local.size.1 * f.1)
+    }
+
+    fn device_pos(&self, f: (f32, f32)) -> (f32, f32) {
+        (
+            self.device[0].0 + (self.device[1].0 - self.device[0].0) * f.0 + (self.device[2].0 - self.device[0].0) * f.1,
+            self.device[0].1 + (self.device[1].1 - self.device[0].1) * f.0 + (self.device[2].1 - self.device[0].1) * f.1,
+        )
+    }
+}
+
+fn mix(a: Vec4, b: Vec4, f: f32) -> Vec4 {
+    [
+        a[0] + (b[0] - a[0]) * f,
+        a[1] + (b[1] - a[1]) * f,
+        a[2] + (b[2] - a[2]) * f,
+        a[3] + (b[3] - a[3]) * f,
+    ]
+}
+
+fn scale(a: Vec4, f: f32) -> Vec4 {
+    [a[0] * f, a[1] * f, a[2] * f, a[3] * f]
+}
+
+/// GLSL's `mod`, which takes the sign of the divisor.
+fn modulo(a: f32, b: f32) -> f32 {
+    a - b * (a / b).floor()
+}
+
+fn fract(x: f32) -> f32 {
+    x - x.floor()
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).max(0.0).min(1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn compare(fun: Comparison, value: f32, reference: f32) -> bool {
+    match fun {
+        Comparison::Never => false,
+        Comparison::Less => value < reference,
+        Comparison::Equal => value == reference,
+        Comparison::LessEqual => value <= reference,
+        Comparison::Greater => value > reference,
+        Comparison::NotEqual => value != reference,
+        Comparison::GreaterEqual => value >= reference,
+        Comparison::Always => true,
+    }
+}
+
+fn blend_factor(factor: Factor, src: Vec4, src1: Vec4, dst: Vec4, constant: Vec4, channel: usize) -> f32 {
+    match factor {
+        Factor::Zero => 0.0,
+        Factor::One => 1.0,
+        Factor::SrcColor => src[channel],
+        Factor::OneMinusSrcColor => 1.0 - src[channel],
+        Factor::DstColor => dst[channel],
+        Factor::OneMinusDstColor => 1.0 - dst[channel],
+        Factor::SrcAlpha => src[3],
+        Factor::OneMinusSrcAlpha => 1.0 - src[3],
+        Factor::DstAlpha => dst[3],
+        Factor::OneMinusDstAlpha => 1.0 - dst[3],
+        Factor::ConstColor => constant[channel],
+        Factor::OneMinusConstColor => 1.0 - constant[channel],
+        Factor::ConstAlpha => constant[3],
+        Factor::OneMinusConstAlpha => 1.0 - constant[3],
+        Factor::SrcAlphaSaturate => if channel == 3 { 1.0 } else { src[3].min(1.0 - dst[3]) },
+        Factor::Src1Color => src1[channel],
+        Factor::OneMinusSrc1Color => 1.0 - src1[channel],
+        Factor::Src1Alpha => src1[3],
+        Factor::OneMinusSrc1Alpha => 1.0 - src1[3],
+    }
+}
+
+fn blend_channel(op: BlendOp, src: Vec4, src1: Vec4, dst: Vec4, constant: Vec4, channel: usize) -> f32 {
+    match op {
+        BlendOp::Add { src: sf, dst: df } => {
+            src[channel] * blend_factor(sf, src, src1, dst, constant, channel) +
+                dst[channel] * blend_factor(df, src, src1, dst, constant, channel)
+        }
+        BlendOp::Sub { src: sf, dst: df } => {
+            src[channel] * blend_factor(sf, src, src1, dst, constant, channel) -
+                dst[channel] * blend_factor(df, src, src1, dst, constant, channel)
+        }
+        BlendOp::RevSub { src: sf, dst: df } => {
+            dst[channel] * blend_factor(df, src, src1, dst, constant, channel) -
+                src[channel] * blend_factor(sf, src, src1, dst, constant, channel)
+        }
+        BlendOp::Min => src[channel].min(dst[channel]),
+        BlendOp::Max => src[channel].max(dst[channel]),
+    }
+}
+
+fn blend(state: BlendState, src: Vec4, src1: Vec4, dst: Vec4, constant: Vec4) -> Vec4 {
+    match state {
+        BlendState::Off => src,
+        BlendState::On { color, alpha } => [
+            blend_channel(color, src, src1, dst, constant, 0),
+            blend_channel(color, src, src1, dst, constant, 1),
+            blend_channel(color, src, src1, dst, constant, 2),
+            blend_channel(alpha, src, src1, dst, constant, 3),
+        ],
+    }
+}
+
+/// Approximates the anti-aliased coverage of a rounded rectangle,
+/// see `rounded_rect` in cs_clip_rectangle.glsl.
+fn rounded_rect_coverage(pos: (f32, f32), rect: &Rect, radii: &[(f32, f32); 4], aa_range: f32) -> f32 {
+    let p1 = rect.p1();
+    let edge = (pos.0 - rect.p0.0)
+        .min(p1.0 - pos.0)
+        .min(pos.1 - rect.p0.1)
+        .min(p1.1 - pos.1);
+    let mut coverage = (edge / aa_range + 0.5).max(0.0).min(1.0);
+
+    // Top left, top right, bottom right, bottom left.
+    let centers = [
+        (rect.p0.0 + radii[0].0, rect.p0.1 + radii[0].1),
+        (p1.0 - radii[1].0, rect.p0.1 + radii[1].1),
+        (p1.0 - radii[2].0, p1.1 - radii[2].1),
+        (rect.p0.0 + radii[3].0, p1.1 - radii[3].1),
+    ];
+    let signs = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+    for i in 0 .. 4 {
+        let (rx, ry) = radii[i];
+        if rx <= 0.0 || ry <= 0.0 {
+            continue;
+        }
+        let dx = (pos.0 - centers[i].0) * signs[i].0;
+        let dy = (pos.1 - centers[i].1) * signs[i].1;
+        if dx <= 0.0 || dy <= 0.0 {
+            continue;
+        }
+        let nx = dx / rx;
+        let ny = dy / ry;
+        let len = (nx * nx + ny * ny).sqrt();
+        let distance = (len - 1.0) * rx.min(ry);
+        coverage = coverage.min((0.5 - distance / aa_range).max(0.0).min(1.0));
+    }
+
+    coverage
+}
+
+pub struct Device<B: hal::Backend> {
+    // device state
+    programs: FastHashMap<ProgramId, Program>,
+    images: FastHashMap<TextureId, SoftwareImage>,
+    fbos: FastHashMap<FBOId, Framebuffer>,
+    rbos: FastHashMap<RBOId, DepthBuffer>,
+    frame_image: SoftwareImage,
+    frame_depth: DepthBuffer,
+    /// The images of the surfaces which are not bound, the bound one lives
+    /// in `frame_image` and `frame_depth`.
+    surfaces: FastHashMap<SurfaceId, (SoftwareImage, DepthBuffer)>,
+    bound_surface: SurfaceId,
+    next_surface_id: u32,
+    bound_textures: [u32; 16],
+    bound_program: ProgramId,
+    bound_sampler: [TextureFilter; 16],
+    bound_read_fbo: FBOId,
+    bound_draw_fbo: FBOId,
+    program_mode_id: i32,
+    scissor_rect: Option<DeviceIntRect>,
+    viewport: DeviceIntRect,
+    current_blend_state: BlendState,
+    blend_color: ColorF,
+    current_depth_test: DepthTest,
+    depth_function: Comparison,
+
+    device_pixel_ratio: f32,
+    upload_method: UploadMethod,
+
+    // HW or API capabilities
+    capabilities: Capabilities,
+
+    // debug
+    inside_frame: bool,
+
+    // resources
+    _resource_override_path: Option<PathBuf>,
+
+    max_texture_size: u32,
+    renderer_name: String,
+
+    // Frame counter. This is used to map between CPU
+    // frames and GPU frames.
+    frame_id: FrameId,
+
+    // Supported features
+    features: hal::Features,
+
+    next_resource_id: u32,
+    _backend: PhantomData<B>,
+}
+
+impl<B: hal::Backend> Device<B> {
+    pub fn new(
+        resource_override_path: Option<PathBuf>,
+        upload_method: UploadMethod,
+        file_changed_handler: Box<FileWatcherHandler>,
+        cached_programs: Option<Rc<ProgramCache>>,
+        _adapter: &hal::Adapter<B>,
+        _surface: Option<&mut <B as hal::Backend>::Surface>,
+        window_size: (u32, u32),
+        _msaa_samples: u8,
+    ) -> Self {
+        Device::new_offscreen(
+            resource_override_path,
+            upload_method,
+            file_changed_handler,
+            cached_programs,
+            window_size,
+        )
+    }
+
+    /// Creates a device drawing into an image of `window_size` in memory,
+    /// the adapter and the surface `new` takes are never used.
+    pub fn new_offscreen(
+        resource_override_path: Option<PathBuf>,
+        upload_method: UploadMethod,
+        _file_changed_handler: Box<FileWatcherHandler>,
+        _cached_programs: Option<Rc<ProgramCache>>,
+        window_size: (u32, u32),
+    ) -> Self {
+        let (width, height) = window_size;
+
+        Device {
+            programs: FastHashMap::default(),
+            images: FastHashMap::default(),
+            fbos: FastHashMap::default(),
+            rbos: FastHashMap::default(),
+            frame_image: SoftwareImage::new(ImageFormat::BGRA8, width, height, 1),
+            frame_depth: DepthBuffer::new(width, height),
+            surfaces: FastHashMap::default(),
+            bound_surface: MAIN_SURFACE,
+            next_surface_id: MAIN_SURFACE.0 + 1,
+            bound_textures: [0; 16],
+            bound_program: INVALID_PROGRAM_ID,
+            bound_sampler: [TextureFilter::Linear; 16],
+            bound_read_fbo: DEFAULT_READ_FBO,
+            bound_draw_fbo: DEFAULT_DRAW_FBO,
+            program_mode_id: 0,
+            scissor_rect: None,
+            viewport: DeviceIntRect::new(
+                DeviceIntPoint::zero(),
+                DeviceIntSize::new(width as i32, height as i32),
+            ),
+            current_blend_state: BlendState::Off,
+            blend_color: ColorF::new(0.0, 0.0, 0.0, 0.0),
+            current_depth_test: DepthTest::Off,
+            depth_function: Comparison::LessEqual,
+
+            device_pixel_ratio: 1.0,
+            upload_method,
+
+            capabilities: Capabilities {
+                supports_multisampling: false,
+                supports_dual_source_blending: false,
+                max_texture_size: MAX_TEXTURE_SIZE,
+                max_texture_layers: MAX_TEXTURE_LAYERS,
+                max_samples: 1,
+            },
+
+            inside_frame: false,
+
+            _resource_override_path: resource_override_path,
+
+            max_texture_size: MAX_TEXTURE_SIZE,
+            renderer_name: "Software".to_owned(),
+            frame_id: FrameId(0),
+            features: hal::Features::empty(),
+
+            next_resource_id: 1,
+            _backend: PhantomData,
+        }
+    }
+
+    pub fn set_device_pixel_ratio(&mut self, ratio: f32) {
+        self.device_pixel_ratio = ratio;
+    }
+
+    pub fn update_program_cache(&mut self, _cached_programs: Rc<ProgramCache>) {
+        // Nothing to cache, the shaders are emulated on the CPU.
+    }
+
+    pub fn max_texture_size(&self) -> u32 {
+        self.max_texture_size
+    }
+
+    /// Multisampling is not emulated, every target has a single sample.
+    pub fn msaa_samples(&self) -> u8 {
+        1
+    }
+
+    /// Adds an image standing in for another window, nothing is presented.
+    pub fn add_surface(
+        &mut self,
+        _adapter: &hal::Adapter<B>,
+        _surface: &mut B::Surface,
+        window_size: (u32, u32),
+    ) -> SurfaceId {
+        let (width, height) = window_size;
+        let id = SurfaceId(self.next_surface_id);
+        self.next_surface_id += 1;
+        self.surfaces.insert(
+            id,
+            (SoftwareImage::new(ImageFormat::BGRA8, width, height, 1), DepthBuffer::new(width, height)),
+        );
+        id
+    }
+
+    pub fn remove_surface(&mut self, id: SurfaceId) {
+        assert_ne!(id, MAIN_SURFACE, "The main surface can't be removed");
+        if self.bound_surface == id {
+            self.bind_surface(MAIN_SURFACE);
+        }
+        self.surfaces.remove(&id);
+    }
+
+    /// Reallocates the image standing in for an added window, for a new
+    /// window size.
+    pub fn resize_surface(
+        &mut self,
+        id: SurfaceId,
+        _adapter: &hal::Adapter<B>,
+        _surface: &mut B::Surface,
+        window_size: (u32, u32),
+    ) {
+        assert_ne!(id, MAIN_SURFACE, "Only added surfaces can be resized");
+        let (width, height) = window_size;
+        let image = SoftwareImage::new(ImageFormat::BGRA8, width, height, 1);
+        let depth = DepthBuffer::new(width, height);
+        if self.bound_surface == id {
+            self.frame_image = image;
+            self.frame_depth = depth;
+        } else {
+            assert!(self.surfaces.contains_key(&id), "Unknown surface");
+            self.surfaces.insert(id, (image, depth));
+        }
+    }
+
+    /// Reallocates the image of the main surface for a new window size.
+    pub fn resize_offscreen_surface(&mut self, window_size: (u32, u32)) {
+        let (width, height) = window_size;
+        let image = SoftwareImage::new(ImageFormat::BGRA8, width, height, 1);
+        let depth = DepthBuffer::new(width, height);
+        if self.bound_surface == MAIN_SURFACE {
+            self.frame_image = image;
+            self.frame_depth = depth;
+        } else {
+            self.surfaces.insert(MAIN_SURFACE, (image, depth));
+        }
+    }
+
+    /// Makes the default framebuffer refer to the given surface.
+    pub fn bind_surface(&mut self, id: SurfaceId) {
+        if self.bound_surface == id {
+            return;
+        }
+        let (image, depth) = self.surfaces.remove(&id).expect("Unknown surface");
+        let image = mem::replace(&mut self.frame_image, image);
+        let depth = mem::replace(&mut self.frame_depth, depth);
+        self.surfaces.insert(self.bound_surface, (image, depth));
+        self.bound_surface = id;
+    }
+
+    pub fn surface_size(&self, id: SurfaceId) -> DeviceUintSize {
+        let image = match self.surfaces.get(&id) {
+            Some(&(ref image, _)) => image,
+            None => &self.frame_image,
+        };
+        DeviceUintSize::new(image.width, image.height)
+    }
+
+    pub fn get_capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    pub fn renderer_name(&self) -> &str {
+        &self.renderer_name
+    }
+
+    pub fn reset_state(&mut self) {
+        self.bound_textures = [0; 16];
+        self.bound_program = INVALID_PROGRAM_ID;
+        self.bound_sampler = [TextureFilter::Linear; 16];
+        self.bound_read_fbo = DEFAULT_READ_FBO;
+        self.bound_draw_fbo = DEFAULT_DRAW_FBO;
+    }
+
+    pub fn delete_program(&mut self, mut _program: ProgramId) {
+        self.programs.remove(&_program);
+        _program = INVALID_PROGRAM_ID;
+    }
+
+    /// Fails for the shaders the software device can't emulate, so that
+    /// the renderer reports them instead of silently dropping the batches.
+    pub(crate) fn create_program(
+        &mut self,
+        _pipeline_requirements: PipelineRequirements,
+        shader_name: &str,
+        shader_kind: &ShaderKind,
+    ) -> Result<ProgramId, ShaderError> {
+        let kind = match ProgramKind::new(shader_name, shader_kind) {
+            Some(kind) => kind,
+            None => {
+                return Err(ShaderError::Link(
+                    shader_name.to_owned(),
+                    "The software device can't rasterize this shader".to_owned(),
+                ));
+            }
+        };
+        let id = ProgramId(self.generate_id());
+        self.programs.insert(id, Program {
+            shader_name: shader_name.to_owned(),
+            kind,
+            transform: Mat4::identity(),
+            instances: Vec::new(),
+        });
+        Ok(id)
+    }
+
+    pub fn bind_program(&mut self, program_id: &ProgramId) {
+        debug_assert!(self.inside_frame);
+
+        if self.bound_program != *program_id {
+            self.bound_program = *program_id;
+        }
+    }
+
+    pub fn bind_textures(&mut self) {
+        debug_assert!(self.inside_frame);
+        assert_ne!(self.bound_program, INVALID_PROGRAM_ID);
+        // Textures are read straight from the bound slots at draw time.
+    }
+
+    #[cfg(feature = "debug_renderer")]
+    pub fn update_indices<I: Copy>(&mut self, _indices: &[I]) {
+        debug_assert!(self.inside_frame);
+        assert_ne!(self.bound_program, INVALID_PROGRAM_ID);
+    }
+
+    #[cfg(feature = "debug_renderer")]
+    pub fn update_vertices<T: Copy>(&mut self, _vertices: &[T]) {
+        debug_assert!(self.inside_frame);
+        assert_ne!(self.bound_program, INVALID_PROGRAM_ID);
+    }
+
+    pub fn set_uniforms(
+        &mut self,
+        program: &ProgramId,
+        transform: &Transform3D<f32>,
+    ) {
+        debug_assert!(self.inside_frame);
+        assert_ne!(self.bound_program, INVALID_PROGRAM_ID);
+        assert_eq!(*program, self.bound_program);
+        let program = self.programs.get_mut(&self.bound_program).expect("Program not found.");
+        program.transform = Mat4(transform.to_row_arrays());
+    }
+
+    fn update_instances<T: Copy>(
+        &mut self,
+        instances: &[T],
+    ) {
+        assert_ne!(self.bound_program, INVALID_PROGRAM_ID);
+        assert_eq!(mem::size_of::<T>() % mem::size_of::<u32>(), 0);
+        assert!(mem::align_of::<T>() <= mem::align_of::<u32>());
+        let words = unsafe {
+            slice::from_raw_parts(
+                instances.as_ptr() as *const u32,
+                instances.len() * mem::size_of::<T>() / mem::size_of::<u32>(),
+            )
+        };
+        let program = self.programs.get_mut(&self.bound_program).expect("Program not found.");
+        program.instances.clear();
+        program.instances.extend_from_slice(words);
+    }
+
+    fn draw(&mut self) {
+        let kind = self.programs[&self.bound_program].kind;
+        match kind {
+            ProgramKind::BrushSolid |
+            ProgramKind::BrushImage |
+            ProgramKind::BrushBlend |
+            ProgramKind::BrushMixBlend |
+            ProgramKind::BrushLinearGradient |
+            ProgramKind::BrushRadialGradient |
+            ProgramKind::BrushConicGradient |
+            ProgramKind::BrushYuvImage(..) => self.draw_brushes(kind),
+            ProgramKind::TextRun(text_kind) => self.draw_text_runs(text_kind),
+            ProgramKind::SplitComposite => self.draw_split_composites(),
+            ProgramKind::Blur => self.draw_blurs(),
+            ProgramKind::Border => self.draw_border_segments(),
+            ProgramKind::Path => self.draw_paths(),
+            ProgramKind::SvgFilter => self.draw_svg_filters(),
+            ProgramKind::ClipRectangle |
+            ProgramKind::ClipBoxShadow |
+            ProgramKind::ClipLine |
+            ProgramKind::ClipImage |
+            ProgramKind::ClipPath => self.draw_clips(kind),
+            ProgramKind::Debug => {}
+        }
+    }
+
+    fn draw_state(&self, program: &Program) -> DrawState {
+        let target_rect = match self.fbos.get(&self.bound_draw_fbo) {
+            Some(fbo) => self.images[&fbo.texture].rect(),
+            None => self.frame_image.rect(),
+        };
+        let scissor = self.scissor_rect
+            .and_then(|rect| rect.intersection(&target_rect))
+            .unwrap_or(if self.scissor_rect.is_some() { DeviceIntRect::zero() } else { target_rect });
+        let blend_color = self.blend_color;
+        DrawState {
+            transform: program.transform,
+            viewport: self.viewport,
+            scissor,
+            blend: self.current_blend_state,
+            blend_color: [blend_color.r, blend_color.g, blend_color.b, blend_color.a],
+            depth: self.current_depth_test,
+            device_pixel_ratio: self.device_pixel_ratio,
+            mode: self.program_mode_id,
+            alpha_pass: program.shader_name.contains("alpha_pass"),
+        }
+    }
+
+    /// Runs `f` with the current render target and the bound shader inputs.
+    fn with_target<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut Target, &ShaderData, &ShaderTextures, &Program, &DrawState),
+    {
+        let (draw_texture, layer, rbo) = match self.fbos.get(&self.bound_draw_fbo) {
+            Some(fbo) => (Some(fbo.texture), fbo.layer_index, fbo.rbo),
+            None => (None, 0, None),
+        };
+        let state = self.draw_state(&self.programs[&self.bound_program]);
+
+        // Take the draw target out of the image map, so that the remaining
+        // images can be borrowed as shader inputs while it is written to.
+        let mut image = match draw_texture {
+            Some(id) => match self.images.remove(&id) {
+                Some(image) => image,
+                None => return,
+            },
+            None => mem::replace(&mut self.frame_image, SoftwareImage::new(ImageFormat::BGRA8, 0, 0, 1)),
+        };
+
+        {
+            let images = &self.images;
+            let bound = &self.bound_textures;
+            let lookup = |slot: usize| {
+                if bound[slot] == INVALID_TEXTURE_ID {
+                    None
+                } else {
+                    images.get(&bound[slot])
+                }
+            };
+            let data = ShaderData {
+                resource_cache: lookup(SLOT_RESOURCE_CACHE),
+                render_tasks: lookup(SLOT_RENDER_TASKS),
+                clip_scroll_nodes: lookup(SLOT_CLIP_SCROLL_NODES),
+                local_clip_rects: lookup(SLOT_LOCAL_CLIP_RECTS),
+            };
+            let textures = ShaderTextures {
+                color0: lookup(SLOT_COLOR0).map(|image| (image, self.bound_sampler[SLOT_COLOR0])),
+                color1: lookup(SLOT_COLOR1).map(|image| (image, self.bound_sampler[SLOT_COLOR1])),
+                color2: lookup(SLOT_COLOR2).map(|image| (image, self.bound_sampler[SLOT_COLOR2])),
+                cache_a8: lookup(SLOT_CACHE_A8),
+                cache_rgba8: lookup(SLOT_CACHE_RGBA8),
+            };
+            let depth = match rbo {
+                Some(rbo) => self.rbos.get_mut(&rbo),
+                None if draw_texture.is_none() => Some(&mut self.frame_depth),
+                None => None,
+            };
+            let mut target = Target {
+                image: &mut image,
+                layer,
+                depth,
+            };
+            f(&mut target, &data, &textures, &self.programs[&self.bound_program], &state);
+        }
+
+        match draw_texture {
+            Some(id) => {
+                self.images.insert(id, image);
+            }
+            None => self.frame_image = image,
+        }
+    }
+
+    fn draw_brushes(&mut self, kind: ProgramKind) {
+        self.with_target(|target, data, textures, program, state| {
+            for instance in program.instances::<PrimitiveInstance>() {
+                draw_brush(kind, instance, target, data, textures, state);
+            }
+        });
+    }
+
+    fn draw_blurs(&mut self) {
+        self.with_target(|target, data, textures, program, state| {
+            let color_target = program.shader_name.contains("color_target");
+            let source = if color_target { textures.cache_rgba8 } else { textures.cache_a8 };
+            let source = match source {
+                Some(source) => source,
+                None => return,
+            };
+            for instance in program.instances::<BlurInstance>() {
+                draw_blur(instance, source, target, data, state);
+            }
+        });
+    }
+
+    fn draw_split_composites(&mut self) {
+        self.with_target(|target, data, textures, program, state| {
+            let source = match textures.cache_rgba8 {
+                Some(source) => source,
+                None => return,
+            };
+            for instance in program.instances::<PrimitiveInstance>() {
+                draw_split_composite(instance, source, target, data, state);
+            }
+        });
+    }
+
+    fn draw_paths(&mut self) {
+        self.with_target(|target, data, _, program, state| {
+            for instance in program.instances::<PathInstance>() {
+                draw_path(instance, target, data, state);
+            }
+        });
+    }
+
+    fn draw_svg_filters(&mut self) {
+        self.with_target(|target, data, textures, program, state| {
+            for instance in program.instances::<SvgFilterInstance>() {
+                draw_svg_filter(instance, target, data, textures, state);
+            }
+        });
+    }
+
+    fn draw_text_runs(&mut self, kind: TextRunKind) {
+        self.with_target(|target, data, textures, program, state| {
+            for instance in program.instances::<PrimitiveInstance>() {
+                draw_text_run(kind, instance, target, data, textures, state);
+            }
+        });
+    }
+
+    fn draw_border_segments(&mut self) {
+        self.with_target(|target, _, _, program, state| {
+            for instance in program.instances::<BorderInstance>() {
+                draw_border_segment(instance, target, state);
+            }
+        });
+    }
+
+    fn draw_clips(&mut self, kind: ProgramKind) {
+        self.with_target(|target, data, textures, program, state| {
+            for instance in program.instances::<ClipMaskInstance>() {
+                match kind {
+                    ProgramKind::ClipRectangle => draw_clip_rectangle(instance, target, data, state),
+                    ProgramKind::ClipBoxShadow => draw_clip_box_shadow(instance, target, data, textures, state),
+                    ProgramKind::ClipLine => draw_clip_line(instance, target, data, state),
+                    ProgramKind::ClipImage => draw_clip_image(instance, target, data, textures, state),
+                    ProgramKind::ClipPath => draw_clip_path(instance, target, data, state),
+                    _ => unreachable!(),
+                }
+            }
+        });
+    }
+
+    pub fn begin_frame(&mut self) -> FrameId {
+        debug_assert!(!self.inside_frame);
+        self.inside_frame = true;
+
+        self.bound_textures = [0; 16];
+        self.bound_sampler = [TextureFilter::Linear; 16];
+        self.bound_read_fbo = DEFAULT_READ_FBO;
+        self.bound_draw_fbo = DEFAULT_DRAW_FBO;
+        self.program_mode_id = 0;
+
+        self.frame_id
+    }
+
+    fn bind_texture_impl(&mut self, slot: TextureSlot, id: u32, sampler: TextureFilter) {
+        debug_assert!(self.inside_frame);
+
+        if self.bound_textures[slot.0] != id {
+            self.bound_textures[slot.0] = id;
+            self.bound_sampler[slot.0] = sampler;
+        }
+    }
+
+    pub fn bind_texture<S>(&mut self, sampler: S, texture: &Texture)
+        where
+            S: Into<TextureSlot>,
+    {
+        self.bind_texture_impl(sampler.into(), texture.id, texture.filter);
+        texture.bound_in_frame.set(self.frame_id);
+    }
+
+    pub fn bind_external_texture<S>(&mut self, sampler: S, external_texture: &ExternalTexture)
+        where
+            S: Into<TextureSlot>,
+    {
+        self.bind_texture_impl(sampler.into(), external_texture.id, TextureFilter::Linear);
+    }
+
+    pub fn bind_read_target_impl(&mut self, fbo_id: FBOId) {
+        debug_assert!(self.inside_frame);
+
+        if self.bound_read_fbo != fbo_id {
+            self.bound_read_fbo = fbo_id;
+        }
+    }
+
+    pub fn bind_read_target(&mut self, texture_and_layer: Option<(&Texture, i32)>) {
+        let fbo_id = texture_and_layer.map_or(DEFAULT_READ_FBO, |texture_and_layer| {
+            texture_and_layer.0.fbo_ids[texture_and_layer.1 as usize]
+        });
+
+        self.bind_read_target_impl(fbo_id)
+    }
+
+    fn bind_draw_target_impl(&mut self, fbo_id: FBOId) {
+        debug_assert!(self.inside_frame);
+
+        if self.bound_draw_fbo != fbo_id {
+            self.bound_draw_fbo = fbo_id;
+        }
+    }
+
+    pub fn bind_draw_target(
+        &mut self,
+        texture_and_layer: Option<(&Texture, i32)>,
+        dimensions: Option<DeviceUintSize>,
+    ) {
+        let fbo_id = texture_and_layer.map_or(DEFAULT_DRAW_FBO, |texture_and_layer| {
+            texture_and_layer.0.fbo_ids[texture_and_layer.1 as usize]
+        });
+
+        self.bind_draw_target_impl(fbo_id);
+
+        if let Some(dimensions) = dimensions {
+            self.viewport = DeviceIntRect::new(
+                DeviceIntPoint::zero(),
+                DeviceIntSize::new(dimensions.width as i32, dimensions.height as i32),
+            );
+        }
+    }
+
+    pub fn create_fbo_for_external_texture(&mut self, _texture_id: u32) -> FBOId {
+        warn!("External texture creation is missing");
+        FBOId(0)
+    }
+
+    pub fn delete_fbo(&mut self, fbo: FBOId) {
+        self.fbos.remove(&fbo);
+    }
+
+    pub fn bind_external_draw_target(&mut self, fbo_id: FBOId) {
+        debug_assert!(self.inside_frame);
+
+        if self.bound_draw_fbo != fbo_id {
+            self.bound_draw_fbo = fbo_id;
+        }
+    }
+
+    fn generate_id(&mut self) -> u32 {
+        // Ids below this are reserved for the default read and draw targets.
+        let id = self.next_resource_id.max(DEFAULT_DRAW_FBO.0 + 1);
+        self.next_resource_id = id + 1;
+        id
+    }
+
+    pub fn create_texture(
+        &mut self,
+        target: TextureTarget,
+        format: ImageFormat,
+    ) -> Texture {
+        Texture {
+            id: 0,
+            _target: target,
+            width: 0,
+            height: 0,
+            layer_count: 0,
+            format,
+            filter: TextureFilter::Nearest,
+            render_target: None,
+            fbo_ids: vec![],
+            depth_rb: None,
+            last_frame_used: self.frame_id,
+            bound_in_frame: Cell::new(FrameId(0)),
+        }
+    }
+
+    /// Resizes a texture with enabled render target views,
+    /// preserves the data by blitting the old texture contents over.
+    pub fn resize_renderable_texture(
+        &mut self,
+        texture: &mut Texture,
+        new_size: DeviceUintSize,
+    ) {
+        debug_assert!(self.inside_frame);
+
+        let old_image = self.images.remove(&texture.id);
+        let rt_info = texture.render_target.unwrap_or(RenderTargetInfo { has_depth: false, samples: 1 });
+        let (filter, layer_count) = (texture.filter, texture.layer_count);
+        self.init_texture::<u8>(texture, new_size.width, new_size.height, filter, Some(rt_info), layer_count, None);
+
+        if let Some(old_image) = old_image {
+            let image = self.images.get_mut(&texture.id).expect("Texture not found.");
+            let bpp = image.format.bytes_per_pixel() as usize;
+            let row_size = old_image.width.min(image.width) as usize * bpp;
+            let (old_stride, new_stride) = (old_image.stride(), image.stride());
+            for (old_layer, layer) in old_image.layers.iter().zip(image.layers.iter_mut()) {
+                for y in 0 .. old_image.height.min(image.height) as usize {
+                    layer[y * new_stride .. y * new_stride + row_size]
+                        .copy_from_slice(&old_layer[y * old_stride .. y * old_stride + row_size]);
+                }
+            }
+        }
+    }
+
+    pub fn init_texture<T: Texel>(
+        &mut self,
+        texture: &mut Texture,
+        mut width: u32,
+        mut height: u32,
+        filter: TextureFilter,
+        render_target: Option<RenderTargetInfo>,
+        layer_count: i32,
+        pixels: Option<&[T]>,
+    ) {
+        debug_assert!(self.inside_frame);
+
+        if width > self.max_texture_size || height > self.max_texture_size {
+            error!("Attempting to allocate a texture of size {}x{} above the limit, trimming", width, height);
+            width = width.min(self.max_texture_size);
+            height = height.min(self.max_texture_size);
+        }
+
+        assert!(
+            render_target.is_none() || texture.format != ImageFormat::RGBAI32,
+            "RGBAI32 textures can't be used as render targets"
+        );
+
+        if texture.id == 0 {
+            texture.id = self.generate_id();
+        } else {
+            self.free_image(texture);
+        }
+
+        texture.width = width;
+        texture.height = height;
+        texture.filter = filter;
+        texture.layer_count = layer_count;
+        texture.render_target = render_target;
+        texture.last_frame_used = self.frame_id;
+        texture.bound_in_frame.set(self.frame_id);
+
+        let mut image = SoftwareImage::new(texture.format, width, height, layer_count);
+
+        if let Some(rt_info) = render_target {
+            if rt_info.has_depth {
+                let rbo = RBOId(self.generate_id());
+                self.rbos.insert(rbo, DepthBuffer::new(width, height));
+                texture.depth_rb = Some(rbo);
+            }
+
+            for layer_index in 0 .. layer_count.max(0) as usize {
+                let fbo_id = FBOId(self.generate_id());
+                self.fbos.insert(fbo_id, Framebuffer {
+                    texture: texture.id,
+                    layer_index,
+                    rbo: texture.depth_rb,
+                });
+                texture.fbo_ids.push(fbo_id);
+            }
+        }
+
+        if let Some(data) = pixels {
+            let data = texels_to_u8_slice(data);
+            for (layer, chunk) in image.layers.iter_mut().zip(data.chunks(data.len().max(1))) {
+                let len = layer.len().min(chunk.len());
+                layer[.. len].copy_from_slice(&chunk[.. len]);
+            }
+        }
+
+        self.images.insert(texture.id, image);
+    }
+
+    fn read_target(&self) -> (&SoftwareImage, usize) {
+        match self.fbos.get(&self.bound_read_fbo) {
+            Some(fbo) => (&self.images[&fbo.texture], fbo.layer_index),
+            None => (&self.frame_image, 0),
+        }
+    }
+
+    pub fn blit_render_target(&mut self, src_rect: DeviceIntRect, dest_rect: DeviceIntRect) {
+        debug_assert!(self.inside_frame);
+
+        if src_rect.size.width <= 0 || src_rect.size.height <= 0 {
+            return;
+        }
+
+        let texels = {
+            let (src, src_layer) = self.read_target();
+            let mut texels = Vec::with_capacity((dest_rect.size.width * dest_rect.size.height).max(0) as usize);
+            for y in 0 .. dest_rect.size.height {
+                let sy = src_rect.origin.y + y * src_rect.size.height / dest_rect.size.height;
+                for x in 0 .. dest_rect.size.width {
+                    let sx = src_rect.origin.x + x * src_rect.size.width / dest_rect.size.width;
+                    texels.push(src.fetch(src_layer, sx, sy));
+                }
+            }
+            texels
+        };
+
+        let (dest, dest_layer) = match self.fbos.get(&self.bound_draw_fbo) {
+            Some(fbo) => match self.images.get_mut(&fbo.texture) {
+                Some(image) => (image, fbo.layer_index),
+                None => return,
+            },
+            None => (&mut self.frame_image, 0),
+        };
+        let mut texels = texels.into_iter();
+        for y in 0 .. dest_rect.size.height {
+            for x in 0 .. dest_rect.size.width {
+                let texel = texels.next().unwrap();
+                dest.store(dest_layer, dest_rect.origin.x + x, dest_rect.origin.y + y, texel);
+            }
+        }
+    }
+
+    pub fn free_texture_storage(&mut self, texture: &mut Texture) {
+        debug_assert!(self.inside_frame);
+        if texture.width + texture.height == 0 {
+            return;
+        }
+
+        self.free_image(texture);
+
+        texture.width = 0;
+        texture.height = 0;
+        texture.layer_count = 0;
+        texture.id = 0;
+    }
+
+    pub fn free_image(&mut self, texture: &mut Texture) {
+        if let Some(depth_rb) = texture.depth_rb.take() {
+            self.rbos.remove(&depth_rb);
+        }
+
+        for old in texture.fbo_ids.drain(..) {
+            self.fbos.remove(&old);
+        }
+
+        self.images.remove(&texture.id);
+    }
+
+    pub fn delete_texture(&mut self, mut texture: Texture) {
+        self.free_texture_storage(&mut texture);
+    }
+
+    #[cfg(feature = "replay")]
+    pub fn delete_external_texture(&mut self, mut external: ExternalTexture) {
+        external.id = 0;
+    }
+
+    pub fn switch_mode(&mut self, mode: i32) {
+        debug_assert!(self.inside_frame);
+        self.program_mode_id = mode;
+    }
+
+    pub fn create_pbo(&mut self) -> PBO {
+        PBO { }
+    }
+
+    pub fn delete_pbo(&mut self, _pbo: PBO) {
+    }
+
+    pub fn upload_texture<'a>(
+        &'a mut self,
+        texture: &'a Texture,
+        _pbo: &PBO,
+        _upload_count: usize,
+    ) -> TextureUploader<'a, B> {
+        debug_assert!(self.inside_frame);
+
+        // Both methods end up as a plain memory copy.
+        match self.upload_method {
+            UploadMethod::Immediate | UploadMethod::PixelBuffer => {
+                TextureUploader {
+                    device: self,
+                    texture,
+                }
+            }
+        }
+    }
+
+    #[cfg(any(feature = "debug_renderer", feature = "capture"))]
+    pub fn read_pixels(&mut self, img_desc: &ImageDescriptor) -> Vec<u8> {
+        let mut pixels = vec![0; (img_desc.size.width * img_desc.size.height * 4) as usize];
+        self.read_pixels_into(DeviceUintRect::new(DeviceUintPoint::zero(), DeviceUintSize::new(img_desc.size.width, img_desc.size.height)), ReadPixelsFormat::Rgba8, &mut pixels);
+        pixels
+    }
+
+    /// Read rectangle of pixels into the specified output slice.
+    pub fn read_pixels_into(
+        &mut self,
+        rect: DeviceUintRect,
+        format: ReadPixelsFormat,
+        output: &mut [u8],
+    ) {
+        let bytes_per_pixel = match format {
+            ReadPixelsFormat::Standard(imf) => imf.bytes_per_pixel(),
+            ReadPixelsFormat::Rgba8 => 4,
+        } as usize;
+        let size_in_bytes = bytes_per_pixel * (rect.size.width * rect.size.height) as usize;
+        assert_eq!(output.len(), size_in_bytes);
+
+        let (image, layer) = self.read_target();
+        let mut offset = 0;
+        for y in rect.origin.y .. rect.max_y() {
+            for x in rect.origin.x .. rect.max_x() {
+                let texel = image.fetch(layer, x as i32, y as i32);
+                let dest = &mut output[offset .. offset + bytes_per_pixel];
+                match format {
+                    ReadPixelsFormat::Standard(imf) => encode_texel(imf, texel, dest),
+                    ReadPixelsFormat::Rgba8 => {
+                        for i in 0 .. 4 {
+                            dest[i] = to_unorm8(texel[i]);
+                        }
+                    }
+                }
+                offset += bytes_per_pixel;
+            }
+        }
+    }
+
+    /// Get texels of a texture into the specified output slice.
+    #[cfg(feature = "debug_renderer")]
+    pub fn get_tex_image_into(
+        &mut self,
+        texture: &Texture,
+        format: ImageFormat,
+        output: &mut [u8],
+    ) {
+        let image = &self.images[&texture.id];
+        if image.format == format {
+            let mut offset = 0;
+            for layer in &image.layers {
+                let len = layer.len().min(output.len() - offset);
+                output[offset .. offset + len].copy_from_slice(&layer[.. len]);
+                offset += len;
+            }
+        } else {
+            let bpp = format.bytes_per_pixel() as usize;
+            let mut chunks = output.chunks_mut(bpp);
+            for layer in 0 .. image.layers.len() {
+                for y in 0 .. image.height as i32 {
+                    for x in 0 .. image.width as i32 {
+                        match chunks.next() {
+                            Some(chunk) => encode_texel(format, image.fetch(layer, x, y), chunk),
+                            None => return,
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Attaches the provided texture to the current Read FBO binding.
+    #[cfg(any(feature = "debug_renderer", feature="capture"))]
+    fn attach_read_texture_raw(
+        &mut self, texture_id: u32, _target: TextureTarget, layer_id: i32
+    ) {
+        let fbo_id = self.fbos
+            .iter()
+            .find(|&(_, fbo)| fbo.texture == texture_id && fbo.layer_index == layer_id as usize)
+            .map(|(id, _)| *id);
+        let fbo_id = match fbo_id {
+            Some(id) => id,
+            None => {
+                let id = FBOId(self.generate_id());
+                self.fbos.insert(id, Framebuffer {
+                    texture: texture_id,
+                    layer_index: layer_id as usize,
+                    rbo: None,
+                });
+                id
+            }
+        };
+        self.bind_read_target_impl(fbo_id);
+    }
+
+    #[cfg(any(feature = "debug_renderer", feature="capture"))]
+    pub fn attach_read_texture_external(
+        &mut self, texture_id: u32, target: TextureTarget, layer_id: i32
+    ) {
+        self.attach_read_texture_raw(texture_id, target, layer_id)
+    }
+
+    #[cfg(any(feature = "debug_renderer", feature="capture"))]
+    pub fn attach_read_texture(&mut self, texture: &Texture, layer_id: i32) {
+        self.attach_read_texture_raw(texture.id, texture._target, layer_id)
+    }
+
+    pub fn bind_vao(&mut self, _vao: &VAO) { }
+
+    pub fn create_vao(&mut self, _descriptor: &VertexDescriptor) -> VAO {
+        VAO { }
+    }
+
+    pub fn delete_vao(&mut self, _vao: VAO) { }
+
+    pub fn create_vao_with_new_instances(
+        &mut self,
+        _descriptor: &VertexDescriptor,
+        _base_vao: &VAO,
+    ) -> VAO {
+        VAO { }
+    }
+
+    pub fn update_vao_main_vertices<V: Copy>(
+        &mut self,
+        _vao: &VAO,
+        _vertices: &[V],
+        _usage_hint: VertexUsageHint,
+    ) {
+        if self.bound_program != INVALID_PROGRAM_ID {
+            #[cfg(feature = "debug_renderer")]
+            self.update_vertices(_vertices);
+        }
+    }
+
+    pub fn update_vao_instances<V: PrimitiveType>(
+        &mut self,
+        _vao: &VAO,
+        instances: &[V],
+        _usage_hint: VertexUsageHint,
+    ) {
+        let data = instances.iter().map(|pi| pi.to_primitive_type()).collect::<Vec<V::Primitive>>();
+        self.update_instances(&data);
+    }
+
+    pub fn update_vao_indices<I: Copy>(
+        &mut self,
+        _vao: &VAO,
+        _indices: &[I],
+        _usage_hint: VertexUsageHint
+    ) {
+        if self.bound_program != INVALID_PROGRAM_ID {
+            #[cfg(feature = "debug_renderer")]
+            self.update_indices(_indices);
+        }
+    }
+
+    pub fn draw_triangles_u16(&mut self, _first_vertex: i32, _index_count: i32) {
+        debug_assert!(self.inside_frame);
+        self.draw();
+    }
+
+    #[cfg(feature = "debug_renderer")]
+    pub fn draw_triangles_u32(&mut self, _first_vertex: i32, _index_count: i32) {
+        debug_assert!(self.inside_frame);
+        self.draw();
+    }
+
+    #[cfg(feature = "debug_renderer")]
+    pub fn draw_nonindexed_lines(&mut self, _first_vertex: i32, _vertex_count: i32) {
+        debug_assert!(self.inside_frame);
+        self.draw();
+    }
+
+    pub fn draw_indexed_triangles_instanced_u16(&mut self, _index_count: i32, _instance_count: i32) {
+        debug_assert!(self.inside_frame);
+        self.draw();
+    }
+
+    pub fn end_frame(&mut self) {
+        self.bind_draw_target(None, None);
+        self.bind_read_target(None);
+
+        debug_assert!(self.inside_frame);
+        self.inside_frame = false;
+
+        self.frame_id.0 += 1;
+    }
+
+    pub fn clear_target(
+        &mut self,
+        color: Option<[f32; 4]>,
+        depth: Option<f32>,
+        rect: Option<DeviceIntRect>,
+    ) {
+        let (image, layer, rbo) = match self.fbos.get(&self.bound_draw_fbo) {
+            Some(fbo) => match self.images.get_mut(&fbo.texture) {
+                Some(image) => (image, fbo.layer_index, fbo.rbo.and_then(|rbo| self.rbos.get_mut(&rbo))),
+                None => return,
+            },
+            None => (&mut self.frame_image, 0, Some(&mut self.frame_depth)),
+        };
+        let rect = rect.unwrap_or(image.rect());
+
+        if let Some(color) = color {
+            image.fill(layer, rect, color);
+        }
+
+        if let (Some(depth), Some(depth_buffer)) = (depth, rbo) {
+            if let Some(rect) = rect.intersection(&image.rect()) {
+                for y in rect.origin.y .. rect.max_y() {
+                    let start = depth_buffer.index(rect.origin.x, y);
+                    for value in &mut depth_buffer.data[start .. start + rect.size.width as usize] {
+                        *value = depth;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn enable_depth(&mut self) {
+        self.current_depth_test = DepthTest::On {
+            fun: self.depth_function,
+            write: false,
+        };
+    }
+
+    pub fn disable_depth(&mut self) {
+        self.current_depth_test = DepthTest::Off;
+    }
+
+    pub fn set_depth_func(&mut self, depth_func: DepthFunction) {
+        self.depth_function = depth_func.comparison();
+        if let DepthTest::On { ref mut fun, .. } = self.current_depth_test {
+            *fun = self.depth_function;
+        }
+    }
+
+    pub fn enable_depth_write(&mut self) {
+        self.current_depth_test = DepthTest::On {
+            fun: self.depth_function,
+            write: true,
+        };
+    }
+
+    pub fn disable_depth_write(&mut self) {
+        if let DepthTest::On { ref mut write, .. } = self.current_depth_test {
+            *write = false;
+        }
+    }
+
+    pub fn disable_stencil(&self) {
+    }
+
+    pub fn set_scissor_rect(&mut self, rect: DeviceIntRect) {
+        self.scissor_rect = Some(rect);
+    }
+
+    pub fn enable_scissor(&self) {}
+
+    pub fn disable_scissor(&mut self) {
+        self.scissor_rect = None;
+    }
+
+    pub fn set_blend(&mut self, enable: bool) {
+        if !enable {
+            self.current_blend_state = BlendState::Off
+        }
+    }
+
+    pub fn set_blend_mode_alpha(&mut self) {
+        self.current_blend_state = ALPHA;
+    }
+
+    pub fn set_blend_mode_premultiplied_alpha(&mut self) {
+        self.current_blend_state = BlendState::PREMULTIPLIED_ALPHA;
+    }
+
+    pub fn set_blend_mode_premultiplied_dest_out(&mut self) {
+        self.current_blend_state = PREMULTIPLIED_DEST_OUT;
+    }
+
+    pub fn set_blend_mode_multiply(&mut self) {
+        self.current_blend_state = BlendState::MULTIPLY;
+    }
+    pub fn set_blend_mode_mask_add(&mut self) {
+        self.current_blend_state = MASK_ADD;
+    }
+    pub fn set_blend_mode_mask_subtract(&mut self) {
+        self.current_blend_state = MASK_SUBTRACT;
+    }
+    pub fn set_blend_mode_mask_exclude(&mut self) {
+        self.current_blend_state = MASK_EXCLUDE;
+    }
+    pub fn set_blend_mode_max(&mut self) {
+        self.current_blend_state = MAX;
+    }
+    #[cfg(feature = "debug_renderer")]
+    pub fn set_blend_mode_min(&mut self) {
+        self.current_blend_state = MIN;
+    }
+    pub fn set_blend_mode_subpixel_pass0(&mut self) {
+        self.current_blend_state = SUBPIXEL_PASS0;
+    }
+    pub fn set_blend_mode_subpixel_pass1(&mut self) {
+        self.current_blend_state = SUBPIXEL_PASS1;
+    }
+    pub fn set_blend_mode_subpixel_with_bg_color_pass0(&mut self) {
+        self.current_blend_state = SUBPIXEL_WITH_BG_COLOR_PASS0;
+    }
+    pub fn set_blend_mode_subpixel_with_bg_color_pass1(&mut self) {
+        self.current_blend_state = SUBPIXEL_WITH_BG_COLOR_PASS1;
+    }
+    pub fn set_blend_mode_subpixel_with_bg_color_pass2(&mut self) {
+        self.current_blend_state = SUBPIXEL_WITH_BG_COLOR_PASS2;
+    }
+    pub fn set_blend_mode_subpixel_constant_text_color(&mut self, color: ColorF) {
+        self.current_blend_state = SUBPIXEL_CONSTANT_TEXT_COLOR;
+        // color is an unpremultiplied color.
+        self.blend_color = ColorF::new(color.r, color.g, color.b, 1.0);
+    }
+    pub fn set_blend_mode_subpixel_dual_source(&mut self) {
+        self.current_blend_state = SUBPIXEL_DUAL_SOURCE;
+    }
+
+    pub fn supports_features(&self, features: hal::Features) -> bool {
+        self.features.contains(features)
+    }
+
+    pub fn set_next_frame_id(&mut self) {
+    }
+
+    pub fn swap_buffers(&mut self) {
+        // The frame stays in memory until it is read back.
+        self.reset_state();
+    }
+
+    pub fn wait_for_resources_and_reset(&mut self) {
+    }
+
+    pub fn deinit(self) {
+    }
+}
+
+pub struct TextureUploader<'a, B: hal::Backend> {
+    device: &'a mut Device<B>,
+    texture: &'a Texture,
+}
+
+impl<'a, B: hal::Backend> TextureUploader<'a, B> {
+    pub fn upload<T>(
+        &mut self,
+        rect: DeviceUintRect,
+        layer_index: i32,
+        stride: Option<u32>,
+        data: &[T],
+    ) {
+        let data = unsafe {
+            slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * mem::size_of::<T>())
+        };
+        let image = self.device.images
+            .get_mut(&self.texture.id)
+            .expect("Texture not found.");
+        let bpp = image.format.bytes_per_pixel() as usize;
+        let row_size = rect.size.width as usize * bpp;
+        let src_stride = stride.map_or(row_size, |stride| stride as usize);
+        let dst_stride = image.stride();
+        let layer = &mut image.layers[layer_index as usize];
+
+        for y in 0 .. rect.size.height as usize {
+            let src = &data[y * src_stride .. y * src_stride + row_size];
+            let dst_offset = (rect.origin.y as usize + y) * dst_stride + rect.origin.x as usize * bpp;
+            layer[dst_offset .. dst_offset + row_size].copy_from_slice(src);
+        }
+    }
+}
+
+fn draw_brush(
+    kind: ProgramKind,
+    instance: &PrimitiveInstance,
+    target: &mut Target,
+    data: &ShaderData,
+    textures: &ShaderTextures,
+    state: &DrawState,
+) {
+    // See `load_brush` in brush.glsl.
+    let picture_address = instance.aData0[0] & 0xffff;
+    let clip_address = instance.aData0[0] >> 16;
+    let prim_address = instance.aData0[1];
+    let clip_chain_rect_index = instance.aData0[2] & ((1 << CLIP_CHAIN_RECT_BITS) - 1);
+    let segment_index = instance.aData0[2] >> CLIP_CHAIN_RECT_BITS;
+    let z = instance.aData0[3] as f32;
+    let scroll_node_id = instance.aData1[0] & ((1 << CLIP_SCROLL_INDEX_BITS) - 1);
+    let flags = (instance.aData1[0] >> (CLIP_SCROLL_INDEX_BITS + 4)) & 0xf;
+    let user_data = [instance.aData1[1], instance.aData1[2], instance.aData1[3]];
+
+    let local_rect = Rect::from_vec4(data.resource_cache(prim_address));
+    let local_clip_rect = Rect::from_vec4(data.resource_cache(prim_address + 1))
+        .intersect(&data.clip_chain_rect(clip_chain_rect_index));
+    let segment_address = prim_address + VECS_PER_BRUSH_PRIM + kind.vecs_per_specific_brush() +
+        segment_index * VECS_PER_SEGMENT;
+    let segment_rect = Rect::from_vec4(data.resource_cache(segment_address));
+    let segment_data = data.resource_cache(segment_address + 1);
+
+    let pic_task = data.render_task(picture_address);
+    let clip_area = data.clip_area(clip_address);
+    let node = data.clip_scroll_node(scroll_node_id);
+
+    let draw_rect = segment_rect.intersect(&local_clip_rect);
+    if draw_rect.is_empty() {
+        return;
+    }
+
+    let task = PictureTransform::new(&pic_task);
+    let mapping = match QuadMapping::new(draw_rect, &node, state.device_pixel_ratio, task, z, state) {
+        Some(mapping) => mapping,
+        None => return,
+    };
+
+    let specific_address = prim_address + VECS_PER_BRUSH_PRIM;
+    let shader = match kind {
+        ProgramKind::BrushImage => match textures.color0 {
+            Some(color0) => BrushShader::Image(ImageBrush::new(
+                specific_address,
+                local_rect,
+                segment_rect,
+                user_data,
+                flags,
+                segment_data,
+                color0,
+                data,
+                state,
+            )),
+            None => return,
+        },
+        ProgramKind::BrushBlend => match textures.color0 {
+            Some((source, _)) => BrushShader::Blend(BlendBrush::new(user_data, source, data)),
+            None => return,
+        },
+        ProgramKind::BrushMixBlend => match textures.cache_rgba8 {
+            Some(cache) => BrushShader::MixBlend(MixBlendBrush::new(user_data, cache, data)),
+            None => return,
+        },
+        ProgramKind::BrushYuvImage(format, color_space) => {
+            match YuvBrush::new(format, color_space, local_rect, user_data, textures, data) {
+                Some(yuv) => BrushShader::Yuv(yuv),
+                None => return,
+            }
+        }
+        ProgramKind::BrushLinearGradient |
+        ProgramKind::BrushRadialGradient |
+        ProgramKind::BrushConicGradient => BrushShader::Gradient(GradientBrush::new(
+            kind,
+            specific_address,
+            local_rect,
+            segment_rect,
+            user_data,
+            flags,
+            segment_data,
+            data,
+        )),
+        _ => BrushShader::Solid(data.resource_cache(specific_address)),
+    };
+
+    let bounds = mapping.pixel_bounds(state);
+    for y in bounds.origin.y .. bounds.max_y() {
+        for x in bounds.origin.x .. bounds.max_x() {
+            let f = match mapping.factors(x, y) {
+                Some(f) => f,
+                None => continue,
+            };
+            let device_pos = mapping.device_pos(f);
+            let (color, color1) = match shader.shade(mapping.local_pos(f), device_pos, data, state) {
+                Some(colors) => colors,
+                None => continue,
+            };
+            let (color, color1) = if state.alpha_pass {
+                let clip = clip_mask(&clip_area, device_pos, textures);
+                (scale(color, clip), scale(color1, clip))
+            } else {
+                (color, color1)
+            };
+            target.write(state, x, y, mapping.z, color, color1);
+        }
+    }
+}
+
+/// The fragment stage of the brush shaders.
+enum BrushShader<'a> {
+    Solid(Vec4),
+    Image(ImageBrush<'a>),
+    Blend(BlendBrush<'a>),
+    MixBlend(MixBlendBrush<'a>),
+    Gradient(GradientBrush),
+    Yuv(YuvBrush<'a>),
+}
+
+impl<'a> BrushShader<'a> {
+    /// Returns the source and the dual source blending colors of a
+    /// fragment, or `None` if the fragment is discarded.
+    fn shade(
+        &self,
+        local_pos: (f32, f32),
+        device_pos: (f32, f32),
+        data: &ShaderData,
+        state: &DrawState,
+    ) -> Option<(Vec4, Vec4)> {
+        match *self {
+            BrushShader::Solid(color) => Some((color, [0.0; 4])),
+            BrushShader::Image(ref image) => Some(image.shade(local_pos, state)),
+            BrushShader::Blend(ref blend) => Some((blend.shade(device_pos), [0.0; 4])),
+            BrushShader::MixBlend(ref mix_blend) => Some((mix_blend.shade(device_pos), [0.0; 4])),
+            BrushShader::Gradient(ref gradient) => {
+                gradient.shade(local_pos, data, state).map(|color| (color, [0.0; 4]))
+            }
+            BrushShader::Yuv(ref yuv) => Some((yuv.shade(local_pos), [0.0; 4])),
+        }
+    }
+}
+
+/// See `do_clip` in prim_shared.glsl.
+fn clip_mask(area: &ClipArea, device_pos: (f32, f32), textures: &ShaderTextures) -> f32 {
+    if area.task_rect.is_empty() {
+        return 1.0;
+    }
+    let uv = (
+        device_pos.0 + area.task_rect.p0.0 - area.screen_origin.0,
+        device_pos.1 + area.task_rect.p0.1 - area.screen_origin.1,
+    );
+    let p1 = area.task_rect.p1();
+    if uv.0 < area.task_rect.p0.0 || uv.1 < area.task_rect.p0.1 || uv.0 > p1.0 || uv.1 > p1.1 {
+        return 0.0;
+    }
+    textures.cache_a8.map_or(1.0, |mask| {
+        mask.fetch(area.layer, uv.0.floor() as i32, uv.1.floor() as i32)[0]
+    })
+}
+
+/// The flat varyings of brush_image.glsl, in texels.
+struct ImageBrush<'a> {
+    texture: &'a SoftwareImage,
+    filter: TextureFilter,
+    layer: usize,
+    local_rect: Rect,
+    uv0: (f32, f32),
+    uv1: (f32, f32),
+    min_uv: (f32, f32),
+    max_uv: (f32, f32),
+    repeat: (f32, f32),
+    color: Vec4,
+    mask_swizzle: (f32, f32),
+}
+
+impl<'a> ImageBrush<'a> {
+    fn new(
+        address: i32,
+        prim_rect: Rect,
+        segment_rect: Rect,
+        user_data: [i32; 3],
+        flags: i32,
+        texel_rect: Vec4,
+        color0: (&'a SoftwareImage, TextureFilter),
+        data: &ShaderData,
+        state: &DrawState,
+    ) -> Self {
+        let color = data.resource_cache(address);
+        let background_color = data.resource_cache(address + 1);
+        let stretch = data.resource_cache(address + 2);
+
+        let res_uv = data.resource_cache(user_data[0]);
+        let res_extra = data.resource_cache(user_data[0] + 1);
+        let mut uv0 = (res_uv[0], res_uv[1]);
+        let mut uv1 = (res_uv[2], res_uv[3]);
+
+        let mut local_rect = prim_rect;
+        let mut stretch_size = (stretch[0], stretch[1]);
+
+        if flags & BRUSH_FLAG_SEGMENT_RELATIVE != 0 {
+            local_rect = segment_rect;
+            stretch_size = local_rect.size;
+            if flags & BRUSH_FLAG_SEGMENT_REPEAT_X != 0 {
+                stretch_size.0 = (texel_rect[2] - texel_rect[0]) / state.device_pixel_ratio;
+            }
+            if flags & BRUSH_FLAG_SEGMENT_REPEAT_Y != 0 {
+                stretch_size.1 = (texel_rect[3] - texel_rect[1]) / state.device_pixel_ratio;
+            }
+            uv0 = (res_uv[0] + texel_rect[0], res_uv[1] + texel_rect[1]);
+            uv1 = (res_uv[0] + texel_rect[2], res_uv[1] + texel_rect[3]);
+        }
+
+        let color_mode = match user_data[1] >> 16 {
+            COLOR_MODE_FROM_PASS => state.mode,
+            mode => mode,
+        };
+        let (mask_swizzle, color) = text_color_mode(color_mode, color, background_color);
+
+        ImageBrush {
+            texture: color0.0,
+            filter: color0.1,
+            layer: res_extra[0] as usize,
+            local_rect,
+            uv0,
+            uv1,
+            min_uv: (uv0.0.min(uv1.0), uv0.1.min(uv1.1)),
+            max_uv: (uv0.0.max(uv1.0), uv0.1.max(uv1.1)),
+            repeat: (local_rect.size.0 / stretch_size.0, local_rect.size.1 / stretch_size.1),
+            color,
+            mask_swizzle,
+        }
+    }
+
+    /// Returns the source and the dual source blending colors of a fragment.
+    fn shade(&self, local_pos: (f32, f32), state: &DrawState) -> (Vec4, Vec4) {
+        let f = (
+            (local_pos.0 - self.local_rect.p0.0) / self.local_rect.size.0,
+            (local_pos.1 - self.local_rect.p0.1) / self.local_rect.size.1,
+        );
+        let uv = (
+            (self.uv0.0 + (self.uv1.0 - self.uv0.0) * f.0 - self.min_uv.0) * self.repeat.0,
+            (self.uv0.1 + (self.uv1.1 - self.uv0.1) * f.1 - self.min_uv.1) * self.repeat.1,
+        );
+        let uv_size = (self.max_uv.0 - self.min_uv.0, self.max_uv.1 - self.min_uv.1);
+        let local_uv = (uv.0.max(0.0), uv.1.max(0.0));
+        let mut repeated = (
+            local_uv.0 % uv_size.0.max(1e-6) + self.min_uv.0,
+            local_uv.1 % uv_size.1.max(1e-6) + self.min_uv.1,
+        );
+        if local_uv.0 >= self.repeat.0 * uv_size.0 {
+            repeated.0 = self.max_uv.0;
+        }
+        if local_uv.1 >= self.repeat.1 * uv_size.1 {
+            repeated.1 = self.max_uv.1;
+        }
+        let sample_uv = (
+            repeated.0.max(self.min_uv.0 + 0.5).min(self.max_uv.0 - 0.5),
+            repeated.1.max(self.min_uv.1 + 0.5).min(self.max_uv.1 - 0.5),
+        );
+        let mut texel = self.texture.sample(self.layer, sample_uv.0, sample_uv.1, self.filter);
+
+        if !state.alpha_pass {
+            return (texel, [0.0; 4]);
+        }
+
+        let (sx, sy) = self.mask_swizzle;
+        for i in 0 .. 3 {
+            texel[i] = texel[i] * sx + texel[3] * sy;
+        }
+        let color = [
+            self.color[0] * texel[0],
+            self.color[1] * texel[1],
+            self.color[2] * texel[2],
+            self.color[3] * texel[3],
+        ];
+        (color, scale(texel, self.color[3]))
+    }
+}
+
+/// One plane of a YUV image, see `write_uv_rect` in brush_yuv_image.glsl.
+struct YuvPlane<'a> {
+    texture: &'a SoftwareImage,
+    filter: TextureFilter,
+    layer: usize,
+    uv0: (f32, f32),
+    uv1: (f32, f32),
+}
+
+impl<'a> YuvPlane<'a> {
+    fn new(address: i32, color: (&'a SoftwareImage, TextureFilter), data: &ShaderData) -> Self {
+        let res_uv = data.resource_cache(address);
+        let res_extra = data.resource_cache(address + 1);
+        YuvPlane {
+            texture: color.0,
+            filter: color.1,
+            layer: res_extra[0] as usize,
+            uv0: (res_uv[0], res_uv[1]),
+            uv1: (res_uv[2], res_uv[3]),
+        }
+    }
+
+    fn sample(&self, f: (f32, f32)) -> Vec4 {
+        let uv = (
+            (self.uv0.0 + (self.uv1.0 - self.uv0.0) * f.0).max(self.uv0.0 + 0.5).min(self.uv1.0 - 0.5),
+            (self.uv0.1 + (self.uv1.1 - self.uv0.1) * f.1).max(self.uv0.1 + 0.5).min(self.uv1.1 - 0.5),
+        );
+        self.texture.sample(self.layer, uv.0, uv.1, self.filter)
+    }
+}
+
+/// The flat varyings of brush_yuv_image.glsl.
+struct YuvBrush<'a> {
+    format: YuvFormat,
+    color_space: YuvColorSpace,
+    local_rect: Rect,
+    planes: Vec<YuvPlane<'a>>,
+}
+
+impl<'a> YuvBrush<'a> {
+    /// Returns `None` if the textures of a plane aren't bound.
+    fn new(
+        format: YuvFormat,
+        color_space: YuvColorSpace,
+        local_rect: Rect,
+        user_data: [i32; 3],
+        textures: &ShaderTextures<'a>,
+        data: &ShaderData,
+    ) -> Option<Self> {
+        let colors = [textures.color0, textures.color1, textures.color2];
+        let plane_count = match format {
+            YuvFormat::PlanarYCbCr => 3,
+            YuvFormat::NV12 => 2,
+            YuvFormat::InterleavedYCbCr => 1,
+        };
+        let mut planes = Vec::with_capacity(plane_count);
+        for i in 0 .. plane_count {
+            planes.push(YuvPlane::new(user_data[i], colors[i]?, data));
+        }
+        Some(YuvBrush {
+            format,
+            color_space,
+            local_rect,
+            planes,
+        })
+    }
+
+    fn shade(&self, local_pos: (f32, f32)) -> Vec4 {
+        let f = (
+            (local_pos.0 - self.local_rect.p0.0) / self.local_rect.size.0,
+            (local_pos.1 - self.local_rect.p0.1) / self.local_rect.size.1,
+        );
+        let yuv = match self.format {
+            YuvFormat::PlanarYCbCr => {
+                [self.planes[0].sample(f)[0], self.planes[1].sample(f)[0], self.planes[2].sample(f)[0]]
+            }
+            YuvFormat::NV12 => {
+                let uv = self.planes[1].sample(f);
+                [self.planes[0].sample(f)[0], uv[0], uv[1]]
+            }
+            YuvFormat::InterleavedYCbCr => {
+                // The Y, Cb and Cr channels are mapped to green, blue and red.
+                let texel = self.planes[0].sample(f);
+                [texel[1], texel[2], texel[0]]
+            }
+        };
+        // The columns of `YuvColorMatrix`.
+        let matrix = match self.color_space {
+            YuvColorSpace::Rec601 => [
+                [1.16438, 1.16438, 1.16438],
+                [0.0, -0.39176, 2.01723],
+                [1.59603, -0.81297, 0.0],
+            ],
+            YuvColorSpace::Rec709 => [
+                [1.16438, 1.16438, 1.16438],
+                [0.0, -0.21325, 2.11240],
+                [1.79274, -0.53291, 0.0],
+            ],
+        };
+        let value = [yuv[0] - 0.06275, yuv[1] - 0.50196, yuv[2] - 0.50196];
+        let mut color = [0.0, 0.0, 0.0, 1.0];
+        for (column, v) in matrix.iter().zip(value.iter()) {
+            for i in 0 .. 3 {
+                color[i] += column[i] * v;
+            }
+        }
+        color
+    }
+}
+
+/// Returns the mask swizzle and the color of a text color mode,
+/// see ps_text_run.glsl.
+fn text_color_mode(color_mode: i32, color: Vec4, background_color: Vec4) -> ((f32, f32), Vec4) {
+    match color_mode {
+        COLOR_MODE_ALPHA | COLOR_MODE_BITMAP | COLOR_MODE_SDF => ((0.0, 1.0), color),
+        COLOR_MODE_SUBPX_BG_PASS2 | COLOR_MODE_SUBPX_DUAL_SOURCE => ((1.0, 0.0), color),
+        COLOR_MODE_SUBPX_CONST_COLOR | COLOR_MODE_SUBPX_BG_PASS0 | COLOR_MODE_COLOR_BITMAP => {
+            ((1.0, 0.0), [color[3]; 4])
+        }
+        COLOR_MODE_SUBPX_BG_PASS1 => ((-1.0, 1.0), scale(background_color, color[3])),
+        _ => ((0.0, 0.0), [1.0; 4]),
+    }
+}
+
+/// The flat varyings of brush_linear_gradient.glsl, brush_radial_gradient.glsl
+/// and brush_conic_gradient.glsl. Dithering is not emulated.
+struct GradientBrush {
+    kind: ProgramKind,
+    params: Vec4,
+    angle: f32,
+    ratio_xy: f32,
+    stops_address: i32,
+    repeat: bool,
+    local_origin: (f32, f32),
+    segment: Option<(Rect, Vec4)>,
+    repeated_size: (f32, f32),
+    tile_repeat: (f32, f32),
+}
+
+impl GradientBrush {
+    fn new(
+        kind: ProgramKind,
+        address: i32,
+        local_rect: Rect,
+        segment_rect: Rect,
+        user_data: [i32; 3],
+        flags: i32,
+        texel_rect: Vec4,
+        data: &ShaderData,
+    ) -> Self {
+        let params = data.resource_cache(address);
+        let extra = data.resource_cache(address + 1);
+        let (angle, ratio_xy, extend_mode, stretch_size) = match kind {
+            ProgramKind::BrushLinearGradient => (0.0, 1.0, extra[0], (extra[1], extra[2])),
+            ProgramKind::BrushRadialGradient => (0.0, extra[0], extra[1], (extra[2], extra[3])),
+            _ => (extra[0], 1.0, extra[1], (extra[2], extra[3])),
+        };
+
+        // Conic gradients don't support segment relative interpolation.
+        let segment_relative = flags & BRUSH_FLAG_SEGMENT_RELATIVE != 0 &&
+            kind != ProgramKind::BrushConicGradient;
+        let tile_repeat = if segment_relative {
+            (1.0, 1.0)
+        } else {
+            (local_rect.size.0 / stretch_size.0, local_rect.size.1 / stretch_size.1)
+        };
+
+        GradientBrush {
+            kind,
+            params,
+            angle,
+            ratio_xy,
+            stops_address: user_data[0],
+            repeat: extend_mode as i32 != EXTEND_MODE_CLAMP,
+            local_origin: local_rect.p0,
+            segment: if segment_relative { Some((segment_rect, texel_rect)) } else { None },
+            repeated_size: (stretch_size.0, stretch_size.1 * ratio_xy),
+            tile_repeat,
+        }
+    }
+
+    fn shade(&self, local_pos: (f32, f32), data: &ShaderData, state: &DrawState) -> Option<Vec4> {
+        let mut pos = match self.segment {
+            Some((ref rect, texel_rect)) => {
+                let f = ((local_pos.0 - rect.p0.0) / rect.size.0, (local_pos.1 - rect.p0.1) / rect.size.1);
+                (
+                    texel_rect[0] + (texel_rect[2] - texel_rect[0]) * f.0,
+                    texel_rect[1] + (texel_rect[3] - texel_rect[1]) * f.1,
+                )
+            }
+            None => (local_pos.0 - self.local_origin.0, local_pos.1 - self.local_origin.1),
+        };
+        // Transform the coordinates by the y scale, so that
+        // radial gradients can work with circles.
+        pos.1 *= self.ratio_xy;
+
+        // Apply potential horizontal and vertical repetitions.
+        let size = self.repeated_size;
+        let pos = if state.alpha_pass {
+            // Handle the inflated edges, see brush_image.
+            let local_pos = (pos.0.max(0.0), pos.1.max(0.0));
+            let mut pos = (modulo(local_pos.0, size.0), modulo(local_pos.1, size.1));
+            if local_pos.0 >= size.0 * self.tile_repeat.0 {
+                pos.0 = size.0;
+            }
+            if local_pos.1 >= size.1 * self.tile_repeat.1 {
+                pos.1 = size.1;
+            }
+            pos
+        } else {
+            (modulo(pos.0, size.0), modulo(pos.1, size.1))
+        };
+
+        let p = self.params;
+        let offset = match self.kind {
+            ProgramKind::BrushLinearGradient => {
+                let dir = (p[2] - p[0], p[3] - p[1]);
+                let length_squared = dir.0 * dir.0 + dir.1 * dir.1;
+                ((pos.0 - p[0]) * dir.0 + (pos.1 - p[1]) * dir.1) / length_squared
+            }
+            ProgramKind::BrushRadialGradient => {
+                let pd = (pos.0 - p[0], pos.1 - p[1] * self.ratio_xy);
+                let (start_radius, end_radius) = (p[2], p[3]);
+                radial_gradient_offset(pd, start_radius, end_radius)?
+            }
+            _ => {
+                // The angle goes clockwise from the top, like in CSS.
+                let pd = (pos.0 - p[0], pos.1 - p[1]);
+                let angle = pd.0.atan2(-pd.1) - self.angle;
+                let turn = fract(angle / (2.0 * f32::consts::PI));
+                (turn - p[2]) / (p[3] - p[2])
+            }
+        };
+
+        Some(sample_gradient(data, self.stops_address, offset, self.repeat))
+    }
+}
+
+/// Solves `length(t - pd) = start_radius + t * (end_radius - start_radius)`
+/// for the largest `t` with a non negative radius, see brush_radial_gradient.glsl.
+fn radial_gradient_offset(pd: (f32, f32), start_radius: f32, end_radius: f32) -> Option<f32> {
+    let rd = end_radius - start_radius;
+    let a = -(rd * rd);
+    let b = start_radius * rd;
+    let c = pd.0 * pd.0 + pd.1 * pd.1 - start_radius * start_radius;
+
+    if a == 0.0 {
+        if b == 0.0 {
+            return None;
+        }
+        let t = 0.5 * c / b;
+        return if start_radius + rd * t >= 0.0 { Some(t) } else { None };
+    }
+
+    let discr = b * b - a * c;
+    if discr < 0.0 {
+        return None;
+    }
+    let discr = discr.sqrt();
+    let t0 = (b + discr) / a;
+    let t1 = (b - discr) / a;
+    if start_radius + rd * t0 >= 0.0 {
+        Some(t0)
+    } else if start_radius + rd * t1 >= 0.0 {
+        Some(t1)
+    } else {
+        None
+    }
+}
+
+/// Looks the color of a gradient offset up in the table built by
+/// `GradientGpuBlockBuilder`, see `sample_gradient` in prim_shared.glsl.
+fn sample_gradient(data: &ShaderData, address: i32, offset: f32, repeat: bool) -> Vec4 {
+    let x = if repeat { fract(offset) } else { offset };
+    let x = 1.0 + x * GRADIENT_ENTRIES as f32;
+    // There is a [start, end] color per entry.
+    let lut_offset = (2 * x.floor() as i32).max(0).min(2 * (GRADIENT_ENTRIES + 1));
+    let start = data.resource_cache(address + lut_offset);
+    let end = data.resource_cache(address + lut_offset + 1);
+    mix(start, end, fract(x))
+}
+
+/// The flat varyings of brush_blend.glsl, in texels.
+struct BlendBrush<'a> {
+    source: &'a SoftwareImage,
+    layer: usize,
+    uv_transform: PictureTransform,
+    uv_bounds: Rect,
+    op: i32,
+    amount: f32,
+    // The columns of the color matrix.
+    color_mat: [[f32; 3]; 3],
+    color_offset: [f32; 3],
+}
+
+impl<'a> BlendBrush<'a> {
+    fn new(user_data: [i32; 3], source: &'a SoftwareImage, data: &ShaderData) -> Self {
+        let src_task = data.render_task(user_data[0]);
+
+        let (lum_r, lum_g, lum_b) = (0.2126, 0.7152, 0.0722);
+        let amount = user_data[2] as f32 / 65536.0;
+        let inv_amount = 1.0 - amount;
+
+        let mut color_mat = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        let mut color_offset = [0.0; 3];
+        match user_data[1] {
+            // Grayscale
+            2 => {
+                color_mat = [
+                    [lum_r + (1.0 - lum_r) * inv_amount, lum_r - lum_r * inv_amount, lum_r - lum_r * inv_amount],
+                    [lum_g - lum_g * inv_amount, lum_g + (1.0 - lum_g) * inv_amount, lum_g - lum_g * inv_amount],
+                    [lum_b - lum_b * inv_amount, lum_b - lum_b * inv_amount, lum_b + (1.0 - lum_b) * inv_amount],
+                ];
+            }
+            // HueRotate
+            3 => {
+                let (c, s) = (amount.cos(), amount.sin());
+                color_mat = [
+                    [
+                        lum_r + (1.0 - lum_r) * c - lum_r * s,
+                        lum_r - lum_r * c + 0.143 * s,
+                        lum_r - lum_r * c - (1.0 - lum_r) * s,
+                    ],
+                    [
+                        lum_g - lum_g * c - lum_g * s,
+                        lum_g + (1.0 - lum_g) * c + 0.140 * s,
+                        lum_g - lum_g * c + lum_g * s,
+                    ],
+                    [
+                        lum_b - lum_b * c + (1.0 - lum_b) * s,
+                        lum_b - lum_b * c - 0.283 * s,
+                        lum_b + (1.0 - lum_b) * c + lum_b * s,
+                    ],
+                ];
+            }
+            // Saturate
+            5 => {
+                color_mat = [
+                    [inv_amount * lum_r + amount, inv_amount * lum_r, inv_amount * lum_r],
+                    [inv_amount * lum_g, inv_amount * lum_g + amount, inv_amount * lum_g],
+                    [inv_amount * lum_b, inv_amount * lum_b, inv_amount * lum_b + amount],
+                ];
+            }
+            // Sepia
+            6 => {
+                color_mat = [
+                    [0.393 + 0.607 * inv_amount, 0.349 - 0.349 * inv_amount, 0.272 - 0.272 * inv_amount],
+                    [0.769 - 0.769 * inv_amount, 0.686 + 0.314 * inv_amount, 0.534 - 0.534 * inv_amount],
+                    [0.189 - 0.189 * inv_amount, 0.168 - 0.168 * inv_amount, 0.131 + 0.869 * inv_amount],
+                ];
+            }
+            // Color Matrix
+            10 => {
+                for (i, column) in color_mat.iter_mut().enumerate() {
+                    let texel = data.resource_cache(user_data[2] + i as i32);
+                    column.copy_from_slice(&texel[.. 3]);
+                }
+                let offset = data.resource_cache(user_data[2] + 4);
+                color_offset.copy_from_slice(&offset[.. 3]);
+            }
+            _ => {}
+        }
+
+        BlendBrush {
+            source,
+            layer: src_task.layer,
+            uv_transform: PictureTransform::new(&src_task),
+            uv_bounds: src_task.task_rect,
+            op: user_data[1],
+            amount,
+            color_mat,
+            color_offset,
+        }
+    }
+
+    fn shade(&self, device_pos: (f32, f32)) -> Vec4 {
+        let uv = self.uv_transform.apply(device_pos);
+        let cs = self.source.sample(self.layer, uv.0, uv.1, TextureFilter::Nearest);
+        if cs[3] == 0.0 {
+            return [0.0; 4];
+        }
+
+        // Un-premultiply the input.
+        let mut alpha = cs[3];
+        let mut color = [cs[0] / alpha, cs[1] / alpha, cs[2] / alpha];
+        match self.op {
+            0 => {}
+            // Contrast
+            1 => for c in &mut color {
+                *c = *c * self.amount - 0.5 * self.amount + 0.5;
+            },
+            // Invert
+            4 => for c in &mut color {
+                *c += (1.0 - 2.0 * *c) * self.amount;
+            },
+            // Brightness
+            7 => for c in &mut color {
+                *c = (*c * self.amount).max(0.0).min(1.0);
+            },
+            // Opacity
+            8 => alpha *= self.amount,
+            _ => {
+                let m = &self.color_mat;
+                let input = color;
+                for i in 0 .. 3 {
+                    color[i] = m[0][i] * input[0] + m[1][i] * input[1] + m[2][i] * input[2] +
+                        self.color_offset[i];
+                }
+            }
+        }
+
+        // Ensure that we don't sample outside the rendered portion of the source.
+        let p1 = self.uv_bounds.p1();
+        if uv.0 < self.uv_bounds.p0.0 || uv.1 < self.uv_bounds.p0.1 || uv.0 > p1.0 || uv.1 > p1.1 {
+            alpha = 0.0;
+        }
+
+        [color[0] * alpha, color[1] * alpha, color[2] * alpha, alpha]
+    }
+}
+
+/// The flat varyings of brush_mix_blend.glsl, in texels.
+struct MixBlendBrush<'a> {
+    cache: &'a SoftwareImage,
+    op: i32,
+    src_layer: usize,
+    src_transform: PictureTransform,
+    backdrop_layer: usize,
+    backdrop_offset: (f32, f32),
+}
+
+impl<'a> MixBlendBrush<'a> {
+    fn new(user_data: [i32; 3], cache: &'a SoftwareImage, data: &ShaderData) -> Self {
+        let src_task = data.render_task(user_data[2]);
+        let backdrop_task = data.render_task(user_data[1]);
+        let content_origin = (src_task.data1[0], src_task.data1[1]);
+        MixBlendBrush {
+            cache,
+            op: user_data[0],
+            src_layer: src_task.layer,
+            src_transform: PictureTransform::new(&src_task),
+            backdrop_layer: backdrop_task.layer,
+            backdrop_offset: (
+                backdrop_task.task_rect.p0.0 - content_origin.0,
+                backdrop_task.task_rect.p0.1 - content_origin.1,
+            ),
+        }
+    }
+
+    fn shade(&self, device_pos: (f32, f32)) -> Vec4 {
+        let cb = self.cache.sample(
+            self.backdrop_layer,
+            device_pos.0 + self.backdrop_offset.0,
+            device_pos.1 + self.backdrop_offset.1,
+            TextureFilter::Nearest,
+        );
+        let src_uv = self.src_transform.apply(device_pos);
+        let cs = self.cache.sample(self.src_layer, src_uv.0, src_uv.1, TextureFilter::Nearest);
+        if cb[3] == 0.0 {
+            return cs;
+        }
+        if cs[3] == 0.0 {
+            return [0.0; 4];
+        }
+
+        // The mix-blend-mode functions assume no premultiplied alpha.
+        let backdrop = [cb[0] / cb[3], cb[1] / cb[3], cb[2] / cb[3]];
+        let source = [cs[0] / cs[3], cs[1] / cs[3], cs[2] / cs[3]];
+        let blended = blend_colors(self.op, backdrop, source);
+
+        let mut result = [0.0, 0.0, 0.0, cs[3]];
+        for i in 0 .. 3 {
+            result[i] = ((1.0 - cb[3]) * source[i] + cb[3] * blended[i]) * cs[3];
+        }
+        result
+    }
+}
+
+/// The separable and non-separable blend modes of the compositing spec,
+/// see blend.glsl. The colors are not premultiplied.
+fn blend_colors(op: i32, cb: [f32; 3], cs: [f32; 3]) -> [f32; 3] {
+    fn multiply(cb: f32, cs: f32) -> f32 {
+        cb * cs
+    }
+    fn screen(cb: f32, cs: f32) -> f32 {
+        cb + cs - cb * cs
+    }
+    fn hard_light(cb: f32, cs: f32) -> f32 {
+        if cs < 0.5 { multiply(cb, 2.0 * cs) } else { screen(cb, 2.0 * cs - 1.0) }
+    }
+    fn color_dodge(cb: f32, cs: f32) -> f32 {
+        if cb == 0.0 { 0.0 } else if cs == 1.0 { 1.0 } else { (cb / (1.0 - cs)).min(1.0) }
+    }
+    fn color_burn(cb: f32, cs: f32) -> f32 {
+        if cb == 1.0 { 1.0 } else if cs == 0.0 { 0.0 } else { 1.0 - ((1.0 - cb) / cs).min(1.0) }
+    }
+    fn soft_light(cb: f32, cs: f32) -> f32 {
+        if cs <= 0.5 {
+            cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb)
+        } else {
+            let d = if cb <= 0.25 { ((16.0 * cb - 12.0) * cb + 4.0) * cb } else { cb.sqrt() };
+            cb + (2.0 * cs - 1.0) * (d - cb)
+        }
+    }
+    fn sat(c: [f32; 3]) -> f32 {
+        c[0].max(c[1]).max(c[2]) - c[0].min(c[1]).min(c[2])
+    }
+    fn lum(c: [f32; 3]) -> f32 {
+        0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+    }
+    fn clip_color(c: [f32; 3]) -> [f32; 3] {
+        let l = lum(c);
+        let n = c[0].min(c[1]).min(c[2]);
+        let x = c[0].max(c[1]).max(c[2]);
+        let mut c = c;
+        if n < 0.0 {
+            for v in &mut c {
+                *v = l + (*v - l) * l / (l - n);
+            }
+        }
+        if x > 1.0 {
+            for v in &mut c {
+                *v = l + (*v - l) * (1.0 - l) / (x - l);
+            }
+        }
+        c
+    }
+    fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+        let d = l - lum(c);
+        clip_color([c[0] + d, c[1] + d, c[2] + d])
+    }
+    fn set_sat(c: [f32; 3], s: f32) -> [f32; 3] {
+        // Sort the channel indices by value.
+        let mut order = [0, 1, 2];
+        order.sort_by(|&a, &b| c[a].partial_cmp(&c[b]).unwrap_or(Ordering::Equal));
+        let (min, mid, max) = (order[0], order[1], order[2]);
+        let mut result = [0.0; 3];
+        if c[max] > c[min] {
+            result[mid] = (c[mid] - c[min]) * s / (c[max] - c[min]);
+            result[max] = s;
+        }
+        result
+    }
+    fn per_channel<F: Fn(f32, f32) -> f32>(cb: [f32; 3], cs: [f32; 3], f: F) -> [f32; 3] {
+        [f(cb[0], cs[0]), f(cb[1], cs[1]), f(cb[2], cs[2])]
+    }
+
+    match op {
+        MIX_BLEND_MULTIPLY => per_channel(cb, cs, multiply),
+        MIX_BLEND_SCREEN => per_channel(cb, cs, screen),
+        // Overlay is the inverse of HardLight.
+        MIX_BLEND_OVERLAY => per_channel(cb, cs, |cb, cs| hard_light(cs, cb)),
+        MIX_BLEND_DARKEN => per_channel(cb, cs, f32::min),
+        MIX_BLEND_LIGHTEN => per_channel(cb, cs, f32::max),
+        MIX_BLEND_COLOR_DODGE => per_channel(cb, cs, color_dodge),
+        MIX_BLEND_COLOR_BURN => per_channel(cb, cs, color_burn),
+        MIX_BLEND_HARD_LIGHT => per_channel(cb, cs, hard_light),
+        MIX_BLEND_SOFT_LIGHT => per_channel(cb, cs, soft_light),
+        MIX_BLEND_DIFFERENCE => per_channel(cb, cs, |cb, cs| (cb - cs).abs()),
+        MIX_BLEND_EXCLUSION => per_channel(cb, cs, |cb, cs| cb + cs - 2.0 * cb * cs),
+        MIX_BLEND_HUE => set_lum(set_sat(cs, sat(cb)), lum(cb)),
+        MIX_BLEND_SATURATION => set_lum(set_sat(cb, sat(cs)), lum(cb)),
+        MIX_BLEND_COLOR => set_lum(cs, lum(cb)),
+        MIX_BLEND_LUMINOSITY => set_lum(cb, lum(cs)),
+        // Yellow, like the shader, if none of the modes match.
+        _ => [1.0, 1.0, 0.0],
+    }
+}
+
+/// See ps_text_run.glsl.
+/// See ps_split_composite.glsl.
+fn draw_split_composite(
+    instance: &PrimitiveInstance,
+    source: &SoftwareImage,
+    target: &mut Target,
+    data: &ShaderData,
+    state: &DrawState,
+) {
+    // See `fetch_composite_instance` in prim_shared.glsl.
+    let dest_task = data.render_task(instance.aData0[0]);
+    let src_task = data.render_task(instance.aData0[1]);
+    let z = instance.aData0[3] as f32;
+    let address = instance.aData1[0];
+    let geometry = [
+        data.resource_cache(address),
+        data.resource_cache(address + 1),
+        data.resource_cache(address + 2),
+    ];
+    // The corners of the polygon, only their x and y are used.
+    let points = [
+        (geometry[0][0], geometry[0][1]),
+        (geometry[0][3], geometry[1][0]),
+        (geometry[1][2], geometry[1][3]),
+        (geometry[2][1], geometry[2][2]),
+    ];
+
+    let dest = PictureTransform::new(&dest_task);
+    let mut pixels = [(0.0, 0.0); 4];
+    let mut depth = 0.0;
+    for (pixel, &point) in pixels.iter_mut().zip(points.iter()) {
+        let dest_pos = dest.apply(point);
+        let (px, py, pz) = state.to_pixel(
+            dest_pos.0 * state.device_pixel_ratio,
+            dest_pos.1 * state.device_pixel_ratio,
+            z,
+        );
+        *pixel = (px, py);
+        depth = pz;
+    }
+
+    let src = PictureTransform::new(&src_task);
+    let src_rect = src_task.task_rect;
+    let src_p1 = src_rect.p1();
+
+    // The quad is drawn as the two triangles of the unit quad,
+    // with corners 0, 1, 3 and 3, 1, 2 of the polygon.
+    let triangles = [[0, 1, 3], [3, 1, 2]];
+    let x0 = pixels.iter().fold(f32::MAX, |a, p| a.min(p.0)).floor() as i32;
+    let y0 = pixels.iter().fold(f32::MAX, |a, p| a.min(p.1)).floor() as i32;
+    let x1 = pixels.iter().fold(f32::MIN, |a, p| a.max(p.0)).ceil() as i32;
+    let y1 = pixels.iter().fold(f32::MIN, |a, p| a.max(p.1)).ceil() as i32;
+    let bounds = DeviceIntRect::new(DeviceIntPoint::new(x0, y0), DeviceIntSize::new(x1 - x0, y1 - y0))
+        .intersection(&state.scissor)
+        .unwrap_or(DeviceIntRect::zero());
+    for y in bounds.origin.y .. bounds.max_y() {
+        for x in bounds.origin.x .. bounds.max_x() {
+            let center = (x as f32 + 0.5, y as f32 + 0.5);
+            // Each pixel is drawn by the first triangle covering it.
+            let world = triangles.iter().filter_map(|t| {
+                let l = barycentric(center, pixels[t[0]], pixels[t[1]], pixels[t[2]])?;
+                Some((
+                    points[t[0]].0 * l.0 + points[t[1]].0 * l.1 + points[t[2]].0 * l.2,
+                    points[t[0]].1 * l.0 + points[t[1]].1 * l.1 + points[t[2]].1 * l.2,
+                ))
+            }).next();
+            let world = match world {
+                Some(world) => world,
+                None => continue,
+            };
+            let uv = src.apply(world);
+            let color = if src_rect.p0.0 <= uv.0 && src_rect.p0.1 <= uv.1 && uv.0 <= src_p1.0 && uv.1 <= src_p1.1 {
+                let uv = (
+                    uv.0.max(src_rect.p0.0 + 0.5).min(src_p1.0 - 0.5),
+                    uv.1.max(src_rect.p0.1 + 0.5).min(src_p1.1 - 0.5),
+                );
+                source.sample(src_task.layer, uv.0, uv.1, TextureFilter::Linear)
+            } else {
+                [0.0; 4]
+            };
+            target.write(state, x, y, depth, color, [0.0; 4]);
+        }
+    }
+}
+
+/// Returns the barycentric coordinates of a point within a triangle,
+/// if it is covered by the triangle.
+fn barycentric(p: (f32, f32), a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> Option<(f32, f32, f32)> {
+    let det = (b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1);
+    if det.abs() < 1e-6 {
+        return None;
+    }
+    let l1 = ((p.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (p.1 - a.1)) / det;
+    let l2 = ((b.0 - a.0) * (p.1 - a.1) - (p.0 - a.0) * (b.1 - a.1)) / det;
+    let l0 = 1.0 - l1 - l2;
+    if l0 < 0.0 || l1 < 0.0 || l2 < 0.0 {
+        return None;
+    }
+    Some((l0, l1, l2))
+}
+
+fn draw_text_run(
+    kind: TextRunKind,
+    instance: &PrimitiveInstance,
+    target: &mut Target,
+    data: &ShaderData,
+    textures: &ShaderTextures,
+    state: &DrawState,
+) {
+    // See `fetch_prim_instance` in prim_shared.glsl.
+    let prim_address = instance.aData0[0];
+    let picture_address = instance.aData0[1] % 0x10000;
+    let clip_address = instance.aData0[1] / 0x10000;
+    let clip_chain_rect_index = instance.aData0[2];
+    let scroll_node_id = instance.aData0[3];
+    let z = instance.aData1[0] as f32;
+    let glyph_index = instance.aData1[1];
+    let resource_address = instance.aData1[2];
+    let subpx_dir = instance.aData1[3] >> 16;
+    let color_mode = match instance.aData1[3] & 0xffff {
+        COLOR_MODE_FROM_PASS => state.mode,
+        mode => mode,
+    };
+
+    let (glyph_cache, filter) = match textures.color0 {
+        Some(color0) => color0,
+        None => return,
+    };
+
+    let local_clip_rect = Rect::from_vec4(data.resource_cache(prim_address + 1))
+        .intersect(&data.clip_chain_rect(clip_chain_rect_index));
+    let specific_address = prim_address + VECS_PER_PRIM_HEADER;
+    let text_color = data.resource_cache(specific_address);
+    let background_color = data.resource_cache(specific_address + 1);
+    let text_data = data.resource_cache(specific_address + 2);
+    let text_offset = (text_data[0], text_data[1]);
+    let sdf_scale = text_data[2];
+
+    // Two glyphs are packed in each block of the GPU cache.
+    let glyph_data = data.resource_cache(specific_address + VECS_PER_TEXT_RUN + glyph_index / 2);
+    let glyph_offset = if glyph_index % 2 != 0 {
+        (glyph_data[2], glyph_data[3])
+    } else {
+        (glyph_data[0], glyph_data[1])
+    };
+    let res_uv = data.resource_cache(resource_address);
+    let res_data = data.resource_cache(resource_address + 1);
+    let layer = res_data[0] as usize;
+    let res_offset = (res_data[1], res_data[2]);
+    let res_scale = res_data[3];
+    let uv_size = (res_uv[2] - res_uv[0], res_uv[3] - res_uv[1]);
+    if uv_size.0 <= 0.0 || uv_size.1 <= 0.0 {
+        return;
+    }
+
+    let snap_bias = match subpx_dir {
+        SUBPX_DIR_HORIZONTAL => (0.125, 0.5),
+        SUBPX_DIR_VERTICAL => (0.5, 0.125),
+        SUBPX_DIR_MIXED => (0.125, 0.125),
+        _ => (0.5, 0.5),
+    };
+
+    let pic_task = data.render_task(picture_address);
+    let clip_area = data.clip_area(clip_address);
+    let mut node = data.clip_scroll_node(scroll_node_id);
+    if kind == TextRunKind::Snapped || kind == TextRunKind::GlyphTransform {
+        // Ensure the transform does not contain a subpixel translation, so that
+        // glyph snapping is stable for equivalent glyph subpixel positions.
+        let translation = &mut (node.transform.0)[3];
+        translation[0] = (translation[0] + 0.5).floor();
+        translation[1] = (translation[1] + 0.5).floor();
+    }
+    let task = PictureTransform::new(&pic_task);
+    let dpr = state.device_pixel_ratio;
+    let t = node.transform.0;
+
+    // The mapping covers the glyph rect, in local space for the regular
+    // glyphs and in glyph space for the transformed ones.
+    let mapping = if kind == TextRunKind::GlyphTransform {
+        let m = [t[0][0] * dpr, t[0][1] * dpr, t[1][0] * dpr, t[1][1] * dpr];
+        let origin = (text_offset.0 + glyph_offset.0, text_offset.1 + glyph_offset.1);
+        let glyph_rect = Rect {
+            p0: (
+                res_offset.0 + m[0] * origin.0 + m[2] * origin.1,
+                res_offset.1 + m[1] * origin.0 + m[3] * origin.1,
+            ),
+            size: uv_size,
+        };
+        // Align the glyph origin to a device pixel.
+        let shift = (
+            (glyph_rect.p0.0 + snap_bias.0).floor() - glyph_rect.p0.0 + t[3][0] * dpr,
+            (glyph_rect.p0.1 + snap_bias.1).floor() - glyph_rect.p0.1 + t[3][1] * dpr,
+        );
+        let p1 = glyph_rect.p1();
+        let device = [
+            (glyph_rect.p0.0 + shift.0, glyph_rect.p0.1 + shift.1),
+            (p1.0 + shift.0, glyph_rect.p0.1 + shift.1),
+            (glyph_rect.p0.0 + shift.0, p1.1 + shift.1),
+        ];
+        QuadMapping::from_device_corners(glyph_rect, device, task, z, state)
+    } else {
+        // Distance field glyphs are rasterized at a fixed size.
+        let scale = if kind == TextRunKind::Sdf { res_scale * sdf_scale } else { res_scale / dpr };
+        let glyph_rect = Rect {
+            p0: (
+                scale * res_offset.0 + text_offset.0 + glyph_offset.0,
+                scale * res_offset.1 + text_offset.1 + glyph_offset.1,
+            ),
+            size: (scale * uv_size.0, scale * uv_size.1),
+        };
+        let offset = if kind == TextRunKind::Snapped {
+            snap_offset(&node, &glyph_rect, snap_bias, dpr)
+        } else {
+            (0.0, 0.0)
+        };
+        QuadMapping::new(glyph_rect, &node, dpr, task.offset(offset), z, state)
+    };
+    let mapping = match mapping {
+        Some(mapping) => mapping,
+        None => return,
+    };
+    // The inverse of the glyph space transform, to find the local
+    // position of the fragments of transformed glyphs.
+    let glyph_to_local = if kind == TextRunKind::GlyphTransform {
+        let det = (t[0][0] * t[1][1] - t[1][0] * t[0][1]) * dpr * dpr;
+        if det.abs() < 1e-6 {
+            return;
+        }
+        Some([t[1][1] * dpr / det, -t[0][1] * dpr / det, -t[1][0] * dpr / det, t[0][0] * dpr / det])
+    } else {
+        None
+    };
+
+    let (mask_swizzle, color) = text_color_mode(color_mode, text_color, background_color);
+    let uv_bounds = (res_uv[0] + 0.5, res_uv[1] + 0.5, res_uv[2] - 0.5, res_uv[3] - 0.5);
+    let sample_mask = |f: (f32, f32)| {
+        let u = (res_uv[0] + uv_size.0 * f.0).max(uv_bounds.0).min(uv_bounds.2);
+        let v = (res_uv[1] + uv_size.1 * f.1).max(uv_bounds.1).min(uv_bounds.3);
+        glyph_cache.sample(layer, u, v, filter)
+    };
+
+    let bounds = mapping.pixel_bounds(state);
+    for y in bounds.origin.y .. bounds.max_y() {
+        for x in bounds.origin.x .. bounds.max_x() {
+            let f = match mapping.factors(x, y) {
+                Some(f) => f,
+                None => continue,
+            };
+            let pos = mapping.local_pos(f);
+            let local_pos = match glyph_to_local {
+                Some(inv) => (inv[0] * pos.0 + inv[2] * pos.1, inv[1] * pos.0 + inv[3] * pos.1),
+                None => pos,
+            };
+            if !local_clip_rect.contains(local_pos) {
+                continue;
+            }
+
+            let mut mask = sample_mask(f);
+            if kind == TextRunKind::Sdf && color_mode == COLOR_MODE_SDF {
+                // Reconstruct the coverage from the distance to the glyph's
+                // edge, which lies at 0.5, anti-aliasing over a device pixel.
+                let dx = sample_mask(mapping.unclipped_factors(x + 1, y))[3] - mask[3];
+                let dy = sample_mask(mapping.unclipped_factors(x, y + 1))[3] - mask[3];
+                let aa_range = 0.7 * (dx.abs() + dy.abs());
+                let coverage = smoothstep(0.5 - aa_range, 0.5 + aa_range, mask[3]);
+                mask = [coverage; 4];
+            }
+            for i in 0 .. 3 {
+                mask[i] = mask[i] * mask_swizzle.0 + mask[3] * mask_swizzle.1;
+            }
+
+            let alpha_mask = scale(mask, clip_mask(&clip_area, mapping.device_pos(f), textures));
+            let color0 = [
+                color[0] * alpha_mask[0],
+                color[1] * alpha_mask[1],
+                color[2] * alpha_mask[2],
+                color[3] * alpha_mask[3],
+            ];
+            target.write(state, x, y, mapping.z, color0, scale(alpha_mask, color[3]));
+        }
+    }
+}
+
+/// Returns the offset snapping the origin of a local rect to the device
+/// pixel grid, see `compute_snap_offset` in snap.glsl.
+fn snap_offset(node: &ClipScrollNode, snap_rect: &Rect, snap_bias: (f32, f32), device_pixel_ratio: f32) -> (f32, f32) {
+    let world = node.transform.mul([snap_rect.p0.0, snap_rect.p0.1, 0.0, 1.0]);
+    if world[3] == 0.0 {
+        return (0.0, 0.0);
+    }
+    let x = world[0] / world[3] * device_pixel_ratio;
+    let y = world[1] / world[3] * device_pixel_ratio;
+    ((x + snap_bias.0).floor() - x, (y + snap_bias.1).floor() - y)
+}
+
+/// Returns the local position of a clip mask pixel, see
+/// `write_clip_tile_vertex` in clip_shared.glsl.
+fn clip_local_pos(area: &ClipArea, node: &ClipScrollNode, x: i32, y: i32, state: &DrawState) -> (f32, f32) {
+    let device_pos = (
+        x as f32 + 0.5 - area.task_rect.p0.0 + area.screen_origin.0,
+        y as f32 + 0.5 - area.task_rect.p0.1 + area.screen_origin.1,
+    );
+    let pos = [
+        device_pos.0 / state.device_pixel_ratio,
+        device_pos.1 / state.device_pixel_ratio,
+        0.0,
+        1.0,
+    ];
+    let local = if area.local_space {
+        pos
+    } else {
+        node.inv_transform.mul(pos)
+    };
+    let w = if local[3] != 0.0 { local[3] } else { 1.0 };
+    (local[0] / w, local[1] / w)
+}
+
+/// See cs_clip_box_shadow.glsl.
+fn draw_clip_box_shadow(
+    instance: &ClipMaskInstance,
+    target: &mut Target,
+    data: &ShaderData,
+    textures: &ShaderTextures,
+    state: &DrawState,
+) {
+    let (shadow, filter) = match textures.color0 {
+        Some(color0) => color0,
+        None => return,
+    };
+    let area = data.clip_area(instance.aClipRenderTaskAddress);
+    let node = data.clip_scroll_node(instance.aScrollNodeId);
+    let (u, v) = (instance.aClipDataResourceAddress[0], instance.aClipDataResourceAddress[1]);
+    let shadow_data = data.resource_cache_direct(u, v);
+    let src_rect_size = (shadow_data[0], shadow_data[1]);
+    let clip_mode = shadow_data[2];
+    let stretch_modes = data.resource_cache_direct(u + 1, v);
+    let dest_rect = Rect::from_vec4(data.resource_cache_direct(u + 2, v));
+    let (ru, rv) = (instance.aClipDataResourceAddress[2], instance.aClipDataResourceAddress[3]);
+    let res_uv = data.resource_cache_direct(ru, rv);
+    let layer = data.resource_cache_direct(ru + 1, rv)[0] as usize;
+
+    // The edges of the stretched region and the size of the unstretched
+    // pattern, for each axis.
+    let axis = |mode: f32, dest_size: f32, src_size: f32| {
+        if mode as i32 == BOX_SHADOW_MODE_STRETCH {
+            ((0.5, dest_size / src_size - 0.5), src_size)
+        } else {
+            ((1.0, 1.0), dest_size)
+        }
+    };
+    let (edge_x, size_x) = axis(stretch_modes[0], dest_rect.size.0, src_rect_size.0);
+    let (edge_y, size_y) = axis(stretch_modes[1], dest_rect.size.1, src_rect_size.1);
+
+    let pixels = state.pixel_rect(&area.task_rect);
+    for y in pixels.origin.y .. pixels.max_y() {
+        for x in pixels.origin.x .. pixels.max_x() {
+            let local_pos = clip_local_pos(&area, &node, x, y, state);
+            let uv = (
+                (local_pos.0 - dest_rect.p0.0) / size_x,
+                (local_pos.1 - dest_rect.p0.1) / size_y,
+            );
+            let f = (
+                uv.0.max(0.0).min(edge_x.0) + (uv.0 - edge_x.1).max(0.0),
+                uv.1.max(0.0).min(edge_y.0) + (uv.1 - edge_y.1).max(0.0),
+            );
+            let texel_uv = (
+                (res_uv[0] + (res_uv[2] - res_uv[0]) * f.0).max(res_uv[0] + 0.5).min(res_uv[2] - 0.5),
+                (res_uv[1] + (res_uv[3] - res_uv[1]) * f.1).max(res_uv[1] + 0.5).min(res_uv[3] - 0.5),
+            );
+            let texel = shadow.sample(layer, texel_uv.0, texel_uv.1, filter)[0];
+            let alpha = texel + (1.0 - 2.0 * texel) * clip_mode;
+            let value = if dest_rect.contains(local_pos) { alpha } else { clip_mode };
+            target.write(state, x, y, 0.0, [value; 4], [0.0; 4]);
+        }
+    }
+}
+
+/// See cs_clip_line.glsl.
+fn draw_clip_line(
+    instance: &ClipMaskInstance,
+    target: &mut Target,
+    data: &ShaderData,
+    state: &DrawState,
+) {
+    let area = data.clip_area(instance.aClipRenderTaskAddress);
+    let node = data.clip_scroll_node(instance.aScrollNodeId);
+    let (u, v) = (instance.aClipDataResourceAddress[0], instance.aClipDataResourceAddress[1]);
+    let local_rect = Rect::from_vec4(data.resource_cache_direct(u, v));
+    let line_data = data.resource_cache_direct(u + 1, v);
+    let wavy_line_thickness = line_data[0];
+    let style = line_data[1] as i32;
+
+    // Work in a space where the line is horizontal.
+    let (vertical, origin, size) = match line_data[2] as i32 {
+        LINE_ORIENTATION_HORIZONTAL => (false, local_rect.p0, local_rect.size),
+        LINE_ORIENTATION_VERTICAL => (
+            true,
+            (local_rect.p0.1, local_rect.p0.0),
+            (local_rect.size.1, local_rect.size.0),
+        ),
+        _ => (false, (0.0, 0.0), (0.0, 0.0)),
+    };
+    let aa_range = compute_aa_range(state.device_pixel_ratio);
+
+    let pixels = state.pixel_rect(&area.task_rect);
+    for y in pixels.origin.y .. pixels.max_y() {
+        for x in pixels.origin.x .. pixels.max_x() {
+            let local_pos = clip_local_pos(&area, &node, x, y, state);
+            let pos = if vertical { (local_pos.1, local_pos.0) } else { local_pos };
+            let alpha = match style {
+                LINE_STYLE_DASHED => {
+                    let dash_length = size.1 * 3.0;
+                    let x = modulo(pos.0 - origin.0, 2.0 * dash_length);
+                    if x <= dash_length { 1.0 } else { 0.0 }
+                }
+                LINE_STYLE_DOTTED => {
+                    let diameter = size.1;
+                    let period = diameter * 2.0;
+                    let radius = diameter / 2.0;
+                    let center_line = origin.1 + 0.5 * size.1;
+                    let max_x = (size.0 / period).floor() * period;
+                    let x = modulo(pos.0 - origin.0, period);
+                    let (dx, dy) = (x - radius, pos.1 - center_line);
+                    let dot_distance = (dx * dx + dy * dy).sqrt() - radius;
+                    // Clip off partial dots.
+                    let partial = if pos.0 - origin.0 <= max_x { 1.0 } else { 0.0 };
+                    distance_aa(aa_range, dot_distance) * partial
+                }
+                LINE_STYLE_WAVY => {
+                    wavy_line_alpha((pos.0 - origin.0, pos.1 - origin.1), size.1, wavy_line_thickness, aa_range)
+                }
+                _ => 1.0,
+            };
+            target.write(state, x, y, 0.0, [alpha; 4], [0.0; 4]);
+        }
+    }
+}
+
+/// The coverage of a wavy line at a position relative to its origin,
+/// with the logic copied from gecko like in cs_clip_line.glsl.
+fn wavy_line_alpha(pos: (f32, f32), height: f32, thickness: f32, aa_range: f32) -> f32 {
+    let line_thickness = thickness.max(1.0);
+    let half_line_thickness = line_thickness / 2.0;
+    // Difference in height between peaks and troughs
+    // (and since slopes are 45 degrees, the length of each slope).
+    let slope_length = height - line_thickness;
+    // Length of flat runs.
+    let flat_length = ((line_thickness - 1.0) * 2.0).max(1.0);
+    // The pattern is just two slopes and two flats.
+    let half_period = slope_length + flat_length;
+
+    let mid_height = height / 2.0;
+    // Flip the wave every half period.
+    let flip = if modulo(pos.0, 2.0 * half_period) <= half_period { -1.0 } else { 1.0 };
+    let peak_height = mid_height + (mid_height - half_line_thickness) * flip;
+
+    // Convert the position to one within a half period.
+    let pos = (modulo(pos.0, half_period), pos.1);
+
+    // Compute the signed distance to the 3 lines that make up an arc.
+    let dist1 = distance_to_line((0.0, peak_height), (1.0, -flip), pos);
+    let dist2 = distance_to_line((0.0, peak_height), (0.0, -flip), pos);
+    let dist3 = distance_to_line((flat_length, peak_height), (-1.0, -flip), pos);
+    let dist = dist1.max(dist2).max(dist3).abs();
+
+    let alpha = distance_aa(aa_range, dist - half_line_thickness);
+    // Disable AA for thin lines.
+    if half_line_thickness <= 1.0 {
+        if alpha <= 0.5 { 0.0 } else { 1.0 }
+    } else {
+        alpha
+    }
+}
+
+/// See cs_clip_image.glsl.
+fn draw_clip_image(
+    instance: &ClipMaskInstance,
+    target: &mut Target,
+    data: &ShaderData,
+    textures: &ShaderTextures,
+    state: &DrawState,
+) {
+    let area = data.clip_area(instance.aClipRenderTaskAddress);
+    let node = data.clip_scroll_node(instance.aScrollNodeId);
+    let (u, v) = (instance.aClipDataResourceAddress[0], instance.aClipDataResourceAddress[1]);
+    let local_rect = Rect::from_vec4(data.resource_cache_direct(u, v));
+    let tile = data.resource_cache_direct(u + 1, v);
+    let tile_size = (tile[0], tile[1]);
+    let tile_stride = (tile[0] + tile[2], tile[1] + tile[3]);
+    let mask_data = data.resource_cache_direct(u + 2, v);
+    let source_kind = mask_data[0] as i32;
+    let channel = mask_data[1] as i32;
+    let stop_count = mask_data[2] as i32;
+    let repeat = mask_data[3] != 0.0;
+    let gradient_line = data.resource_cache_direct(u + 3, v);
+    let (ru, rv) = (instance.aClipDataResourceAddress[2], instance.aClipDataResourceAddress[3]);
+    let res_uv = data.resource_cache_direct(ru, rv);
+    let layer = data.resource_cache_direct(ru + 1, rv)[0] as usize;
+
+    if source_kind != MASK_SOURCE_GRADIENT && textures.color0.is_none() {
+        return;
+    }
+    // The gradient stops are stored two per block as (offset, value) pairs.
+    let stop = |i: i32| {
+        let block = data.resource_cache_direct(u + 4 + i / 2, v);
+        if i % 2 == 0 { (block[0], block[1]) } else { (block[2], block[3]) }
+    };
+    let aa_range = compute_aa_range(state.device_pixel_ratio);
+
+    let pixels = state.pixel_rect(&area.task_rect);
+    for y in pixels.origin.y .. pixels.max_y() {
+        for x in pixels.origin.x .. pixels.max_x() {
+            let local_pos = clip_local_pos(&area, &node, x, y, state);
+            let mut alpha = distance_aa(aa_range, signed_distance_rect(local_pos, &local_rect));
+
+            // Find the position within the tile, the mask is
+            // transparent in the spacing between tiles.
+            let tile_pos = (
+                modulo(local_pos.0 - local_rect.p0.0, tile_stride.0),
+                modulo(local_pos.1 - local_rect.p0.1, tile_stride.1),
+            );
+            if tile_pos.0 >= tile_size.0 || tile_pos.1 >= tile_size.1 {
+                alpha = 0.0;
+            }
+
+            let clip_alpha = if source_kind == MASK_SOURCE_GRADIENT {
+                let dir = (gradient_line[2] - gradient_line[0], gradient_line[3] - gradient_line[1]);
+                let offset = ((tile_pos.0 - gradient_line[0]) * dir.0 + (tile_pos.1 - gradient_line[1]) * dir.1) /
+                    (dir.0 * dir.0 + dir.1 * dir.1).max(1.0e-6);
+                let x = if repeat { fract(offset) } else { offset };
+                let mut value = stop(0).1;
+                for i in 1 .. stop_count {
+                    let (prev, next) = (stop(i - 1), stop(i));
+                    if x >= next.0 {
+                        value = next.1;
+                    } else if x > prev.0 {
+                        value = prev.1 + (next.1 - prev.1) * (x - prev.0) / (next.0 - prev.0);
+                    }
+                }
+                value
+            } else {
+                let (mask, filter) = textures.color0.unwrap();
+                let f = (
+                    (tile_pos.0 / tile_size.0).max(0.0).min(1.0),
+                    (tile_pos.1 / tile_size.1).max(0.0).min(1.0),
+                );
+                let uv = (
+                    (res_uv[0] + (res_uv[2] - res_uv[0]) * f.0).max(res_uv[0] + 0.5).min(res_uv[2] - 0.5),
+                    (res_uv[1] + (res_uv[3] - res_uv[1]) * f.1).max(res_uv[1] + 0.5).min(res_uv[3] - 0.5),
+                );
+                let texel = mask.sample(layer, uv.0, uv.1, filter);
+                match channel {
+                    MASK_CHANNEL_ALPHA => texel[3],
+                    // The image is premultiplied, so this is the luminance times alpha.
+                    MASK_CHANNEL_LUMINANCE => 0.2125 * texel[0] + 0.7154 * texel[1] + 0.0721 * texel[2],
+                    _ => texel[0],
+                }
+            };
+
+            target.write(state, x, y, 0.0, [alpha * clip_alpha, 1.0, 1.0, 1.0], [0.0; 4]);
+        }
+    }
+}
+
+/// See cs_clip_path.glsl.
+fn draw_clip_path(
+    instance: &ClipMaskInstance,
+    target: &mut Target,
+    data: &ShaderData,
+    state: &DrawState,
+) {
+    let area = data.clip_area(instance.aClipRenderTaskAddress);
+    let node = data.clip_scroll_node(instance.aScrollNodeId);
+    let (u, v) = (instance.aClipDataResourceAddress[0], instance.aClipDataResourceAddress[1]);
+    let bounds = Rect::from_vec4(data.resource_cache_direct(u, v));
+    let header = data.resource_cache_direct(u + 1, v);
+    let segment_count = header[0] as i32;
+    let fill_rule = header[1] as i32;
+    let aa_range = compute_aa_range(state.device_pixel_ratio);
+
+    let pixels = state.pixel_rect(&area.task_rect);
+    for y in pixels.origin.y .. pixels.max_y() {
+        for x in pixels.origin.x .. pixels.max_x() {
+            let local_pos = clip_local_pos(&area, &node, x, y, state);
+            let alpha = distance_aa(aa_range, signed_distance_rect(local_pos, &bounds));
+            // The addresses of the segment chunks follow the two header blocks.
+            let path_alpha = path_coverage(data, (u + 2, v), segment_count, fill_rule, local_pos, aa_range);
+            target.write(state, x, y, 0.0, [alpha * path_alpha; 4], [0.0; 4]);
+        }
+    }
+}
+
+/// The coverage of a path at a local position, shared by cs_path.glsl and
+/// cs_clip_path.glsl. The segments are split into chunks of one resource
+/// cache row, and the addresses of the chunks are stored two per block.
+fn path_coverage(
+    data: &ShaderData,
+    chunks_address: (i32, i32),
+    segment_count: i32,
+    fill_rule: i32,
+    pos: (f32, f32),
+    aa_range: f32,
+) -> f32 {
+    let row_width = MAX_VERTEX_TEXTURE_WIDTH as i32;
+    // Find the winding number of the path around the position, counting the
+    // crossings of a ray towards +x, and the distance to the nearest segment.
+    let mut winding = 0;
+    let mut min_distance = 1.0e6f32;
+    let mut segments_address = (0, 0);
+    for i in 0 .. segment_count {
+        let index = i % row_width;
+        if index == 0 {
+            let chunk = i / row_width;
+            let addresses = data.resource_cache_direct(chunks_address.0 + chunk / 2, chunks_address.1);
+            segments_address = if chunk % 2 == 0 {
+                (addresses[0] as i32, addresses[1] as i32)
+            } else {
+                (addresses[2] as i32, addresses[3] as i32)
+            };
+        }
+        let segment = data.resource_cache_direct(segments_address.0 + index, segments_address.1);
+        let from = (segment[0], segment[1]);
+        let to = (segment[2], segment[3]);
+
+        if (from.1 <= pos.1) != (to.1 <= pos.1) {
+            let x = from.0 + (pos.1 - from.1) * (to.0 - from.0) / (to.1 - from.1);
+            if x > pos.0 {
+                winding += if to.1 > from.1 { 1 } else { -1 };
+            }
+        }
+
+        let dir = (to.0 - from.0, to.1 - from.1);
+        let t = ((pos.0 - from.0) * dir.0 + (pos.1 - from.1) * dir.1) /
+            (dir.0 * dir.0 + dir.1 * dir.1).max(1.0e-6);
+        let t = t.max(0.0).min(1.0);
+        let d = (pos.0 - from.0 - dir.0 * t, pos.1 - from.1 - dir.1 * t);
+        min_distance = min_distance.min((d.0 * d.0 + d.1 * d.1).sqrt());
+    }
+
+    let inside = if fill_rule == FILL_RULE_EVEN_ODD { winding & 1 != 0 } else { winding != 0 };
+    distance_aa(aa_range, if inside { -min_distance } else { min_distance })
+}
+
+/// See cs_border_segment.glsl.
+fn draw_border_segment(instance: &BorderInstance, target: &mut Target, state: &DrawState) {
+    let segment = instance.aFlags & 0xff;
+    let style0 = (instance.aFlags >> 8) & 0xff;
+    let style1 = (instance.aFlags >> 16) & 0xff;
+    let clip_mode = (instance.aFlags >> 24) & 0xff;
+    let size = (instance.aRect[2], instance.aRect[3]);
+    let widths = (instance.aWidths[0], instance.aWidths[1]);
+    let radii = (instance.aRadii[0], instance.aRadii[1]);
+
+    let outer_scale = match segment {
+        BORDER_SEGMENT_TOP_RIGHT => (1.0, 0.0),
+        BORDER_SEGMENT_BOTTOM_RIGHT => (1.0, 1.0),
+        BORDER_SEGMENT_BOTTOM_LEFT => (0.0, 1.0),
+        _ => (0.0, 0.0),
+    };
+    let outer = (outer_scale.0 * size.0, outer_scale.1 * size.1);
+    let clip_sign = (1.0 - 2.0 * outer_scale.0, 1.0 - 2.0 * outer_scale.1);
+
+    // The orientation of the two edges of the segment, and the positions
+    // of the edge clips.
+    let (edge_axis, edge_reference) = match segment {
+        BORDER_SEGMENT_TOP_LEFT => ((0, 1), outer),
+        BORDER_SEGMENT_TOP_RIGHT => ((1, 0), (outer.0 - widths.0, outer.1)),
+        BORDER_SEGMENT_BOTTOM_RIGHT => ((0, 1), (outer.0 - widths.0, outer.1 - widths.1)),
+        BORDER_SEGMENT_BOTTOM_LEFT => ((1, 0), (outer.0, outer.1 - widths.1)),
+        BORDER_SEGMENT_TOP | BORDER_SEGMENT_BOTTOM => ((1, 1), (0.0, 0.0)),
+        _ => ((0, 0), (0.0, 0.0)),
+    };
+
+    let border = BorderSegment {
+        segment,
+        colors0: border_side_colors(instance.aColor0, style0),
+        colors1: border_side_colors(instance.aColor1, style1),
+        clip_center: (outer.0 + clip_sign.0 * radii.0, outer.1 + clip_sign.1 * radii.1),
+        clip_sign,
+        clip_radii: [radii.0, radii.1, (radii.0 - widths.0).max(0.0), (radii.1 - widths.1).max(0.0)],
+        color_line: [outer.0, outer.1, widths.1 * -clip_sign.1, widths.0 * clip_sign.0],
+        edge_reference: [
+            edge_reference.0,
+            edge_reference.1,
+            edge_reference.0 + widths.0,
+            edge_reference.1 + widths.1,
+        ],
+        partial_widths: [widths.0 / 3.0, widths.1 / 3.0, widths.0 / 2.0, widths.1 / 2.0],
+    };
+
+    // Only the pixels around a dot are touched, with some room for AA.
+    let mut local = Rect { p0: (0.0, 0.0), size };
+    if clip_mode == BORDER_CLIP_DOT {
+        let expanded_radius = instance.aClipParams1[2] + 2.0;
+        let p0 = (
+            (instance.aClipParams1[0] - expanded_radius).max(0.0).min(size.0),
+            (instance.aClipParams1[1] - expanded_radius).max(0.0).min(size.1),
+        );
+        let p1 = (
+            (instance.aClipParams1[0] + expanded_radius).max(0.0).min(size.0),
+            (instance.aClipParams1[1] + expanded_radius).max(0.0).min(size.1),
+        );
+        local = Rect { p0, size: (p1.0 - p0.0, p1.1 - p0.1) };
+    }
+    let origin = (
+        instance.aTaskOrigin[0] + instance.aRect[0],
+        instance.aTaskOrigin[1] + instance.aRect[1],
+    );
+    let target_rect = Rect { p0: (origin.0 + local.p0.0, origin.1 + local.p0.1), size: local.size };
+
+    // Border segments are drawn in device space.
+    let aa_range = compute_aa_range(1.0);
+    let pixels = state.pixel_rect(&target_rect);
+    for y in pixels.origin.y .. pixels.max_y() {
+        for x in pixels.origin.x .. pixels.max_x() {
+            let pos = (x as f32 + 0.5 - origin.0, y as f32 + 0.5 - origin.1);
+
+            let mix_factor = if edge_axis.0 != edge_axis.1 {
+                let c = border.color_line;
+                distance_aa(aa_range, -distance_to_line((c[0], c[1]), (c[2], c[3]), pos))
+            } else {
+                0.0
+            };
+
+            let mut d = match clip_mode {
+                BORDER_CLIP_DOT => {
+                    let p = instance.aClipParams1;
+                    let (dx, dy) = (pos.0 - p[0], pos.1 - p[1]);
+                    (dx * dx + dy * dy).sqrt() - p[2]
+                }
+                BORDER_CLIP_DASH => {
+                    let (p, q) = (instance.aClipParams1, instance.aClipParams2);
+                    let d0 = distance_to_line((p[0], p[1]), (p[2], p[3]), pos);
+                    let d1 = distance_to_line((q[0], q[1]), (q[2], q[3]), pos);
+                    d0.max(-d1)
+                }
+                _ => -1.0,
+            };
+
+            // Check if inside the corner clip region.
+            let clip_relative_pos = (pos.0 - border.clip_center.0, pos.1 - border.clip_center.1);
+            let in_clip_region = border.clip_sign.0 * clip_relative_pos.0 < 0.0 &&
+                border.clip_sign.1 * clip_relative_pos.1 < 0.0;
+
+            let (color0, color1) = if in_clip_region {
+                let r = border.clip_radii;
+                let d_radii_a = distance_to_ellipse(clip_relative_pos, (r[0], r[1]), aa_range);
+                let d_radii_b = distance_to_ellipse(clip_relative_pos, (r[2], r[3]), aa_range);
+                d = d.max(d_radii_a.max(-d_radii_b));
+                (
+                    border.corner_color(clip_relative_pos, style0, &border.colors0, mix_factor, aa_range),
+                    border.corner_color(clip_relative_pos, style1, &border.colors1, mix_factor, aa_range),
+                )
+            } else {
+                (
+                    border.edge_color(pos, style0, &border.colors0, aa_range, edge_axis.0),
+                    border.edge_color(pos, style1, &border.colors1, aa_range, edge_axis.1),
+                )
+            };
+
+            let alpha = distance_aa(aa_range, d);
+            let color = scale(mix(color0, color1, mix_factor), alpha);
+            target.write(state, x, y, 0.0, color, [0.0; 4]);
+        }
+    }
+}
+
+/// Returns the two colors of a border side, which differ for the
+/// groove and ridge styles.
+fn border_side_colors(color: [f32; 4], style: i32) -> [Vec4; 2] {
+    let modulate = |f: f32| {
+        let mut result = color;
+        for c in &mut result[.. 3] {
+            *c = (*c * f).max(0.0).min(color[3]);
+        }
+        result
+    };
+    match style {
+        BORDER_STYLE_GROOVE => [modulate(1.3), modulate(0.7)],
+        BORDER_STYLE_RIDGE => [modulate(0.7), modulate(1.3)],
+        _ => [color, color],
+    }
+}
+
+/// The flat varyings of cs_border_segment.glsl.
+struct BorderSegment {
+    segment: i32,
+    colors0: [Vec4; 2],
+    colors1: [Vec4; 2],
+    clip_center: (f32, f32),
+    clip_sign: (f32, f32),
+    clip_radii: Vec4,
+    color_line: Vec4,
+    edge_reference: Vec4,
+    partial_widths: Vec4,
+}
+
+impl BorderSegment {
+    fn corner_color(
+        &self,
+        clip_relative_pos: (f32, f32),
+        style: i32,
+        color: &[Vec4; 2],
+        mix_factor: f32,
+        aa_range: f32,
+    ) -> Vec4 {
+        let (r, w) = (self.clip_radii, self.partial_widths);
+        match style {
+            BORDER_STYLE_DOUBLE => {
+                // Clip out the inside third of the rounded edge.
+                let d_radii_a = distance_to_ellipse(clip_relative_pos, (r[0] - w[0], r[1] - w[1]), aa_range);
+                let d_radii_b =
+                    distance_to_ellipse(clip_relative_pos, (r[0] - 2.0 * w[0], r[1] - 2.0 * w[1]), aa_range);
+                let d = (-d_radii_a).min(d_radii_b);
+                scale(color[0], distance_aa(aa_range, d))
+            }
+            BORDER_STYLE_GROOVE | BORDER_STYLE_RIDGE => {
+                let d = distance_to_ellipse(clip_relative_pos, (r[0] - w[2], r[1] - w[3]), aa_range);
+                let alpha = distance_aa(aa_range, d);
+                let swizzled_factor = match self.segment {
+                    BORDER_SEGMENT_TOP_RIGHT => mix_factor,
+                    BORDER_SEGMENT_BOTTOM_RIGHT => 1.0,
+                    BORDER_SEGMENT_BOTTOM_LEFT => 1.0 - mix_factor,
+                    _ => 0.0,
+                };
+                let c0 = mix(color[1], color[0], swizzled_factor);
+                let c1 = mix(color[0], color[1], swizzled_factor);
+                mix(c0, c1, alpha)
+            }
+            _ => color[0],
+        }
+    }
+
+    fn edge_color(&self, pos: (f32, f32), style: i32, color: &[Vec4; 2], aa_range: f32, edge_axis: usize) -> Vec4 {
+        let pos = if edge_axis == 0 { pos.0 } else { pos.1 };
+        let (reference, w) = (self.edge_reference, self.partial_widths);
+        match style {
+            BORDER_STYLE_DOUBLE => {
+                let d = if w[edge_axis] > 1.0 {
+                    let d0 = pos - (reference[edge_axis] + w[edge_axis]);
+                    let d1 = reference[edge_axis + 2] - w[edge_axis] - pos;
+                    d0.min(d1)
+                } else {
+                    -1.0
+                };
+                scale(color[0], distance_aa(aa_range, d))
+            }
+            BORDER_STYLE_GROOVE | BORDER_STYLE_RIDGE => {
+                let d = pos - (reference[edge_axis] + w[edge_axis + 2]);
+                mix(color[0], color[1], distance_aa(aa_range, d))
+            }
+            _ => color[0],
+        }
+    }
+}
+
+/// Returns the half range to apply the anti-aliasing over, given the number
+/// of device pixels per unit, see `compute_aa_range` in shared.glsl.
+fn compute_aa_range(device_pixels_per_unit: f32) -> f32 {
+    0.5 / device_pixels_per_unit
+}
+
+/// See `distance_aa` in shared.glsl, 0.0 is outside the shape and 1.0 inside.
+fn distance_aa(aa_range: f32, signed_distance: f32) -> f32 {
+    let dist = 0.5 * signed_distance / aa_range;
+    if dist <= -0.5 + EPSILON {
+        1.0
+    } else if dist >= 0.5 - EPSILON {
+        0.0
+    } else {
+        0.5 + dist * (0.8431027 * dist * dist - 1.14453603)
+    }
+}
+
+fn distance_to_line(p0: (f32, f32), perp_dir: (f32, f32), p: (f32, f32)) -> f32 {
+    let length = (perp_dir.0 * perp_dir.0 + perp_dir.1 * perp_dir.1).sqrt();
+    ((p0.0 - p.0) * perp_dir.0 + (p0.1 - p.1) * perp_dir.1) / length
+}
+
+/// See `distance_to_ellipse` in ellipse.glsl.
+fn distance_to_ellipse(p: (f32, f32), radii: (f32, f32), aa_range: f32) -> f32 {
+    let dist = if radii.0 <= 0.0 || radii.1 <= 0.0 {
+        (p.0 * p.0 + p.1 * p.1).sqrt()
+    } else {
+        let inv_radii_sq = (1.0 / (radii.0 * radii.0), 1.0 / (radii.1 * radii.1));
+        let g = p.0 * p.0 * inv_radii_sq.0 + p.1 * p.1 * inv_radii_sq.1 - 1.0;
+        let dg = (2.0 * p.0 * inv_radii_sq.0, 2.0 * p.1 * inv_radii_sq.1);
+        g / (dg.0 * dg.0 + dg.1 * dg.1).sqrt()
+    };
+    dist.max(-aa_range).min(aa_range)
+}
+
+/// See `signed_distance_rect` in transform.glsl.
+fn signed_distance_rect(pos: (f32, f32), rect: &Rect) -> f32 {
+    let p1 = rect.p1();
+    let d = ((rect.p0.0 - pos.0).max(pos.0 - p1.0), (rect.p0.1 - pos.1).max(pos.1 - p1.1));
+    let outside = (d.0.max(0.0), d.1.max(0.0));
+    (outside.0 * outside.0 + outside.1 * outside.1).sqrt() + d.0.max(d.1).min(0.0)
+}
+
+/// See cs_blur.glsl.
+fn draw_blur(
+    instance: &BlurInstance,
+    source: &SoftwareImage,
+    target: &mut Target,
+    data: &ShaderData,
+    state: &DrawState,
+) {
+    let blur_task = data.render_task(instance.aBlurRenderTaskAddress);
+    let src_task = data.render_task(instance.aBlurSourceTaskAddress);
+    let sigma = blur_task.data1[0];
+    let radius = (3.0 * sigma) as i32;
+    let offset = match instance.aBlurDirection {
+        DIR_HORIZONTAL => (1.0, 0.0),
+        DIR_VERTICAL => (0.0, 1.0),
+        _ => (0.0, 0.0),
+    };
+    let src_rect = src_task.task_rect;
+    let src_p1 = src_rect.p1();
+    let bounds = (src_rect.p0.0 + 0.5, src_rect.p0.1 + 0.5, src_p1.0 - 0.5, src_p1.1 - 0.5);
+    let target_rect = blur_task.task_rect;
+    let fetch = |x: f32, y: f32| {
+        let x = x.max(bounds.0).min(bounds.2);
+        let y = y.max(bounds.1).min(bounds.3);
+        source.fetch(src_task.layer, x.floor() as i32, y.floor() as i32)
+    };
+
+    let pixels = state.pixel_rect(&target_rect);
+    for y in pixels.origin.y .. pixels.max_y() {
+        for x in pixels.origin.x .. pixels.max_x() {
+            let uv = (
+                src_rect.p0.0 + (x as f32 + 0.5 - target_rect.p0.0),
+                src_rect.p0.1 + (y as f32 + 0.5 - target_rect.p0.1),
+            );
+            let original = fetch(uv.0, uv.1);
+            if radius == 0 {
+                target.write(state, x, y, 0.0, original, [0.0; 4]);
+                continue;
+            }
+
+            // Incremental Gaussian Coefficent Calculation (See GPU Gems 3 pp. 877 - 889)
+            let mut gauss = (
+                1.0 / ((2.0 * f32::consts::PI).sqrt() * sigma),
+                (-0.5 / (sigma * sigma)).exp(),
+                0.0,
+            );
+            gauss.2 = gauss.1 * gauss.1;
+
+            let mut sum = gauss.0;
+            let mut avg = scale(original, gauss.0);
+            gauss.0 *= gauss.1;
+            gauss.1 *= gauss.2;
+
+            for i in 1 .. radius + 1 {
+                let d = (offset.0 * i as f32, offset.1 * i as f32);
+                let a = fetch(uv.0 - d.0, uv.1 - d.1);
+                let b = fetch(uv.0 + d.0, uv.1 + d.1);
+                for c in 0 .. 4 {
+                    avg[c] += (a[c] + b[c]) * gauss.0;
+                }
+                sum += 2.0 * gauss.0;
+                gauss.0 *= gauss.1;
+                gauss.1 *= gauss.2;
+            }
+
+            target.write(state, x, y, 0.0, scale(avg, 1.0 / sum), [0.0; 4]);
+        }
+    }
+}
+
+/// See cs_path.glsl.
+fn draw_path(
+    instance: &PathInstance,
+    target: &mut Target,
+    data: &ShaderData,
+    state: &DrawState,
+) {
+    let header = data.resource_cache(instance.aPathDataAddress);
+    let paint_data = data.resource_cache(instance.aPathDataAddress + 1);
+    let segment_count = header[0] as i32;
+    let fill_rule = header[1] as i32;
+    let paint_kind = header[2] as i32;
+    let gradient_repeat = header[3] != 0.0;
+    // The addresses of the segment chunks follow the two header blocks.
+    let chunks = instance.aPathDataAddress + 2;
+    let row_width = MAX_VERTEX_TEXTURE_WIDTH as i32;
+    let chunks_address = (chunks % row_width, chunks / row_width);
+
+    // The task covers the local bounds of the path.
+    let rect = Rect {
+        p0: (instance.aTaskOrigin[0] + instance.aRect[0], instance.aTaskOrigin[1] + instance.aRect[1]),
+        size: (instance.aRect[2], instance.aRect[3]),
+    };
+    let local_size = (instance.aLocalSize[0], instance.aLocalSize[1]);
+    if rect.is_empty() || local_size.0 <= 0.0 {
+        return;
+    }
+    let aa_range = compute_aa_range(rect.size.0 / local_size.0);
+
+    let pixels = state.pixel_rect(&rect);
+    for y in pixels.origin.y .. pixels.max_y() {
+        for x in pixels.origin.x .. pixels.max_x() {
+            let local_pos = (
+                (x as f32 + 0.5 - rect.p0.0) / rect.size.0 * local_size.0,
+                (y as f32 + 0.5 - rect.p0.1) / rect.size.1 * local_size.1,
+            );
+            let alpha = path_coverage(data, chunks_address, segment_count, fill_rule, local_pos, aa_range);
+            let color = if paint_kind == PATH_PAINT_GRADIENT {
+                let dir = (paint_data[2] - paint_data[0], paint_data[3] - paint_data[1]);
+                let offset = ((local_pos.0 - paint_data[0]) * dir.0 + (local_pos.1 - paint_data[1]) * dir.1) /
+                    (dir.0 * dir.0 + dir.1 * dir.1).max(1.0e-6);
+                sample_gradient(data, instance.aPathStopsAddress, offset, gradient_repeat)
+            } else {
+                paint_data
+            };
+            target.write(state, x, y, 0.0, scale(color, alpha), [0.0; 4]);
+        }
+    }
+}
+
+/// An input of cs_svg_filter.glsl, which may have been drawn at a
+/// different size than the filter task.
+struct FilterInput<'a> {
+    texture: Option<(&'a SoftwareImage, TextureFilter)>,
+    rect: Rect,
+    layer: usize,
+    // The size of a target pixel in texels of the input.
+    scale: (f32, f32),
+    // The color space to convert the input to, if any.
+    convert_to_linear: Option<bool>,
+}
+
+impl<'a> FilterInput<'a> {
+    fn new(
+        task: RenderTaskData,
+        texture: Option<(&'a SoftwareImage, TextureFilter)>,
+        target_rect: &Rect,
+        convert_to_linear: Option<bool>,
+    ) -> Self {
+        FilterInput {
+            texture,
+            rect: task.task_rect,
+            layer: task.layer,
+            scale: (task.task_rect.size.0 / target_rect.size.0, task.task_rect.size.1 / target_rect.size.1),
+            convert_to_linear,
+        }
+    }
+
+    /// Samples the input at the interpolation factors of a target pixel,
+    /// shifted by an offset in target pixels. Inputs are transparent black
+    /// outside of their task rect.
+    fn sample(&self, f: (f32, f32), offset: (f32, f32)) -> Vec4 {
+        let (texture, filter) = match self.texture {
+            Some(texture) => texture,
+            None => return [0.0; 4],
+        };
+        let p1 = self.rect.p1();
+        let uv = (
+            self.rect.p0.0 + self.rect.size.0 * f.0 + offset.0 * self.scale.0,
+            self.rect.p0.1 + self.rect.size.1 * f.1 + offset.1 * self.scale.1,
+        );
+        if uv.0 < self.rect.p0.0 || uv.1 < self.rect.p0.1 || uv.0 > p1.0 || uv.1 > p1.1 {
+            return [0.0; 4];
+        }
+        let color = texture.sample(self.layer, uv.0, uv.1, filter);
+        match self.convert_to_linear {
+            Some(to_linear) => convert_color_space(color, to_linear),
+            None => color,
+        }
+    }
+}
+
+fn unpremultiply(color: Vec4) -> Vec4 {
+    if color[3] > 0.0 {
+        [color[0] / color[3], color[1] / color[3], color[2] / color[3], color[3]]
+    } else {
+        [0.0; 4]
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    let c = c.max(0.0).min(1.0);
+    if c < 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    let c = c.max(0.0).min(1.0);
+    if c < 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
+/// Converts a premultiplied color between sRGB and linearRGB.
+fn convert_color_space(color: Vec4, to_linear: bool) -> Vec4 {
+    if color[3] == 0.0 {
+        return color;
+    }
+    let mut result = color;
+    for i in 0 .. 3 {
+        let c = color[i] / color[3];
+        let c = if to_linear { srgb_to_linear(c) } else { linear_to_srgb(c) };
+        result[i] = c * color[3];
+    }
+    result
+}
+
+/// See `composite` in cs_svg_filter.glsl.
+fn composite_colors(cs: Vec4, cb: Vec4, op: i32, k: Vec4) -> Vec4 {
+    let mut result = [0.0; 4];
+    for i in 0 .. 4 {
+        result[i] = match op {
+            COMPOSITE_OVER => cs[i] + cb[i] * (1.0 - cs[3]),
+            COMPOSITE_IN => cs[i] * cb[3],
+            COMPOSITE_OUT => cs[i] * (1.0 - cb[3]),
+            COMPOSITE_ATOP => cs[i] * cb[3] + cb[i] * (1.0 - cs[3]),
+            COMPOSITE_XOR => cs[i] * (1.0 - cb[3]) + cb[i] * (1.0 - cs[3]),
+            COMPOSITE_ARITHMETIC => {
+                (k[0] * cs[i] * cb[i] + k[1] * cs[i] + k[2] * cb[i] + k[3]).max(0.0).min(1.0)
+            }
+            _ => 0.0,
+        };
+    }
+    if op == COMPOSITE_ARITHMETIC {
+        for i in 0 .. 3 {
+            result[i] = result[i].min(result[3]);
+        }
+    }
+    result
+}
+
+/// See `blend` in cs_svg_filter.glsl.
+fn blend_filter_colors(cs: Vec4, cb: Vec4, op: i32) -> Vec4 {
+    let s = unpremultiply(cs);
+    let b = unpremultiply(cb);
+    let mixed = blend_colors(op, [b[0], b[1], b[2]], [s[0], s[1], s[2]]);
+    let mut result = [0.0, 0.0, 0.0, cs[3] + cb[3] * (1.0 - cs[3])];
+    for i in 0 .. 3 {
+        result[i] = (1.0 - cb[3]) * cs[i] + (1.0 - cs[3]) * cb[i] + cs[3] * cb[3] * mixed[i];
+    }
+    result
+}
+
+/// The noise function of the reference implementation in the SVG
+/// specification for all four channels, see `noise2` in cs_svg_filter.glsl.
+fn turbulence_noise(p: (f32, f32), data: &ShaderData, address: i32) -> Vec4 {
+    let lattice = |index: i32| {
+        let block = data.resource_cache(address + TURBULENCE_LATTICE_OFFSET + index / 4);
+        block[(index % 4) as usize] as i32
+    };
+    let gradient = |channel: i32, index: i32| {
+        let block = data.resource_cache(
+            address + TURBULENCE_GRADIENT_OFFSET + channel * TURBULENCE_GRADIENT_BLOCKS + index / 2
+        );
+        if index % 2 == 0 { (block[0], block[1]) } else { (block[2], block[3]) }
+    };
+    let dot = |a: (f32, f32), b: (f32, f32)| a.0 * b.0 + a.1 * b.1;
+
+    let t = (p.0 + 4096.0, p.1 + 4096.0);
+    let b0 = (t.0 as i32 & 255, t.1 as i32 & 255);
+    let b1 = ((b0.0 + 1) & 255, (b0.1 + 1) & 255);
+    let r0 = (fract(t.0), fract(t.1));
+    let r1 = (r0.0 - 1.0, r0.1 - 1.0);
+
+    let i = lattice(b0.0);
+    let j = lattice(b1.0);
+    let b00 = lattice(i + b0.1);
+    let b10 = lattice(j + b0.1);
+    let b01 = lattice(i + b1.1);
+    let b11 = lattice(j + b1.1);
+
+    let s = (r0.0 * r0.0 * (3.0 - 2.0 * r0.0), r0.1 * r0.1 * (3.0 - 2.0 * r0.1));
+
+    let mut result = [0.0; 4];
+    for channel in 0 .. 4 {
+        let u = dot(r0, gradient(channel, b00));
+        let v = dot((r1.0, r0.1), gradient(channel, b10));
+        let a = u + (v - u) * s.0;
+        let u = dot((r0.0, r1.1), gradient(channel, b01));
+        let v = dot(r1, gradient(channel, b11));
+        let b = u + (v - u) * s.0;
+        result[channel as usize] = a + (b - a) * s.1;
+    }
+    result
+}
+
+/// See `turbulence` in cs_svg_filter.glsl.
+fn turbulence(point: (f32, f32), params: Vec4, fractal_noise: bool, data: &ShaderData, address: i32) -> Vec4 {
+    let num_octaves = (params[2] as i32).min(MAX_TURBULENCE_OCTAVES);
+    let mut p = (point.0 * params[0], point.1 * params[1]);
+    let mut sum = [0.0; 4];
+    let mut ratio = 1.0;
+    for _ in 0 .. num_octaves {
+        let n = turbulence_noise(p, data, address);
+        for i in 0 .. 4 {
+            sum[i] += (if fractal_noise { n[i] } else { n[i].abs() }) / ratio;
+        }
+        p = (p.0 * 2.0, p.1 * 2.0);
+        ratio *= 2.0;
+    }
+
+    let mut color = [0.0; 4];
+    for i in 0 .. 4 {
+        let c = if fractal_noise { (sum[i] + 1.0) * 0.5 } else { sum[i] };
+        color[i] = c.max(0.0).min(1.0);
+    }
+    [color[0] * color[3], color[1] * color[3], color[2] * color[3], color[3]]
+}
+
+/// See cs_svg_filter.glsl.
+fn draw_svg_filter(
+    instance: &SvgFilterInstance,
+    target: &mut Target,
+    data: &ShaderData,
+    textures: &ShaderTextures,
+    state: &DrawState,
+) {
+    let task = data.render_task(instance.aFilterRenderTaskAddress);
+    let target_rect = task.task_rect;
+    if target_rect.is_empty() {
+        return;
+    }
+    let origin = (task.data1[0], task.data1[1]);
+    let device_pixel_scale = task.data1[2];
+    let flags = instance.aFilterFlags;
+    let linear_rgb = flags & FILTER_FLAG_LINEAR_RGB != 0;
+    let input1 = FilterInput::new(
+        data.render_task(instance.aFilterInput1TaskAddress),
+        textures.color0,
+        &target_rect,
+        if flags & FILTER_FLAG_CONVERT_INPUT_1 != 0 { Some(linear_rgb) } else { None },
+    );
+    let input2 = FilterInput::new(
+        data.render_task(instance.aFilterInput2TaskAddress),
+        textures.color1,
+        &target_rect,
+        if flags & FILTER_FLAG_CONVERT_INPUT_2 != 0 { Some(linear_rgb) } else { None },
+    );
+
+    let kind = instance.aFilterKind;
+    let generic_int = instance.aFilterGenericInt;
+    let address = instance.aFilterExtraDataAddress;
+    // Distances in the extra data are in local units, so
+    // scale them to device pixels here.
+    let filter_data = match kind {
+        FILTER_FLOOD | FILTER_COMPOSITE | FILTER_TURBULENCE => data.resource_cache(address),
+        FILTER_OFFSET | FILTER_MORPHOLOGY | FILTER_DISPLACEMENT_MAP => {
+            scale(data.resource_cache(address), device_pixel_scale)
+        }
+        _ => [0.0; 4],
+    };
+    let (color_matrix, color_offset) = if kind == FILTER_COLOR_MATRIX {
+        let matrix = Mat4([
+            data.resource_cache(address),
+            data.resource_cache(address + 1),
+            data.resource_cache(address + 2),
+            data.resource_cache(address + 3),
+        ]);
+        (matrix, data.resource_cache(address + 4))
+    } else {
+        (Mat4::identity(), [0.0; 4])
+    };
+
+    let pixels = state.pixel_rect(&target_rect);
+    for y in pixels.origin.y .. pixels.max_y() {
+        for x in pixels.origin.x .. pixels.max_x() {
+            let pos = (x as f32 + 0.5 - target_rect.p0.0, y as f32 + 0.5 - target_rect.p0.1);
+            let f = (pos.0 / target_rect.size.0, pos.1 / target_rect.size.1);
+            let result = match kind {
+                FILTER_IDENTITY => input1.sample(f, (0.0, 0.0)),
+                FILTER_FLOOD => filter_data,
+                FILTER_OFFSET => input1.sample(f, (-filter_data[0], -filter_data[1])),
+                FILTER_COLOR_MATRIX => {
+                    let color = color_matrix.mul(unpremultiply(input1.sample(f, (0.0, 0.0))));
+                    let mut result = [0.0; 4];
+                    for i in 0 .. 4 {
+                        result[i] = (color[i] + color_offset[i]).max(0.0).min(1.0);
+                    }
+                    [result[0] * result[3], result[1] * result[3], result[2] * result[3], result[3]]
+                }
+                FILTER_COMPONENT_TRANSFER => {
+                    let color = unpremultiply(input1.sample(f, (0.0, 0.0)));
+                    let mut result = [0.0; 4];
+                    for i in 0 .. 4 {
+                        let k = (color[i] * 255.0 + 0.5) as i32;
+                        result[i] = data.resource_cache(address + k)[i];
+                    }
+                    [result[0] * result[3], result[1] * result[3], result[2] * result[3], result[3]]
+                }
+                FILTER_COMPOSITE => composite_colors(
+                    input1.sample(f, (0.0, 0.0)),
+                    input2.sample(f, (0.0, 0.0)),
+                    generic_int,
+                    filter_data,
+                ),
+                FILTER_BLEND => blend_filter_colors(
+                    input1.sample(f, (0.0, 0.0)),
+                    input2.sample(f, (0.0, 0.0)),
+                    generic_int,
+                ),
+                FILTER_MORPHOLOGY => {
+                    // One pass of the morphology, the rectangle is covered
+                    // by a horizontal pass followed by a vertical one.
+                    let erode = generic_int & 0xff == MORPHOLOGY_ERODE;
+                    let (radius, direction) = if generic_int >> 8 == MORPHOLOGY_VERTICAL {
+                        (filter_data[1], (0.0, 1.0))
+                    } else {
+                        (filter_data[0], (1.0, 0.0))
+                    };
+                    let r = ((radius + 0.5) as i32).max(0);
+                    let mut result = if erode { [1.0; 4] } else { [0.0; 4] };
+                    for i in -r .. r + 1 {
+                        let color = input1.sample(f, (direction.0 * i as f32, direction.1 * i as f32));
+                        for c in 0 .. 4 {
+                            result[c] = if erode { result[c].min(color[c]) } else { result[c].max(color[c]) };
+                        }
+                    }
+                    result
+                }
+                FILTER_TURBULENCE => {
+                    // Position in the local space of the filtered picture.
+                    let user_pos = ((pos.0 - origin.0) / device_pixel_scale, (pos.1 - origin.1) / device_pixel_scale);
+                    turbulence(user_pos, filter_data, generic_int == TURBULENCE_FRACTAL_NOISE, data, address)
+                }
+                FILTER_DISPLACEMENT_MAP => {
+                    let map = unpremultiply(input2.sample(f, (0.0, 0.0)));
+                    let (x_channel, y_channel) = ((generic_int & 0xff) as usize, (generic_int >> 8) as usize);
+                    let offset = (
+                        filter_data[0] * (map[x_channel.min(3)] - 0.5),
+                        filter_data[0] * (map[y_channel.min(3)] - 0.5),
+                    );
+                    input1.sample(f, offset)
+                }
+                // Red if none of the filters match (shouldn't happen).
+                _ => [1.0, 0.0, 0.0, 1.0],
+            };
+            target.write(state, x, y, 0.0, result, [0.0; 4]);
+        }
+    }
+}
+
+/// See cs_clip_rectangle.glsl.
+fn draw_clip_rectangle(
+    instance: &ClipMaskInstance,
+    target: &mut Target,
+    data: &ShaderData,
+    state: &DrawState,
+) {
+    let area = data.clip_area(instance.aClipRenderTaskAddress);
+    let node = data.clip_scroll_node(instance.aScrollNodeId);
+    let (u, v) = (instance.aClipDataResourceAddress[0], instance.aClipDataResourceAddress[1]);
+    let clip_rect = Rect::from_vec4(data.resource_cache_direct(u, v));
+    let clip_mode = data.resource_cache_direct(u + 1, v)[0];
+    let mut radii = [(0.0, 0.0); 4];
+    for (i, radius) in radii.iter_mut().enumerate() {
+        let outer_inner_radius = data.resource_cache_direct(u + 3 + 2 * i as i32, v);
+        *radius = (outer_inner_radius[0], outer_inner_radius[1]);
+    }
+    let aa_range = 1.0 / state.device_pixel_ratio;
+
+    let pixels = state.pixel_rect(&area.task_rect);
+    for y in pixels.origin.y .. pixels.max_y() {
+        for x in pixels.origin.x .. pixels.max_x() {
+            let local_pos = clip_local_pos(&area, &node, x, y, state);
+            let coverage = rounded_rect_coverage(local_pos, &clip_rect, &radii, aa_range);
+            let alpha = coverage + (1.0 - 2.0 * coverage) * clip_mode;
+            target.write(state, x, y, 0.0, [alpha, 0.0, 0.0, 1.0], [0.0; 4]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns an `RGBAF32` image, like the data textures, with the given texels set.
+    fn data_image(width: u32, height: u32, texels: &[(i32, i32, Vec4)]) -> SoftwareImage {
+        let mut image = SoftwareImage::new(ImageFormat::RGBAF32, width, height, 1);
+        for &(x, y, texel) in texels {
+            image.store(0, x, y, texel);
+        }
+        image
+    }
+
+    /// A draw state mapping the render target space to the pixels of a `width` x `height` target.
+    fn draw_state(width: i32, height: i32) -> DrawState {
+        let rect = DeviceIntRect::new(DeviceIntPoint::zero(), DeviceIntSize::new(width, height));
+        DrawState {
+            transform: Mat4([
+                [2.0 / width as f32, 0.0, 0.0, 0.0],
+                [0.0, 2.0 / height as f32, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [-1.0, -1.0, 0.0, 1.0],
+            ]),
+            viewport: rect,
+            scissor: rect,
+            blend: BlendState::Off,
+            blend_color: [0.0; 4],
+            depth: DepthTest::Off,
+            device_pixel_ratio: 1.0,
+            mode: 0,
+            alpha_pass: false,
+        }
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-3, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn distance_aa_matches_shader() {
+        assert_eq!(distance_aa(0.5, -1.0), 1.0);
+        assert_eq!(distance_aa(0.5, 1.0), 0.0);
+        assert_near(distance_aa(0.5, 0.0), 0.5);
+        assert_near(distance_aa(0.5, 0.25), 0.5 + 0.25 * (0.8431027 * 0.0625 - 1.14453603));
+    }
+
+    #[test]
+    fn picture_transform_scales_content() {
+        let task = RenderTaskData {
+            task_rect: Rect { p0: (100.0, 50.0), size: (200.0, 100.0) },
+            layer: 0,
+            data1: [400.0, 200.0, 0.5],
+        };
+        let transform = PictureTransform::new(&task);
+        assert_eq!(transform.apply((400.0, 200.0)), (100.0, 50.0));
+        assert_eq!(transform.apply((800.0, 400.0)), (300.0, 150.0));
+        assert_eq!(transform.offset((2.0, 4.0)).apply((398.0, 196.0)), (100.0, 50.0));
+    }
+
+    #[test]
+    fn radial_gradient_offset_of_circle() {
+        assert_near(radial_gradient_offset((5.0, 0.0), 0.0, 10.0).unwrap(), 0.5);
+        assert_near(radial_gradient_offset((0.0, 15.0), 5.0, 15.0).unwrap(), 1.0);
+        // Both radii are zero, the gradient is empty.
+        assert!(radial_gradient_offset((1.0, 0.0), 0.0, 0.0).is_none());
+    }
+
+    #[test]
+    fn linear_gradient_samples_lut() {
+        // Entry `k` of the table fades from `k` to `k + 1` in the red channel,
+        // so the sampled red is the position in the table.
+        let stops_address = 100;
+        let mut texels = vec![
+            (0, 0, [0.0, 0.0, 10.0, 0.0]),
+            (1, 0, [EXTEND_MODE_CLAMP as f32, 10.0, 10.0, 0.0]),
+        ];
+        for k in 0 .. GRADIENT_ENTRIES + 2 {
+            texels.push((stops_address + 2 * k, 0, [k as f32, 0.0, 0.0, 1.0]));
+            texels.push((stops_address + 2 * k + 1, 0, [k as f32 + 1.0, 0.0, 0.0, 1.0]));
+        }
+        let cache = data_image(MAX_VERTEX_TEXTURE_WIDTH as u32, 1, &texels);
+        let data = ShaderData {
+            resource_cache: Some(&cache),
+            render_tasks: None,
+            clip_scroll_nodes: None,
+            local_clip_rects: None,
+        };
+        let rect = Rect { p0: (0.0, 0.0), size: (10.0, 10.0) };
+        let gradient = GradientBrush::new(
+            ProgramKind::BrushLinearGradient,
+            0,
+            rect,
+            rect,
+            [stops_address, 0, 0],
+            0,
+            [0.0; 4],
+            &data,
+        );
+        let state = draw_state(10, 10);
+
+        let color = gradient.shade((5.5, 3.0), &data, &state).unwrap();
+        assert_near(color[0], 1.0 + 0.55 * GRADIENT_ENTRIES as f32);
+        // Clamped before the start of the gradient.
+        let color = gradient.shade((0.0, 3.0), &data, &state).unwrap();
+        assert_near(color[0], 1.0);
+    }
+
+    #[test]
+    fn path_fill_rules() {
+        // Two overlapping squares drawn in the same direction, the
+        // overlap has a winding number of two.
+        let squares = [(0.0, 0.0, 10.0), (5.0, 5.0, 10.0)];
+        let mut texels = vec![(0, 0, [10.0, 0.0, 0.0, 0.0])];
+        for (i, &(x, y, size)) in squares.iter().enumerate() {
+            let corners = [(x, y), (x + size, y), (x + size, y + size), (x, y + size)];
+            for j in 0 .. 4 {
+                let (from, to) = (corners[j], corners[(j + 1) % 4]);
+                texels.push((10 + 4 * i as i32 + j as i32, 0, [from.0, from.1, to.0, to.1]));
+            }
+        }
+        let cache = data_image(MAX_VERTEX_TEXTURE_WIDTH as u32, 1, &texels);
+        let data = ShaderData {
+            resource_cache: Some(&cache),
+            render_tasks: None,
+            clip_scroll_nodes: None,
+            local_clip_rects: None,
+        };
+        let coverage = |pos, fill_rule| path_coverage(&data, (0, 0), 8, fill_rule, pos, 0.5);
+
+        assert_eq!(coverage((2.0, 2.0), 0), 1.0);
+        assert_eq!(coverage((7.0, 7.0), 0), 1.0);
+        assert_eq!(coverage((7.0, 7.0), FILL_RULE_EVEN_ODD), 0.0);
+        assert_eq!(coverage((12.0, 2.0), 0), 0.0);
+        // Anti-aliased along the edges.
+        assert_near(coverage((2.0, 0.0), 0), 0.5);
+    }
+
+    #[test]
+    fn separable_blend_modes() {
+        let (cb, cs) = ([0.5, 0.25, 1.0], [0.5, 1.0, 0.0]);
+        assert_eq!(blend_colors(MIX_BLEND_MULTIPLY, cb, cs), [0.25, 0.25, 0.0]);
+        assert_eq!(blend_colors(MIX_BLEND_SCREEN, cb, cs), [0.75, 1.0, 1.0]);
+        assert_eq!(blend_colors(MIX_BLEND_DIFFERENCE, cb, cs), [0.0, 0.75, 1.0]);
+        assert_eq!(blend_colors(MIX_BLEND_DARKEN, cb, cs), [0.5, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn luminosity_blend_mode() {
+        // Takes the luminosity of the source, red has a luminosity of 0.3.
+        let color = blend_colors(MIX_BLEND_LUMINOSITY, [0.5, 0.5, 0.5], [1.0, 0.0, 0.0]);
+        for &c in &color {
+            assert_near(c, 0.3);
+        }
+    }
+
+    #[test]
+    fn solid_border_edge() {
+        let mut image = SoftwareImage::new(ImageFormat::RGBAF32, 8, 8, 1);
+        let state = draw_state(8, 8);
+        let red = [1.0, 0.0, 0.0, 1.0];
+        let instance = BorderInstance {
+            aTaskOrigin: [0.0, 2.0],
+            aRect: [0.0, 0.0, 8.0, 4.0],
+            aColor0: red,
+            aColor1: red,
+            aFlags: BORDER_SEGMENT_TOP | (1 << 8) | (1 << 16),
+            aWidths: [0.0, 4.0],
+            aRadii: [0.0, 0.0],
+            aClipParams1: [0.0; 4],
+            aClipParams2: [0.0; 4],
+        };
+        draw_border_segment(&instance, &mut Target { image: &mut image, layer: 0, depth: None }, &state);
+
+        for y in 0 .. 8 {
+            for x in 0 .. 8 {
+                let expected = if y >= 2 && y < 6 { red } else { [0.0; 4] };
+                assert_eq!(image.fetch(0, x, y), expected, "at {}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn rounded_border_corner() {
+        let mut image = SoftwareImage::new(ImageFormat::RGBAF32, 4, 4, 1);
+        let state = draw_state(4, 4);
+        let instance = BorderInstance {
+            aTaskOrigin: [0.0, 0.0],
+            aRect: [0.0, 0.0, 4.0, 4.0],
+            aColor0: [1.0; 4],
+            aColor1: [1.0; 4],
+            aFlags: BORDER_SEGMENT_TOP_LEFT | (1 << 8) | (1 << 16),
+            aWidths: [2.0, 2.0],
+            aRadii: [4.0, 4.0],
+            aClipParams1: [0.0; 4],
+            aClipParams2: [0.0; 4],
+        };
+        draw_border_segment(&instance, &mut Target { image: &mut image, layer: 0, depth: None }, &state);
+
+        // Outside the outer radius.
+        assert_eq!(image.fetch(0, 0, 0)[3], 0.0);
+        // Between the outer and inner radii.
+        assert!(image.fetch(0, 3, 0)[3] > 0.9);
+        assert!(image.fetch(0, 0, 3)[3] > 0.9);
+        // Inside the inner radius.
+        assert_eq!(image.fetch(0, 3, 3)[3], 0.0);
+    }
+
+    #[test]
+    fn dashed_line_clip() {
+        let cache = data_image(4, 1, &[
+            (0, 0, [0.0, 0.0, 16.0, 2.0]),
+            (1, 0, [0.0, LINE_STYLE_DASHED as f32, LINE_ORIENTATION_HORIZONTAL as f32, 0.0]),
+        ]);
+        // A clip task covering the target, in local space.
+        let tasks = data_image(2, 1, &[
+            (0, 0, [0.0, 0.0, 16.0, 2.0]),
+            (1, 0, [0.0, 0.0, 0.0, 0.0]),
+        ]);
+        let data = ShaderData {
+            resource_cache: Some(&cache),
+            render_tasks: Some(&tasks),
+            clip_scroll_nodes: None,
+            local_clip_rects: None,
+        };
+        let instance = ClipMaskInstance {
+            aClipRenderTaskAddress: 0,
+            aScrollNodeId: 0,
+            aClipSegment: 0,
+            aClipDataResourceAddress: [0, 0, 0, 0],
+        };
+        let mut image = SoftwareImage::new(ImageFormat::RGBAF32, 16, 2, 1);
+        let state = draw_state(16, 2);
+        draw_clip_line(&instance, &mut Target { image: &mut image, layer: 0, depth: None }, &data, &state);
+
+        // The dashes are three times as long as the line is thick.
+        for x in 0 .. 16 {
+            let expected = if x < 6 || x >= 12 { 1.0 } else { 0.0 };
+            assert_eq!(image.fetch(0, x, 1)[0], expected, "at {}", x);
+        }
+    }
+
+    #[test]
+    fn glyph_snap_offset() {
+        let node = ClipScrollNode { transform: Mat4::identity(), inv_transform: Mat4::identity() };
+        let rect = Rect { p0: (10.3, 4.75), size: (5.0, 5.0) };
+        let offset = snap_offset(&node, &rect, (0.5, 0.5), 1.0);
+        assert_near(offset.0, -0.3);
+        assert_near(offset.1, 0.25);
+        let offset = snap_offset(&node, &rect, (0.125, 0.5), 2.0);
+        assert_near(offset.0, -0.6);
+        assert_near(offset.1, 0.5);
+    }
+}
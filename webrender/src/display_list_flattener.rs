@@ -3,32 +3,38 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use api::{AlphaType, BorderDetails, BorderDisplayItem, BuiltDisplayListIter, ClipAndScrollInfo};
+use api::{AlphaType, BorderDetails, BorderDisplayItem, BorderWidths, BuiltDisplayListIter};
+use api::ClipAndScrollInfo;
 use api::{ClipId, ColorF, ComplexClipRegion, DeviceIntPoint, DeviceIntRect, DeviceIntSize};
 use api::{DevicePixelScale, DeviceUintRect, DisplayItemRef, Epoch, ExtendMode, ExternalScrollId};
-use api::{FilterOp, FontInstanceKey, GlyphInstance, GlyphOptions, GlyphRasterSpace, GradientStop};
+use api::{FillRule, FilterOp, FontInstanceKey, FontRenderMode, GlyphInstance, GlyphOptions};
+use api::{GlyphRasterSpace, Gradient, GradientStop, MAX_TEXT_RUN_LENGTH};
 use api::{IframeDisplayItem, ImageKey, ImageRendering, ItemRange, LayoutPoint};
 use api::{LayoutPrimitiveInfo, LayoutRect, LayoutSize, LayoutTransform, LayoutVector2D};
-use api::{LineOrientation, LineStyle, LocalClip, NinePatchBorderSource, PipelineId};
-use api::{PropertyBinding, ReferenceFrame, RepeatMode, ScrollFrameDisplayItem, ScrollSensitivity};
+use api::{LineOrientation, LineStyle, LocalClip, MaskComposite, MaskSource, NinePatchBorderSource};
+use api::{PathCommand, PathDisplayItem, PathPaint, PipelineId};
+use api::{PropertyBinding, ReferenceFrame, ScrollFrameDisplayItem, ScrollSensitivity};
 use api::{Shadow, SpecificDisplayItem, StackingContext, StickyFrameDisplayItem, TexelRect};
-use api::{TransformStyle, YuvColorSpace, YuvData};
+use api::{TextDecorationDisplayItem, TransformStyle, YuvColorSpace, YuvData};
 use app_units::Au;
-use clip::{ClipRegion, ClipSource, ClipSources, ClipStore};
+use box_shadow::BLUR_SAMPLE_SCALE;
+use border::{NinePatchDescriptor, create_nine_patch_segments};
+use clip::{ClipRegion, ClipSource, ClipSources, ClipStore, MaskLayerClipSource};
 use clip_scroll_node::{ClipScrollNode, NodeType, StickyFrameInfo};
 use clip_scroll_tree::{ClipChainIndex, ClipScrollNodeIndex, ClipScrollTree};
-use euclid::{SideOffsets2D, vec2};
+use euclid::SideOffsets2D;
 use frame_builder::{FrameBuilder, FrameBuilderConfig};
 use glyph_rasterizer::FontInstance;
 use gpu_cache::GpuCacheHandle;
-use gpu_types::BrushFlags;
 use hit_test::{HitTestingItem, HitTestingRun};
 use image::simplify_repeated_primitive;
 use internal_types::{FastHashMap, FastHashSet};
+use path::{MAX_PATH_SEGMENTS, flatten_path, flatten_subpaths, segments_bounding_rect, stroke_path};
 use picture::PictureCompositeMode;
+use platform::font::FontContext;
 use prim_store::{BrushClipMaskKind, BrushKind, BrushPrimitive, BrushSegmentDescriptor};
-use prim_store::{EdgeAaSegmentMask, ImageSource};
-use prim_store::{BorderSource, BrushSegment, PictureIndex, PrimitiveContainer, PrimitiveIndex, PrimitiveStore};
+use prim_store::ImageSource;
+use prim_store::{BorderSource, PictureIndex, PrimitiveContainer, PrimitiveIndex, PrimitiveStore};
 use prim_store::{OpacityBinding, ScrollNodeAndClipChain, TextRunPrimitiveCpu};
 use render_backend::{DocumentView};
 use resource_cache::{FontInstanceMap, ImageRequest};
@@ -138,6 +144,17 @@ impl ClipIdToIndexMapper {
     }
 }
 
+/// The glyphs of the last text item, which the text decoration items following
+/// it refer to.
+struct DecoratedText {
+    font_key: FontInstanceKey,
+    /// The builder splits long text items, so one text item may be flattened
+    /// from several consecutive runs.
+    glyph_ranges: Vec<ItemRange<GlyphInstance>>,
+    /// Whether the next text item is the continuation of a split text item.
+    split: bool,
+}
+
 /// A structure that converts a serialized display list into a form that WebRender
 /// can use to later build a frame. This structure produces a FrameBuilder. Public
 /// members are typically those that are destructured into the FrameBuilder.
@@ -175,6 +192,9 @@ pub struct DisplayListFlattener<'a> {
     /// A stack of the currently active shadows
     shadow_stack: Vec<(Shadow, PictureIndex)>,
 
+    /// The text item that following text decoration items decorate.
+    decorated_text: Option<DecoratedText>,
+
     /// A list of scrollbar primitives.
     pub scrollbar_prims: Vec<ScrollbarPrimitive>,
 
@@ -227,6 +247,7 @@ impl<'a> DisplayListFlattener<'a> {
             reference_frame_stack: Vec::new(),
             picture_stack: Vec::new(),
             shadow_stack: Vec::new(),
+            decorated_text: None,
             sc_stack: Vec::new(),
             prim_store: old_builder.prim_store.recycle(),
             clip_store: old_builder.clip_store.recycle(),
@@ -268,6 +289,17 @@ impl<'a> DisplayListFlattener<'a> {
         self.scene.get_display_list_for_pipeline(pipeline_id).get(complex_clips).collect()
     }
 
+    fn get_path(
+        &self,
+        pipeline_id: PipelineId,
+        path: ItemRange<PathCommand>,
+    ) -> Vec<PathCommand> {
+        if path.is_empty() {
+            return vec![];
+        }
+        self.scene.get_display_list_for_pipeline(pipeline_id).get(path).collect()
+    }
+
     fn get_clip_chain_items(
         &self,
         pipeline_id: PipelineId,
@@ -293,6 +325,7 @@ impl<'a> DisplayListFlattener<'a> {
         self.push_stacking_context(
             pipeline_id,
             CompositeOps::default(),
+            Vec::new(),
             TransformStyle::Flat,
             true,
             true,
@@ -311,7 +344,7 @@ impl<'a> DisplayListFlattener<'a> {
                     self.add_solid_rectangle(
                         reference_frame_info,
                         &info,
-                        bg_color,
+                        PropertyBinding::Value(bg_color),
                         None,
                         Vec::new(),
                     );
@@ -432,6 +465,7 @@ impl<'a> DisplayListFlattener<'a> {
             &frame_rect,
             &content_rect.size,
             info.scroll_sensitivity,
+            info.scroll_offset,
         );
     }
 
@@ -474,18 +508,64 @@ impl<'a> DisplayListFlattener<'a> {
             return;
         }
 
-        let composition_operations = {
+        let (composition_operations, backdrop_filters, mask) = {
             // TODO(optimization?): self.traversal.display_list()
             let display_list = self.scene.get_display_list_for_pipeline(pipeline_id);
-            CompositeOps::new(
+            let composition_operations = CompositeOps::new(
                 stacking_context.filter_ops_for_compositing(display_list, item.filters()),
                 stacking_context.mix_blend_mode_for_compositing(),
-            )
+                display_list.get(item.filter_primitives()).collect(),
+            );
+            let backdrop_filters =
+                stacking_context.filter_ops_for_compositing(display_list, item.backdrop_filters());
+
+            // The mask layers are in the same space as the contents
+            // of the stacking context.
+            let mask_offset = reference_frame_relative_offset + item.rect().origin.to_vector();
+            let mut mask_stops = display_list.get(item.mask_stops());
+            let mut mask = Vec::new();
+            for layer in display_list.get(item.mask_layers()) {
+                let layer_stops: Vec<GradientStop> = match layer.source {
+                    MaskSource::Gradient { stop_count, .. } => {
+                        mask_stops.by_ref().take(stop_count).collect()
+                    }
+                    MaskSource::Image(..) => Vec::new(),
+                };
+                if layer.tile_size.width <= 0.0 || layer.tile_size.height <= 0.0 {
+                    continue;
+                }
+                // The bottom layer is drawn over an opaque clip mask.
+                let composite = if mask.is_empty() {
+                    MaskComposite::Intersect
+                } else {
+                    layer.composite
+                };
+                mask.push(ClipSource::MaskLayer(MaskLayerClipSource::new(
+                    &layer,
+                    &layer_stops,
+                    composite,
+                    &mask_offset,
+                )));
+            }
+
+            (composition_operations, backdrop_filters, mask)
         };
 
+        if !backdrop_filters.is_empty() {
+            self.add_backdrop_filters(
+                pipeline_id,
+                &backdrop_filters,
+                &item.rect().translate(&reference_frame_relative_offset),
+                scroll_node_id,
+                stacking_context.clip_node_id,
+                is_backface_visible,
+            );
+        }
+
         self.push_stacking_context(
             pipeline_id,
             composition_operations,
+            mask,
             stacking_context.transform_style,
             is_backface_visible,
             false,
@@ -553,6 +633,7 @@ impl<'a> DisplayListFlattener<'a> {
             &iframe_rect,
             &pipeline.content_size,
             ScrollSensitivity::ScriptAndInputEvents,
+            None,
         );
 
         self.flatten_root(pipeline, &iframe_rect.size);
@@ -570,6 +651,12 @@ impl<'a> DisplayListFlattener<'a> {
         let clip_and_scroll = self.id_to_index_mapper.map_clip_and_scroll(&clip_and_scroll_ids);
 
         let prim_info = item.get_layout_primitive_info(&reference_frame_relative_offset);
+        match *item.item() {
+            SpecificDisplayItem::Text(..) |
+            SpecificDisplayItem::TextDecoration(..) => {}
+            _ => self.decorated_text = None,
+        }
+
         match *item.item() {
             SpecificDisplayItem::Image(ref info) => {
                 self.add_image(
@@ -593,16 +680,54 @@ impl<'a> DisplayListFlattener<'a> {
                 );
             }
             SpecificDisplayItem::Text(ref text_info) => {
+                let glyph_range = item.glyphs();
+                let split = item.display_list().get(glyph_range).len() == MAX_TEXT_RUN_LENGTH;
+                let is_continuation = self.decorated_text.as_ref().map_or(false, |text| {
+                    text.split && text.font_key == text_info.font_key
+                });
+                if !is_continuation {
+                    self.decorated_text = Some(DecoratedText {
+                        font_key: text_info.font_key,
+                        glyph_ranges: Vec::new(),
+                        split: false,
+                    });
+                }
+                if let Some(ref mut text) = self.decorated_text {
+                    text.glyph_ranges.push(glyph_range);
+                    text.split = split;
+                }
+
                 self.add_text(
                     clip_and_scroll,
                     reference_frame_relative_offset,
                     &prim_info,
                     &text_info.font_key,
                     &text_info.color,
-                    item.glyphs(),
+                    glyph_range,
                     text_info.glyph_options,
                 );
             }
+            SpecificDisplayItem::TextDecoration(ref info) => {
+                let (font_key, glyph_ranges) = match self.decorated_text {
+                    Some(ref mut text) => {
+                        text.split = false;
+                        (text.font_key, text.glyph_ranges.clone())
+                    }
+                    None => {
+                        warn!("Text decoration without a text item");
+                        return None;
+                    }
+                };
+
+                self.add_text_decoration(
+                    clip_and_scroll,
+                    reference_frame_relative_offset,
+                    &prim_info,
+                    info,
+                    font_key,
+                    glyph_ranges,
+                );
+            }
             SpecificDisplayItem::Rectangle(ref info) => {
                 self.add_solid_rectangle(
                     clip_and_scroll,
@@ -628,6 +753,18 @@ impl<'a> DisplayListFlattener<'a> {
                     info.style,
                 );
             }
+            SpecificDisplayItem::Path(ref info) => {
+                self.add_path(
+                    clip_and_scroll,
+                    &prim_info,
+                    pipeline_id,
+                    info,
+                    item.path(),
+                    item.dashes(),
+                    item.gradient_stops(),
+                    &reference_frame_relative_offset,
+                );
+            }
             SpecificDisplayItem::Gradient(ref info) => {
                 self.add_gradient(
                     clip_and_scroll,
@@ -654,6 +791,20 @@ impl<'a> DisplayListFlattener<'a> {
                     info.tile_spacing,
                 );
             }
+            SpecificDisplayItem::ConicGradient(ref info) => {
+                self.add_conic_gradient(
+                    clip_and_scroll,
+                    &prim_info,
+                    info.gradient.center,
+                    info.gradient.angle,
+                    info.gradient.start_offset,
+                    info.gradient.end_offset,
+                    item.gradient_stops(),
+                    info.gradient.extend_mode,
+                    info.tile_size,
+                    info.tile_spacing,
+                );
+            }
             SpecificDisplayItem::BoxShadow(ref box_shadow_info) => {
                 let bounds = box_shadow_info
                     .box_bounds
@@ -723,6 +874,16 @@ impl<'a> DisplayListFlattener<'a> {
                 );
                 self.add_clip_node(info.id, clip_and_scroll_ids.scroll_node_id, clip_region);
             }
+            SpecificDisplayItem::ClipPath(ref info) => {
+                let path = self.get_path(pipeline_id, item.path());
+                let clip_region = ClipRegion::create_for_clip_path(
+                    *item.clip_rect(),
+                    &path,
+                    info.fill_rule,
+                    &reference_frame_relative_offset,
+                );
+                self.add_clip_node(info.id, clip_and_scroll_ids.scroll_node_id, clip_region);
+            }
             SpecificDisplayItem::ClipChain(ref info) => {
                 let items = self.get_clip_chain_items(pipeline_id, item.clip_chain_items())
                                 .iter()
@@ -755,7 +916,9 @@ impl<'a> DisplayListFlattener<'a> {
             }
 
             // Do nothing; these are dummy items for the display list parser
-            SpecificDisplayItem::SetGradientStops => {}
+            SpecificDisplayItem::SetGradientStops |
+            SpecificDisplayItem::StartKeyedRange(..) |
+            SpecificDisplayItem::EndKeyedRange => {}
 
             SpecificDisplayItem::PopStackingContext | SpecificDisplayItem::PopReferenceFrame => {
                 unreachable!("Should have returned in parent method.")
@@ -885,6 +1048,7 @@ impl<'a> DisplayListFlattener<'a> {
         &mut self,
         pipeline_id: PipelineId,
         composite_ops: CompositeOps,
+        mask: Vec<ClipSource>,
         transform_style: TransformStyle,
         is_backface_visible: bool,
         is_pipeline_root: bool,
@@ -963,6 +1127,7 @@ impl<'a> DisplayListFlattener<'a> {
         // elements in this context.
         let participating_in_3d_context =
             composite_ops.count() == 0 &&
+            mask.is_empty() &&
             (parent_transform_style == TransformStyle::Preserve3D ||
              transform_style == TransformStyle::Preserve3D);
 
@@ -1009,6 +1174,14 @@ impl<'a> DisplayListFlattener<'a> {
             None
         };
 
+        // The mask is applied as a clip to the outermost picture of the
+        // stacking context, so that it masks the composited result.
+        let mut mask_clip_sources = if mask.is_empty() {
+            None
+        } else {
+            Some(self.clip_store.insert(ClipSources::new(mask)))
+        };
+
         let mut parent_pic_index = if !establishes_3d_context && participating_in_3d_context {
             // If we're in a 3D context, we will parent the picture
             // to the first stacking context we find that is a
@@ -1041,7 +1214,39 @@ impl<'a> DisplayListFlattener<'a> {
                 &LayoutRect::zero(),
                 &max_clip,
                 is_backface_visible,
+                mask_clip_sources.take(),
                 None,
+                PrimitiveContainer::Brush(src_prim),
+            );
+
+            let parent_pic = &mut self.prim_store.pictures[parent_pic_index.0];
+            parent_pic_index = src_pic_index;
+
+            parent_pic.add_primitive(src_prim_index, clip_and_scroll);
+
+            self.picture_stack.push(src_pic_index);
+        }
+
+        // The SVG filter graph is drawn into a single picture, which
+        // the filters above are applied to.
+        if !composite_ops.filter_primitives.is_empty() {
+            let src_pic_index = self.prim_store.add_image_picture(
+                Some(PictureCompositeMode::SvgFilter),
+                false,
+                pipeline_id,
+                current_reference_frame_index,
+                None,
+                true,
+            );
+            self.prim_store.pictures[src_pic_index.0].filter_primitives =
+                composite_ops.filter_primitives.clone();
+
+            let src_prim = BrushPrimitive::new_picture(src_pic_index);
+            let src_prim_index = self.prim_store.add_primitive(
+                &LayoutRect::zero(),
+                &max_clip,
+                is_backface_visible,
+                mask_clip_sources.take(),
                 None,
                 PrimitiveContainer::Brush(src_prim),
             );
@@ -1071,7 +1276,7 @@ impl<'a> DisplayListFlattener<'a> {
                 &LayoutRect::zero(),
                 &max_clip,
                 is_backface_visible,
-                None,
+                mask_clip_sources.take(),
                 None,
                 PrimitiveContainer::Brush(src_prim),
             );
@@ -1096,10 +1301,10 @@ impl<'a> DisplayListFlattener<'a> {
         }
 
         // Force an intermediate surface if the stacking context
-        // has a clip node. In the future, we may decide during
+        // has a clip node or a mask. In the future, we may decide during
         // prepare step to skip the intermediate surface if the
         // clip node doesn't affect the stacking context rect.
-        if participating_in_3d_context || clipping_node.is_some() {
+        if participating_in_3d_context || clipping_node.is_some() || mask_clip_sources.is_some() {
             // TODO(gw): For now, as soon as this picture is in
             //           a 3D context, we draw it to an intermediate
             //           surface and apply plane splitting. However,
@@ -1128,7 +1333,7 @@ impl<'a> DisplayListFlattener<'a> {
             &LayoutRect::zero(),
             &max_clip,
             is_backface_visible,
-            None,
+            mask_clip_sources.take(),
             None,
             PrimitiveContainer::Brush(sc_prim),
         );
@@ -1153,6 +1358,125 @@ impl<'a> DisplayListFlattener<'a> {
         self.sc_stack.push(sc);
     }
 
+    // Add the primitives that draw the given filters, applied to everything
+    // the current picture has drawn so far, clipped to the bounds of the
+    // stacking context that is about to be pushed.
+    fn add_backdrop_filters(
+        &mut self,
+        pipeline_id: PipelineId,
+        filters: &[FilterOp],
+        bounds: &LayoutRect,
+        positioning_node: ClipId,
+        clipping_node: Option<ClipId>,
+        is_backface_visible: bool,
+    ) {
+        let parent_pic_index = *self.picture_stack.last().unwrap();
+
+        // If nothing has been drawn behind this stacking context,
+        // there's nothing to filter.
+        if self.prim_store.pictures[parent_pic_index.0].runs.is_empty() {
+            return;
+        }
+
+        let clip_chain_id = match clipping_node {
+            Some(ref clipping_node) => self.id_to_index_mapper.get_clip_chain_index(clipping_node),
+            None => ClipChainIndex(0), // This means no clipping.
+        };
+        let clip_and_scroll = ScrollNodeAndClipChain::new(
+            self.id_to_index_mapper.get_node_index(positioning_node),
+            clip_chain_id
+        );
+        let current_reference_frame_index = self.current_reference_frame_index();
+        let max_clip = LayoutRect::max_rect();
+
+        // Move everything drawn so far into a picture of its own, so that it
+        // gets rendered to a surface the filters can sample from. The parent
+        // picture then draws that surface as its first primitive.
+        let (parent_pipeline_id, parent_reference_frame_index, runs) = {
+            let parent_pic = &mut self.prim_store.pictures[parent_pic_index.0];
+            (
+                parent_pic.pipeline_id,
+                parent_pic.reference_frame_index,
+                mem::replace(&mut parent_pic.runs, Vec::new()),
+            )
+        };
+
+        let backdrop_pic_index = self.prim_store.add_image_picture(
+            Some(PictureCompositeMode::Blit),
+            false,
+            parent_pipeline_id,
+            parent_reference_frame_index,
+            None,
+            true,
+        );
+        self.prim_store.pictures[backdrop_pic_index.0].runs = runs;
+
+        let backdrop_pic_prim_index = self.prim_store.add_primitive(
+            &LayoutRect::zero(),
+            &max_clip,
+            true,
+            None,
+            None,
+            PrimitiveContainer::Brush(BrushPrimitive::new_picture(backdrop_pic_index)),
+        );
+        self.prim_store.pictures[parent_pic_index.0].add_primitive(
+            backdrop_pic_prim_index,
+            ScrollNodeAndClipChain::new(parent_reference_frame_index, ClipChainIndex(0)),
+        );
+
+        // Blurs need to see the backdrop around the bounds as well, otherwise
+        // the edges of the filtered area would fade out.
+        let inflate_size = filters.iter().fold(0.0, |size, filter| match *filter {
+            FilterOp::Blur(blur_radius) => size + (blur_radius * BLUR_SAMPLE_SCALE).ceil(),
+            _ => size,
+        });
+
+        // For each filter, create a new image with that composite mode, as
+        // for normal stacking context filters. Only the outermost one is
+        // clipped to the stacking context bounds.
+        let mut parent_pic_index = parent_pic_index;
+        let mut clip_rect = *bounds;
+        for filter in filters.iter().rev() {
+            let src_pic_index = self.prim_store.add_image_picture(
+                Some(PictureCompositeMode::Filter(*filter)),
+                false,
+                pipeline_id,
+                current_reference_frame_index,
+                None,
+                true,
+            );
+
+            let src_prim_index = self.prim_store.add_primitive(
+                &LayoutRect::zero(),
+                &clip_rect,
+                is_backface_visible,
+                None,
+                None,
+                PrimitiveContainer::Brush(BrushPrimitive::new_picture(src_pic_index)),
+            );
+
+            self.prim_store.pictures[parent_pic_index.0].add_primitive(src_prim_index, clip_and_scroll);
+            parent_pic_index = src_pic_index;
+            clip_rect = max_clip;
+        }
+
+        let backdrop_brush = BrushPrimitive::new(
+            BrushKind::Backdrop {
+                pic_index: backdrop_pic_index,
+            },
+            None,
+        );
+        let backdrop_prim_index = self.prim_store.add_primitive(
+            &bounds.inflate(inflate_size, inflate_size),
+            &max_clip,
+            is_backface_visible,
+            None,
+            None,
+            PrimitiveContainer::Brush(backdrop_brush),
+        );
+        self.prim_store.pictures[parent_pic_index.0].add_primitive(backdrop_prim_index, clip_and_scroll);
+    }
+
     pub fn pop_stacking_context(&mut self) {
         let sc = self.sc_stack.pop().unwrap();
 
@@ -1257,6 +1581,7 @@ impl<'a> DisplayListFlattener<'a> {
             &LayoutRect::new(LayoutPoint::zero(), *viewport_size),
             content_size,
             ScrollSensitivity::ScriptAndInputEvents,
+            None,
         );
     }
 
@@ -1289,6 +1614,7 @@ impl<'a> DisplayListFlattener<'a> {
         frame_rect: &LayoutRect,
         content_size: &LayoutSize,
         scroll_sensitivity: ScrollSensitivity,
+        offset_binding: Option<PropertyBinding<LayoutVector2D>>,
     ) -> ClipScrollNodeIndex {
         let node_index = self.id_to_index_mapper.get_node_index(new_node_id);
         let node = ClipScrollNode::new_scroll_frame(
@@ -1298,6 +1624,7 @@ impl<'a> DisplayListFlattener<'a> {
             frame_rect,
             content_size,
             scroll_sensitivity,
+            offset_binding,
         );
 
         self.clip_scroll_tree.add_node(node, node_index);
@@ -1369,19 +1696,21 @@ impl<'a> DisplayListFlattener<'a> {
         &mut self,
         clip_and_scroll: ScrollNodeAndClipChain,
         info: &LayoutPrimitiveInfo,
-        color: ColorF,
+        color: PropertyBinding<ColorF>,
         segments: Option<BrushSegmentDescriptor>,
         extra_clips: Vec<ClipSource>,
     ) {
-        if color.a == 0.0 {
-            // Don't add transparent rectangles to the draw list, but do consider them for hit
-            // testing. This allows specifying invisible hit testing areas.
-            self.add_primitive_to_hit_testing_list(info, clip_and_scroll);
-            return;
+        if let PropertyBinding::Value(value) = color {
+            if value.a == 0.0 {
+                // Don't add transparent rectangles to the draw list, but do consider them for hit
+                // testing. This allows specifying invisible hit testing areas.
+                self.add_primitive_to_hit_testing_list(info, clip_and_scroll);
+                return;
+            }
         }
 
         let prim = BrushPrimitive::new(
-            BrushKind::new_solid(color),
+            BrushKind::new_solid_with_binding(color),
             segments,
         );
 
@@ -1485,281 +1814,208 @@ impl<'a> DisplayListFlattener<'a> {
         );
     }
 
-    pub fn add_border(
+    pub fn add_path(
         &mut self,
         clip_and_scroll: ScrollNodeAndClipChain,
         info: &LayoutPrimitiveInfo,
-        border_item: &BorderDisplayItem,
-        gradient_stops: ItemRange<GradientStop>,
+        pipeline_id: PipelineId,
+        path_item: &PathDisplayItem,
+        path_range: ItemRange<PathCommand>,
+        dashes_range: ItemRange<f32>,
+        stops_range: ItemRange<GradientStop>,
+        offset: &LayoutVector2D,
     ) {
-        let rect = info.rect;
-        let create_segments = |outset: SideOffsets2D<f32>| {
-            // Calculate the modified rect as specific by border-image-outset
-            let origin = LayoutPoint::new(rect.origin.x - outset.left, rect.origin.y - outset.top);
-            let size = LayoutSize::new(
-                rect.size.width + outset.left + outset.right,
-                rect.size.height + outset.top + outset.bottom,
-            );
-            let rect = LayoutRect::new(origin, size);
-
-            let tl_outer = LayoutPoint::new(rect.origin.x, rect.origin.y);
-            let tl_inner = tl_outer + vec2(border_item.widths.left, border_item.widths.top);
-
-            let tr_outer = LayoutPoint::new(rect.origin.x + rect.size.width, rect.origin.y);
-            let tr_inner = tr_outer + vec2(-border_item.widths.right, border_item.widths.top);
-
-            let bl_outer = LayoutPoint::new(rect.origin.x, rect.origin.y + rect.size.height);
-            let bl_inner = bl_outer + vec2(border_item.widths.left, -border_item.widths.bottom);
-
-            let br_outer = LayoutPoint::new(
-                rect.origin.x + rect.size.width,
-                rect.origin.y + rect.size.height,
-            );
-            let br_inner = br_outer - vec2(border_item.widths.right, border_item.widths.bottom);
-
-            // Build the list of gradient segments
-            vec![
-                // Top left
-                LayoutRect::from_floats(tl_outer.x, tl_outer.y, tl_inner.x, tl_inner.y),
-                // Top right
-                LayoutRect::from_floats(tr_inner.x, tr_outer.y, tr_outer.x, tr_inner.y),
-                // Bottom right
-                LayoutRect::from_floats(br_inner.x, br_inner.y, br_outer.x, br_outer.y),
-                // Bottom left
-                LayoutRect::from_floats(bl_outer.x, bl_inner.y, bl_inner.x, bl_outer.y),
-                // Top
-                LayoutRect::from_floats(tl_inner.x, tl_outer.y, tr_inner.x, tl_inner.y),
-                // Bottom
-                LayoutRect::from_floats(bl_inner.x, bl_inner.y, br_inner.x, bl_outer.y),
-                // Left
-                LayoutRect::from_floats(tl_outer.x, tl_inner.y, tl_inner.x, bl_inner.y),
-                // Right
-                LayoutRect::from_floats(tr_inner.x, tr_inner.y, br_outer.x, br_inner.y),
-            ]
+        // The path is only drawn inside the item rect.
+        let clip_rect = match info.clip_rect.intersection(&info.rect) {
+            Some(clip_rect) => clip_rect,
+            None => return,
         };
 
-        match border_item.details {
-            BorderDetails::NinePatch(ref border) => {
-                // Calculate the modified rect as specific by border-image-outset
-                let origin = LayoutPoint::new(
-                    rect.origin.x - border.outset.left,
-                    rect.origin.y - border.outset.top,
-                );
-                let size = LayoutSize::new(
-                    rect.size.width + border.outset.left + border.outset.right,
-                    rect.size.height + border.outset.top + border.outset.bottom,
-                );
-                let rect = LayoutRect::new(origin, size);
-
-                // Calculate the local texel coords of the slices.
-                let px0 = 0.0;
-                let px1 = border.slice.left as f32;
-                let px2 = border.width as f32 - border.slice.right as f32;
-                let px3 = border.width as f32;
-
-                let py0 = 0.0;
-                let py1 = border.slice.top as f32;
-                let py2 = border.height as f32 - border.slice.bottom as f32;
-                let py3 = border.height as f32;
+        let (mut segments, fill_rule, stops) = {
+            let display_list = self.scene.get_display_list_for_pipeline(pipeline_id);
+            let path: Vec<PathCommand> = display_list.get(path_range).collect();
+
+            // Strokes are drawn by filling their outline.
+            let (segments, fill_rule) = match path_item.stroke {
+                Some(ref style) => {
+                    let dashes: Vec<f32> = display_list.get(dashes_range).collect();
+                    let subpaths = flatten_subpaths(&path, offset);
+                    (stroke_path(&subpaths, style, &dashes), FillRule::Nonzero)
+                }
+                None => {
+                    (flatten_path(&path, offset), path_item.fill_rule)
+                }
+            };
 
-                let tl_outer = LayoutPoint::new(rect.origin.x, rect.origin.y);
-                let tl_inner = tl_outer + vec2(border_item.widths.left, border_item.widths.top);
+            let stops: Vec<GradientStop> = match path_item.paint {
+                PathPaint::Gradient(..) => display_list.get(stops_range).collect(),
+                PathPaint::Color(..) => Vec::new(),
+            };
 
-                let tr_outer = LayoutPoint::new(rect.origin.x + rect.size.width, rect.origin.y);
-                let tr_inner = tr_outer + vec2(-border_item.widths.right, border_item.widths.top);
+            (segments, fill_rule, stops)
+        };
 
-                let bl_outer = LayoutPoint::new(rect.origin.x, rect.origin.y + rect.size.height);
-                let bl_inner = bl_outer + vec2(border_item.widths.left, -border_item.widths.bottom);
+        if segments.len() > MAX_PATH_SEGMENTS {
+            warn!("Path has {} segments, only the first {} are drawn",
+                  segments.len(), MAX_PATH_SEGMENTS);
+            segments.truncate(MAX_PATH_SEGMENTS);
+        }
 
-                let br_outer = LayoutPoint::new(
-                    rect.origin.x + rect.size.width,
-                    rect.origin.y + rect.size.height,
-                );
-                let br_inner = br_outer - vec2(border_item.widths.right, border_item.widths.bottom);
-
-                fn add_segment(
-                    segments: &mut Vec<BrushSegment>,
-                    rect: LayoutRect,
-                    uv_rect: TexelRect,
-                    repeat_horizontal: RepeatMode,
-                    repeat_vertical: RepeatMode
-                ) {
-                    if uv_rect.uv1.x > uv_rect.uv0.x &&
-                       uv_rect.uv1.y > uv_rect.uv0.y {
-
-                        // Use segment relative interpolation for all
-                        // instances in this primitive.
-                        let mut brush_flags = BrushFlags::SEGMENT_RELATIVE;
-
-                        // Enable repeat modes on the segment.
-                        if repeat_horizontal == RepeatMode::Repeat {
-                            brush_flags |= BrushFlags::SEGMENT_REPEAT_X;
-                        }
-                        if repeat_vertical == RepeatMode::Repeat {
-                            brush_flags |= BrushFlags::SEGMENT_REPEAT_Y;
-                        }
-
-                        let segment = BrushSegment::new(
-                            rect,
-                            true,
-                            EdgeAaSegmentMask::empty(),
-                            [
-                                uv_rect.uv0.x,
-                                uv_rect.uv0.y,
-                                uv_rect.uv1.x,
-                                uv_rect.uv1.y,
-                            ],
-                            brush_flags,
-                        );
-
-                        segments.push(segment);
-                    }
-                }
+        let bounds = segments_bounding_rect(&segments);
+        if bounds.size.width <= 0.0 || bounds.size.height <= 0.0 {
+            return;
+        }
 
-                // Build the list of image segments
-                let mut segments = vec![];
+        // Store the path relative to the primitive, so that the rasterized
+        // path can be reused when it moves.
+        let origin = bounds.origin.to_vector();
+        for segment in &mut segments {
+            segment.from -= origin;
+            segment.to -= origin;
+        }
+        let paint = match path_item.paint {
+            PathPaint::Color(color) => PathPaint::Color(color),
+            PathPaint::Gradient(gradient) => PathPaint::Gradient(Gradient {
+                start_point: gradient.start_point + *offset - origin,
+                end_point: gradient.end_point + *offset - origin,
+                ..gradient
+            }),
+        };
 
-                // Top left
-                add_segment(
-                    &mut segments,
-                    LayoutRect::from_floats(tl_outer.x, tl_outer.y, tl_inner.x, tl_inner.y),
-                    TexelRect::new(px0, py0, px1, py1),
-                    RepeatMode::Stretch,
-                    RepeatMode::Stretch
-                );
-                // Top right
-                add_segment(
-                    &mut segments,
-                    LayoutRect::from_floats(tr_inner.x, tr_outer.y, tr_outer.x, tr_inner.y),
-                    TexelRect::new(px2, py0, px3, py1),
-                    RepeatMode::Stretch,
-                    RepeatMode::Stretch
-                );
-                // Bottom right
-                add_segment(
-                    &mut segments,
-                    LayoutRect::from_floats(br_inner.x, br_inner.y, br_outer.x, br_outer.y),
-                    TexelRect::new(px2, py2, px3, py3),
-                    RepeatMode::Stretch,
-                    RepeatMode::Stretch
-                );
-                // Bottom left
-                add_segment(
-                    &mut segments,
-                    LayoutRect::from_floats(bl_outer.x, bl_inner.y, bl_inner.x, bl_outer.y),
-                    TexelRect::new(px0, py2, px1, py3),
-                    RepeatMode::Stretch,
-                    RepeatMode::Stretch
-                );
+        let prim = BrushPrimitive::new(
+            BrushKind::new_path(segments, fill_rule, paint, stops_range, &stops),
+            None,
+        );
 
-                // Center
-                if border.fill {
-                    add_segment(
-                        &mut segments,
-                        LayoutRect::from_floats(tl_inner.x, tl_inner.y, tr_inner.x, bl_inner.y),
-                        TexelRect::new(px1, py1, px2, py2),
-                        border.repeat_horizontal,
-                        border.repeat_vertical
-                    );
-                }
+        let info = LayoutPrimitiveInfo {
+            rect: bounds,
+            clip_rect,
+            .. *info
+        };
 
-                // Add edge segments.
+        self.add_primitive(
+            clip_and_scroll,
+            &info,
+            Vec::new(),
+            PrimitiveContainer::Brush(prim),
+        );
+    }
 
-                // Top
-                add_segment(
-                    &mut segments,
-                    LayoutRect::from_floats(tl_inner.x, tl_outer.y, tr_inner.x, tl_inner.y),
-                    TexelRect::new(px1, py0, px2, py1),
-                    border.repeat_horizontal,
-                    RepeatMode::Stretch,
-                );
-                // Bottom
-                add_segment(
-                    &mut segments,
-                    LayoutRect::from_floats(bl_inner.x, bl_inner.y, br_inner.x, bl_outer.y),
-                    TexelRect::new(px1, py2, px2, py3),
-                    border.repeat_horizontal,
-                    RepeatMode::Stretch,
-                );
-                // Left
-                add_segment(
-                    &mut segments,
-                    LayoutRect::from_floats(tl_outer.x, tl_inner.y, tl_inner.x, bl_inner.y),
-                    TexelRect::new(px0, py1, px1, py2),
-                    RepeatMode::Stretch,
-                    border.repeat_vertical,
-                );
-                // Right
-                add_segment(
-                    &mut segments,
-                    LayoutRect::from_floats(tr_inner.x, tr_inner.y, br_outer.x, br_inner.y),
-                    TexelRect::new(px2, py1, px3, py2),
-                    RepeatMode::Stretch,
-                    border.repeat_vertical,
+    pub fn add_border(
+        &mut self,
+        clip_and_scroll: ScrollNodeAndClipChain,
+        info: &LayoutPrimitiveInfo,
+        border_item: &BorderDisplayItem,
+        gradient_stops: ItemRange<GradientStop>,
+    ) {
+        match border_item.details {
+            BorderDetails::NinePatch(ref border) => {
+                self.add_nine_patch_border(
+                    clip_and_scroll,
+                    info,
+                    &border_item.widths,
+                    &NinePatchDescriptor::from(border),
+                    &border.outset,
+                    border.source,
+                    gradient_stops,
                 );
-                let descriptor = BrushSegmentDescriptor {
-                    segments,
-                    clip_mask_kind: BrushClipMaskKind::Unknown,
-                };
-
-                let prim = PrimitiveContainer::Brush(match border.source {
-                    NinePatchBorderSource::Image(image_key) => {
-                        let source = BorderSource::Image(ImageRequest {
-                            key: image_key,
-                            rendering: ImageRendering::Auto,
-                            tile: None,
-                        });
-
-                        BrushPrimitive::new(
-                            BrushKind::Border {
-                                source
-                            },
-                            Some(descriptor),
-                        )
-                    }
-                });
-
-                self.add_primitive(clip_and_scroll, info, Vec::new(), prim);
             }
             BorderDetails::Normal(ref border) => {
                 self.add_normal_border(info, border, &border_item.widths, clip_and_scroll);
             }
-            BorderDetails::Gradient(ref border) => for segment in create_segments(border.outset) {
-                let segment_rel = segment.origin - rect.origin;
-                let mut info = info.clone();
-                info.rect = segment;
-
-                self.add_gradient(
+            BorderDetails::Gradient(ref border) => {
+                // The gradient covers the whole border, including the outset.
+                let size = outset_rect(&info.rect, &border.outset).size;
+                self.add_nine_patch_border(
                     clip_and_scroll,
-                    &info,
-                    border.gradient.start_point - segment_rel,
-                    border.gradient.end_point - segment_rel,
+                    info,
+                    &border_item.widths,
+                    &NinePatchDescriptor::stretched(size, &border_item.widths),
+                    &border.outset,
+                    NinePatchBorderSource::Gradient(border.gradient),
                     gradient_stops,
-                    border.gradient.extend_mode,
-                    segment.size,
-                    LayoutSize::zero(),
                 );
-            },
+            }
             BorderDetails::RadialGradient(ref border) => {
-                for segment in create_segments(border.outset) {
-                    let segment_rel = segment.origin - rect.origin;
-                    let mut info = info.clone();
-                    info.rect = segment;
+                let size = outset_rect(&info.rect, &border.outset).size;
+                self.add_nine_patch_border(
+                    clip_and_scroll,
+                    info,
+                    &border_item.widths,
+                    &NinePatchDescriptor::stretched(size, &border_item.widths),
+                    &border.outset,
+                    NinePatchBorderSource::RadialGradient(border.gradient),
+                    gradient_stops,
+                );
+            }
+        }
+    }
 
-                    self.add_radial_gradient(
-                        clip_and_scroll,
-                        &info,
-                        border.gradient.center - segment_rel,
-                        border.gradient.start_offset * border.gradient.radius.width,
-                        border.gradient.end_offset * border.gradient.radius.width,
-                        border.gradient.radius.width / border.gradient.radius.height,
+    fn add_nine_patch_border(
+        &mut self,
+        clip_and_scroll: ScrollNodeAndClipChain,
+        info: &LayoutPrimitiveInfo,
+        widths: &BorderWidths,
+        nine_patch: &NinePatchDescriptor,
+        outset: &SideOffsets2D<f32>,
+        source: NinePatchBorderSource,
+        gradient_stops: ItemRange<GradientStop>,
+    ) {
+        let rect = outset_rect(&info.rect, outset);
+
+        // Gradient sources are drawn in the space of the 9-part
+        // image, which the segments map onto the border.
+        let nine_patch_size = LayoutSize::new(nine_patch.width, nine_patch.height);
+
+        // Borders with too many tiles for one primitive are split into
+        // several, which share the source.
+        for segments in create_nine_patch_segments(&rect, widths, nine_patch) {
+            let descriptor = BrushSegmentDescriptor {
+                segments,
+                clip_mask_kind: BrushClipMaskKind::Unknown,
+            };
+
+            let kind = match source {
+                NinePatchBorderSource::Image(image_key) => {
+                    let source = BorderSource::Image(ImageRequest {
+                        key: image_key,
+                        rendering: ImageRendering::Auto,
+                        tile: None,
+                    });
+
+                    BrushKind::Border {
+                        source
+                    }
+                }
+                NinePatchBorderSource::Gradient(gradient) => {
+                    BrushKind::new_linear_gradient(
+                        gradient.start_point,
+                        gradient.end_point,
                         gradient_stops,
-                        border.gradient.extend_mode,
-                        segment.size,
+                        gradient.extend_mode,
+                        nine_patch_size,
                         LayoutSize::zero(),
-                    );
+                    )
                 }
-            }
+                NinePatchBorderSource::RadialGradient(gradient) => {
+                    BrushKind::new_radial_gradient(
+                        gradient.center,
+                        gradient.start_offset * gradient.radius.width,
+                        gradient.end_offset * gradient.radius.width,
+                        gradient.radius.width / gradient.radius.height,
+                        gradient_stops,
+                        gradient.extend_mode,
+                        nine_patch_size,
+                        LayoutSize::zero(),
+                    )
+                }
+            };
+
+            let prim = PrimitiveContainer::Brush(
+                BrushPrimitive::new(kind, Some(descriptor))
+            );
+
+            self.add_primitive(clip_and_scroll, info, Vec::new(), prim);
         }
     }
 
@@ -1781,36 +2037,15 @@ impl<'a> DisplayListFlattener<'a> {
             .. *info
         };
 
-        // Try to ensure that if the gradient is specified in reverse, then so long as the stops
-        // are also supplied in reverse that the rendered result will be equivalent. To do this,
-        // a reference orientation for the gradient line must be chosen, somewhat arbitrarily, so
-        // just designate the reference orientation as start < end. Aligned gradient rendering
-        // manages to produce the same result regardless of orientation, so don't worry about
-        // reversing in that case.
-        let reverse_stops = start_point.x > end_point.x ||
-            (start_point.x == end_point.x && start_point.y > end_point.y);
-
-        // To get reftests exactly matching with reverse start/end
-        // points, it's necessary to reverse the gradient
-        // line in some cases.
-        let (sp, ep) = if reverse_stops {
-            (end_point, start_point)
-        } else {
-            (start_point, end_point)
-        };
-
         let prim = BrushPrimitive::new(
-            BrushKind::LinearGradient {
-                stops_range: stops,
+            BrushKind::new_linear_gradient(
+                start_point,
+                end_point,
+                stops,
                 extend_mode,
-                reverse_stops,
-                start_point: sp,
-                end_point: ep,
-                stops_handle: GpuCacheHandle::new(),
                 stretch_size,
                 tile_spacing,
-                visible_tiles: Vec::new(),
-            },
+            ),
             None,
         );
 
@@ -1840,13 +2075,55 @@ impl<'a> DisplayListFlattener<'a> {
         };
 
         let prim = BrushPrimitive::new(
-            BrushKind::RadialGradient {
-                stops_range: stops,
-                extend_mode,
+            BrushKind::new_radial_gradient(
                 center,
                 start_radius,
                 end_radius,
                 ratio_xy,
+                stops,
+                extend_mode,
+                stretch_size,
+                tile_spacing,
+            ),
+            None,
+        );
+
+        self.add_primitive(
+            clip_and_scroll,
+            &info,
+            Vec::new(),
+            PrimitiveContainer::Brush(prim),
+        );
+    }
+
+    pub fn add_conic_gradient(
+        &mut self,
+        clip_and_scroll: ScrollNodeAndClipChain,
+        info: &LayoutPrimitiveInfo,
+        center: LayoutPoint,
+        angle: f32,
+        start_offset: f32,
+        end_offset: f32,
+        stops: ItemRange<GradientStop>,
+        extend_mode: ExtendMode,
+        stretch_size: LayoutSize,
+        mut tile_spacing: LayoutSize,
+    ) {
+        let mut prim_rect = info.rect;
+        simplify_repeated_primitive(&stretch_size, &mut tile_spacing, &mut prim_rect);
+        let info = LayoutPrimitiveInfo {
+            rect: prim_rect,
+            .. *info
+        };
+
+        let prim = BrushPrimitive::new(
+            BrushKind::ConicGradient {
+                stops_range: stops,
+                extend_mode,
+                center,
+                angle,
+                start_offset,
+                end_offset,
                 stops_handle: GpuCacheHandle::new(),
                 stretch_size,
                 tile_spacing,
@@ -1906,9 +2183,22 @@ impl<'a> DisplayListFlattener<'a> {
                 .default_font_render_mode
                 .limit_by(font_instance.render_mode);
             let mut flags = font_instance.flags;
+            let mut wants_sdf = font_instance.render_mode == FontRenderMode::Sdf;
             if let Some(options) = glyph_options {
                 render_mode = render_mode.limit_by(options.render_mode);
                 flags |= options.flags;
+                wants_sdf |= options.render_mode == FontRenderMode::Sdf;
+            }
+
+            // Distance fields are a way of rasterizing grayscale glyphs rather
+            // than an amount of AA, so they are picked explicitly when asked
+            // for and grayscale AA is allowed. Platforms that can't rasterize
+            // distance fields keep the grayscale glyphs.
+            if wants_sdf && render_mode == FontRenderMode::Alpha {
+                render_mode = FontRenderMode::Sdf;
+            }
+            if render_mode == FontRenderMode::Sdf && !FontContext::supports_sdf() {
+                render_mode = FontRenderMode::Alpha;
             }
 
             let glyph_raster_space = match self.sc_stack.last() {
@@ -1923,8 +2213,10 @@ impl<'a> DisplayListFlattener<'a> {
                 font_instance.bg_color,
                 render_mode,
                 flags,
+                font_instance.palette_index,
                 font_instance.platform_options,
                 font_instance.variations.clone(),
+                font_instance.variation_bindings.clone(),
             );
             TextRunPrimitiveCpu::new(
                 prim_font,
@@ -1944,6 +2236,65 @@ impl<'a> DisplayListFlattener<'a> {
         );
     }
 
+    pub fn add_text_decoration(
+        &mut self,
+        clip_and_scroll: ScrollNodeAndClipChain,
+        run_offset: LayoutVector2D,
+        prim_info: &LayoutPrimitiveInfo,
+        info: &TextDecorationDisplayItem,
+        font_key: FontInstanceKey,
+        glyph_ranges: Vec<ItemRange<GlyphInstance>>,
+    ) {
+        let font = {
+            let instance_map = self.font_instances.read().unwrap();
+            let font_instance = match instance_map.get(&font_key) {
+                Some(instance) => instance,
+                None => {
+                    warn!("Unknown font instance key");
+                    debug!("key={:?}", font_key);
+                    return;
+                }
+            };
+
+            if font_instance.size.0 <= 0 {
+                return;
+            }
+
+            FontInstance::new(
+                font_instance.font_key,
+                font_instance.size,
+                info.color,
+                font_instance.bg_color,
+                font_instance.render_mode,
+                font_instance.flags,
+                font_instance.palette_index,
+                font_instance.platform_options,
+                font_instance.variations.clone(),
+                font_instance.variation_bindings.clone(),
+            )
+        };
+
+        let prim = BrushPrimitive::new(
+            BrushKind::TextDecoration {
+                color: info.color,
+                font,
+                kind: info.kind,
+                thickness: info.thickness,
+                skip_ink: info.skip_ink,
+                glyph_ranges,
+                offset: run_offset,
+            },
+            None,
+        );
+
+        self.add_primitive(
+            clip_and_scroll,
+            prim_info,
+            Vec::new(),
+            PrimitiveContainer::Brush(prim),
+        );
+    }
+
     pub fn add_image(
         &mut self,
         clip_and_scroll: ScrollNodeAndClipChain,
@@ -2036,6 +2387,17 @@ impl<'a> DisplayListFlattener<'a> {
     }
 }
 
+/// The rect of a border, extended by its border-image-outset.
+fn outset_rect(rect: &LayoutRect, outset: &SideOffsets2D<f32>) -> LayoutRect {
+    LayoutRect::new(
+        LayoutPoint::new(rect.origin.x - outset.left, rect.origin.y - outset.top),
+        LayoutSize::new(
+            rect.size.width + outset.left + outset.right,
+            rect.size.height + outset.top + outset.bottom,
+        ),
+    )
+}
+
 pub fn build_scene(config: &FrameBuilderConfig, request: SceneRequest) -> BuiltScene {
 
     let mut clip_scroll_tree = ClipScrollTree::new();
@@ -17,7 +17,8 @@ use picture::PictureSurface;
 use prim_store::{PrimitiveIndex, PrimitiveRun, PrimitiveStore};
 use profiler::{FrameProfileCounters, GpuCacheProfileCounters, TextureCacheProfileCounters};
 use render_backend::FrameId;
-use render_task::{RenderTask, RenderTaskId, RenderTaskLocation, RenderTaskTree};
+use render_task::{PassAssignmentScratch, RenderTask, RenderTaskId, RenderTaskLocation};
+use render_task::RenderTaskTree;
 use resource_cache::{ResourceCache};
 use scene::{ScenePipeline, SceneProperties};
 use std::{mem, f32};
@@ -47,6 +48,7 @@ pub struct FrameBuilder {
     pub hit_testing_runs: Vec<HitTestingRun>,
     pub config: FrameBuilderConfig,
     pub scrollbar_prims: Vec<ScrollbarPrimitive>,
+    pass_assignment_scratch: PassAssignmentScratch,
 }
 
 pub struct FrameBuildingContext<'a> {
@@ -133,6 +135,7 @@ impl FrameBuilder {
                 dual_source_blending_is_enabled: true,
                 dual_source_blending_is_supported: false,
             },
+            pass_assignment_scratch: PassAssignmentScratch::default(),
         }
     }
 
@@ -153,6 +156,7 @@ impl FrameBuilder {
             window_size,
             scene_id,
             config: flattener.config,
+            pass_assignment_scratch: PassAssignmentScratch::default(),
         }
     }
 
@@ -320,7 +324,7 @@ impl FrameBuilder {
 
         self.update_scroll_bars(clip_scroll_tree, gpu_cache);
 
-        let mut render_tasks = RenderTaskTree::new(frame_id);
+        let mut render_tasks = RenderTaskTree::new(frame_id, resource_cache.max_texture_size());
 
         let screen_size = self.screen_rect.size.to_i32();
         let mut special_render_passes = SpecialRenderPasses::new(&screen_size);
@@ -364,6 +368,7 @@ impl FrameBuilder {
                 main_render_task_id,
                 required_pass_count - 1,
                 &mut passes[2..],
+                &mut self.pass_assignment_scratch,
             );
         }
 
@@ -376,6 +381,7 @@ impl FrameBuilder {
             let mut ctx = RenderTargetContext {
                 device_pixel_scale,
                 prim_store: &self.prim_store,
+                clip_store: &self.clip_store,
                 resource_cache,
                 clip_scroll_tree,
                 use_dual_source_blending,
@@ -4,15 +4,16 @@
 
 use api::{ColorF, ColorU, DevicePoint};
 use api::{FontInstanceFlags, FontInstancePlatformOptions};
-use api::{FontKey, FontRenderMode, FontTemplate, FontVariation};
+use api::{FontKey, FontRenderMode, FontTemplate, FontVariation, FontVariationBinding};
 use api::{GlyphIndex, GlyphDimensions};
 use api::{LayoutPoint, LayoutToWorldTransform, WorldPoint};
 use app_units::Au;
 use euclid::approxeq::ApproxEq;
-use internal_types::ResourceCacheError;
+use internal_types::{FastHashMap, FastHashSet, ResourceCacheError};
 use platform::font::FontContext;
 use rayon::ThreadPool;
 use std::cmp;
+use std::f32;
 use std::hash::{Hash, Hasher};
 use std::mem;
 use std::sync::{Arc, Mutex, MutexGuard};
@@ -28,6 +29,14 @@ pub use self::pathfinder::{ThreadSafePathfinderFontContext, NativeFontHandleWrap
 #[cfg(not(feature = "pathfinder"))]
 mod no_pathfinder;
 
+/// The size in device pixels at which distance field glyphs are rasterized,
+/// independently of the size and transform they are drawn with.
+pub const SDF_GLYPH_SIZE: f32 = 64.0;
+
+/// The distance in pixels, on either side of a glyph's edge, that is encoded
+/// in its distance field. Distance field glyphs are padded by this amount.
+pub const SDF_GLYPH_SPREAD: usize = 8;
+
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
 #[cfg_attr(feature = "capture", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
@@ -159,6 +168,55 @@ impl<'a> From<&'a LayoutToWorldTransform> for FontTransform {
     }
 }
 
+// 'fvar'
+const FVAR_TABLE_TAG: u32 = 0x6676_6172;
+
+// The steps that animated values of variation axes are rounded to, which
+// are below a visible difference for the registered axes. Custom axes can
+// have any scale, so they are split into 200 steps over the range the font
+// declares for them.
+fn variation_quantum(tag: u32, range: Option<(f32, f32)>) -> f32 {
+    match tag {
+        // 'wght', on the 1 to 1000 weight scale.
+        0x77676874 => 5.0,
+        // 'wdth', in percent of the normal width.
+        0x77647468 => 0.5,
+        // 'opsz', in points, and 'slnt', in degrees.
+        0x6F70737A | 0x736C6E74 => 0.25,
+        // 'ital', from 0 to 1.
+        0x6974616C => 0.05,
+        _ => match range {
+            Some((min, max)) if max > min => (max - min) / 200.0,
+            _ => 0.05,
+        },
+    }
+}
+
+/// Reads the tag, minimum and maximum value of the axes in an 'fvar' table.
+fn read_variation_axes(fvar: &[u8]) -> Vec<(u32, f32, f32)> {
+    let read_u16 = |offset: usize| {
+        fvar.get(offset .. offset + 2).map(|bytes| (bytes[0] as u16) << 8 | bytes[1] as u16)
+    };
+    let read_u32 = |offset: usize| {
+        fvar.get(offset .. offset + 4).map(|bytes| {
+            (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32
+        })
+    };
+    // The values are 16.16 fixed point numbers.
+    let read_fixed = |offset: usize| read_u32(offset).map(|value| value as i32 as f32 / 65536.0);
+
+    let (axes_offset, axis_count, axis_size) = match (read_u16(4), read_u16(8), read_u16(10)) {
+        (Some(offset), Some(count), Some(size)) => (offset as usize, count as usize, size as usize),
+        _ => return Vec::new(),
+    };
+    (0 .. axis_count)
+        .filter_map(|index| {
+            let record = axes_offset + index * axis_size;
+            Some((read_u32(record)?, read_fixed(record + 4)?, read_fixed(record + 12)?))
+        })
+        .collect()
+}
+
 #[derive(Clone, Hash, PartialEq, Eq, Debug, Ord, PartialOrd)]
 #[cfg_attr(feature = "capture", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
@@ -174,8 +232,10 @@ pub struct FontInstance {
     pub bg_color: ColorU,
     pub render_mode: FontRenderMode,
     pub flags: FontInstanceFlags,
+    pub palette_index: u16,
     pub platform_options: Option<FontInstancePlatformOptions>,
     pub variations: Vec<FontVariation>,
+    pub variation_bindings: Vec<FontVariationBinding>,
     pub transform: FontTransform,
 }
 
@@ -187,8 +247,10 @@ impl FontInstance {
         bg_color: ColorU,
         render_mode: FontRenderMode,
         flags: FontInstanceFlags,
+        palette_index: u16,
         platform_options: Option<FontInstancePlatformOptions>,
         variations: Vec<FontVariation>,
+        variation_bindings: Vec<FontVariationBinding>,
     ) -> Self {
         // If a background color is enabled, it only makes sense
         // for it to be completely opaque.
@@ -201,8 +263,10 @@ impl FontInstance {
             bg_color,
             render_mode,
             flags,
+            palette_index,
             platform_options,
             variations,
+            variation_bindings,
             transform: FontTransform::identity(),
         }
     }
@@ -225,7 +289,34 @@ impl FontInstance {
 
     pub fn use_subpixel_position(&self) -> bool {
         self.flags.contains(FontInstanceFlags::SUBPIXEL_POSITION) &&
-        self.render_mode != FontRenderMode::Mono
+        self.render_mode != FontRenderMode::Mono &&
+        self.render_mode != FontRenderMode::Sdf
+    }
+
+    /// Distance field glyphs don't depend on the size and transform they are
+    /// drawn with, so they are all requested at `SDF_GLYPH_SIZE` without a
+    /// transform and shared between every instance of the font.
+    pub fn to_sdf_instance(&self) -> Self {
+        let mut font = self.clone();
+        font.size = Au::from_f32_px(SDF_GLYPH_SIZE);
+        font.transform = FontTransform::identity();
+        font.disable_subpixel_position();
+        font
+    }
+
+    /// Sets the value of an animated variation axis. The value is rounded to
+    /// the quantum of the axis, see `GlyphRasterizer::get_variation_quantum`,
+    /// so that glyphs rasterized for nearby positions of an animation share
+    /// the same cache entries.
+    pub fn set_animated_variation(&mut self, tag: u32, value: f32, quantum: f32) {
+        let variation = FontVariation {
+            tag,
+            value: (value / quantum).round() * quantum,
+        };
+        match self.variations.iter().position(|v| v.tag == tag) {
+            Some(index) => self.variations[index] = variation,
+            None => self.variations.push(variation),
+        }
     }
 
     pub fn get_subpx_dir(&self) -> SubpixelDirection {
@@ -255,6 +346,7 @@ impl FontInstance {
         match self.render_mode {
             FontRenderMode::Mono | FontRenderMode::Alpha => self.get_alpha_glyph_format(),
             FontRenderMode::Subpixel => self.get_subpixel_glyph_format(),
+            FontRenderMode::Sdf => GlyphFormat::Sdf,
         }
     }
 
@@ -286,7 +378,8 @@ impl SubpixelDirection {
     pub fn limit_by(self, glyph_format: GlyphFormat) -> Self {
         match glyph_format {
             GlyphFormat::Bitmap |
-            GlyphFormat::ColorBitmap => SubpixelDirection::None,
+            GlyphFormat::ColorBitmap |
+            GlyphFormat::Sdf => SubpixelDirection::None,
             _ => self,
         }
     }
@@ -388,6 +481,7 @@ pub enum GlyphFormat {
     TransformedSubpixel,
     Bitmap,
     ColorBitmap,
+    Sdf,
 }
 
 impl GlyphFormat {
@@ -399,6 +493,156 @@ impl GlyphFormat {
     }
 }
 
+/// Converts an alpha glyph bitmap in BGRA8 format into a signed distance
+/// field, padded by `spread` pixels on each side. Each output pixel stores
+/// the distance to the nearest edge of the glyph, mapped so that 0.5 lies on
+/// the edge and 0.0 and 1.0 lie `spread` pixels outside and inside of it.
+pub fn compute_distance_field(
+    coverage: &[u8],
+    width: usize,
+    height: usize,
+    spread: usize,
+) -> Vec<u8> {
+    let padded_width = width + 2 * spread;
+    let padded_height = height + 2 * spread;
+    let is_inside = |x: usize, y: usize| -> bool {
+        x >= spread && y >= spread && x - spread < width && y - spread < height &&
+            coverage[((y - spread) * width + x - spread) * 4 + 3] >= 0x80
+    };
+
+    // The squared distances from each pixel to the nearest inside and the
+    // nearest outside pixel.
+    let mut to_inside = vec![f32::INFINITY; padded_width * padded_height];
+    let mut to_outside = vec![f32::INFINITY; padded_width * padded_height];
+    for y in 0 .. padded_height {
+        for x in 0 .. padded_width {
+            if is_inside(x, y) {
+                to_inside[y * padded_width + x] = 0.0;
+            } else {
+                to_outside[y * padded_width + x] = 0.0;
+            }
+        }
+    }
+    squared_distance_transform(&mut to_inside, padded_width, padded_height);
+    squared_distance_transform(&mut to_outside, padded_width, padded_height);
+
+    let mut field = vec![0u8; padded_width * padded_height * 4];
+    for y in 0 .. padded_height {
+        for x in 0 .. padded_width {
+            let index = y * padded_width + x;
+            // The edge lies half way between the pixel and the nearest pixel
+            // on the other side of it.
+            let inside = to_inside[index] == 0.0;
+            let nearest = if inside { to_outside[index] } else { to_inside[index] };
+            let distance = (nearest.sqrt() - 0.5).min(spread as f32);
+            let signed = if inside { distance } else { -distance };
+            let value = (0.5 + 0.5 * signed / spread as f32).max(0.0).min(1.0);
+            let value = (value * 255.0).round() as u8;
+
+            let offset = index * 4;
+            field[offset .. offset + 4].copy_from_slice(&[value; 4]);
+        }
+    }
+    field
+}
+
+/// Replaces each value of the grid by the smallest squared euclidean distance
+/// to a zero valued pixel, with the separable transform from Felzenszwalb and
+/// Huttenlocher's "Distance Transforms of Sampled Functions". The grid has to
+/// hold only zeros and infinities.
+fn squared_distance_transform(grid: &mut [f32], width: usize, height: usize) {
+    let len = cmp::max(width, height);
+    let mut values = vec![0.0; len];
+    let mut distances = vec![0.0; len];
+    let mut parabolas = Vec::with_capacity(len);
+    let mut boundaries = Vec::with_capacity(len);
+
+    for x in 0 .. width {
+        for y in 0 .. height {
+            values[y] = grid[y * width + x];
+        }
+        distance_transform_1d(&values[.. height], &mut distances[.. height], &mut parabolas, &mut boundaries);
+        for y in 0 .. height {
+            grid[y * width + x] = distances[y];
+        }
+    }
+    for row in grid.chunks_mut(width) {
+        values[.. width].copy_from_slice(row);
+        distance_transform_1d(&values[.. width], &mut distances[.. width], &mut parabolas, &mut boundaries);
+        row.copy_from_slice(&distances[.. width]);
+    }
+}
+
+/// The one dimensional pass of `squared_distance_transform`, computing the
+/// lower envelope of the parabolas rooted at the finite values.
+fn distance_transform_1d(
+    values: &[f32],
+    distances: &mut [f32],
+    parabolas: &mut Vec<usize>,
+    boundaries: &mut Vec<f32>,
+) {
+    // The vertices of the parabolas of the envelope, with the position
+    // each parabola starts being the lowest at.
+    parabolas.clear();
+    boundaries.clear();
+    for q in 0 .. values.len() {
+        if values[q] == f32::INFINITY {
+            continue;
+        }
+        let qf = q as f32;
+        loop {
+            let p = match parabolas.last() {
+                Some(&p) => p,
+                None => {
+                    parabolas.push(q);
+                    boundaries.push(f32::NEG_INFINITY);
+                    break;
+                }
+            };
+            let pf = p as f32;
+            let intersection = ((values[q] + qf * qf) - (values[p] + pf * pf)) / (2.0 * (qf - pf));
+            if intersection <= *boundaries.last().unwrap() {
+                parabolas.pop();
+                boundaries.pop();
+            } else {
+                parabolas.push(q);
+                boundaries.push(intersection);
+                break;
+            }
+        }
+    }
+
+    if parabolas.is_empty() {
+        for distance in distances.iter_mut() {
+            *distance = f32::INFINITY;
+        }
+        return;
+    }
+    let mut k = 0;
+    for q in 0 .. values.len() {
+        let qf = q as f32;
+        while k + 1 < parabolas.len() && boundaries[k + 1] < qf {
+            k += 1;
+        }
+        let p = parabolas[k];
+        let offset = qf - p as f32;
+        distances[q] = offset * offset + values[p];
+    }
+}
+
+/// Line decoration metrics of a font instance, in pixels. The ascent and descent
+/// are distances from the baseline. Offsets are measured from the baseline to the
+/// center of the line, and are positive downwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontMetrics {
+    pub ascent: f32,
+    pub descent: f32,
+    pub underline_offset: f32,
+    pub underline_thickness: f32,
+    pub strikeout_offset: f32,
+    pub strikeout_thickness: f32,
+}
+
 pub struct RasterizedGlyph {
     pub top: f32,
     pub left: f32,
@@ -474,6 +718,13 @@ pub struct GlyphRasterizer {
     //   a frame where it is used (although it seems unlikely).
     fonts_to_remove: Vec<FontKey>,
 
+    // Fonts with glyphs that have layers drawn in the text color, which
+    // are cached separately for each text color.
+    color_layer_fonts: FastHashSet<FontKey>,
+
+    // The tag, minimum and maximum value of the variation axes of each font.
+    variation_axes: FastHashMap<FontKey, Vec<(u32, f32, f32)>>,
+
     #[allow(dead_code)]
     next_gpu_glyph_cache_key: GpuGlyphCacheKey,
 }
@@ -506,6 +757,8 @@ impl GlyphRasterizer {
             glyph_tx,
             workers,
             fonts_to_remove: Vec::new(),
+            color_layer_fonts: FastHashSet::default(),
+            variation_axes: FastHashMap::default(),
             next_gpu_glyph_cache_key: GpuGlyphCacheKey(0),
         })
     }
@@ -514,9 +767,16 @@ impl GlyphRasterizer {
         let font_contexts = Arc::clone(&self.font_contexts);
         // It's important to synchronously add the font for the shared context because
         // we use it to check that fonts have been properly added when requesting glyphs.
-        font_contexts
-            .lock_shared_context()
-            .add_font(&font_key, &template);
+        {
+            let mut shared_context = font_contexts.lock_shared_context();
+            shared_context.add_font(&font_key, &template);
+            if shared_context.has_color_layers(&font_key) {
+                self.color_layer_fonts.insert(font_key);
+            }
+            if let Some(fvar) = shared_context.get_font_table(&font_key, FVAR_TABLE_TAG) {
+                self.variation_axes.insert(font_key, read_variation_axes(&fvar));
+            }
+        }
 
         // TODO: this locks each font context while adding the font data, probably not a big deal,
         // but if there is contention on this lock we could easily have a queue of per-context
@@ -539,7 +799,23 @@ impl GlyphRasterizer {
     }
 
     pub fn prepare_font(&self, font: &mut FontInstance) {
+        let color = font.color;
         FontContext::prepare_font(font);
+        // The text alpha is applied when drawing color glyphs, so only
+        // the opaque text color is part of the key.
+        if self.color_layer_fonts.contains(&font.font_key) {
+            font.color = ColorU::new(color.r, color.g, color.b, 0xFF);
+        }
+    }
+
+    /// The step animated values of a variation axis of the font are rounded
+    /// to, based on the range of the axis for custom axes.
+    pub fn get_variation_quantum(&self, font_key: FontKey, tag: u32) -> f32 {
+        let range = self.variation_axes
+            .get(&font_key)
+            .and_then(|axes| axes.iter().find(|axis| axis.0 == tag))
+            .map(|&(_, min, max)| (min, max));
+        variation_quantum(tag, range)
     }
 
     pub fn get_glyph_dimensions(
@@ -558,6 +834,12 @@ impl GlyphRasterizer {
             .get_glyph_dimensions(font, &glyph_key)
     }
 
+    pub fn get_font_metrics(&mut self, font: &FontInstance) -> Option<FontMetrics> {
+        self.font_contexts
+            .lock_shared_context()
+            .get_font_metrics(font)
+    }
+
     pub fn get_glyph_index(&mut self, font_key: FontKey, ch: char) -> Option<u32> {
         self.font_contexts
             .lock_shared_context()
@@ -571,6 +853,10 @@ impl GlyphRasterizer {
 
         let font_contexts = Arc::clone(&self.font_contexts);
         let fonts_to_remove = mem::replace(&mut self.fonts_to_remove, Vec::new());
+        for font_key in &fonts_to_remove {
+            self.color_layer_fonts.remove(font_key);
+            self.variation_axes.remove(font_key);
+        }
 
         self.workers.spawn(move || {
             for font_key in &fonts_to_remove {
@@ -635,6 +921,70 @@ struct GlyphRasterJobs {
 
 #[cfg(test)]
 mod test_glyph_rasterizer {
+    #[test]
+    fn distance_field_of_square() {
+        use glyph_rasterizer::compute_distance_field;
+
+        // A 4x4 fully covered square.
+        let coverage = vec![0xFF; 4 * 4 * 4];
+        let field = compute_distance_field(&coverage, 4, 4, 2);
+        let value = |x: usize, y: usize| field[(y * 8 + x) * 4 + 3];
+
+        // The padded field is 8x8, with the square in the middle.
+        assert_eq!(field.len(), 8 * 8 * 4);
+        // Pixels next to the edge sit half a pixel away from it.
+        assert!(value(2, 4) > 0x80 && value(1, 4) < 0x80);
+        assert_eq!(value(2, 4) as i32 - 0x80, 0x7F - value(1, 4) as i32);
+        // The field saturates at the spread.
+        assert_eq!(value(0, 0), 0);
+        // Distances increase towards the middle of the square.
+        assert!(value(3, 3) > value(2, 3));
+    }
+
+    #[test]
+    fn distance_field_matches_exhaustive_search() {
+        use glyph_rasterizer::compute_distance_field;
+
+        // A ring with a notch, so that pixels have their nearest edge in
+        // every direction.
+        let (width, height, spread) = (13, 9, 3);
+        let mut coverage = vec![0u8; width * height * 4];
+        for y in 0 .. height {
+            for x in 0 .. width {
+                let (dx, dy) = (x as i32 - 6, y as i32 - 4);
+                let dist = dx * dx + dy * dy;
+                if dist >= 4 && dist <= 16 && !(dx > 0 && dy == 0) {
+                    coverage[(y * width + x) * 4 + 3] = 0xFF;
+                }
+            }
+        }
+        let field = compute_distance_field(&coverage, width, height, spread);
+
+        let padded_width = width + 2 * spread;
+        let is_inside = |x: i32, y: i32| {
+            let (x, y) = (x - spread as i32, y - spread as i32);
+            x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height &&
+                coverage[(y as usize * width + x as usize) * 4 + 3] >= 0x80
+        };
+        for y in 0 .. (height + 2 * spread) as i32 {
+            for x in 0 .. padded_width as i32 {
+                let inside = is_inside(x, y);
+                let mut nearest = i32::max_value();
+                for oy in -20 .. 21 {
+                    for ox in -20 .. 21 {
+                        if is_inside(x + ox, y + oy) != inside {
+                            nearest = nearest.min(ox * ox + oy * oy);
+                        }
+                    }
+                }
+                let distance = ((nearest as f32).sqrt() - 0.5).min(spread as f32);
+                let signed = if inside { distance } else { -distance };
+                let expected = ((0.5 + 0.5 * signed / spread as f32).max(0.0).min(1.0) * 255.0).round() as u8;
+                assert_eq!(field[(y as usize * padded_width + x as usize) * 4 + 3], expected);
+            }
+        }
+    }
+
     #[test]
     fn rasterize_200_glyphs() {
         // This test loads a font from disc, the renders 4 requests containing
@@ -668,9 +1018,9 @@ mod test_glyph_rasterizer {
         let mut glyph_rasterizer = GlyphRasterizer::new(workers).unwrap();
         let mut glyph_cache = GlyphCache::new();
         let mut gpu_cache = GpuCache::new();
-        let mut texture_cache = TextureCache::new(2048);
+        let mut texture_cache = TextureCache::new(2048, 256);
         let mut render_task_cache = RenderTaskCache::new();
-        let mut render_task_tree = RenderTaskTree::new(FrameId(0));
+        let mut render_task_tree = RenderTaskTree::new(FrameId(0), 2048);
         let mut special_render_passes = SpecialRenderPasses::new(&DeviceIntSize::new(1366, 768));
 
         let mut font_file =
@@ -690,8 +1040,10 @@ mod test_glyph_rasterizer {
             ColorU::new(0, 0, 0, 0),
             FontRenderMode::Subpixel,
             Default::default(),
+            0,
             None,
             Vec::new(),
+            Vec::new(),
         );
         let subpx_dir = font.get_subpx_dir();
 
@@ -721,7 +1073,7 @@ mod test_glyph_rasterizer {
 
         glyph_rasterizer.resolve_glyphs(
             &mut glyph_cache,
-            &mut TextureCache::new(4096),
+            &mut TextureCache::new(4096, 256),
             &mut gpu_cache,
             &mut render_task_cache,
             &mut render_task_tree,
@@ -772,4 +1124,68 @@ mod test_glyph_rasterizer {
         assert_eq!(SubpixelOffset::quantize(-1.625), SubpixelOffset::Half);
         assert_eq!(SubpixelOffset::quantize(-4.33), SubpixelOffset::ThreeQuarters);
     }
+
+    #[test]
+    fn animated_variations_are_quantized() {
+        use api::{ColorF, ColorU, FontKey, FontRenderMode, FontVariation, IdNamespace};
+        use app_units::Au;
+        use glyph_rasterizer::{FontInstance, variation_quantum};
+
+        const WGHT: u32 = 0x77676874;
+        const WDTH: u32 = 0x77647468;
+
+        let mut font = FontInstance::new(
+            FontKey::new(IdNamespace(0), 0),
+            Au::from_px(16),
+            ColorF::new(0.0, 0.0, 0.0, 1.0),
+            ColorU::new(0, 0, 0, 0),
+            FontRenderMode::Alpha,
+            Default::default(),
+            0,
+            None,
+            vec![FontVariation { tag: WDTH, value: 100.0 }],
+            Vec::new(),
+        );
+
+        // Nearby positions of an animation end up as the same instance.
+        let wght_quantum = variation_quantum(WGHT, Some((100.0, 900.0)));
+        font.set_animated_variation(WGHT, 402.0, wght_quantum);
+        let first = font.clone();
+        font.set_animated_variation(WGHT, 398.0, wght_quantum);
+        assert_eq!(font, first);
+        assert_eq!(
+            font.variations,
+            vec![FontVariation { tag: WDTH, value: 100.0 }, FontVariation { tag: WGHT, value: 400.0 }]
+        );
+
+        // Existing axes are replaced rather than added again.
+        font.set_animated_variation(WDTH, 87.6, variation_quantum(WDTH, None));
+        assert_eq!(font.variations[0], FontVariation { tag: WDTH, value: 87.5 });
+        assert_eq!(font.variations.len(), 2);
+    }
+
+    #[test]
+    fn custom_axis_quantum_follows_axis_range() {
+        use glyph_rasterizer::{read_variation_axes, variation_quantum};
+
+        const WGHT: u32 = 0x77676874;
+        const GRAD: u32 = 0x47524144;
+
+        // An 'fvar' header followed by two 20 byte axis records.
+        let mut fvar = vec![0, 1, 0, 0, 0, 16, 0, 2, 0, 2, 0, 20, 0, 0, 0, 0];
+        for &(tag, min, default, max) in &[(WGHT, 100i32, 400i32, 900i32), (GRAD, -200, 0, 150)] {
+            for &value in &[tag, (min << 16) as u32, (default << 16) as u32, (max << 16) as u32] {
+                fvar.extend_from_slice(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]);
+            }
+            fvar.extend_from_slice(&[0, 0, 0, 0]);
+        }
+
+        let axes = read_variation_axes(&fvar);
+        assert_eq!(axes, vec![(WGHT, 100.0, 900.0), (GRAD, -200.0, 150.0)]);
+
+        // Registered axes keep their fixed steps.
+        assert_eq!(variation_quantum(WGHT, Some((100.0, 900.0))), 5.0);
+        assert_eq!(variation_quantum(GRAD, Some((-200.0, 150.0))), 1.75);
+        assert_eq!(variation_quantum(GRAD, None), 0.05);
+    }
 }
@@ -303,7 +303,9 @@ fn request_render_task_from_pathfinder(glyph_key: &GlyphKey,
 
     let root_task_id = render_tasks.add(glyph_render_task);
     let render_pass = match render_mode {
-        FontRenderMode::Mono | FontRenderMode::Alpha => &mut render_passes.alpha_glyph_pass,
+        FontRenderMode::Mono |
+        FontRenderMode::Alpha |
+        FontRenderMode::Sdf => &mut render_passes.alpha_glyph_pass,
         FontRenderMode::Subpixel => &mut render_passes.color_glyph_pass,
     };
     render_pass.add_render_task(root_task_id, *glyph_size, RenderTargetKind::Color);
@@ -121,6 +121,7 @@ impl Renderer {
                                         TextureFilter::Nearest,
                                         Some(RenderTargetInfo {
                                             has_depth: false,
+                                            samples: 1,
                                         }),
                                         1,
                                         None);
@@ -285,6 +286,6 @@ impl VectorCoverInstanceAttrs {
 fn x_scale_for_render_mode(render_mode: FontRenderMode) -> i32 {
     match render_mode {
         FontRenderMode::Subpixel => 3,
-        FontRenderMode::Mono | FontRenderMode::Alpha => 1,
+        FontRenderMode::Mono | FontRenderMode::Alpha | FontRenderMode::Sdf => 1,
     }
 }
@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use api::{DevicePoint, DeviceSize, DeviceRect, LayoutToWorldTransform};
+use api::{DevicePoint, DeviceSize, DeviceRect, LayoutSize, LayoutToWorldTransform};
 use api::{PremultipliedColorF, WorldToLayoutTransform};
 use gpu_cache::{GpuCacheAddress, GpuDataRequest};
 use prim_store::{VECS_PER_SEGMENT, EdgeAaSegmentMask};
@@ -80,6 +80,22 @@ pub struct BlurInstance {
     pub blur_direction: BlurDirection,
 }
 
+/// A single primitive of an SVG filter graph, drawn by cs_svg_filter.
+/// The input tasks are sampled from their saved targets.
+#[derive(Debug)]
+#[repr(C)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct SvgFilterInstance {
+    pub task_address: RenderTaskAddress,
+    pub input_1_task_address: RenderTaskAddress,
+    pub input_2_task_address: RenderTaskAddress,
+    pub kind: i32,
+    pub generic_int: i32,
+    pub flags: i32,
+    pub extra_data_address: GpuCacheAddress,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 #[repr(C)]
 #[cfg_attr(feature = "capture", derive(Serialize))]
@@ -110,6 +126,20 @@ pub struct BorderInstance {
     pub clip_params: [f32; 8],
 }
 
+/// A path filled with its paint into a texture cache target by cs_path.
+/// The path data holds the segments and the paint of the path.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct PathInstance {
+    pub task_origin: DevicePoint,
+    pub local_rect: DeviceRect,
+    pub local_size: LayoutSize,
+    pub path_data_address: GpuCacheAddress,
+    pub stops_address: GpuCacheAddress,
+}
+
 /// A clipping primitive drawn into the clipping mask.
 /// Could be an image or a rectangle, which defines the
 /// way `address` is treated.
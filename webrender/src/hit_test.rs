@@ -2,12 +2,13 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use api::{BorderRadius, ClipMode, HitTestFlags, HitTestItem, HitTestResult, ItemTag, LayoutPoint};
+use api::{BorderRadius, ClipMode, FillRule, HitTestFlags, HitTestItem, HitTestResult, ItemTag, LayoutPoint};
 use api::{LayoutPrimitiveInfo, LayoutRect, PipelineId, WorldPoint};
 use clip::{ClipSource, ClipStore, rounded_rectangle_contains_point};
 use clip_scroll_node::{ClipScrollNode, NodeType};
 use clip_scroll_tree::{ClipChainIndex, ClipScrollNodeIndex, ClipScrollTree};
 use internal_types::FastHashMap;
+use path::{LineSegment, segments_contain_point};
 use prim_store::ScrollNodeAndClipChain;
 use util::LayoutToWorldFastTransform;
 
@@ -74,6 +75,7 @@ pub struct HitTestingRun(pub Vec<HitTestingItem>, pub ScrollNodeAndClipChain);
 enum HitTestRegion {
     Rectangle(LayoutRect, ClipMode),
     RoundedRectangle(LayoutRect, BorderRadius, ClipMode),
+    Path(Vec<LineSegment>, FillRule),
 }
 
 impl HitTestRegion {
@@ -87,6 +89,8 @@ impl HitTestRegion {
                 rounded_rectangle_contains_point(point, &rect, &radii),
             HitTestRegion::RoundedRectangle(rect, radii, ClipMode::ClipOut) =>
                 !rounded_rectangle_contains_point(point, &rect, &radii),
+            HitTestRegion::Path(ref segments, fill_rule) =>
+                segments_contain_point(segments, fill_rule, point),
         }
     }
 }
@@ -338,9 +342,12 @@ fn get_regions_for_clip_scroll_node(
             ClipSource::RoundedRectangle(ref rect, ref radii, ref mode) =>
                 HitTestRegion::RoundedRectangle(*rect, *radii, *mode),
             ClipSource::Image(ref mask) => HitTestRegion::Rectangle(mask.rect, ClipMode::Clip),
+            ClipSource::Path(ref path) =>
+                HitTestRegion::Path(path.segments().to_vec(), path.fill_rule()),
             ClipSource::LineDecoration(_) |
-            ClipSource::BoxShadow(_) => {
-                unreachable!("Didn't expect to hit test against BorderCorner / BoxShadow / LineDecoration");
+            ClipSource::BoxShadow(_) |
+            ClipSource::MaskLayer(_) => {
+                unreachable!("Didn't expect to hit test against BoxShadow / LineDecoration / MaskLayer");
             }
         }
     }).collect()
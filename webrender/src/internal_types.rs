@@ -74,6 +74,9 @@ pub const ORTHO_FAR_PLANE: f32 = 0000000.0;
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 pub struct RenderTargetInfo {
     pub has_depth: bool,
+    /// The number of samples per texel, drawing to a multisampled target
+    /// is resolved into the texture.
+    pub samples: u8,
 }
 
 #[derive(Debug)]
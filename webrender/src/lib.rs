@@ -87,6 +87,7 @@ mod gpu_types;
 mod hit_test;
 mod image;
 mod internal_types;
+mod path;
 mod picture;
 mod prim_store;
 mod print_tree;
@@ -101,6 +102,7 @@ mod scene;
 mod scene_builder;
 mod segment;
 mod shade;
+mod svg_filter;
 mod texture_allocator;
 mod texture_cache;
 mod tiling;
@@ -0,0 +1,869 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use api::{ColorU, ExtendMode, FillRule, GradientStop, LayoutPoint, LayoutRect, LayoutVector2D};
+use api::{LineCap, LineJoin, PathCommand, PathPaint, StrokeStyle};
+use app_units::Au;
+use fxhash::FxHasher;
+use gpu_cache::{GpuCacheAddress, GpuDataRequest};
+use renderer::MAX_VERTEX_TEXTURE_WIDTH;
+use std::{f32, mem};
+use std::hash::{Hash, Hasher};
+use util::pack_as_float;
+
+/// The maximum distance, in local units, between a curve and
+/// the line segments it is approximated with.
+const FLATTENING_TOLERANCE: f32 = 0.1;
+
+/// The maximum number of line segments a single curve is split into.
+const MAX_CURVE_SEGMENTS: f32 = 64.0;
+
+// The GPU cache blocks of a path primitive before the addresses of its segment chunks.
+const PATH_HEADER_BLOCKS: usize = 2;
+
+/// The segments of a path are written in chunks of one GPU cache row, one segment
+/// per block.
+pub const PATH_CHUNK_SEGMENTS: usize = MAX_VERTEX_TEXTURE_WIDTH;
+
+/// The path data holds the addresses of the segment chunks, two per block.
+pub const MAX_PATH_SEGMENTS: usize =
+    (MAX_VERTEX_TEXTURE_WIDTH - PATH_HEADER_BLOCKS) * 2 * PATH_CHUNK_SEGMENTS;
+
+/// The largest width or height, in device pixels, of a rasterized path.
+/// Larger paths are rasterized at a lower resolution.
+pub const MAX_PATH_TASK_SIZE: f32 = 2048.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineSegment {
+    pub from: LayoutPoint,
+    pub to: LayoutPoint,
+}
+
+/// A flattened subpath.
+#[derive(Clone, Debug)]
+pub struct Polyline {
+    pub points: Vec<LayoutPoint>,
+    pub closed: bool,
+}
+
+/// Approximates a path with line segments, offset by `offset`. All
+/// subpaths are closed, as they are when the path is filled.
+pub fn flatten_path(commands: &[PathCommand], offset: &LayoutVector2D) -> Vec<LineSegment> {
+    let mut segments = Vec::new();
+    for subpath in flatten_subpaths(commands, offset) {
+        push_polygon(&subpath.points, &mut segments);
+    }
+    segments
+}
+
+/// Approximates each subpath of a path with a polyline, offset by `offset`.
+pub fn flatten_subpaths(commands: &[PathCommand], offset: &LayoutVector2D) -> Vec<Polyline> {
+    let mut subpaths = Vec::new();
+    let mut points = Vec::new();
+    let mut start = LayoutPoint::zero();
+    let mut current = LayoutPoint::zero();
+
+    for command in commands {
+        match *command {
+            PathCommand::MoveTo(to) => {
+                end_subpath(&mut subpaths, &mut points, false);
+                start = to + *offset;
+                current = start;
+            }
+            PathCommand::LineTo(to) => {
+                let to = to + *offset;
+                begin_subpath(&mut points, current);
+                points.push(to);
+                current = to;
+            }
+            PathCommand::QuadraticCurveTo(ctrl, to) => {
+                let ctrl = ctrl + *offset;
+                let to = to + *offset;
+                let count = curve_segment_count((current - ctrl * 2.0 + to.to_vector()).length(), 0.25);
+                begin_subpath(&mut points, current);
+                for i in 1 .. count + 1 {
+                    let t = i as f32 / count as f32;
+                    let mt = 1.0 - t;
+                    points.push((current.to_vector() * (mt * mt) +
+                                 ctrl.to_vector() * (2.0 * mt * t) +
+                                 to.to_vector() * (t * t)).to_point());
+                }
+                current = to;
+            }
+            PathCommand::CubicCurveTo(ctrl1, ctrl2, to) => {
+                let ctrl1 = ctrl1 + *offset;
+                let ctrl2 = ctrl2 + *offset;
+                let to = to + *offset;
+                let dd = (current - ctrl1 * 2.0 + ctrl2.to_vector()).length()
+                    .max((ctrl1 - ctrl2 * 2.0 + to.to_vector()).length());
+                let count = curve_segment_count(dd, 0.75);
+                begin_subpath(&mut points, current);
+                for i in 1 .. count + 1 {
+                    let t = i as f32 / count as f32;
+                    let mt = 1.0 - t;
+                    points.push((current.to_vector() * (mt * mt * mt) +
+                                 ctrl1.to_vector() * (3.0 * mt * mt * t) +
+                                 ctrl2.to_vector() * (3.0 * mt * t * t) +
+                                 to.to_vector() * (t * t * t)).to_point());
+                }
+                current = to;
+            }
+            PathCommand::Close => {
+                end_subpath(&mut subpaths, &mut points, true);
+                current = start;
+            }
+        }
+    }
+
+    end_subpath(&mut subpaths, &mut points, false);
+    subpaths
+}
+
+fn begin_subpath(points: &mut Vec<LayoutPoint>, current: LayoutPoint) {
+    if points.is_empty() {
+        points.push(current);
+    }
+}
+
+fn end_subpath(subpaths: &mut Vec<Polyline>, points: &mut Vec<LayoutPoint>, closed: bool) {
+    if points.len() > 1 {
+        subpaths.push(Polyline {
+            points: mem::replace(points, Vec::new()),
+            closed,
+        });
+    } else {
+        points.clear();
+    }
+}
+
+// Adds the edges of the closed polygon through the points.
+fn push_polygon(points: &[LayoutPoint], segments: &mut Vec<LineSegment>) {
+    for pair in points.windows(2) {
+        segments.push(LineSegment { from: pair[0], to: pair[1] });
+    }
+    if let (Some(&first), Some(&last)) = (points.first(), points.last()) {
+        if first != last {
+            segments.push(LineSegment { from: last, to: first });
+        }
+    }
+}
+
+// The number of segments needed to keep a curve within the flattening
+// tolerance, from Wang's formula. `dd` is the largest second difference
+// of the control points, and `factor` depends on the degree of the curve.
+fn curve_segment_count(dd: f32, factor: f32) -> usize {
+    (factor * dd / FLATTENING_TOLERANCE)
+        .sqrt()
+        .ceil()
+        .max(1.0)
+        .min(MAX_CURVE_SEGMENTS) as usize
+}
+
+pub fn segments_bounding_rect(segments: &[LineSegment]) -> LayoutRect {
+    let first = match segments.first() {
+        Some(segment) => segment.from,
+        None => return LayoutRect::zero(),
+    };
+
+    let (min, max) = segments.iter().fold((first, first), |(min, max), segment| {
+        (min.min(segment.from), max.max(segment.from))
+    });
+    LayoutRect::new(min, (max - min).to_size())
+}
+
+/// Returns whether `point` is inside the area enclosed by the (closed)
+/// line segments, according to the fill rule.
+pub fn segments_contain_point(
+    segments: &[LineSegment],
+    fill_rule: FillRule,
+    point: &LayoutPoint,
+) -> bool {
+    let mut winding = 0;
+    for segment in segments {
+        let (from, to) = (segment.from, segment.to);
+        if (from.y <= point.y) != (to.y <= point.y) {
+            let x = from.x + (point.y - from.y) * (to.x - from.x) / (to.y - from.y);
+            if x > point.x {
+                winding += if to.y > from.y { 1 } else { -1 };
+            }
+        }
+    }
+
+    match fill_rule {
+        FillRule::Nonzero => winding != 0,
+        FillRule::EvenOdd => winding % 2 != 0,
+    }
+}
+
+/// Returns the outline of the stroke of the subpaths as closed polygons,
+/// which cover the stroke when filled with the nonzero fill rule.
+pub fn stroke_path(
+    subpaths: &[Polyline],
+    style: &StrokeStyle,
+    dashes: &[f32],
+) -> Vec<LineSegment> {
+    let mut segments = Vec::new();
+    let half_width = 0.5 * style.width;
+    if half_width <= 0.0 {
+        return segments;
+    }
+
+    // An odd number of dash lengths is repeated to get an even one.
+    let pattern: Vec<f32> = if dashes.len() % 2 == 1 {
+        dashes.iter().chain(dashes.iter()).cloned().collect()
+    } else {
+        dashes.to_vec()
+    };
+    let dashed = !pattern.is_empty() &&
+        pattern.iter().all(|length| *length >= 0.0) &&
+        pattern.iter().sum::<f32>() > 0.0;
+
+    for subpath in subpaths {
+        let mut points = subpath.points.clone();
+        points.dedup();
+        if subpath.closed && points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+
+        if dashed {
+            for dash in dash_polyline(&points, subpath.closed, &pattern, style.dash_offset) {
+                stroke_open_polyline(&dash, half_width, style, &mut segments);
+            }
+        } else if subpath.closed && points.len() > 2 {
+            let mut outline = Vec::new();
+            offset_polyline(&points, true, half_width, style, &mut outline);
+            push_polygon(&outline, &mut segments);
+
+            // The inner side of the stroke is the outer side of the
+            // reversed polyline.
+            points.reverse();
+            outline.clear();
+            offset_polyline(&points, true, half_width, style, &mut outline);
+            push_polygon(&outline, &mut segments);
+        } else {
+            stroke_open_polyline(&points, half_width, style, &mut segments);
+        }
+    }
+
+    segments
+}
+
+fn stroke_open_polyline(
+    points: &[LayoutPoint],
+    half_width: f32,
+    style: &StrokeStyle,
+    segments: &mut Vec<LineSegment>,
+) {
+    let mut points = points.to_vec();
+    points.dedup();
+    if points.len() < 2 {
+        return;
+    }
+
+    // Go around the stroke: along one side, around the end cap,
+    // back along the other side and around the start cap.
+    let mut outline = Vec::new();
+    for _ in 0 .. 2 {
+        offset_polyline(&points, false, half_width, style, &mut outline);
+        let end = points[points.len() - 1];
+        let direction = unit_direction(points[points.len() - 2], end);
+        add_cap(end, direction, half_width, style.cap, &mut outline);
+        points.reverse();
+    }
+
+    push_polygon(&outline, segments);
+}
+
+// Adds the points of the left side of the polyline, offset by the half
+// width, and of the joins between its segments.
+fn offset_polyline(
+    points: &[LayoutPoint],
+    closed: bool,
+    half_width: f32,
+    style: &StrokeStyle,
+    outline: &mut Vec<LayoutPoint>,
+) {
+    let count = if closed { points.len() } else { points.len() - 1 };
+    for i in 0 .. count {
+        let from = points[i];
+        let to = points[(i + 1) % points.len()];
+        let direction = unit_direction(from, to);
+        let offset = normal(direction) * half_width;
+        outline.push(from + offset);
+        outline.push(to + offset);
+
+        if closed || i + 1 < count {
+            let next = points[(i + 2) % points.len()];
+            add_join(to, direction, unit_direction(to, next), half_width, style, outline);
+        }
+    }
+}
+
+// Adds the points between the offset ends of two segments meeting at `point`.
+fn add_join(
+    point: LayoutPoint,
+    incoming: LayoutVector2D,
+    outgoing: LayoutVector2D,
+    half_width: f32,
+    style: &StrokeStyle,
+    outline: &mut Vec<LayoutPoint>,
+) {
+    let cross = incoming.cross(outgoing);
+    let dot = incoming.dot(outgoing);
+
+    if cross > 0.0 {
+        // This is the inner side of the turn. Going through the point
+        // itself keeps the overlap of the segments covered.
+        outline.push(point);
+        return;
+    }
+    if cross == 0.0 && dot > 0.0 {
+        return;
+    }
+
+    let n0 = normal(incoming);
+    let n1 = normal(outgoing);
+    match style.join {
+        LineJoin::Bevel => {}
+        LineJoin::Miter => {
+            // The ratio of the miter length to the stroke width is the
+            // inverse of the cosine of half the angle between the normals.
+            let cos_half_angle = (0.5 * (1.0 + n0.dot(n1))).max(0.0).sqrt();
+            if cos_half_angle > 0.0 && 1.0 / cos_half_angle <= style.miter_limit {
+                let bisector = (n0 + n1) / (n0 + n1).length();
+                outline.push(point + bisector * (half_width / cos_half_angle));
+            }
+        }
+        LineJoin::Round => {
+            add_arc(point, n0, -cross.abs().atan2(dot), half_width, outline);
+        }
+    }
+}
+
+fn add_cap(
+    point: LayoutPoint,
+    direction: LayoutVector2D,
+    half_width: f32,
+    cap: LineCap,
+    outline: &mut Vec<LayoutPoint>,
+) {
+    let n = normal(direction);
+    match cap {
+        LineCap::Butt => {}
+        LineCap::Square => {
+            outline.push(point + (n + direction) * half_width);
+            outline.push(point + (direction - n) * half_width);
+        }
+        LineCap::Round => {
+            add_arc(point, n, -f32::consts::PI, half_width, outline);
+        }
+    }
+}
+
+// Adds the points strictly inside an arc around `center`, starting
+// in the direction `from` and turning by `sweep` radians.
+fn add_arc(
+    center: LayoutPoint,
+    from: LayoutVector2D,
+    sweep: f32,
+    radius: f32,
+    outline: &mut Vec<LayoutPoint>,
+) {
+    // The largest angle of a chord that stays within the flattening
+    // tolerance of the arc.
+    let max_step = 2.0 * (1.0 - FLATTENING_TOLERANCE / radius).max(-1.0).acos();
+    let count = (sweep.abs() / max_step.max(0.01))
+        .ceil()
+        .max(1.0)
+        .min(MAX_CURVE_SEGMENTS) as usize;
+
+    for i in 1 .. count {
+        let (sin, cos) = (sweep * i as f32 / count as f32).sin_cos();
+        let direction = LayoutVector2D::new(
+            from.x * cos - from.y * sin,
+            from.x * sin + from.y * cos,
+        );
+        outline.push(center + direction * radius);
+    }
+}
+
+// Splits the polyline into the pieces covered by the dashes of the pattern.
+fn dash_polyline(
+    points: &[LayoutPoint],
+    closed: bool,
+    pattern: &[f32],
+    dash_offset: f32,
+) -> Vec<Vec<LayoutPoint>> {
+    let mut dashes = Vec::new();
+    if points.len() < 2 {
+        return dashes;
+    }
+
+    // Find where in the pattern the polyline starts.
+    let total: f32 = pattern.iter().sum();
+    let mut offset = dash_offset % total;
+    if offset < 0.0 {
+        offset += total;
+    }
+    let mut index = 0;
+    let mut remaining = pattern[0];
+    while offset >= remaining {
+        offset -= remaining;
+        index = (index + 1) % pattern.len();
+        remaining = pattern[index];
+    }
+    remaining -= offset;
+
+    let mut dash = Vec::new();
+    if index % 2 == 0 {
+        dash.push(points[0]);
+    }
+
+    let count = if closed { points.len() } else { points.len() - 1 };
+    for i in 0 .. count {
+        let mut from = points[i];
+        let to = points[(i + 1) % points.len()];
+        let mut length = (to - from).length();
+
+        while length > remaining {
+            let point = from + (to - from) * (remaining / length);
+            dash.push(point);
+            if index % 2 == 0 {
+                dashes.push(mem::replace(&mut dash, Vec::new()));
+            }
+            from = point;
+            length -= remaining;
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+
+        remaining -= length;
+        if index % 2 == 0 {
+            dash.push(to);
+        }
+    }
+
+    if dash.len() > 1 {
+        dashes.push(dash);
+    }
+    dashes
+}
+
+fn unit_direction(from: LayoutPoint, to: LayoutPoint) -> LayoutVector2D {
+    let direction = to - from;
+    direction / direction.length()
+}
+
+fn normal(direction: LayoutVector2D) -> LayoutVector2D {
+    LayoutVector2D::new(-direction.y, direction.x)
+}
+
+/// Identifies a path rasterized with its paint in the render task cache.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct PathCacheKey {
+    /// A hash of the segments, fill rule and paint of the path.
+    pub hash: u64,
+    pub scale: Au,
+}
+
+impl PathCacheKey {
+    pub fn new(
+        segments: &[LineSegment],
+        fill_rule: FillRule,
+        paint: &PathPaint,
+        stops: &[GradientStop],
+    ) -> Self {
+        let mut hasher = FxHasher::default();
+        for segment in segments {
+            for value in &[segment.from.x, segment.from.y, segment.to.x, segment.to.y] {
+                value.to_bits().hash(&mut hasher);
+            }
+        }
+        fill_rule.hash(&mut hasher);
+        match *paint {
+            PathPaint::Color(color) => {
+                ColorU::from(color).hash(&mut hasher);
+            }
+            PathPaint::Gradient(ref gradient) => {
+                for value in &[
+                    gradient.start_point.x,
+                    gradient.start_point.y,
+                    gradient.end_point.x,
+                    gradient.end_point.y,
+                ] {
+                    value.to_bits().hash(&mut hasher);
+                }
+                gradient.extend_mode.hash(&mut hasher);
+                for stop in stops {
+                    stop.offset.to_bits().hash(&mut hasher);
+                    ColorU::from(stop.color).hash(&mut hasher);
+                }
+            }
+        }
+
+        PathCacheKey {
+            hash: hasher.finish(),
+            scale: Au(0),
+        }
+    }
+}
+
+/// Writes the path data read by cs_path: the header, the paint and the
+/// addresses of the segment chunks, see `write_path_segments`. The gradient
+/// points are relative to the origin of the primitive.
+pub fn write_path_gpu_data(
+    request: &mut GpuDataRequest,
+    segment_count: usize,
+    chunk_addresses: &[GpuCacheAddress],
+    fill_rule: FillRule,
+    paint: &PathPaint,
+) {
+    let (paint_kind, repeat, paint_data) = match *paint {
+        PathPaint::Color(color) => {
+            (0, 0.0, color.premultiplied().to_array())
+        }
+        PathPaint::Gradient(ref gradient) => {
+            let repeat = if gradient.extend_mode == ExtendMode::Repeat { 1.0 } else { 0.0 };
+            let points = [
+                gradient.start_point.x,
+                gradient.start_point.y,
+                gradient.end_point.x,
+                gradient.end_point.y,
+            ];
+            (1, repeat, points)
+        }
+    };
+
+    request.push([
+        segment_count as f32,
+        pack_as_float(fill_rule as u32),
+        pack_as_float(paint_kind),
+        repeat,
+    ]);
+    request.push(paint_data);
+    write_path_chunk_addresses(request, chunk_addresses);
+}
+
+/// Writes the addresses of the segment chunks of a path, two per block.
+pub fn write_path_chunk_addresses(request: &mut GpuDataRequest, chunk_addresses: &[GpuCacheAddress]) {
+    for pair in chunk_addresses.chunks(2) {
+        let second = pair.get(1).cloned().unwrap_or(GpuCacheAddress::invalid());
+        request.push([
+            pair[0].u as f32,
+            pair[0].v as f32,
+            second.u as f32,
+            second.v as f32,
+        ]);
+    }
+}
+
+/// Writes a chunk of at most `PATH_CHUNK_SEGMENTS` segments of a path, relative
+/// to the origin of the primitive.
+pub fn write_path_segments(request: &mut GpuDataRequest, segments: &[LineSegment]) {
+    debug_assert!(segments.len() <= PATH_CHUNK_SEGMENTS);
+    for segment in segments {
+        request.push([
+            segment.from.x,
+            segment.from.y,
+            segment.to.x,
+            segment.to.y,
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use api::LayoutSize;
+    use super::*;
+
+    fn rect_path(x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<PathCommand> {
+        vec![
+            PathCommand::MoveTo(LayoutPoint::new(x0, y0)),
+            PathCommand::LineTo(LayoutPoint::new(x1, y0)),
+            PathCommand::LineTo(LayoutPoint::new(x1, y1)),
+            PathCommand::LineTo(LayoutPoint::new(x0, y1)),
+            PathCommand::Close,
+        ]
+    }
+
+    #[test]
+    fn flatten_lines() {
+        let segments = flatten_path(&rect_path(10.0, 10.0, 30.0, 20.0), &LayoutVector2D::new(5.0, 0.0));
+        let expected = [
+            ((15.0, 10.0), (35.0, 10.0)),
+            ((35.0, 10.0), (35.0, 20.0)),
+            ((35.0, 20.0), (15.0, 20.0)),
+            ((15.0, 20.0), (15.0, 10.0)),
+        ];
+        assert_eq!(segments.len(), expected.len());
+        for (segment, &(from, to)) in segments.iter().zip(expected.iter()) {
+            assert_eq!(segment.from, LayoutPoint::new(from.0, from.1));
+            assert_eq!(segment.to, LayoutPoint::new(to.0, to.1));
+        }
+        assert_eq!(
+            segments_bounding_rect(&segments),
+            LayoutRect::new(LayoutPoint::new(15.0, 10.0), LayoutSize::new(20.0, 10.0)),
+        );
+    }
+
+    #[test]
+    fn flatten_closes_subpaths() {
+        // Open subpaths are closed, and each subpath is a separate polygon.
+        let mut commands = vec![
+            PathCommand::MoveTo(LayoutPoint::new(0.0, 0.0)),
+            PathCommand::LineTo(LayoutPoint::new(10.0, 0.0)),
+            PathCommand::LineTo(LayoutPoint::new(10.0, 10.0)),
+        ];
+        commands.extend(rect_path(20.0, 0.0, 30.0, 10.0));
+        let segments = flatten_path(&commands, &LayoutVector2D::zero());
+        assert_eq!(segments.len(), 7);
+        assert_eq!(segments[2].from, LayoutPoint::new(10.0, 10.0));
+        assert_eq!(segments[2].to, LayoutPoint::new(0.0, 0.0));
+
+        // Subpaths without an area are dropped.
+        let commands = [
+            PathCommand::MoveTo(LayoutPoint::new(0.0, 0.0)),
+            PathCommand::MoveTo(LayoutPoint::new(10.0, 0.0)),
+            PathCommand::Close,
+        ];
+        assert!(flatten_path(&commands, &LayoutVector2D::zero()).is_empty());
+    }
+
+    #[test]
+    fn flatten_curves() {
+        let start = LayoutPoint::new(0.0, 0.0);
+        let end = LayoutPoint::new(100.0, 0.0);
+        let curves = [
+            PathCommand::QuadraticCurveTo(LayoutPoint::new(50.0, 100.0), end),
+            PathCommand::CubicCurveTo(LayoutPoint::new(0.0, 100.0), LayoutPoint::new(100.0, 100.0), end),
+        ];
+
+        for curve in &curves {
+            let segments = flatten_path(&[PathCommand::MoveTo(start), *curve], &LayoutVector2D::zero());
+            assert!(segments.len() > 2);
+            assert!(segments.len() <= MAX_CURVE_SEGMENTS as usize + 1);
+            assert_eq!(segments[0].from, start);
+            assert!((segments[segments.len() - 2].to - end).length() < 1.0e-3);
+            assert_eq!(segments[segments.len() - 1].to, start);
+
+            // Both curves peak at the middle, at a height of 50 and 75.
+            let top = segments_bounding_rect(&segments).max_y();
+            assert!(top > 49.0 && top <= 75.0);
+        }
+    }
+
+    #[test]
+    fn contain_point_fill_rules() {
+        let outer = flatten_path(&rect_path(0.0, 0.0, 30.0, 30.0), &LayoutVector2D::zero());
+        let inner = flatten_path(&rect_path(10.0, 10.0, 20.0, 20.0), &LayoutVector2D::zero());
+        let contains = |segments: &[LineSegment], fill_rule, x, y| {
+            segments_contain_point(segments, fill_rule, &LayoutPoint::new(x, y))
+        };
+
+        for &fill_rule in &[FillRule::Nonzero, FillRule::EvenOdd] {
+            assert!(contains(&outer, fill_rule, 15.0, 15.0));
+            assert!(contains(&outer, fill_rule, 0.5, 29.5));
+            assert!(!contains(&outer, fill_rule, -0.5, 15.0));
+            assert!(!contains(&outer, fill_rule, 15.0, 30.5));
+            assert!(!contains(&[], fill_rule, 15.0, 15.0));
+        }
+
+        // The same orientation: the hole is only left by the even-odd rule.
+        let mut nested = outer.clone();
+        nested.extend_from_slice(&inner);
+        assert!(contains(&nested, FillRule::Nonzero, 15.0, 15.0));
+        assert!(!contains(&nested, FillRule::EvenOdd, 15.0, 15.0));
+        assert!(contains(&nested, FillRule::Nonzero, 5.0, 15.0));
+        assert!(contains(&nested, FillRule::EvenOdd, 5.0, 15.0));
+
+        // The opposite orientation: the hole is left by both rules.
+        let mut nested = outer.clone();
+        nested.extend(inner.iter().map(|segment| LineSegment { from: segment.to, to: segment.from }));
+        assert!(!contains(&nested, FillRule::Nonzero, 15.0, 15.0));
+        assert!(!contains(&nested, FillRule::EvenOdd, 15.0, 15.0));
+        assert!(contains(&nested, FillRule::Nonzero, 25.0, 15.0));
+    }
+
+    fn style(width: f32, join: LineJoin, cap: LineCap) -> StrokeStyle {
+        StrokeStyle {
+            width,
+            join,
+            cap,
+            miter_limit: 4.0,
+            dash_offset: 0.0,
+        }
+    }
+
+    fn polyline(points: &[(f32, f32)], closed: bool) -> Polyline {
+        Polyline {
+            points: points.iter().map(|&(x, y)| LayoutPoint::new(x, y)).collect(),
+            closed,
+        }
+    }
+
+    fn stroke_contains(segments: &[LineSegment], x: f32, y: f32) -> bool {
+        segments_contain_point(segments, FillRule::Nonzero, &LayoutPoint::new(x, y))
+    }
+
+    #[test]
+    fn stroke_open_line() {
+        let line = [polyline(&[(0.0, 0.0), (10.0, 0.0)], false)];
+
+        let butt = stroke_path(&line, &style(2.0, LineJoin::Miter, LineCap::Butt), &[]);
+        assert!(stroke_contains(&butt, 5.0, 0.5));
+        assert!(stroke_contains(&butt, 5.0, -0.5));
+        assert!(!stroke_contains(&butt, 5.0, 1.5));
+        assert!(!stroke_contains(&butt, -0.5, 0.0));
+        assert!(!stroke_contains(&butt, 10.5, 0.0));
+
+        let square = stroke_path(&line, &style(2.0, LineJoin::Miter, LineCap::Square), &[]);
+        assert!(stroke_contains(&square, -0.5, 0.5));
+        assert!(stroke_contains(&square, 10.5, -0.5));
+        assert!(!stroke_contains(&square, -1.5, 0.0));
+
+        let round = stroke_path(&line, &style(2.0, LineJoin::Miter, LineCap::Round), &[]);
+        assert!(stroke_contains(&round, -0.5, 0.0));
+        assert!(!stroke_contains(&round, -0.9, 0.9));
+
+        assert!(stroke_path(&line, &style(0.0, LineJoin::Miter, LineCap::Butt), &[]).is_empty());
+    }
+
+    #[test]
+    fn stroke_closed_square() {
+        let square = [polyline(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)], true)];
+
+        let miter = stroke_path(&square, &style(2.0, LineJoin::Miter, LineCap::Butt), &[]);
+        assert!(stroke_contains(&miter, 5.0, 0.0));
+        assert!(stroke_contains(&miter, 5.0, -0.9));
+        assert!(stroke_contains(&miter, 5.0, 0.9));
+        assert!(!stroke_contains(&miter, 5.0, 5.0));
+        assert!(!stroke_contains(&miter, 5.0, -1.1));
+        assert!(stroke_contains(&miter, -0.9, -0.9));
+
+        let bevel = stroke_path(&square, &style(2.0, LineJoin::Bevel, LineCap::Butt), &[]);
+        assert!(stroke_contains(&bevel, -0.4, -0.4));
+        assert!(!stroke_contains(&bevel, -0.9, -0.9));
+        assert!(!stroke_contains(&bevel, 5.0, 5.0));
+    }
+
+    #[test]
+    fn stroke_dashed_line() {
+        let line = [polyline(&[(0.0, 0.0), (10.0, 0.0)], false)];
+        let dashed = stroke_path(&line, &style(2.0, LineJoin::Miter, LineCap::Butt), &[2.0, 3.0]);
+        assert!(stroke_contains(&dashed, 1.0, 0.0));
+        assert!(!stroke_contains(&dashed, 3.0, 0.0));
+        assert!(stroke_contains(&dashed, 6.0, 0.0));
+        assert!(!stroke_contains(&dashed, 9.0, 0.0));
+    }
+
+    fn assert_dashes(dashes: Vec<Vec<LayoutPoint>>, expected: &[&[(f32, f32)]]) {
+        assert_eq!(dashes.len(), expected.len());
+        for (dash, expected) in dashes.iter().zip(expected) {
+            assert_eq!(dash.len(), expected.len());
+            for (point, &(x, y)) in dash.iter().zip(expected.iter()) {
+                assert!((*point - LayoutPoint::new(x, y)).length() < 1.0e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn dash_straight_line() {
+        let line = [LayoutPoint::new(0.0, 0.0), LayoutPoint::new(10.0, 0.0)];
+
+        assert_dashes(
+            dash_polyline(&line, false, &[2.0, 3.0], 0.0),
+            &[&[(0.0, 0.0), (2.0, 0.0)], &[(5.0, 0.0), (7.0, 0.0)]],
+        );
+
+        // The offset moves the pattern backwards along the line.
+        assert_dashes(
+            dash_polyline(&line, false, &[2.0, 3.0], 1.0),
+            &[
+                &[(0.0, 0.0), (1.0, 0.0)],
+                &[(4.0, 0.0), (6.0, 0.0)],
+                &[(9.0, 0.0), (10.0, 0.0)],
+            ],
+        );
+
+        // Negative offsets wrap around the pattern, so this starts in a gap.
+        assert_dashes(
+            dash_polyline(&line, false, &[2.0, 3.0], -3.0),
+            &[&[(3.0, 0.0), (5.0, 0.0)], &[(8.0, 0.0), (10.0, 0.0)]],
+        );
+    }
+
+    #[test]
+    fn dash_around_corner() {
+        let corner = [
+            LayoutPoint::new(0.0, 0.0),
+            LayoutPoint::new(4.0, 0.0),
+            LayoutPoint::new(4.0, 4.0),
+        ];
+        assert_dashes(
+            dash_polyline(&corner, false, &[6.0, 2.0], 0.0),
+            &[&[(0.0, 0.0), (4.0, 0.0), (4.0, 2.0)]],
+        );
+
+        // Closing the polyline adds the edge back to the start.
+        assert_dashes(
+            dash_polyline(&corner, true, &[6.0, 2.0], 0.0),
+            &[&[(0.0, 0.0), (4.0, 0.0), (4.0, 2.0)], &[(4.0, 4.0), (0.0, 0.0)]],
+        );
+    }
+
+    fn join_points(
+        join: LineJoin,
+        miter_limit: f32,
+        outgoing: LayoutVector2D,
+        half_width: f32,
+    ) -> Vec<LayoutPoint> {
+        let stroke_style = StrokeStyle {
+            miter_limit,
+            .. style(2.0 * half_width, join, LineCap::Butt)
+        };
+        let mut outline = Vec::new();
+        add_join(
+            LayoutPoint::new(10.0, 0.0),
+            LayoutVector2D::new(1.0, 0.0),
+            outgoing,
+            half_width,
+            &stroke_style,
+            &mut outline,
+        );
+        outline
+    }
+
+    #[test]
+    fn join_inner_and_straight() {
+        // The inner side of a turn goes through the point itself.
+        let inner = join_points(LineJoin::Miter, 4.0, LayoutVector2D::new(0.0, 1.0), 1.0);
+        assert_eq!(inner, vec![LayoutPoint::new(10.0, 0.0)]);
+
+        let straight = join_points(LineJoin::Round, 4.0, LayoutVector2D::new(1.0, 0.0), 1.0);
+        assert!(straight.is_empty());
+    }
+
+    #[test]
+    fn join_outer() {
+        let outgoing = LayoutVector2D::new(0.0, -1.0);
+
+        let miter = join_points(LineJoin::Miter, 4.0, outgoing, 1.0);
+        assert_eq!(miter.len(), 1);
+        assert!((miter[0] - LayoutPoint::new(11.0, 1.0)).length() < 1.0e-5);
+
+        // The miter length ratio of a right angle is sqrt(2).
+        assert!(join_points(LineJoin::Miter, 1.4, outgoing, 1.0).is_empty());
+        assert!(join_points(LineJoin::Bevel, 4.0, outgoing, 1.0).is_empty());
+
+        let round = join_points(LineJoin::Round, 4.0, outgoing, 10.0);
+        assert!(!round.is_empty());
+        for point in &round {
+            let offset = *point - LayoutPoint::new(10.0, 0.0);
+            assert!((offset.length() - 10.0).abs() < 1.0e-3);
+            assert!(offset.x > 0.0 && offset.y > 0.0);
+        }
+    }
+}
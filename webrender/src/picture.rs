@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use api::{DeviceRect, FilterOp, MixBlendMode, PipelineId, PremultipliedColorF};
+use api::{DeviceRect, FilterOp, FilterPrimitive, MixBlendMode, PipelineId, PremultipliedColorF};
 use api::{DeviceIntRect, DeviceIntSize, DevicePoint, LayoutPoint, LayoutRect};
 use api::{DevicePixelScale, PictureIntPoint, PictureIntRect, PictureIntSize};
 use box_shadow::{BLUR_SAMPLE_SCALE};
@@ -17,6 +17,7 @@ use render_task::{ClearMode, RenderTask, RenderTaskCacheEntryHandle};
 use render_task::{RenderTaskCacheKey, RenderTaskCacheKeyKind, RenderTaskId, RenderTaskLocation};
 use scene::{FilterOpHelpers, SceneProperties};
 use std::mem;
+use svg_filter::{self, SvgFilterContext};
 use tiling::RenderTargetKind;
 use util::TransformedRectKind;
 
@@ -41,6 +42,8 @@ pub enum PictureCompositeMode {
     /// Draw to intermediate surface, copy straight across. This
     /// is used for CSS isolation, and plane splitting.
     Blit,
+    /// Apply the SVG filter graph in `filter_primitives`.
+    SvgFilter,
 }
 
 // Stores the location of the picture if it is drawn to
@@ -157,6 +160,11 @@ pub struct PicturePrimitive {
     // picture.
     pub extra_gpu_data_handle: GpuCacheHandle,
 
+    // The filter graph of an SVG filter picture, and the
+    // GPU cache handles for the data of each primitive.
+    pub filter_primitives: Vec<FilterPrimitive>,
+    filter_data_handles: Vec<GpuCacheHandle>,
+
     // Unique identifier for this picture.
     pub id: PictureId,
 }
@@ -197,6 +205,8 @@ impl PicturePrimitive {
             reference_frame_index,
             real_local_rect: LayoutRect::zero(),
             extra_gpu_data_handle: GpuCacheHandle::new(),
+            filter_primitives: Vec::new(),
+            filter_data_handles: Vec::new(),
             apply_local_clip_rect,
             pipeline_id,
             id,
@@ -250,6 +260,14 @@ impl PicturePrimitive {
                 //     .translate(&offset);
                 // shadow_rect.union(&local_content_rect)
             }
+            Some(PictureCompositeMode::SvgFilter) => {
+                // The default filter region of SVG, 10% larger than
+                // the content on each side.
+                local_content_rect.inflate(
+                    local_content_rect.size.width * 0.1,
+                    local_content_rect.size.height * 0.1,
+                )
+            }
             _ => {
                 local_content_rect
             }
@@ -262,6 +280,7 @@ impl PicturePrimitive {
                 filter.is_noop()
             }
             Some(PictureCompositeMode::Blit) |
+            Some(PictureCompositeMode::SvgFilter) |
             Some(PictureCompositeMode::MixBlend(..)) => {
                 false
             }
@@ -344,9 +363,10 @@ impl PicturePrimitive {
                     );
 
                     let picture_task_id = frame_state.render_tasks.add(picture_task);
+                    let content_scale = frame_state.render_tasks[picture_task_id].content_scale();
 
                     let blur_render_task = RenderTask::new_blur(
-                        blur_std_deviation,
+                        blur_std_deviation * content_scale,
                         picture_task_id,
                         frame_state.render_tasks,
                         RenderTargetKind::Color,
@@ -400,9 +420,10 @@ impl PicturePrimitive {
                             );
 
                             let picture_task_id = render_tasks.add(picture_task);
+                            let content_scale = render_tasks[picture_task_id].content_scale();
 
                             let blur_render_task = RenderTask::new_blur(
-                                blur_std_deviation,
+                                blur_std_deviation * content_scale,
                                 picture_task_id,
                                 render_tasks,
                                 RenderTargetKind::Color,
@@ -457,9 +478,10 @@ impl PicturePrimitive {
                 picture_task.mark_for_saving();
 
                 let picture_task_id = frame_state.render_tasks.add(picture_task);
+                let content_scale = frame_state.render_tasks[picture_task_id].content_scale();
 
                 let blur_render_task = RenderTask::new_blur(
-                    blur_std_deviation.round(),
+                    (blur_std_deviation * content_scale).round(),
                     picture_task_id,
                     frame_state.render_tasks,
                     RenderTargetKind::Color,
@@ -570,6 +592,74 @@ impl PicturePrimitive {
                 pic_state.tasks.push(render_task_id);
                 self.surface = Some(PictureSurface::RenderTask(render_task_id));
             }
+            Some(PictureCompositeMode::SvgFilter) => {
+                // Like blurs, primitives may sample their inputs at an
+                // offset, so draw enough of the picture around the clipped
+                // rect to get correct results.
+                let inflation = svg_filter::filter_primitives_inflation(&self.filter_primitives);
+                let inflate_size = (inflation * frame_context.device_pixel_scale.0).ceil() as i32;
+                let device_rect = prim_screen_rect
+                    .clipped
+                    .inflate(inflate_size, inflate_size)
+                    .intersection(&prim_screen_rect.unclipped)
+                    .unwrap();
+
+                let uv_rect_kind = calculate_uv_rect_kind(
+                    &prim_metadata.local_rect,
+                    &prim_run_context.scroll_node,
+                    &device_rect,
+                    frame_context.device_pixel_scale,
+                );
+
+                let picture_task = RenderTask::new_picture(
+                    RenderTaskLocation::Dynamic(None, Some(device_rect.size)),
+                    prim_index,
+                    device_rect.origin,
+                    pic_state_for_children.tasks,
+                    uv_rect_kind,
+                );
+                let picture_task_id = frame_state.render_tasks.add(picture_task);
+
+                self.filter_data_handles.resize(self.filter_primitives.len(), GpuCacheHandle::new());
+                for (primitive, handle) in self.filter_primitives.iter().zip(self.filter_data_handles.iter_mut()) {
+                    svg_filter::write_filter_primitive_data(primitive, handle, frame_state.gpu_cache);
+                }
+
+                // Position dependent primitives (e.g. turbulence) are
+                // evaluated in the local space of the picture.
+                let world_origin = prim_run_context
+                    .scroll_node
+                    .world_content_transform
+                    .transform_point2d(&LayoutPoint::zero());
+                let origin = world_origin * frame_context.device_pixel_scale -
+                    device_rect.origin.to_f32().to_vector();
+
+                // The filter tasks work on the picture as it was drawn,
+                // which is scaled down if it didn't fit in a render target.
+                let (task_size, content_scale) = {
+                    let picture_task = &frame_state.render_tasks[picture_task_id];
+                    (picture_task.get_dynamic_size(), picture_task.content_scale())
+                };
+
+                let filter_context = SvgFilterContext {
+                    task_size,
+                    uv_rect_kind,
+                    origin: origin * content_scale,
+                    device_pixel_scale: DevicePixelScale::new(
+                        frame_context.device_pixel_scale.0 * content_scale
+                    ),
+                };
+                let render_task_id = svg_filter::add_svg_filter_tasks(
+                    &self.filter_primitives,
+                    &self.filter_data_handles,
+                    picture_task_id,
+                    &filter_context,
+                    frame_state.render_tasks,
+                );
+
+                pic_state.tasks.push(render_task_id);
+                self.surface = Some(PictureSurface::RenderTask(render_task_id));
+            }
             Some(PictureCompositeMode::Blit) | None => {
                 let uv_rect_kind = calculate_uv_rect_kind(
                     &prim_metadata.local_rect,
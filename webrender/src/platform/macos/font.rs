@@ -26,7 +26,7 @@ use core_text;
 use core_text::font::{CTFont, CTFontRef};
 use core_text::font_descriptor::{kCTFontDefaultOrientation, kCTFontColorGlyphsTrait};
 use gamma_lut::{ColorLut, GammaLut};
-use glyph_rasterizer::{FontInstance, FontTransform, GlyphKey};
+use glyph_rasterizer::{FontInstance, FontMetrics, FontTransform, GlyphKey};
 #[cfg(feature = "pathfinder")]
 use glyph_rasterizer::NativeFontHandleWrapper;
 #[cfg(not(feature = "pathfinder"))]
@@ -273,6 +273,14 @@ fn is_bitmap_font(ct_font: &CTFont) -> bool {
 // Skew factor matching Gecko/CG.
 const OBLIQUE_SKEW_FACTOR: f32 = 0.25;
 
+// 'OS/2'
+const OS2_TABLE_TAG: u32 = 0x4F53_2F32;
+
+fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+    let bytes = data.get(offset .. offset + 2)?;
+    Some(((bytes[0] as u16) << 8 | bytes[1] as u16) as i16)
+}
+
 impl FontContext {
     pub fn new() -> Result<FontContext, ResourceCacheError> {
         debug!("Test for subpixel AA support: {}", supports_subpixel_aa());
@@ -292,6 +300,18 @@ impl FontContext {
         self.cg_fonts.contains_key(font_key)
     }
 
+    /// Whether the glyphs of the font depend on the text color. Color
+    /// glyphs are drawn by Core Text independently of it.
+    pub fn has_color_layers(&self, _font_key: &FontKey) -> bool {
+        false
+    }
+
+    pub fn get_font_table(&self, font_key: &FontKey, tag: u32) -> Option<Vec<u8>> {
+        let cg_font = self.cg_fonts.get(font_key)?;
+        let ct_font = core_text::font::new_from_CGFont(cg_font, 0.0);
+        ct_font.get_font_table(tag).map(|table| table.bytes().to_vec())
+    }
+
     pub fn add_raw_font(&mut self, font_key: &FontKey, bytes: Arc<Vec<u8>>, index: u32) {
         if self.cg_fonts.contains_key(font_key) {
             return;
@@ -357,6 +377,39 @@ impl FontContext {
             })
     }
 
+    pub fn get_font_metrics(&mut self, font: &FontInstance) -> Option<FontMetrics> {
+        self.get_ct_font(font.font_key, font.size, &font.variations)
+            .map(|ct_font| {
+                let underline_thickness = ct_font.underline_thickness() as f32;
+
+                // Core Text doesn't expose strikeout metrics, so read them from
+                // the OS/2 table, which gives the top of the line positive
+                // upwards. Without one, center the line on the x-height.
+                let scale = ct_font.pt_size() as f32 / ct_font.units_per_em() as f32;
+                let strikeout = ct_font.get_font_table(OS2_TABLE_TAG).and_then(|os2| {
+                    let os2 = os2.bytes();
+                    Some((read_i16(os2, 28)?, read_i16(os2, 26)?))
+                });
+                let (strikeout_offset, strikeout_thickness) = match strikeout {
+                    Some((position, size)) if size > 0 => {
+                        let thickness = size as f32 * scale;
+                        (-(position as f32) * scale + 0.5 * thickness, thickness)
+                    }
+                    _ => (-0.5 * ct_font.x_height() as f32, underline_thickness),
+                };
+
+                FontMetrics {
+                    ascent: ct_font.ascent() as f32,
+                    descent: ct_font.descent() as f32,
+                    // Core Text gives the underline position positive upwards.
+                    underline_offset: -ct_font.underline_position() as f32,
+                    underline_thickness,
+                    strikeout_offset,
+                    strikeout_thickness,
+                }
+            })
+    }
+
     pub fn get_glyph_dimensions(
         &mut self,
         font: &FontInstance,
@@ -428,7 +481,7 @@ impl FontContext {
     ) {
         // Then convert back to gamma corrected values.
         match render_mode {
-            FontRenderMode::Alpha => {
+            FontRenderMode::Alpha | FontRenderMode::Sdf => {
                 self.gamma_lut.preblend_grayscale(pixels, color);
             }
             FontRenderMode::Subpixel => {
@@ -456,6 +509,11 @@ impl FontContext {
         }
     }
 
+    /// Whether glyphs can be rasterized as distance fields.
+    pub fn supports_sdf() -> bool {
+        false
+    }
+
     pub fn prepare_font(font: &mut FontInstance) {
         match font.render_mode {
             FontRenderMode::Mono => {
@@ -464,7 +522,7 @@ impl FontContext {
                 // Subpixel positioning is disabled in mono mode.
                 font.disable_subpixel_position();
             }
-            FontRenderMode::Alpha => {
+            FontRenderMode::Alpha | FontRenderMode::Sdf => {
                 font.color = if font.flags.contains(FontInstanceFlags::FONT_SMOOTHING) {
                     // Only the G channel is used to index grayscale tables,
                     // so use R and B to preserve light/dark determination.
@@ -615,7 +673,8 @@ impl FontContext {
                 } else {
                     (true, true, 0.0, 1.0, 1.0, true)
                 },
-                (FontRenderMode::Alpha, false) => (true, false, 0.0, 1.0, 1.0, true),
+                (FontRenderMode::Alpha, false) |
+                (FontRenderMode::Sdf, _) => (true, false, 0.0, 1.0, 1.0, true),
                 (FontRenderMode::Mono, _) => (false, false, 0.0, 1.0, 1.0, true),
             }
         };
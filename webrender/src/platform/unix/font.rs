@@ -12,13 +12,15 @@ use freetype::freetype::{FT_F26Dot6, FT_Face, FT_Glyph_Format, FT_Long, FT_UInt}
 use freetype::freetype::{FT_GlyphSlot, FT_LcdFilter, FT_New_Face, FT_New_Memory_Face};
 use freetype::freetype::{FT_Init_FreeType, FT_Load_Glyph, FT_Render_Glyph};
 use freetype::freetype::{FT_Library, FT_Outline_Get_CBox, FT_Set_Char_Size, FT_Select_Size};
-use freetype::freetype::{FT_Fixed, FT_Matrix, FT_Set_Transform};
+use freetype::freetype::{FT_Fixed, FT_Matrix, FT_Set_Transform, FT_ULong};
 use freetype::freetype::{FT_LOAD_COLOR, FT_LOAD_DEFAULT, FT_LOAD_FORCE_AUTOHINT};
 use freetype::freetype::{FT_LOAD_IGNORE_GLOBAL_ADVANCE_WIDTH, FT_LOAD_NO_AUTOHINT};
 use freetype::freetype::{FT_LOAD_NO_BITMAP, FT_LOAD_NO_HINTING, FT_LOAD_VERTICAL_LAYOUT};
 use freetype::freetype::{FT_FACE_FLAG_SCALABLE, FT_FACE_FLAG_FIXED_SIZES};
 use freetype::succeeded;
-use glyph_rasterizer::{FontInstance, GlyphFormat, GlyphKey, GlyphRasterResult, RasterizedGlyph};
+use glyph_rasterizer::{FontInstance, FontMetrics, GlyphFormat, GlyphKey, GlyphRasterResult};
+use glyph_rasterizer::{SDF_GLYPH_SPREAD, compute_distance_field};
+use glyph_rasterizer::RasterizedGlyph;
 #[cfg(feature = "pathfinder")]
 use glyph_rasterizer::NativeFontHandleWrapper;
 use internal_types::{FastHashMap, ResourceCacheError};
@@ -37,9 +39,120 @@ const FT_LOAD_TARGET_LIGHT: FT_UInt  = 1 << 16;
 const FT_LOAD_TARGET_MONO: FT_UInt   = 2 << 16;
 const FT_LOAD_TARGET_LCD: FT_UInt    = 3 << 16;
 const FT_LOAD_TARGET_LCD_V: FT_UInt  = 4 << 16;
+const FT_FACE_FLAG_COLOR: FT_Long    = 1 << 14;
+
+// The palette entry that COLR layers use to be drawn in the text color.
+#[cfg(not(feature = "pathfinder"))]
+const COLR_FOREGROUND_ENTRY: u16 = 0xFFFF;
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset .. offset + 2)?;
+    Some((bytes[0] as u16) << 8 | bytes[1] as u16)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset .. offset + 4)?;
+    Some((bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32)
+}
+
+fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+    read_u16(data, offset).map(|value| value as i16)
+}
+
+fn make_tag(tag: &[u8; 4]) -> FT_ULong {
+    (tag[0] as FT_ULong) << 24 | (tag[1] as FT_ULong) << 16 | (tag[2] as FT_ULong) << 8 | tag[3] as FT_ULong
+}
+
+fn load_sfnt_table(face: FT_Face, tag: &[u8; 4]) -> Option<Vec<u8>> {
+    let tag = make_tag(tag);
+    let mut length: FT_ULong = 0;
+    let result = unsafe { FT_Load_Sfnt_Table(face, tag, 0, ptr::null_mut(), &mut length) };
+    if !succeeded(result) || length == 0 {
+        return None;
+    }
+    let mut table = vec![0u8; length as usize];
+    let result = unsafe { FT_Load_Sfnt_Table(face, tag, 0, table.as_mut_ptr(), &mut length) };
+    if succeeded(result) {
+        Some(table)
+    } else {
+        None
+    }
+}
+
+/// The COLR and CPAL tables of a face, which describe glyphs made of
+/// several outline layers that are each drawn in a palette color.
+///
+/// The tables are read directly rather than through FreeType's color API,
+/// which is only available in FreeType 2.10 and later.
+struct ColorTables {
+    colr: Vec<u8>,
+    cpal: Vec<u8>,
+}
+
+impl ColorTables {
+    // Older FreeType versions don't flag faces with COLR tables as color
+    // faces, so just look for the tables. Only the version 0 layer records
+    // are used, which later versions of COLR still contain.
+    fn load(face: FT_Face) -> Option<ColorTables> {
+        let colr = load_sfnt_table(face, b"COLR")?;
+        let cpal = load_sfnt_table(face, b"CPAL")?;
+        Some(ColorTables { colr, cpal })
+    }
+
+    /// Gets the (glyph index, palette entry) pairs of the layers of a glyph,
+    /// from bottom to top, or None if the glyph isn't a color glyph.
+    fn get_layers(&self, glyph_index: u32) -> Option<Vec<(u32, u16)>> {
+        let num_base_glyphs = read_u16(&self.colr, 2)? as usize;
+        let base_glyphs_offset = read_u32(&self.colr, 4)? as usize;
+        let layers_offset = read_u32(&self.colr, 8)? as usize;
+        let num_layers = read_u16(&self.colr, 12)? as usize;
+
+        // Base glyph records are sorted by glyph index.
+        let (mut low, mut high) = (0, num_base_glyphs);
+        while low < high {
+            let mid = (low + high) / 2;
+            let record = base_glyphs_offset + mid * 6;
+            let base_glyph = read_u16(&self.colr, record)? as u32;
+            if base_glyph < glyph_index {
+                low = mid + 1;
+            } else if base_glyph > glyph_index {
+                high = mid;
+            } else {
+                let first_layer = read_u16(&self.colr, record + 2)? as usize;
+                let layer_count = read_u16(&self.colr, record + 4)? as usize;
+                let last_layer = cmp::min(first_layer + layer_count, num_layers);
+                let mut layers = Vec::with_capacity(last_layer.saturating_sub(first_layer));
+                for layer in first_layer .. last_layer {
+                    let record = layers_offset + layer * 4;
+                    layers.push((read_u16(&self.colr, record)? as u32, read_u16(&self.colr, record + 2)?));
+                }
+                return Some(layers);
+            }
+        }
+        None
+    }
+
+    /// Gets the unpremultiplied BGRA color of a palette entry, the
+    /// foreground entry is left to the caller.
+    #[cfg(not(feature = "pathfinder"))]
+    fn get_color(&self, palette_index: u16, entry: u16) -> Option<[u8; 4]> {
+        let num_entries = read_u16(&self.cpal, 2)?;
+        let num_palettes = read_u16(&self.cpal, 4)?;
+        let records_offset = read_u32(&self.cpal, 8)? as usize;
+        if entry >= num_entries || num_palettes == 0 {
+            return None;
+        }
+        let palette_index = if palette_index < num_palettes { palette_index } else { 0 };
+        let first_record = read_u16(&self.cpal, 12 + palette_index as usize * 2)? as usize;
+        let record = records_offset + (first_record + entry as usize) * 4;
+        let color = self.cpal.get(record .. record + 4)?;
+        Some([color[0], color[1], color[2], color[3]])
+    }
+}
 
 struct Face {
     face: FT_Face,
+    color_tables: Option<ColorTables>,
     // Raw byte data has to live until the font is deleted, according to
     // https://www.freetype.org/freetype2/docs/reference/ft2-base_interface.html#FT_New_Memory_Face
     _bytes: Option<Arc<Vec<u8>>>,
@@ -58,6 +171,13 @@ unsafe impl Send for FontContext {}
 
 extern "C" {
     fn FT_GlyphSlot_Embolden(slot: FT_GlyphSlot);
+    fn FT_Load_Sfnt_Table(
+        face: FT_Face,
+        tag: FT_ULong,
+        offset: FT_Long,
+        buffer: *mut u8,
+        length: *mut FT_ULong,
+    ) -> FT_Error;
 }
 
 // Skew factor matching Gecko/FreeType.
@@ -142,6 +262,69 @@ fn flip_bitmap_y(bitmap: &mut [u8], width: usize, height: usize) {
     }
 }
 
+/// Applies the synthetic italics, transpose and flips of the font to the
+/// bounds of a glyph that is rasterized without them.
+fn transform_glyph_bounds(
+    font: &FontInstance,
+    mut left: i32,
+    mut top: i32,
+    mut width: u32,
+    mut height: u32,
+) -> (i32, i32, u32, u32) {
+    if font.flags.contains(FontInstanceFlags::SYNTHETIC_ITALICS) {
+        let (skew_min, skew_max) = get_skew_bounds(top - height as i32, top);
+        left += skew_min as i32;
+        width += (skew_max - skew_min) as u32;
+    }
+    if font.flags.contains(FontInstanceFlags::TRANSPOSE) {
+        mem::swap(&mut width, &mut height);
+        mem::swap(&mut left, &mut top);
+        left -= width as i32;
+        top += height as i32;
+    }
+    if font.flags.contains(FontInstanceFlags::FLIP_X) {
+        left = -(left + width as i32);
+    }
+    if font.flags.contains(FontInstanceFlags::FLIP_Y) {
+        top = -(top - height as i32);
+    }
+    (left, top, width, height)
+}
+
+/// Applies the synthetic italics, transpose and flips of the font to a BGRA
+/// glyph bitmap, the same way `transform_glyph_bounds` does to its bounds.
+fn transform_glyph_bitmap(
+    font: &FontInstance,
+    mut bitmap: Vec<u8>,
+    mut width: usize,
+    mut height: usize,
+    mut left: i32,
+    mut top: i32,
+) -> (Vec<u8>, usize, usize, i32, i32) {
+    if font.flags.contains(FontInstanceFlags::SYNTHETIC_ITALICS) {
+        let (skew_buffer, skew_width, skew_left) = skew_bitmap(&bitmap, width, height, left, top);
+        bitmap = skew_buffer;
+        width = skew_width;
+        left = skew_left;
+    }
+    if font.flags.contains(FontInstanceFlags::TRANSPOSE) {
+        bitmap = transpose_bitmap(&bitmap, width, height);
+        mem::swap(&mut width, &mut height);
+        mem::swap(&mut left, &mut top);
+        left -= width as i32;
+        top += height as i32;
+    }
+    if font.flags.contains(FontInstanceFlags::FLIP_X) {
+        flip_bitmap_x(&mut bitmap, width, height);
+        left = -(left + width as i32);
+    }
+    if font.flags.contains(FontInstanceFlags::FLIP_Y) {
+        flip_bitmap_y(&mut bitmap, width, height);
+        top = -(top - height as i32);
+    }
+    (bitmap, width, height, left, top)
+}
+
 impl FontContext {
     pub fn new() -> Result<FontContext, ResourceCacheError> {
         let mut lib: FT_Library = ptr::null_mut();
@@ -175,6 +358,17 @@ impl FontContext {
         self.faces.contains_key(font_key)
     }
 
+    /// Whether the font has COLR layered glyphs, whose foreground layers
+    /// are drawn in the text color.
+    pub fn has_color_layers(&self, font_key: &FontKey) -> bool {
+        self.faces.get(font_key).map_or(false, |face| face.color_tables.is_some())
+    }
+
+    pub fn get_font_table(&self, font_key: &FontKey, tag: u32) -> Option<Vec<u8>> {
+        let face = self.faces.get(font_key)?;
+        load_sfnt_table(face.face, &[(tag >> 24) as u8, (tag >> 16) as u8, (tag >> 8) as u8, tag as u8])
+    }
+
     pub fn add_raw_font(&mut self, font_key: &FontKey, bytes: Arc<Vec<u8>>, index: u32) {
         if !self.faces.contains_key(&font_key) {
             let mut face: FT_Face = ptr::null_mut();
@@ -192,6 +386,7 @@ impl FontContext {
                     *font_key,
                     Face {
                         face,
+                        color_tables: ColorTables::load(face),
                         _bytes: Some(bytes),
                     },
                 );
@@ -219,6 +414,7 @@ impl FontContext {
                     *font_key,
                     Face {
                         face,
+                        color_tables: ColorTables::load(face),
                         _bytes: None,
                     },
                 );
@@ -242,8 +438,10 @@ impl FontContext {
 
         let mut load_flags = FT_LOAD_DEFAULT;
         let FontInstancePlatformOptions { mut hinting, .. } = font.platform_options.unwrap_or_default();
-        // Disable hinting if there is a non-axis-aligned transform.
-        if font.flags.contains(FontInstanceFlags::SYNTHETIC_ITALICS) ||
+        // Disable hinting if there is a non-axis-aligned transform, or if the
+        // glyph is a distance field that will be drawn at arbitrary scales.
+        if font.render_mode == FontRenderMode::Sdf ||
+           font.flags.contains(FontInstanceFlags::SYNTHETIC_ITALICS) ||
            ((font.transform.scale_x != 0.0 || font.transform.scale_y != 0.0) &&
             (font.transform.skew_x != 0.0 || font.transform.skew_y != 0.0)) {
             hinting = FontHinting::None;
@@ -272,7 +470,11 @@ impl FontContext {
         if font.flags.contains(FontInstanceFlags::NO_AUTOHINT) {
             load_flags |= FT_LOAD_NO_AUTOHINT;
         }
-        if !font.flags.contains(FontInstanceFlags::EMBEDDED_BITMAPS) {
+        // Color bitmap fonts such as CBDT and sbix usually have no useful
+        // outlines, so their bitmaps are always loaded.
+        let face_flags = unsafe { (*face.face).face_flags };
+        let is_color_face = (face_flags & FT_FACE_FLAG_COLOR) != 0;
+        if !font.flags.contains(FontInstanceFlags::EMBEDDED_BITMAPS) && !is_color_face {
             load_flags |= FT_LOAD_NO_BITMAP;
         }
         if font.flags.contains(FontInstanceFlags::VERTICAL_LAYOUT) {
@@ -284,9 +486,10 @@ impl FontContext {
 
         let (x_scale, y_scale) = font.transform.compute_scale().unwrap_or((1.0, 1.0));
         let req_size = font.size.to_f64_px();
-        let face_flags = unsafe { (*face.face).face_flags };
+        // Scalable color fonts with fixed sizes, like sbix, only have their
+        // color glyphs in the bitmap strikes, so select one of those too.
         let mut result = if (face_flags & (FT_FACE_FLAG_FIXED_SIZES as FT_Long)) != 0 &&
-                            (face_flags & (FT_FACE_FLAG_SCALABLE as FT_Long)) == 0 &&
+                            ((face_flags & (FT_FACE_FLAG_SCALABLE as FT_Long)) == 0 || is_color_face) &&
                             (load_flags & FT_LOAD_NO_BITMAP) == 0 {
             unsafe { FT_Set_Transform(face.face, ptr::null_mut(), ptr::null_mut()) };
             self.choose_bitmap_size(face.face, req_size * y_scale)
@@ -437,23 +640,11 @@ impl FontContext {
                     width = (x1.ceil() - x0.floor()) as u32;
                     height = (y1.ceil() - y0.floor()) as u32;
                     advance *= scale;
-                    if font.flags.contains(FontInstanceFlags::SYNTHETIC_ITALICS) {
-                        let (skew_min, skew_max) = get_skew_bounds(top - height as i32, top);
-                        left += skew_min as i32;
-                        width += (skew_max - skew_min) as u32;
-                    }
-                    if font.flags.contains(FontInstanceFlags::TRANSPOSE) {
-                        mem::swap(&mut width, &mut height);
-                        mem::swap(&mut left, &mut top);
-                        left -= width as i32;
-                        top += height as i32;
-                    }
-                    if font.flags.contains(FontInstanceFlags::FLIP_X) {
-                        left = -(left + width as i32);
-                    }
-                    if font.flags.contains(FontInstanceFlags::FLIP_Y) {
-                        top = -(top - height as i32);
-                    }
+                    let bounds = transform_glyph_bounds(font, left, top, width, height);
+                    left = bounds.0;
+                    top = bounds.1;
+                    width = bounds.2;
+                    height = bounds.3;
                 }
                 Some(GlyphDimensions {
                     left,
@@ -494,10 +685,146 @@ impl FontContext {
         font: &FontInstance,
         key: &GlyphKey,
     ) -> Option<GlyphDimensions> {
+        if let Some(layers) = self.get_color_layers(font, key) {
+            return self.get_color_glyph_dimensions(font, key, &layers);
+        }
         let slot = self.load_glyph(font, key);
         slot.and_then(|slot| self.get_glyph_dimensions_impl(slot, font, key, true))
     }
 
+    /// Gets the COLR layers of a glyph, unless it is drawn as a distance field,
+    /// which can't hold color, or through Pathfinder, which draws the outlines.
+    fn get_color_layers(&self, font: &FontInstance, key: &GlyphKey) -> Option<Vec<(u32, u16)>> {
+        if font.render_mode == FontRenderMode::Sdf || cfg!(feature = "pathfinder") {
+            return None;
+        }
+        let face = self.faces.get(&font.font_key)?;
+        face.color_tables.as_ref()?.get_layers(key.index)
+    }
+
+    // The layers of color glyphs are rasterized as plain coverage that is
+    // then tinted with the palette colors while compositing. Synthetic
+    // italics, transposition and flips are applied to the composited glyph,
+    // like for color bitmaps, so that all the layers line up.
+    fn get_color_layer_font(font: &FontInstance) -> FontInstance {
+        let mut layer_font = font.clone();
+        layer_font.render_mode = FontRenderMode::Alpha;
+        layer_font.flags.remove(
+            FontInstanceFlags::SYNTHETIC_ITALICS |
+            FontInstanceFlags::TRANSPOSE |
+            FontInstanceFlags::FLIP_X |
+            FontInstanceFlags::FLIP_Y
+        );
+        layer_font
+    }
+
+    fn get_color_glyph_dimensions(
+        &self,
+        font: &FontInstance,
+        key: &GlyphKey,
+        layers: &[(u32, u16)],
+    ) -> Option<GlyphDimensions> {
+        let dimensions = self.get_color_layer_bounds(font, key, layers)?;
+        if dimensions.width == 0 || dimensions.height == 0 {
+            return Some(dimensions);
+        }
+        let (left, top, width, height) =
+            transform_glyph_bounds(font, dimensions.left, dimensions.top, dimensions.width, dimensions.height);
+        Some(GlyphDimensions {
+            left,
+            top,
+            width,
+            height,
+            advance: dimensions.advance,
+        })
+    }
+
+    /// Gets the bounds of the composited layers of a color glyph, before the
+    /// synthetic italics, transpose and flips of the font are applied.
+    fn get_color_layer_bounds(
+        &self,
+        font: &FontInstance,
+        key: &GlyphKey,
+        layers: &[(u32, u16)],
+    ) -> Option<GlyphDimensions> {
+        // The advance comes from the base glyph, while the bounds are the
+        // union of the bounds of all the layers.
+        let advance = self.load_glyph(font, key)
+            .and_then(|slot| self.get_glyph_dimensions_impl(slot, font, key, true))?
+            .advance;
+        let layer_font = Self::get_color_layer_font(font);
+        let mut bounds: Option<(i32, i32, i32, i32)> = None;
+        for &(index, _) in layers {
+            let layer_key = GlyphKey { index, ..key.clone() };
+            let dimensions = match self.load_glyph(&layer_font, &layer_key)
+                .and_then(|slot| self.get_glyph_dimensions_impl(slot, &layer_font, &layer_key, false)) {
+                Some(dimensions) if dimensions.width > 0 && dimensions.height > 0 => dimensions,
+                _ => continue,
+            };
+            let layer_bounds = (
+                dimensions.left,
+                dimensions.top - dimensions.height as i32,
+                dimensions.left + dimensions.width as i32,
+                dimensions.top,
+            );
+            bounds = Some(match bounds {
+                Some((left, bottom, right, top)) => (
+                    cmp::min(left, layer_bounds.0),
+                    cmp::min(bottom, layer_bounds.1),
+                    cmp::max(right, layer_bounds.2),
+                    cmp::max(top, layer_bounds.3),
+                ),
+                None => layer_bounds,
+            });
+        }
+        let (left, bottom, right, top) = bounds.unwrap_or((0, 0, 0, 0));
+        Some(GlyphDimensions {
+            left,
+            top,
+            width: (right - left) as u32,
+            height: (top - bottom) as u32,
+            advance,
+        })
+    }
+
+    pub fn get_font_metrics(&mut self, font: &FontInstance) -> Option<FontMetrics> {
+        let ft_face = self.faces.get(&font.font_key)?.face;
+        let face = unsafe { &*ft_face };
+        // Bitmap-only fonts don't have scalable metrics.
+        if face.units_per_EM == 0 {
+            return None;
+        }
+
+        let scale = font.size.to_f32_px() / face.units_per_EM as f32;
+        let ascent = face.ascender as f32 * scale;
+        let underline_thickness = face.underline_thickness as f32 * scale;
+
+        // The OS/2 table gives the top of the strikeout, positive upwards. Fonts
+        // without one get a line through the middle of the x-height, using the
+        // usual half ascent guess for it.
+        let strikeout = load_sfnt_table(ft_face, b"OS/2").and_then(|os2| {
+            Some((read_i16(&os2, 28)?, read_i16(&os2, 26)?))
+        });
+        let (strikeout_offset, strikeout_thickness) = match strikeout {
+            Some((position, size)) if size > 0 => {
+                let thickness = size as f32 * scale;
+                (-(position as f32) * scale + 0.5 * thickness, thickness)
+            }
+            _ => (-0.25 * ascent, underline_thickness),
+        };
+
+        Some(FontMetrics {
+            ascent,
+            // FreeType gives the descender as a negative value.
+            descent: -(face.descender as f32) * scale,
+            // FreeType gives the center of the underline, positive upwards.
+            underline_offset: -(face.underline_position as f32) * scale,
+            underline_thickness,
+            strikeout_offset,
+            strikeout_thickness,
+        })
+    }
+
     fn choose_bitmap_size(&self, face: FT_Face, requested_size: f64) -> FT_Error {
         let mut best_dist = unsafe { *(*face).available_sizes.offset(0) }.y_ppem as f64 / 64.0 - requested_size;
         let mut best_size = 0;
@@ -515,6 +842,12 @@ impl FontContext {
         unsafe { FT_Select_Size(face, best_size) }
     }
 
+    /// Whether glyphs can be rasterized as distance fields, which is only
+    /// done for FreeType outlines rather than through Pathfinder.
+    pub fn supports_sdf() -> bool {
+        cfg!(not(feature = "pathfinder"))
+    }
+
     pub fn prepare_font(font: &mut FontInstance) {
         match font.render_mode {
             FontRenderMode::Mono => {
@@ -523,7 +856,7 @@ impl FontContext {
                 // Subpixel positioning is disabled in mono mode.
                 font.disable_subpixel_position();
             }
-            FontRenderMode::Alpha | FontRenderMode::Subpixel => {
+            FontRenderMode::Alpha | FontRenderMode::Subpixel | FontRenderMode::Sdf => {
                 // We don't do any preblending with FreeType currently, so the color is not used.
                 font.color = ColorU::new(0xFF, 0xFF, 0xFF, 0xFF);
             }
@@ -568,7 +901,7 @@ impl FontContext {
         }
         let render_mode = match font.render_mode {
             FontRenderMode::Mono => FT_Render_Mode::FT_RENDER_MODE_MONO,
-            FontRenderMode::Alpha => FT_Render_Mode::FT_RENDER_MODE_NORMAL,
+            FontRenderMode::Alpha | FontRenderMode::Sdf => FT_Render_Mode::FT_RENDER_MODE_NORMAL,
             FontRenderMode::Subpixel => if font.flags.contains(FontInstanceFlags::LCD_VERTICAL) {
                 FT_Render_Mode::FT_RENDER_MODE_LCD_V
             } else {
@@ -590,8 +923,119 @@ impl FontContext {
         }
     }
 
+    #[cfg(not(feature = "pathfinder"))]
+    fn rasterize_color_glyph(
+        &mut self,
+        font: &FontInstance,
+        key: &GlyphKey,
+        layers: &[(u32, u16)],
+    ) -> GlyphRasterResult {
+        let dimensions = match self.get_color_layer_bounds(font, key, layers) {
+            Some(val) => val,
+            None => return GlyphRasterResult::LoadFailed,
+        };
+        let GlyphDimensions { left, top, width, height, .. } = dimensions;
+
+        // For spaces and other non-printable characters, early out.
+        if width == 0 || height == 0 {
+            return GlyphRasterResult::LoadFailed;
+        }
+
+        let (width, height) = (width as usize, height as usize);
+        let mut final_buffer = vec![0u8; width * height * 4];
+        let layer_font = Self::get_color_layer_font(font);
+        for &(index, entry) in layers {
+            // The foreground layers use the text color, which is part of the
+            // font instance of color layer fonts. Its alpha is applied when
+            // the glyph is drawn.
+            let color = if entry == COLR_FOREGROUND_ENTRY {
+                [font.color.b, font.color.g, font.color.r, 0xFF]
+            } else {
+                match self.faces
+                    .get(&font.font_key)
+                    .and_then(|face| face.color_tables.as_ref())
+                    .and_then(|tables| tables.get_color(font.palette_index, entry)) {
+                    Some(color) => color,
+                    None => continue,
+                }
+            };
+            let layer_key = GlyphKey { index, ..key.clone() };
+            let slot = match self.load_glyph(&layer_font, &layer_key) {
+                Some(slot) => slot,
+                None => continue,
+            };
+            let layer_dimensions = match self.get_glyph_dimensions_impl(slot, &layer_font, &layer_key, false) {
+                Some(dimensions) if dimensions.width > 0 && dimensions.height > 0 => dimensions,
+                _ => continue,
+            };
+            match unsafe { (*slot).format } {
+                FT_Glyph_Format::FT_GLYPH_FORMAT_OUTLINE => {}
+                _ => continue,
+            }
+            if !self.rasterize_glyph_outline(slot, &layer_font, &layer_key) {
+                continue;
+            }
+
+            let bitmap = unsafe { &(*slot).bitmap };
+            let pixel_mode = unsafe { mem::transmute(bitmap.pixel_mode as u32) };
+            match pixel_mode {
+                FT_Pixel_Mode::FT_PIXEL_MODE_GRAY => {}
+                _ => continue,
+            }
+            let (layer_left, layer_top) = unsafe {
+                (
+                    layer_dimensions.left + (*slot).bitmap_left,
+                    layer_dimensions.top + (*slot).bitmap_top - layer_dimensions.height as i32,
+                )
+            };
+
+            // Composite the layer over the previous ones, with the coverage
+            // scaling the palette color into premultiplied BGRA.
+            for y in 0 .. bitmap.rows as i32 {
+                let dest_y = top - layer_top + y;
+                if dest_y < 0 || dest_y >= height as i32 {
+                    continue;
+                }
+                let src_row = unsafe { bitmap.buffer.offset((y * bitmap.pitch) as isize) };
+                for x in 0 .. bitmap.width as i32 {
+                    let dest_x = layer_left - left + x;
+                    if dest_x < 0 || dest_x >= width as i32 {
+                        continue;
+                    }
+                    let coverage = unsafe { *src_row.offset(x as isize) } as u32;
+                    let alpha = (color[3] as u32 * coverage + 127) / 255;
+                    let inv_alpha = 255 - alpha;
+                    let dest = (dest_y as usize * width + dest_x as usize) * 4;
+                    for i in 0 .. 3 {
+                        final_buffer[dest + i] =
+                            ((color[i] as u32 * alpha + final_buffer[dest + i] as u32 * inv_alpha + 127) / 255) as u8;
+                    }
+                    final_buffer[dest + 3] =
+                        ((alpha * 255 + final_buffer[dest + 3] as u32 * inv_alpha + 127) / 255) as u8;
+                }
+            }
+        }
+
+        let (final_buffer, width, height, left, top) =
+            transform_glyph_bitmap(font, final_buffer, width, height, left, top);
+
+        GlyphRasterResult::Bitmap(RasterizedGlyph {
+            left: left as f32,
+            top: top as f32,
+            width: width as u32,
+            height: height as u32,
+            scale: 1.0,
+            format: GlyphFormat::ColorBitmap,
+            bytes: final_buffer,
+        })
+    }
+
     #[cfg(not(feature = "pathfinder"))]
     pub fn rasterize_glyph(&mut self, font: &FontInstance, key: &GlyphKey) -> GlyphRasterResult {
+        if let Some(layers) = self.get_color_layers(font, key) {
+            return self.rasterize_color_glyph(font, key, &layers);
+        }
+
         let slot = match self.load_glyph(font, key) {
             Some(slot) => slot,
             None => return GlyphRasterResult::LoadFailed,
@@ -737,28 +1181,13 @@ impl FontContext {
 
         match format {
             FT_Glyph_Format::FT_GLYPH_FORMAT_BITMAP => {
-                if font.flags.contains(FontInstanceFlags::SYNTHETIC_ITALICS) {
-                    let (skew_buffer, skew_width, skew_left) =
-                        skew_bitmap(&final_buffer, actual_width, actual_height, left, top);
-                    final_buffer = skew_buffer;
-                    actual_width = skew_width;
-                    left = skew_left;
-                }
-                if font.flags.contains(FontInstanceFlags::TRANSPOSE) {
-                    final_buffer = transpose_bitmap(&final_buffer, actual_width, actual_height);
-                    mem::swap(&mut actual_width, &mut actual_height);
-                    mem::swap(&mut left, &mut top);
-                    left -= actual_width as i32;
-                    top += actual_height as i32;
-                }
-                if font.flags.contains(FontInstanceFlags::FLIP_X) {
-                    flip_bitmap_x(&mut final_buffer, actual_width, actual_height);
-                    left = -(left + actual_width as i32);
-                }
-                if font.flags.contains(FontInstanceFlags::FLIP_Y) {
-                    flip_bitmap_y(&mut final_buffer, actual_width, actual_height);
-                    top = -(top - actual_height as i32);
-                }
+                let (buffer, buffer_width, buffer_height, buffer_left, buffer_top) =
+                    transform_glyph_bitmap(font, final_buffer, actual_width, actual_height, left, top);
+                final_buffer = buffer;
+                actual_width = buffer_width;
+                actual_height = buffer_height;
+                left = buffer_left;
+                top = buffer_top;
             }
             FT_Glyph_Format::FT_GLYPH_FORMAT_OUTLINE => {
                 unsafe {
@@ -774,6 +1203,21 @@ impl FontContext {
             (FT_Pixel_Mode::FT_PIXEL_MODE_LCD_V, _) => font.get_subpixel_glyph_format(),
             (FT_Pixel_Mode::FT_PIXEL_MODE_BGRA, _) => GlyphFormat::ColorBitmap,
             (_, FT_Glyph_Format::FT_GLYPH_FORMAT_BITMAP) => GlyphFormat::Bitmap,
+            _ if font.render_mode == FontRenderMode::Sdf => {
+                // Convert the coverage into a distance field, which is padded
+                // by the spread so that it fades out around the glyph.
+                final_buffer = compute_distance_field(
+                    &final_buffer,
+                    actual_width,
+                    actual_height,
+                    SDF_GLYPH_SPREAD,
+                );
+                actual_width += 2 * SDF_GLYPH_SPREAD;
+                actual_height += 2 * SDF_GLYPH_SPREAD;
+                left -= SDF_GLYPH_SPREAD as i32;
+                top += SDF_GLYPH_SPREAD as i32;
+                GlyphFormat::Sdf
+            }
             _ => font.get_alpha_glyph_format(),
         };
 
@@ -804,3 +1248,46 @@ impl<'a> Into<pf_freetype::FontDescriptor> for NativeFontHandleWrapper<'a> {
         pf_freetype::FontDescriptor::new(font_handle.pathname.clone().into(), font_handle.index)
     }
 }
+
+#[cfg(all(test, not(feature = "pathfinder")))]
+mod test {
+    use super::ColorTables;
+
+    #[test]
+    fn color_table_layers() {
+        let colr = vec![
+            // Version 0, two base glyphs and three layers.
+            0, 0, 0, 2, 0, 0, 0, 14, 0, 0, 0, 26, 0, 3,
+            // Base glyphs 5 and 9.
+            0, 5, 0, 0, 0, 2,
+            0, 9, 0, 2, 0, 1,
+            // Layers, with the second one in the text color.
+            0, 10, 0, 1,
+            0, 11, 0xFF, 0xFF,
+            0, 12, 0, 0,
+        ];
+        let cpal = vec![
+            // Version 0, two palettes of two entries each.
+            0, 0, 0, 2, 0, 2, 0, 4, 0, 0, 0, 16, 0, 0, 0, 2,
+            // BGRA color records.
+            0, 0, 255, 255,
+            0, 255, 0, 255,
+            255, 0, 0, 255,
+            255, 255, 255, 128,
+        ];
+        let tables = ColorTables { colr, cpal };
+
+        assert_eq!(tables.get_layers(5), Some(vec![(10, 1), (11, 0xFFFF)]));
+        assert_eq!(tables.get_layers(9), Some(vec![(12, 0)]));
+        assert_eq!(tables.get_layers(7), None);
+
+        assert_eq!(tables.get_color(0, 1), Some([0, 255, 0, 255]));
+        assert_eq!(tables.get_color(1, 0), Some([255, 0, 0, 255]));
+        assert_eq!(tables.get_color(1, 1), Some([255, 255, 255, 128]));
+        // Out of range palettes fall back to the first one.
+        assert_eq!(tables.get_color(5, 1), Some([0, 255, 0, 255]));
+        // The foreground entry isn't part of the palettes.
+        assert_eq!(tables.get_color(0, 0xFFFF), None);
+        assert_eq!(tables.get_color(0, 2), None);
+    }
+}
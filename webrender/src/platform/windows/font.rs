@@ -6,7 +6,7 @@ use api::{FontInstanceFlags, FontKey, FontRenderMode};
 use api::{ColorU, GlyphDimensions};
 use dwrote;
 use gamma_lut::ColorLut;
-use glyph_rasterizer::{FontInstance, FontTransform, GlyphKey};
+use glyph_rasterizer::{FontInstance, FontMetrics, FontTransform, GlyphKey};
 use internal_types::{FastHashMap, ResourceCacheError};
 use std::collections::hash_map::Entry;
 use std::sync::Arc;
@@ -46,7 +46,8 @@ fn dwrite_texture_type(render_mode: FontRenderMode) -> dwrote::DWRITE_TEXTURE_TY
     match render_mode {
         FontRenderMode::Mono => dwrote::DWRITE_TEXTURE_ALIASED_1x1,
         FontRenderMode::Alpha |
-        FontRenderMode::Subpixel => dwrote::DWRITE_TEXTURE_CLEARTYPE_3x1,
+        FontRenderMode::Subpixel |
+        FontRenderMode::Sdf => dwrote::DWRITE_TEXTURE_CLEARTYPE_3x1,
     }
 }
 
@@ -59,7 +60,9 @@ fn dwrite_measure_mode(
     } else {
       match font.render_mode {
           FontRenderMode::Mono => dwrote::DWRITE_MEASURING_MODE_GDI_CLASSIC,
-          FontRenderMode::Alpha | FontRenderMode::Subpixel => dwrote::DWRITE_MEASURING_MODE_NATURAL,
+          FontRenderMode::Alpha | FontRenderMode::Subpixel | FontRenderMode::Sdf => {
+              dwrote::DWRITE_MEASURING_MODE_NATURAL
+          }
       }
     }
 }
@@ -73,7 +76,7 @@ fn dwrite_render_mode(
 ) -> dwrote::DWRITE_RENDERING_MODE {
     let dwrite_render_mode = match font.render_mode {
         FontRenderMode::Mono => dwrote::DWRITE_RENDERING_MODE_ALIASED,
-        FontRenderMode::Alpha | FontRenderMode::Subpixel => {
+        FontRenderMode::Alpha | FontRenderMode::Subpixel | FontRenderMode::Sdf => {
             if bitmaps || font.flags.contains(FontInstanceFlags::FORCE_GDI) {
                 dwrote::DWRITE_RENDERING_MODE_GDI_CLASSIC
             } else {
@@ -127,6 +130,18 @@ impl FontContext {
         self.fonts.contains_key(font_key)
     }
 
+    /// Whether the glyphs of the font depend on the text color. Color
+    /// glyphs aren't rasterized through DirectWrite.
+    pub fn has_color_layers(&self, _font_key: &FontKey) -> bool {
+        false
+    }
+
+    /// Font tables aren't read through DirectWrite, the variation axes of
+    /// the fonts aren't applied either.
+    pub fn get_font_table(&self, _font_key: &FontKey, _tag: u32) -> Option<Vec<u8>> {
+        None
+    }
+
     pub fn add_raw_font(&mut self, font_key: &FontKey, data: Arc<Vec<u8>>, index: u32) {
         if self.fonts.contains_key(font_key) {
             return;
@@ -250,6 +265,27 @@ impl FontContext {
         indices.first().map(|idx| *idx as u32)
     }
 
+    pub fn get_font_metrics(&mut self, font: &FontInstance) -> Option<FontMetrics> {
+        if !self.fonts.contains_key(&font.font_key) {
+            return None;
+        }
+
+        let metrics = self.get_font_face(font).metrics();
+        let scale = font.size.to_f32_px() / metrics.designUnitsPerEm as f32;
+        let underline_thickness = metrics.underlineThickness as f32 * scale;
+        let strikeout_thickness = metrics.strikethroughThickness as f32 * scale;
+
+        // DirectWrite gives the position of the top of the lines, positive upwards.
+        Some(FontMetrics {
+            ascent: metrics.ascent as f32 * scale,
+            descent: metrics.descent as f32 * scale,
+            underline_offset: -(metrics.underlinePosition as f32) * scale + 0.5 * underline_thickness,
+            underline_thickness,
+            strikeout_offset: -(metrics.strikethroughPosition as f32) * scale + 0.5 * strikeout_thickness,
+            strikeout_thickness,
+        })
+    }
+
     pub fn get_glyph_dimensions(
         &mut self,
         font: &FontInstance,
@@ -339,7 +375,7 @@ impl FontContext {
                 }
                 bgra_pixels
             }
-            (FontRenderMode::Alpha, _) | (_, true) => {
+            (FontRenderMode::Alpha, _) | (FontRenderMode::Sdf, _) | (_, true) => {
                 let length = pixels.len() / 3;
                 let mut bgra_pixels: Vec<u8> = vec![0; length * 4];
                 for i in 0 .. length {
@@ -366,6 +402,11 @@ impl FontContext {
         }
     }
 
+    /// Whether glyphs can be rasterized as distance fields.
+    pub fn supports_sdf() -> bool {
+        false
+    }
+
     pub fn prepare_font(font: &mut FontInstance) {
         match font.render_mode {
             FontRenderMode::Mono => {
@@ -374,7 +415,7 @@ impl FontContext {
                 // Subpixel positioning is disabled in mono mode.
                 font.disable_subpixel_position();
             }
-            FontRenderMode::Alpha => {
+            FontRenderMode::Alpha | FontRenderMode::Sdf => {
                 font.color = font.color.luminance_color().quantize();
             }
             FontRenderMode::Subpixel => {
@@ -436,7 +477,7 @@ impl FontContext {
 
         let lut_correction = match font.render_mode {
             FontRenderMode::Mono => &self.gdi_gamma_lut,
-            FontRenderMode::Alpha | FontRenderMode::Subpixel => {
+            FontRenderMode::Alpha | FontRenderMode::Subpixel | FontRenderMode::Sdf => {
                 if bitmaps || font.flags.contains(FontInstanceFlags::FORCE_GDI) {
                     &self.gdi_gamma_lut
                 } else {
@@ -3,11 +3,12 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use api::{AlphaType, BorderRadius, BoxShadowClipMode, BuiltDisplayList, ClipMode, ColorF, ComplexClipRegion};
-use api::{DeviceIntRect, DeviceIntSize, DevicePixelScale, Epoch, ExtendMode};
+use api::{DeviceIntRect, DeviceIntSize, DevicePixelScale, DevicePoint, DeviceRect, Epoch, ExtendMode};
 use api::{FilterOp, GlyphInstance, GradientStop, ImageKey, ImageRendering, ItemRange, ItemTag, TileOffset};
 use api::{GlyphRasterSpace, LayoutPoint, LayoutRect, LayoutSize, LayoutToWorldTransform, LayoutVector2D};
 use api::{PipelineId, PremultipliedColorF, PropertyBinding, Shadow, YuvColorSpace, YuvFormat, DeviceIntSideOffsets};
-use api::{BorderWidths, LayoutToWorldScale, NormalBorder};
+use api::{BorderWidths, FillRule, FontRenderMode, LayoutToWorldScale, NormalBorder, PathPaint, TextDecorationKind};
+use api::{EmphasisMark, EmphasisMarkPosition, EmphasisMarkShape, LineCap, LineJoin, PathCommand, StrokeStyle};
 use app_units::Au;
 use border::{BorderCacheKey, BorderRenderTaskInfo};
 use box_shadow::BLUR_SAMPLE_SCALE;
@@ -17,21 +18,25 @@ use clip::{ClipChain, ClipChainNode, ClipChainNodeIter, ClipChainNodeRef, ClipSo
 use clip::{ClipSourcesHandle, ClipWorkItem};
 use frame_builder::{FrameBuildingContext, FrameBuildingState, PictureContext, PictureState};
 use frame_builder::PrimitiveRunContext;
-use glyph_rasterizer::{FontInstance, FontTransform, GlyphKey};
+use glyph_rasterizer::{FontInstance, FontTransform, GlyphKey, SDF_GLYPH_SIZE};
 use gpu_cache::{GpuBlockData, GpuCache, GpuCacheAddress, GpuCacheHandle, GpuDataRequest,
                 ToGpuBlocks};
-use gpu_types::{BrushFlags, ClipChainRectIndex};
+use gpu_types::{BrushFlags, ClipChainRectIndex, PathInstance};
 use image::{for_each_tile, for_each_repetition};
-use picture::{PictureCompositeMode, PictureId, PicturePrimitive};
+use path::{LineSegment, MAX_PATH_TASK_SIZE, PATH_CHUNK_SEGMENTS, PathCacheKey};
+use path::{write_path_gpu_data, write_path_segments};
+use path::{MAX_PATH_SEGMENTS, flatten_path, flatten_subpaths, stroke_path};
+use picture::{PictureCompositeMode, PictureId, PicturePrimitive, PictureSurface};
 #[cfg(debug_assertions)]
 use render_backend::FrameId;
 use render_task::{BlitSource, RenderTask, RenderTaskCacheKey};
 use render_task::{RenderTaskCacheKeyKind, RenderTaskId, RenderTaskCacheEntryHandle};
 use renderer::{MAX_VERTEX_TEXTURE_WIDTH};
-use resource_cache::{ImageProperties, ImageRequest};
+use resource_cache::{ImageProperties, ImageRequest, ResourceCache};
 use scene::SceneProperties;
 use segment::SegmentBuilder;
-use std::{mem, usize};
+use svg_filter;
+use std::{f32, mem, usize};
 use std::sync::Arc;
 use util::{MatrixHelpers, WorldToLayoutFastTransform, calculate_screen_bounding_rect};
 use util::{pack_as_float, recycle_vec};
@@ -257,6 +262,7 @@ pub enum BorderSource {
 pub enum BrushKind {
     Solid {
         color: ColorF,
+        color_binding: PropertyBinding<ColorF>,
         opacity_binding: OpacityBinding,
     },
     Clear,
@@ -292,6 +298,18 @@ pub enum BrushKind {
         tile_spacing: LayoutSize,
         visible_tiles: Vec<VisibleGradientTile>,
     },
+    ConicGradient {
+        stops_handle: GpuCacheHandle,
+        stops_range: ItemRange<GradientStop>,
+        extend_mode: ExtendMode,
+        center: LayoutPoint,
+        angle: f32,
+        start_offset: f32,
+        end_offset: f32,
+        stretch_size: LayoutSize,
+        tile_spacing: LayoutSize,
+        visible_tiles: Vec<VisibleGradientTile>,
+    },
     LinearGradient {
         stops_handle: GpuCacheHandle,
         stops_range: ItemRange<GradientStop>,
@@ -306,6 +324,37 @@ pub enum BrushKind {
     Border {
         source: BorderSource,
     },
+    /// A filled path, rasterized with its paint into the texture
+    /// cache and drawn as an image. Strokes are filled outlines.
+    Path {
+        /// The outline of the path, relative to the primitive origin.
+        segments: Vec<LineSegment>,
+        fill_rule: FillRule,
+        /// The paint, with gradient points relative to the primitive origin.
+        paint: PathPaint,
+        stops_range: ItemRange<GradientStop>,
+        stops_handle: GpuCacheHandle,
+        data_handle: GpuCacheHandle,
+        /// One handle per chunk of `PATH_CHUNK_SEGMENTS` segments.
+        segment_handles: Vec<GpuCacheHandle>,
+        cache_key: PathCacheKey,
+        handle: Option<RenderTaskCacheEntryHandle>,
+    },
+    TextDecoration {
+        color: ColorF,
+        font: FontInstance,
+        kind: TextDecorationKind,
+        thickness: Option<f32>,
+        skip_ink: bool,
+        /// The glyph runs of the decorated text item.
+        glyph_ranges: Vec<ItemRange<GlyphInstance>>,
+        offset: LayoutVector2D,
+    },
+    /// Draws the surface of the given picture, sampled in screen
+    /// space. Used as the source of backdrop filters.
+    Backdrop {
+        pic_index: PictureIndex,
+    },
 }
 
 impl BrushKind {
@@ -315,24 +364,130 @@ impl BrushKind {
             BrushKind::Image { .. } |
             BrushKind::YuvImage { .. } |
             BrushKind::RadialGradient { .. } |
+            BrushKind::ConicGradient { .. } |
             BrushKind::Border { .. } |
+            BrushKind::Path { .. } |
+            BrushKind::TextDecoration { .. } |
             BrushKind::LinearGradient { .. } => true,
 
             // TODO(gw): Allow batch.rs to add segment instances
             //           for Picture primitives.
             BrushKind::Picture { .. } => false,
 
+            BrushKind::Backdrop { .. } |
             BrushKind::Clear => false,
         }
     }
 
     // Construct a brush that is a solid color rectangle.
     pub fn new_solid(color: ColorF) -> BrushKind {
+        BrushKind::new_solid_with_binding(PropertyBinding::Value(color))
+    }
+
+    // Construct a solid color brush whose color may be animated. The
+    // initial color is the default value of the binding.
+    pub fn new_solid_with_binding(color_binding: PropertyBinding<ColorF>) -> BrushKind {
+        let color = match color_binding {
+            PropertyBinding::Value(value) => value,
+            PropertyBinding::Binding(_, value) => value,
+        };
+
         BrushKind::Solid {
             color,
+            color_binding,
             opacity_binding: OpacityBinding::new(),
         }
     }
+
+    // Construct a linear gradient brush.
+    pub fn new_linear_gradient(
+        start_point: LayoutPoint,
+        end_point: LayoutPoint,
+        stops: ItemRange<GradientStop>,
+        extend_mode: ExtendMode,
+        stretch_size: LayoutSize,
+        tile_spacing: LayoutSize,
+    ) -> BrushKind {
+        // Try to ensure that if the gradient is specified in reverse, then so long as the stops
+        // are also supplied in reverse that the rendered result will be equivalent. To do this,
+        // a reference orientation for the gradient line must be chosen, somewhat arbitrarily, so
+        // just designate the reference orientation as start < end. Aligned gradient rendering
+        // manages to produce the same result regardless of orientation, so don't worry about
+        // reversing in that case.
+        let reverse_stops = start_point.x > end_point.x ||
+            (start_point.x == end_point.x && start_point.y > end_point.y);
+
+        // To get reftests exactly matching with reverse start/end
+        // points, it's necessary to reverse the gradient
+        // line in some cases.
+        let (sp, ep) = if reverse_stops {
+            (end_point, start_point)
+        } else {
+            (start_point, end_point)
+        };
+
+        BrushKind::LinearGradient {
+            stops_range: stops,
+            extend_mode,
+            reverse_stops,
+            start_point: sp,
+            end_point: ep,
+            stops_handle: GpuCacheHandle::new(),
+            stretch_size,
+            tile_spacing,
+            visible_tiles: Vec::new(),
+        }
+    }
+
+    // Construct a radial gradient brush.
+    pub fn new_radial_gradient(
+        center: LayoutPoint,
+        start_radius: f32,
+        end_radius: f32,
+        ratio_xy: f32,
+        stops: ItemRange<GradientStop>,
+        extend_mode: ExtendMode,
+        stretch_size: LayoutSize,
+        tile_spacing: LayoutSize,
+    ) -> BrushKind {
+        BrushKind::RadialGradient {
+            stops_range: stops,
+            extend_mode,
+            center,
+            start_radius,
+            end_radius,
+            ratio_xy,
+            stops_handle: GpuCacheHandle::new(),
+            stretch_size,
+            tile_spacing,
+            visible_tiles: Vec::new(),
+        }
+    }
+
+    // Construct a brush that fills the segments with the paint. The
+    // stops are only used by gradient paints.
+    pub fn new_path(
+        segments: Vec<LineSegment>,
+        fill_rule: FillRule,
+        paint: PathPaint,
+        stops_range: ItemRange<GradientStop>,
+        stops: &[GradientStop],
+    ) -> BrushKind {
+        let cache_key = PathCacheKey::new(&segments, fill_rule, &paint, stops);
+        let chunk_count = (segments.len() + PATH_CHUNK_SEGMENTS - 1) / PATH_CHUNK_SEGMENTS;
+
+        BrushKind::Path {
+            segments,
+            fill_rule,
+            paint,
+            stops_range,
+            stops_handle: GpuCacheHandle::new(),
+            data_handle: GpuCacheHandle::new(),
+            segment_handles: (0 .. chunk_count).map(|_| GpuCacheHandle::new()).collect(),
+            cache_key,
+            handle: None,
+        }
+    }
 }
 
 bitflags! {
@@ -441,9 +596,10 @@ impl BrushPrimitive {
     ) {
         // has to match VECS_PER_SPECIFIC_BRUSH
         match self.kind {
-            BrushKind::Border { .. } => {
-                // Border primitives currently used for
-                // image borders, and run through the
+            BrushKind::Border { .. } |
+            BrushKind::Path { .. } => {
+                // Border and path primitives are drawn from
+                // the texture cache, and run through the
                 // normal brush_image shader.
                 request.push(PremultipliedColorF::WHITE);
                 request.push(PremultipliedColorF::WHITE);
@@ -455,7 +611,8 @@ impl BrushPrimitive {
                 ]);
             }
             BrushKind::YuvImage { .. } => {}
-            BrushKind::Picture { .. } => {
+            BrushKind::Picture { .. } |
+            BrushKind::Backdrop { .. } => {
                 request.push(PremultipliedColorF::WHITE);
                 request.push(PremultipliedColorF::WHITE);
                 request.push([
@@ -481,6 +638,10 @@ impl BrushPrimitive {
             BrushKind::Solid { color, ref opacity_binding, .. } => {
                 request.push(color.scale_alpha(opacity_binding.current).premultiplied());
             }
+            // Text decorations are drawn as solid segments.
+            BrushKind::TextDecoration { color, .. } => {
+                request.push(color.premultiplied());
+            }
             BrushKind::Clear => {
                 // Opaque black with operator dest out
                 request.push(PremultipliedColorF::BLACK);
@@ -513,6 +674,20 @@ impl BrushPrimitive {
                     stretch_size.height,
                 ]);
             }
+            BrushKind::ConicGradient { stretch_size, center, angle, start_offset, end_offset, extend_mode, .. } => {
+                request.push([
+                    center.x,
+                    center.y,
+                    start_offset,
+                    end_offset,
+                ]);
+                request.push([
+                    angle,
+                    pack_as_float(extend_mode as u32),
+                    stretch_size.width,
+                    stretch_size.height,
+                ]);
+            }
         }
     }
 }
@@ -776,6 +951,14 @@ impl TextRunPrimitiveCpu {
         transform: Option<LayoutToWorldTransform>,
     ) -> FontInstance {
         let mut font = self.font.clone();
+        // Animated variations have already been resolved for this frame, so
+        // the bindings are left out to share glyphs with any instance that
+        // is at the same axis values.
+        font.variation_bindings.clear();
+        if font.render_mode == FontRenderMode::Sdf {
+            return font.to_sdf_instance();
+        }
+
         font.size = font.size.scale_by(device_pixel_scale.0);
         if let Some(transform) = transform {
             if transform.has_perspective_component() ||
@@ -796,12 +979,23 @@ impl TextRunPrimitiveCpu {
         transform: Option<LayoutToWorldTransform>,
         allow_subpixel_aa: bool,
         display_list: &BuiltDisplayList,
+        scene_properties: &SceneProperties,
         frame_building_state: &mut FrameBuildingState,
     ) {
         if !allow_subpixel_aa && self.font.bg_color.a == 0 {
             self.font.disable_subpixel_aa();
         }
 
+        // Resolve any animated variation axes to their values for this frame.
+        let animated_variations: Vec<_> = self.font.variation_bindings
+            .iter()
+            .map(|binding| (binding.tag, scene_properties.resolve_float(&binding.value)))
+            .collect();
+        for (tag, value) in animated_variations {
+            let quantum = frame_building_state.resource_cache.get_variation_quantum(self.font.font_key, tag);
+            self.font.set_animated_variation(tag, value, quantum);
+        }
+
         let font = self.get_font(device_pixel_scale, transform);
 
         // Cache the glyph positions, if not in the cache already.
@@ -809,6 +1003,9 @@ impl TextRunPrimitiveCpu {
         //           completely, and just reference the glyphs
         //           directly from the display list.
         if self.glyph_keys.is_empty() || self.glyph_transform != (device_pixel_scale, font.transform) {
+            self.glyph_keys.clear();
+            self.glyph_gpu_blocks.clear();
+
             let subpx_dir = font.get_subpx_dir();
             let src_glyphs = display_list.get(self.glyph_range);
 
@@ -857,10 +1054,17 @@ impl TextRunPrimitiveCpu {
         // this is the only case where we need to provide plain color to GPU
         let bg_color = ColorF::from(self.font.bg_color);
         request.push([bg_color.r, bg_color.g, bg_color.b, 1.0]);
+        // Distance field glyphs are scaled from their rasterized size to
+        // the local font size in the shader.
+        let sdf_scale = if self.font.render_mode == FontRenderMode::Sdf {
+            self.font.size.to_f32_px() / SDF_GLYPH_SIZE
+        } else {
+            0.0
+        };
         request.push([
             self.offset.x,
             self.offset.y,
-            0.0,
+            sdf_scale,
             0.0,
         ]);
         request.extend_from_slice(&self.glyph_gpu_blocks);
@@ -914,15 +1118,36 @@ impl ClipCorner {
     }
 }
 
+/// Which channels of a mask image give the mask value.
+#[derive(Debug, Copy, Clone)]
+#[repr(u32)]
+pub enum ImageMaskChannel {
+    Red = 0,
+    Alpha = 1,
+    Luminance = 2,
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct ImageMaskData {
     pub local_rect: LayoutRect,
+    pub tile_size: LayoutSize,
+    pub tile_spacing: LayoutSize,
+    pub channel: ImageMaskChannel,
 }
 
 impl ToGpuBlocks for ImageMaskData {
     fn write_gpu_blocks(&self, mut request: GpuDataRequest) {
         request.push(self.local_rect);
+        request.push([
+            self.tile_size.width,
+            self.tile_size.height,
+            self.tile_spacing.width,
+            self.tile_spacing.height,
+        ]);
+        // An image source, see cs_clip_image.
+        request.push([0.0, pack_as_float(self.channel as u32), 0.0, 0.0]);
+        request.push([0.0; 4]);
     }
 }
 
@@ -1073,14 +1298,23 @@ impl PrimitiveContainer {
             }
             PrimitiveContainer::Brush(ref brush) => {
                 match brush.kind {
-                    BrushKind::Solid { ref color, .. } => {
+                    // An animated color may become visible later.
+                    BrushKind::Solid { color_binding: PropertyBinding::Binding(..), .. } => {
+                        true
+                    }
+                    BrushKind::Solid { ref color, .. } |
+                    BrushKind::TextDecoration { ref color, .. } |
+                    BrushKind::Path { paint: PathPaint::Color(ref color), .. } => {
                         color.a > 0.0
                     }
+                    BrushKind::Path { .. } |
                     BrushKind::Clear |
                     BrushKind::Picture { .. } |
+                    BrushKind::Backdrop { .. } |
                     BrushKind::Image { .. } |
                     BrushKind::YuvImage { .. } |
                     BrushKind::RadialGradient { .. } |
+                    BrushKind::ConicGradient { .. } |
                     BrushKind::Border { .. } |
                     BrushKind::LinearGradient { .. } => {
                         true
@@ -1121,12 +1355,40 @@ impl PrimitiveContainer {
                             None,
                         ))
                     }
+                    BrushKind::TextDecoration { ref font, kind, thickness, skip_ink, ref glyph_ranges, offset, .. } => {
+                        PrimitiveContainer::Brush(BrushPrimitive::new(
+                            BrushKind::TextDecoration {
+                                color: shadow.color,
+                                font: font.clone(),
+                                kind,
+                                thickness,
+                                skip_ink,
+                                glyph_ranges: glyph_ranges.clone(),
+                                offset: offset + shadow.offset,
+                            },
+                            None,
+                        ))
+                    }
+                    BrushKind::Path { ref segments, fill_rule, .. } => {
+                        PrimitiveContainer::Brush(BrushPrimitive::new(
+                            BrushKind::new_path(
+                                segments.clone(),
+                                fill_rule,
+                                PathPaint::Color(shadow.color),
+                                ItemRange::default(),
+                                &[],
+                            ),
+                            None,
+                        ))
+                    }
                     BrushKind::Clear |
                     BrushKind::Picture { .. } |
+                    BrushKind::Backdrop { .. } |
                     BrushKind::Image { .. } |
                     BrushKind::YuvImage { .. } |
                     BrushKind::Border { .. } |
                     BrushKind::RadialGradient { .. } |
+                    BrushKind::ConicGradient { .. } |
                     BrushKind::LinearGradient { .. } => {
                         panic!("bug: other brush kinds not expected here yet");
                     }
@@ -1230,9 +1492,13 @@ impl PrimitiveStore {
                     BrushKind::Image { .. } => PrimitiveOpacity::translucent(),
                     BrushKind::YuvImage { .. } => PrimitiveOpacity::opaque(),
                     BrushKind::RadialGradient { .. } => PrimitiveOpacity::translucent(),
+                    BrushKind::ConicGradient { .. } => PrimitiveOpacity::translucent(),
                     BrushKind::LinearGradient { .. } => PrimitiveOpacity::translucent(),
                     BrushKind::Picture { .. } => PrimitiveOpacity::translucent(),
+                    BrushKind::Backdrop { .. } => PrimitiveOpacity::translucent(),
                     BrushKind::Border { .. } => PrimitiveOpacity::translucent(),
+                    BrushKind::Path { .. } => PrimitiveOpacity::translucent(),
+                    BrushKind::TextDecoration { .. } => PrimitiveOpacity::translucent(),
                 };
 
                 let metadata = PrimitiveMetadata {
@@ -1308,9 +1574,13 @@ impl PrimitiveStore {
                         return Some(run.base_prim_index)
                     }
                     BrushKind::Border { .. } |
+                    BrushKind::Path { .. } |
                     BrushKind::YuvImage { .. } |
                     BrushKind::LinearGradient { .. } |
                     BrushKind::RadialGradient { .. } |
+                    BrushKind::ConicGradient { .. } |
+                    BrushKind::TextDecoration { .. } |
+                    BrushKind::Backdrop { .. } |
                     BrushKind::Clear => {}
                 }
             }
@@ -1357,8 +1627,12 @@ impl PrimitiveStore {
                         BrushKind::Picture { .. } |
                         BrushKind::YuvImage { .. } |
                         BrushKind::Border { .. } |
+                        BrushKind::Path { .. } |
                         BrushKind::LinearGradient { .. } |
-                        BrushKind::RadialGradient { .. } => {
+                        BrushKind::RadialGradient { .. } |
+                        BrushKind::ConicGradient { .. } |
+                        BrushKind::TextDecoration { .. } |
+                        BrushKind::Backdrop { .. } => {
                             unreachable!("bug: invalid prim type for opacity collapse");
                         }
                     };
@@ -1389,6 +1663,7 @@ impl PrimitiveStore {
         &mut self,
         prim_index: PrimitiveIndex,
         pic_state: &mut PictureState,
+        display_list: &BuiltDisplayList,
         frame_state: &mut FrameBuildingState,
         frame_context: &FrameBuildingContext,
     ) {
@@ -1467,6 +1742,64 @@ impl PrimitiveStore {
                 }
             }
         }
+
+        let mut emphasis_path = None;
+        if let BrushKind::TextDecoration { color, ref font, kind, thickness, skip_ink, ref glyph_ranges, offset } = brush.kind {
+            // The decoration only depends on local space glyph metrics, so it
+            // is built once.
+            if brush.segment_desc.is_none() {
+                let glyphs: Vec<GlyphInstance> = glyph_ranges
+                    .iter()
+                    .flat_map(|range| display_list.get(*range))
+                    .collect();
+
+                match kind {
+                    TextDecorationKind::Emphasis(mark) => {
+                        // Emphasis marks aren't rectangles, so once the glyph
+                        // bounds are known they are drawn as a path.
+                        let segments = emphasis_mark_segments(
+                            &metadata.local_rect,
+                            mark,
+                            font,
+                            &glyphs,
+                            offset,
+                            frame_state.resource_cache,
+                        );
+                        emphasis_path = Some(BrushKind::new_path(
+                            segments,
+                            FillRule::Nonzero,
+                            PathPaint::Color(color),
+                            ItemRange::default(),
+                            &[],
+                        ));
+                    }
+                    TextDecorationKind::Underline |
+                    TextDecorationKind::Overline |
+                    TextDecorationKind::LineThrough => {
+                        let segments = text_decoration_segments(
+                            &metadata.local_rect,
+                            kind,
+                            thickness,
+                            skip_ink,
+                            font,
+                            &glyphs,
+                            offset,
+                            frame_state.resource_cache,
+                        );
+
+                        brush.segment_desc = Some(BrushSegmentDescriptor {
+                            segments,
+                            clip_mask_kind: BrushClipMaskKind::Unknown,
+                        });
+                    }
+                }
+
+                frame_state.gpu_cache.invalidate(&mut metadata.gpu_location);
+            }
+        }
+        if let Some(kind) = emphasis_path {
+            brush.kind = kind;
+        }
     }
 
     fn prepare_prim_for_render_inner(
@@ -1496,6 +1829,7 @@ impl PrimitiveStore {
                     transform,
                     pic_context.allow_subpixel_aa,
                     pic_context.display_list,
+                    frame_context.scene_properties,
                     frame_state,
                 );
             }
@@ -1807,6 +2141,58 @@ impl PrimitiveStore {
                             );
                         }
                     }
+                    BrushKind::ConicGradient {
+                        stops_range,
+                        center,
+                        angle,
+                        start_offset,
+                        end_offset,
+                        extend_mode,
+                        stretch_size,
+                        tile_spacing,
+                        ref mut stops_handle,
+                        ref mut visible_tiles,
+                        ..
+                    } => {
+                        build_gradient_stops_request(
+                            stops_handle,
+                            stops_range,
+                            false,
+                            frame_state,
+                            pic_context,
+                        );
+
+                        if tile_spacing != LayoutSize::zero() {
+                            is_tiled = true;
+
+                            decompose_repeated_primitive(
+                                visible_tiles,
+                                metadata,
+                                &stretch_size,
+                                &tile_spacing,
+                                prim_run_context,
+                                frame_context,
+                                frame_state,
+                                &mut |rect, clip_rect, mut request| {
+                                    request.push(*rect);
+                                    request.push(*clip_rect);
+                                    request.push([
+                                        center.x,
+                                        center.y,
+                                        start_offset,
+                                        end_offset,
+                                    ]);
+                                    request.push([
+                                        angle,
+                                        pack_as_float(extend_mode as u32),
+                                        stretch_size.width,
+                                        stretch_size.height,
+                                    ]);
+                                    request.write_segment(*rect, [0.0; 4]);
+                                },
+                            );
+                        }
+                    }
                     BrushKind::LinearGradient {
                         stops_range,
                         reverse_stops,
@@ -1871,16 +2257,141 @@ impl PrimitiveStore {
                             frame_state,
                         );
                     }
-                    BrushKind::Solid { ref color, ref mut opacity_binding, .. } => {
-                        // If the opacity changed, invalidate the GPU cache so that
-                        // the new color for this primitive gets uploaded. Also update
+                    BrushKind::Solid { ref mut color, ref color_binding, ref mut opacity_binding } => {
+                        // If the color or opacity changed, invalidate the GPU cache so
+                        // that the new color for this primitive gets uploaded. Also update
                         // the opacity field that controls which batches this primitive
                         // will be added to.
-                        if opacity_binding.update(frame_context.scene_properties) {
+                        let new_color = frame_context.scene_properties.resolve_color(color_binding);
+                        let color_changed = new_color != *color;
+                        *color = new_color;
+
+                        let opacity_changed = opacity_binding.update(frame_context.scene_properties);
+                        if color_changed || opacity_changed {
                             metadata.opacity = PrimitiveOpacity::from_alpha(opacity_binding.current * color.a);
                             frame_state.gpu_cache.invalidate(&mut metadata.gpu_location);
                         }
                     }
+                    BrushKind::TextDecoration { .. } => {
+                        // Handled earlier since we need to update the segment
+                        // descriptor *before* update_clip_task() is called.
+                    }
+                    BrushKind::Path {
+                        ref segments,
+                        fill_rule,
+                        ref paint,
+                        stops_range,
+                        ref mut stops_handle,
+                        ref mut data_handle,
+                        ref mut segment_handles,
+                        ref mut cache_key,
+                        ref mut handle,
+                    } => {
+                        // Rasterize the path at the scale it's drawn with, so
+                        // that it stays sharp when it's scaled up.
+                        let world_transform = &prim_run_context.scroll_node.world_content_transform;
+                        let world_scale = if world_transform.has_perspective_component() {
+                            1.0
+                        } else {
+                            let transform = world_transform.to_transform();
+                            (transform.m11 * transform.m11 + transform.m12 * transform.m12).sqrt()
+                                .max((transform.m21 * transform.m21 + transform.m22 * transform.m22).sqrt())
+                        };
+                        let local_size = metadata.local_rect.size;
+                        let scale = (frame_context.device_pixel_scale.0 * world_scale.max(0.01))
+                            .min(MAX_PATH_TASK_SIZE / local_size.width.max(local_size.height));
+                        let task_size = DeviceIntSize::new(
+                            (local_size.width * scale).ceil() as i32,
+                            (local_size.height * scale).ceil() as i32,
+                        );
+                        cache_key.scale = Au::from_f32_px(scale);
+
+                        let has_gradient = match *paint {
+                            PathPaint::Gradient(..) => true,
+                            PathPaint::Color(..) => false,
+                        };
+                        if has_gradient {
+                            build_gradient_stops_request(
+                                stops_handle,
+                                stops_range,
+                                false,
+                                frame_state,
+                                pic_context,
+                            );
+                        }
+                        // The path data refers to the segment chunks, so it has
+                        // to be rewritten when any of them moved.
+                        let mut chunks_moved = false;
+                        for (chunk_handle, chunk) in segment_handles
+                            .iter_mut()
+                            .zip(segments.chunks(PATH_CHUNK_SEGMENTS))
+                        {
+                            if let Some(mut request) = frame_state.gpu_cache.request(chunk_handle) {
+                                write_path_segments(&mut request, chunk);
+                                chunks_moved = true;
+                            }
+                        }
+                        if chunks_moved {
+                            frame_state.gpu_cache.invalidate(data_handle);
+                        }
+                        let chunk_addresses: Vec<GpuCacheAddress> = segment_handles
+                            .iter()
+                            .map(|chunk_handle| frame_state.gpu_cache.get_address(chunk_handle))
+                            .collect();
+                        if let Some(mut request) = frame_state.gpu_cache.request(data_handle) {
+                            write_path_gpu_data(
+                                &mut request,
+                                segments.len(),
+                                &chunk_addresses,
+                                fill_rule,
+                                paint,
+                            );
+                        }
+
+                        // The path data is requested every frame, so that it's
+                        // available whenever the cached task needs to be redrawn.
+                        let instance = PathInstance {
+                            task_origin: DevicePoint::zero(),
+                            local_rect: DeviceRect::new(DevicePoint::zero(), task_size.to_f32()),
+                            local_size,
+                            path_data_address: frame_state.gpu_cache.get_address(data_handle),
+                            stops_address: if has_gradient {
+                                frame_state.gpu_cache.get_address(stops_handle)
+                            } else {
+                                GpuCacheAddress::invalid()
+                            },
+                        };
+
+                        *handle = Some(frame_state.resource_cache.request_render_task(
+                            RenderTaskCacheKey {
+                                size: task_size,
+                                kind: RenderTaskCacheKeyKind::Path(cache_key.clone()),
+                            },
+                            frame_state.gpu_cache,
+                            frame_state.render_tasks,
+                            None,
+                            false,
+                            |render_tasks| {
+                                let task = RenderTask::new_path(task_size, instance);
+                                let task_id = render_tasks.add(task);
+
+                                pic_state.tasks.push(task_id);
+
+                                task_id
+                            }
+                        ));
+                    }
+                    BrushKind::Backdrop { pic_index } => {
+                        // The backdrop picture is always added before the
+                        // filters that sample from it, so if it's visible this
+                        // frame it has already allocated a surface. Make it a
+                        // dependency of the picture we are drawn into, and save
+                        // it so that the parent can still draw it afterwards.
+                        if let Some(PictureSurface::RenderTask(task_id)) = self.pictures[pic_index.0].surface {
+                            frame_state.render_tasks[task_id].mark_for_saving();
+                            pic_state.tasks.push(task_id);
+                        }
+                    }
                     BrushKind::Clear => {}
                 }
             }
@@ -2025,6 +2536,8 @@ impl PrimitiveStore {
                         continue;
                     }
                     ClipSource::LineDecoration(..) |
+                    ClipSource::Path(..) |
+                    ClipSource::MaskLayer(..) |
                     ClipSource::Image(..) => {
                         rect_clips_only = false;
 
@@ -2367,6 +2880,9 @@ impl PrimitiveStore {
                             // this picture to ensure the visibility check is correct.
                             BLUR_SAMPLE_SCALE * blur_radius
                         }
+                        Some(PictureCompositeMode::SvgFilter) => {
+                            svg_filter::filter_primitives_inflation(&pic.filter_primitives)
+                        }
                         _ => {
                             0.0
                         }
@@ -2463,6 +2979,7 @@ impl PrimitiveStore {
         self.build_prim_segments_if_needed(
             prim_index,
             pic_state,
+            pic_context.display_list,
             frame_state,
             frame_context,
         );
@@ -2497,6 +3014,11 @@ impl PrimitiveStore {
         for md in &mut self.cpu_metadata {
             md.screen_rect = None;
         }
+        // Backdrop brushes look at the surface of another picture, so
+        // make sure they don't see one left over from a previous frame.
+        for pic in &mut self.pictures {
+            pic.surface = None;
+        }
     }
 
     pub fn prepare_prim_runs(
@@ -2723,6 +3245,211 @@ fn edge_flags_for_tile_spacing(tile_spacing: &LayoutSize) -> EdgeAaSegmentMask {
     flags
 }
 
+/// Computes the visible pieces of a text decoration line, positioned from the
+/// font metrics relative to the baseline of the glyph run. With `skip_ink`, the
+/// line is interrupted wherever it crosses the bounds of a rasterized glyph.
+fn text_decoration_segments(
+    local_rect: &LayoutRect,
+    kind: TextDecorationKind,
+    thickness: Option<f32>,
+    skip_ink: bool,
+    font: &FontInstance,
+    glyphs: &[GlyphInstance],
+    offset: LayoutVector2D,
+    resource_cache: &mut ResourceCache,
+) -> Vec<BrushSegment> {
+    let baseline = match glyphs.first() {
+        Some(glyph) => glyph.point.y + offset.y,
+        None => return Vec::new(),
+    };
+    let metrics = match resource_cache.get_font_metrics(font) {
+        Some(metrics) => metrics,
+        None => return Vec::new(),
+    };
+
+    let (line_offset, line_thickness) = match kind {
+        TextDecorationKind::Underline => {
+            (metrics.underline_offset, thickness.unwrap_or(metrics.underline_thickness))
+        }
+        TextDecorationKind::Overline => {
+            let line_thickness = thickness.unwrap_or(metrics.underline_thickness);
+            (0.5 * line_thickness - metrics.ascent, line_thickness)
+        }
+        TextDecorationKind::LineThrough => {
+            (metrics.strikeout_offset, thickness.unwrap_or(metrics.strikeout_thickness))
+        }
+        TextDecorationKind::Emphasis(..) => return Vec::new(),
+    };
+
+    let line_rect = LayoutRect::new(
+        LayoutPoint::new(local_rect.origin.x, baseline + line_offset - 0.5 * line_thickness),
+        LayoutSize::new(local_rect.size.width, line_thickness),
+    );
+    let line_rect = match line_rect.intersection(local_rect) {
+        Some(rect) => rect,
+        None => return Vec::new(),
+    };
+
+    // Horizontal ranges of the line that are covered by glyphs, keeping
+    // a margin of the line thickness around them.
+    let mut gaps = Vec::new();
+    if skip_ink {
+        for glyph in glyphs {
+            let dimensions = match resource_cache.get_glyph_dimensions(font, glyph.index) {
+                Some(dimensions) => dimensions,
+                None => continue,
+            };
+            let glyph_rect = LayoutRect::new(
+                LayoutPoint::new(
+                    glyph.point.x + offset.x + dimensions.left as f32,
+                    glyph.point.y + offset.y - dimensions.top as f32,
+                ),
+                LayoutSize::new(dimensions.width as f32, dimensions.height as f32),
+            ).inflate(line_thickness, 0.0);
+
+            if glyph_rect.intersects(&line_rect) {
+                gaps.push((glyph_rect.min_x(), glyph_rect.max_x()));
+            }
+        }
+        gaps.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    }
+
+    let mut segments = Vec::new();
+    let mut x = line_rect.min_x();
+    for (gap_start, gap_end) in gaps.into_iter().chain(Some((line_rect.max_x(), line_rect.max_x()))) {
+        let end = gap_start.min(line_rect.max_x());
+        if end > x {
+            segments.push(BrushSegment::new(
+                LayoutRect::new(
+                    LayoutPoint::new(x, line_rect.origin.y),
+                    LayoutSize::new(end - x, line_rect.size.height),
+                ),
+                true,
+                EdgeAaSegmentMask::all(),
+                [0.0; 4],
+                BrushFlags::empty(),
+            ));
+        }
+        x = x.max(gap_end);
+    }
+
+    segments
+}
+
+/// Appends an ellipse, rotated by `angle` radians, to a path.
+fn push_ellipse(
+    commands: &mut Vec<PathCommand>,
+    center: LayoutPoint,
+    radius_x: f32,
+    radius_y: f32,
+    angle: f32,
+) {
+    // The distance of the control points of a cubic curve approximating
+    // a quarter of a unit circle.
+    const KAPPA: f32 = 0.5523;
+    let (sin, cos) = angle.sin_cos();
+    let point = |x: f32, y: f32| {
+        let (x, y) = (x * radius_x, y * radius_y);
+        LayoutPoint::new(center.x + x * cos - y * sin, center.y + x * sin + y * cos)
+    };
+
+    commands.push(PathCommand::MoveTo(point(1.0, 0.0)));
+    commands.push(PathCommand::CubicCurveTo(point(1.0, KAPPA), point(KAPPA, 1.0), point(0.0, 1.0)));
+    commands.push(PathCommand::CubicCurveTo(point(-KAPPA, 1.0), point(-1.0, KAPPA), point(-1.0, 0.0)));
+    commands.push(PathCommand::CubicCurveTo(point(-1.0, -KAPPA), point(-KAPPA, -1.0), point(0.0, -1.0)));
+    commands.push(PathCommand::CubicCurveTo(point(KAPPA, -1.0), point(1.0, -KAPPA), point(1.0, 0.0)));
+    commands.push(PathCommand::Close);
+}
+
+/// Computes the outline of the emphasis marks of a glyph run, relative to the
+/// primitive origin. Each glyph with ink gets a mark centered on its bounds,
+/// over the ascent or under the descent, in a box of half the font size.
+fn emphasis_mark_segments(
+    local_rect: &LayoutRect,
+    mark: EmphasisMark,
+    font: &FontInstance,
+    glyphs: &[GlyphInstance],
+    offset: LayoutVector2D,
+    resource_cache: &mut ResourceCache,
+) -> Vec<LineSegment> {
+    let baseline = match glyphs.first() {
+        Some(glyph) => glyph.point.y + offset.y,
+        None => return Vec::new(),
+    };
+    let metrics = match resource_cache.get_font_metrics(font) {
+        Some(metrics) => metrics,
+        None => return Vec::new(),
+    };
+
+    let size = 0.5 * font.size.to_f32_px();
+    let center_y = match mark.position {
+        EmphasisMarkPosition::Over => baseline - metrics.ascent - 0.5 * size,
+        EmphasisMarkPosition::Under => baseline + metrics.descent + 0.5 * size,
+    };
+
+    // The outer ring of a double circle is always stroked, the other
+    // shapes are stroked for open marks and filled otherwise.
+    let mut rings = Vec::new();
+    let mut shapes = Vec::new();
+    for glyph in glyphs {
+        let dimensions = match resource_cache.get_glyph_dimensions(font, glyph.index) {
+            Some(dimensions) => dimensions,
+            None => continue,
+        };
+        let center = LayoutPoint::new(
+            glyph.point.x + offset.x + dimensions.left as f32 + 0.5 * dimensions.width as f32,
+            center_y,
+        );
+
+        match mark.shape {
+            EmphasisMarkShape::Dot => {
+                push_ellipse(&mut shapes, center, 0.15 * size, 0.15 * size, 0.0);
+            }
+            EmphasisMarkShape::Circle => {
+                push_ellipse(&mut shapes, center, 0.3 * size, 0.3 * size, 0.0);
+            }
+            EmphasisMarkShape::DoubleCircle => {
+                push_ellipse(&mut rings, center, 0.3 * size, 0.3 * size, 0.0);
+                push_ellipse(&mut shapes, center, 0.15 * size, 0.15 * size, 0.0);
+            }
+            EmphasisMarkShape::Triangle => {
+                let radius = 0.35 * size;
+                let half_side = radius * 0.75f32.sqrt();
+                shapes.push(PathCommand::MoveTo(LayoutPoint::new(center.x, center.y - radius)));
+                shapes.push(PathCommand::LineTo(LayoutPoint::new(center.x + half_side, center.y + 0.5 * radius)));
+                shapes.push(PathCommand::LineTo(LayoutPoint::new(center.x - half_side, center.y + 0.5 * radius)));
+                shapes.push(PathCommand::Close);
+            }
+            EmphasisMarkShape::Sesame => {
+                push_ellipse(&mut shapes, center, 0.1 * size, 0.3 * size, -f32::consts::FRAC_PI_6);
+            }
+        }
+    }
+
+    let origin = -local_rect.origin.to_vector();
+    let stroke = StrokeStyle {
+        width: 0.1 * size,
+        join: LineJoin::Miter,
+        cap: LineCap::Butt,
+        miter_limit: 4.0,
+        dash_offset: 0.0,
+    };
+    let mut segments = stroke_path(&flatten_subpaths(&rings, &origin), &stroke, &[]);
+    if mark.open {
+        segments.extend(stroke_path(&flatten_subpaths(&shapes, &origin), &stroke, &[]));
+    } else {
+        segments.extend(flatten_path(&shapes, &origin));
+    }
+
+    if segments.len() > MAX_PATH_SEGMENTS {
+        warn!("Emphasis marks have {} segments, only the first {} are drawn",
+              segments.len(), MAX_PATH_SEGMENTS);
+        segments.truncate(MAX_PATH_SEGMENTS);
+    }
+
+    segments
+}
+
 //Test for one clip region contains another
 trait InsideTest<T> {
     fn might_contain(&self, clip: &T) -> bool;
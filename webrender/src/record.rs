@@ -74,6 +74,7 @@ pub fn should_record_msg(msg: &ApiMsg) -> bool {
             for msg in &msgs.scene_ops {
                 match *msg {
                     SceneMsg::SetDisplayList { .. } |
+                    SceneMsg::SpliceDisplayList { .. } |
                     SceneMsg::SetRootPipeline { .. } => return true,
                     _ => {}
                 }
@@ -2,13 +2,13 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use api::{ApiMsg, BuiltDisplayList, ClearCache, DebugCommand};
+use api::{ApiMsg, BuiltDisplayList, ClearCache, ColorF, DebugCommand};
 #[cfg(feature = "debugger")]
 use api::{BuiltDisplayListIter, SpecificDisplayItem};
 use api::{DeviceIntPoint, DevicePixelScale, DeviceUintPoint, DeviceUintRect, DeviceUintSize};
-use api::{DocumentId, DocumentLayer, ExternalScrollId, FrameMsg, HitTestFlags, HitTestResult};
-use api::{IdNamespace, LayoutPoint, PipelineId, RenderNotifier, SceneMsg, ScrollClamping};
-use api::{ScrollLocation, ScrollNodeState, TransactionMsg};
+use api::{DocumentId, DocumentLayer, Epoch, ExternalScrollId, FrameMsg, HitTestFlags};
+use api::{HitTestResult, IdNamespace, LayoutPoint, LayoutSize, PipelineId, RenderNotifier};
+use api::{SceneMsg, ScrollClamping, ScrollLocation, ScrollNodeState, TransactionMsg};
 use api::channel::{MsgReceiver, Payload};
 #[cfg(feature = "capture")]
 use api::CaptureBits;
@@ -229,7 +229,7 @@ impl Document {
         document_ops: &DocumentOps,
         document_id: DocumentId,
         scene_id: u64,
-        resource_cache: &ResourceCache,
+        resource_cache: &mut ResourceCache,
         scene_tx: &Sender<SceneBuilderRequest>,
     ) {
         // Do as much of the error handling as possible here before dispatching to
@@ -252,8 +252,13 @@ impl Document {
             None
         };
 
+        let (blob_rasterizer, blob_requests) = resource_cache
+            .create_blob_scene_builder_requests(&transaction_msg.resource_updates);
+
         scene_tx.send(SceneBuilderRequest::Transaction {
             scene: scene_request,
+            blob_requests,
+            blob_rasterizer,
             resource_updates: transaction_msg.resource_updates,
             frame_ops: transaction_msg.frame_ops,
             render: transaction_msg.generate_frame,
@@ -304,6 +309,54 @@ impl Document {
         }
     }
 
+    fn set_display_list(
+        &mut self,
+        pipeline_id: PipelineId,
+        epoch: Epoch,
+        built_display_list: BuiltDisplayList,
+        background: Option<ColorF>,
+        viewport_size: LayoutSize,
+        content_size: LayoutSize,
+        preserve_frame_state: bool,
+        ipc_profile_counters: &mut IpcProfileCounters,
+    ) {
+        if !preserve_frame_state {
+            self.discard_frame_state_for_pipeline(pipeline_id);
+        }
+
+        let display_list_len = built_display_list.data().len();
+        let (builder_start_time, builder_finish_time, send_start_time) =
+            built_display_list.times();
+        let display_list_received_time = precise_time_ns();
+
+        self.pending.scene.set_display_list(
+            pipeline_id,
+            epoch,
+            built_display_list,
+            background,
+            viewport_size,
+            content_size,
+        );
+
+        if let Some(ref mut ros) = self.render_on_scroll {
+            *ros = false; //wait for `GenerateFrame`
+        }
+
+        // Note: this isn't quite right as auxiliary values will be
+        // pulled out somewhere in the prim_store, but aux values are
+        // really simple and cheap to access, so it's not a big deal.
+        let display_list_consumed_time = precise_time_ns();
+
+        ipc_profile_counters.set(
+            builder_start_time,
+            builder_finish_time,
+            send_start_time,
+            display_list_received_time,
+            display_list_consumed_time,
+            display_list_len,
+        );
+    }
+
     pub fn discard_frame_state_for_pipeline(&mut self, pipeline_id: PipelineId) {
         self.clip_scroll_tree
             .discard_frame_state_for_pipeline(pipeline_id);
@@ -347,6 +400,30 @@ impl Document {
     }
 }
 
+/// Takes the payload matching `epoch` and `pipeline_id` out of the buffer, or
+/// receives payloads until it arrives.
+fn take_payload(
+    payload_rx: &Receiver<Payload>,
+    payload_buffer: &mut Vec<Payload>,
+    epoch: Epoch,
+    pipeline_id: PipelineId,
+) -> Payload {
+    if let Some(idx) = payload_buffer.iter().position(|data|
+        data.epoch == epoch && data.pipeline_id == pipeline_id
+    ) {
+        return payload_buffer.swap_remove(idx);
+    }
+
+    loop {
+        let data = payload_rx.recv().unwrap();
+        if data.epoch == epoch && data.pipeline_id == pipeline_id {
+            break data;
+        } else {
+            payload_buffer.push(data);
+        }
+    }
+}
+
 struct DocumentOps {
     scroll: bool,
     build: bool,
@@ -512,20 +589,7 @@ impl RenderBackend {
             } => {
                 profile_scope!("SetDisplayList");
 
-                let data = if let Some(idx) = self.payload_buffer.iter().position(|data|
-                    data.epoch == epoch && data.pipeline_id == pipeline_id
-                ) {
-                    self.payload_buffer.swap_remove(idx)
-                } else {
-                    loop {
-                        let data = self.payload_rx.recv().unwrap();
-                        if data.epoch == epoch && data.pipeline_id == pipeline_id {
-                            break data;
-                        } else {
-                            self.payload_buffer.push(data);
-                        }
-                    }
-                };
+                let data = take_payload(&self.payload_rx, &mut self.payload_buffer, epoch, pipeline_id);
 
                 if let Some(ref mut r) = self.recorder {
                     r.write_payload(frame_counter, &data.to_data());
@@ -534,42 +598,69 @@ impl RenderBackend {
                 let built_display_list =
                     BuiltDisplayList::from_data(data.display_list_data, list_descriptor);
 
-                if !preserve_frame_state {
-                    doc.discard_frame_state_for_pipeline(pipeline_id);
-                }
+                doc.set_display_list(
+                    pipeline_id,
+                    epoch,
+                    built_display_list,
+                    background,
+                    viewport_size,
+                    content_size,
+                    preserve_frame_state,
+                    ipc_profile_counters,
+                );
+
+                DocumentOps::build()
+            }
+            SceneMsg::SpliceDisplayList {
+                epoch,
+                base_epoch,
+                pipeline_id,
+                background,
+                viewport_size,
+                content_size,
+                list_descriptor,
+                ops,
+                preserve_frame_state,
+            } => {
+                profile_scope!("SpliceDisplayList");
 
-                let display_list_len = built_display_list.data().len();
-                let (builder_start_time, builder_finish_time, send_start_time) =
-                    built_display_list.times();
-                let display_list_received_time = precise_time_ns();
+                let data = take_payload(&self.payload_rx, &mut self.payload_buffer, epoch, pipeline_id);
 
-                {
-                    doc.pending.scene.set_display_list(
-                        pipeline_id,
-                        epoch,
-                        built_display_list,
-                        background,
-                        viewport_size,
-                        content_size,
-                    );
+                if let Some(ref mut r) = self.recorder {
+                    r.write_payload(frame_counter, &data.to_data());
                 }
 
-                if let Some(ref mut ros) = doc.render_on_scroll {
-                    *ros = false; //wait for `GenerateFrame`
+                let delta = BuiltDisplayList::from_data(data.display_list_data, list_descriptor);
+
+                // The epoch of a pipeline can be updated without a display list,
+                // in which case there is nothing to apply the delta to.
+                if !doc.pending.scene.pipelines.contains_key(&pipeline_id) ||
+                   doc.pending.scene.pipeline_epochs.get(&pipeline_id) != Some(&base_epoch) {
+                    error!("Display list delta for {:?} is based on {:?}, which isn't the current display list",
+                        pipeline_id, base_epoch);
+                    return DocumentOps::nop();
                 }
 
-                // Note: this isn't quite right as auxiliary values will be
-                // pulled out somewhere in the prim_store, but aux values are
-                // really simple and cheap to access, so it's not a big deal.
-                let display_list_consumed_time = precise_time_ns();
-
-                ipc_profile_counters.set(
-                    builder_start_time,
-                    builder_finish_time,
-                    send_start_time,
-                    display_list_received_time,
-                    display_list_consumed_time,
-                    display_list_len,
+                let built_display_list = match doc.pending.scene
+                    .get_display_list_for_pipeline(pipeline_id)
+                    .apply_delta(&delta, &ops)
+                {
+                    Ok(built_display_list) => built_display_list,
+                    Err(err) => {
+                        error!("Failed to apply display list delta for {:?}: {:?}", pipeline_id, err);
+                        return DocumentOps::nop();
+                    }
+                };
+
+                doc.set_display_list(
+                    pipeline_id,
+                    epoch,
+                    built_display_list,
+                    background,
+                    viewport_size,
+                    content_size,
+                    preserve_frame_state,
+                    ipc_profile_counters,
                 );
 
                 DocumentOps::build()
@@ -714,11 +805,15 @@ impl RenderBackend {
                     SceneBuilderResult::Transaction {
                         document_id,
                         mut built_scene,
+                        blob_requests,
+                        rasterized_blobs,
                         resource_updates,
                         frame_ops,
                         render,
                         result_tx,
                     } => {
+                        self.resource_cache.end_blob_scene_builder_transaction();
+
                         if let Some(doc) = self.documents.get_mut(&document_id) {
                             if let Some(mut built_scene) = built_scene.take() {
                                 doc.new_async_scene_ready(built_scene);
@@ -742,15 +837,27 @@ impl RenderBackend {
                             continue;
                         }
 
+                        // The blob images are only valid once the resource updates
+                        // they were rasterized from have been applied.
+                        let has_resource_updates = !resource_updates.is_empty();
+                        self.resource_cache.update_resources(
+                            resource_updates,
+                            &mut profile_counters.resources,
+                        );
+                        self.resource_cache.add_rasterized_blob_images(
+                            &blob_requests,
+                            rasterized_blobs,
+                        );
+
                         let transaction_msg = TransactionMsg {
                             scene_ops: Vec::new(),
                             frame_ops,
-                            resource_updates,
+                            resource_updates: Vec::new(),
                             generate_frame: render,
                             use_scene_builder_thread: false,
                         };
 
-                        if !transaction_msg.is_empty() {
+                        if has_resource_updates || !transaction_msg.is_empty() {
                             self.update_document(
                                 document_id,
                                 transaction_msg,
@@ -760,6 +867,36 @@ impl RenderBackend {
                             );
                         }
                     },
+                    SceneBuilderResult::RasterizedBlobs {
+                        document_id,
+                        blob_requests,
+                        rasterized_blobs,
+                    } => {
+                        self.resource_cache.add_async_blob_images(
+                            &blob_requests,
+                            rasterized_blobs,
+                        );
+
+                        if !self.documents.contains_key(&document_id) {
+                            continue;
+                        }
+
+                        // Render again to replace the placeholders.
+                        let transaction_msg = TransactionMsg {
+                            scene_ops: Vec::new(),
+                            frame_ops: Vec::new(),
+                            resource_updates: Vec::new(),
+                            generate_frame: true,
+                            use_scene_builder_thread: false,
+                        };
+                        self.update_document(
+                            document_id,
+                            transaction_msg,
+                            &mut frame_counter,
+                            &mut profile_counters,
+                            DocumentOps::render(),
+                        );
+                    }
                     SceneBuilderResult::FlushComplete(tx) => {
                         tx.send(()).ok();
                     }
@@ -995,7 +1132,7 @@ impl RenderBackend {
                 &op,
                 document_id,
                 scene_id,
-                &self.resource_cache,
+                &mut self.resource_cache,
                 &self.scene_tx,
             );
 
@@ -1093,6 +1230,16 @@ impl RenderBackend {
             );
             self.result_tx.send(msg).unwrap();
             profile_counters.reset();
+
+            // Rasterize the blob images that were drawn with a placeholder.
+            let (blob_rasterizer, blob_requests) = self.resource_cache.create_async_blob_requests();
+            if let Some(blob_rasterizer) = blob_rasterizer {
+                self.scene_tx.send(SceneBuilderRequest::RasterizeBlobs {
+                    document_id,
+                    blob_requests,
+                    blob_rasterizer,
+                }).unwrap();
+            }
         } else if op.render {
             // WR-internal optimization to avoid doing a bunch of render work if
             // there's no pixels. We still want to pretend to render and request
@@ -1210,10 +1357,14 @@ impl ToDebugString for SpecificDisplayItem {
             SpecificDisplayItem::ClearRectangle => String::from("clear_rectangle"),
             SpecificDisplayItem::Clip(..) => String::from("clip"),
             SpecificDisplayItem::ClipChain(..) => String::from("clip_chain"),
+            SpecificDisplayItem::ClipPath(..) => String::from("clip_path"),
+            SpecificDisplayItem::ConicGradient(..) => String::from("conic_gradient"),
+            SpecificDisplayItem::EndKeyedRange => String::from("end_keyed_range"),
             SpecificDisplayItem::Gradient(..) => String::from("gradient"),
             SpecificDisplayItem::Iframe(..) => String::from("iframe"),
             SpecificDisplayItem::Image(..) => String::from("image"),
             SpecificDisplayItem::Line(..) => String::from("line"),
+            SpecificDisplayItem::Path(..) => String::from("path"),
             SpecificDisplayItem::PopAllShadows => String::from("pop_all_shadows"),
             SpecificDisplayItem::PopReferenceFrame => String::from("pop_reference_frame"),
             SpecificDisplayItem::PopStackingContext => String::from("pop_stacking_context"),
@@ -1224,8 +1375,10 @@ impl ToDebugString for SpecificDisplayItem {
             SpecificDisplayItem::Rectangle(..) => String::from("rectangle"),
             SpecificDisplayItem::ScrollFrame(..) => String::from("scroll_frame"),
             SpecificDisplayItem::SetGradientStops => String::from("set_gradient_stops"),
+            SpecificDisplayItem::StartKeyedRange(..) => String::from("start_keyed_range"),
             SpecificDisplayItem::StickyFrame(..) => String::from("sticky_frame"),
             SpecificDisplayItem::Text(..) => String::from("text"),
+            SpecificDisplayItem::TextDecoration(..) => String::from("text_decoration"),
             SpecificDisplayItem::YuvImage(..) => String::from("yuv_image"),
         }
     }
@@ -3,6 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use api::{DeviceIntPoint, DeviceIntRect, DeviceIntSize, DeviceSize, DeviceIntSideOffsets, ImageDescriptor, ImageFormat};
+use api::{DevicePixelScale, DevicePoint};
 #[cfg(feature = "pathfinder")]
 use api::FontRenderMode;
 use border::BorderCacheKey;
@@ -15,8 +16,9 @@ use euclid::{TypedPoint2D, TypedVector2D};
 use freelist::{FreeList, FreeListHandle, WeakFreeListHandle};
 use glyph_rasterizer::GpuGlyphCacheKey;
 use gpu_cache::{GpuCache, GpuCacheAddress, GpuCacheHandle};
-use gpu_types::{BorderInstance, ImageSource, RasterizationSpace, UvRectKind};
+use gpu_types::{BorderInstance, ImageSource, PathInstance, RasterizationSpace, UvRectKind};
 use internal_types::{FastHashMap, SavedTargetIndex, SourceTexture};
+use path::PathCacheKey;
 #[cfg(feature = "pathfinder")]
 use pathfinder_partitioner::mesh::Mesh;
 use picture::PictureCacheKey;
@@ -26,6 +28,7 @@ use print_tree::{PrintTreePrinter};
 use render_backend::FrameId;
 use resource_cache::{CacheItem, ResourceCache};
 use std::{cmp, ops, usize, f32, i32};
+use svg_filter::SvgFilterInfo;
 use texture_cache::{TextureCache, TextureCacheHandle};
 use tiling::{RenderPass, RenderTargetIndex};
 use tiling::{RenderTargetKind};
@@ -47,6 +50,14 @@ pub struct RenderTaskId(pub u32, FrameId); // TODO(gw): Make private when using
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 pub struct RenderTaskAddress(pub u32);
 
+/// Storage used by `RenderTaskTree::assign_to_passes`, kept by the frame
+/// builder so that it isn't reallocated every frame.
+#[derive(Debug, Default)]
+pub struct PassAssignmentScratch {
+    pass_indices: Vec<Option<usize>>,
+    assigned: Vec<bool>,
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "capture", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
@@ -55,19 +66,51 @@ pub struct RenderTaskTree {
     pub task_data: Vec<RenderTaskData>,
     next_saved: SavedTargetIndex,
     frame_id: FrameId,
+    max_texture_size: u32,
 }
 
 impl RenderTaskTree {
-    pub fn new(frame_id: FrameId) -> Self {
+    pub fn new(frame_id: FrameId, max_texture_size: u32) -> Self {
         RenderTaskTree {
             tasks: Vec::new(),
             task_data: Vec::new(),
             next_saved: SavedTargetIndex(0),
             frame_id,
+            max_texture_size,
         }
     }
 
-    pub fn add(&mut self, task: RenderTask) -> RenderTaskId {
+    pub fn add(&mut self, mut task: RenderTask) -> RenderTaskId {
+        // Dynamic tasks end up in a render target sized to fit the largest
+        // of them, which can't be bigger than what the device supports.
+        // Oversized pictures are drawn at a smaller scale instead, which is
+        // recorded in the task data so that the primitives drawn into them
+        // and the shaders sampling them agree on where the content is. Most
+        // other tasks are sized from their (already scaled) sources, but
+        // clip masks, readbacks and blits follow screen or cache rects and
+        // have no way to be drawn scaled, so they are clamped and whatever
+        // falls outside of them is lost.
+        if let RenderTaskLocation::Dynamic(_, Some(ref mut size)) = task.location {
+            let max_size = self.max_texture_size as i32;
+            if size.width > max_size || size.height > max_size {
+                match task.kind {
+                    RenderTaskKind::Picture(ref mut info) => {
+                        let scale = max_size as f32 / cmp::max(size.width, size.height) as f32;
+                        info.content_scale *= scale;
+                        *size = DeviceIntSize::new(
+                            cmp::min((size.width as f32 * scale).ceil() as i32, max_size),
+                            cmp::min((size.height as f32 * scale).ceil() as i32, max_size),
+                        );
+                    }
+                    _ => {
+                        warn!("Render task of size {:?} exceeds the max texture size {}", size, max_size);
+                        size.width = cmp::min(size.width, max_size);
+                        size.height = cmp::min(size.height, max_size);
+                    }
+                }
+            }
+        }
+
         let id = self.tasks.len();
         self.tasks.push(task);
         RenderTaskId(id as _, self.frame_id)
@@ -88,14 +131,57 @@ impl RenderTaskTree {
         id: RenderTaskId,
         pass_index: usize,
         passes: &mut [RenderPass],
+        scratch: &mut PassAssignmentScratch,
+    ) {
+        // A task can be the input of more than one task (e.g. a picture
+        // that is sampled by backdrop filters). Such a task is rendered
+        // once, in the earliest pass that any of its users requires.
+        scratch.pass_indices.clear();
+        scratch.pass_indices.resize(self.tasks.len(), None);
+        self.resolve_pass_indices(id, pass_index, &mut scratch.pass_indices);
+
+        scratch.assigned.clear();
+        scratch.assigned.resize(self.tasks.len(), false);
+        self.assign_to_resolved_passes(id, &scratch.pass_indices, &mut scratch.assigned, passes);
+    }
+
+    fn resolve_pass_indices(
+        &self,
+        id: RenderTaskId,
+        pass_index: usize,
+        pass_indices: &mut [Option<usize>],
     ) {
         debug_assert_eq!(self.frame_id, id.1);
+        match pass_indices[id.0 as usize] {
+            Some(existing) if existing <= pass_index => return,
+            _ => pass_indices[id.0 as usize] = Some(pass_index),
+        }
+
+        for child in &self.tasks[id.0 as usize].children {
+            self.resolve_pass_indices(*child, pass_index - 1, pass_indices);
+        }
+    }
+
+    fn assign_to_resolved_passes(
+        &self,
+        id: RenderTaskId,
+        pass_indices: &[Option<usize>],
+        assigned: &mut [bool],
+        passes: &mut [RenderPass],
+    ) {
+        if assigned[id.0 as usize] {
+            return;
+        }
+        assigned[id.0 as usize] = true;
+
         let task = &self.tasks[id.0 as usize];
 
         for child in &task.children {
-            self.assign_to_passes(*child, pass_index - 1, passes);
+            self.assign_to_resolved_passes(*child, pass_indices, assigned, passes);
         }
 
+        let pass_index = pass_indices[id.0 as usize].expect("bug: unresolved pass index");
+
         // Sanity check - can be relaxed if needed
         match task.location {
             RenderTaskLocation::Fixed(..) => {
@@ -196,6 +282,10 @@ pub struct ClipRegionTask {
 pub struct PictureTask {
     pub prim_index: PrimitiveIndex,
     pub content_origin: DeviceIntPoint,
+    /// The scale the content is drawn at, below 1.0 when the picture
+    /// doesn't fit in a render target. Positions relative to the
+    /// `content_origin` are multiplied by it.
+    pub content_scale: f32,
     pub uv_rect_handle: GpuCacheHandle,
     uv_rect_kind: UvRectKind,
 }
@@ -218,6 +308,20 @@ impl BlurTask {
     }
 }
 
+#[derive(Debug)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct SvgFilterTask {
+    pub info: SvgFilterInfo,
+    // Position of the local space origin of the filtered
+    // picture within this task, used by the position
+    // dependent primitives (e.g. turbulence).
+    pub origin: DevicePoint,
+    pub device_pixel_scale: f32,
+    pub uv_rect_handle: GpuCacheHandle,
+    uv_rect_kind: UvRectKind,
+}
+
 #[derive(Debug)]
 #[cfg(feature = "pathfinder")]
 #[cfg_attr(feature = "capture", derive(Serialize))]
@@ -257,6 +361,13 @@ pub struct BorderTask {
     pub instances: Vec<BorderInstance>,
 }
 
+#[derive(Debug)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct PathTask {
+    pub instance: PathInstance,
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "capture", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
@@ -287,6 +398,8 @@ pub enum RenderTaskKind {
     Scaling(RenderTargetKind),
     Blit(BlitTask),
     Border(BorderTask),
+    Path(PathTask),
+    SvgFilter(SvgFilterTask),
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -326,6 +439,7 @@ impl RenderTask {
             kind: RenderTaskKind::Picture(PictureTask {
                 prim_index,
                 content_origin,
+                content_scale: 1.0,
                 uv_rect_handle: GpuCacheHandle::new(),
                 uv_rect_kind,
             }),
@@ -453,7 +567,9 @@ impl RenderTask {
                     ClipSource::Rectangle(..) |
                     ClipSource::RoundedRectangle(..) |
                     ClipSource::Image(..) |
-                    ClipSource::LineDecoration(..) => {}
+                    ClipSource::LineDecoration(..) |
+                    ClipSource::Path(..) |
+                    ClipSource::MaskLayer(..) => {}
                 }
             }
         }
@@ -580,6 +696,46 @@ impl RenderTask {
         }
     }
 
+    pub fn new_path(
+        size: DeviceIntSize,
+        instance: PathInstance,
+    ) -> Self {
+        RenderTask {
+            children: Vec::new(),
+            location: RenderTaskLocation::Dynamic(None, Some(size)),
+            kind: RenderTaskKind::Path(PathTask {
+                instance,
+            }),
+            clear_mode: ClearMode::Transparent,
+            saved_index: None,
+        }
+    }
+
+    // Draws one primitive of an SVG filter graph, reading from
+    // the (saved) targets of the input tasks.
+    pub fn new_svg_filter(
+        info: SvgFilterInfo,
+        inputs: Vec<RenderTaskId>,
+        target_size: DeviceIntSize,
+        uv_rect_kind: UvRectKind,
+        origin: DevicePoint,
+        device_pixel_scale: DevicePixelScale,
+    ) -> Self {
+        RenderTask {
+            children: inputs,
+            location: RenderTaskLocation::Dynamic(None, Some(target_size)),
+            kind: RenderTaskKind::SvgFilter(SvgFilterTask {
+                info,
+                origin,
+                device_pixel_scale: device_pixel_scale.0,
+                uv_rect_handle: GpuCacheHandle::new(),
+                uv_rect_kind,
+            }),
+            clear_mode: ClearMode::Transparent,
+            saved_index: None,
+        }
+    }
+
     pub fn new_scaling(
         target_kind: RenderTargetKind,
         src_task_id: RenderTaskId,
@@ -637,9 +793,14 @@ impl RenderTask {
                 task.uv_rect_kind
             }
 
+            RenderTaskKind::SvgFilter(ref task) => {
+                task.uv_rect_kind
+            }
+
             RenderTaskKind::ClipRegion(..) |
             RenderTaskKind::Glyph(_) |
             RenderTaskKind::Border(..) |
+            RenderTaskKind::Path(..) |
             RenderTaskKind::Blit(..) => {
                 UvRectKind::Rect
             }
@@ -664,7 +825,7 @@ impl RenderTask {
                 [
                     task.content_origin.x as f32,
                     task.content_origin.y as f32,
-                    0.0,
+                    task.content_scale,
                 ]
             }
             RenderTaskKind::CacheMask(ref task) => {
@@ -689,12 +850,20 @@ impl RenderTask {
                     0.0,
                 ]
             }
+            RenderTaskKind::SvgFilter(ref task) => {
+                [
+                    task.origin.x,
+                    task.origin.y,
+                    task.device_pixel_scale,
+                ]
+            }
             RenderTaskKind::Glyph(_) => {
                 [1.0, 0.0, 0.0]
             }
             RenderTaskKind::Readback(..) |
             RenderTaskKind::Scaling(..) |
             RenderTaskKind::Border(..) |
+            RenderTaskKind::Path(..) |
             RenderTaskKind::Blit(..) => {
                 [0.0; 3]
             }
@@ -731,11 +900,15 @@ impl RenderTask {
             RenderTaskKind::HorizontalBlur(ref info) => {
                 gpu_cache.get_address(&info.uv_rect_handle)
             }
+            RenderTaskKind::SvgFilter(ref info) => {
+                gpu_cache.get_address(&info.uv_rect_handle)
+            }
             RenderTaskKind::ClipRegion(..) |
             RenderTaskKind::Readback(..) |
             RenderTaskKind::Scaling(..) |
             RenderTaskKind::Blit(..) |
             RenderTaskKind::Border(..) |
+            RenderTaskKind::Path(..) |
             RenderTaskKind::CacheMask(..) |
             RenderTaskKind::Glyph(..) => {
                 panic!("texture handle not supported for this task kind");
@@ -743,6 +916,14 @@ impl RenderTask {
         }
     }
 
+    /// Returns the scale the content of this task is drawn at.
+    pub fn content_scale(&self) -> f32 {
+        match self.kind {
+            RenderTaskKind::Picture(ref info) => info.content_scale,
+            _ => 1.0,
+        }
+    }
+
     pub fn get_dynamic_size(&self) -> DeviceIntSize {
         match self.location {
             RenderTaskLocation::Fixed(..) => DeviceIntSize::zero(),
@@ -809,6 +990,8 @@ impl RenderTask {
             }
 
             RenderTaskKind::Border(..) |
+            RenderTaskKind::Path(..) |
+            RenderTaskKind::SvgFilter(..) |
             RenderTaskKind::Picture(..) => {
                 RenderTargetKind::Color
             }
@@ -835,6 +1018,8 @@ impl RenderTask {
             RenderTaskKind::ClipRegion(..) |
             RenderTaskKind::Blit(..) |
             RenderTaskKind::Border(..) |
+            RenderTaskKind::Path(..) |
+            RenderTaskKind::SvgFilter(..) |
             RenderTaskKind::Glyph(..) => false,
 
             // TODO(gw): For now, we've disabled the shared clip mask
@@ -867,11 +1052,15 @@ impl RenderTask {
             RenderTaskKind::Picture(ref mut info) => {
                 (&mut info.uv_rect_handle, info.uv_rect_kind)
             }
+            RenderTaskKind::SvgFilter(ref mut info) => {
+                (&mut info.uv_rect_handle, info.uv_rect_kind)
+            }
             RenderTaskKind::Readback(..) |
             RenderTaskKind::Scaling(..) |
             RenderTaskKind::Blit(..) |
             RenderTaskKind::ClipRegion(..) |
             RenderTaskKind::Border(..) |
+            RenderTaskKind::Path(..) |
             RenderTaskKind::CacheMask(..) |
             RenderTaskKind::Glyph(..) => {
                 return;
@@ -925,6 +1114,9 @@ impl RenderTask {
             RenderTaskKind::Border(..) => {
                 pt.new_level("Border".to_owned());
             }
+            RenderTaskKind::Path(..) => {
+                pt.new_level("Path".to_owned());
+            }
             RenderTaskKind::Blit(ref task) => {
                 pt.new_level("Blit".to_owned());
                 pt.add_item(format!("source: {:?}", task.source));
@@ -932,6 +1124,10 @@ impl RenderTask {
             RenderTaskKind::Glyph(..) => {
                 pt.new_level("Glyph".to_owned());
             }
+            RenderTaskKind::SvgFilter(ref task) => {
+                pt.new_level("SvgFilter".to_owned());
+                pt.add_item(format!("op: {:?}", task.info.op));
+            }
         }
 
         pt.add_item(format!("clear to: {:?}", self.clear_mode));
@@ -970,6 +1166,7 @@ pub enum RenderTaskCacheKeyKind {
     Glyph(GpuGlyphCacheKey),
     Picture(PictureCacheKey),
     Border(BorderCacheKey),
+    Path(PathCacheKey),
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
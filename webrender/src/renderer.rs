@@ -11,7 +11,7 @@
 
 use api::{BlobImageRenderer, ColorF, DeviceIntPoint, DeviceIntRect, DeviceIntSize};
 use api::{DeviceUintPoint, DeviceUintRect, DeviceUintSize, DocumentId, Epoch, ExternalImageId};
-use api::{ExternalImageType, FontRenderMode, FrameMsg, ImageFormat, PipelineId};
+use api::{ExternalImageType, FontRenderMode, FrameMsg, ImageFormat, MaskComposite, PipelineId};
 use api::{RenderApiSender, RenderNotifier, TexelRect, TextureTarget};
 use api::{channel};
 use api::DebugCommand;
@@ -24,7 +24,7 @@ use device::{DepthFunction, Device, FrameId, UploadMethod, Texture, PrimitiveTyp
 use device::{ExternalTexture, FBOId, TextureSlot};
 use device::{FileWatcherHandler, ShaderError, TextureFilter, ReadPixelsFormat};
 use device::{VertexUsageHint, VAO, PBO, ProgramCache};
-use device::{VertexArrayKind};
+use device::{VertexArrayKind, SurfaceId, MAIN_SURFACE};
 use euclid::{rect, Transform3D};
 use frame_builder::FrameBuilderConfig;
 //use gleam::gl;
@@ -105,6 +105,10 @@ const GPU_TAG_BRUSH_RADIAL_GRADIENT: GpuProfileTag = GpuProfileTag {
     label: "B_RadialGradient",
     color: debug_colors::LIGHTPINK,
 };
+const GPU_TAG_BRUSH_CONIC_GRADIENT: GpuProfileTag = GpuProfileTag {
+    label: "B_ConicGradient",
+    color: debug_colors::PLUM,
+};
 const GPU_TAG_BRUSH_YUV_IMAGE: GpuProfileTag = GpuProfileTag {
     label: "B_YuvImage",
     color: debug_colors::DARKGREEN,
@@ -133,6 +137,10 @@ const GPU_TAG_CACHE_BORDER: GpuProfileTag = GpuProfileTag {
     label: "C_Border",
     color: debug_colors::CORNSILK,
 };
+const GPU_TAG_CACHE_PATH: GpuProfileTag = GpuProfileTag {
+    label: "C_Path",
+    color: debug_colors::KHAKI,
+};
 const GPU_TAG_SETUP_TARGET: GpuProfileTag = GpuProfileTag {
     label: "target init",
     color: debug_colors::SLATEGREY,
@@ -153,6 +161,10 @@ const GPU_TAG_BLUR: GpuProfileTag = GpuProfileTag {
     label: "Blur",
     color: debug_colors::VIOLET,
 };
+const GPU_TAG_SVG_FILTER: GpuProfileTag = GpuProfileTag {
+    label: "SvgFilter",
+    color: debug_colors::LEMONCHIFFON,
+};
 const GPU_TAG_BLIT: GpuProfileTag = GpuProfileTag {
     label: "Blit",
     color: debug_colors::LIME,
@@ -199,6 +211,7 @@ impl BatchKind {
                     BrushBatchKind::MixBlend { .. } => "Brush (Composite)",
                     BrushBatchKind::YuvImage(..) => "Brush (YuvImage)",
                     BrushBatchKind::RadialGradient => "Brush (RadialGradient)",
+                    BrushBatchKind::ConicGradient => "Brush (ConicGradient)",
                     BrushBatchKind::LinearGradient => "Brush (LinearGradient)",
                 }
             }
@@ -217,6 +230,7 @@ impl BatchKind {
                     BrushBatchKind::MixBlend { .. } => GPU_TAG_BRUSH_MIXBLEND,
                     BrushBatchKind::YuvImage(..) => GPU_TAG_BRUSH_YUV_IMAGE,
                     BrushBatchKind::RadialGradient => GPU_TAG_BRUSH_RADIAL_GRADIENT,
+                    BrushBatchKind::ConicGradient => GPU_TAG_BRUSH_CONIC_GRADIENT,
                     BrushBatchKind::LinearGradient => GPU_TAG_BRUSH_LINEAR_GRADIENT,
                 }
             }
@@ -263,6 +277,7 @@ pub enum ShaderColorMode {
     SubpixelDualSource = 6,
     Bitmap = 7,
     ColorBitmap = 8,
+    Sdf = 9,
 }
 
 impl From<GlyphFormat> for ShaderColorMode {
@@ -274,6 +289,7 @@ impl From<GlyphFormat> for ShaderColorMode {
             }
             GlyphFormat::Bitmap => ShaderColorMode::Bitmap,
             GlyphFormat::ColorBitmap => ShaderColorMode::ColorBitmap,
+            GlyphFormat::Sdf => ShaderColorMode::Sdf,
         }
     }
 }
@@ -385,6 +401,53 @@ pub(crate) mod desc {
         ],
     };
 
+    pub const SVG_FILTER: VertexDescriptor = VertexDescriptor {
+        vertex_attributes: &[
+            VertexAttribute {
+                name: "aPosition",
+                count: 2,
+                kind: VertexAttributeKind::F32,
+            },
+        ],
+        instance_attributes: &[
+            VertexAttribute {
+                name: "aFilterRenderTaskAddress",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+            VertexAttribute {
+                name: "aFilterInput1TaskAddress",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+            VertexAttribute {
+                name: "aFilterInput2TaskAddress",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+            VertexAttribute {
+                name: "aFilterKind",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+            VertexAttribute {
+                name: "aFilterGenericInt",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+            VertexAttribute {
+                name: "aFilterFlags",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+            VertexAttribute {
+                name: "aFilterExtraDataAddress",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+        ],
+    };
+
     pub const BORDER: VertexDescriptor = VertexDescriptor {
         vertex_attributes: &[
             VertexAttribute {
@@ -442,6 +505,43 @@ pub(crate) mod desc {
         ],
     };
 
+    pub const PATH: VertexDescriptor = VertexDescriptor {
+        vertex_attributes: &[
+            VertexAttribute {
+                name: "aPosition",
+                count: 2,
+                kind: VertexAttributeKind::F32,
+            },
+        ],
+        instance_attributes: &[
+            VertexAttribute {
+                name: "aTaskOrigin",
+                count: 2,
+                kind: VertexAttributeKind::F32,
+            },
+            VertexAttribute {
+                name: "aRect",
+                count: 4,
+                kind: VertexAttributeKind::F32,
+            },
+            VertexAttribute {
+                name: "aLocalSize",
+                count: 2,
+                kind: VertexAttributeKind::F32,
+            },
+            VertexAttribute {
+                name: "aPathDataAddress",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+            VertexAttribute {
+                name: "aPathStopsAddress",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+        ],
+    };
+
     pub const CLIP: VertexDescriptor = VertexDescriptor {
         vertex_attributes: &[
             VertexAttribute {
@@ -873,7 +973,7 @@ impl SourceTextureResolver {
                 device.bind_texture(sampler, texture);
             }
             SourceTexture::RenderTaskCache(saved_index) => {
-                let texture = &self.saved_textures[saved_index.0];
+                let texture = self.saved_texture(saved_index);
                 device.bind_texture(sampler, texture)
             }
         }
@@ -904,10 +1004,23 @@ impl SourceTextureResolver {
                 Some(&self.cache_texture_map[index.0])
             }
             SourceTexture::RenderTaskCache(saved_index) => {
-                Some(&self.saved_textures[saved_index.0])
+                Some(self.saved_texture(saved_index))
             }
         }
     }
+
+    // A target saved by the previous pass is only moved to the saved
+    // textures once the current pass ends, so until then it has to be
+    // looked up among the current cache textures.
+    fn saved_texture(&self, saved_index: SavedTargetIndex) -> &Texture {
+        let cache_textures = self.cache_rgba8_texture.iter().chain(self.cache_a8_texture.iter());
+        for at in cache_textures {
+            if at.saved_index == Some(saved_index) {
+                return &at.texture;
+            }
+        }
+        &self.saved_textures[saved_index.0]
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -1081,6 +1194,7 @@ impl CacheTexture {
                             TextureFilter::Nearest,
                             Some(RenderTargetInfo {
                                 has_depth: false,
+                                samples: 1,
                             }),
                             1,
                             None,
@@ -1336,6 +1450,8 @@ pub struct RendererVAOs {
     clip_vao: VAO,
     dash_and_dot_vao: VAO,
     border_vao: VAO,
+    path_vao: VAO,
+    svg_filter_vao: VAO,
 }
 
 /// The renderer is responsible for submitting to the GPU the work prepared by the
@@ -1348,6 +1464,8 @@ pub struct Renderer<B: hal::Backend> {
     pending_gpu_cache_updates: Vec<GpuCacheUpdateList>,
     pending_shader_updates: Vec<PathBuf>,
     active_documents: Vec<(DocumentId, RenderedDocument)>,
+    /// Documents rendered into another window than the main one.
+    document_surfaces: FastHashMap<DocumentId, SurfaceId>,
 
     shaders: Shaders,
 
@@ -1462,13 +1580,58 @@ impl<B: hal::Backend> Renderer<B> {
     /// let (renderer, sender) = Renderer::new(opts);
     /// ```
     /// [rendereroptions]: struct.RendererOptions.html
+    ///
+    /// Without a `surface` the frames are rendered into an offscreen image of
+    /// `window_size`, which can be read back with `read_pixels_rgba8`.
     pub fn new(
         notifier: Box<RenderNotifier>,
         adapter: &hal::Adapter<B>,
-        surface: &mut B::Surface,
+        surface: Option<&mut B::Surface>,
         window_size: (u32, u32),
-        mut options: RendererOptions,
+        options: RendererOptions,
     ) -> Result<(Self, RenderApiSender), RendererError> {
+        Self::new_with_device(notifier, options, |file_watch_handler, cached_programs, options| {
+            Device::new(
+                options.resource_override_path.clone(),
+                options.upload_method.clone(),
+                file_watch_handler,
+                cached_programs,
+                adapter,
+                surface,
+                window_size,
+                options.msaa_samples,
+            )
+        })
+    }
+
+    /// Creates a renderer that draws with the software device, without a
+    /// window or a graphics adapter. The frames are rendered into an offscreen
+    /// image of `window_size`, which can be read back with `read_pixels_rgba8`.
+    #[cfg(feature = "software")]
+    pub fn new_software(
+        notifier: Box<RenderNotifier>,
+        window_size: (u32, u32),
+        options: RendererOptions,
+    ) -> Result<(Self, RenderApiSender), RendererError> {
+        Self::new_with_device(notifier, options, |file_watch_handler, cached_programs, options| {
+            Device::new_offscreen(
+                options.resource_override_path.clone(),
+                options.upload_method.clone(),
+                file_watch_handler,
+                cached_programs,
+                window_size,
+            )
+        })
+    }
+
+    fn new_with_device<F>(
+        notifier: Box<RenderNotifier>,
+        mut options: RendererOptions,
+        create_device: F,
+    ) -> Result<(Self, RenderApiSender), RendererError>
+    where
+        F: FnOnce(Box<FileWatcherHandler>, Option<Rc<ProgramCache>>, &RendererOptions) -> Device<B>,
+    {
         let (api_tx, api_rx) = channel::msg_channel()?;
         let (payload_tx, payload_rx) = channel::payload_channel()?;
         let (result_tx, result_rx) = channel();
@@ -1481,20 +1644,14 @@ impl<B: hal::Backend> Renderer<B> {
             notifier: notifier.clone(),
         };
 
-        let mut device = Device::new(
-            options.resource_override_path.clone(),
-            options.upload_method.clone(),
-            Box::new(file_watch_handler),
-            options.cached_programs.take(),
-            adapter,
-            surface,
-            window_size,
-        );
+        let cached_programs = options.cached_programs.take();
+        let mut device = create_device(Box::new(file_watch_handler), cached_programs, &options);
 
         let ext_dual_source_blending = !options.disable_dual_source_blending &&
-            device.supports_features(hal::Features::DUAL_SRC_BLENDING);
+            device.get_capabilities().supports_dual_source_blending;
 
         let device_max_size = device.max_texture_size();
+        let max_texture_layers = device.get_capabilities().max_texture_layers as usize;
         // 512 is the minimum that the texture cache can work with.
         // Broken GL contexts can return a max texture size of zero (See #1260). Better to
         // gracefully fail now than panic as soon as a texture is allocated.
@@ -1634,6 +1791,10 @@ impl<B: hal::Backend> Renderer<B> {
             device.create_vao_with_new_instances(&desc::BORDER, &prim_vao);
         let dash_and_dot_vao =
             device.create_vao_with_new_instances(&desc::BORDER_CORNER_DASH_AND_DOT, &prim_vao);
+        let path_vao =
+            device.create_vao_with_new_instances(&desc::PATH, &prim_vao);
+        let svg_filter_vao =
+            device.create_vao_with_new_instances(&desc::SVG_FILTER, &prim_vao);
         let texture_cache_upload_pbo = device.create_pbo();
 
         let texture_resolver = SourceTextureResolver::new(&mut device);
@@ -1697,6 +1858,7 @@ impl<B: hal::Backend> Renderer<B> {
         let enable_render_on_scroll = options.enable_render_on_scroll;
 
         let blob_image_renderer = options.blob_image_renderer.take();
+        let enable_blob_placeholders = options.enable_blob_placeholders;
         let thread_listener_for_render_backend = thread_listener.clone();
         let thread_listener_for_scene_builder = thread_listener.clone();
         let scene_builder_hooks = options.scene_builder_hooks;
@@ -1728,11 +1890,12 @@ impl<B: hal::Backend> Renderer<B> {
                 thread_listener.thread_started(&rb_thread_name);
             }
 
-            let texture_cache = TextureCache::new(max_device_size);
+            let texture_cache = TextureCache::new(max_device_size, max_texture_layers);
             let resource_cache = ResourceCache::new(
                 texture_cache,
                 glyph_rasterizer,
                 blob_image_renderer,
+                enable_blob_placeholders,
             );
 
             let mut backend = RenderBackend::new(
@@ -1764,6 +1927,7 @@ impl<B: hal::Backend> Renderer<B> {
             debug_server,
             device,
             active_documents: Vec::new(),
+            document_surfaces: FastHashMap::default(),
             pending_texture_updates: Vec::new(),
             pending_gpu_cache_updates: Vec::new(),
             pending_shader_updates: Vec::new(),
@@ -1792,6 +1956,8 @@ impl<B: hal::Backend> Renderer<B> {
                 clip_vao,
                 dash_and_dot_vao,
                 border_vao,
+                path_vao,
+                svg_filter_vao,
             },
             node_data_texture,
             local_clip_rects_texture,
@@ -1829,7 +1995,65 @@ impl<B: hal::Backend> Renderer<B> {
         GraphicsApiInfo {
             kind: GraphicsApi::OpenGL,
             version: String::from("0.0.1"), //self.device.gl().get_string(gl::VERSION),
-            renderer: String::from("gfx-hal"), //self.device.gl().get_string(gl::RENDERER),
+            renderer: self.device.renderer_name().to_owned(),
+        }
+    }
+
+    /// Renders the document into the given window from now on, instead of
+    /// the window the renderer was created with. The surface shares every
+    /// cache with the other windows, so it needs to come from the same adapter.
+    pub fn add_document_surface(
+        &mut self,
+        document_id: DocumentId,
+        adapter: &hal::Adapter<B>,
+        surface: &mut B::Surface,
+        window_size: (u32, u32),
+    ) {
+        let surface_id = self.device.add_surface(adapter, surface, window_size);
+        if let Some(old_surface_id) = self.document_surfaces.insert(document_id, surface_id) {
+            self.device.remove_surface(old_surface_id);
+        }
+    }
+
+    /// Recreates the swapchain of a window added with `add_document_surface`
+    /// after it was resized.
+    pub fn resize_document_surface(
+        &mut self,
+        document_id: DocumentId,
+        adapter: &hal::Adapter<B>,
+        surface: &mut B::Surface,
+        window_size: (u32, u32),
+    ) {
+        let surface_id = self.document_surfaces[&document_id];
+        self.device.resize_surface(surface_id, adapter, surface, window_size);
+    }
+
+    /// Resizes the offscreen image the frames are rendered into, for a
+    /// renderer created without a surface.
+    pub fn resize_offscreen_surface(&mut self, window_size: (u32, u32)) {
+        self.device.resize_offscreen_surface(window_size);
+    }
+
+    /// Moves the document back to the main window.
+    pub fn remove_document_surface(&mut self, document_id: DocumentId) {
+        if let Some(surface_id) = self.document_surfaces.remove(&document_id) {
+            self.device.remove_surface(surface_id);
+        }
+    }
+
+    fn document_surface(&self, document_id: DocumentId) -> SurfaceId {
+        self.document_surfaces
+            .get(&document_id)
+            .cloned()
+            .unwrap_or(MAIN_SURFACE)
+    }
+
+    /// The size of the main window is the one passed to `render`.
+    fn surface_size(&self, surface_id: SurfaceId, framebuffer_size: DeviceUintSize) -> DeviceUintSize {
+        if surface_id == MAIN_SURFACE {
+            framebuffer_size
+        } else {
+            self.device.surface_size(surface_id)
         }
     }
 
@@ -1898,7 +2122,6 @@ impl<B: hal::Backend> Renderer<B> {
                     //               frees Texture X.
                     //            3) bad stuff happens.
 
-                    //TODO: associate `document_id` with target window
                     self.pending_texture_updates.push(texture_update_list);
                     self.backend_profile_counters = profile_counters;
                 }
@@ -1996,6 +2219,11 @@ impl<B: hal::Backend> Renderer<B> {
             "LineDecorations",
             target.clip_batcher.line_decorations.len(),
         );
+        debug_target.add(
+            debug_server::BatchKind::Clip,
+            "Paths",
+            target.clip_batcher.paths.len(),
+        );
         debug_target.add(
             debug_server::BatchKind::Cache,
             "Vertical Blur",
@@ -2014,6 +2242,9 @@ impl<B: hal::Backend> Renderer<B> {
         for (_, items) in target.clip_batcher.images.iter() {
             debug_target.add(debug_server::BatchKind::Clip, "Image mask", items.len());
         }
+        for batch in &target.clip_batcher.mask_layers {
+            debug_target.add(debug_server::BatchKind::Clip, "Mask layer", batch.instances.len());
+        }
 
         debug_target
     }
@@ -2042,6 +2273,11 @@ impl<B: hal::Backend> Renderer<B> {
             "Horizontal Blur",
             target.horizontal_blurs.len(),
         );
+        debug_target.add(
+            debug_server::BatchKind::Cache,
+            "SVG Filters",
+            target.svg_filters.iter().map(|&(_, ref instances)| instances.len()).sum(),
+        );
 
         for alpha_batch_container in &target.alpha_batch_containers {
             for batch in alpha_batch_container
@@ -2313,24 +2549,36 @@ impl<B: hal::Backend> Renderer<B> {
 
             // don't clear the framebuffer if one of the rendered documents will overwrite it
             if let Some(framebuffer_size) = framebuffer_size {
-                let needs_color_clear = !active_documents
-                    .iter()
-                    .any(|&(_, RenderedDocument { ref frame, .. })| {
-                        frame.background_color.is_some() &&
-                        frame.inner_rect.origin == DeviceUintPoint::zero() &&
-                        frame.inner_rect.size == framebuffer_size
-                    });
-
-                if needs_color_clear || clear_depth_value.is_some() {
-                    let clear_color = if needs_color_clear {
-                        self.clear_color.map(|color| color.to_array())
-                    } else {
-                        None
-                    };
-                    self.device.bind_draw_target(None, None);
-                    self.device.enable_depth_write();
-                    self.device.clear_target(clear_color, clear_depth_value, None);
-                    self.device.disable_depth_write();
+                let mut surfaces = vec![MAIN_SURFACE];
+                for &surface_id in self.document_surfaces.values() {
+                    if !surfaces.contains(&surface_id) {
+                        surfaces.push(surface_id);
+                    }
+                }
+
+                for surface_id in surfaces {
+                    let surface_size = self.surface_size(surface_id, framebuffer_size);
+                    let needs_color_clear = !active_documents
+                        .iter()
+                        .filter(|&&(document_id, _)| self.document_surface(document_id) == surface_id)
+                        .any(|&(_, RenderedDocument { ref frame, .. })| {
+                            frame.background_color.is_some() &&
+                            frame.inner_rect.origin == DeviceUintPoint::zero() &&
+                            frame.inner_rect.size == surface_size
+                        });
+
+                    if needs_color_clear || clear_depth_value.is_some() {
+                        let clear_color = if needs_color_clear {
+                            self.clear_color.map(|color| color.to_array())
+                        } else {
+                            None
+                        };
+                        self.device.bind_surface(surface_id);
+                        self.device.bind_draw_target(None, None);
+                        self.device.enable_depth_write();
+                        self.device.clear_target(clear_color, clear_depth_value, None);
+                        self.device.disable_depth_write();
+                    }
                 }
             }
 
@@ -2339,7 +2587,12 @@ impl<B: hal::Backend> Renderer<B> {
                 self.owned_external_images.iter().map(|(key, value)| (*key, value.clone()))
             );
 
-            for &mut (_, RenderedDocument { ref mut frame, .. }) in &mut active_documents {
+            for &mut (document_id, RenderedDocument { ref mut frame, .. }) in &mut active_documents {
+                let surface_id = self.document_surface(document_id);
+                self.device.bind_surface(surface_id);
+                let framebuffer_size = framebuffer_size
+                    .map(|size| self.surface_size(surface_id, size));
+
                 frame.profile_counters.reset_targets();
                 self.prepare_gpu_cache(frame);
                 assert!(frame.gpu_cache_frame_id <= self.gpu_cache_frame_id,
@@ -2359,6 +2612,9 @@ impl<B: hal::Backend> Renderer<B> {
                 }
             }
 
+            // The profiler and the debug overlays are drawn into the main window.
+            self.device.bind_surface(MAIN_SURFACE);
+
             self.unlock_external_images();
             self.active_documents = active_documents;
         });
@@ -2701,8 +2957,8 @@ impl<B: hal::Backend> Renderer<B> {
         // composite operation in this batch.
         let (readback_rect, readback_layer) = readback.get_target_rect();
         let (backdrop_rect, _) = backdrop.get_target_rect();
-        let backdrop_screen_origin = match backdrop.kind {
-            RenderTaskKind::Picture(ref task_info) => task_info.content_origin,
+        let (backdrop_screen_origin, backdrop_scale) = match backdrop.kind {
+            RenderTaskKind::Picture(ref task_info) => (task_info.content_origin, task_info.content_scale),
             _ => panic!("bug: composite on non-picture?"),
         };
         let source_screen_origin = match source.kind {
@@ -2717,9 +2973,12 @@ impl<B: hal::Backend> Renderer<B> {
         let cache_draw_target = (cache_texture, readback_layer.0 as i32);
         self.device.bind_draw_target(Some(cache_draw_target), None);
 
+        // The backdrop may be drawn at a smaller scale, in which case the
+        // blit scales the region back up to the size of the readback.
         let src = DeviceIntRect::new(
-            source_screen_origin + (backdrop_rect.origin - backdrop_screen_origin),
-            readback_rect.size,
+            backdrop_rect.origin +
+                ((source_screen_origin - backdrop_screen_origin).to_f32() * backdrop_scale).round().to_i32(),
+            (readback_rect.size.to_f32() * backdrop_scale).round().to_i32(),
         );
         let dest = readback_rect.to_i32();
 
@@ -2911,6 +3170,25 @@ impl<B: hal::Backend> Renderer<B> {
             }
         }
 
+        // Draw any SVG filter primitives for this target. These
+        // sample their inputs from saved render targets.
+        if !target.svg_filters.is_empty() {
+            let _timer = self.gpu_profile.start_timer(GPU_TAG_SVG_FILTER);
+
+            self.device.set_blend(false);
+            self.shaders.cs_svg_filter
+                .bind(&mut self.device, projection, &mut self.renderer_errors);
+
+            for &(ref textures, ref instances) in &target.svg_filters {
+                self.draw_instanced_batch(
+                    instances,
+                    VertexArrayKind::SvgFilter,
+                    textures,
+                    stats,
+                );
+            }
+        }
+
         self.handle_scaling(render_tasks, &target.scalings, SourceTexture::CacheRGBA8);
 
         //TODO: record the pixel count for cached primitives
@@ -2919,8 +3197,9 @@ impl<B: hal::Backend> Renderer<B> {
             let _gl = self.gpu_profile.start_marker("opaque batches");
             let opaque_sampler = self.gpu_profile.start_sampler(GPU_SAMPLER_TAG_OPAQUE);
             self.device.set_blend(false);
-            //Note: depth equality is needed for split planes
-            self.device.set_depth_func(DepthFunction::LessEqual);
+            // Every opaque primitive has its own depth, so a strict test
+            // rejects the ones drawn behind.
+            self.device.set_depth_func(DepthFunction::Less);
             self.device.enable_depth();
             self.device.enable_depth_write();
 
@@ -2969,6 +3248,8 @@ impl<B: hal::Backend> Renderer<B> {
             }
 
             self.device.disable_depth_write();
+            //Note: depth equality is needed for split planes
+            self.device.set_depth_func(DepthFunction::LessEqual);
             self.gpu_profile.finish_sampler(opaque_sampler);
         }
 
@@ -3212,8 +3493,35 @@ impl<B: hal::Backend> Renderer<B> {
         {
             let _timer = self.gpu_profile.start_timer(GPU_TAG_CACHE_CLIP);
 
-            // switch to multiplicative blending
             self.device.set_blend(true);
+
+            // draw mask layers, each composited with the ones before it
+            for batch in &target.clip_batcher.mask_layers {
+                let _gm2 = self.gpu_profile.start_marker("mask layers");
+                match batch.composite {
+                    MaskComposite::Add => self.device.set_blend_mode_mask_add(),
+                    MaskComposite::Subtract => self.device.set_blend_mode_mask_subtract(),
+                    MaskComposite::Intersect => self.device.set_blend_mode_multiply(),
+                    MaskComposite::Exclude => self.device.set_blend_mode_mask_exclude(),
+                }
+                let textures = BatchTextures {
+                    colors: [
+                        batch.texture,
+                        SourceTexture::Invalid,
+                        SourceTexture::Invalid,
+                    ],
+                };
+                self.shaders.cs_clip_image
+                    .bind(&mut self.device, projection, &mut self.renderer_errors);
+                self.draw_instanced_batch(
+                    &batch.instances,
+                    VertexArrayKind::Clip,
+                    &textures,
+                    stats,
+                );
+            }
+
+            // switch to multiplicative blending
             self.device.set_blend_mode_multiply();
 
             // draw rounded cornered rectangles
@@ -3267,6 +3575,22 @@ impl<B: hal::Backend> Renderer<B> {
                 );
             }
 
+            // draw path clips
+            if !target.clip_batcher.paths.is_empty() {
+                let _gm2 = self.gpu_profile.start_marker("clip paths");
+                self.shaders.cs_clip_path.bind(
+                    &mut self.device,
+                    projection,
+                    &mut self.renderer_errors,
+                );
+                self.draw_instanced_batch(
+                    &target.clip_batcher.paths,
+                    VertexArrayKind::Clip,
+                    &BatchTextures::no_texture(),
+                    stats,
+                );
+            }
+
             // draw image masks
             for (mask_texture_id, items) in target.clip_batcher.images.iter() {
                 let _gm2 = self.gpu_profile.start_marker("clip images");
@@ -3365,6 +3689,29 @@ impl<B: hal::Backend> Renderer<B> {
             self.device.set_blend(false);
         }
 
+        // Draw any paths for this target.
+        if !target.paths.is_empty() {
+            let _timer = self.gpu_profile.start_timer(GPU_TAG_CACHE_PATH);
+
+            self.device.set_blend(true);
+            self.device.set_blend_mode_premultiplied_alpha();
+
+            self.shaders.cs_path.bind(
+                &mut self.device,
+                &projection,
+                &mut self.renderer_errors,
+            );
+
+            self.draw_instanced_batch(
+                &target.paths,
+                VertexArrayKind::Path,
+                &BatchTextures::no_texture(),
+                stats,
+            );
+
+            self.device.set_blend(false);
+        }
+
         // Draw any blurs for this target.
         if !target.horizontal_blurs.is_empty() {
             let _timer = self.gpu_profile.start_timer(GPU_TAG_BLUR);
@@ -3547,6 +3894,7 @@ impl<B: hal::Backend> Renderer<B> {
             TextureFilter::Linear,
             Some(RenderTargetInfo {
                 has_depth: list.needs_depth(),
+                samples: if list.supports_multisampling() { self.device.msaa_samples() } else { 1 },
             }),
             list.targets.len() as _,
             None,
@@ -3976,6 +4324,8 @@ impl<B: hal::Backend> Renderer<B> {
         self.device.delete_vao(self.vaos.blur_vao);
         self.device.delete_vao(self.vaos.dash_and_dot_vao);
         self.device.delete_vao(self.vaos.border_vao);
+        self.device.delete_vao(self.vaos.path_vao);
+        self.device.delete_vao(self.vaos.svg_filter_vao);
 
         #[cfg(feature = "debug_renderer")]
         {
@@ -4100,6 +4450,9 @@ pub struct RendererOptions {
     pub precache_shaders: bool,
     pub renderer_kind: RendererKind,
     pub enable_subpixel_aa: bool,
+    /// The number of samples per pixel of the framebuffer and of the picture
+    /// render targets, 1 disables multisampling.
+    pub msaa_samples: u8,
     pub clear_color: Option<ColorF>,
     pub enable_clear_scissor: bool,
     pub max_texture_size: Option<u32>,
@@ -4107,6 +4460,11 @@ pub struct RendererOptions {
     pub upload_method: UploadMethod,
     pub workers: Option<Arc<ThreadPool>>,
     pub blob_image_renderer: Option<Box<BlobImageRenderer>>,
+    /// Draw newly visible blob images with a transparent placeholder while they
+    /// are rasterized on the scene builder thread, instead of rasterizing them
+    /// while building the frame. Ignored if the blob image renderer doesn't
+    /// support asynchronous rasterization.
+    pub enable_blob_placeholders: bool,
     pub recorder: Option<Box<ApiRecordingReceiver>>,
     pub thread_listener: Option<Box<ThreadListener + Send + Sync>>,
     pub enable_render_on_scroll: bool,
@@ -4131,6 +4489,7 @@ impl Default for RendererOptions {
             precache_shaders: false,
             renderer_kind: RendererKind::Native,
             enable_subpixel_aa: false,
+            msaa_samples: 1,
             clear_color: Some(ColorF::new(1.0, 1.0, 1.0, 1.0)),
             enable_clear_scissor: true,
             max_texture_size: None,
@@ -4141,6 +4500,7 @@ impl Default for RendererOptions {
             upload_method: UploadMethod::PixelBuffer,
             workers: None,
             blob_image_renderer: None,
+            enable_blob_placeholders: true,
             recorder: None,
             thread_listener: None,
             enable_render_on_scroll: true,
@@ -4576,6 +4936,8 @@ fn get_vao<'a>(vertex_array_kind: VertexArrayKind,
         VertexArrayKind::VectorStencil => &gpu_glyph_renderer.vector_stencil_vao,
         VertexArrayKind::VectorCover => &gpu_glyph_renderer.vector_cover_vao,
         VertexArrayKind::Border => &vaos.border_vao,
+        VertexArrayKind::Path => &vaos.path_vao,
+        VertexArrayKind::SvgFilter => &vaos.svg_filter_vao,
     }
 }
 
@@ -4590,5 +4952,7 @@ fn get_vao<'a>(vertex_array_kind: VertexArrayKind,
         VertexArrayKind::Blur => &vaos.blur_vao,
         VertexArrayKind::VectorStencil | VertexArrayKind::VectorCover => unreachable!(),
         VertexArrayKind::Border => &vaos.border_vao,
+        VertexArrayKind::Path => &vaos.path_vao,
+        VertexArrayKind::SvgFilter => &vaos.svg_filter_vao,
     }
 }
@@ -2,12 +2,13 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use api::{AddFont, BlobImageResources, ResourceUpdate};
-use api::{BlobImageDescriptor, BlobImageError, BlobImageRenderer, BlobImageRequest};
+use api::{AddFont, AsyncBlobImageRasterizer, BlobImageResources, ResourceUpdate};
+use api::{BlobImageDescriptor, BlobImageError, BlobImageParams, BlobImageRenderer};
+use api::{BlobImageRequest, BlobImageResult, RasterizedBlobImage};
 use api::{ClearCache, ColorF, DevicePoint, DeviceUintPoint, DeviceUintRect, DeviceUintSize};
 use api::{Epoch, FontInstanceKey, FontKey, FontTemplate, GlyphIndex};
 use api::{ExternalImageData, ExternalImageType};
-use api::{FontInstanceOptions, FontInstancePlatformOptions, FontVariation};
+use api::{FontInstanceOptions, FontInstancePlatformOptions, FontVariation, FontVariationBinding};
 use api::{GlyphDimensions, IdNamespace};
 use api::{ImageData, ImageDescriptor, ImageKey, ImageRendering};
 use api::{TileOffset, TileSize};
@@ -23,7 +24,7 @@ use euclid::size2;
 use glyph_cache::GlyphCache;
 #[cfg(not(feature = "pathfinder"))]
 use glyph_cache::GlyphCacheEntry;
-use glyph_rasterizer::{FontInstance, GlyphFormat, GlyphKey, GlyphRasterizer};
+use glyph_rasterizer::{FontInstance, FontMetrics, GlyphFormat, GlyphKey, GlyphRasterizer};
 use gpu_cache::{GpuCache, GpuCacheAddress, GpuCacheHandle};
 use gpu_types::UvRectKind;
 use internal_types::{FastHashMap, FastHashSet, SourceTexture, TextureUpdateList};
@@ -32,9 +33,11 @@ use render_backend::FrameId;
 use render_task::{RenderTaskCache, RenderTaskCacheKey, RenderTaskId};
 use render_task::{RenderTaskCacheEntry, RenderTaskCacheEntryHandle, RenderTaskTree};
 use std::collections::hash_map::Entry::{self, Occupied, Vacant};
+use std::collections::hash_map::Iter;
 use std::cmp;
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::mem;
 #[cfg(any(feature = "capture", feature = "replay"))]
 use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
@@ -101,7 +104,19 @@ struct ImageResource {
     descriptor: ImageDescriptor,
     epoch: Epoch,
     tiling: Option<TileSize>,
+}
+
+// What a blob image will look like once the transactions that were sent to the
+// scene builder thread for it are applied.
+struct PendingBlobUpdate {
+    descriptor: ImageDescriptor,
+    tiling: Option<TileSize>,
+    // The area these transactions invalidate, `None` means the whole image.
     dirty_rect: Option<DeviceUintRect>,
+    // Whether the cached tiles of the image will be discarded.
+    discards_cache: bool,
+    // The last of these transactions.
+    transaction: u64,
 }
 
 #[derive(Clone, Debug)]
@@ -137,7 +152,9 @@ impl ImageTemplates {
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 struct CachedImageInfo {
     texture_cache_handle: TextureCacheHandle,
-    epoch: Epoch,
+    /// The area of this image (or tile, relative to the tile) that changed since
+    /// it was last uploaded, if any.
+    dirty_rect: Option<DeviceUintRect>,
 }
 
 #[cfg_attr(feature = "capture", derive(Serialize))]
@@ -168,6 +185,62 @@ pub fn intersect_for_tile(
     })
 }
 
+// Compute the part of an image, or of one of its tiles (relative to the tile),
+// that is covered by a dirty rect. No dirty rect means the whole image is dirty.
+fn compute_request_dirty_rect(
+    descriptor: &ImageDescriptor,
+    tiling: Option<TileSize>,
+    tile: Option<TileOffset>,
+    dirty_rect: Option<DeviceUintRect>,
+) -> Option<DeviceUintRect> {
+    match (tiling, tile) {
+        (Some(tile_size), Some(tile)) => {
+            let tile_rect = DeviceUintRect::new(
+                DeviceUintPoint::zero(),
+                compute_tile_size(descriptor, tile_size, tile),
+            );
+            match dirty_rect {
+                Some(dirty) => intersect_for_tile(dirty, tile_rect.size, tile_size, tile),
+                None => Some(tile_rect),
+            }
+        }
+        _ => Some(dirty_rect.unwrap_or(descriptor.full_rect())),
+    }
+}
+
+fn union_dirty_rects(
+    rect: Option<DeviceUintRect>,
+    prev_rect: Option<DeviceUintRect>,
+) -> Option<DeviceUintRect> {
+    match (rect, prev_rect) {
+        (Some(rect), Some(prev_rect)) => Some(rect.union(&prev_rect)),
+        (rect, prev_rect) => rect.or(prev_rect),
+    }
+}
+
+fn compute_blob_descriptor(
+    descriptor: &ImageDescriptor,
+    tiling: Option<TileSize>,
+    tile: Option<TileOffset>,
+) -> BlobImageDescriptor {
+    let (offset, size) = match (tiling, tile) {
+        (Some(tile_size), Some(tile)) => {
+            let offset = DevicePoint::new(
+                tile.x as f32 * tile_size as f32,
+                tile.y as f32 * tile_size as f32,
+            );
+            (offset, compute_tile_size(descriptor, tile_size, tile))
+        }
+        _ => (DevicePoint::zero(), descriptor.size),
+    };
+
+    BlobImageDescriptor {
+        size,
+        offset,
+        format: descriptor.format,
+    }
+}
+
 
 impl<K, V, U> ResourceClassCache<K, V, U>
 where
@@ -199,6 +272,10 @@ where
         self.resources.entry(key)
     }
 
+    pub fn iter(&self) -> Iter<K, V> {
+        self.resources.iter()
+    }
+
     pub fn clear(&mut self) {
         self.resources.clear();
     }
@@ -297,6 +374,7 @@ pub struct ResourceCache {
 
     // TODO(gw): We should expire (parts of) this cache semi-regularly!
     cached_glyph_dimensions: GlyphDimensionsCache,
+    cached_font_metrics: FastHashMap<FontInstance, Option<FontMetrics>>,
     glyph_rasterizer: GlyphRasterizer,
 
     // The set of images that aren't present or valid in the texture cache,
@@ -305,26 +383,59 @@ pub struct ResourceCache {
     pending_image_requests: FastHashSet<ImageRequest>,
 
     blob_image_renderer: Option<Box<BlobImageRenderer>>,
+
+    // Blob images rasterized on the scene builder thread, along with the part
+    // of the image (or tile) that was rasterized.
+    rasterized_blob_images: FastHashMap<BlobImageRequest, (DeviceUintRect, RasterizedBlobImage)>,
+
+    // Newly visible blob images are drawn with a transparent placeholder while
+    // they are rasterized on the scene builder thread.
+    enable_blob_placeholders: bool,
+    placeholder_blob_images: FastHashSet<BlobImageRequest>,
+    pending_async_blob_requests: Vec<BlobImageParams>,
+
+    // The blob image updates that were sent to the scene builder thread and
+    // haven't been applied yet. The image templates are out of date until they
+    // are, so the requests of later transactions are based on these instead.
+    pending_blob_updates: FastHashMap<ImageKey, PendingBlobUpdate>,
+    sent_blob_transactions: u64,
+    applied_blob_transactions: u64,
 }
 
 impl ResourceCache {
     pub fn new(
         texture_cache: TextureCache,
         glyph_rasterizer: GlyphRasterizer,
-        blob_image_renderer: Option<Box<BlobImageRenderer>>,
+        mut blob_image_renderer: Option<Box<BlobImageRenderer>>,
+        enable_blob_placeholders: bool,
     ) -> Self {
+        // The placeholders are only ever replaced if the blob images can be
+        // rasterized on the scene builder thread.
+        let enable_blob_placeholders = enable_blob_placeholders &&
+            blob_image_renderer
+                .as_mut()
+                .map_or(false, |renderer| renderer.create_async_rasterizer().is_some());
+
         ResourceCache {
             cached_glyphs: GlyphCache::new(),
             cached_images: ResourceClassCache::new(),
             cached_render_tasks: RenderTaskCache::new(),
             resources: Resources::default(),
             cached_glyph_dimensions: FastHashMap::default(),
+            cached_font_metrics: FastHashMap::default(),
             texture_cache,
             state: State::Idle,
             current_frame_id: FrameId(0),
             pending_image_requests: FastHashSet::default(),
             glyph_rasterizer,
             blob_image_renderer,
+            rasterized_blob_images: FastHashMap::default(),
+            enable_blob_placeholders,
+            placeholder_blob_images: FastHashSet::default(),
+            pending_async_blob_requests: Vec::new(),
+            pending_blob_updates: FastHashMap::default(),
+            sent_blob_transactions: 0,
+            applied_blob_transactions: 0,
         }
     }
 
@@ -412,6 +523,7 @@ impl ResourceCache {
                         instance.options,
                         instance.platform_options,
                         instance.variations,
+                        instance.variation_bindings,
                     );
                 }
                 ResourceUpdate::DeleteFontInstance(instance) => {
@@ -446,12 +558,13 @@ impl ResourceCache {
         options: Option<FontInstanceOptions>,
         platform_options: Option<FontInstancePlatformOptions>,
         variations: Vec<FontVariation>,
+        variation_bindings: Vec<FontVariationBinding>,
     ) {
         let FontInstanceOptions {
             render_mode,
             flags,
             bg_color,
-            ..
+            palette_index,
         } = options.unwrap_or_default();
         let instance = FontInstance::new(
             font_key,
@@ -460,8 +573,10 @@ impl ResourceCache {
             bg_color,
             render_mode,
             flags,
+            palette_index,
             platform_options,
             variations,
+            variation_bindings,
         );
         self.resources.font_instances
             .write()
@@ -508,14 +623,12 @@ impl ResourceCache {
                 tiling,
             );
         }
-        let dirty_rect = Some(descriptor.full_rect());
 
         let resource = ImageResource {
             descriptor,
             data,
             epoch: Epoch(0),
             tiling,
-            dirty_rect,
         };
 
         self.resources.image_templates.insert(image_key, resource);
@@ -546,16 +659,42 @@ impl ResourceCache {
                 .update(image_key, Arc::clone(&blob), dirty_rect);
         }
 
+        if tiling != image.tiling ||
+           descriptor.size != image.descriptor.size ||
+           descriptor.format != image.descriptor.format {
+            // The existing tiles don't match the new layout of the image anymore.
+            self.cached_images
+                .clear_keys(|request| request.key == image_key);
+        } else {
+            // Only invalidate the parts of the cached image (or of each of its
+            // tiles) that are covered by the dirty rect.
+            self.cached_images.retain(|request, entry| {
+                if request.key != image_key {
+                    return true;
+                }
+                if let Ok(ref mut info) = *entry {
+                    let tile_dirty_rect = compute_request_dirty_rect(
+                        &descriptor,
+                        tiling,
+                        request.tile,
+                        dirty_rect,
+                    );
+                    info.dirty_rect = union_dirty_rects(tile_dirty_rect, info.dirty_rect);
+                }
+                true
+            });
+        }
+
+        // Anything rasterized ahead of time for this image is out of date.
+        self.rasterized_blob_images.retain(|request, _| request.key != image_key);
+        self.placeholder_blob_images.retain(|request| request.key != image_key);
+        self.pending_async_blob_requests.retain(|params| params.request.key != image_key);
+
         *image = ImageResource {
             descriptor,
             data,
             epoch: Epoch(image.epoch.0 + 1),
             tiling,
-            dirty_rect: match (dirty_rect, image.dirty_rect) {
-                (Some(rect), Some(prev_rect)) => Some(rect.union(&prev_rect)),
-                (Some(rect), None) => Some(rect),
-                (None, _) => None,
-            },
         };
     }
 
@@ -564,6 +703,9 @@ impl ResourceCache {
 
         self.cached_images
             .clear_keys(|request| request.key == image_key);
+        self.rasterized_blob_images.retain(|request, _| request.key != image_key);
+        self.placeholder_blob_images.retain(|request| request.key != image_key);
+        self.pending_async_blob_requests.retain(|params| params.request.key != image_key);
 
         match value {
             Some(image) => if image.data.is_blob() {
@@ -608,80 +750,339 @@ impl ResourceCache {
             return;
         }
 
-        // If this image exists in the texture cache, *and* the epoch
-        // in the cache matches that of the template, then it is
-        // valid to use as-is.
-        let (entry, needs_update) = match self.cached_images.entry(request) {
-            Occupied(entry) => {
-                let info = entry.into_mut();
-                let needs_update = info.as_mut().unwrap().epoch != template.epoch;
-                info.as_mut().unwrap().epoch = template.epoch;
-                (info, needs_update)
-            }
-            Vacant(entry) => (
-                entry.insert(Ok(
-                    CachedImageInfo {
-                        epoch: template.epoch,
-                        texture_cache_handle: TextureCacheHandle::new(),
-                    }
-                )),
-                true,
-            ),
+        let descriptor = compute_blob_descriptor(
+            &template.descriptor,
+            template.tiling,
+            request.tile,
+        );
+
+        // If this image (or tile) exists in the texture cache, *and* it hasn't
+        // been invalidated since it was uploaded, then it is valid to use as-is.
+        let entry = match self.cached_images.entry(request) {
+            Occupied(entry) => entry.into_mut(),
+            Vacant(entry) => entry.insert(Ok(
+                CachedImageInfo {
+                    texture_cache_handle: TextureCacheHandle::new(),
+                    dirty_rect: None,
+                }
+            )),
         };
+        let info = entry.as_mut().unwrap();
 
         let needs_upload = self.texture_cache
-            .request(&entry.as_ref().unwrap().texture_cache_handle, gpu_cache);
+            .request(&info.texture_cache_handle, gpu_cache);
 
-        let dirty_rect = if needs_upload {
+        if needs_upload {
             // the texture cache entry has been evicted, treat it as all dirty
-            Some(template.descriptor.full_rect())
-        } else if needs_update {
-            template.dirty_rect
-        } else {
-            return
+            info.dirty_rect = Some(DeviceUintRect::new(DeviceUintPoint::zero(), descriptor.size));
+        }
+
+        // Don't bother requesting unchanged images or tiles.
+        let dirty_rect = match info.dirty_rect {
+            Some(rect) => rect,
+            None => return,
         };
 
         // We can start a worker thread rasterizing right now, if:
         //  - The image is a blob.
         //  - The blob hasn't already been requested this frame.
-        if self.pending_image_requests.insert(request) && template.data.is_blob() {
-            if let Some(ref mut renderer) = self.blob_image_renderer {
-                let (offset, size) = match template.tiling {
-                    Some(tile_size) => {
-                        let tile_offset = request.tile.unwrap();
-                        let actual_size = compute_tile_size(
-                            &template.descriptor,
-                            tile_size,
-                            tile_offset,
+        if !self.pending_image_requests.insert(request) {
+            return;
+        }
+        let blob_data = match template.data {
+            ImageData::Blob(ref data) => data,
+            _ => return,
+        };
+        let blob_request: BlobImageRequest = request.into();
+
+        let is_rasterized = match self.rasterized_blob_images.get(&blob_request) {
+            Some(&(ref rect, _)) => rect.contains_rect(&dirty_rect),
+            None => false,
+        };
+        if is_rasterized {
+            // It was rasterized on the scene builder thread already.
+            return;
+        }
+        self.rasterized_blob_images.remove(&blob_request);
+
+        if self.placeholder_blob_images.contains(&blob_request) {
+            // Still being rasterized asynchronously, the placeholder only needs
+            // to be uploaded again if it was evicted.
+            if !needs_upload {
+                self.pending_image_requests.remove(&request);
+            }
+        } else if needs_upload && self.enable_blob_placeholders {
+            // Don't block frame building on newly visible tiles, draw a placeholder
+            // until they are rasterized on the scene builder thread.
+            self.placeholder_blob_images.insert(blob_request);
+            self.pending_async_blob_requests.push(BlobImageParams {
+                request: blob_request,
+                data: Arc::clone(blob_data),
+                descriptor,
+                dirty_rect: None,
+            });
+        } else if let Some(ref mut renderer) = self.blob_image_renderer {
+            renderer.request(
+                &self.resources,
+                blob_request,
+                &descriptor,
+                Some(dirty_rect),
+            );
+        }
+    }
+
+    /// Returns the rasterization requests for the blob images added or updated by
+    /// a transaction that is about to be sent to the scene builder thread, so that
+    /// they are rasterized there before the new scene is swapped in.
+    ///
+    /// Only the parts of the images that are currently in use are requested, the
+    /// tiles of new tiled images are requested once they become visible.
+    pub fn create_blob_scene_builder_requests(
+        &mut self,
+        updates: &[ResourceUpdate],
+    ) -> (Option<Box<AsyncBlobImageRasterizer>>, Vec<BlobImageParams>) {
+        let max_texture_size = self.max_texture_size();
+        let mut requests = Vec::new();
+
+        self.sent_blob_transactions += 1;
+        let transaction = self.sent_blob_transactions;
+
+        for update in updates {
+            match *update {
+                ResourceUpdate::AddImage(ref img) => {
+                    let data = match img.data {
+                        ImageData::Blob(ref data) => data,
+                        _ => continue,
+                    };
+                    let mut tiling = img.tiling;
+                    if tiling.is_none() &&
+                       Self::should_tile(max_texture_size, &img.descriptor, &img.data) {
+                        tiling = Some(DEFAULT_TILE_SIZE);
+                    }
+                    self.pending_blob_updates.insert(img.key, PendingBlobUpdate {
+                        descriptor: img.descriptor,
+                        tiling,
+                        dirty_rect: None,
+                        discards_cache: true,
+                        transaction,
+                    });
+                    if tiling.is_none() {
+                        requests.push(BlobImageParams {
+                            request: BlobImageRequest { key: img.key, tile: None },
+                            data: Arc::clone(data),
+                            descriptor: compute_blob_descriptor(&img.descriptor, None, None),
+                            dirty_rect: None,
+                        });
+                    }
+                }
+                ResourceUpdate::UpdateImage(ref img) => {
+                    let data = match img.data {
+                        ImageData::Blob(ref data) => data,
+                        _ => continue,
+                    };
+
+                    // Start from the state the image will be in once the earlier
+                    // transactions are applied. Their rasterized images are dropped
+                    // when this one is applied, so the areas they invalidated have
+                    // to be rasterized again.
+                    let (prev_descriptor, prev_tiling, prev_discards_cache, dirty_rect) =
+                        match self.pending_blob_updates.get(&img.key) {
+                            Some(pending) => (
+                                pending.descriptor,
+                                pending.tiling,
+                                pending.discards_cache,
+                                match (img.dirty_rect, pending.dirty_rect) {
+                                    (Some(rect), Some(prev_rect)) => Some(rect.union(&prev_rect)),
+                                    _ => None,
+                                },
+                            ),
+                            None => match self.resources.image_templates.get(img.key) {
+                                Some(template) => (
+                                    template.descriptor,
+                                    template.tiling,
+                                    false,
+                                    img.dirty_rect,
+                                ),
+                                None => continue,
+                            },
+                        };
+
+                    let mut tiling = prev_tiling;
+                    if tiling.is_none() &&
+                       Self::should_tile(max_texture_size, &img.descriptor, &img.data) {
+                        tiling = Some(DEFAULT_TILE_SIZE);
+                    }
+                    let discards_cache = prev_discards_cache ||
+                        tiling != prev_tiling ||
+                        img.descriptor.size != prev_descriptor.size ||
+                        img.descriptor.format != prev_descriptor.format;
+
+                    self.pending_blob_updates.insert(img.key, PendingBlobUpdate {
+                        descriptor: img.descriptor,
+                        tiling,
+                        dirty_rect,
+                        discards_cache,
+                        transaction,
+                    });
+
+                    if discards_cache {
+                        // The cached tiles will be discarded, the ones that are still
+                        // visible are requested again when the frame is built.
+                        if tiling.is_none() {
+                            requests.push(BlobImageParams {
+                                request: BlobImageRequest { key: img.key, tile: None },
+                                data: Arc::clone(data),
+                                descriptor: compute_blob_descriptor(&img.descriptor, None, None),
+                                dirty_rect: None,
+                            });
+                        }
+                        continue;
+                    }
+
+                    let mut dirty_rects = FastHashMap::default();
+                    for (request, entry) in self.cached_images.iter() {
+                        if request.key != img.key {
+                            continue;
+                        }
+                        let info = match *entry {
+                            Ok(ref info) => info,
+                            Err(_) => continue,
+                        };
+                        let request_dirty_rect = compute_request_dirty_rect(
+                            &img.descriptor,
+                            tiling,
+                            request.tile,
+                            dirty_rect,
                         );
-                        let offset = DevicePoint::new(
-                            tile_offset.x as f32 * tile_size as f32,
-                            tile_offset.y as f32 * tile_size as f32,
+                        let tile_dirty_rect = dirty_rects.entry(request.tile).or_insert(None);
+                        *tile_dirty_rect = union_dirty_rects(
+                            union_dirty_rects(request_dirty_rect, info.dirty_rect),
+                            *tile_dirty_rect,
                         );
+                    }
 
-                        if let Some(dirty) = dirty_rect {
-                            if intersect_for_tile(dirty, actual_size, tile_size, tile_offset).is_none() {
-                                // don't bother requesting unchanged tiles
-                                self.pending_image_requests.remove(&request);
-                                return
-                            }
+                    for (tile, tile_dirty_rect) in dirty_rects {
+                        if let Some(tile_dirty_rect) = tile_dirty_rect {
+                            requests.push(BlobImageParams {
+                                request: BlobImageRequest { key: img.key, tile },
+                                data: Arc::clone(data),
+                                descriptor: compute_blob_descriptor(
+                                    &img.descriptor,
+                                    tiling,
+                                    tile,
+                                ),
+                                dirty_rect: Some(tile_dirty_rect),
+                            });
                         }
-
-                        (offset, actual_size)
                     }
-                    None => (DevicePoint::zero(), template.descriptor.size),
-                };
+                }
+                _ => {}
+            }
+        }
+
+        if requests.is_empty() {
+            return (None, requests);
+        }
 
-                renderer.request(
-                    &self.resources,
-                    request.into(),
-                    &BlobImageDescriptor {
-                        size,
-                        offset,
-                        format: template.descriptor.format,
-                    },
-                    dirty_rect,
-                );
+        match self.blob_image_renderer.as_mut().and_then(|r| r.create_async_rasterizer()) {
+            Some(rasterizer) => (Some(rasterizer), requests),
+            None => (None, Vec::new()),
+        }
+    }
+
+    /// Returns the rasterization requests for the blob images that are currently
+    /// drawn with a placeholder, to be sent to the scene builder thread.
+    pub fn create_async_blob_requests(
+        &mut self,
+    ) -> (Option<Box<AsyncBlobImageRasterizer>>, Vec<BlobImageParams>) {
+        if self.pending_async_blob_requests.is_empty() {
+            return (None, Vec::new());
+        }
+
+        let requests = mem::replace(&mut self.pending_async_blob_requests, Vec::new());
+        match self.blob_image_renderer.as_mut().and_then(|r| r.create_async_rasterizer()) {
+            Some(rasterizer) => (Some(rasterizer), requests),
+            None => {
+                warn!("Blob image placeholders require an asynchronous blob rasterizer");
+                // Rasterize them the next time they are drawn instead.
+                for params in &requests {
+                    self.placeholder_blob_images.remove(&params.request);
+                }
+                (None, Vec::new())
+            }
+        }
+    }
+
+    /// Called when a transaction comes back from the scene builder thread, before
+    /// its resource updates are applied.
+    pub fn end_blob_scene_builder_transaction(&mut self) {
+        self.applied_blob_transactions += 1;
+        let applied = self.applied_blob_transactions;
+        self.pending_blob_updates.retain(|_, pending| pending.transaction > applied);
+    }
+
+    /// Keeps the blob images rasterized on the scene builder thread around, they
+    /// are uploaded instead of being rasterized again when they are requested.
+    pub fn add_rasterized_blob_images(
+        &mut self,
+        requests: &[BlobImageParams],
+        rasterized_blobs: Vec<(BlobImageRequest, BlobImageResult)>,
+    ) {
+        self.add_blob_images(requests, rasterized_blobs, false);
+    }
+
+    /// Same as `add_rasterized_blob_images` for the blob images that were drawn
+    /// with a placeholder.
+    pub fn add_async_blob_images(
+        &mut self,
+        requests: &[BlobImageParams],
+        rasterized_blobs: Vec<(BlobImageRequest, BlobImageResult)>,
+    ) {
+        self.add_blob_images(requests, rasterized_blobs, true);
+    }
+
+    fn add_blob_images(
+        &mut self,
+        requests: &[BlobImageParams],
+        rasterized_blobs: Vec<(BlobImageRequest, BlobImageResult)>,
+        replaces_placeholders: bool,
+    ) {
+        let requests = requests
+            .iter()
+            .map(|params| (params.request, params))
+            .collect::<FastHashMap<_, _>>();
+
+        for (request, result) in rasterized_blobs {
+            let params = match requests.get(&request) {
+                Some(params) => *params,
+                None => continue,
+            };
+
+            // Ignore the images rasterized from commands that were replaced since.
+            let is_up_to_date = match self.resources.image_templates.get(request.key) {
+                Some(&ImageResource { data: ImageData::Blob(ref data), .. }) => {
+                    Arc::ptr_eq(data, &params.data)
+                }
+                _ => false,
+            };
+            if !is_up_to_date {
+                continue;
+            }
+            if replaces_placeholders && !self.placeholder_blob_images.remove(&request) {
+                continue;
+            }
+
+            match result {
+                Ok(image) => {
+                    let rect = params.dirty_rect.unwrap_or(
+                        DeviceUintRect::new(DeviceUintPoint::zero(), params.descriptor.size)
+                    );
+                    self.rasterized_blob_images.insert(request, (rect, image));
+                }
+                Err(err) => {
+                    // It will be rasterized again the next time it is drawn.
+                    warn!("Failed to rasterize blob image {:?}: {:?}", request, err);
+                }
             }
         }
     }
@@ -825,6 +1226,19 @@ impl ResourceCache {
         }
     }
 
+    pub fn get_font_metrics(&mut self, font: &FontInstance) -> Option<FontMetrics> {
+        match self.cached_font_metrics.entry(font.clone()) {
+            Occupied(entry) => *entry.get(),
+            Vacant(entry) => *entry.insert(
+                self.glyph_rasterizer.get_font_metrics(font),
+            ),
+        }
+    }
+
+    pub fn get_variation_quantum(&self, font_key: FontKey, tag: u32) -> f32 {
+        self.glyph_rasterizer.get_variation_quantum(font_key, tag)
+    }
+
     pub fn get_glyph_index(&mut self, font_key: FontKey, ch: char) -> Option<u32> {
         self.glyph_rasterizer.get_glyph_index(font_key, ch)
     }
@@ -924,9 +1338,14 @@ impl ResourceCache {
 
     fn update_texture_cache(&mut self, gpu_cache: &mut GpuCache) {
         for request in self.pending_image_requests.drain() {
-            let image_template = self.resources.image_templates.get_mut(request.key).unwrap();
+            let image_template = self.resources.image_templates.get(request.key).unwrap();
             debug_assert!(image_template.data.uses_texture_cache());
-            let mut dirty_rect = image_template.dirty_rect;
+            let entry = self.cached_images.get_mut(&request).as_mut().unwrap();
+            // Relative to the tile for tiled images.
+            let dirty_rect = entry.dirty_rect.take();
+            if dirty_rect.is_none() {
+                continue;
+            }
 
             let image_data = match image_template.data {
                 ImageData::Raw(..) | ImageData::External(..) => {
@@ -935,13 +1354,33 @@ impl ResourceCache {
                     image_template.data.clone()
                 }
                 ImageData::Blob(..) => {
-                    // Extract the rasterized image from the blob renderer.
-                    match self.blob_image_renderer
-                        .as_mut()
-                        .unwrap()
-                        .resolve(request.into())
-                    {
-                        Ok(image) => ImageData::new(image.data),
+                    let blob_request: BlobImageRequest = request.into();
+                    let result = if let Some((_, image)) = self.rasterized_blob_images.remove(&blob_request) {
+                        Ok(image)
+                    } else if self.placeholder_blob_images.contains(&blob_request) {
+                        // Keep the image dirty until it is rasterized on the scene builder
+                        // thread, and upload a transparent placeholder in the meantime.
+                        entry.dirty_rect = dirty_rect;
+                        let descriptor = &image_template.descriptor;
+                        let size = compute_blob_descriptor(descriptor, image_template.tiling, request.tile).size;
+                        let bpp = descriptor.format.bytes_per_pixel();
+                        Ok(RasterizedBlobImage {
+                            tile: request.tile,
+                            size,
+                            data: vec![0; (size.width * size.height * bpp) as usize],
+                        })
+                    } else {
+                        // Extract the rasterized image from the blob renderer.
+                        self.blob_image_renderer
+                            .as_mut()
+                            .unwrap()
+                            .resolve(blob_request)
+                    };
+                    match result {
+                        Ok(image) => {
+                            debug_assert_eq!(image.tile, request.tile);
+                            ImageData::new(image.data)
+                        }
                         // TODO(nical): I think that we should handle these somewhat gracefully,
                         // at least in the out-of-memory scenario.
                         Err(BlobImageError::Oom) => {
@@ -968,13 +1407,6 @@ impl ResourceCache {
 
                 let clipped_tile_size = compute_tile_size(image_descriptor, tile_size, tile);
 
-                if let Some(dirty) = dirty_rect {
-                    dirty_rect = intersect_for_tile(dirty, clipped_tile_size, tile_size, tile);
-                    if dirty_rect.is_none() {
-                        continue
-                    }
-                }
-
                 // The tiled image could be stored on the CPU as one large image or be
                 // already broken up into tiles. This affects the way we compute the stride
                 // and offset.
@@ -1027,7 +1459,6 @@ impl ResourceCache {
                 }
             };
 
-            let entry = self.cached_images.get_mut(&request).as_mut().unwrap();
             self.texture_cache.update(
                 &mut entry.texture_cache_handle,
                 descriptor,
@@ -1039,7 +1470,6 @@ impl ResourceCache {
                 None,
                 UvRectKind::Rect,
             );
-            image_template.dirty_rect = None;
         }
     }
 
@@ -1057,6 +1487,7 @@ impl ResourceCache {
         }
         if what.contains(ClearCache::GLYPH_DIMENSIONS) {
             self.cached_glyph_dimensions.clear();
+            self.cached_font_metrics.clear();
         }
         if what.contains(ClearCache::RENDER_TASKS) {
             self.cached_render_tasks.clear();
@@ -1254,28 +1685,59 @@ impl ResourceCache {
                     entry.insert(short_path);
                 }
                 ImageData::Blob(_) => {
-                    assert_eq!(template.tiling, None);
-                    let request = BlobImageRequest {
-                        key,
-                        //TODO: support tiled blob images
-                        // https://github.com/servo/webrender/issues/2236
-                        tile: None,
-                    };
                     let renderer = self.blob_image_renderer.as_mut().unwrap();
-                    renderer.request(
-                        &self.resources,
-                        request,
-                        &BlobImageDescriptor {
-                            size: desc.size,
-                            offset: DevicePoint::zero(),
-                            format: desc.format,
-                        },
-                        None,
-                    );
-                    let result = renderer.resolve(request)
-                        .expect("Blob resolve failed");
-                    assert_eq!(result.size, desc.size);
-                    assert_eq!(result.data.len(), desc.compute_total_size() as usize);
+                    let tiles = match template.tiling {
+                        Some(tile_size) => {
+                            let tile_size = tile_size as u32;
+                            let num_tiles_x = (desc.size.width + tile_size - 1) / tile_size;
+                            let num_tiles_y = (desc.size.height + tile_size - 1) / tile_size;
+                            let mut tiles = Vec::new();
+                            for y in 0 .. num_tiles_y {
+                                for x in 0 .. num_tiles_x {
+                                    tiles.push(Some(TileOffset::new(x as u16, y as u16)));
+                                }
+                            }
+                            tiles
+                        }
+                        None => vec![None],
+                    };
+
+                    // Request all of the tiles before resolving any of them so that
+                    // they can be rasterized in parallel.
+                    for &tile in &tiles {
+                        renderer.request(
+                            &self.resources,
+                            BlobImageRequest { key, tile },
+                            &compute_blob_descriptor(desc, template.tiling, tile),
+                            None,
+                        );
+                    }
+
+                    // Stitch the tiles back together so that the blob can be
+                    // replayed as a regular image.
+                    let bpp = desc.format.bytes_per_pixel();
+                    let stride = desc.size.width * bpp;
+                    let mut data = vec![0u8; (stride * desc.size.height) as usize];
+                    for &tile in &tiles {
+                        let result = renderer.resolve(BlobImageRequest { key, tile })
+                            .expect("Blob resolve failed");
+                        assert_eq!(result.tile, tile);
+                        let origin = match (template.tiling, tile) {
+                            (Some(tile_size), Some(tile)) => DeviceUintPoint::new(
+                                tile.x as u32 * tile_size as u32,
+                                tile.y as u32 * tile_size as u32,
+                            ),
+                            _ => DeviceUintPoint::zero(),
+                        };
+                        let row_size = (result.size.width * bpp) as usize;
+                        assert_eq!(result.data.len(), row_size * result.size.height as usize);
+                        for row in 0 .. result.size.height {
+                            let src = row as usize * row_size;
+                            let dst = ((origin.y + row) * stride + origin.x * bpp) as usize;
+                            data[dst .. dst + row_size]
+                                .copy_from_slice(&result.data[src .. src + row_size]);
+                        }
+                    }
 
                     num_blobs += 1;
                     #[cfg(feature = "png")]
@@ -1283,14 +1745,14 @@ impl ResourceCache {
                         root.join(format!("blobs/{}.png", num_blobs)),
                         (desc.size.width, desc.size.height),
                         ReadPixelsFormat::Standard(desc.format),
-                        &result.data,
+                        &data,
                     );
                     let file_name = format!("{}.raw", num_blobs);
                     let short_path = format!("blobs/{}", file_name);
                     let full_path = path_blobs.clone().join(&file_name);
                     fs::File::create(full_path)
                         .expect(&format!("Unable to create {}", short_path))
-                        .write_all(&result.data)
+                        .write_all(&data)
                         .unwrap();
                     other_paths.insert(key, short_path);
                 }
@@ -1376,6 +1838,7 @@ impl ResourceCache {
                 self.current_frame_id = cached.current_frame_id;
                 self.cached_glyphs = cached.glyphs;
                 self.cached_glyph_dimensions = cached.glyph_dimensions;
+                self.cached_font_metrics.clear();
                 self.cached_images = cached.images;
                 self.cached_render_tasks = cached.render_tasks;
                 self.texture_cache = cached.textures;
@@ -1384,10 +1847,12 @@ impl ResourceCache {
                 self.current_frame_id = FrameId(0);
                 self.cached_glyphs.clear();
                 self.cached_glyph_dimensions.clear();
+                self.cached_font_metrics.clear();
                 self.cached_images.clear();
                 self.cached_render_tasks.clear();
                 let max_texture_size = self.texture_cache.max_texture_size();
-                self.texture_cache = TextureCache::new(max_texture_size);
+                let max_texture_layers = self.texture_cache.max_texture_layers();
+                self.texture_cache = TextureCache::new(max_texture_size, max_texture_layers);
             }
         }
 
@@ -1460,7 +1925,6 @@ impl ResourceCache {
                 descriptor: template.descriptor,
                 tiling: template.tiling,
                 epoch: template.epoch,
-                dirty_rect: None,
             });
         }
 
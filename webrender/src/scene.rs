@@ -4,19 +4,21 @@
 
 use api::{BuiltDisplayList, ColorF, DynamicProperties, Epoch, LayoutSize};
 use api::{FilterOp, LayoutTransform, PipelineId, PropertyBinding, PropertyBindingId};
-use api::{ItemRange, MixBlendMode, StackingContext};
+use api::{ItemRange, LayoutVector2D, MixBlendMode, StackingContext};
 use internal_types::FastHashMap;
 use std::sync::Arc;
 
 /// Stores a map of the animated property bindings for the current display list. These
-/// can be used to animate the transform and/or opacity of a display list without
-/// re-submitting the display list itself.
+/// can be used to animate the transform, opacity, colors and/or scroll offsets of a
+/// display list without re-submitting the display list itself.
 #[cfg_attr(feature = "capture", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 #[derive(Clone)]
 pub struct SceneProperties {
     transform_properties: FastHashMap<PropertyBindingId, LayoutTransform>,
     float_properties: FastHashMap<PropertyBindingId, f32>,
+    color_properties: FastHashMap<PropertyBindingId, ColorF>,
+    vector_properties: FastHashMap<PropertyBindingId, LayoutVector2D>,
 }
 
 impl SceneProperties {
@@ -24,6 +26,8 @@ impl SceneProperties {
         SceneProperties {
             transform_properties: FastHashMap::default(),
             float_properties: FastHashMap::default(),
+            color_properties: FastHashMap::default(),
+            vector_properties: FastHashMap::default(),
         }
     }
 
@@ -31,6 +35,8 @@ impl SceneProperties {
     pub fn set_properties(&mut self, properties: DynamicProperties) {
         self.transform_properties.clear();
         self.float_properties.clear();
+        self.color_properties.clear();
+        self.vector_properties.clear();
         self.add_properties(properties);
     }
 
@@ -45,6 +51,16 @@ impl SceneProperties {
             self.float_properties
                 .insert(property.key.id, property.value);
         }
+
+        for property in properties.colors {
+            self.color_properties
+                .insert(property.key.id, property.value);
+        }
+
+        for property in properties.scroll_offsets {
+            self.vector_properties
+                .insert(property.key.id, property.value);
+        }
     }
 
     /// Get the current value for a transform property.
@@ -78,6 +94,38 @@ impl SceneProperties {
             }
         }
     }
+
+    /// Get the current value for a color property.
+    pub fn resolve_color(
+        &self,
+        property: &PropertyBinding<ColorF>
+    ) -> ColorF {
+        match *property {
+            PropertyBinding::Value(value) => value,
+            PropertyBinding::Binding(ref key, v) => {
+                self.color_properties
+                    .get(&key.id)
+                    .cloned()
+                    .unwrap_or(v)
+            }
+        }
+    }
+
+    /// Get the current value for a vector property, such as a scroll offset.
+    pub fn resolve_layout_vector(
+        &self,
+        property: &PropertyBinding<LayoutVector2D>
+    ) -> LayoutVector2D {
+        match *property {
+            PropertyBinding::Value(value) => value,
+            PropertyBinding::Binding(ref key, v) => {
+                self.vector_properties
+                    .get(&key.id)
+                    .cloned()
+                    .unwrap_or(v)
+            }
+        }
+    }
 }
 
 /// A representation of the layout within the display port for a given document or iframe.
@@ -2,6 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use api::{AsyncBlobImageRasterizer, BlobImageParams, BlobImageRequest, BlobImageResult};
 use api::{DocumentId, PipelineId, ApiMsg, FrameMsg, ResourceUpdate};
 use api::channel::MsgSender;
 use display_list_flattener::build_scene;
@@ -19,10 +20,18 @@ pub enum SceneBuilderRequest {
     Transaction {
         document_id: DocumentId,
         scene: Option<SceneRequest>,
+        blob_requests: Vec<BlobImageParams>,
+        blob_rasterizer: Option<Box<AsyncBlobImageRasterizer>>,
         resource_updates: Vec<ResourceUpdate>,
         frame_ops: Vec<FrameMsg>,
         render: bool,
     },
+    // Blob images that are drawn with a placeholder until they are rasterized.
+    RasterizeBlobs {
+        document_id: DocumentId,
+        blob_requests: Vec<BlobImageParams>,
+        blob_rasterizer: Box<AsyncBlobImageRasterizer>,
+    },
     WakeUp,
     Flush(MsgSender<()>),
     Stop
@@ -33,11 +42,18 @@ pub enum SceneBuilderResult {
     Transaction {
         document_id: DocumentId,
         built_scene: Option<BuiltScene>,
+        blob_requests: Vec<BlobImageParams>,
+        rasterized_blobs: Vec<(BlobImageRequest, BlobImageResult)>,
         resource_updates: Vec<ResourceUpdate>,
         frame_ops: Vec<FrameMsg>,
         render: bool,
         result_tx: Option<Sender<SceneSwapResult>>,
     },
+    RasterizedBlobs {
+        document_id: DocumentId,
+        blob_requests: Vec<BlobImageParams>,
+        rasterized_blobs: Vec<(BlobImageRequest, BlobImageResult)>,
+    },
     FlushComplete(MsgSender<()>),
     Stopped,
 }
@@ -133,6 +149,8 @@ impl SceneBuilder {
             SceneBuilderRequest::Transaction {
                 document_id,
                 scene,
+                blob_requests,
+                blob_rasterizer,
                 resource_updates,
                 frame_ops,
                 render,
@@ -141,7 +159,13 @@ impl SceneBuilder {
                     build_scene(&self.config, request)
                 });
 
-                // TODO: pre-rasterization.
+                // Rasterize the blob images that the transaction changed, so that the
+                // new scene isn't swapped in before they are ready and frame building
+                // doesn't have to wait for them.
+                let rasterized_blobs = match blob_rasterizer {
+                    Some(mut rasterizer) => rasterizer.rasterize(&blob_requests),
+                    None => Vec::new(),
+                };
 
                 // We only need the pipeline info and the result channel if we
                 // have a hook callback *and* if this transaction actually built
@@ -167,6 +191,8 @@ impl SceneBuilder {
                 self.tx.send(SceneBuilderResult::Transaction {
                     document_id,
                     built_scene,
+                    blob_requests,
+                    rasterized_blobs,
                     resource_updates,
                     frame_ops,
                     render,
@@ -192,6 +218,19 @@ impl SceneBuilder {
                     }
                 }
             }
+            SceneBuilderRequest::RasterizeBlobs {
+                document_id,
+                blob_requests,
+                mut blob_rasterizer,
+            } => {
+                let rasterized_blobs = blob_rasterizer.rasterize(&blob_requests);
+                self.tx.send(SceneBuilderResult::RasterizedBlobs {
+                    document_id,
+                    blob_requests,
+                    rasterized_blobs,
+                }).unwrap();
+                let _ = self.api_tx.send(ApiMsg::WakeUp);
+            }
             SceneBuilderRequest::Stop => {
                 self.tx.send(SceneBuilderResult::Stopped).unwrap();
                 // We don't need to send a WakeUp to api_tx because we only
@@ -125,7 +125,7 @@ impl LazilyCompiledShader {
                 self.pipeline_requirements.clone(),
                 self.name,
                 &self.kind,
-            );
+            )?;
             self.program = Some(program);
         }
 
@@ -232,6 +232,7 @@ pub struct TextShader {
     simple: LazilyCompiledShader,
     transform: LazilyCompiledShader,
     glyph_transform: LazilyCompiledShader,
+    sdf: LazilyCompiledShader,
 }
 
 impl TextShader {
@@ -239,6 +240,7 @@ impl TextShader {
         name: &'static str,
         transform_name: &'static str,
         glyph_transform_name: &'static str,
+        sdf_name: &'static str,
         pipeline_requirements: &mut HashMap<String, PipelineRequirements>,
         device: &mut Device<B>,
         precache: bool,
@@ -267,7 +269,15 @@ impl TextShader {
             precache,
         )?;
 
-        Ok(TextShader { simple, transform, glyph_transform })
+        let sdf = LazilyCompiledShader::new(
+            ShaderKind::Text,
+            sdf_name,
+            pipeline_requirements,
+            device,
+            precache,
+        )?;
+
+        Ok(TextShader { simple, transform, glyph_transform, sdf })
     }
 
     pub fn get(
@@ -285,6 +295,9 @@ impl TextShader {
             }
             GlyphFormat::TransformedAlpha |
             GlyphFormat::TransformedSubpixel => &mut self.glyph_transform,
+            // Distance field glyphs are scaled and transformed by the shader,
+            // so they don't depend on the transform kind.
+            GlyphFormat::Sdf => &mut self.sdf,
         }
     }
 
@@ -292,6 +305,7 @@ impl TextShader {
         self.simple.deinit(device);
         self.transform.deinit(device);
         self.glyph_transform.deinit(device);
+        self.sdf.deinit(device);
     }
 }
 
@@ -302,6 +316,8 @@ pub struct Shaders {
     pub cs_blur_a8: LazilyCompiledShader,
     pub cs_blur_rgba8: LazilyCompiledShader,
     pub cs_border_segment: LazilyCompiledShader,
+    pub cs_path: LazilyCompiledShader,
+    pub cs_svg_filter: LazilyCompiledShader,
 
     // Brush shaders
     brush_solid: BrushShader,
@@ -312,6 +328,7 @@ pub struct Shaders {
     //brush_yuv_image: Vec<Option<BrushShader<B>>>,
     brush_yuv_image: Vec<BrushShader>,
     brush_radial_gradient: BrushShader,
+    brush_conic_gradient: BrushShader,
     brush_linear_gradient: BrushShader,
 
     /// These are "cache clip shaders". These shaders are used to
@@ -321,6 +338,7 @@ pub struct Shaders {
     pub cs_clip_box_shadow: LazilyCompiledShader,
     pub cs_clip_image: LazilyCompiledShader,
     pub cs_clip_line: LazilyCompiledShader,
+    pub cs_clip_path: LazilyCompiledShader,
 
     // The are "primitive shaders". These shaders draw and blend
     // final results on screen. They are aware of tile boundaries.
@@ -408,6 +426,28 @@ impl Shaders {
             false,
         )?;
 
+        let brush_conic_gradient = BrushShader::new(
+            if options.enable_dithering {
+                "brush_conic_gradient_dithering"
+            } else {
+                "brush_conic_gradient"
+            },
+            if options.enable_dithering {
+                "brush_conic_gradient_dithering_alpha_pass"
+            } else {
+                "brush_conic_gradient_alpha_pass"
+            },
+            if options.enable_dithering {
+                "brush_conic_gradient_dual_source_dithering"
+            } else {
+                "brush_conic_gradient_alpha_pass_dual_source_blending"
+            },
+            &mut pipeline_requirements,
+            device,
+            options.precache_shaders,
+            false,
+        )?;
+
         let brush_linear_gradient = BrushShader::new(
             if options.enable_dithering {
                 "brush_linear_gradient_dithering"
@@ -470,8 +510,16 @@ impl Shaders {
             options.precache_shaders,
         )?;
 
-        let cs_clip_image = LazilyCompiledShader::new(
+        let cs_clip_path = LazilyCompiledShader::new(
             ShaderKind::ClipCache,
+            "cs_clip_path_transform",
+            &mut pipeline_requirements,
+            device,
+            options.precache_shaders,
+        )?;
+
+        let cs_clip_image = LazilyCompiledShader::new(
+            ShaderKind::ClipImage,
             "cs_clip_image_transform",
             &mut pipeline_requirements,
             device,
@@ -482,6 +530,7 @@ impl Shaders {
             "ps_text_run",
             "ps_text_run_transform",
             "ps_text_run_glyph_transform",
+            "ps_text_run_glyph_sdf",
             &mut pipeline_requirements,
             device,
             options.precache_shaders,
@@ -491,6 +540,7 @@ impl Shaders {
             "ps_text_run_dual_source_blending",
             "ps_text_run_dual_source_blending_transform",
             "ps_text_run_dual_source_blending_glyph_transform",
+            "ps_text_run_dual_source_blending_glyph_sdf",
             &mut pipeline_requirements,
             device,
             options.precache_shaders,
@@ -562,6 +612,22 @@ impl Shaders {
              options.precache_shaders,
         )?;
 
+        let cs_path = LazilyCompiledShader::new(
+            ShaderKind::Cache(VertexArrayKind::Path),
+            "cs_path",
+            &mut pipeline_requirements,
+            device,
+            options.precache_shaders,
+        )?;
+
+        let cs_svg_filter = LazilyCompiledShader::new(
+            ShaderKind::Cache(VertexArrayKind::SvgFilter),
+            "cs_svg_filter",
+            &mut pipeline_requirements,
+            device,
+            options.precache_shaders,
+        )?;
+
         let ps_split_composite = LazilyCompiledShader::new(
             ShaderKind::Primitive,
             "ps_split_composite",
@@ -574,17 +640,21 @@ impl Shaders {
             cs_blur_a8,
             cs_blur_rgba8,
             cs_border_segment,
+            cs_path,
+            cs_svg_filter,
             brush_solid,
             brush_image,
             brush_blend,
             brush_mix_blend,
             brush_yuv_image,
             brush_radial_gradient,
+            brush_conic_gradient,
             brush_linear_gradient,
             cs_clip_rectangle,
             cs_clip_box_shadow,
             cs_clip_image,
             cs_clip_line,
+            cs_clip_path,
             ps_text_run,
             ps_text_run_dual_source,
             ps_split_composite,
@@ -624,6 +694,9 @@ impl Shaders {
                     BrushBatchKind::RadialGradient => {
                         &mut self.brush_radial_gradient
                     }
+                    BrushBatchKind::ConicGradient => {
+                        &mut self.brush_conic_gradient
+                    }
                     BrushBatchKind::LinearGradient => {
                         &mut self.brush_linear_gradient
                     }
@@ -663,11 +736,13 @@ impl Shaders {
         self.brush_blend.deinit(device);
         self.brush_mix_blend.deinit(device);
         self.brush_radial_gradient.deinit(device);
+        self.brush_conic_gradient.deinit(device);
         self.brush_linear_gradient.deinit(device);
         self.cs_clip_rectangle.deinit(device);
         self.cs_clip_box_shadow.deinit(device);
         self.cs_clip_image.deinit(device);
         self.cs_clip_line.deinit(device);
+        self.cs_clip_path.deinit(device);
         self.ps_text_run.deinit(device);
         self.ps_text_run_dual_source.deinit(device);
         /*for shader in self.brush_image {
@@ -682,6 +757,8 @@ impl Shaders {
             //}
         }
         self.cs_border_segment.deinit(device);
+        self.cs_path.deinit(device);
+        self.cs_svg_filter.deinit(device);
         self.ps_split_composite.deinit(device);
     }
 }
@@ -0,0 +1,473 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Support for SVG style filter graphs on stacking contexts.
+//!
+//! Each `FilterPrimitive` is lowered to a render task that draws it with
+//! the cs_svg_filter shader, reading its inputs from the saved targets of
+//! earlier tasks. Blur primitives reuse the regular blur render tasks.
+
+use api::{ColorF, ColorSpace, CompositeOperator, DeviceIntSize, DevicePixelScale, DevicePoint};
+use api::{FilterPrimitive, FilterPrimitiveInput, FilterPrimitiveKind, TransferFunction};
+use box_shadow::BLUR_SAMPLE_SCALE;
+use gpu_cache::{GpuCache, GpuCacheHandle, GpuDataRequest};
+use gpu_types::UvRectKind;
+use render_task::{ClearMode, RenderTask, RenderTaskId, RenderTaskTree};
+use tiling::RenderTargetKind;
+
+/// The operation of an SVG filter render task.
+/// Note: has to match the `FILTER_*` defines in cs_svg_filter.glsl
+#[repr(i32)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub enum SvgFilterOp {
+    Identity = 0,
+    Flood = 1,
+    Offset = 2,
+    ColorMatrix = 3,
+    ComponentTransfer = 4,
+    Composite = 5,
+    Blend = 6,
+    Morphology = 7,
+    Turbulence = 8,
+    DisplacementMap = 9,
+}
+
+// Note: has to match the `FLAG_*` defines in cs_svg_filter.glsl
+/// The primitive operates in linearRGB.
+pub const SVG_FILTER_LINEAR_RGB: i32 = 1;
+/// The first input is converted to the color space of the primitive.
+pub const SVG_FILTER_CONVERT_INPUT_1: i32 = 2;
+/// The second input is converted to the color space of the primitive.
+pub const SVG_FILTER_CONVERT_INPUT_2: i32 = 4;
+
+// Note: has to match `MORPHOLOGY_VERTICAL` in cs_svg_filter.glsl
+/// The pass of a morphology along the y axis, in the second byte of the
+/// generic int.
+const MORPHOLOGY_VERTICAL: i32 = 1;
+
+// The size of the turbulence lattice. The GPU cache data of a turbulence
+// primitive is one block with the base frequency and octave count, then
+// the lattice selector (four entries per block) and the gradients of each
+// channel (two per block), see `TURBULENCE_*` in cs_svg_filter.glsl.
+const TURBULENCE_SIZE: usize = 256;
+
+/// What an SVG filter render task draws.
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct SvgFilterInfo {
+    pub op: SvgFilterOp,
+    // Operator, blend mode or channels, depending on the op.
+    pub generic_int: i32,
+    pub flags: i32,
+    // Extra data in the GPU cache, see `write_filter_primitive_data`.
+    pub extra_data_handle: Option<GpuCacheHandle>,
+}
+
+/// The device space properties shared by all the render tasks
+/// of a filter graph.
+#[derive(Debug, Copy, Clone)]
+pub struct SvgFilterContext {
+    pub task_size: DeviceIntSize,
+    pub uv_rect_kind: UvRectKind,
+    // The position of the local space origin within the tasks.
+    pub origin: DevicePoint,
+    pub device_pixel_scale: DevicePixelScale,
+}
+
+/// Returns how far (in local units) the output of a filter graph can
+/// extend past the content it was given.
+pub fn filter_primitives_inflation(primitives: &[FilterPrimitive]) -> f32 {
+    primitives.iter().fold(0.0, |inflation, primitive| {
+        inflation + match primitive.kind {
+            FilterPrimitiveKind::Offset(_, offset) => offset.x.abs().max(offset.y.abs()),
+            FilterPrimitiveKind::Blur(_, radius) => BLUR_SAMPLE_SCALE * radius,
+            FilterPrimitiveKind::Morphology(_, _, radius) => radius.width.max(radius.height),
+            FilterPrimitiveKind::DisplacementMap { scale, .. } => 0.5 * scale.abs(),
+            _ => 0.0,
+        }
+    })
+}
+
+/// Uploads the extra data of a filter primitive that the shader
+/// reads from the GPU cache, if it has any.
+pub fn write_filter_primitive_data(
+    primitive: &FilterPrimitive,
+    handle: &mut GpuCacheHandle,
+    gpu_cache: &mut GpuCache,
+) {
+    match primitive.kind {
+        FilterPrimitiveKind::Identity(..) |
+        FilterPrimitiveKind::Blur(..) |
+        FilterPrimitiveKind::Blend(..) => return,
+        _ => {}
+    }
+
+    let mut request = match gpu_cache.request(handle) {
+        Some(request) => request,
+        None => return,
+    };
+
+    match primitive.kind {
+        FilterPrimitiveKind::Flood(color) => {
+            let color = match primitive.color_space {
+                ColorSpace::Srgb => color,
+                ColorSpace::LinearRgb => ColorF::new(
+                    srgb_to_linear(color.r),
+                    srgb_to_linear(color.g),
+                    srgb_to_linear(color.b),
+                    color.a,
+                ),
+            };
+            request.push(color.premultiplied());
+        }
+        FilterPrimitiveKind::Offset(_, offset) => {
+            request.push([offset.x, offset.y, 0.0, 0.0]);
+        }
+        FilterPrimitiveKind::ColorMatrix(_, ref m) => {
+            for i in 0..5 {
+                request.push([m[i*4], m[i*4+1], m[i*4+2], m[i*4+3]]);
+            }
+        }
+        FilterPrimitiveKind::ComponentTransfer(_, ref functions) => {
+            let tables = [
+                transfer_table(&functions[0]),
+                transfer_table(&functions[1]),
+                transfer_table(&functions[2]),
+                transfer_table(&functions[3]),
+            ];
+            for i in 0 .. 256 {
+                request.push([tables[0][i], tables[1][i], tables[2][i], tables[3][i]]);
+            }
+        }
+        FilterPrimitiveKind::Composite(_, _, operator) => {
+            let k = match operator {
+                CompositeOperator::Arithmetic(k) => k,
+                _ => [0.0; 4],
+            };
+            request.push(k);
+        }
+        FilterPrimitiveKind::Morphology(_, _, radius) => {
+            request.push([radius.width, radius.height, 0.0, 0.0]);
+        }
+        FilterPrimitiveKind::Turbulence { base_frequency, num_octaves, seed, .. } => {
+            request.push([base_frequency.0, base_frequency.1, num_octaves as f32, 0.0]);
+            write_turbulence_tables(seed, &mut request);
+        }
+        FilterPrimitiveKind::DisplacementMap { scale, .. } => {
+            request.push([scale, 0.0, 0.0, 0.0]);
+        }
+        FilterPrimitiveKind::Identity(..) |
+        FilterPrimitiveKind::Blur(..) |
+        FilterPrimitiveKind::Blend(..) => unreachable!(),
+    }
+}
+
+/// Adds the render tasks for a filter graph, applied to the contents
+/// in `source_task_id`. Returns the task with the final output.
+pub fn add_svg_filter_tasks(
+    primitives: &[FilterPrimitive],
+    data_handles: &[GpuCacheHandle],
+    source_task_id: RenderTaskId,
+    ctx: &SvgFilterContext,
+    render_tasks: &mut RenderTaskTree,
+) -> RenderTaskId {
+    let original = (source_task_id, ColorSpace::Srgb);
+    let mut outputs: Vec<(RenderTaskId, ColorSpace)> = Vec::with_capacity(primitives.len());
+
+    for (primitive, handle) in primitives.iter().zip(data_handles) {
+        let task_id = add_primitive_tasks(
+            primitive,
+            *handle,
+            &outputs,
+            original,
+            ctx,
+            render_tasks,
+        );
+        outputs.push((task_id, primitive.color_space));
+    }
+
+    // The result is composited in sRGB.
+    match outputs.last() {
+        Some(&(task_id, ColorSpace::Srgb)) => task_id,
+        Some(&output) => add_filter_task(
+            SvgFilterOp::Identity,
+            0,
+            None,
+            ColorSpace::Srgb,
+            &[output],
+            ctx,
+            render_tasks,
+        ),
+        None => source_task_id,
+    }
+}
+
+fn add_primitive_tasks(
+    primitive: &FilterPrimitive,
+    data_handle: GpuCacheHandle,
+    outputs: &[(RenderTaskId, ColorSpace)],
+    original: (RenderTaskId, ColorSpace),
+    ctx: &SvgFilterContext,
+    render_tasks: &mut RenderTaskTree,
+) -> RenderTaskId {
+    let color_space = primitive.color_space;
+    let input = |input| resolve_input(input, outputs, original);
+
+    let (op, generic_int, inputs) = match primitive.kind {
+        FilterPrimitiveKind::Blur(in1, radius) => {
+            // The blur shader reads its input from the previous
+            // pass, so blur a copy in the right color space.
+            let copy_id = add_filter_task(
+                SvgFilterOp::Identity,
+                0,
+                None,
+                color_space,
+                &[input(in1)],
+                ctx,
+                render_tasks,
+            );
+            let blur_task = RenderTask::new_blur(
+                radius * ctx.device_pixel_scale.0,
+                copy_id,
+                render_tasks,
+                RenderTargetKind::Color,
+                ClearMode::Transparent,
+            );
+            return render_tasks.add(blur_task);
+        }
+        FilterPrimitiveKind::Identity(in1) => {
+            (SvgFilterOp::Identity, 0, vec![input(in1)])
+        }
+        FilterPrimitiveKind::Flood(..) => {
+            (SvgFilterOp::Flood, 0, vec![])
+        }
+        FilterPrimitiveKind::Offset(in1, _) => {
+            (SvgFilterOp::Offset, 0, vec![input(in1)])
+        }
+        FilterPrimitiveKind::ColorMatrix(in1, _) => {
+            (SvgFilterOp::ColorMatrix, 0, vec![input(in1)])
+        }
+        FilterPrimitiveKind::ComponentTransfer(in1, _) => {
+            (SvgFilterOp::ComponentTransfer, 0, vec![input(in1)])
+        }
+        FilterPrimitiveKind::Composite(in1, in2, operator) => {
+            // Note: has to match `COMPOSITE_*` in cs_svg_filter.glsl
+            let operator = match operator {
+                CompositeOperator::Over => 0,
+                CompositeOperator::In => 1,
+                CompositeOperator::Out => 2,
+                CompositeOperator::Atop => 3,
+                CompositeOperator::Xor => 4,
+                CompositeOperator::Arithmetic(..) => 5,
+            };
+            (SvgFilterOp::Composite, operator, vec![input(in1), input(in2)])
+        }
+        FilterPrimitiveKind::Blend(in1, in2, mode) => {
+            (SvgFilterOp::Blend, mode as u32 as i32, vec![input(in1), input(in2)])
+        }
+        FilterPrimitiveKind::Morphology(in1, operator, _) => {
+            // The minimum or maximum over a rectangle is the one over the rows
+            // of the minimum or maximum over the columns, so the horizontal
+            // pass is drawn first and the vertical one reads its output.
+            let horizontal_id = add_filter_task(
+                SvgFilterOp::Morphology,
+                operator as u32 as i32,
+                Some(data_handle),
+                color_space,
+                &[input(in1)],
+                ctx,
+                render_tasks,
+            );
+            let generic_int = operator as u32 as i32 | MORPHOLOGY_VERTICAL << 8;
+            (SvgFilterOp::Morphology, generic_int, vec![(horizontal_id, color_space)])
+        }
+        FilterPrimitiveKind::Turbulence { kind, .. } => {
+            (SvgFilterOp::Turbulence, kind as u32 as i32, vec![])
+        }
+        FilterPrimitiveKind::DisplacementMap { input: in1, map, x_channel, y_channel, .. } => {
+            let channels = x_channel as u32 as i32 | (y_channel as u32 as i32) << 8;
+            (SvgFilterOp::DisplacementMap, channels, vec![input(in1), input(map)])
+        }
+    };
+
+    let extra_data_handle = match op {
+        SvgFilterOp::Identity |
+        SvgFilterOp::Blend => None,
+        _ => Some(data_handle),
+    };
+
+    add_filter_task(
+        op,
+        generic_int,
+        extra_data_handle,
+        color_space,
+        &inputs,
+        ctx,
+        render_tasks,
+    )
+}
+
+fn resolve_input(
+    input: FilterPrimitiveInput,
+    outputs: &[(RenderTaskId, ColorSpace)],
+    original: (RenderTaskId, ColorSpace),
+) -> (RenderTaskId, ColorSpace) {
+    match input {
+        FilterPrimitiveInput::Original => original,
+        FilterPrimitiveInput::Previous => {
+            outputs.last().cloned().unwrap_or(original)
+        }
+        // References to the primitive itself or later ones
+        // are treated like `Previous`.
+        FilterPrimitiveInput::OutputOfPrimitiveIndex(index) => {
+            outputs.get(index).or(outputs.last()).cloned().unwrap_or(original)
+        }
+    }
+}
+
+fn add_filter_task(
+    op: SvgFilterOp,
+    generic_int: i32,
+    extra_data_handle: Option<GpuCacheHandle>,
+    color_space: ColorSpace,
+    inputs: &[(RenderTaskId, ColorSpace)],
+    ctx: &SvgFilterContext,
+    render_tasks: &mut RenderTaskTree,
+) -> RenderTaskId {
+    let mut flags = match color_space {
+        ColorSpace::Srgb => 0,
+        ColorSpace::LinearRgb => SVG_FILTER_LINEAR_RGB,
+    };
+
+    for (i, &(task_id, input_color_space)) in inputs.iter().enumerate() {
+        if input_color_space != color_space {
+            flags |= [SVG_FILTER_CONVERT_INPUT_1, SVG_FILTER_CONVERT_INPUT_2][i];
+        }
+        // Inputs may be from any earlier pass.
+        render_tasks[task_id].mark_for_saving();
+    }
+
+    let task = RenderTask::new_svg_filter(
+        SvgFilterInfo {
+            op,
+            generic_int,
+            flags,
+            extra_data_handle,
+        },
+        inputs.iter().map(|&(task_id, _)| task_id).collect(),
+        ctx.task_size,
+        ctx.uv_rect_kind,
+        ctx.origin,
+        ctx.device_pixel_scale,
+    );
+
+    render_tasks.add(task)
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Evaluates a transfer function for all 256 channel values.
+fn transfer_table(function: &TransferFunction) -> [f32; 256] {
+    let mut table = [0.0; 256];
+    for (i, value) in table.iter_mut().enumerate() {
+        let c = i as f32 / 255.0;
+        let result = match *function {
+            TransferFunction::Identity => c,
+            TransferFunction::Table(ref values) => match values.len() {
+                0 => c,
+                1 => values[0],
+                n => {
+                    let k = ((c * (n - 1) as f32) as usize).min(n - 2);
+                    let t = c * (n - 1) as f32 - k as f32;
+                    values[k] + t * (values[k + 1] - values[k])
+                }
+            },
+            TransferFunction::Discrete(ref values) => match values.len() {
+                0 => c,
+                n => values[((c * n as f32) as usize).min(n - 1)],
+            },
+            TransferFunction::Linear { slope, intercept } => slope * c + intercept,
+            TransferFunction::Gamma { amplitude, exponent, offset } => {
+                amplitude * c.powf(exponent) + offset
+            }
+        };
+        *value = result.max(0.0).min(1.0);
+    }
+    table
+}
+
+// Park and Miller's minimal standard generator, as used by the
+// reference turbulence implementation in the SVG specification.
+fn turbulence_random(seed: i32) -> i32 {
+    const RAND_M: i32 = 2147483647;
+    const RAND_A: i32 = 16807;
+    const RAND_Q: i32 = 127773;
+    const RAND_R: i32 = 2836;
+
+    let result = RAND_A * (seed % RAND_Q) - RAND_R * (seed / RAND_Q);
+    if result <= 0 {
+        result + RAND_M
+    } else {
+        result
+    }
+}
+
+// Writes the lattice selector and gradients for a seed, following
+// the `init` function of the reference implementation.
+fn write_turbulence_tables(seed: i32, request: &mut GpuDataRequest) {
+    let mut seed = if seed <= 0 {
+        -(seed % (i32::max_value() - 1)) + 1
+    } else {
+        seed
+    };
+    if seed > i32::max_value() - 1 {
+        seed = i32::max_value() - 1;
+    }
+
+    let mut lattice = [0usize; 2 * TURBULENCE_SIZE];
+    let mut gradients = [[[0.0f32; 2]; TURBULENCE_SIZE]; 4];
+
+    for channel in gradients.iter_mut() {
+        for (i, gradient) in channel.iter_mut().enumerate() {
+            lattice[i] = i;
+            for value in gradient.iter_mut() {
+                seed = turbulence_random(seed);
+                *value = ((seed % (2 * TURBULENCE_SIZE as i32)) - TURBULENCE_SIZE as i32) as f32 /
+                    TURBULENCE_SIZE as f32;
+            }
+            let length = (gradient[0] * gradient[0] + gradient[1] * gradient[1]).sqrt();
+            if length > 0.0 {
+                gradient[0] /= length;
+                gradient[1] /= length;
+            }
+        }
+    }
+
+    for i in (1 .. TURBULENCE_SIZE).rev() {
+        seed = turbulence_random(seed);
+        let j = (seed % TURBULENCE_SIZE as i32) as usize;
+        lattice.swap(i, j);
+    }
+    for i in 0 .. TURBULENCE_SIZE {
+        lattice[TURBULENCE_SIZE + i] = lattice[i];
+    }
+
+    for block in lattice.chunks(4) {
+        request.push([block[0] as f32, block[1] as f32, block[2] as f32, block[3] as f32]);
+    }
+    for channel in gradients.iter() {
+        for pair in channel.chunks(2) {
+            request.push([pair[0][0], pair[0][1], pair[1][0], pair[1][1]]);
+        }
+    }
+}
@@ -232,6 +232,9 @@ pub struct TextureCache {
     // Maximum texture size supported by hardware.
     max_texture_size: u32,
 
+    // Maximum number of texture array layers supported by hardware.
+    max_texture_layers: usize,
+
     // A list of texture IDs that represent native
     // texture handles. This indirection allows the texture
     // cache to create / destroy / reuse texture handles
@@ -262,23 +265,24 @@ pub struct TextureCache {
 }
 
 impl TextureCache {
-    pub fn new(max_texture_size: u32) -> Self {
+    pub fn new(max_texture_size: u32, max_texture_layers: usize) -> Self {
         TextureCache {
             max_texture_size,
+            max_texture_layers,
             array_a8_linear: TextureArray::new(
                 ImageFormat::R8,
                 TextureFilter::Linear,
-                TEXTURE_ARRAY_LAYERS_LINEAR,
+                cmp::min(TEXTURE_ARRAY_LAYERS_LINEAR, max_texture_layers),
             ),
             array_rgba8_linear: TextureArray::new(
                 ImageFormat::BGRA8,
                 TextureFilter::Linear,
-                TEXTURE_ARRAY_LAYERS_LINEAR,
+                cmp::min(TEXTURE_ARRAY_LAYERS_LINEAR, max_texture_layers),
             ),
             array_rgba8_nearest: TextureArray::new(
                 ImageFormat::BGRA8,
                 TextureFilter::Nearest,
-                TEXTURE_ARRAY_LAYERS_NEAREST,
+                cmp::min(TEXTURE_ARRAY_LAYERS_NEAREST, max_texture_layers),
             ),
             cache_textures: CacheTextureIdList::new(),
             pending_updates: TextureUpdateList::new(),
@@ -384,6 +388,10 @@ impl TextureCache {
         self.max_texture_size
     }
 
+    pub fn max_texture_layers(&self) -> usize {
+        self.max_texture_layers
+    }
+
     pub fn pending_updates(&mut self) -> TextureUpdateList {
         mem::replace(&mut self.pending_updates, TextureUpdateList::new())
     }
@@ -490,6 +498,9 @@ impl TextureCache {
             (ImageFormat::BGRA8, TextureFilter::Linear) => &mut self.array_rgba8_linear,
             (ImageFormat::BGRA8, TextureFilter::Nearest) => &mut self.array_rgba8_nearest,
             (ImageFormat::RGBAF32, _) |
+            (ImageFormat::RGBAI32, _) |
+            (ImageFormat::RGBA8, _) |
+            (ImageFormat::R16, _) |
             (ImageFormat::RG8, _) |
             (ImageFormat::R8, TextureFilter::Nearest) |
             (ImageFormat::R8, TextureFilter::Trilinear) |
@@ -715,6 +726,9 @@ impl TextureCache {
             (ImageFormat::BGRA8, TextureFilter::Linear) => &mut self.array_rgba8_linear,
             (ImageFormat::BGRA8, TextureFilter::Nearest) => &mut self.array_rgba8_nearest,
             (ImageFormat::RGBAF32, _) |
+            (ImageFormat::RGBAI32, _) |
+            (ImageFormat::RGBA8, _) |
+            (ImageFormat::R16, _) |
             (ImageFormat::R8, TextureFilter::Nearest) |
             (ImageFormat::R8, TextureFilter::Trilinear) |
             (ImageFormat::BGRA8, TextureFilter::Trilinear) |
@@ -739,7 +753,7 @@ impl TextureCache {
                     //           texture, and then we can remove this
                     //           memory allocation (same for the other
                     //           standalone texture below).
-                    render_target: Some(RenderTargetInfo { has_depth: false }),
+                    render_target: Some(RenderTargetInfo { has_depth: false, samples: 1 }),
                     layer_count: texture_array.layer_count as i32,
                 },
             };
@@ -775,6 +789,13 @@ impl TextureCache {
             allowed_in_shared_cache = false;
         }
 
+        // The shared texture arrays only exist for R8 and BGRA8, any other
+        // format (e.g. RGBA8, or R16 YUV planes) gets a standalone texture.
+        match descriptor.format {
+            ImageFormat::R8 | ImageFormat::BGRA8 => {}
+            _ => allowed_in_shared_cache = false,
+        }
+
         // Anything larger than TEXTURE_REGION_DIMENSIONS goes in a standalone texture.
         // TODO(gw): If we find pages that suffer from batch breaks in this
         //           case, add support for storing these in a standalone
@@ -847,7 +868,7 @@ impl TextureCache {
                     height: descriptor.size.height,
                     format: descriptor.format,
                     filter,
-                    render_target: Some(RenderTargetInfo { has_depth: false }),
+                    render_target: Some(RenderTargetInfo { has_depth: false, samples: 1 }),
                     layer_count: 1,
                 },
             };
@@ -4,16 +4,16 @@
 
 use api::{ColorF, DeviceIntPoint, DeviceIntRect, DeviceIntSize, DevicePixelScale, DeviceUintPoint};
 use api::{DeviceUintRect, DeviceUintSize, DocumentLayer, FilterOp, ImageFormat, LayoutRect};
-use api::{MixBlendMode, PipelineId};
-use batch::{AlphaBatchBuilder, AlphaBatchContainer, ClipBatcher, resolve_image};
+use api::{FilterPrimitive, MixBlendMode, PipelineId};
+use batch::{AlphaBatchBuilder, AlphaBatchContainer, BatchTextures, ClipBatcher, resolve_image};
 use clip::{ClipStore};
 use clip_scroll_tree::{ClipScrollTree, ClipScrollNodeIndex};
 use device::{FrameId, Texture};
 #[cfg(feature = "pathfinder")]
 use euclid::{TypedPoint2D, TypedVector2D};
-use gpu_cache::{GpuCache};
-use gpu_types::{BorderInstance, BlurDirection, BlurInstance};
-use gpu_types::{ClipScrollNodeData, ZBufferIdGenerator};
+use gpu_cache::{GpuCache, GpuCacheAddress};
+use gpu_types::{BorderInstance, BlurDirection, BlurInstance, PathInstance};
+use gpu_types::{ClipScrollNodeData, SvgFilterInstance, ZBufferIdGenerator};
 use internal_types::{FastHashMap, SavedTargetIndex, SourceTexture};
 #[cfg(feature = "pathfinder")]
 use pathfinder_partitioner::mesh::Mesh;
@@ -45,6 +45,7 @@ pub struct RenderTargetIndex(pub usize);
 pub struct RenderTargetContext<'a, 'rc> {
     pub device_pixel_scale: DevicePixelScale,
     pub prim_store: &'a PrimitiveStore,
+    pub clip_store: &'a ClipStore,
     pub resource_cache: &'rc mut ResourceCache,
     pub clip_scroll_tree: &'a ClipScrollTree,
     pub use_dual_source_blending: bool,
@@ -123,6 +124,11 @@ pub trait RenderTarget {
     );
     fn used_rect(&self) -> DeviceIntRect;
     fn needs_depth(&self) -> bool;
+    /// Whether the target can be drawn with multisampling. Only draw calls
+    /// are resolved, so anything copied into the target would be lost.
+    fn supports_multisampling(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -222,6 +228,10 @@ impl<T: RenderTarget> RenderTargetList<T> {
         self.targets.iter().any(|target| target.needs_depth())
     }
 
+    pub fn supports_multisampling(&self) -> bool {
+        self.targets.iter().all(|target| target.supports_multisampling())
+    }
+
     pub fn check_ready(&self, t: &Texture) {
         assert_eq!(t.get_dimensions(), self.max_size);
         assert_eq!(t.get_format(), self.format);
@@ -294,6 +304,8 @@ pub struct ColorRenderTarget {
     pub readbacks: Vec<DeviceIntRect>,
     pub scalings: Vec<ScalingInfo>,
     pub blits: Vec<BlitJob>,
+    // List of SVG filter primitives, grouped by their input textures.
+    pub svg_filters: Vec<(BatchTextures, Vec<SvgFilterInstance>)>,
     // List of frame buffer outputs for this render target.
     pub outputs: Vec<FrameOutput>,
     allocator: Option<TextureAllocator>,
@@ -320,6 +332,7 @@ impl RenderTarget for ColorRenderTarget {
             readbacks: Vec::new(),
             scalings: Vec::new(),
             blits: Vec::new(),
+            svg_filters: Vec::new(),
             allocator: size.map(TextureAllocator::new),
             outputs: Vec::new(),
             alpha_tasks: Vec::new(),
@@ -433,6 +446,7 @@ impl RenderTarget for ColorRenderTarget {
             }
             RenderTaskKind::ClipRegion(..) |
             RenderTaskKind::Border(..) |
+            RenderTaskKind::Path(..) |
             RenderTaskKind::CacheMask(..) => {
                 panic!("Should not be added to color target!");
             }
@@ -443,6 +457,41 @@ impl RenderTarget for ColorRenderTarget {
             RenderTaskKind::Readback(device_rect) => {
                 self.readbacks.push(device_rect);
             }
+            RenderTaskKind::SvgFilter(ref task_info) => {
+                // The inputs were marked for saving, so they are read
+                // from their saved targets rather than the previous pass.
+                let mut textures = BatchTextures::no_texture();
+                // Unused inputs are never sampled by the shader.
+                let mut input_task_addresses = [RenderTaskAddress(0); 2];
+                for (i, &input_id) in task.children.iter().enumerate() {
+                    let saved_index = render_tasks[input_id]
+                        .saved_index
+                        .expect("bug: svg filter input must be saved");
+                    textures.colors[i] = SourceTexture::RenderTaskCache(saved_index);
+                    input_task_addresses[i] = render_tasks.get_task_address(input_id);
+                }
+
+                let instance = SvgFilterInstance {
+                    task_address: render_tasks.get_task_address(task_id),
+                    input_1_task_address: input_task_addresses[0],
+                    input_2_task_address: input_task_addresses[1],
+                    kind: task_info.info.op as i32,
+                    generic_int: task_info.info.generic_int,
+                    flags: task_info.info.flags,
+                    extra_data_address: task_info.info.extra_data_handle
+                        .map_or(GpuCacheAddress::invalid(), |handle| gpu_cache.get_address(&handle)),
+                };
+
+                match self.svg_filters.last_mut() {
+                    Some(&mut (ref last_textures, ref mut instances))
+                        if last_textures.colors == textures.colors => {
+                        instances.push(instance);
+                        return;
+                    }
+                    _ => {}
+                }
+                self.svg_filters.push((textures, vec![instance]));
+            }
             RenderTaskKind::Scaling(..) => {
                 self.scalings.push(ScalingInfo {
                     src_task_id: task.children[0],
@@ -507,6 +556,10 @@ impl RenderTarget for ColorRenderTarget {
             !ab.opaque_batches.is_empty()
         })
     }
+
+    fn supports_multisampling(&self) -> bool {
+        self.blits.is_empty() && self.scalings.is_empty() && self.readbacks.is_empty()
+    }
 }
 
 #[cfg_attr(feature = "capture", derive(Serialize))]
@@ -566,6 +619,8 @@ impl RenderTarget for AlphaRenderTarget {
             RenderTaskKind::Picture(..) |
             RenderTaskKind::Blit(..) |
             RenderTaskKind::Border(..) |
+            RenderTaskKind::Path(..) |
+            RenderTaskKind::SvgFilter(..) |
             RenderTaskKind::Glyph(..) => {
                 panic!("BUG: should not be added to alpha target!");
             }
@@ -629,6 +684,7 @@ pub struct TextureCacheRenderTarget {
     pub blits: Vec<BlitJob>,
     pub glyphs: Vec<GlyphJob>,
     pub border_segments: Vec<BorderInstance>,
+    pub paths: Vec<PathInstance>,
     pub clears: Vec<DeviceIntRect>,
 }
 
@@ -640,6 +696,7 @@ impl TextureCacheRenderTarget {
             blits: vec![],
             glyphs: vec![],
             border_segments: vec![],
+            paths: vec![],
             clears: vec![],
         }
     }
@@ -697,6 +754,14 @@ impl TextureCacheRenderTarget {
 
                 self.border_segments.extend(instances);
             }
+            RenderTaskKind::Path(ref task_info) => {
+                self.clears.push(target_rect.0);
+
+                self.paths.push(PathInstance {
+                    task_origin: target_rect.0.origin.to_f32(),
+                    ..task_info.instance
+                });
+            }
             RenderTaskKind::Glyph(ref mut task_info) => {
                 self.add_glyph_task(task_info, target_rect.0)
             }
@@ -705,6 +770,7 @@ impl TextureCacheRenderTarget {
             RenderTaskKind::ClipRegion(..) |
             RenderTaskKind::CacheMask(..) |
             RenderTaskKind::Readback(..) |
+            RenderTaskKind::SvgFilter(..) |
             RenderTaskKind::Scaling(..) => {
                 panic!("BUG: unexpected task kind for texture cache target");
             }
@@ -926,18 +992,28 @@ pub struct CompositeOps {
 
     // Requires two source textures (e.g. mix-blend-mode)
     pub mix_blend_mode: Option<MixBlendMode>,
+
+    // An SVG filter graph, drawn to a single picture
+    pub filter_primitives: Vec<FilterPrimitive>,
 }
 
 impl CompositeOps {
-    pub fn new(filters: Vec<FilterOp>, mix_blend_mode: Option<MixBlendMode>) -> Self {
+    pub fn new(
+        filters: Vec<FilterOp>,
+        mix_blend_mode: Option<MixBlendMode>,
+        filter_primitives: Vec<FilterPrimitive>,
+    ) -> Self {
         CompositeOps {
             filters,
             mix_blend_mode,
+            filter_primitives,
         }
     }
 
     pub fn count(&self) -> usize {
-        self.filters.len() + if self.mix_blend_mode.is_some() { 1 } else { 0 }
+        self.filters.len() +
+            if self.mix_blend_mode.is_some() { 1 } else { 0 } +
+            if self.filter_primitives.is_empty() { 0 } else { 1 }
     }
 }
 
@@ -68,6 +68,32 @@ pub struct PrimitiveInstance {
     pub aData1: [i32; 4],
 }
 
+#[derive(Debug, Clone, Copy)]
+#[allow(non_snake_case)]
+pub struct PathInstance {
+    pub aData0: [i32; 4],
+    pub aData1: [i32; 4],
+    pub aTaskOrigin: [f32; 2],
+    pub aRect: [f32; 4],
+    pub aLocalSize: [f32; 2],
+    pub aPathDataAddress: i32,
+    pub aPathStopsAddress: i32,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[allow(non_snake_case)]
+pub struct SvgFilterInstance {
+    pub aData0: [i32; 4],
+    pub aData1: [i32; 4],
+    pub aFilterRenderTaskAddress: i32,
+    pub aFilterInput1TaskAddress: i32,
+    pub aFilterInput2TaskAddress: i32,
+    pub aFilterKind: i32,
+    pub aFilterGenericInt: i32,
+    pub aFilterFlags: i32,
+    pub aFilterExtraDataAddress: i32,
+}
+
 #[derive(Debug, Clone, Copy)]
 #[allow(non_snake_case)]
 pub struct Vertex {
@@ -40,6 +40,10 @@ const SHADERS: &[Shader] = &[
         name: "cs_clip_line",
         features: CLIP_FEATURES,
     },
+    Shader {
+        name: "cs_clip_path",
+        features: CLIP_FEATURES,
+    },
     // Cache shaders
     Shader {
         name: "cs_blur",
@@ -49,6 +53,14 @@ const SHADERS: &[Shader] = &[
         name: "cs_border_segment",
         features: CACHE_FEATURES,
     },
+    Shader {
+        name: "cs_path",
+        features: CACHE_FEATURES,
+    },
+    Shader {
+        name: "cs_svg_filter",
+        features: CACHE_FEATURES,
+    },
     // Prim shaders
     Shader {
         name: "ps_split_composite",
@@ -83,6 +95,10 @@ const SHADERS: &[Shader] = &[
         name: "brush_radial_gradient",
         features: &[ "DITHERING" ],
     },
+    Shader {
+        name: "brush_conic_gradient",
+        features: &[ "DITHERING" ],
+    },
     Shader {
         name: "brush_linear_gradient",
         features: &[],
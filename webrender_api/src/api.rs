@@ -12,10 +12,10 @@ use std::marker::PhantomData;
 use std::path::PathBuf;
 use std::u32;
 use {BuiltDisplayList, BuiltDisplayListDescriptor, ColorF, DeviceIntPoint, DeviceUintRect};
-use {DeviceUintSize, ExternalScrollId, FontInstanceKey, FontInstanceOptions};
-use {FontInstancePlatformOptions, FontKey, FontVariation, GlyphDimensions, GlyphIndex, ImageData};
-use {ImageDescriptor, ImageKey, ItemTag, LayoutPoint, LayoutSize, LayoutTransform, LayoutVector2D};
-use {NativeFontHandle, WorldPoint};
+use {DeviceUintSize, DisplayListDeltaOp, ExternalScrollId, FontInstanceKey, FontInstanceOptions};
+use {FontInstancePlatformOptions, FontKey, FontVariation, FontVariationBinding, GlyphDimensions};
+use {GlyphIndex, ImageData, ImageDescriptor, ImageKey, ItemTag, LayoutPoint, LayoutSize};
+use {LayoutTransform, LayoutVector2D, NativeFontHandle, WorldPoint};
 
 pub type TileSize = u16;
 /// Documents are rendered in the ascending order of their associated layer values.
@@ -163,6 +163,47 @@ impl Transaction {
         self.payloads.push(Payload { epoch, pipeline_id, display_list_data });
     }
 
+    /// Supplies a new frame to WebRender by splicing keyed ranges into the display
+    /// list of a previous epoch, so that only the changed ranges are sent.
+    ///
+    /// The delta display list only needs to contain the keyed ranges referred to by
+    /// `ops`, and should be built with `DisplayListBuilder::new_delta`. If the current
+    /// display list of the pipeline isn't the one of `base_epoch` the update is dropped.
+    ///
+    /// This only reduces the amount of data sent: the spliced display list is
+    /// flattened as a whole, just like one passed to `set_display_list`.
+    ///
+    /// Arguments are the same as for `set_display_list`, plus:
+    ///
+    /// * `base_epoch`: The epoch of the display list the delta applies to.
+    /// * `ops`: The edits to apply to the base display list, in order.
+    pub fn splice_display_list(
+        &mut self,
+        epoch: Epoch,
+        base_epoch: Epoch,
+        background: Option<ColorF>,
+        viewport_size: LayoutSize,
+        (pipeline_id, content_size, delta): (PipelineId, LayoutSize, BuiltDisplayList),
+        ops: Vec<DisplayListDeltaOp>,
+        preserve_frame_state: bool,
+    ) {
+        let (display_list_data, list_descriptor) = delta.into_data();
+        self.scene_ops.push(
+            SceneMsg::SpliceDisplayList {
+                epoch,
+                base_epoch,
+                pipeline_id,
+                background,
+                viewport_size,
+                content_size,
+                list_descriptor,
+                ops,
+                preserve_frame_state,
+            }
+        );
+        self.payloads.push(Payload { epoch, pipeline_id, display_list_data });
+    }
+
     pub fn update_resources(&mut self, resources: Vec<ResourceUpdate>) {
         self.merge(resources);
     }
@@ -316,6 +357,29 @@ impl Transaction {
         options: Option<FontInstanceOptions>,
         platform_options: Option<FontInstancePlatformOptions>,
         variations: Vec<FontVariation>,
+    ) {
+        self.add_font_instance_with_animation(
+            key,
+            font_key,
+            glyph_size,
+            options,
+            platform_options,
+            variations,
+            Vec::new(),
+        );
+    }
+
+    /// Adds a font instance whose variation axes can be animated through the
+    /// float properties of `update_dynamic_properties`, using the given bindings.
+    pub fn add_font_instance_with_animation(
+        &mut self,
+        key: FontInstanceKey,
+        font_key: FontKey,
+        glyph_size: Au,
+        options: Option<FontInstanceOptions>,
+        platform_options: Option<FontInstancePlatformOptions>,
+        variations: Vec<FontVariation>,
+        variation_bindings: Vec<FontVariationBinding>,
     ) {
         self.resource_updates
             .push(ResourceUpdate::AddFontInstance(AddFontInstance {
@@ -325,6 +389,7 @@ impl Transaction {
                 options,
                 platform_options,
                 variations,
+                variation_bindings,
             }));
     }
 
@@ -446,6 +511,7 @@ pub struct AddFontInstance {
     pub options: Option<FontInstanceOptions>,
     pub platform_options: Option<FontInstancePlatformOptions>,
     pub variations: Vec<FontVariation>,
+    pub variation_bindings: Vec<FontVariationBinding>,
 }
 
 // Frame messages affect building the scene.
@@ -465,6 +531,17 @@ pub enum SceneMsg {
         content_size: LayoutSize,
         preserve_frame_state: bool,
     },
+    SpliceDisplayList {
+        list_descriptor: BuiltDisplayListDescriptor,
+        epoch: Epoch,
+        base_epoch: Epoch,
+        pipeline_id: PipelineId,
+        background: Option<ColorF>,
+        viewport_size: LayoutSize,
+        content_size: LayoutSize,
+        ops: Vec<DisplayListDeltaOp>,
+        preserve_frame_state: bool,
+    },
     SetWindowParameters {
         window_size: DeviceUintSize,
         inner_rect: DeviceUintRect,
@@ -491,6 +568,7 @@ impl fmt::Debug for SceneMsg {
         f.write_str(match *self {
             SceneMsg::UpdateEpoch(..) => "SceneMsg::UpdateEpoch",
             SceneMsg::SetDisplayList { .. } => "SceneMsg::SetDisplayList",
+            SceneMsg::SpliceDisplayList { .. } => "SceneMsg::SpliceDisplayList",
             SceneMsg::SetPageZoom(..) => "SceneMsg::SetPageZoom",
             SceneMsg::SetPinchZoom(..) => "SceneMsg::SetPinchZoom",
             SceneMsg::RemovePipeline(..) => "SceneMsg::RemovePipeline",
@@ -1026,7 +1104,7 @@ impl ZoomFactor {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, Eq, Hash)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, Eq, Hash, Ord, PartialOrd)]
 pub struct PropertyBindingId {
     namespace: IdNamespace,
     uid: u32,
@@ -1098,6 +1176,8 @@ pub struct PropertyValue<T> {
 pub struct DynamicProperties {
     pub transforms: Vec<PropertyValue<LayoutTransform>>,
     pub floats: Vec<PropertyValue<f32>>,
+    pub colors: Vec<PropertyValue<ColorF>>,
+    pub scroll_offsets: Vec<PropertyValue<LayoutVector2D>>,
 }
 
 pub trait RenderNotifier: Send {
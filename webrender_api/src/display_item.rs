@@ -93,18 +93,22 @@ pub type LayoutPrimitiveInfo = PrimitiveInfo<LayoutPixel>;
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub enum SpecificDisplayItem {
     Clip(ClipDisplayItem),
+    ClipPath(ClipPathDisplayItem),
     ScrollFrame(ScrollFrameDisplayItem),
     StickyFrame(StickyFrameDisplayItem),
     Rectangle(RectangleDisplayItem),
     ClearRectangle,
     Line(LineDisplayItem),
+    Path(PathDisplayItem),
     Text(TextDisplayItem),
+    TextDecoration(TextDecorationDisplayItem),
     Image(ImageDisplayItem),
     YuvImage(YuvImageDisplayItem),
     Border(BorderDisplayItem),
     BoxShadow(BoxShadowDisplayItem),
     Gradient(GradientDisplayItem),
     RadialGradient(RadialGradientDisplayItem),
+    ConicGradient(ConicGradientDisplayItem),
     ClipChain(ClipChainItem),
     Iframe(IframeDisplayItem),
     PushStackingContext(PushStackingContextDisplayItem),
@@ -114,6 +118,8 @@ pub enum SpecificDisplayItem {
     SetGradientStops,
     PushShadow(Shadow),
     PopAllShadows,
+    StartKeyedRange(DisplayItemKey),
+    EndKeyedRange,
 }
 
 /// This is a "complete" version of the DI specifics,
@@ -124,27 +130,40 @@ pub enum SpecificDisplayItem {
 #[cfg_attr(feature = "deserialize", derive(Deserialize))]
 pub enum CompletelySpecificDisplayItem {
     Clip(ClipDisplayItem, Vec<ComplexClipRegion>),
+    ClipPath(ClipPathDisplayItem, Vec<PathCommand>),
     ClipChain(ClipChainItem, Vec<ClipId>),
     ScrollFrame(ScrollFrameDisplayItem, Vec<ComplexClipRegion>),
     StickyFrame(StickyFrameDisplayItem),
     Rectangle(RectangleDisplayItem),
     ClearRectangle,
     Line(LineDisplayItem),
+    Path(PathDisplayItem, Vec<PathCommand>, Vec<f32>),
     Text(TextDisplayItem, Vec<GlyphInstance>),
+    TextDecoration(TextDecorationDisplayItem),
     Image(ImageDisplayItem),
     YuvImage(YuvImageDisplayItem),
     Border(BorderDisplayItem),
     BoxShadow(BoxShadowDisplayItem),
     Gradient(GradientDisplayItem),
     RadialGradient(RadialGradientDisplayItem),
+    ConicGradient(ConicGradientDisplayItem),
     Iframe(IframeDisplayItem),
-    PushStackingContext(PushStackingContextDisplayItem, Vec<FilterOp>),
+    PushStackingContext(
+        PushStackingContextDisplayItem,
+        Vec<FilterOp>,
+        Vec<FilterOp>,
+        Vec<FilterPrimitive>,
+        Vec<MaskLayer>,
+        Vec<GradientStop>,
+    ),
     PopStackingContext,
     PushReferenceFrame(PushReferenceFrameDisplayListItem),
     PopReferenceFrame,
     SetGradientStops(Vec<GradientStop>),
     PushShadow(Shadow),
     PopAllShadows,
+    StartKeyedRange(DisplayItemKey),
+    EndKeyedRange,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
@@ -153,6 +172,80 @@ pub struct ClipDisplayItem {
     pub image_mask: Option<ImageMask>,
 }
 
+/// Clips to the inside of a vector path, as well as to the clip rect
+/// of the item.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ClipPathDisplayItem {
+    pub id: ClipId,
+    pub fill_rule: FillRule,
+} // IMPLICIT: path: Vec<PathCommand>
+
+/// A command of a vector path. Subpaths are implicitly closed when
+/// they are filled.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum PathCommand {
+    MoveTo(LayoutPoint),
+    LineTo(LayoutPoint),
+    /// A quadratic curve with a control point and an end point.
+    QuadraticCurveTo(LayoutPoint, LayoutPoint),
+    /// A cubic curve with two control points and an end point.
+    CubicCurveTo(LayoutPoint, LayoutPoint, LayoutPoint),
+    Close,
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum FillRule {
+    Nonzero = 0,
+    EvenOdd = 1,
+}
+
+/// Fills or strokes a vector path. Dashes only apply to strokes.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PathDisplayItem {
+    pub fill_rule: FillRule,
+    /// Strokes the outline of the path instead of filling it.
+    pub stroke: Option<StrokeStyle>,
+    pub paint: PathPaint,
+} // IMPLICIT: path: Vec<PathCommand>, dashes: Vec<f32>
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct StrokeStyle {
+    pub width: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    /// The maximum ratio of a miter join length to the stroke width,
+    /// beyond which the join is beveled.
+    pub miter_limit: f32,
+    /// The distance into the dash pattern at which the stroke starts.
+    pub dash_offset: f32,
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum LineJoin {
+    Miter = 0,
+    Round = 1,
+    Bevel = 2,
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum LineCap {
+    Butt = 0,
+    Round = 1,
+    Square = 2,
+}
+
+/// What a path is filled or stroked with. The stops of a gradient
+/// paint are given by the preceding `SetGradientStops` item, and its
+/// points are in the same space as the path.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum PathPaint {
+    Color(ColorF),
+    Gradient(Gradient),
+}
+
 /// The minimum and maximum allowable offset for a sticky frame in a single dimension.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
@@ -216,11 +309,14 @@ pub struct ScrollFrameDisplayItem {
     pub external_id: Option<ExternalScrollId>,
     pub image_mask: Option<ImageMask>,
     pub scroll_sensitivity: ScrollSensitivity,
+    /// When present, the scroll position of the frame is taken from
+    /// this property on every frame, in place of the API scroll offset.
+    pub scroll_offset: Option<PropertyBinding<LayoutVector2D>>,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct RectangleDisplayItem {
-    pub color: ColorF,
+    pub color: PropertyBinding<ColorF>,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
@@ -254,6 +350,52 @@ pub struct TextDisplayItem {
     pub glyph_options: Option<GlyphOptions>,
 } // IMPLICIT: glyphs: Vec<GlyphInstance>
 
+/// A line or emphasis marks decorating the glyphs of the text item pushed
+/// right before it, positioned and sized from the font metrics.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TextDecorationDisplayItem {
+    pub kind: TextDecorationKind,
+    pub color: ColorF,
+    /// Overrides the line thickness given by the font metrics.
+    pub thickness: Option<f32>,
+    /// Leaves gaps in the line where it crosses the glyphs.
+    pub skip_ink: bool,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum TextDecorationKind {
+    Underline,
+    Overline,
+    LineThrough,
+    /// A mark over or under each glyph with ink.
+    Emphasis(EmphasisMark),
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct EmphasisMark {
+    pub shape: EmphasisMarkShape,
+    /// Draws the outline of the shape instead of filling it.
+    pub open: bool,
+    pub position: EmphasisMarkPosition,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum EmphasisMarkShape {
+    Dot,
+    Circle,
+    DoubleCircle,
+    Triangle,
+    Sesame,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum EmphasisMarkPosition {
+    Over,
+    Under,
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct NormalBorder {
     pub left: BorderSide,
@@ -263,12 +405,21 @@ pub struct NormalBorder {
     pub radius: BorderRadius,
 }
 
+/// How the edge and center parts of a 9-part image fill their area of the
+/// border, as in CSS `border-image-repeat`.
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum RepeatMode {
+    /// The part is stretched to fill the area.
     Stretch,
+    /// The part is tiled, with a tile centered in the area and the
+    /// tiles at the ends clipped.
     Repeat,
+    /// The part is tiled and the tiles are scaled so that a whole
+    /// number of them fills the area.
     Round,
+    /// The part is tiled with as many whole tiles as fit, and the
+    /// leftover space is distributed evenly around the tiles.
     Space,
 }
 
@@ -285,10 +436,16 @@ pub struct RadialGradientBorder {
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
-/// TODO(mrobinson): Currently only images are supported, but we will
-/// eventually add support for Gradient and RadialGradient.
 pub enum NinePatchBorderSource {
     Image(ImageKey),
+    /// A linear gradient, with its points in the width x height space of the
+    /// 9-part image. The stops are the current gradient stops, as pushed by
+    /// `create_gradient`.
+    Gradient(Gradient),
+    /// A radial gradient, with its center and radius in the width x height
+    /// space of the 9-part image. The stops are the current gradient stops,
+    /// as pushed by `create_radial_gradient`.
+    RadialGradient(RadialGradient),
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
@@ -309,7 +466,8 @@ pub struct NinePatchBorder {
     /// parts of the border as given by the border widths. This can lead to
     /// stretching.
     /// Slices can be overlapping. In that case, the same pixels from the
-    /// 9-part image will show up in multiple parts of the resulting border.
+    /// 9-part image will show up in multiple parts of the resulting border,
+    /// and as in CSS, the edges between overlapping slices are empty.
     pub slice: SideOffsets2D<u32>,
 
     /// Controls whether the center of the 9 patch image is rendered or
@@ -472,6 +630,24 @@ pub struct RadialGradientDisplayItem {
     pub tile_spacing: LayoutSize,
 }
 
+/// A gradient whose stops go around the center, starting at `angle`
+/// (clockwise from the top, in radians), like CSS `conic-gradient()`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ConicGradient {
+    pub center: LayoutPoint,
+    pub angle: f32,
+    pub start_offset: f32,
+    pub end_offset: f32,
+    pub extend_mode: ExtendMode,
+} // IMPLICIT stops: Vec<GradientStop>
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ConicGradientDisplayItem {
+    pub gradient: ConicGradient,
+    pub tile_size: LayoutSize,
+    pub tile_spacing: LayoutSize,
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct PushReferenceFrameDisplayListItem {
     pub reference_frame: ReferenceFrame,
@@ -495,8 +671,21 @@ pub struct StackingContext {
     pub mix_blend_mode: MixBlendMode,
     pub clip_node_id: Option<ClipId>,
     pub glyph_raster_space: GlyphRasterSpace,
-} // IMPLICIT: filters: Vec<FilterOp>
-
+} // IMPLICIT: filters: Vec<FilterOp>, backdrop_filters: Vec<FilterOp>,
+  //           filter_primitives: Vec<FilterPrimitive>, mask_layers: Vec<MaskLayer>,
+  //           mask_stops: Vec<GradientStop>
+
+/// The effects of a stacking context that are only set through
+/// `DisplayListBuilder::push_stacking_context_with_effects`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StackingContextEffects {
+    /// Applied to what was drawn behind the stacking context, within its bounds.
+    pub backdrop_filters: Vec<FilterOp>,
+    pub filter_primitives: Vec<FilterPrimitive>,
+    pub mask_layers: Vec<MaskLayer>,
+    /// The stops of the gradient mask layers, see `MaskSource::Gradient`.
+    pub mask_stops: Vec<GradientStop>,
+}
 
 #[repr(u32)]
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -558,6 +747,117 @@ pub enum FilterOp {
     ColorMatrix([f32; 20]),
 }
 
+/// Where a filter primitive takes one of its inputs from.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum FilterPrimitiveInput {
+    /// The contents of the stacking context, `SourceGraphic` in SVG.
+    Original,
+    /// The output of the previous primitive, or the contents of the
+    /// stacking context for the first primitive.
+    Previous,
+    /// The output of an earlier primitive of the same stacking context.
+    OutputOfPrimitiveIndex(usize),
+}
+
+/// The color space a filter primitive operates in, see the SVG
+/// `color-interpolation-filters` property.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum ColorSpace {
+    Srgb = 0,
+    LinearRgb = 1,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum CompositeOperator {
+    Over,
+    In,
+    Out,
+    Atop,
+    Xor,
+    /// `k1 * i1 * i2 + k2 * i1 + k3 * i2 + k4`
+    Arithmetic([f32; 4]),
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum MorphologyOperator {
+    Erode = 0,
+    Dilate = 1,
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum TurbulenceKind {
+    FractalNoise = 0,
+    Turbulence = 1,
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum ColorChannel {
+    Red = 0,
+    Green = 1,
+    Blue = 2,
+    Alpha = 3,
+}
+
+/// A transfer function applied to one channel by a component transfer
+/// primitive, as in SVG `feFuncR` and friends.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum TransferFunction {
+    Identity,
+    Table(Vec<f32>),
+    Discrete(Vec<f32>),
+    Linear { slope: f32, intercept: f32 },
+    Gamma { amplitude: f32, exponent: f32, offset: f32 },
+}
+
+/// The operation of a filter primitive, together with its inputs. Lengths
+/// are in the local space of the stacking context.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum FilterPrimitiveKind {
+    Identity(FilterPrimitiveInput),
+    Flood(ColorF),
+    Offset(FilterPrimitiveInput, LayoutVector2D),
+    Blur(FilterPrimitiveInput, f32),
+    /// Uses the same layout as `FilterOp::ColorMatrix`.
+    ColorMatrix(FilterPrimitiveInput, [f32; 20]),
+    /// Functions for the red, green, blue and alpha channels.
+    ComponentTransfer(FilterPrimitiveInput, [TransferFunction; 4]),
+    /// Composites the first input with the second one.
+    Composite(FilterPrimitiveInput, FilterPrimitiveInput, CompositeOperator),
+    /// Blends the first input onto the second one.
+    Blend(FilterPrimitiveInput, FilterPrimitiveInput, MixBlendMode),
+    Morphology(FilterPrimitiveInput, MorphologyOperator, LayoutSize),
+    /// Perlin noise as specified by SVG `feTurbulence`. Tile stitching is
+    /// not supported.
+    Turbulence {
+        base_frequency: (f32, f32),
+        num_octaves: u32,
+        seed: i32,
+        kind: TurbulenceKind,
+    },
+    /// Displaces the pixels of `input` by the `x_channel` and `y_channel`
+    /// values of `map`.
+    DisplacementMap {
+        input: FilterPrimitiveInput,
+        map: FilterPrimitiveInput,
+        scale: f32,
+        x_channel: ColorChannel,
+        y_channel: ColorChannel,
+    },
+}
+
+/// A node of an SVG style filter graph. The primitives of a stacking
+/// context are evaluated in order, and the output of the last one is
+/// what the `filters` of the stacking context are applied to.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct FilterPrimitive {
+    pub kind: FilterPrimitiveKind,
+    pub color_space: ColorSpace,
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct IframeDisplayItem {
     pub clip_id: ClipId,
@@ -659,6 +959,51 @@ impl YuvFormat {
     }
 }
 
+/// A layer of the mask of a stacking context, as for CSS `mask-image`. The
+/// layers are listed bottom to top, and each is composited with the result
+/// of the layers below it.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct MaskLayer {
+    pub source: MaskSource,
+    /// The area the mask is painted in, outside of which the layer is
+    /// transparent. Tiles are placed from its origin.
+    pub rect: LayoutRect,
+    pub tile_size: LayoutSize,
+    pub tile_spacing: LayoutSize,
+    pub mode: MaskMode,
+    /// How the layer is combined with the layers below it. This is
+    /// ignored for the bottom layer.
+    pub composite: MaskComposite,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum MaskSource {
+    Image(ImageKey),
+    /// A linear gradient, with points relative to each tile. Its stops are
+    /// the next `stop_count` mask stops of the stacking context, after those
+    /// of the gradient layers below.
+    Gradient {
+        gradient: Gradient,
+        stop_count: usize,
+    },
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum MaskMode {
+    Alpha = 0,
+    Luminance = 1,
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum MaskComposite {
+    Add = 0,
+    Subtract = 1,
+    Intersect = 2,
+    Exclude = 3,
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ImageMask {
@@ -818,6 +1163,11 @@ impl ComplexClipRegion {
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct ClipChainId(pub u64, pub PipelineId);
 
+/// Identifies a range of items of a display list across epochs, so that a
+/// later display list can be sent as a delta against it.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct DisplayItemKey(pub u64);
+
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum ClipId {
     Clip(usize, PipelineId),
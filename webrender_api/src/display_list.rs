@@ -9,21 +9,28 @@ use serde::de::Deserializer;
 #[cfg(feature = "serialize")]
 use serde::ser::{Serializer, SerializeSeq};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::marker::PhantomData;
+use std::ops::Range;
 use std::{io, mem, ptr, slice};
 use time::precise_time_ns;
 use {AlphaType, BorderDetails, BorderDisplayItem, BorderRadius, BorderWidths, BoxShadowClipMode};
 use {BoxShadowDisplayItem, ClipAndScrollInfo, ClipChainId, ClipChainItem, ClipDisplayItem, ClipId};
-use {ColorF, ComplexClipRegion, DisplayItem, ExtendMode, ExternalScrollId, FilterOp};
+use {ClipPathDisplayItem, ColorF, ComplexClipRegion, ConicGradient, ConicGradientDisplayItem, DisplayItem};
+use {DisplayItemKey};
+use {ExtendMode, ExternalScrollId, FillRule, FilterOp, FilterPrimitive};
 use {FontInstanceKey, GlyphInstance, GlyphOptions, GlyphRasterSpace, Gradient};
 use {GradientDisplayItem, GradientStop, IframeDisplayItem, ImageDisplayItem, ImageKey, ImageMask};
 use {ImageRendering, LayoutPoint, LayoutPrimitiveInfo, LayoutRect, LayoutSize, LayoutTransform};
-use {LayoutVector2D, LineDisplayItem, LineOrientation, LineStyle, MixBlendMode, PipelineId};
-use {PropertyBinding, PushReferenceFrameDisplayListItem, PushStackingContextDisplayItem};
+use {LayoutVector2D, LineDisplayItem, LineOrientation, LineStyle, MaskLayer, MixBlendMode};
+use {PathCommand, PathDisplayItem, PathPaint, StrokeStyle};
+use {PipelineId, PropertyBinding, PushReferenceFrameDisplayListItem, PushStackingContextDisplayItem};
 use {RadialGradient, RadialGradientDisplayItem, RectangleDisplayItem, ReferenceFrame};
 use {ScrollFrameDisplayItem, ScrollSensitivity, Shadow, SpecificDisplayItem, StackingContext};
-use {StickyFrameDisplayItem, StickyOffsetBounds, TextDisplayItem, TransformStyle, YuvColorSpace};
+use {StackingContextEffects};
+use {StickyFrameDisplayItem, StickyOffsetBounds, TextDecorationDisplayItem, TextDecorationKind};
+use {TextDisplayItem, TransformStyle, YuvColorSpace};
 use {YuvData, YuvImageDisplayItem};
 
 // We don't want to push a long text-run. If a text-run is too long, split it into several parts.
@@ -81,6 +88,8 @@ pub struct BuiltDisplayListDescriptor {
     send_start_time: u64,
     /// The amount of clips ids assigned while building this display list.
     total_clip_ids: usize,
+    /// The amount of clip chain ids assigned while building this display list.
+    total_clip_chain_ids: u64,
 }
 
 pub struct BuiltDisplayListIter<'a> {
@@ -90,8 +99,14 @@ pub struct BuiltDisplayListIter<'a> {
     cur_stops: ItemRange<GradientStop>,
     cur_glyphs: ItemRange<GlyphInstance>,
     cur_filters: ItemRange<FilterOp>,
+    cur_backdrop_filters: ItemRange<FilterOp>,
+    cur_filter_primitives: ItemRange<FilterPrimitive>,
+    cur_mask_layers: ItemRange<MaskLayer>,
+    cur_mask_stops: ItemRange<GradientStop>,
     cur_clip_chain_items: ItemRange<ClipId>,
     cur_complex_clip: (ItemRange<ComplexClipRegion>, usize),
+    cur_path: ItemRange<PathCommand>,
+    cur_dashes: ItemRange<f32>,
     peeking: Peek,
 }
 
@@ -157,6 +172,221 @@ impl BuiltDisplayList {
     pub fn get<'de, T: Deserialize<'de>>(&self, range: ItemRange<T>) -> AuxIter<T> {
         AuxIter::new(&self.data[range.start .. range.start + range.length])
     }
+
+    /// Builds a new display list by applying `ops` to this one, taking the keyed
+    /// ranges they refer to from `delta`.
+    ///
+    /// Only the keyed ranges named by `ops` are taken from `delta`, the rest of the
+    /// display list is copied over as is. Since keyed ranges are balanced, and the
+    /// clip and scroll nodes they define are only used within them, this only
+    /// changes the stacking contexts and reference frames within them.
+    ///
+    /// The delta should be built with `DisplayListBuilder::new_delta` against this
+    /// display list, so that the clip ids of the two lists don't collide.
+    pub fn apply_delta(
+        &self,
+        delta: &BuiltDisplayList,
+        ops: &[DisplayListDeltaOp],
+    ) -> Result<BuiltDisplayList, DisplayListDeltaError> {
+        fn position(
+            pieces: &[(Option<DisplayItemKey>, &[u8])],
+            key: DisplayItemKey,
+        ) -> Result<usize, DisplayListDeltaError> {
+            pieces
+                .iter()
+                .position(|piece| piece.0 == Some(key))
+                .ok_or(DisplayListDeltaError::MissingKey(key))
+        }
+
+        let delta_pieces: Vec<_> = delta
+            .segments()?
+            .into_iter()
+            .map(|(key, range)| (key, &delta.data[range]))
+            .collect();
+        let mut pieces: Vec<_> = self
+            .segments()?
+            .into_iter()
+            .map(|(key, range)| (key, &self.data[range]))
+            .collect();
+
+        for op in ops {
+            match *op {
+                DisplayListDeltaOp::Replace(key) => {
+                    let index = position(&pieces, key)?;
+                    pieces[index] = delta_pieces[position(&delta_pieces, key)?];
+                }
+                DisplayListDeltaOp::Insert { key, after } => {
+                    if position(&pieces, key).is_ok() {
+                        return Err(DisplayListDeltaError::DuplicateKey(key));
+                    }
+                    let index = match after {
+                        Some(after) => position(&pieces, after)? + 1,
+                        None => 0,
+                    };
+                    pieces.insert(index, delta_pieces[position(&delta_pieces, key)?]);
+                }
+                DisplayListDeltaOp::Remove(key) => {
+                    let index = position(&pieces, key)?;
+                    pieces.remove(index);
+                }
+            }
+        }
+
+        let mut data = Vec::with_capacity(pieces.iter().map(|piece| piece.1.len()).sum());
+        for &(_, bytes) in &pieces {
+            data.extend_from_slice(bytes);
+        }
+
+        Ok(BuiltDisplayList {
+            data,
+            descriptor: BuiltDisplayListDescriptor {
+                builder_start_time: delta.descriptor.builder_start_time,
+                builder_finish_time: delta.descriptor.builder_finish_time,
+                send_start_time: delta.descriptor.send_start_time,
+                total_clip_ids: self.descriptor.total_clip_ids
+                    .max(delta.descriptor.total_clip_ids),
+                total_clip_chain_ids: self.descriptor.total_clip_chain_ids
+                    .max(delta.descriptor.total_clip_chain_ids),
+            },
+        })
+    }
+
+    /// Splits the display list into its keyed ranges and the unkeyed items
+    /// between them, as byte ranges of the data.
+    ///
+    /// Gradient stops that directly precede a keyed range belong to it, since
+    /// the stops of a gradient are pushed before the gradient itself.
+    fn segments(
+        &self,
+    ) -> Result<Vec<(Option<DisplayItemKey>, Range<usize>)>, DisplayListDeltaError> {
+        let mut segments = Vec::new();
+        let mut iter = self.iter();
+        let mut current_key = None;
+        let mut depth = 0;
+        let mut segment_start = 0;
+        let mut item_start = 0;
+        let mut stops_start = None;
+        // The keyed range each clip and scroll node was defined in, since the
+        // node goes away with the range it was defined in.
+        let mut keyed_clips = HashMap::new();
+        let mut defined = Vec::new();
+        let mut referenced = Vec::new();
+
+        loop {
+            defined.clear();
+            referenced.clear();
+            let item = match iter.next_raw() {
+                Some(item) => {
+                    let clip_and_scroll = item.clip_and_scroll();
+                    referenced.push(clip_and_scroll.scroll_node_id);
+                    referenced.extend(clip_and_scroll.clip_node_id);
+                    match *item.item() {
+                        SpecificDisplayItem::Clip(ref info) => defined.push(info.id),
+                        SpecificDisplayItem::ClipPath(ref info) => defined.push(info.id),
+                        SpecificDisplayItem::ScrollFrame(ref info) => {
+                            defined.push(info.clip_id);
+                            defined.push(info.scroll_frame_id);
+                        }
+                        SpecificDisplayItem::StickyFrame(ref info) => defined.push(info.id),
+                        SpecificDisplayItem::PushReferenceFrame(ref info) => {
+                            defined.push(info.reference_frame.id);
+                        }
+                        SpecificDisplayItem::PushStackingContext(ref info) => {
+                            referenced.extend(info.stacking_context.clip_node_id);
+                        }
+                        SpecificDisplayItem::ClipChain(ref info) => {
+                            defined.push(ClipId::ClipChain(info.id));
+                            referenced.extend(info.parent.map(ClipId::ClipChain));
+                            referenced.extend(self.get(item.clip_chain_items()));
+                        }
+                        _ => {}
+                    }
+                    *item.item()
+                }
+                None => break,
+            };
+            let item_end = self.data.len() - iter.data.len();
+
+            for id in &referenced {
+                if let Some(&key) = keyed_clips.get(id) {
+                    if current_key != Some(key) {
+                        return Err(DisplayListDeltaError::ClipOutsideRange(key));
+                    }
+                }
+            }
+            if let Some(key) = current_key {
+                for &id in &defined {
+                    keyed_clips.insert(id, key);
+                }
+            }
+
+            match item {
+                SpecificDisplayItem::StartKeyedRange(key) => {
+                    let range_start = stops_start.unwrap_or(item_start);
+                    if range_start > segment_start {
+                        segments.push((None, segment_start .. range_start));
+                    }
+                    segment_start = range_start;
+                    current_key = Some(key);
+                    depth = 0;
+                }
+                SpecificDisplayItem::EndKeyedRange => {
+                    if let Some(key) = current_key.take() {
+                        if depth != 0 {
+                            return Err(DisplayListDeltaError::UnbalancedRange(key));
+                        }
+                        segments.push((Some(key), segment_start .. item_end));
+                        segment_start = item_end;
+                    }
+                }
+                SpecificDisplayItem::PushStackingContext(..) |
+                SpecificDisplayItem::PushReferenceFrame(..) => depth += 1,
+                SpecificDisplayItem::PopStackingContext |
+                SpecificDisplayItem::PopReferenceFrame => depth -= 1,
+                _ => {}
+            }
+
+            stops_start = match item {
+                SpecificDisplayItem::SetGradientStops => Some(item_start),
+                _ => None,
+            };
+            item_start = item_end;
+        }
+
+        if segment_start < self.data.len() {
+            segments.push((None, segment_start .. self.data.len()));
+        }
+        Ok(segments)
+    }
+}
+
+/// An edit of a display list, in terms of the keyed ranges of the base display
+/// list and of the delta it is applied with.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum DisplayListDeltaOp {
+    /// Replaces the keyed range of the base with the delta range of the same key.
+    Replace(DisplayItemKey),
+    /// Inserts the keyed range of the delta after the given keyed range of the
+    /// base, or at the start of the display list if `after` is `None`.
+    Insert {
+        key: DisplayItemKey,
+        after: Option<DisplayItemKey>,
+    },
+    /// Removes the keyed range from the base.
+    Remove(DisplayItemKey),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DisplayListDeltaError {
+    /// An operation referred to a key that isn't in the display list.
+    MissingKey(DisplayItemKey),
+    /// An insertion used a key that is already in the display list.
+    DuplicateKey(DisplayItemKey),
+    /// A keyed range doesn't pop all the stacking contexts and reference frames
+    /// it pushes.
+    UnbalancedRange(DisplayItemKey),
+    /// A clip or scroll node defined in a keyed range is used outside of it.
+    ClipOutsideRange(DisplayItemKey),
 }
 
 /// Returns the byte-range the slice occupied, and the number of elements
@@ -207,8 +437,14 @@ impl<'a> BuiltDisplayListIter<'a> {
             cur_stops: ItemRange::default(),
             cur_glyphs: ItemRange::default(),
             cur_filters: ItemRange::default(),
+            cur_backdrop_filters: ItemRange::default(),
+            cur_filter_primitives: ItemRange::default(),
+            cur_mask_layers: ItemRange::default(),
+            cur_mask_stops: ItemRange::default(),
             cur_clip_chain_items: ItemRange::default(),
             cur_complex_clip: (ItemRange::default(), 0),
+            cur_path: ItemRange::default(),
+            cur_dashes: ItemRange::default(),
             peeking: Peek::NotPeeking,
         }
     }
@@ -238,11 +474,11 @@ impl<'a> BuiltDisplayListIter<'a> {
 
         loop {
             self.next_raw()?;
-            if let SetGradientStops = self.cur_item.item {
-                // SetGradientStops is a dummy item that most consumers should ignore
-                continue;
+            match self.cur_item.item {
+                // These are dummy items that most consumers should ignore
+                SetGradientStops | StartKeyedRange(_) | EndKeyedRange => continue,
+                _ => break,
             }
-            break;
         }
 
         Some(self.as_ref())
@@ -274,8 +510,19 @@ impl<'a> BuiltDisplayListIter<'a> {
             Clip(_) | ScrollFrame(_) => {
                 self.cur_complex_clip = self.skip_slice::<ComplexClipRegion>()
             }
+            ClipPath(_) => self.cur_path = self.skip_slice::<PathCommand>().0,
+            Path(_) => {
+                self.cur_path = self.skip_slice::<PathCommand>().0;
+                self.cur_dashes = self.skip_slice::<f32>().0;
+            }
             Text(_) => self.cur_glyphs = self.skip_slice::<GlyphInstance>().0,
-            PushStackingContext(_) => self.cur_filters = self.skip_slice::<FilterOp>().0,
+            PushStackingContext(_) => {
+                self.cur_filters = self.skip_slice::<FilterOp>().0;
+                self.cur_backdrop_filters = self.skip_slice::<FilterOp>().0;
+                self.cur_filter_primitives = self.skip_slice::<FilterPrimitive>().0;
+                self.cur_mask_layers = self.skip_slice::<MaskLayer>().0;
+                self.cur_mask_stops = self.skip_slice::<GradientStop>().0;
+            }
             _ => { /* do nothing */ }
         }
 
@@ -369,6 +616,14 @@ impl<'a, 'b> DisplayItemRef<'a, 'b> {
         self.iter.cur_complex_clip
     }
 
+    pub fn path(&self) -> ItemRange<PathCommand> {
+        self.iter.cur_path
+    }
+
+    pub fn dashes(&self) -> ItemRange<f32> {
+        self.iter.cur_dashes
+    }
+
     pub fn gradient_stops(&self) -> ItemRange<GradientStop> {
         self.iter.cur_stops
     }
@@ -381,6 +636,22 @@ impl<'a, 'b> DisplayItemRef<'a, 'b> {
         self.iter.cur_filters
     }
 
+    pub fn backdrop_filters(&self) -> ItemRange<FilterOp> {
+        self.iter.cur_backdrop_filters
+    }
+
+    pub fn filter_primitives(&self) -> ItemRange<FilterPrimitive> {
+        self.iter.cur_filter_primitives
+    }
+
+    pub fn mask_layers(&self) -> ItemRange<MaskLayer> {
+        self.iter.cur_mask_layers
+    }
+
+    pub fn mask_stops(&self) -> ItemRange<GradientStop> {
+        self.iter.cur_mask_stops
+    }
+
     pub fn clip_chain_items(&self) -> ItemRange<ClipId> {
         self.iter.cur_clip_chain_items
     }
@@ -454,6 +725,10 @@ impl Serialize for BuiltDisplayList {
                         v,
                         item.iter.list.get(item.iter.cur_complex_clip.0).collect()
                     ),
+                    SpecificDisplayItem::ClipPath(v) => ClipPath(
+                        v,
+                        item.iter.list.get(item.iter.cur_path).collect()
+                    ),
                     SpecificDisplayItem::ClipChain(v) => ClipChain(
                         v,
                         item.iter.list.get(item.iter.cur_clip_chain_items).collect(),
@@ -466,20 +741,31 @@ impl Serialize for BuiltDisplayList {
                     SpecificDisplayItem::Rectangle(v) => Rectangle(v),
                     SpecificDisplayItem::ClearRectangle => ClearRectangle,
                     SpecificDisplayItem::Line(v) => Line(v),
+                    SpecificDisplayItem::Path(v) => Path(
+                        v,
+                        item.iter.list.get(item.iter.cur_path).collect(),
+                        item.iter.list.get(item.iter.cur_dashes).collect(),
+                    ),
                     SpecificDisplayItem::Text(v) => Text(
                         v,
                         item.iter.list.get(item.iter.cur_glyphs).collect()
                     ),
+                    SpecificDisplayItem::TextDecoration(v) => TextDecoration(v),
                     SpecificDisplayItem::Image(v) => Image(v),
                     SpecificDisplayItem::YuvImage(v) => YuvImage(v),
                     SpecificDisplayItem::Border(v) => Border(v),
                     SpecificDisplayItem::BoxShadow(v) => BoxShadow(v),
                     SpecificDisplayItem::Gradient(v) => Gradient(v),
                     SpecificDisplayItem::RadialGradient(v) => RadialGradient(v),
+                    SpecificDisplayItem::ConicGradient(v) => ConicGradient(v),
                     SpecificDisplayItem::Iframe(v) => Iframe(v),
                     SpecificDisplayItem::PushStackingContext(v) => PushStackingContext(
                         v,
-                        item.iter.list.get(item.iter.cur_filters).collect()
+                        item.iter.list.get(item.iter.cur_filters).collect(),
+                        item.iter.list.get(item.iter.cur_backdrop_filters).collect(),
+                        item.iter.list.get(item.iter.cur_filter_primitives).collect(),
+                        item.iter.list.get(item.iter.cur_mask_layers).collect(),
+                        item.iter.list.get(item.iter.cur_mask_stops).collect()
                     ),
                     SpecificDisplayItem::PopStackingContext => PopStackingContext,
                     SpecificDisplayItem::PushReferenceFrame(v) => PushReferenceFrame(v),
@@ -489,6 +775,8 @@ impl Serialize for BuiltDisplayList {
                     ),
                     SpecificDisplayItem::PushShadow(v) => PushShadow(v),
                     SpecificDisplayItem::PopAllShadows => PopAllShadows,
+                    SpecificDisplayItem::StartKeyedRange(v) => StartKeyedRange(v),
+                    SpecificDisplayItem::EndKeyedRange => EndKeyedRange,
                 },
                 clip_and_scroll: display_item.clip_and_scroll,
                 info: display_item.info,
@@ -518,6 +806,7 @@ impl<'de> Deserialize<'de> for BuiltDisplayList {
         let mut data = Vec::new();
         let mut temp = Vec::new();
         let mut total_clip_ids = FIRST_CLIP_ID;
+        let mut total_clip_chain_ids = 0;
         for complete in list {
             let item = DisplayItem {
                 item: match complete.item {
@@ -526,7 +815,13 @@ impl<'de> Deserialize<'de> for BuiltDisplayList {
                         DisplayListBuilder::push_iter_impl(&mut temp, complex_clips);
                         SpecificDisplayItem::Clip(specific_item)
                     },
+                    ClipPath(specific_item, path) => {
+                        total_clip_ids += 1;
+                        DisplayListBuilder::push_iter_impl(&mut temp, path);
+                        SpecificDisplayItem::ClipPath(specific_item)
+                    },
                     ClipChain(specific_item, clip_chain_ids) => {
+                        total_clip_chain_ids += 1;
                         DisplayListBuilder::push_iter_impl(&mut temp, clip_chain_ids);
                         SpecificDisplayItem::ClipChain(specific_item)
                     }
@@ -542,10 +837,16 @@ impl<'de> Deserialize<'de> for BuiltDisplayList {
                     Rectangle(specific_item) => SpecificDisplayItem::Rectangle(specific_item),
                     ClearRectangle => SpecificDisplayItem::ClearRectangle,
                     Line(specific_item) => SpecificDisplayItem::Line(specific_item),
+                    Path(specific_item, path, dashes) => {
+                        DisplayListBuilder::push_iter_impl(&mut temp, path);
+                        DisplayListBuilder::push_iter_impl(&mut temp, dashes);
+                        SpecificDisplayItem::Path(specific_item)
+                    },
                     Text(specific_item, glyphs) => {
                         DisplayListBuilder::push_iter_impl(&mut temp, glyphs);
                         SpecificDisplayItem::Text(specific_item)
                     },
+                    TextDecoration(specific_item) => SpecificDisplayItem::TextDecoration(specific_item),
                     Image(specific_item) => SpecificDisplayItem::Image(specific_item),
                     YuvImage(specific_item) => SpecificDisplayItem::YuvImage(specific_item),
                     Border(specific_item) => SpecificDisplayItem::Border(specific_item),
@@ -553,12 +854,25 @@ impl<'de> Deserialize<'de> for BuiltDisplayList {
                     Gradient(specific_item) => SpecificDisplayItem::Gradient(specific_item),
                     RadialGradient(specific_item) =>
                         SpecificDisplayItem::RadialGradient(specific_item),
+                    ConicGradient(specific_item) =>
+                        SpecificDisplayItem::ConicGradient(specific_item),
                     Iframe(specific_item) => {
                         total_clip_ids += 1;
                         SpecificDisplayItem::Iframe(specific_item)
                     }
-                    PushStackingContext(specific_item, filters) => {
+                    PushStackingContext(
+                        specific_item,
+                        filters,
+                        backdrop_filters,
+                        filter_primitives,
+                        mask_layers,
+                        mask_stops,
+                    ) => {
                         DisplayListBuilder::push_iter_impl(&mut temp, filters);
+                        DisplayListBuilder::push_iter_impl(&mut temp, backdrop_filters);
+                        DisplayListBuilder::push_iter_impl(&mut temp, filter_primitives);
+                        DisplayListBuilder::push_iter_impl(&mut temp, mask_layers);
+                        DisplayListBuilder::push_iter_impl(&mut temp, mask_stops);
                         SpecificDisplayItem::PushStackingContext(specific_item)
                     },
                     PopStackingContext => SpecificDisplayItem::PopStackingContext,
@@ -573,6 +887,8 @@ impl<'de> Deserialize<'de> for BuiltDisplayList {
                     },
                     PushShadow(specific_item) => SpecificDisplayItem::PushShadow(specific_item),
                     PopAllShadows => SpecificDisplayItem::PopAllShadows,
+                    StartKeyedRange(key) => SpecificDisplayItem::StartKeyedRange(key),
+                    EndKeyedRange => SpecificDisplayItem::EndKeyedRange,
                 },
                 clip_and_scroll: complete.clip_and_scroll,
                 info: complete.info,
@@ -589,6 +905,7 @@ impl<'de> Deserialize<'de> for BuiltDisplayList {
                 builder_finish_time: 1,
                 send_start_time: 0,
                 total_clip_ids,
+                total_clip_chain_ids,
             },
         })
     }
@@ -815,6 +1132,7 @@ pub struct SaveState {
     clip_stack_len: usize,
     next_clip_id: usize,
     next_clip_chain_id: u64,
+    keyed_range: Option<DisplayItemKey>,
 }
 
 #[derive(Clone)]
@@ -830,6 +1148,9 @@ pub struct DisplayListBuilder {
     /// outside the bounds of the display list items themselves.
     content_size: LayoutSize,
     save_state: Option<SaveState>,
+
+    /// The key of the keyed range currently being built, if any.
+    keyed_range: Option<DisplayItemKey>,
 }
 
 impl DisplayListBuilder {
@@ -855,9 +1176,26 @@ impl DisplayListBuilder {
             builder_start_time: start_time,
             content_size,
             save_state: None,
+            keyed_range: None,
         }
     }
 
+    /// Creates a builder for a display list that is going to be sent as a delta
+    /// against `base`, see `BuiltDisplayList::apply_delta`.
+    ///
+    /// Clip and clip chain ids continue where the base display list stopped, so the
+    /// items of the delta can't collide with the ones they are merged with.
+    pub fn new_delta(
+        pipeline_id: PipelineId,
+        content_size: LayoutSize,
+        base: &BuiltDisplayListDescriptor,
+    ) -> Self {
+        let mut builder = Self::new(pipeline_id, content_size);
+        builder.next_clip_id = base.total_clip_ids;
+        builder.next_clip_chain_id = base.total_clip_chain_ids;
+        builder
+    }
+
     /// Return the content size for this display list
     pub fn content_size(&self) -> LayoutSize {
         self.content_size
@@ -878,6 +1216,7 @@ impl DisplayListBuilder {
             dl_len: self.data.len(),
             next_clip_id: self.next_clip_id,
             next_clip_chain_id: self.next_clip_chain_id,
+            keyed_range: self.keyed_range,
         });
     }
 
@@ -889,6 +1228,7 @@ impl DisplayListBuilder {
         self.data.truncate(state.dl_len);
         self.next_clip_id = state.next_clip_id;
         self.next_clip_chain_id = state.next_clip_chain_id;
+        self.keyed_range = state.keyed_range;
     }
 
     /// Discards the builder's save (indicating the attempted operation was successful).
@@ -991,6 +1331,16 @@ impl DisplayListBuilder {
     }
 
     pub fn push_rect(&mut self, info: &LayoutPrimitiveInfo, color: ColorF) {
+        self.push_rect_with_animation(info, PropertyBinding::Value(color));
+    }
+
+    /// Pushes a rectangle whose color can be updated with
+    /// `Transaction::update_dynamic_properties`.
+    pub fn push_rect_with_animation(
+        &mut self,
+        info: &LayoutPrimitiveInfo,
+        color: PropertyBinding<ColorF>,
+    ) {
         let item = SpecificDisplayItem::Rectangle(RectangleDisplayItem { color });
         self.push_item(item, info);
     }
@@ -1017,6 +1367,34 @@ impl DisplayListBuilder {
         self.push_item(item, info);
     }
 
+    /// Fills the path with the paint or, if `stroke` is given, strokes its
+    /// outline. `dashes` alternates the lengths of the dashes and the gaps
+    /// between them, and is ignored for fills. A gradient paint should be
+    /// created with `create_gradient`, which pushes its stops.
+    pub fn push_path<I>(
+        &mut self,
+        info: &LayoutPrimitiveInfo,
+        path: I,
+        fill_rule: FillRule,
+        stroke: Option<StrokeStyle>,
+        dashes: &[f32],
+        paint: PathPaint,
+    )
+    where
+        I: IntoIterator<Item = PathCommand>,
+        I::IntoIter: ExactSizeIterator + Clone,
+    {
+        let item = SpecificDisplayItem::Path(PathDisplayItem {
+            fill_rule,
+            stroke,
+            paint,
+        });
+
+        self.push_item(item, info);
+        self.push_iter(path);
+        self.push_iter(dashes);
+    }
+
     pub fn push_image(
         &mut self,
         info: &LayoutPrimitiveInfo,
@@ -1073,6 +1451,29 @@ impl DisplayListBuilder {
         }
     }
 
+    /// Pushes a line or emphasis marks decorating the glyphs of the last text
+    /// item, so it has to follow `push_text` and any other decorations of the
+    /// text. `info.rect` is the area the decoration may cover, usually the bounds
+    /// of the text item, extended to the marks for emphasis. The position and
+    /// default thickness of the line come from the font metrics.
+    pub fn push_text_decoration(
+        &mut self,
+        info: &LayoutPrimitiveInfo,
+        kind: TextDecorationKind,
+        color: ColorF,
+        thickness: Option<f32>,
+        skip_ink: bool,
+    ) {
+        let item = SpecificDisplayItem::TextDecoration(TextDecorationDisplayItem {
+            kind,
+            color,
+            thickness,
+            skip_ink,
+        });
+
+        self.push_item(item, info);
+    }
+
     // Gradients can be defined with stops outside the range of [0, 1]
     // when this happens the gradient needs to be normalized by adjusting
     // the gradient stops and gradient line into an equivalent gradient
@@ -1198,6 +1599,29 @@ impl DisplayListBuilder {
         }
     }
 
+    // NOTE: the stops are stored for the next gradient item, so the conic
+    // gradient has to be pushed before another gradient is created.
+    pub fn create_conic_gradient(
+        &mut self,
+        center: LayoutPoint,
+        angle: f32,
+        mut stops: Vec<GradientStop>,
+        extend_mode: ExtendMode,
+    ) -> ConicGradient {
+        let (start_offset, end_offset) =
+            DisplayListBuilder::normalize_stops(&mut stops, extend_mode);
+
+        self.push_stops(&stops);
+
+        ConicGradient {
+            center,
+            angle,
+            start_offset,
+            end_offset,
+            extend_mode,
+        }
+    }
+
     pub fn push_border(
         &mut self,
         info: &LayoutPrimitiveInfo,
@@ -1282,6 +1706,25 @@ impl DisplayListBuilder {
         self.push_item(item, info);
     }
 
+    /// Pushes a conic gradient to be displayed.
+    ///
+    /// See [`push_gradient`](#method.push_gradient) for explanation.
+    pub fn push_conic_gradient(
+        &mut self,
+        info: &LayoutPrimitiveInfo,
+        gradient: ConicGradient,
+        tile_size: LayoutSize,
+        tile_spacing: LayoutSize,
+    ) {
+        let item = SpecificDisplayItem::ConicGradient(ConicGradientDisplayItem {
+            gradient,
+            tile_size,
+            tile_spacing,
+        });
+
+        self.push_item(item, info);
+    }
+
     pub fn push_reference_frame(
         &mut self,
         info: &LayoutPrimitiveInfo,
@@ -1312,6 +1755,29 @@ impl DisplayListBuilder {
         mix_blend_mode: MixBlendMode,
         filters: Vec<FilterOp>,
         glyph_raster_space: GlyphRasterSpace,
+    ) {
+        self.push_stacking_context_with_effects(
+            info,
+            clip_node_id,
+            transform_style,
+            mix_blend_mode,
+            filters,
+            glyph_raster_space,
+            StackingContextEffects::default(),
+        );
+    }
+
+    /// Pushes a stacking context that also has backdrop filters, a filter
+    /// graph or mask layers.
+    pub fn push_stacking_context_with_effects(
+        &mut self,
+        info: &LayoutPrimitiveInfo,
+        clip_node_id: Option<ClipId>,
+        transform_style: TransformStyle,
+        mix_blend_mode: MixBlendMode,
+        filters: Vec<FilterOp>,
+        glyph_raster_space: GlyphRasterSpace,
+        effects: StackingContextEffects,
     ) {
         let item = SpecificDisplayItem::PushStackingContext(PushStackingContextDisplayItem {
             stacking_context: StackingContext {
@@ -1324,6 +1790,10 @@ impl DisplayListBuilder {
 
         self.push_item(item, info);
         self.push_iter(&filters);
+        self.push_iter(&effects.backdrop_filters);
+        self.push_iter(&effects.filter_primitives);
+        self.push_iter(&effects.mask_layers);
+        self.push_iter(&effects.mask_stops);
     }
 
     pub fn pop_stacking_context(&mut self) {
@@ -1372,6 +1842,34 @@ impl DisplayListBuilder {
             scroll_sensitivity)
     }
 
+    /// Defines a scroll frame whose scroll offset can be updated with
+    /// `Transaction::update_dynamic_properties`.
+    pub fn define_scroll_frame_with_animation<I>(
+        &mut self,
+        external_id: Option<ExternalScrollId>,
+        content_rect: LayoutRect,
+        clip_rect: LayoutRect,
+        complex_clips: I,
+        image_mask: Option<ImageMask>,
+        scroll_sensitivity: ScrollSensitivity,
+        scroll_offset: PropertyBinding<LayoutVector2D>,
+    ) -> ClipId
+    where
+        I: IntoIterator<Item = ComplexClipRegion>,
+        I::IntoIter: ExactSizeIterator + Clone,
+    {
+        let parent = self.clip_stack.last().unwrap().scroll_node_id;
+        self.push_scroll_frame(
+            parent,
+            external_id,
+            content_rect,
+            clip_rect,
+            complex_clips,
+            image_mask,
+            scroll_sensitivity,
+            Some(scroll_offset))
+    }
+
     pub fn define_scroll_frame_with_parent<I>(
         &mut self,
         parent: ClipId,
@@ -1382,6 +1880,32 @@ impl DisplayListBuilder {
         image_mask: Option<ImageMask>,
         scroll_sensitivity: ScrollSensitivity,
     ) -> ClipId
+    where
+        I: IntoIterator<Item = ComplexClipRegion>,
+        I::IntoIter: ExactSizeIterator + Clone,
+    {
+        self.push_scroll_frame(
+            parent,
+            external_id,
+            content_rect,
+            clip_rect,
+            complex_clips,
+            image_mask,
+            scroll_sensitivity,
+            None)
+    }
+
+    fn push_scroll_frame<I>(
+        &mut self,
+        parent: ClipId,
+        external_id: Option<ExternalScrollId>,
+        content_rect: LayoutRect,
+        clip_rect: LayoutRect,
+        complex_clips: I,
+        image_mask: Option<ImageMask>,
+        scroll_sensitivity: ScrollSensitivity,
+        scroll_offset: Option<PropertyBinding<LayoutVector2D>>,
+    ) -> ClipId
     where
         I: IntoIterator<Item = ComplexClipRegion>,
         I::IntoIter: ExactSizeIterator + Clone,
@@ -1394,6 +1918,7 @@ impl DisplayListBuilder {
             external_id,
             image_mask,
             scroll_sensitivity,
+            scroll_offset,
         });
 
         self.push_item_with_clip_scroll_info(
@@ -1465,6 +1990,45 @@ impl DisplayListBuilder {
         id
     }
 
+    pub fn define_clip_path<I>(
+        &mut self,
+        clip_rect: LayoutRect,
+        path: I,
+        fill_rule: FillRule,
+    ) -> ClipId
+    where
+        I: IntoIterator<Item = PathCommand>,
+        I::IntoIter: ExactSizeIterator + Clone,
+    {
+        let parent = self.clip_stack.last().unwrap().scroll_node_id;
+        self.define_clip_path_with_parent(parent, clip_rect, path, fill_rule)
+    }
+
+    pub fn define_clip_path_with_parent<I>(
+        &mut self,
+        parent: ClipId,
+        clip_rect: LayoutRect,
+        path: I,
+        fill_rule: FillRule,
+    ) -> ClipId
+    where
+        I: IntoIterator<Item = PathCommand>,
+        I::IntoIter: ExactSizeIterator + Clone,
+    {
+        let id = self.generate_clip_id();
+        let item = SpecificDisplayItem::ClipPath(ClipPathDisplayItem {
+            id,
+            fill_rule,
+        });
+
+        let info = LayoutPrimitiveInfo::new(clip_rect);
+
+        let scrollinfo = ClipAndScrollInfo::simple(parent);
+        self.push_item_with_clip_scroll_info(item, &info, scrollinfo);
+        self.push_iter(path);
+        id
+    }
+
     pub fn define_sticky_frame(
         &mut self,
         frame_rect: LayoutRect,
@@ -1527,8 +2091,29 @@ impl DisplayListBuilder {
         self.push_new_empty_item(SpecificDisplayItem::PopAllShadows);
     }
 
+    /// Starts a range of items that can later be replaced or removed by a
+    /// display list delta, see `BuiltDisplayList::apply_delta`.
+    ///
+    /// # Conditions:
+    ///
+    /// * Keyed ranges can't be nested.
+    /// * The items of the range must push and pop the same amount of stacking
+    ///   contexts, reference frames and clips.
+    /// * Keys must be unique within a display list.
+    pub fn start_keyed_range(&mut self, key: DisplayItemKey) {
+        assert!(self.keyed_range.is_none(), "DisplayListBuilder doesn't support nested keyed ranges");
+        self.keyed_range = Some(key);
+        self.push_new_empty_item(SpecificDisplayItem::StartKeyedRange(key));
+    }
+
+    pub fn end_keyed_range(&mut self) {
+        self.keyed_range.take().expect("No keyed range to end in DisplayListBuilder");
+        self.push_new_empty_item(SpecificDisplayItem::EndKeyedRange);
+    }
+
     pub fn finalize(self) -> (PipelineId, LayoutSize, BuiltDisplayList) {
         assert!(self.save_state.is_none(), "Finalized DisplayListBuilder with a pending save");
+        assert!(self.keyed_range.is_none(), "Finalized DisplayListBuilder with an open keyed range");
 
         let end_time = precise_time_ns();
 
@@ -1542,9 +2127,184 @@ impl DisplayListBuilder {
                     builder_finish_time: end_time,
                     send_start_time: 0,
                     total_clip_ids: self.next_clip_id,
+                    total_clip_chain_ids: self.next_clip_chain_id,
                 },
                 data: self.data,
             },
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rect_info(x: f32) -> LayoutPrimitiveInfo {
+        LayoutPrimitiveInfo::new(LayoutRect::new(LayoutPoint::new(x, 0.0), LayoutSize::new(10.0, 10.0)))
+    }
+
+    /// Builds a display list of one rectangle per entry, each in a keyed range if
+    /// it has a key. The rectangles are told apart by their x position.
+    fn build(base: Option<&BuiltDisplayList>, rects: &[(Option<u64>, f32)]) -> BuiltDisplayList {
+        let pipeline_id = PipelineId::dummy();
+        let content_size = LayoutSize::new(100.0, 100.0);
+        let mut builder = match base {
+            Some(base) => DisplayListBuilder::new_delta(pipeline_id, content_size, base.descriptor()),
+            None => DisplayListBuilder::new(pipeline_id, content_size),
+        };
+        for &(key, x) in rects {
+            if let Some(key) = key {
+                builder.start_keyed_range(DisplayItemKey(key));
+            }
+            builder.push_rect(&rect_info(x), ColorF::new(1.0, 0.0, 0.0, 1.0));
+            if key.is_some() {
+                builder.end_keyed_range();
+            }
+        }
+        builder.finalize().2
+    }
+
+    fn rect_positions(list: &BuiltDisplayList) -> Vec<f32> {
+        let mut positions = Vec::new();
+        let mut iter = list.iter();
+        while let Some(item) = iter.next() {
+            if let SpecificDisplayItem::Rectangle(..) = *item.item() {
+                positions.push(item.rect().origin.x);
+            }
+        }
+        positions
+    }
+
+    #[test]
+    fn replace_keyed_range() {
+        let base = build(None, &[(None, 0.0), (Some(1), 10.0), (Some(2), 20.0)]);
+        let delta = build(Some(&base), &[(Some(1), 15.0)]);
+
+        let merged = base.apply_delta(&delta, &[DisplayListDeltaOp::Replace(DisplayItemKey(1))]).unwrap();
+        assert_eq!(rect_positions(&merged), vec![0.0, 15.0, 20.0]);
+    }
+
+    #[test]
+    fn insert_keyed_range() {
+        let base = build(None, &[(None, 0.0), (Some(1), 10.0), (Some(2), 20.0)]);
+        let delta = build(Some(&base), &[(Some(3), 15.0), (Some(4), -5.0)]);
+
+        let merged = base.apply_delta(&delta, &[
+            DisplayListDeltaOp::Insert { key: DisplayItemKey(3), after: Some(DisplayItemKey(1)) },
+            DisplayListDeltaOp::Insert { key: DisplayItemKey(4), after: None },
+        ]).unwrap();
+        assert_eq!(rect_positions(&merged), vec![-5.0, 0.0, 10.0, 15.0, 20.0]);
+
+        assert_eq!(
+            base.apply_delta(&delta, &[
+                DisplayListDeltaOp::Insert { key: DisplayItemKey(3), after: Some(DisplayItemKey(5)) },
+            ]).err(),
+            Some(DisplayListDeltaError::MissingKey(DisplayItemKey(5)))
+        );
+        assert_eq!(
+            merged.apply_delta(&delta, &[
+                DisplayListDeltaOp::Insert { key: DisplayItemKey(3), after: None },
+            ]).err(),
+            Some(DisplayListDeltaError::DuplicateKey(DisplayItemKey(3)))
+        );
+    }
+
+    #[test]
+    fn remove_keyed_range() {
+        let base = build(None, &[(Some(1), 10.0), (None, 15.0), (Some(2), 20.0)]);
+        let delta = build(Some(&base), &[]);
+
+        let merged = base.apply_delta(&delta, &[DisplayListDeltaOp::Remove(DisplayItemKey(1))]).unwrap();
+        assert_eq!(rect_positions(&merged), vec![15.0, 20.0]);
+
+        assert_eq!(
+            merged.apply_delta(&delta, &[DisplayListDeltaOp::Remove(DisplayItemKey(1))]).err(),
+            Some(DisplayListDeltaError::MissingKey(DisplayItemKey(1)))
+        );
+    }
+
+    #[test]
+    fn clip_used_outside_keyed_range() {
+        let pipeline_id = PipelineId::dummy();
+        let content_size = LayoutSize::new(100.0, 100.0);
+        let mut builder = DisplayListBuilder::new(pipeline_id, content_size);
+        builder.start_keyed_range(DisplayItemKey(1));
+        let clip_id = builder.define_clip(rect_info(0.0).rect, vec![], None);
+        builder.push_clip_id(clip_id);
+        builder.push_rect(&rect_info(0.0), ColorF::new(1.0, 0.0, 0.0, 1.0));
+        builder.end_keyed_range();
+        builder.push_rect(&rect_info(10.0), ColorF::new(1.0, 0.0, 0.0, 1.0));
+        builder.pop_clip_id();
+        let base = builder.finalize().2;
+        let delta = build(Some(&base), &[(Some(1), 15.0)]);
+
+        assert_eq!(
+            base.apply_delta(&delta, &[DisplayListDeltaOp::Replace(DisplayItemKey(1))]).err(),
+            Some(DisplayListDeltaError::ClipOutsideRange(DisplayItemKey(1)))
+        );
+    }
+
+    #[test]
+    fn gradient_stops_belong_to_keyed_range() {
+        fn build_gradient(base: Option<&BuiltDisplayList>, offset: f32) -> BuiltDisplayList {
+            let pipeline_id = PipelineId::dummy();
+            let content_size = LayoutSize::new(100.0, 100.0);
+            let mut builder = match base {
+                Some(base) => DisplayListBuilder::new_delta(pipeline_id, content_size, base.descriptor()),
+                None => DisplayListBuilder::new(pipeline_id, content_size),
+            };
+            if base.is_none() {
+                builder.push_rect(&rect_info(0.0), ColorF::new(1.0, 0.0, 0.0, 1.0));
+            }
+            let stops = vec![
+                GradientStop { offset: 0.0, color: ColorF::new(1.0, 0.0, 0.0, 1.0) },
+                GradientStop { offset, color: ColorF::new(0.0, 1.0, 0.0, 1.0) },
+                GradientStop { offset: 1.0, color: ColorF::new(0.0, 0.0, 1.0, 1.0) },
+            ];
+            let gradient = builder.create_gradient(
+                LayoutPoint::zero(),
+                LayoutPoint::new(10.0, 0.0),
+                stops,
+                ExtendMode::Clamp,
+            );
+            builder.start_keyed_range(DisplayItemKey(1));
+            builder.push_gradient(&rect_info(10.0), gradient, LayoutSize::new(10.0, 10.0), LayoutSize::zero());
+            builder.end_keyed_range();
+            builder.finalize().2
+        }
+
+        fn gradient_stop_offsets(list: &BuiltDisplayList) -> Vec<f32> {
+            let mut offsets = Vec::new();
+            let mut iter = list.iter();
+            while let Some(item) = iter.next() {
+                if let SpecificDisplayItem::Gradient(..) = *item.item() {
+                    offsets.extend(list.get(item.gradient_stops()).map(|stop| stop.offset));
+                }
+            }
+            offsets
+        }
+
+        fn stops_items(list: &BuiltDisplayList) -> usize {
+            let mut count = 0;
+            let mut iter = list.iter();
+            while let Some(item) = iter.next_raw() {
+                if let SpecificDisplayItem::SetGradientStops = *item.item() {
+                    count += 1;
+                }
+            }
+            count
+        }
+
+        let base = build_gradient(None, 0.25);
+        let delta = build_gradient(Some(&base), 0.5);
+        assert_eq!(gradient_stop_offsets(&base), vec![0.0, 0.25, 1.0]);
+
+        let replaced = base.apply_delta(&delta, &[DisplayListDeltaOp::Replace(DisplayItemKey(1))]).unwrap();
+        assert_eq!(gradient_stop_offsets(&replaced), vec![0.0, 0.5, 1.0]);
+        assert_eq!(stops_items(&replaced), 1);
+
+        let removed = base.apply_delta(&delta, &[DisplayListDeltaOp::Remove(DisplayItemKey(1))]).unwrap();
+        assert_eq!(stops_items(&removed), 0);
+        assert_eq!(rect_positions(&removed), vec![0.0]);
+    }
+}
@@ -15,7 +15,7 @@ use serde::ser::{Serialize, Serializer};
 use std::cmp::Ordering;
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
-use {ColorU, IdNamespace, LayoutPoint};
+use {ColorU, IdNamespace, LayoutPoint, PropertyBinding, PropertyBindingId};
 
 
 #[cfg(not(any(target_os = "macos", target_os = "windows")))]
@@ -92,13 +92,20 @@ pub enum FontRenderMode {
     Mono = 0,
     Alpha,
     Subpixel,
+    /// Glyphs are rasterized once as signed distance fields and scaled,
+    /// rotated and anti-aliased in the shader.
+    Sdf,
 }
 
 impl FontRenderMode {
     // Combine two font render modes such that the lesser amount of AA limits the AA of the result.
+    // Sdf is a grayscale mode, so it limits Subpixel to Alpha, but it is never the result of
+    // limiting another mode.
     pub fn limit_by(self, other: FontRenderMode) -> FontRenderMode {
         match (self, other) {
-            (FontRenderMode::Subpixel, _) | (_, FontRenderMode::Mono) => other,
+            (_, FontRenderMode::Mono) => other,
+            (FontRenderMode::Subpixel, FontRenderMode::Sdf) => FontRenderMode::Alpha,
+            (FontRenderMode::Subpixel, _) => other,
             _ => self,
         }
     }
@@ -134,6 +141,51 @@ impl Hash for FontVariation {
     }
 }
 
+/// A variation axis whose value can be bound to a float property, so that it
+/// can be animated with `Transaction::update_dynamic_properties` instead of
+/// adding a new font instance for every value. The bound value overrides any
+/// variation of the same axis given when adding the font instance.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct FontVariationBinding {
+    pub tag: u32,
+    pub value: PropertyBinding<f32>,
+}
+
+impl FontVariationBinding {
+    fn sort_key(&self) -> (u32, Option<PropertyBindingId>, u32) {
+        match self.value {
+            PropertyBinding::Value(value) => (self.tag, None, value.to_bits()),
+            PropertyBinding::Binding(key, value) => (self.tag, Some(key.id), value.to_bits()),
+        }
+    }
+}
+
+impl Ord for FontVariationBinding {
+    fn cmp(&self, other: &FontVariationBinding) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+impl PartialOrd for FontVariationBinding {
+    fn partial_cmp(&self, other: &FontVariationBinding) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for FontVariationBinding {
+    fn eq(&self, other: &FontVariationBinding) -> bool {
+        self.sort_key() == other.sort_key()
+    }
+}
+
+impl Eq for FontVariationBinding {}
+
+impl Hash for FontVariationBinding {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sort_key().hash(state);
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Deserialize, Hash, Eq, PartialEq, PartialOrd, Ord, Serialize)]
 pub struct GlyphOptions {
@@ -206,6 +258,9 @@ pub struct FontInstanceOptions {
     /// the text will be rendered with bg_color.r/g/b as an opaque estimated
     /// background color.
     pub bg_color: ColorU,
+    /// The palette used for layered color glyphs, if the font has several.
+    /// Fonts fall back to their first palette if the index is out of range.
+    pub palette_index: u16,
 }
 
 impl Default for FontInstanceOptions {
@@ -214,6 +269,7 @@ impl Default for FontInstanceOptions {
             render_mode: FontRenderMode::Subpixel,
             flags: Default::default(),
             bg_color: ColorU::new(0, 0, 0, 0),
+            palette_index: 0,
         }
     }
 }
@@ -56,18 +56,24 @@ pub struct ExternalImageData {
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum ImageFormat {
     R8 = 1,
+    R16 = 2,
     BGRA8 = 3,
     RGBAF32 = 4,
     RG8 = 5,
+    RGBAI32 = 6,
+    RGBA8 = 7,
 }
 
 impl ImageFormat {
     pub fn bytes_per_pixel(self) -> u32 {
         match self {
             ImageFormat::R8 => 1,
+            ImageFormat::R16 => 2,
             ImageFormat::BGRA8 => 4,
             ImageFormat::RGBAF32 => 16,
             ImageFormat::RG8 => 2,
+            ImageFormat::RGBAI32 => 16,
+            ImageFormat::RGBA8 => 4,
         }
     }
 }
@@ -184,6 +190,11 @@ pub trait BlobImageRenderer: Send {
 
     fn delete(&mut self, key: ImageKey);
 
+    /// Request the rasterization of an image, or of a single tile of a tiled image.
+    ///
+    /// The dirty rect is relative to the requested tile (or to the image if it isn't
+    /// tiled) and `None` means the whole tile must be rasterized. Tiles are requested
+    /// independently so implementations are free to rasterize them in parallel.
     fn request(
         &mut self,
         services: &BlobImageResources,
@@ -194,6 +205,14 @@ pub trait BlobImageRenderer: Send {
 
     fn resolve(&mut self, key: BlobImageRequest) -> BlobImageResult;
 
+    /// Create a rasterizer that the scene builder thread can use to rasterize blob
+    /// images ahead of frame building.
+    ///
+    /// Blob images are only rasterized while building frames if this returns `None`.
+    fn create_async_rasterizer(&mut self) -> Option<Box<AsyncBlobImageRasterizer>> {
+        None
+    }
+
     fn delete_font(&mut self, key: FontKey);
 
     fn delete_font_instance(&mut self, key: FontInstanceKey);
@@ -201,6 +220,24 @@ pub trait BlobImageRenderer: Send {
     fn clear_namespace(&mut self, namespace: IdNamespace);
 }
 
+/// Rasterizes blob images away from the render backend thread.
+///
+/// Each request carries the commands of the image it refers to, so the rasterizer
+/// doesn't need to keep track of the blob images itself.
+pub trait AsyncBlobImageRasterizer: Send {
+    fn rasterize(&mut self, requests: &[BlobImageParams]) -> Vec<(BlobImageRequest, BlobImageResult)>;
+}
+
+/// A blob image rasterization request sent to an `AsyncBlobImageRasterizer`.
+#[derive(Clone, Debug)]
+pub struct BlobImageParams {
+    pub request: BlobImageRequest,
+    pub data: Arc<BlobImageData>,
+    pub descriptor: BlobImageDescriptor,
+    /// Relative to the requested tile, `None` means the whole tile.
+    pub dirty_rect: Option<DeviceUintRect>,
+}
+
 pub type BlobImageData = Vec<u8>;
 
 pub type BlobImageResult = Result<RasterizedBlobImage, BlobImageError>;
@@ -214,6 +251,8 @@ pub struct BlobImageDescriptor {
 }
 
 pub struct RasterizedBlobImage {
+    /// The tile covered by this rasterization, `None` for non-tiled images.
+    pub tile: Option<TileOffset>,
     pub size: DeviceUintSize,
     pub data: Vec<u8>,
 }
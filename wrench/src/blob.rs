@@ -4,12 +4,14 @@
 
 // A very basic BlobImageRenderer that can only render a checkerboard pattern.
 
+use rayon::ThreadPool;
+use rayon::prelude::*;
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, Sender, channel};
 use webrender::api::*;
-use webrender::intersect_for_tile;
-use euclid::size2;
 
 // Serialize/deserialize the blob.
 
@@ -37,7 +39,7 @@ fn premul(x: u8, a: u8) -> u8 {
 fn render_blob(
     color: ColorU,
     descriptor: &BlobImageDescriptor,
-    tile: Option<(TileSize, TileOffset)>,
+    tile: Option<TileOffset>,
     dirty_rect: Option<DeviceUintRect>,
 ) -> BlobImageResult {
     // Allocate storage for the result. Right now the resource cache expects the
@@ -50,21 +52,15 @@ fn render_blob(
     // Generate a per-tile pattern to see it in the demo. For a real use case it would not
     // make sense for the rendered content to depend on its tile.
     let tile_checker = match tile {
-        Some((_, tile)) => (tile.x % 2 == 0) != (tile.y % 2 == 0),
+        Some(tile) => (tile.x % 2 == 0) != (tile.y % 2 == 0),
         None => true,
     };
 
-    let mut dirty_rect = dirty_rect.unwrap_or(DeviceUintRect::new(
+    // The dirty rect is relative to the tile.
+    let dirty_rect = dirty_rect.unwrap_or(DeviceUintRect::new(
         DeviceUintPoint::new(0, 0),
         DeviceUintSize::new(descriptor.size.width, descriptor.size.height)));
 
-    if let Some((tile_size, tile)) = tile {
-        dirty_rect = intersect_for_tile(dirty_rect, size2(tile_size as u32, tile_size as u32),
-                                        tile_size, tile)
-            .expect("empty rects should be culled by webrender");
-    }
-
-
     for y in dirty_rect.min_y() .. dirty_rect.max_y() {
         for x in dirty_rect.min_x() .. dirty_rect.max_x() {
             // Apply the tile's offset. This is important: all drawing commands should be
@@ -102,6 +98,7 @@ fn render_blob(
     }
 
     Ok(RasterizedBlobImage {
+        tile,
         data: texels,
         size: descriptor.size,
     })
@@ -118,34 +115,68 @@ impl BlobCallbacks {
     }
 }
 
+// Rasterizes blob images on the scene builder thread.
+struct AsyncCheckerboardRasterizer {
+    callbacks: Arc<Mutex<BlobCallbacks>>,
+    workers: Arc<ThreadPool>,
+}
+
+impl AsyncBlobImageRasterizer for AsyncCheckerboardRasterizer {
+    fn rasterize(&mut self, requests: &[BlobImageParams]) -> Vec<(BlobImageRequest, BlobImageResult)> {
+        for params in requests {
+            (self.callbacks.lock().unwrap().request)(&params.request);
+        }
+
+        self.workers.install(|| {
+            requests.par_iter().map(|params| {
+                let result = match deserialize_blob(&params.data[..]) {
+                    Ok(color) => render_blob(color, &params.descriptor, params.request.tile, params.dirty_rect),
+                    Err(()) => Err(BlobImageError::InvalidData),
+                };
+                (params.request, result)
+            }).collect()
+        })
+    }
+}
+
 pub struct CheckerboardRenderer {
-    image_cmds: HashMap<ImageKey, (ColorU, Option<TileSize>)>,
+    image_cmds: HashMap<ImageKey, ColorU>,
     callbacks: Arc<Mutex<BlobCallbacks>>,
 
+    // Tiles are rasterized in parallel on the same thread pool as the glyphs.
+    workers: Arc<ThreadPool>,
+    tx: Sender<(BlobImageRequest, BlobImageResult)>,
+    rx: Receiver<(BlobImageRequest, BlobImageResult)>,
+
     // The images rendered in the current frame (not kept here between frames).
-    rendered_images: HashMap<BlobImageRequest, BlobImageResult>,
+    // None means the image has been requested but we haven't received it yet.
+    rendered_images: HashMap<BlobImageRequest, Option<BlobImageResult>>,
 }
 
 impl CheckerboardRenderer {
-    pub fn new(callbacks: Arc<Mutex<BlobCallbacks>>) -> Self {
+    pub fn new(callbacks: Arc<Mutex<BlobCallbacks>>, workers: Arc<ThreadPool>) -> Self {
+        let (tx, rx) = channel();
         CheckerboardRenderer {
             callbacks,
             image_cmds: HashMap::new(),
+            workers,
+            tx,
+            rx,
             rendered_images: HashMap::new(),
         }
     }
 }
 
 impl BlobImageRenderer for CheckerboardRenderer {
-    fn add(&mut self, key: ImageKey, cmds: Arc<BlobImageData>, tile_size: Option<TileSize>) {
+    fn add(&mut self, key: ImageKey, cmds: Arc<BlobImageData>, _tile_size: Option<TileSize>) {
         self.image_cmds
-            .insert(key, (deserialize_blob(&cmds[..]).unwrap(), tile_size));
+            .insert(key, deserialize_blob(&cmds[..]).unwrap());
     }
 
     fn update(&mut self, key: ImageKey, cmds: Arc<BlobImageData>, _dirty_rect: Option<DeviceUintRect>) {
         // Here, updating is just replacing the current version of the commands with
         // the new one (no incremental updates).
-        self.image_cmds.get_mut(&key).unwrap().0 = deserialize_blob(&cmds[..]).unwrap();
+        *self.image_cmds.get_mut(&key).unwrap() = deserialize_blob(&cmds[..]).unwrap();
     }
 
     fn delete(&mut self, key: ImageKey) {
@@ -166,18 +197,48 @@ impl BlobImageRenderer for CheckerboardRenderer {
         // In this example we will use the thread pool to render individual tiles.
 
         // Gather the input data to send to a worker thread.
-        let &(color, tile_size) = self.image_cmds.get(&request.key).unwrap();
+        let color = *self.image_cmds.get(&request.key).unwrap();
+        let descriptor = descriptor.clone();
+        let tx = self.tx.clone();
 
-        let tile = request.tile.map(|tile| (tile_size.unwrap(), tile));
+        self.workers.spawn(move || {
+            let result = render_blob(color, &descriptor, request.tile, dirty_rect);
+            tx.send((request, result)).unwrap();
+        });
 
-        let result = render_blob(color, descriptor, tile, dirty_rect);
-
-        self.rendered_images.insert(request, result);
+        self.rendered_images.insert(request, None);
     }
 
     fn resolve(&mut self, request: BlobImageRequest) -> BlobImageResult {
         (self.callbacks.lock().unwrap().resolve)();
-        self.rendered_images.remove(&request).unwrap()
+        match self.rendered_images.entry(request) {
+            Entry::Vacant(_) => {
+                return Err(BlobImageError::InvalidKey);
+            }
+            Entry::Occupied(entry) => {
+                if entry.get().is_some() {
+                    return entry.remove().unwrap();
+                }
+                entry.remove();
+            }
+        }
+
+        // Wait for the workers, keeping the other tiles around until they are resolved.
+        while let Ok((req, result)) = self.rx.recv() {
+            if req == request {
+                return result;
+            }
+            self.rendered_images.insert(req, Some(result));
+        }
+
+        Err(BlobImageError::Other("Channel closed".into()))
+    }
+
+    fn create_async_rasterizer(&mut self) -> Option<Box<AsyncBlobImageRasterizer>> {
+        Some(Box::new(AsyncCheckerboardRasterizer {
+            callbacks: Arc::clone(&self.callbacks),
+            workers: Arc::clone(&self.workers),
+        }))
     }
 
     fn delete_font(&mut self, _key: FontKey) {}
@@ -41,6 +41,7 @@ extern crate log;
 extern crate mozangle;
 #[cfg(feature = "headless")]
 extern crate osmesa_sys;
+extern crate rayon;
 extern crate ron;
 #[macro_use]
 extern crate serde;
@@ -77,7 +78,7 @@ use binary_frame_reader::BinaryFrameReader;
 use gleam::gl;
 #[cfg(feature = "gl")]
 use glutin::GlContext;
-#[cfg(not(feature = "gl"))]
+#[cfg(any(feature = "vulkan", feature = "dx12"))]
 use hal::Instance;
 use perf::PerfHarness;
 use png::save_flipped;
@@ -278,7 +279,10 @@ impl WindowWrapper {
     fn resize(&mut self, size: DeviceUintSize) {
         match *self {
             WindowWrapper::Window(ref mut window) => window.set_inner_size(size.width, size.height),
-            WindowWrapper::Headless(_) => unimplemented!(),
+            WindowWrapper::Headless(ref mut context) => {
+                context.width = size.width;
+                context.height = size.height;
+            }
         }
     }
 
@@ -316,14 +320,6 @@ impl WindowWrapper {
             WindowWrapper::Headless(_, ref gl) => gl.clone(),
         }
     }
-
-    #[cfg(not(feature = "gl"))]
-    fn get_window(&self) -> &winit::Window {
-        match *self {
-            WindowWrapper::Window(ref window) => &window,
-            WindowWrapper::Headless(..) => unreachable!(),
-        }
-    }
 }
 
 #[cfg(feature = "gl")]
@@ -411,7 +407,7 @@ fn make_window(
     wrapper
 }
 
-#[cfg(not(feature = "gl"))]
+#[cfg(any(feature = "vulkan", feature = "dx12"))]
 fn make_window(
     size: DeviceUintSize,
     _dp_ratio: Option<f32>,
@@ -789,7 +785,7 @@ fn main() {
     wrench.renderer.deinit();
 }
 
-#[cfg(any(feature = "vulkan", feature = "dx12"))]
+#[cfg(any(feature = "vulkan", feature = "dx12", feature = "software"))]
 fn main() {
     #[cfg(feature = "logging")]
         env_logger::init();
@@ -827,20 +823,51 @@ fn main() {
         .unwrap_or(DeviceUintSize::new(1920, 1080));
     let zoom_factor = args.value_of("zoom").map(|z| z.parse::<f32>().unwrap());
 
-    let mut events_loop = if args.is_present("headless") {
+    // The software device draws into memory, so it always runs without a window.
+    let mut events_loop = if args.is_present("headless") || cfg!(feature = "software") {
         None
     } else {
         Some(winit::EventsLoop::new())
     };
 
+    #[cfg(any(feature = "vulkan", feature = "dx12"))]
     let mut window = make_window(
         size, dp_ratio, args.is_present("vsync"), &events_loop, args.is_present("angle"),
     );
-    let instance = back::Instance::create("gfx-rs instance", 1);
-    let mut adapters = instance.enumerate_adapters();
-    let adapter = adapters.remove(0);
-    let mut surface = instance.create_surface(window.get_window());
+    #[cfg(not(any(feature = "vulkan", feature = "dx12")))]
+    let mut window = WindowWrapper::Headless(HeadlessContext::new(size.width, size.height));
     let window_size = window.get_inner_size();
+
+    #[cfg(any(feature = "vulkan", feature = "dx12"))]
+    let (adapter, mut surface) = {
+        let instance = back::Instance::create("gfx-rs instance", 1);
+        let mut adapters = instance.enumerate_adapters();
+        let adapter = adapters.remove(0);
+        // Headless runs render into an offscreen framebuffer instead.
+        let surface = match window {
+            WindowWrapper::Window(ref window) => Some(instance.create_surface(window)),
+            WindowWrapper::Headless(_) => None,
+        };
+        (adapter, surface)
+    };
+    #[cfg(any(feature = "vulkan", feature = "dx12"))]
+    let create_renderer = |notifier, opts| {
+        webrender::Renderer::new(
+            notifier,
+            &adapter,
+            surface.as_mut(),
+            (window_size.width, window_size.height),
+            opts,
+        ).unwrap()
+    };
+    #[cfg(not(any(feature = "vulkan", feature = "dx12")))]
+    let create_renderer = |notifier, opts| {
+        webrender::Renderer::<back::Backend>::new_software(
+            notifier,
+            (window_size.width, window_size.height),
+            opts,
+        ).unwrap()
+    };
     let dp_ratio = dp_ratio.unwrap_or(window.hidpi_factor());
     let dim = window.get_inner_size();
 
@@ -855,10 +882,8 @@ fn main() {
     };
 
     let mut wrench = Wrench::new(
-        (window_size.width, window_size.height),
+        create_renderer,
         events_loop.as_mut().map(|el| el.create_proxy()),
-        adapter,
-        &mut surface,
         res_path,
         dp_ratio,
         save_type,
@@ -869,6 +894,12 @@ fn main() {
         args.is_present("no_scissor"),
         args.is_present("no_batch"),
         args.is_present("precache"),
+        if args.is_present("immediate_upload") {
+            webrender::UploadMethod::Immediate
+        } else {
+            webrender::UploadMethod::PixelBuffer
+        },
+        args.value_of("msaa").map_or(1, |samples| samples.parse().expect("Invalid MSAA sample count")),
         args.is_present("slow_subpixel"),
         zoom_factor.unwrap_or(1.0),
         notifier,
@@ -931,6 +962,10 @@ fn main() {
         println!("loaded {:?}", documents.iter().map(|cd| cd.document_id).collect::<Vec<_>>());
         let captured = documents.swap_remove(0);
         window.resize(captured.window_size);
+        if let WindowWrapper::Headless(_) = window {
+            let size = captured.window_size;
+            wrench.renderer.resize_offscreen_surface((size.width, size.height));
+        }
         wrench.document_id = captured.document_id;
         Box::new(captured) as Box<WrenchThing>
     } else {
@@ -1113,7 +1148,7 @@ fn main() {
     wrench.renderer.deinit();
 }
 
-#[cfg(not(any(feature = "dx12", feature = "gl", feature = "vulkan")))]
+#[cfg(not(any(feature = "dx12", feature = "gl", feature = "vulkan", feature = "software")))]
 fn main() {
-    println!("You need to enable native API features (dx12/gl/vulkan) in order to test webrender");
+    println!("You need to enable native API features (dx12/gl/vulkan/software) in order to test webrender");
 }
@@ -11,10 +11,7 @@ use std::io::{BufRead, BufReader};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::sync::mpsc::Receiver;
-use wrench::{Wrench};
-#[cfg(feature = "gl")]
-use wrench::{WrenchThing};
-#[cfg(feature = "gl")]
+use wrench::{Wrench, WrenchThing};
 use yaml_frame_reader::YamlFrameReader;
 
 const COLOR_DEFAULT: &str = "\x1b[0m";
@@ -22,9 +19,7 @@ const COLOR_RED: &str = "\x1b[31m";
 const COLOR_GREEN: &str = "\x1b[32m";
 const COLOR_MAGENTA: &str = "\x1b[95m";
 
-#[cfg(feature = "gl")]
 const MIN_SAMPLE_COUNT: usize = 50;
-#[cfg(feature = "gl")]
 const SAMPLE_EXCLUDE_COUNT: usize = 10;
 
 pub struct Benchmark {
@@ -150,7 +145,6 @@ impl<'a> PerfHarness<'a> {
         profile.save(filename);
     }
 
-    #[cfg(feature = "gl")]
     fn render_yaml(&mut self, filename: &Path) -> TestProfile {
         let mut reader = YamlFrameReader::new(filename);
 
@@ -165,6 +159,8 @@ impl<'a> PerfHarness<'a> {
             reader.do_frame(self._wrench);
             self._rx.recv().unwrap();
             self._wrench.render();
+            // The gfx renderer presents, or keeps the offscreen frame, by itself.
+            #[cfg(feature = "gl")]
             self._window.swap_buffers();
             let (cpu_profiles, gpu_profiles) = self._wrench.get_frame_profiles();
             cpu_frame_profiles.extend(cpu_profiles);
@@ -192,14 +188,8 @@ impl<'a> PerfHarness<'a> {
         }
     }
 
-
-    #[cfg(not(feature = "gl"))]
-    fn render_yaml(&mut self, _filename: &Path) -> TestProfile {
-        unimplemented!()
-    }
 }
 
-#[cfg(feature = "gl")]
 fn extract_sample<F, T>(profiles: &mut [T], f: F) -> u64
 where
     F: Fn(&T) -> u64,
@@ -5,9 +5,9 @@
 use {WindowWrapper, NotifierEvent};
 use blob;
 use euclid::{TypedRect, TypedSize2D, TypedPoint2D};
-use std::sync::Arc;
-use std::sync::atomic::{AtomicIsize, Ordering};
-use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+use std::sync::mpsc::{Receiver, channel};
 use webrender::api::*;
 use wrench::Wrench;
 
@@ -45,11 +45,16 @@ impl<'a> RawtestHarness<'a> {
         self.test_retained_blob_images_test();
         self.test_blob_update_test();
         self.test_blob_update_epoch_test();
+        self.test_blob_tile_invalidation_test();
+        self.test_blob_scene_builder_rasterization();
+        self.test_blob_placeholders();
+        self.test_stale_async_blob();
         self.test_tile_decomposition();
         self.test_very_large_blob();
         self.test_offscreen_blob();
         self.test_save_restore();
         self.test_blur_cache();
+        self.test_dynamic_properties();
         self.test_capture();
         self.test_zero_height_window();
     }
@@ -122,6 +127,10 @@ impl<'a> RawtestHarness<'a> {
         self.rx.recv().unwrap();
         self.wrench.render();
 
+        // wait for the frame that replaces the placeholders of the new tiles
+        self.rx.recv().unwrap();
+        self.wrench.render();
+
         // Leaving a tiled blob image in the resource cache
         // confuses the `test_capture`. TODO: remove this
         txn = Transaction::new();
@@ -186,6 +195,10 @@ impl<'a> RawtestHarness<'a> {
 
         self.submit_dl(&mut epoch, layout_size, builder, &txn.resource_updates);
 
+        // the first frame only has placeholders for the new tiles
+        self.rx.recv().unwrap();
+        self.wrench.render();
+
         let pixels = self.render_and_get_pixels(window_rect);
 
         // make sure we didn't request too many blobs
@@ -644,6 +657,334 @@ impl<'a> RawtestHarness<'a> {
         assert!(pixels_first != pixels_third);
     }
 
+    fn test_blob_tile_invalidation_test(&mut self) {
+        println!("\tblob tile invalidation test...");
+        let window_size = self.window.get_inner_size();
+
+        let test_size = DeviceUintSize::new(400, 400);
+
+        let window_rect = DeviceUintRect::new(
+            point(0, window_size.height - test_size.height),
+            test_size,
+        );
+        let layout_size = LayoutSize::new(400., 400.);
+        let mut txn = Transaction::new();
+
+        let blob_img = {
+            let img = self.wrench.api.generate_image_key();
+            txn.add_image(
+                img,
+                ImageDescriptor::new(400, 400, ImageFormat::BGRA8, false, false),
+                ImageData::new_blob_image(blob::serialize_blob(ColorU::new(50, 50, 150, 255))),
+                Some(100),
+            );
+            img
+        };
+
+        let called = Arc::new(AtomicIsize::new(0));
+        let called_inner = Arc::clone(&called);
+
+        self.wrench.callbacks.lock().unwrap().request = Box::new(move |_| {
+            called_inner.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let mut epoch = Epoch(0);
+
+        // (dirty rect, number of tiles that should be rasterized again)
+        let updates = [
+            (None, 16),
+            (Some(rect(10, 10, 20, 20)), 1),
+            (Some(rect(90, 190, 20, 20)), 4),
+            (None, 16),
+        ];
+
+        for (i, &(dirty_rect, expected_requests)) in updates.iter().enumerate() {
+            if i > 0 {
+                txn = Transaction::new();
+                txn.update_image(
+                    blob_img,
+                    ImageDescriptor::new(400, 400, ImageFormat::BGRA8, false, false),
+                    ImageData::new_blob_image(blob::serialize_blob(ColorU::new(50, 50, 150, 255))),
+                    dirty_rect,
+                );
+            }
+
+            let mut builder = DisplayListBuilder::new(self.wrench.root_pipeline_id, layout_size);
+            let info = LayoutPrimitiveInfo::new(rect(0.0, 0.0, 400.0, 400.0));
+            builder.push_image(
+                &info,
+                size(400.0, 400.0),
+                size(0.0, 0.0),
+                ImageRendering::Auto,
+                AlphaType::PremultipliedAlpha,
+                blob_img,
+            );
+
+            called.store(0, Ordering::SeqCst);
+            self.submit_dl(&mut epoch, layout_size, builder, &txn.resource_updates);
+            self.render_and_get_pixels(window_rect);
+            if i == 0 {
+                // wait for the frame that replaces the placeholders of the new tiles
+                self.render_and_get_pixels(window_rect);
+            }
+
+            // only the tiles touched by the dirty rect should have been requested
+            assert_eq!(expected_requests, called.load(Ordering::SeqCst));
+        }
+
+        // cleanup
+        let mut txn = Transaction::new();
+        txn.delete_image(blob_img);
+        self.wrench.api.update_resources(txn.resource_updates);
+
+        *self.wrench.callbacks.lock().unwrap() = blob::BlobCallbacks::new();
+    }
+
+    fn test_blob_scene_builder_rasterization(&mut self) {
+        println!("\tblob scene builder rasterization test...");
+        let window_size = self.window.get_inner_size();
+
+        let test_size = DeviceUintSize::new(400, 400);
+
+        let window_rect = DeviceUintRect::new(
+            point(0, window_size.height - test_size.height),
+            test_size,
+        );
+        let layout_size = LayoutSize::new(400., 400.);
+        let mut txn = Transaction::new();
+
+        let blob_img = {
+            let img = self.wrench.api.generate_image_key();
+            txn.add_image(
+                img,
+                ImageDescriptor::new(200, 200, ImageFormat::BGRA8, false, false),
+                ImageData::new_blob_image(blob::serialize_blob(ColorU::new(50, 50, 150, 255))),
+                None,
+            );
+            img
+        };
+
+        // count the rasterizations, and the ones that happened while building a frame
+        let requested = Arc::new(AtomicIsize::new(0));
+        let requested_inner = Arc::clone(&requested);
+        let resolved = Arc::new(AtomicIsize::new(0));
+        let resolved_inner = Arc::clone(&resolved);
+        {
+            let mut callbacks = self.wrench.callbacks.lock().unwrap();
+            callbacks.request = Box::new(move |_| {
+                requested_inner.fetch_add(1, Ordering::SeqCst);
+            });
+            callbacks.resolve = Box::new(move || {
+                resolved_inner.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        let info = LayoutPrimitiveInfo::new(rect(0.0, 0.0, 200.0, 200.0));
+        let push_image = |builder: &mut DisplayListBuilder| {
+            builder.push_image(
+                &info,
+                size(200.0, 200.0),
+                size(0.0, 0.0),
+                ImageRendering::Auto,
+                AlphaType::PremultipliedAlpha,
+                blob_img,
+            );
+        };
+
+        // draw the new image
+        let mut builder = DisplayListBuilder::new(self.wrench.root_pipeline_id, layout_size);
+        push_image(&mut builder);
+
+        let mut epoch = Epoch(0);
+
+        self.submit_dl(&mut epoch, layout_size, builder, &txn.resource_updates);
+        let pixels_first = self.render_and_get_pixels(window_rect);
+
+        // update it with a different color and draw it again
+        let mut txn = Transaction::new();
+        txn.update_image(
+            blob_img,
+            ImageDescriptor::new(200, 200, ImageFormat::BGRA8, false, false),
+            ImageData::new_blob_image(blob::serialize_blob(ColorU::new(50, 150, 150, 255))),
+            None,
+        );
+
+        let mut builder = DisplayListBuilder::new(self.wrench.root_pipeline_id, layout_size);
+        push_image(&mut builder);
+        self.submit_dl(&mut epoch, layout_size, builder, &txn.resource_updates);
+        let pixels_second = self.render_and_get_pixels(window_rect);
+
+        // both versions were rasterized on the scene builder thread and shown right away
+        assert_eq!(requested.load(Ordering::SeqCst), 2);
+        assert_eq!(resolved.load(Ordering::SeqCst), 0);
+        assert!(pixels_first.iter().any(|&p| p != 255));
+        assert!(pixels_first != pixels_second);
+
+        // cleanup
+        let mut txn = Transaction::new();
+        txn.delete_image(blob_img);
+        self.wrench.api.update_resources(txn.resource_updates);
+
+        *self.wrench.callbacks.lock().unwrap() = blob::BlobCallbacks::new();
+    }
+
+    fn test_blob_placeholders(&mut self) {
+        println!("\tblob placeholders test...");
+        let window_size = self.window.get_inner_size();
+
+        let test_size = DeviceUintSize::new(256, 256);
+
+        let window_rect = DeviceUintRect::new(
+            point(0, window_size.height - test_size.height),
+            test_size,
+        );
+        let layout_size = LayoutSize::new(256., 256.);
+        let mut txn = Transaction::new();
+
+        let blob_img = {
+            let img = self.wrench.api.generate_image_key();
+            txn.add_image(
+                img,
+                ImageDescriptor::new(256, 256, ImageFormat::BGRA8, false, false),
+                ImageData::new_blob_image(blob::serialize_blob(ColorU::new(50, 50, 150, 255))),
+                Some(128),
+            );
+            img
+        };
+
+        let resolved = Arc::new(AtomicIsize::new(0));
+        let resolved_inner = Arc::clone(&resolved);
+
+        self.wrench.callbacks.lock().unwrap().resolve = Box::new(move || {
+            resolved_inner.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let mut builder = DisplayListBuilder::new(self.wrench.root_pipeline_id, layout_size);
+        let info = LayoutPrimitiveInfo::new(rect(0.0, 0.0, 256.0, 256.0));
+        builder.push_image(
+            &info,
+            size(256.0, 256.0),
+            size(0.0, 0.0),
+            ImageRendering::Auto,
+            AlphaType::PremultipliedAlpha,
+            blob_img,
+        );
+
+        let mut epoch = Epoch(0);
+
+        self.submit_dl(&mut epoch, layout_size, builder, &txn.resource_updates);
+
+        // the new tiles are transparent until they are rasterized
+        let pixels_placeholder = self.render_and_get_pixels(window_rect);
+        assert!(pixels_placeholder.iter().all(|&p| p == 255));
+
+        // which happens off the render backend thread, and triggers another frame
+        let pixels_rasterized = self.render_and_get_pixels(window_rect);
+        assert!(pixels_rasterized != pixels_placeholder);
+        assert_eq!(resolved.load(Ordering::SeqCst), 0);
+
+        // cleanup
+        let mut txn = Transaction::new();
+        txn.delete_image(blob_img);
+        self.wrench.api.update_resources(txn.resource_updates);
+
+        *self.wrench.callbacks.lock().unwrap() = blob::BlobCallbacks::new();
+    }
+
+    fn test_stale_async_blob(&mut self) {
+        println!("\tstale async blob test...");
+        let window_size = self.window.get_inner_size();
+
+        let test_size = DeviceUintSize::new(256, 256);
+
+        let window_rect = DeviceUintRect::new(
+            point(0, window_size.height - test_size.height),
+            test_size,
+        );
+        let layout_size = LayoutSize::new(256., 256.);
+        let mut txn = Transaction::new();
+
+        let blob_img = {
+            let img = self.wrench.api.generate_image_key();
+            txn.add_image(
+                img,
+                ImageDescriptor::new(256, 256, ImageFormat::BGRA8, false, false),
+                ImageData::new_blob_image(blob::serialize_blob(ColorU::new(50, 50, 150, 255))),
+                Some(128),
+            );
+            img
+        };
+
+        // hold back the rasterization of the placeholders until we let it go
+        let (release_tx, release_rx) = channel();
+        let release_rx = Mutex::new(release_rx);
+        let held_back = AtomicBool::new(false);
+        self.wrench.callbacks.lock().unwrap().request = Box::new(move |_| {
+            if !held_back.swap(true, Ordering::SeqCst) {
+                release_rx.lock().unwrap().recv().unwrap();
+            }
+        });
+
+        let info = LayoutPrimitiveInfo::new(rect(0.0, 0.0, 256.0, 256.0));
+        let push_image = |builder: &mut DisplayListBuilder| {
+            builder.push_image(
+                &info,
+                size(256.0, 256.0),
+                size(0.0, 0.0),
+                ImageRendering::Auto,
+                AlphaType::PremultipliedAlpha,
+                blob_img,
+            );
+        };
+
+        let mut builder = DisplayListBuilder::new(self.wrench.root_pipeline_id, layout_size);
+        push_image(&mut builder);
+
+        let mut epoch = Epoch(0);
+
+        self.submit_dl(&mut epoch, layout_size, builder, &txn.resource_updates);
+        let _pixels_placeholder = self.render_and_get_pixels(window_rect);
+
+        // update the image while the old commands are being rasterized
+        let mut txn = Transaction::new();
+        txn.update_image(
+            blob_img,
+            ImageDescriptor::new(256, 256, ImageFormat::BGRA8, false, false),
+            ImageData::new_blob_image(blob::serialize_blob(ColorU::new(50, 150, 150, 255))),
+            None,
+        );
+        self.wrench.api.update_resources(txn.resource_updates);
+        // make sure the render backend has applied the update before letting go
+        self.wrench.api.get_scroll_node_state(self.wrench.document_id);
+        release_tx.send(()).unwrap();
+
+        // the old result must be dropped rather than replace the placeholders
+        let pixels_after_update = self.render_and_get_pixels(window_rect);
+
+        // compare with the updated image rasterized on the scene builder thread
+        let mut txn = Transaction::new();
+        txn.update_image(
+            blob_img,
+            ImageDescriptor::new(256, 256, ImageFormat::BGRA8, false, false),
+            ImageData::new_blob_image(blob::serialize_blob(ColorU::new(50, 150, 150, 255))),
+            None,
+        );
+
+        let mut builder = DisplayListBuilder::new(self.wrench.root_pipeline_id, layout_size);
+        push_image(&mut builder);
+        self.submit_dl(&mut epoch, layout_size, builder, &txn.resource_updates);
+        let pixels_expected = self.render_and_get_pixels(window_rect);
+
+        assert!(pixels_after_update == pixels_expected);
+
+        // cleanup
+        let mut txn = Transaction::new();
+        txn.delete_image(blob_img);
+        self.wrench.api.update_resources(txn.resource_updates);
+
+        *self.wrench.callbacks.lock().unwrap() = blob::BlobCallbacks::new();
+    }
+
     // Ensures that content doing a save-restore produces the same results as not
     fn test_save_restore(&mut self) {
         println!("\tsave/restore...");
@@ -766,6 +1107,78 @@ impl<'a> RawtestHarness<'a> {
         assert_ne!(first, second);
     }
 
+    fn test_dynamic_properties(&mut self) {
+        println!("\tdynamic properties...");
+        let window_size = self.window.get_inner_size();
+
+        let test_size = DeviceUintSize::new(400, 400);
+
+        let window_rect = DeviceUintRect::new(
+            point(0, window_size.height - test_size.height),
+            test_size,
+        );
+        let layout_size = LayoutSize::new(400., 400.);
+
+        // the pixels are read bottom-up
+        let pixel_at = |pixels: &[u8], x: u32, y: u32| -> [u8; 4] {
+            let i = (((test_size.height - 1 - y) * test_size.width + x) * 4) as usize;
+            [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+        };
+
+        let red = ColorF::new(1.0, 0.0, 0.0, 1.0);
+        let green = ColorF::new(0.0, 1.0, 0.0, 1.0);
+        let blue = ColorF::new(0.0, 0.0, 1.0, 1.0);
+
+        let color_key = self.wrench.api.generate_property_binding_key();
+        let offset_key = self.wrench.api.generate_property_binding_key();
+
+        let mut builder = DisplayListBuilder::new(self.wrench.root_pipeline_id, layout_size);
+
+        builder.push_rect_with_animation(
+            &PrimitiveInfo::new(rect(0., 0., 100., 100.)),
+            PropertyBinding::Binding(color_key, red),
+        );
+
+        // a scroll frame showing the top half of a red rect above a blue one
+        let clip_id = builder.define_scroll_frame_with_animation(
+            None,
+            rect(200., 0., 100., 200.),
+            rect(200., 0., 100., 100.),
+            vec![],
+            None,
+            ScrollSensitivity::Script,
+            PropertyBinding::Binding(offset_key, LayoutVector2D::zero()),
+        );
+        builder.push_clip_id(clip_id);
+        builder.push_rect(&PrimitiveInfo::new(rect(200., 0., 100., 100.)), red);
+        builder.push_rect(&PrimitiveInfo::new(rect(200., 100., 100., 100.)), blue);
+        builder.pop_clip_id();
+
+        let txn = Transaction::new();
+        self.submit_dl(&mut Epoch(0), layout_size, builder, &txn.resource_updates);
+        let pixels = self.render_and_get_pixels(window_rect);
+
+        assert_eq!(pixel_at(&pixels, 50, 50), [255, 0, 0, 255]);
+        assert_eq!(pixel_at(&pixels, 250, 50), [255, 0, 0, 255]);
+
+        // update both properties without sending a new display list
+        let mut txn = Transaction::new();
+        txn.update_dynamic_properties(DynamicProperties {
+            transforms: Vec::new(),
+            floats: Vec::new(),
+            colors: vec![PropertyValue { key: color_key, value: green }],
+            scroll_offsets: vec![
+                PropertyValue { key: offset_key, value: LayoutVector2D::new(0., 100.) },
+            ],
+        });
+        txn.generate_frame();
+        self.wrench.api.send_transaction(self.wrench.document_id, txn);
+        let pixels = self.render_and_get_pixels(window_rect);
+
+        assert_eq!(pixel_at(&pixels, 50, 50), [0, 255, 0, 255]);
+        assert_eq!(pixel_at(&pixels, 250, 50), [0, 0, 255, 255]);
+    }
+
     fn test_capture(&mut self) {
         println!("\tcapture...");
         let path = "../captures/test";
@@ -36,6 +36,7 @@ const OPTION_DISABLE_SUBPX: &str = "disable-subpixel";
 const OPTION_DISABLE_AA: &str = "disable-aa";
 const OPTION_DISABLE_DUAL_SOURCE_BLENDING: &str = "disable-dual-source-blending";
 const OPTION_ALLOW_MIPMAPS: &str = "allow-mipmaps";
+const OPTION_SDF_TEXT: &str = "sdf-text";
 
 pub struct ReftestOptions {
     // These override values that are lower.
@@ -251,6 +252,9 @@ impl ReftestManifest {
                         if args.iter().any(|arg| arg == &OPTION_DISABLE_AA) {
                             font_render_mode = Some(FontRenderMode::Mono);
                         }
+                        if args.iter().any(|arg| arg == &OPTION_SDF_TEXT) {
+                            font_render_mode = Some(FontRenderMode::Sdf);
+                        }
                         if args.iter().any(|arg| arg == &OPTION_DISABLE_DUAL_SOURCE_BLENDING) {
                             disable_dual_source_blending = true;
                         }
@@ -4,16 +4,18 @@
 
 use std::collections::HashMap;
 use webrender::api::{BuiltDisplayList, ColorF, Epoch};
-use webrender::api::{LayoutSize, PipelineId};
+use webrender::api::{LayoutSize, LayoutVector2D, PipelineId};
 use webrender::api::{PropertyBinding, PropertyBindingId, LayoutTransform, DynamicProperties};
 
 /// Stores a map of the animated property bindings for the current display list. These
-/// can be used to animate the transform and/or opacity of a display list without
-/// re-submitting the display list itself.
+/// can be used to animate the transform, opacity, colors and/or scroll offsets of a
+/// display list without re-submitting the display list itself.
 #[derive(Default)]
 pub struct SceneProperties {
     transform_properties: HashMap<PropertyBindingId, LayoutTransform>,
     float_properties: HashMap<PropertyBindingId, f32>,
+    color_properties: HashMap<PropertyBindingId, ColorF>,
+    vector_properties: HashMap<PropertyBindingId, LayoutVector2D>,
 }
 
 impl SceneProperties {
@@ -21,6 +23,8 @@ impl SceneProperties {
     pub fn set_properties(&mut self, properties: &DynamicProperties) {
         self.transform_properties.clear();
         self.float_properties.clear();
+        self.color_properties.clear();
+        self.vector_properties.clear();
 
         for property in &properties.transforms {
             self.transform_properties
@@ -31,6 +35,16 @@ impl SceneProperties {
             self.float_properties
                 .insert(property.key.id, property.value);
         }
+
+        for property in &properties.colors {
+            self.color_properties
+                .insert(property.key.id, property.value);
+        }
+
+        for property in &properties.scroll_offsets {
+            self.vector_properties
+                .insert(property.key.id, property.value);
+        }
     }
 
     /// Get the current value for a transform property.
@@ -62,6 +76,28 @@ impl SceneProperties {
                 .unwrap_or(v),
         }
     }
+
+    /// Get the current value for a color property.
+    pub fn resolve_color(&self, property: &PropertyBinding<ColorF>) -> ColorF {
+        match *property {
+            PropertyBinding::Value(value) => value,
+            PropertyBinding::Binding(ref key, v) => self.color_properties
+                .get(&key.id)
+                .cloned()
+                .unwrap_or(v),
+        }
+    }
+
+    /// Get the current value for a vector property, such as a scroll offset.
+    pub fn resolve_layout_vector(&self, property: &PropertyBinding<LayoutVector2D>) -> LayoutVector2D {
+        match *property {
+            PropertyBinding::Value(value) => value,
+            PropertyBinding::Binding(ref key, v) => self.vector_properties
+                .get(&key.id)
+                .cloned()
+                .unwrap_or(v),
+        }
+    }
 }
 
 /// A representation of the layout within the display port for a given document or iframe.
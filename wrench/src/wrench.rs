@@ -12,9 +12,8 @@ use crossbeam::sync::chase_lev;
 use dwrote;
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 use font_loader::system_fonts;
-#[cfg(not(feature = "gl"))]
-use hal;
 use json_frame_writer::JsonFrameWriter;
+use rayon::ThreadPoolBuilder;
 use ron_frame_writer::RonFrameWriter;
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -208,6 +207,13 @@ impl Wrench {
         let mut debug_flags = DebugFlags::ECHO_DRIVER_MESSAGES;
         debug_flags.set(DebugFlags::DISABLE_BATCHING, no_batch);
         let callbacks = Arc::new(Mutex::new(blob::BlobCallbacks::new()));
+        // Share the worker threads between the glyph rasterizer and the blob renderer.
+        let workers = Arc::new(
+            ThreadPoolBuilder::new()
+                .thread_name(|idx| format!("WRWorker#{}", idx))
+                .build()
+                .unwrap()
+        );
 
         let opts = webrender::RendererOptions {
             device_pixel_ratio: dp_ratio,
@@ -218,7 +224,10 @@ impl Wrench {
             enable_clear_scissor: !no_scissor,
             max_recorded_profiles: 16,
             precache_shaders,
-            blob_image_renderer: Some(Box::new(blob::CheckerboardRenderer::new(callbacks.clone()))),
+            blob_image_renderer: Some(Box::new(
+                blob::CheckerboardRenderer::new(callbacks.clone(), Arc::clone(&workers))
+            )),
+            workers: Some(workers),
             disable_dual_source_blending,
             ..Default::default()
         };
@@ -272,12 +281,12 @@ impl Wrench {
         wrench
     }
 
+    /// `create_renderer` creates the renderer on the device wrench runs on,
+    /// a gfx-hal adapter or the software device.
     #[cfg(not(feature = "gl"))]
-    pub fn new(
-        window_size: (u32, u32),
+    pub fn new<F>(
+        create_renderer: F,
         proxy: Option<EventsLoopProxy>,
-        adapter: hal::Adapter<back::Backend>,
-        surface: &mut <back::Backend as hal::Backend>::Surface,
         shader_override_path: Option<PathBuf>,
         dp_ratio: f32,
         save_type: Option<SaveType>,
@@ -288,10 +297,18 @@ impl Wrench {
         no_scissor: bool,
         no_batch: bool,
         precache_shaders: bool,
+        upload_method: webrender::UploadMethod,
+        msaa_samples: u8,
         disable_dual_source_blending: bool,
         zoom_factor: f32,
         notifier: Option<Box<RenderNotifier>>,
-    ) -> Self {
+    ) -> Self
+    where
+        F: FnOnce(
+            Box<RenderNotifier>,
+            webrender::RendererOptions,
+        ) -> (webrender::Renderer<back::Backend>, RenderApiSender),
+    {
         println!("Shader override path: {:?}", shader_override_path);
 
         let recorder = save_type.map(|save_type| match save_type {
@@ -310,6 +327,13 @@ impl Wrench {
         let mut debug_flags = DebugFlags::ECHO_DRIVER_MESSAGES;
         debug_flags.set(DebugFlags::DISABLE_BATCHING, no_batch);
         let callbacks = Arc::new(Mutex::new(blob::BlobCallbacks::new()));
+        // Share the worker threads between the glyph rasterizer and the blob renderer.
+        let workers = Arc::new(
+            ThreadPoolBuilder::new()
+                .thread_name(|idx| format!("WRWorker#{}", idx))
+                .build()
+                .unwrap()
+        );
 
         let opts = webrender::RendererOptions {
             device_pixel_ratio: dp_ratio,
@@ -320,7 +344,12 @@ impl Wrench {
             enable_clear_scissor: !no_scissor,
             max_recorded_profiles: 16,
             precache_shaders,
-            blob_image_renderer: Some(Box::new(blob::CheckerboardRenderer::new(callbacks.clone()))),
+            upload_method,
+            msaa_samples,
+            blob_image_renderer: Some(Box::new(
+                blob::CheckerboardRenderer::new(callbacks.clone(), Arc::clone(&workers))
+            )),
+            workers: Some(workers),
             disable_dual_source_blending,
             ..Default::default()
         };
@@ -337,8 +366,7 @@ impl Wrench {
             Box::new(Notifier(data))
         });
 
-        //let (renderer, sender) = webrender::Renderer::new(window.clone_gl(), notifier, opts).unwrap();
-        let (renderer, sender) =  webrender::Renderer::new(notifier, &adapter, surface, window_size, opts).unwrap();
+        let (renderer, sender) = create_renderer(notifier, opts);
         let api = sender.create_api();
         let document_id = api.add_document(size, 0);
 
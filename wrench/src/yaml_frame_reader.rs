@@ -19,6 +19,16 @@ use yaml_helper::{StringEnum, YamlHelper, make_perspective};
 use yaml_rust::{Yaml, YamlLoader};
 use {BLACK_COLOR, PLATFORM_DEFAULT_FACE_NAME, WHITE_COLOR};
 
+/// The properties of a border that make it a 9-part image border.
+const NINE_PATCH_KEYS: &[&str] = &[
+    "image-width",
+    "image-height",
+    "slice",
+    "fill",
+    "repeat-horizontal",
+    "repeat-vertical",
+];
+
 fn rsrc_path(item: &Yaml, aux_dir: &PathBuf) -> PathBuf {
     let filename = item.as_str().unwrap();
     let mut file = aux_dir.clone();
@@ -161,7 +171,7 @@ fn generate_solid_color_image(
 
 fn is_image_opaque(format: ImageFormat, bytes: &[u8]) -> bool {
     match format {
-        ImageFormat::BGRA8 => {
+        ImageFormat::BGRA8 | ImageFormat::RGBA8 => {
             let mut is_opaque = true;
             for i in 0 .. (bytes.len() / 4) {
                 if bytes[i * 4 + 3] != 255 {
@@ -171,10 +181,31 @@ fn is_image_opaque(format: ImageFormat, bytes: &[u8]) -> bool {
             }
             is_opaque
         }
-        ImageFormat::RG8 => true,
+        ImageFormat::RG8 | ImageFormat::R16 => true,
         ImageFormat::R8 => false,
-        ImageFormat::RGBAF32 => unreachable!(),
+        ImageFormat::RGBAF32 | ImageFormat::RGBAI32 => unreachable!(),
+    }
+}
+
+// `DynamicImage` has no 16 bit variants, so high bit depth planes
+// are decoded with the PNG decoder directly.
+fn load_gray16_png(file: &Path, allow_mipmaps: bool) -> (ImageDescriptor, ImageData) {
+    use image::ImageDecoder;
+
+    let mut decoder = image::png::PNGDecoder::new(File::open(file).unwrap());
+    let (width, height) = decoder.dimensions().unwrap();
+    let mut bytes = match decoder.read_image().unwrap() {
+        image::DecodingResult::U8(bytes) => bytes,
+        image::DecodingResult::U16(..) => panic!("Unexpected 16 bit samples for {:?}", file),
+    };
+    // PNG stores 16 bit samples in big endian order.
+    if cfg!(target_endian = "little") {
+        for sample in bytes.chunks_mut(2) {
+            sample.swap(0, 1);
+        }
     }
+    let descriptor = ImageDescriptor::new(width, height, ImageFormat::R16, true, allow_mipmaps);
+    (descriptor, ImageData::new(bytes))
 }
 
 pub struct YamlFrameReader {
@@ -451,6 +482,9 @@ impl YamlFrameReader {
                 let data = ImageData::new(bytes);
                 (descriptor, data)
             }
+            Err(image::ImageError::UnsupportedColor(image::ColorType::Gray(16))) => {
+                load_gray16_png(file, self.allow_mipmaps)
+            }
             _ => {
                 // This is a hack but it is convenient when generating test cases and avoids
                 // bloating the repository.
@@ -609,6 +643,77 @@ impl YamlFrameReader {
         })
     }
 
+    fn to_mask_layers(
+        &mut self,
+        item: &Yaml,
+        wrench: &mut Wrench,
+    ) -> (Vec<MaskLayer>, Vec<GradientStop>) {
+        let mut layers = Vec::new();
+        let mut stops = Vec::new();
+
+        for layer in item.as_vec().unwrap_or(&Vec::new()) {
+            let (source, size) = if let Some(filename) = layer["image"].as_str() {
+                let mut file = self.aux_dir.clone();
+                file.push(filename);
+                let (image_key, image_dims) = self.add_or_get_image(&file, None, wrench);
+                (MaskSource::Image(image_key), Some(image_dims))
+            } else {
+                let gradient = &layer["gradient"];
+                let layer_stops = gradient["stops"]
+                    .as_vec()
+                    .expect("mask layer must have an image or a gradient")
+                    .chunks(2)
+                    .map(|chunk| {
+                        GradientStop {
+                            offset: chunk[0]
+                                .as_force_f32()
+                                .expect("gradient stop offset is not f32"),
+                            color: chunk[1]
+                                .as_colorf()
+                                .expect("gradient stop color is not color"),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let source = MaskSource::Gradient {
+                    gradient: Gradient {
+                        start_point: gradient["start"].as_point().expect("gradient must have start"),
+                        end_point: gradient["end"].as_point().expect("gradient must have end"),
+                        extend_mode: if gradient["repeat"].as_bool().unwrap_or(false) {
+                            ExtendMode::Repeat
+                        } else {
+                            ExtendMode::Clamp
+                        },
+                    },
+                    stop_count: layer_stops.len(),
+                };
+                stops.extend(layer_stops);
+                (source, None)
+            };
+
+            let rect = layer["bounds"]
+                .as_rect()
+                .or(size.map(|size| LayoutRect::new(LayoutPoint::zero(), size)))
+                .expect("mask layer must have bounds");
+
+            layers.push(MaskLayer {
+                source,
+                rect,
+                tile_size: layer["tile-size"].as_size().unwrap_or(rect.size),
+                tile_spacing: layer["tile-spacing"].as_size().unwrap_or(LayoutSize::zero()),
+                mode: layer["mode"]
+                    .as_str()
+                    .and_then(MaskMode::from_str)
+                    .unwrap_or(MaskMode::Alpha),
+                composite: layer["composite"]
+                    .as_str()
+                    .and_then(MaskComposite::from_str)
+                    .unwrap_or(MaskComposite::Add),
+            });
+        }
+
+        (layers, stops)
+    }
+
     fn to_gradient(&mut self, dl: &mut DisplayListBuilder, item: &Yaml) -> Gradient {
         let start = item["start"].as_point().expect("gradient must have start");
         let end = item["end"].as_point().expect("gradient must have end");
@@ -663,6 +768,33 @@ impl YamlFrameReader {
         dl.create_radial_gradient(center, radius, stops, extend_mode)
     }
 
+    fn to_conic_gradient(&mut self, dl: &mut DisplayListBuilder, item: &Yaml) -> ConicGradient {
+        let center = item["center"].as_point().expect("conic gradient must have center");
+        let angle = item["angle"].as_force_f32().unwrap_or(0.0).to_radians();
+        let stops = item["stops"]
+            .as_vec()
+            .expect("conic gradient must have stops")
+            .chunks(2)
+            .map(|chunk| {
+                GradientStop {
+                    offset: chunk[0]
+                        .as_force_f32()
+                        .expect("gradient stop offset is not f32"),
+                    color: chunk[1]
+                        .as_colorf()
+                        .expect("gradient stop color is not color"),
+                }
+            })
+            .collect::<Vec<_>>();
+        let extend_mode = if item["repeat"].as_bool().unwrap_or(false) {
+            ExtendMode::Repeat
+        } else {
+            ExtendMode::Clamp
+        };
+
+        dl.create_conic_gradient(center, angle, stops, extend_mode)
+    }
+
     fn handle_rect(
         &mut self,
         dl: &mut DisplayListBuilder,
@@ -753,6 +885,45 @@ impl YamlFrameReader {
         );
     }
 
+    fn handle_path(
+        &mut self,
+        dl: &mut DisplayListBuilder,
+        item: &Yaml,
+        info: &mut LayoutPrimitiveInfo,
+    ) {
+        info.rect = item["bounds"].as_rect().expect("path must have bounds");
+        let path = item["path"].as_path().expect("path must have path data");
+        let fill_rule = item["fill-rule"]
+            .as_str()
+            .and_then(FillRule::from_str)
+            .unwrap_or(FillRule::Nonzero);
+
+        let stroke = item["stroke-width"].as_force_f32().map(|width| {
+            StrokeStyle {
+                width,
+                join: item["line-join"]
+                    .as_str()
+                    .and_then(LineJoin::from_str)
+                    .unwrap_or(LineJoin::Miter),
+                cap: item["line-cap"]
+                    .as_str()
+                    .and_then(LineCap::from_str)
+                    .unwrap_or(LineCap::Butt),
+                miter_limit: item["miter-limit"].as_force_f32().unwrap_or(4.0),
+                dash_offset: item["dash-offset"].as_force_f32().unwrap_or(0.0),
+            }
+        });
+        let dashes = item["dashes"].as_vec_f32().unwrap_or(Vec::new());
+
+        let paint = if item["stops"].is_badvalue() {
+            PathPaint::Color(item["color"].as_colorf().unwrap_or(*BLACK_COLOR))
+        } else {
+            PathPaint::Gradient(self.to_gradient(dl, item))
+        };
+
+        dl.push_path(&info, path, fill_rule, stroke, &dashes, paint);
+    }
+
     fn handle_gradient(
         &mut self,
         dl: &mut DisplayListBuilder,
@@ -797,6 +968,28 @@ impl YamlFrameReader {
         dl.push_radial_gradient(&info, gradient, tile_size, tile_spacing);
     }
 
+    fn handle_conic_gradient(
+        &mut self,
+        dl: &mut DisplayListBuilder,
+        item: &Yaml,
+        info: &mut LayoutPrimitiveInfo,
+    ) {
+        let bounds_key = if item["type"].is_badvalue() {
+            "conic-gradient"
+        } else {
+            "bounds"
+        };
+        let bounds = item[bounds_key]
+            .as_rect()
+            .expect("conic gradient must have bounds");
+        info.rect = bounds;
+        let gradient = self.to_conic_gradient(dl, item);
+        let tile_size = item["tile-size"].as_size().unwrap_or(bounds.size);
+        let tile_spacing = item["tile-spacing"].as_size().unwrap_or(LayoutSize::zero());
+
+        dl.push_conic_gradient(&info, gradient, tile_size, tile_spacing);
+    }
+
     fn handle_border(
         &mut self,
         dl: &mut DisplayListBuilder,
@@ -880,26 +1073,80 @@ impl YamlFrameReader {
                         radius,
                     }))
                 }
-                "image" => {
-                    let file = rsrc_path(&item["image-source"], &self.aux_dir);
-                    let (image_key, _) = self
-                        .add_or_get_image(&file, None, wrench);
+                // Gradients without any of the properties of a 9-part
+                // image cover the whole border.
+                "gradient" | "radial-gradient" if NINE_PATCH_KEYS
+                    .iter()
+                    .all(|key| item[*key].is_badvalue()) => {
+                    let outset = item["outset"]
+                        .as_vec_f32()
+                        .unwrap_or(vec![0.0]);
+                    let outset = broadcast(&outset, 4);
+                    let outset = SideOffsets2D::new(outset[0], outset[1], outset[2], outset[3]);
+                    if border_type == "gradient" {
+                        Some(BorderDetails::Gradient(GradientBorder {
+                            gradient: self.to_gradient(dl, item),
+                            outset,
+                        }))
+                    } else {
+                        Some(BorderDetails::RadialGradient(RadialGradientBorder {
+                            gradient: self.to_radial_gradient(dl, item),
+                            outset,
+                        }))
+                    }
+                }
+                "image" | "gradient" | "radial-gradient" => {
+                    let outset = item["outset"]
+                        .as_vec_f32()
+                        .unwrap_or(vec![0.0]);
+                    let outset = broadcast(&outset, 4);
+                    let outset = SideOffsets2D::new(outset[0], outset[1], outset[2], outset[3]);
+
+                    // Gradient sources are laid out over the outset border
+                    // box unless an explicit nine-patch size is given.
+                    let outset_size = LayoutSize::new(
+                        info.rect.size.width + outset.left + outset.right,
+                        info.rect.size.height + outset.top + outset.bottom,
+                    );
+                    let (source, default_size) = match border_type {
+                        "image" => {
+                            let file = rsrc_path(&item["image-source"], &self.aux_dir);
+                            let (image_key, image_size) = self
+                                .add_or_get_image(&file, None, wrench);
+                            (NinePatchBorderSource::Image(image_key), image_size)
+                        }
+                        "gradient" => {
+                            let gradient = self.to_gradient(dl, item);
+                            (NinePatchBorderSource::Gradient(gradient), outset_size)
+                        }
+                        _ => {
+                            let gradient = self.to_radial_gradient(dl, item);
+                            (NinePatchBorderSource::RadialGradient(gradient), outset_size)
+                        }
+                    };
+
                     let image_width = item["image-width"]
                         .as_i64()
-                        .expect("border must have image-width");
+                        .unwrap_or(default_size.width as i64);
                     let image_height = item["image-height"]
                         .as_i64()
-                        .expect("border must have image-height");
+                        .unwrap_or(default_size.height as i64);
                     let fill = item["fill"].as_bool().unwrap_or(false);
-                    let slice = item["slice"].as_vec_u32().expect("border must have slice");
-                    let slice = broadcast(&slice, 4);
-                    let outset = item["outset"]
-                        .as_vec_f32()
-                        .expect("border must have outset");
-                    let outset = broadcast(&outset, 4);
+                    let slice = match item["slice"].as_vec_u32() {
+                        Some(slice) => {
+                            let slice = broadcast(&slice, 4);
+                            SideOffsets2D::new(slice[0], slice[1], slice[2], slice[3])
+                        }
+                        None => SideOffsets2D::new(
+                            widths.top as u32,
+                            widths.right as u32,
+                            widths.bottom as u32,
+                            widths.left as u32,
+                        ),
+                    };
                     let repeat_horizontal = match item["repeat-horizontal"]
                         .as_str()
-                        .expect("border must have repeat-horizontal")
+                        .unwrap_or("stretch")
                     {
                         "stretch" => RepeatMode::Stretch,
                         "repeat" => RepeatMode::Repeat,
@@ -909,7 +1156,7 @@ impl YamlFrameReader {
                     };
                     let repeat_vertical = match item["repeat-vertical"]
                         .as_str()
-                        .expect("border must have repeat-vertical")
+                        .unwrap_or("stretch")
                     {
                         "stretch" => RepeatMode::Stretch,
                         "repeat" => RepeatMode::Repeat,
@@ -918,36 +1165,14 @@ impl YamlFrameReader {
                         s => panic!("Unknown box border image repeat mode {}", s),
                     };
                     Some(BorderDetails::NinePatch(NinePatchBorder {
-                        source: NinePatchBorderSource::Image(image_key),
+                        source,
                         width: image_width as u32,
                         height: image_height as u32,
-                        slice: SideOffsets2D::new(slice[0], slice[1], slice[2], slice[3]),
+                        slice,
                         fill,
                         repeat_horizontal,
                         repeat_vertical,
-                        outset: SideOffsets2D::new(outset[0], outset[1], outset[2], outset[3]),
-                    }))
-                }
-                "gradient" => {
-                    let gradient = self.to_gradient(dl, item);
-                    let outset = item["outset"]
-                        .as_vec_f32()
-                        .expect("borders must have outset");
-                    let outset = broadcast(&outset, 4);
-                    Some(BorderDetails::Gradient(GradientBorder {
-                        gradient,
-                        outset: SideOffsets2D::new(outset[0], outset[1], outset[2], outset[3]),
-                    }))
-                }
-                "radial-gradient" => {
-                    let gradient = self.to_radial_gradient(dl, item);
-                    let outset = item["outset"]
-                        .as_vec_f32()
-                        .expect("borders must have outset");
-                    let outset = broadcast(&outset, 4);
-                    Some(BorderDetails::RadialGradient(RadialGradientBorder {
-                        gradient,
-                        outset: SideOffsets2D::new(outset[0], outset[1], outset[2], outset[3]),
+                        outset,
                     }))
                 }
                 _ => {
@@ -1232,6 +1457,49 @@ impl YamlFrameReader {
         info.rect = rect;
 
         dl.push_text(&info, &glyphs, font_instance_key, color, None);
+
+        if let Some(decorations) = item["decorations"].as_vec() {
+            let decoration_color = item["decoration-color"].as_colorf().unwrap_or(color);
+            let thickness = item["decoration-thickness"].as_f32();
+            let skip_ink = item["skip-ink"].as_bool().unwrap_or(true);
+
+            for decoration in decorations {
+                let kind = decoration
+                    .as_str()
+                    .map(|name| text_decoration_kind(name, item))
+                    .expect("Unknown text decoration");
+                // Emphasis marks are drawn outside of the glyph bounds.
+                let mut decoration_info = *info;
+                if let TextDecorationKind::Emphasis(..) = kind {
+                    decoration_info.rect = info.rect.inflate(0.0, size.to_f32_px());
+                }
+                dl.push_text_decoration(
+                    &decoration_info,
+                    kind,
+                    decoration_color,
+                    thickness,
+                    skip_ink,
+                );
+            }
+        }
+    }
+
+    fn handle_text_decoration(
+        &mut self,
+        dl: &mut DisplayListBuilder,
+        item: &Yaml,
+        info: &mut LayoutPrimitiveInfo,
+    ) {
+        info.rect = item["bounds"].as_rect().expect("text decoration must have bounds");
+        let kind = item["decoration"]
+            .as_str()
+            .map(|name| text_decoration_kind(name, item))
+            .expect("text decoration must have a decoration");
+        let color = item["color"].as_colorf().unwrap_or(*BLACK_COLOR);
+        let thickness = item["thickness"].as_f32();
+        let skip_ink = item["skip-ink"].as_bool().unwrap_or(true);
+
+        dl.push_text_decoration(&info, kind, color, thickness, skip_ink);
     }
 
     fn handle_iframe(
@@ -1332,13 +1600,16 @@ impl YamlFrameReader {
                 "image" => self.handle_image(dl, wrench, item, &mut info),
                 "yuv-image" => self.handle_yuv_image(dl, wrench, item, &mut info),
                 "text" | "glyphs" => self.handle_text(dl, wrench, item, &mut info),
+                "text-decoration" => self.handle_text_decoration(dl, item, &mut info),
                 "scroll-frame" => self.handle_scroll_frame(dl, wrench, item),
                 "sticky-frame" => self.handle_sticky_frame(dl, wrench, item),
                 "clip" => self.handle_clip(dl, wrench, item),
                 "clip-chain" => self.handle_clip_chain(dl, item),
                 "border" => self.handle_border(dl, wrench, item, &mut info),
+                "path" => self.handle_path(dl, item, &mut info),
                 "gradient" => self.handle_gradient(dl, item, &mut info),
                 "radial-gradient" => self.handle_radial_gradient(dl, item, &mut info),
+                "conic-gradient" => self.handle_conic_gradient(dl, item, &mut info),
                 "box-shadow" => self.handle_box_shadow(dl, item, &mut info),
                 "iframe" => self.handle_iframe(dl, item, &mut info),
                 "stacking-context" => {
@@ -1483,10 +1754,17 @@ impl YamlFrameReader {
     pub fn handle_clip(&mut self, dl: &mut DisplayListBuilder, wrench: &mut Wrench, yaml: &Yaml) {
         let clip_rect = yaml["bounds"].as_rect().expect("clip must have a bounds");
         let numeric_id = yaml["id"].as_i64();
-        let complex_clips = self.to_complex_clip_regions(&yaml["complex"]);
-        let image_mask = self.to_image_mask(&yaml["image-mask"], wrench);
-
-        let real_id = dl.define_clip(clip_rect, complex_clips, image_mask);
+        let real_id = if let Some(path) = yaml["path"].as_path() {
+            let fill_rule = yaml["fill-rule"]
+                .as_str()
+                .and_then(StringEnum::from_str)
+                .unwrap_or(FillRule::Nonzero);
+            dl.define_clip_path(clip_rect, path, fill_rule)
+        } else {
+            let complex_clips = self.to_complex_clip_regions(&yaml["complex"]);
+            let image_mask = self.to_image_mask(&yaml["image-mask"], wrench);
+            dl.define_clip(clip_rect, complex_clips, image_mask)
+        };
         if let Some(numeric_id) = numeric_id {
             self.add_clip_id_mapping(numeric_id as u64, real_id);
         }
@@ -1611,18 +1889,33 @@ impl YamlFrameReader {
         }
 
         let filters = yaml["filters"].as_vec_filter_op().unwrap_or(vec![]);
+        let backdrop_filters = yaml["backdrop-filters"].as_vec_filter_op().unwrap_or(vec![]);
+        let filter_primitives = match yaml["filter-primitives"] {
+            Yaml::BadValue => vec![],
+            ref primitives => primitives.as_vec_filter_primitive().unwrap_or_else(|| {
+                println!("Ignoring invalid filter primitives: {:?}", primitives);
+                vec![]
+            }),
+        };
+        let (mask_layers, mask_stops) = self.to_mask_layers(&yaml["mask-layers"], wrench);
 
         if let Some(reference_frame_id) = reference_frame_id {
             dl.push_clip_id(reference_frame_id);
         }
 
-        dl.push_stacking_context(
+        dl.push_stacking_context_with_effects(
             &info,
             clip_node_id,
             transform_style,
             mix_blend_mode,
             filters,
             glyph_raster_space,
+            StackingContextEffects {
+                backdrop_filters,
+                filter_primitives,
+                mask_layers,
+                mask_stops,
+            },
         );
 
         if !yaml["items"].is_badvalue() {
@@ -1673,3 +1966,24 @@ impl WrenchThing for YamlFrameReader {
         self.queue_depth
     }
 }
+
+/// Parses a text decoration, reading the emphasis mark from the keys of `item`.
+fn text_decoration_kind(name: &str, item: &Yaml) -> TextDecorationKind {
+    match name {
+        "underline" => TextDecorationKind::Underline,
+        "overline" => TextDecorationKind::Overline,
+        "line-through" => TextDecorationKind::LineThrough,
+        "emphasis" => TextDecorationKind::Emphasis(EmphasisMark {
+            shape: item["emphasis-mark"]
+                .as_str()
+                .and_then(EmphasisMarkShape::from_str)
+                .unwrap_or(EmphasisMarkShape::Dot),
+            open: item["emphasis-open"].as_bool().unwrap_or(false),
+            position: item["emphasis-position"]
+                .as_str()
+                .and_then(EmphasisMarkPosition::from_str)
+                .unwrap_or(EmphasisMarkPosition::Over),
+        }),
+        _ => panic!("Unknown text decoration '{}'", name),
+    }
+}
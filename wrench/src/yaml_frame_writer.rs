@@ -202,6 +202,8 @@ fn write_stacking_context(
     sc: &StackingContext,
     properties: &SceneProperties,
     filter_iter: AuxIter<FilterOp>,
+    backdrop_filter_iter: AuxIter<FilterOp>,
+    filter_primitive_iter: AuxIter<FilterPrimitive>,
     clip_id_mapper: &ClipIdMapper,
 ) {
     enum_node(parent, "transform-style", sc.transform_style);
@@ -224,7 +226,20 @@ fn write_stacking_context(
     if sc.mix_blend_mode != MixBlendMode::Normal {
         enum_node(parent, "mix-blend-mode", sc.mix_blend_mode)
     }
-    // filters
+    yaml_node(parent, "filters", Yaml::Array(filters_to_yaml(filter_iter, properties)));
+
+    let backdrop_filters = filters_to_yaml(backdrop_filter_iter, properties);
+    if !backdrop_filters.is_empty() {
+        yaml_node(parent, "backdrop-filters", Yaml::Array(backdrop_filters));
+    }
+
+    let filter_primitives = filter_primitives_to_yaml(filter_primitive_iter);
+    if !filter_primitives.is_empty() {
+        yaml_node(parent, "filter-primitives", Yaml::Array(filter_primitives));
+    }
+}
+
+fn filters_to_yaml(filter_iter: AuxIter<FilterOp>, properties: &SceneProperties) -> Vec<Yaml> {
     let mut filters = vec![];
     for filter in filter_iter {
         match filter {
@@ -252,7 +267,144 @@ fn write_stacking_context(
         }
     }
 
-    yaml_node(parent, "filters", Yaml::Array(filters));
+    filters
+}
+
+fn path_to_string(path: AuxIter<PathCommand>) -> String {
+    let commands: Vec<String> = path.map(|command| match command {
+        PathCommand::MoveTo(p) => format!("M {} {}", p.x, p.y),
+        PathCommand::LineTo(p) => format!("L {} {}", p.x, p.y),
+        PathCommand::QuadraticCurveTo(c, p) => format!("Q {} {} {} {}", c.x, c.y, p.x, p.y),
+        PathCommand::CubicCurveTo(c1, c2, p) => {
+            format!("C {} {} {} {} {} {}", c1.x, c1.y, c2.x, c2.y, p.x, p.y)
+        }
+        PathCommand::Close => "Z".to_owned(),
+    }).collect();
+    commands.join(" ")
+}
+
+fn filter_input_node(parent: &mut Table, key: &str, input: FilterPrimitiveInput) {
+    match input {
+        FilterPrimitiveInput::Original => str_node(parent, key, "original"),
+        FilterPrimitiveInput::Previous => str_node(parent, key, "previous"),
+        FilterPrimitiveInput::OutputOfPrimitiveIndex(index) => usize_node(parent, key, index),
+    }
+}
+
+fn transfer_function_yaml(function: &TransferFunction) -> Yaml {
+    let mut table = new_table();
+    match *function {
+        TransferFunction::Identity => {
+            str_node(&mut table, "type", "identity");
+        }
+        TransferFunction::Table(ref values) => {
+            str_node(&mut table, "type", "table");
+            f32_vec_node(&mut table, "values", values);
+        }
+        TransferFunction::Discrete(ref values) => {
+            str_node(&mut table, "type", "discrete");
+            f32_vec_node(&mut table, "values", values);
+        }
+        TransferFunction::Linear { slope, intercept } => {
+            str_node(&mut table, "type", "linear");
+            f32_node(&mut table, "slope", slope);
+            f32_node(&mut table, "intercept", intercept);
+        }
+        TransferFunction::Gamma { amplitude, exponent, offset } => {
+            str_node(&mut table, "type", "gamma");
+            f32_node(&mut table, "amplitude", amplitude);
+            f32_node(&mut table, "exponent", exponent);
+            f32_node(&mut table, "offset", offset);
+        }
+    }
+    Yaml::Hash(table)
+}
+
+fn filter_primitives_to_yaml(filter_primitive_iter: AuxIter<FilterPrimitive>) -> Vec<Yaml> {
+    let mut primitives = vec![];
+    for primitive in filter_primitive_iter {
+        let mut v = new_table();
+        match primitive.kind {
+            FilterPrimitiveKind::Identity(input) => {
+                str_node(&mut v, "type", "identity");
+                filter_input_node(&mut v, "in", input);
+            }
+            FilterPrimitiveKind::Flood(color) => {
+                str_node(&mut v, "type", "flood");
+                color_node(&mut v, "color", color);
+            }
+            FilterPrimitiveKind::Offset(input, offset) => {
+                str_node(&mut v, "type", "offset");
+                filter_input_node(&mut v, "in", input);
+                vector_node(&mut v, "offset", &offset);
+            }
+            FilterPrimitiveKind::Blur(input, radius) => {
+                str_node(&mut v, "type", "blur");
+                filter_input_node(&mut v, "in", input);
+                f32_node(&mut v, "radius", radius);
+            }
+            FilterPrimitiveKind::ColorMatrix(input, ref matrix) => {
+                str_node(&mut v, "type", "color-matrix");
+                filter_input_node(&mut v, "in", input);
+                f32_vec_node(&mut v, "matrix", matrix);
+            }
+            FilterPrimitiveKind::ComponentTransfer(input, ref functions) => {
+                str_node(&mut v, "type", "component-transfer");
+                filter_input_node(&mut v, "in", input);
+                for (key, function) in ["func-r", "func-g", "func-b", "func-a"].iter().zip(functions.iter()) {
+                    yaml_node(&mut v, key, transfer_function_yaml(function));
+                }
+            }
+            FilterPrimitiveKind::Composite(input, input2, operator) => {
+                str_node(&mut v, "type", "composite");
+                filter_input_node(&mut v, "in", input);
+                filter_input_node(&mut v, "in2", input2);
+                let operator = match operator {
+                    CompositeOperator::Over => "over",
+                    CompositeOperator::In => "in",
+                    CompositeOperator::Out => "out",
+                    CompositeOperator::Atop => "atop",
+                    CompositeOperator::Xor => "xor",
+                    CompositeOperator::Arithmetic(ref k) => {
+                        f32_vec_node(&mut v, "k", k);
+                        "arithmetic"
+                    }
+                };
+                str_node(&mut v, "operator", operator);
+            }
+            FilterPrimitiveKind::Blend(input, input2, mode) => {
+                str_node(&mut v, "type", "blend");
+                filter_input_node(&mut v, "in", input);
+                filter_input_node(&mut v, "in2", input2);
+                enum_node(&mut v, "mode", mode);
+            }
+            FilterPrimitiveKind::Morphology(input, operator, radius) => {
+                str_node(&mut v, "type", "morphology");
+                filter_input_node(&mut v, "in", input);
+                enum_node(&mut v, "operator", operator);
+                size_node(&mut v, "radius", &radius);
+            }
+            FilterPrimitiveKind::Turbulence { base_frequency, num_octaves, seed, kind } => {
+                str_node(&mut v, "type", "turbulence");
+                f32_vec_node(&mut v, "base-frequency", &[base_frequency.0, base_frequency.1]);
+                u32_node(&mut v, "num-octaves", num_octaves);
+                yaml_node(&mut v, "seed", Yaml::Integer(seed as i64));
+                enum_node(&mut v, "kind", kind);
+            }
+            FilterPrimitiveKind::DisplacementMap { input, map, scale, x_channel, y_channel } => {
+                str_node(&mut v, "type", "displacement-map");
+                filter_input_node(&mut v, "in", input);
+                filter_input_node(&mut v, "in2", map);
+                f32_node(&mut v, "scale", scale);
+                enum_node(&mut v, "x-channel", x_channel);
+                enum_node(&mut v, "y-channel", y_channel);
+            }
+        }
+        enum_node(&mut v, "color-space", primitive.color_space);
+        primitives.push(Yaml::Hash(v));
+    }
+
+    primitives
 }
 
 #[cfg(target_os = "windows")]
@@ -328,6 +480,30 @@ fn radial_gradient_to_yaml(
     bool_node(table, "repeat", gradient.extend_mode == ExtendMode::Repeat);
 }
 
+fn conic_gradient_to_yaml(
+    table: &mut Table,
+    gradient: &webrender::api::ConicGradient,
+    stops_range: ItemRange<GradientStop>,
+    display_list: &BuiltDisplayList
+) {
+    point_node(table, "center", &gradient.center);
+    f32_node(table, "angle", gradient.angle.to_degrees());
+
+    let first_offset = gradient.start_offset;
+    let last_offset = gradient.end_offset;
+    let stops_delta = last_offset - first_offset;
+    assert!(first_offset <= last_offset);
+
+    let mut denormalized_stops = vec![];
+    for stop in display_list.get(stops_range) {
+        let denormalized_stop = (stop.offset * stops_delta) + first_offset;
+        denormalized_stops.push(Yaml::Real(denormalized_stop.to_string()));
+        denormalized_stops.push(Yaml::String(color_to_string(stop.color)));
+    }
+    yaml_node(table, "stops", Yaml::Array(denormalized_stops));
+    bool_node(table, "repeat", gradient.extend_mode == ExtendMode::Repeat);
+}
+
 enum CachedFont {
     Native(NativeFontHandle, Option<PathBuf>),
     Raw(Option<Vec<u8>>, u32, Option<PathBuf>),
@@ -380,6 +556,8 @@ pub struct YamlFrameWriter {
     pipeline_id: Option<PipelineId>,
 
     dl_descriptor: Option<BuiltDisplayListDescriptor>,
+    /// The ops of the display list delta being written, if any.
+    dl_delta_ops: Option<Vec<DisplayListDeltaOp>>,
 }
 
 pub struct YamlFrameWriterReceiver {
@@ -422,6 +600,7 @@ impl YamlFrameWriter {
             font_instances: HashMap::new(),
 
             dl_descriptor: None,
+            dl_delta_ops: None,
 
             pipeline_id: None,
 
@@ -446,17 +625,63 @@ impl YamlFrameWriter {
         }
 
         self.dl_descriptor = Some(display_list.clone());
+        self.dl_delta_ops = None;
         self.pipeline_id = Some(pipeline_id.clone());
 
         scene.begin_display_list(pipeline_id, epoch, background_color, viewport_size);
     }
 
+    /// Like `begin_write_display_list`, but the payload is a delta that is
+    /// applied to the last display list of the pipeline before it's written.
+    pub fn begin_write_display_list_delta(
+        &mut self,
+        scene: &mut Scene,
+        epoch: &Epoch,
+        pipeline_id: &PipelineId,
+        background_color: &Option<ColorF>,
+        viewport_size: &LayoutSize,
+        display_list: &BuiltDisplayListDescriptor,
+        ops: &[DisplayListDeltaOp],
+    ) {
+        self.begin_write_display_list(
+            scene,
+            epoch,
+            pipeline_id,
+            background_color,
+            viewport_size,
+            display_list,
+        );
+        if self.dl_descriptor.is_some() {
+            self.dl_delta_ops = Some(ops.to_vec());
+        }
+    }
+
     pub fn finish_write_display_list(&mut self, scene: &mut Scene, data: &[u8]) {
         let dl_desc = self.dl_descriptor.take().unwrap();
 
         let payload = Payload::from_data(data);
 
         let dl = BuiltDisplayList::from_data(payload.display_list_data, dl_desc);
+        let dl = match self.dl_delta_ops.take() {
+            Some(ops) => {
+                let pipeline_id = self.pipeline_id.unwrap();
+                let merged = match scene.display_lists.get(&pipeline_id) {
+                    Some(base) => base.apply_delta(&dl, &ops),
+                    None => {
+                        println!("Skipping display list delta for {:?} without a base", pipeline_id);
+                        return;
+                    }
+                };
+                match merged {
+                    Ok(merged) => merged,
+                    Err(err) => {
+                        println!("Skipping display list delta for {:?}: {:?}", pipeline_id, err);
+                        return;
+                    }
+                }
+            }
+            None => dl,
+        };
 
         let mut root_dl_table = new_table();
         {
@@ -613,8 +838,9 @@ impl YamlFrameWriter {
 
         assert!(data.stride > 0);
         let (color_type, bpp) = match data.format {
-            ImageFormat::BGRA8 => (ColorType::RGBA(8), 4),
+            ImageFormat::BGRA8 | ImageFormat::RGBA8 => (ColorType::RGBA(8), 4),
             ImageFormat::R8 => (ColorType::Gray(8), 1),
+            ImageFormat::R16 => (ColorType::Gray(16), 2),
             _ => {
                 println!(
                     "Failed to write image with format {:?}, dimensions {}x{}, stride {}",
@@ -627,27 +853,38 @@ impl YamlFrameWriter {
             }
         };
 
-        if data.stride == data.width * bpp {
-            if data.format == ImageFormat::BGRA8 {
-                unpremultiply(bytes.as_mut_slice());
-            }
-            save_buffer(&path_file, &bytes, data.width, data.height, color_type).unwrap();
-        } else {
+        if data.stride != data.width * bpp {
             // takes a buffer with a stride and copies it into a new buffer that has stride == width
             assert!(data.stride > data.width * bpp);
-            let mut tmp: Vec<_> = bytes[..]
+            bytes = bytes[..]
                 .chunks(data.stride as usize)
                 .flat_map(|chunk| {
                     chunk[.. (data.width * bpp) as usize].iter().cloned()
                 })
                 .collect();
-            if data.format == ImageFormat::BGRA8 {
-                unpremultiply(tmp.as_mut_slice());
-            }
+        }
 
-            save_buffer(&path_file, &tmp, data.width, data.height, color_type).unwrap();
+        match data.format {
+            ImageFormat::BGRA8 => unpremultiply(bytes.as_mut_slice()),
+            // `unpremultiply` reads BGRA texels and writes them as RGBA,
+            // so RGBA8 texels are swizzled to BGRA first.
+            ImageFormat::RGBA8 => {
+                for texel in bytes.chunks_mut(4) {
+                    texel.swap(0, 2);
+                }
+                unpremultiply(bytes.as_mut_slice());
+            }
+            // PNG stores 16 bit samples in big endian order.
+            ImageFormat::R16 if cfg!(target_endian = "little") => {
+                for sample in bytes.chunks_mut(2) {
+                    sample.swap(0, 1);
+                }
+            }
+            _ => {}
         }
 
+        save_buffer(&path_file, &bytes, data.width, data.height, color_type).unwrap();
+
         data.path = Some(path.clone());
         Some(path)
     }
@@ -699,6 +936,53 @@ impl YamlFrameWriter {
         Some(Yaml::Hash(mask_table))
     }
 
+    fn make_mask_layers_node(
+        &mut self,
+        layers: AuxIter<MaskLayer>,
+        mut stops: AuxIter<GradientStop>,
+    ) -> Option<Yaml> {
+        let mut mask_layers = vec![];
+        for layer in layers {
+            let mut layer_table = new_table();
+            match layer.source {
+                MaskSource::Image(key) => {
+                    if let Some(path) = self.path_for_image(key) {
+                        path_node(&mut layer_table, "image", &path);
+                    }
+                }
+                MaskSource::Gradient { gradient, stop_count } => {
+                    let mut gradient_table = new_table();
+                    point_node(&mut gradient_table, "start", &gradient.start_point);
+                    point_node(&mut gradient_table, "end", &gradient.end_point);
+                    let mut gradient_stops = vec![];
+                    for stop in stops.by_ref().take(stop_count) {
+                        gradient_stops.push(Yaml::Real(stop.offset.to_string()));
+                        gradient_stops.push(Yaml::String(color_to_string(stop.color)));
+                    }
+                    yaml_node(&mut gradient_table, "stops", Yaml::Array(gradient_stops));
+                    bool_node(
+                        &mut gradient_table,
+                        "repeat",
+                        gradient.extend_mode == ExtendMode::Repeat,
+                    );
+                    table_node(&mut layer_table, "gradient", gradient_table);
+                }
+            }
+            rect_node(&mut layer_table, "bounds", &layer.rect);
+            size_node(&mut layer_table, "tile-size", &layer.tile_size);
+            size_node(&mut layer_table, "tile-spacing", &layer.tile_spacing);
+            enum_node(&mut layer_table, "mode", layer.mode);
+            enum_node(&mut layer_table, "composite", layer.composite);
+            mask_layers.push(Yaml::Hash(layer_table));
+        }
+
+        if mask_layers.is_empty() {
+            None
+        } else {
+            Some(Yaml::Array(mask_layers))
+        }
+    }
+
     fn write_display_list_items(
         &mut self,
         list: &mut Vec<Yaml>,
@@ -737,7 +1021,7 @@ impl YamlFrameWriter {
             match *base.item() {
                 Rectangle(item) => {
                     str_node(&mut v, "type", "rect");
-                    color_node(&mut v, "color", item.color);
+                    color_node(&mut v, "color", scene.properties.resolve_color(&item.color));
                 }
                 ClearRectangle => {
                     str_node(&mut v, "type", "clear-rect");;
@@ -751,6 +1035,40 @@ impl YamlFrameWriter {
                     color_node(&mut v, "color", item.color);
                     str_node(&mut v, "style", item.style.as_str());
                 }
+                SpecificDisplayItem::Path(item) => {
+                    str_node(&mut v, "type", "path");
+                    str_node(&mut v, "path", &path_to_string(display_list.get(base.path())));
+                    enum_node(&mut v, "fill-rule", item.fill_rule);
+                    if let Some(stroke) = item.stroke {
+                        f32_node(&mut v, "stroke-width", stroke.width);
+                        enum_node(&mut v, "line-join", stroke.join);
+                        enum_node(&mut v, "line-cap", stroke.cap);
+                        f32_node(&mut v, "miter-limit", stroke.miter_limit);
+                        f32_node(&mut v, "dash-offset", stroke.dash_offset);
+                        let dashes: Vec<f32> = display_list.get(base.dashes()).collect();
+                        if !dashes.is_empty() {
+                            f32_vec_node(&mut v, "dashes", &dashes);
+                        }
+                    }
+                    match item.paint {
+                        PathPaint::Color(color) => color_node(&mut v, "color", color),
+                        PathPaint::Gradient(gradient) => {
+                            point_node(&mut v, "start", &gradient.start_point);
+                            point_node(&mut v, "end", &gradient.end_point);
+                            let mut stops = vec![];
+                            for stop in display_list.get(base.gradient_stops()) {
+                                stops.push(Yaml::Real(stop.offset.to_string()));
+                                stops.push(Yaml::String(color_to_string(stop.color)));
+                            }
+                            yaml_node(&mut v, "stops", Yaml::Array(stops));
+                            bool_node(
+                                &mut v,
+                                "repeat",
+                                gradient.extend_mode == ExtendMode::Repeat,
+                            );
+                        }
+                    }
+                }
                 Text(item) => {
                     let gi = display_list.get(base.glyphs());
                     let mut indices: Vec<u32> = vec![];
@@ -805,6 +1123,27 @@ impl YamlFrameWriter {
                         }
                     }
                 }
+                TextDecoration(item) => {
+                    str_node(&mut v, "type", "text-decoration");
+                    match item.kind {
+                        TextDecorationKind::Underline => str_node(&mut v, "decoration", "underline"),
+                        TextDecorationKind::Overline => str_node(&mut v, "decoration", "overline"),
+                        TextDecorationKind::LineThrough => {
+                            str_node(&mut v, "decoration", "line-through")
+                        }
+                        TextDecorationKind::Emphasis(mark) => {
+                            str_node(&mut v, "decoration", "emphasis");
+                            enum_node(&mut v, "emphasis-mark", mark.shape);
+                            bool_node(&mut v, "emphasis-open", mark.open);
+                            enum_node(&mut v, "emphasis-position", mark.position);
+                        }
+                    }
+                    color_node(&mut v, "color", item.color);
+                    if let Some(thickness) = item.thickness {
+                        f32_node(&mut v, "thickness", thickness);
+                    }
+                    bool_node(&mut v, "skip-ink", item.skip_ink);
+                }
                 Image(item) => {
                     if let Some(path) = self.path_for_image(item.image_key) {
                         path_node(&mut v, "image", &path);
@@ -885,14 +1224,39 @@ impl YamlFrameWriter {
                                 details.outset.left,
                             ];
                             yaml_node(&mut v, "width", f32_vec_yaml(&widths, true));
-                            str_node(&mut v, "border-type", "image");
 
                             match details.source {
                                 NinePatchBorderSource::Image(image_key) => {
+                                    str_node(&mut v, "border-type", "image");
                                     if let Some(path) = self.path_for_image(image_key) {
-                                        path_node(&mut v, "image", &path);
+                                        path_node(&mut v, "image-source", &path);
                                     }
                                 }
+                                NinePatchBorderSource::Gradient(ref gradient) => {
+                                    str_node(&mut v, "border-type", "gradient");
+                                    point_node(&mut v, "start", &gradient.start_point);
+                                    point_node(&mut v, "end", &gradient.end_point);
+                                    let mut stops = vec![];
+                                    for stop in display_list.get(base.gradient_stops()) {
+                                        stops.push(Yaml::Real(stop.offset.to_string()));
+                                        stops.push(Yaml::String(color_to_string(stop.color)));
+                                    }
+                                    yaml_node(&mut v, "stops", Yaml::Array(stops));
+                                    bool_node(
+                                        &mut v,
+                                        "repeat",
+                                        gradient.extend_mode == ExtendMode::Repeat,
+                                    );
+                                }
+                                NinePatchBorderSource::RadialGradient(ref gradient) => {
+                                    str_node(&mut v, "border-type", "radial-gradient");
+                                    radial_gradient_to_yaml(
+                                        &mut v,
+                                        gradient,
+                                        base.gradient_stops(),
+                                        display_list
+                                    );
+                                }
                             }
 
                             u32_node(&mut v, "image-width", details.width);
@@ -905,6 +1269,7 @@ impl YamlFrameWriter {
                             ];
                             yaml_node(&mut v, "slice", u32_vec_yaml(&slice, true));
                             yaml_node(&mut v, "outset", f32_vec_yaml(&outset, true));
+                            bool_node(&mut v, "fill", details.fill);
                             match details.repeat_horizontal {
                                 RepeatMode::Stretch => {
                                     str_node(&mut v, "repeat-horizontal", "stretch")
@@ -1024,6 +1389,17 @@ impl YamlFrameWriter {
                         display_list
                     );
                 }
+                ConicGradient(item) => {
+                    str_node(&mut v, "type", "conic-gradient");
+                    size_node(&mut v, "tile-size", &item.tile_size);
+                    size_node(&mut v, "tile-spacing", &item.tile_spacing);
+                    conic_gradient_to_yaml(
+                        &mut v,
+                        &item.gradient,
+                        base.gradient_stops(),
+                        display_list
+                    );
+                }
                 Iframe(item) => {
                     str_node(&mut v, "type", "iframe");
                     u32_vec_node(&mut v, "id", &[item.pipeline_id.0, item.pipeline_id.1]);
@@ -1032,13 +1408,23 @@ impl YamlFrameWriter {
                 PushStackingContext(item) => {
                     str_node(&mut v, "type", "stacking-context");
                     let filters = display_list.get(base.filters());
+                    let backdrop_filters = display_list.get(base.backdrop_filters());
+                    let filter_primitives = display_list.get(base.filter_primitives());
                     write_stacking_context(
                         &mut v,
                         &item.stacking_context,
                         &scene.properties,
                         filters,
+                        backdrop_filters,
+                        filter_primitives,
                         clip_id_mapper,
                     );
+                    if let Some(mask_yaml) = self.make_mask_layers_node(
+                        display_list.get(base.mask_layers()),
+                        display_list.get(base.mask_stops()),
+                    ) {
+                        yaml_node(&mut v, "mask-layers", mask_yaml);
+                    }
 
                     let mut sub_iter = base.sub_iter();
                     self.write_display_list(&mut v, display_list, scene, &mut sub_iter, clip_id_mapper);
@@ -1074,6 +1460,12 @@ impl YamlFrameWriter {
                         yaml_node(&mut v, "image-mask", mask_yaml);
                     }
                 }
+                ClipPath(item) => {
+                    str_node(&mut v, "type", "clip");
+                    usize_node(&mut v, "id", clip_id_mapper.add_id(item.id));
+                    str_node(&mut v, "path", &path_to_string(display_list.get(base.path())));
+                    enum_node(&mut v, "fill-rule", item.fill_rule);
+                }
                 ClipChain(item) => {
                     str_node(&mut v, "type", "clip-chain");
 
@@ -1108,6 +1500,11 @@ impl YamlFrameWriter {
                     if let Some(mask_yaml) = self.make_clip_mask_image_node(&item.image_mask) {
                         yaml_node(&mut v, "image-mask", mask_yaml);
                     }
+
+                    if let Some(ref scroll_offset) = item.scroll_offset {
+                        let offset = scene.properties.resolve_layout_vector(scroll_offset);
+                        point_node(&mut v, "scroll-offset", &offset.to_point());
+                    }
                 }
                 StickyFrame(item) => {
                     str_node(&mut v, "type", "sticky-frame");
@@ -1148,7 +1545,9 @@ impl YamlFrameWriter {
 
                 PopStackingContext => return,
                 PopReferenceFrame => return,
-                SetGradientStops => panic!("dummy item yielded?"),
+                SetGradientStops |
+                StartKeyedRange(..) |
+                EndKeyedRange => panic!("dummy item yielded?"),
                 PushShadow(shadow) => {
                     str_node(&mut v, "type", "shadow");
                     vector_node(&mut v, "offset", &shadow.offset);
@@ -1206,6 +1605,25 @@ impl webrender::ApiRecordingReceiver for YamlFrameWriterReceiver {
                                 list_descriptor,
                             );
                         }
+                        SceneMsg::SpliceDisplayList {
+                            ref epoch,
+                            ref pipeline_id,
+                            ref background,
+                            ref viewport_size,
+                            ref list_descriptor,
+                            ref ops,
+                            ..
+                        } => {
+                            self.frame_writer.begin_write_display_list_delta(
+                                &mut self.scene,
+                                epoch,
+                                pipeline_id,
+                                background,
+                                viewport_size,
+                                list_descriptor,
+                                ops,
+                            );
+                        }
                         SceneMsg::SetRootPipeline(ref pipeline_id) => {
                             self.scene.set_root_pipeline_id(pipeline_id.clone());
                         }
@@ -36,6 +36,11 @@ pub trait YamlHelper {
     fn as_mix_blend_mode(&self) -> Option<MixBlendMode>;
     fn as_filter_op(&self) -> Option<FilterOp>;
     fn as_vec_filter_op(&self) -> Option<Vec<FilterOp>>;
+    fn as_filter_primitive_input(&self) -> Option<FilterPrimitiveInput>;
+    fn as_transfer_function(&self) -> Option<TransferFunction>;
+    fn as_filter_primitive(&self) -> Option<FilterPrimitive>;
+    fn as_vec_filter_primitive(&self) -> Option<Vec<FilterPrimitive>>;
+    fn as_path(&self) -> Option<Vec<PathCommand>>;
 }
 
 fn string_to_color(color: &str) -> Option<ColorF> {
@@ -138,6 +143,51 @@ define_string_enum!(
 
 define_string_enum!(ClipMode, [Clip = "clip", ClipOut = "clip-out"]);
 
+define_string_enum!(
+    EmphasisMarkShape,
+    [
+        Dot = "dot",
+        Circle = "circle",
+        DoubleCircle = "double-circle",
+        Triangle = "triangle",
+        Sesame = "sesame"
+    ]
+);
+
+define_string_enum!(EmphasisMarkPosition, [Over = "over", Under = "under"]);
+
+define_string_enum!(FillRule, [Nonzero = "nonzero", EvenOdd = "evenodd"]);
+
+define_string_enum!(LineJoin, [Miter = "miter", Round = "round", Bevel = "bevel"]);
+
+define_string_enum!(LineCap, [Butt = "butt", Round = "round", Square = "square"]);
+
+define_string_enum!(ColorSpace, [Srgb = "srgb", LinearRgb = "linear-rgb"]);
+
+define_string_enum!(MaskMode, [Alpha = "alpha", Luminance = "luminance"]);
+
+define_string_enum!(
+    MaskComposite,
+    [
+        Add = "add",
+        Subtract = "subtract",
+        Intersect = "intersect",
+        Exclude = "exclude"
+    ]
+);
+
+define_string_enum!(MorphologyOperator, [Erode = "erode", Dilate = "dilate"]);
+
+define_string_enum!(
+    TurbulenceKind,
+    [FractalNoise = "fractal-noise", Turbulence = "turbulence"]
+);
+
+define_string_enum!(
+    ColorChannel,
+    [Red = "r", Green = "g", Blue = "b", Alpha = "a"]
+);
+
 // Rotate around `axis` by `degrees` angle
 fn make_rotation(
     origin: &LayoutPoint,
@@ -600,4 +650,181 @@ impl YamlHelper for Yaml {
             self.as_filter_op().map(|op| vec![op])
         }
     }
+
+    fn as_filter_primitive_input(&self) -> Option<FilterPrimitiveInput> {
+        if self.is_badvalue() {
+            return Some(FilterPrimitiveInput::Previous);
+        }
+        match self.as_str() {
+            Some("previous") => Some(FilterPrimitiveInput::Previous),
+            Some("original") => Some(FilterPrimitiveInput::Original),
+            Some(_) => None,
+            None => self.as_i64().map(|index| FilterPrimitiveInput::OutputOfPrimitiveIndex(index as usize)),
+        }
+    }
+
+    fn as_transfer_function(&self) -> Option<TransferFunction> {
+        if self.is_badvalue() {
+            return Some(TransferFunction::Identity);
+        }
+        match self["type"].as_str() {
+            Some("identity") => Some(TransferFunction::Identity),
+            Some("table") => self["values"].as_vec_f32().map(TransferFunction::Table),
+            Some("discrete") => self["values"].as_vec_f32().map(TransferFunction::Discrete),
+            Some("linear") => Some(TransferFunction::Linear {
+                slope: self["slope"].as_f32().unwrap_or(1.0),
+                intercept: self["intercept"].as_f32().unwrap_or(0.0),
+            }),
+            Some("gamma") => Some(TransferFunction::Gamma {
+                amplitude: self["amplitude"].as_f32().unwrap_or(1.0),
+                exponent: self["exponent"].as_f32().unwrap_or(1.0),
+                offset: self["offset"].as_f32().unwrap_or(0.0),
+            }),
+            _ => None,
+        }
+    }
+
+    fn as_filter_primitive(&self) -> Option<FilterPrimitive> {
+        let input = self["in"].as_filter_primitive_input()?;
+        let kind = match self["type"].as_str() {
+            Some("identity") => FilterPrimitiveKind::Identity(input),
+            Some("flood") => FilterPrimitiveKind::Flood(self["color"].as_colorf()?),
+            Some("offset") => FilterPrimitiveKind::Offset(input, self["offset"].as_vector()?),
+            Some("blur") => FilterPrimitiveKind::Blur(input, self["radius"].as_f32()?),
+            Some("color-matrix") => {
+                let m = self["matrix"].as_vec_f32()?;
+                if m.len() != 20 {
+                    println!("Color matrix has {} values instead of 20", m.len());
+                    return None;
+                }
+                let mut matrix: [f32; 20] = [0.0; 20];
+                matrix.clone_from_slice(&m);
+                FilterPrimitiveKind::ColorMatrix(input, matrix)
+            }
+            Some("component-transfer") => {
+                FilterPrimitiveKind::ComponentTransfer(
+                    input,
+                    [
+                        self["func-r"].as_transfer_function()?,
+                        self["func-g"].as_transfer_function()?,
+                        self["func-b"].as_transfer_function()?,
+                        self["func-a"].as_transfer_function()?,
+                    ],
+                )
+            }
+            Some("composite") => {
+                let operator = match self["operator"].as_str().unwrap_or("over") {
+                    "over" => CompositeOperator::Over,
+                    "in" => CompositeOperator::In,
+                    "out" => CompositeOperator::Out,
+                    "atop" => CompositeOperator::Atop,
+                    "xor" => CompositeOperator::Xor,
+                    "arithmetic" => {
+                        let k = self["k"].as_vec_f32()?;
+                        if k.len() != 4 {
+                            println!("Arithmetic composite has {} coefficients instead of 4", k.len());
+                            return None;
+                        }
+                        CompositeOperator::Arithmetic([k[0], k[1], k[2], k[3]])
+                    }
+                    operator => {
+                        println!("Unrecognized composite operator '{}'", operator);
+                        return None;
+                    }
+                };
+                let input2 = self["in2"].as_filter_primitive_input()?;
+                FilterPrimitiveKind::Composite(input, input2, operator)
+            }
+            Some("blend") => {
+                let mode = self["mode"].as_mix_blend_mode().unwrap_or(MixBlendMode::Normal);
+                let input2 = self["in2"].as_filter_primitive_input()?;
+                FilterPrimitiveKind::Blend(input, input2, mode)
+            }
+            Some("morphology") => {
+                let operator = self["operator"].as_str().and_then(StringEnum::from_str)?;
+                FilterPrimitiveKind::Morphology(input, operator, self["radius"].as_size()?)
+            }
+            Some("turbulence") => {
+                // A single base frequency is used for both axes.
+                let frequencies = self["base-frequency"].as_vec_f32()?;
+                let base_frequency = match frequencies.len() {
+                    1 => (frequencies[0], frequencies[0]),
+                    2 => (frequencies[0], frequencies[1]),
+                    count => {
+                        println!("Turbulence has {} base frequencies instead of 1 or 2", count);
+                        return None;
+                    }
+                };
+                FilterPrimitiveKind::Turbulence {
+                    base_frequency,
+                    num_octaves: self["num-octaves"].as_i64().unwrap_or(1) as u32,
+                    seed: self["seed"].as_i64().unwrap_or(0) as i32,
+                    kind: self["kind"]
+                        .as_str()
+                        .and_then(StringEnum::from_str)
+                        .unwrap_or(TurbulenceKind::Turbulence),
+                }
+            }
+            Some("displacement-map") => FilterPrimitiveKind::DisplacementMap {
+                input,
+                map: self["in2"].as_filter_primitive_input()?,
+                scale: self["scale"].as_f32().unwrap_or(0.0),
+                x_channel: self["x-channel"]
+                    .as_str()
+                    .and_then(StringEnum::from_str)
+                    .unwrap_or(ColorChannel::Alpha),
+                y_channel: self["y-channel"]
+                    .as_str()
+                    .and_then(StringEnum::from_str)
+                    .unwrap_or(ColorChannel::Alpha),
+            },
+            kind => {
+                println!("Unrecognized filter primitive type {:?}", kind);
+                return None;
+            }
+        };
+        let color_space = self["color-space"]
+            .as_str()
+            .and_then(StringEnum::from_str)
+            .unwrap_or(ColorSpace::Srgb);
+
+        Some(FilterPrimitive { kind, color_space })
+    }
+
+    fn as_vec_filter_primitive(&self) -> Option<Vec<FilterPrimitive>> {
+        self.as_vec()?.iter().map(|x| x.as_filter_primitive()).collect()
+    }
+
+    // Parses the absolute commands of SVG path data, e.g. "M 0 0 L 10 0 Z".
+    fn as_path(&self) -> Option<Vec<PathCommand>> {
+        let data = self.as_str()?.replace(',', " ");
+        let mut tokens = data.split_whitespace();
+        let mut commands = Vec::new();
+        while let Some(command) = tokens.next() {
+            let mut point = || -> Option<LayoutPoint> {
+                let x = tokens.next()?.parse().ok()?;
+                let y = tokens.next()?.parse().ok()?;
+                Some(LayoutPoint::new(x, y))
+            };
+            commands.push(match command {
+                "M" => PathCommand::MoveTo(point()?),
+                "L" => PathCommand::LineTo(point()?),
+                "Q" => {
+                    let ctrl = point()?;
+                    PathCommand::QuadraticCurveTo(ctrl, point()?)
+                }
+                "C" => {
+                    let ctrl1 = point()?;
+                    let ctrl2 = point()?;
+                    PathCommand::CubicCurveTo(ctrl1, ctrl2, point()?)
+                }
+                "Z" => PathCommand::Close,
+                _ => {
+                    println!("Unrecognized path command '{}'", command);
+                    return None;
+                }
+            });
+        }
+        Some(commands)
+    }
 }